@@ -5,6 +5,8 @@ use std::ops::{Add, Mul, Neg, Sub};
 
 use memuse::DynamicUsage;
 
+pub mod zec;
+
 pub const COIN: u64 = 1_0000_0000;
 pub const MAX_MONEY: u64 = 21_000_000 * COIN;
 pub const MAX_BALANCE: i64 = MAX_MONEY as i64;
@@ -122,6 +124,23 @@ impl ZatBalance {
         self.0.is_negative()
     }
 
+    /// Adds `rhs` to this ZatBalance.
+    ///
+    /// Returns an error identifying the bound that was exceeded if the result is outside
+    /// the range `{-MAX_BALANCE..MAX_BALANCE}`.
+    pub fn checked_add(self, rhs: ZatBalance) -> Result<ZatBalance, BalanceError> {
+        // Both operands are within the valid range, so their sum cannot overflow an i64.
+        ZatBalance::from_i64(self.0 + rhs.0)
+    }
+
+    /// Subtracts `rhs` from this ZatBalance.
+    ///
+    /// Returns an error identifying the bound that was exceeded if the result is outside
+    /// the range `{-MAX_BALANCE..MAX_BALANCE}`.
+    pub fn checked_sub(self, rhs: ZatBalance) -> Result<ZatBalance, BalanceError> {
+        ZatBalance::from_i64(self.0 - rhs.0)
+    }
+
     pub fn sum<I: IntoIterator<Item = ZatBalance>>(values: I) -> Option<ZatBalance> {
         let mut result = ZatBalance::zero();
         for value in values {
@@ -298,6 +317,24 @@ impl Zatoshis {
     pub fn is_positive(&self) -> bool {
         self > &Zatoshis::ZERO
     }
+
+    /// Adds `rhs` to this Zatoshis.
+    ///
+    /// Returns [`BalanceError::Overflow`] if the result is greater than `MAX_MONEY`.
+    pub fn checked_add(self, rhs: Zatoshis) -> Result<Zatoshis, BalanceError> {
+        // Both operands are at most `MAX_MONEY`, so their sum cannot overflow a u64.
+        Zatoshis::from_u64(self.0 + rhs.0)
+    }
+
+    /// Subtracts `rhs` from this Zatoshis.
+    ///
+    /// Returns [`BalanceError::Underflow`] if `rhs` is greater than `self`.
+    pub fn checked_sub(self, rhs: Zatoshis) -> Result<Zatoshis, BalanceError> {
+        self.0
+            .checked_sub(rhs.0)
+            .map(Zatoshis)
+            .ok_or(BalanceError::Underflow)
+    }
 }
 
 impl From<Zatoshis> for ZatBalance {
@@ -318,6 +355,13 @@ impl From<Zatoshis> for u64 {
     }
 }
 
+impl From<Zatoshis> for i64 {
+    fn from(n: Zatoshis) -> Self {
+        // `MAX_MONEY` is representable as an i64.
+        n.0 as i64
+    }
+}
+
 impl TryFrom<u64> for Zatoshis {
     type Error = BalanceError;
 
@@ -326,6 +370,14 @@ impl TryFrom<u64> for Zatoshis {
     }
 }
 
+impl TryFrom<i64> for Zatoshis {
+    type Error = BalanceError;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        Zatoshis::from_nonnegative_i64(value)
+    }
+}
+
 impl TryFrom<ZatBalance> for Zatoshis {
     type Error = BalanceError;
 
@@ -452,9 +504,9 @@ pub mod testing {
 
 #[cfg(test)]
 mod tests {
-    use crate::value::MAX_BALANCE;
+    use crate::value::{MAX_BALANCE, MAX_MONEY};
 
-    use super::ZatBalance;
+    use super::{BalanceError, ZatBalance, Zatoshis};
 
     #[test]
     fn amount_in_range() {
@@ -518,4 +570,68 @@ mod tests {
         let v = ZatBalance(-MAX_BALANCE);
         assert_eq!(v - ZatBalance(1), None)
     }
+
+    #[test]
+    fn zat_balance_checked_arithmetic() {
+        let max = ZatBalance(MAX_BALANCE);
+        let min = ZatBalance(-MAX_BALANCE);
+        let one = ZatBalance(1);
+
+        assert_eq!(max.checked_add(ZatBalance::zero()), Ok(max));
+        assert_eq!(max.checked_add(one), Err(BalanceError::Overflow));
+        assert_eq!(max.checked_add(max), Err(BalanceError::Overflow));
+        assert_eq!(min.checked_add(-one), Err(BalanceError::Underflow));
+        assert_eq!(min.checked_add(max), Ok(ZatBalance::zero()));
+
+        assert_eq!(min.checked_sub(ZatBalance::zero()), Ok(min));
+        assert_eq!(min.checked_sub(one), Err(BalanceError::Underflow));
+        assert_eq!(min.checked_sub(max), Err(BalanceError::Underflow));
+        assert_eq!(max.checked_sub(-one), Err(BalanceError::Overflow));
+        assert_eq!(max.checked_sub(max), Ok(ZatBalance::zero()));
+    }
+
+    #[test]
+    fn zatoshis_checked_arithmetic() {
+        let max = Zatoshis(MAX_MONEY);
+        let one = Zatoshis(1);
+
+        assert_eq!(max.checked_add(Zatoshis::ZERO), Ok(max));
+        assert_eq!(max.checked_add(one), Err(BalanceError::Overflow));
+        assert_eq!(max.checked_add(max), Err(BalanceError::Overflow));
+        assert_eq!(
+            Zatoshis(MAX_MONEY - 1).checked_add(one),
+            Ok(Zatoshis(MAX_MONEY))
+        );
+
+        assert_eq!(max.checked_sub(max), Ok(Zatoshis::ZERO));
+        assert_eq!(
+            Zatoshis::ZERO.checked_sub(one),
+            Err(BalanceError::Underflow)
+        );
+        assert_eq!(one.checked_sub(max), Err(BalanceError::Underflow));
+    }
+
+    #[test]
+    fn zatoshis_conversions() {
+        assert_eq!(Zatoshis::try_from(0i64), Ok(Zatoshis::ZERO));
+        assert_eq!(Zatoshis::try_from(-1i64), Err(BalanceError::Underflow));
+        assert_eq!(Zatoshis::try_from(i64::MIN), Err(BalanceError::Underflow));
+        assert_eq!(Zatoshis::try_from(MAX_BALANCE), Ok(Zatoshis(MAX_MONEY)));
+        assert_eq!(
+            Zatoshis::try_from(MAX_BALANCE + 1),
+            Err(BalanceError::Overflow)
+        );
+        assert_eq!(Zatoshis::try_from(u64::MAX), Err(BalanceError::Overflow));
+        assert_eq!(i64::from(Zatoshis(MAX_MONEY)), MAX_BALANCE);
+
+        assert_eq!(
+            Zatoshis::try_from(ZatBalance(-1)),
+            Err(BalanceError::Underflow)
+        );
+        assert_eq!(
+            ZatBalance::from(Zatoshis(MAX_MONEY)),
+            ZatBalance(MAX_BALANCE)
+        );
+        assert_eq!(u64::try_from(ZatBalance(-1)), Err(BalanceError::Underflow));
+    }
 }
@@ -0,0 +1,230 @@
+//! Parsing and formatting of values as decimal ZEC strings.
+//!
+//! Values are formatted with exactly eight decimal places (the precision of a zatoshi), and
+//! may be parsed from strings having between zero and eight decimal places.
+
+use std::error;
+use std::fmt;
+
+use super::{BalanceError, ZatBalance, Zatoshis, COIN};
+
+/// The number of decimal places in a ZEC value.
+const DECIMAL_PLACES: usize = 8;
+
+/// Errors that can occur when parsing a decimal ZEC value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input was not a decimal number of the form `<digits>[.<digits>]`.
+    InvalidFormat,
+    /// The input had more than eight decimal places, and so cannot be represented in
+    /// zatoshis.
+    TooManyDecimalPlaces,
+    /// The input was outside the valid monetary range.
+    OutOfRange(BalanceError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidFormat => write!(f, "Not a valid decimal ZEC value."),
+            ParseError::TooManyDecimalPlaces => write!(
+                f,
+                "ZEC values may have at most {} decimal places.",
+                DECIMAL_PLACES
+            ),
+            ParseError::OutOfRange(e) => write!(f, "ZEC value out of range: {}", e),
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseError::OutOfRange(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<BalanceError> for ParseError {
+    fn from(e: BalanceError) -> Self {
+        ParseError::OutOfRange(e)
+    }
+}
+
+/// Parses a nonnegative decimal ZEC value, such as `"1.5"` or `"0.00010000"`.
+pub fn parse_zatoshis(input: &str) -> Result<Zatoshis, ParseError> {
+    let (whole, fraction) = match input.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (input, None),
+    };
+
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(whole) || !fraction.map_or(true, is_digits) {
+        return Err(ParseError::InvalidFormat);
+    }
+
+    let zats = match fraction {
+        Some(f) if f.len() > DECIMAL_PLACES => return Err(ParseError::TooManyDecimalPlaces),
+        Some(f) => format!("{:0<width$}", f, width = DECIMAL_PLACES)
+            .parse::<u64>()
+            .map_err(|_| ParseError::InvalidFormat)?,
+        None => 0,
+    };
+
+    // A whole part that does not fit in a u64 is necessarily greater than `MAX_MONEY`.
+    let coins = whole
+        .parse::<u64>()
+        .map_err(|_| ParseError::OutOfRange(BalanceError::Overflow))?;
+
+    coins
+        .checked_mul(COIN)
+        .and_then(|coin_zats| coin_zats.checked_add(zats))
+        .ok_or(BalanceError::Overflow)
+        .and_then(Zatoshis::from_u64)
+        .map_err(ParseError::from)
+}
+
+/// Parses a decimal ZEC value that may be negative, such as `"-1.5"`.
+pub fn parse_zat_balance(input: &str) -> Result<ZatBalance, ParseError> {
+    match input.strip_prefix('-') {
+        Some(magnitude) => parse_zatoshis(magnitude)
+            .map(|value| -ZatBalance::from(value))
+            .map_err(|e| match e {
+                ParseError::OutOfRange(_) => ParseError::OutOfRange(BalanceError::Underflow),
+                e => e,
+            }),
+        None => parse_zatoshis(input).map(ZatBalance::from),
+    }
+}
+
+/// Formats the given value as a decimal ZEC string with eight decimal places.
+pub fn format_zatoshis(value: Zatoshis) -> String {
+    let zats = u64::from(value);
+    format!(
+        "{}.{:0>width$}",
+        zats / COIN,
+        zats % COIN,
+        width = DECIMAL_PLACES
+    )
+}
+
+/// Formats the given value as a decimal ZEC string with eight decimal places, prefixed by
+/// `-` if the value is negative.
+pub fn format_zat_balance(value: ZatBalance) -> String {
+    let zats = i64::from(value);
+    let magnitude = Zatoshis::from_u64(zats.unsigned_abs())
+        .expect("The magnitude of a valid ZatBalance is a valid Zatoshis value.");
+    if zats < 0 {
+        format!("-{}", format_zatoshis(magnitude))
+    } else {
+        format_zatoshis(magnitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{
+        format_zat_balance, format_zatoshis, parse_zat_balance, parse_zatoshis, ParseError,
+    };
+    use crate::value::{
+        testing::{arb_zat_balance, arb_zatoshis},
+        BalanceError, ZatBalance, Zatoshis, MAX_BALANCE, MAX_MONEY,
+    };
+
+    #[test]
+    fn format_boundaries() {
+        assert_eq!(format_zatoshis(Zatoshis::ZERO), "0.00000000");
+        assert_eq!(format_zatoshis(Zatoshis::const_from_u64(1)), "0.00000001");
+        assert_eq!(
+            format_zatoshis(Zatoshis::const_from_u64(1_0000_0000)),
+            "1.00000000"
+        );
+        assert_eq!(
+            format_zatoshis(Zatoshis::const_from_u64(MAX_MONEY)),
+            "21000000.00000000"
+        );
+        assert_eq!(
+            format_zat_balance(ZatBalance::const_from_i64(-1)),
+            "-0.00000001"
+        );
+        assert_eq!(
+            format_zat_balance(ZatBalance::const_from_i64(-MAX_BALANCE)),
+            "-21000000.00000000"
+        );
+        assert_eq!(format_zat_balance(ZatBalance::zero()), "0.00000000");
+    }
+
+    #[test]
+    fn parse_valid() {
+        assert_eq!(parse_zatoshis("0"), Ok(Zatoshis::ZERO));
+        assert_eq!(
+            parse_zatoshis("0.00000001"),
+            Ok(Zatoshis::const_from_u64(1))
+        );
+        assert_eq!(
+            parse_zatoshis("1.5"),
+            Ok(Zatoshis::const_from_u64(1_5000_0000))
+        );
+        assert_eq!(
+            parse_zatoshis("21000000"),
+            Ok(Zatoshis::const_from_u64(MAX_MONEY))
+        );
+        assert_eq!(
+            parse_zat_balance("-21000000.00000000"),
+            Ok(ZatBalance::const_from_i64(-MAX_BALANCE))
+        );
+        assert_eq!(parse_zat_balance("-0"), Ok(ZatBalance::zero()));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for input in ["", ".", "1.", ".1", "+1", "1e8", "1.0.0", " 1", "-1", "1,5"] {
+            assert_eq!(
+                parse_zatoshis(input),
+                Err(ParseError::InvalidFormat),
+                "{:?}",
+                input
+            );
+        }
+        assert_eq!(parse_zat_balance("--1"), Err(ParseError::InvalidFormat));
+        assert_eq!(
+            parse_zatoshis("0.000000001"),
+            Err(ParseError::TooManyDecimalPlaces)
+        );
+    }
+
+    #[test]
+    fn parse_out_of_range() {
+        assert_eq!(
+            parse_zatoshis("21000000.00000001"),
+            Err(ParseError::OutOfRange(BalanceError::Overflow))
+        );
+        assert_eq!(
+            parse_zatoshis("184467440737.09551616"),
+            Err(ParseError::OutOfRange(BalanceError::Overflow))
+        );
+        assert_eq!(
+            parse_zatoshis("99999999999999999999999"),
+            Err(ParseError::OutOfRange(BalanceError::Overflow))
+        );
+        assert_eq!(
+            parse_zat_balance("-21000000.00000001"),
+            Err(ParseError::OutOfRange(BalanceError::Underflow))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_zatoshis_roundtrip(value in arb_zatoshis()) {
+            assert_eq!(parse_zatoshis(&format_zatoshis(value)), Ok(value));
+        }
+
+        #[test]
+        fn prop_zat_balance_roundtrip(value in arb_zat_balance()) {
+            assert_eq!(parse_zat_balance(&format_zat_balance(value)), Ok(value));
+        }
+    }
+}
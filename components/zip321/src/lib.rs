@@ -7,6 +7,7 @@
 use core::fmt::Debug;
 use std::{
     collections::BTreeMap,
+    convert::Infallible,
     fmt::{self, Display},
 };
 
@@ -16,8 +17,9 @@ use nom::{
     sequence::preceded,
 };
 
-use zcash_address::{ConversionError, ZcashAddress};
+use zcash_address::{unified, ConversionError, TryFromRawAddress, ZcashAddress};
 use zcash_protocol::{
+    consensus::{self, NetworkType},
     memo::{self, MemoBytes},
     value::BalanceError,
     value::Zatoshis,
@@ -43,6 +45,14 @@ pub enum Zip321Error {
     RecipientMissing(usize),
     /// The ZIP 321 URI was malformed and failed to parse.
     ParseError(String),
+    /// The recipient address of the payment at the given index is not an address for the
+    /// expected network.
+    IncorrectNetwork {
+        payment_index: usize,
+        address: ZcashAddress,
+        expected: NetworkType,
+        actual: NetworkType,
+    },
 }
 
 impl<E: Display> From<ConversionError<E>> for Zip321Error {
@@ -82,6 +92,16 @@ impl Display for Zip321Error {
                 write!(f, "Payment {} is missing its recipient address", idx)
             }
             Zip321Error::ParseError(s) => write!(f, "Parse failure: {}", s),
+            Zip321Error::IncorrectNetwork {
+                payment_index,
+                address,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Payment {} is invalid: recipient address {} is for {:?} but we expected {:?}",
+                payment_index, address, actual, expected
+            ),
         }
     }
 }
@@ -288,6 +308,48 @@ impl TransactionRequest {
             })
     }
 
+    /// Checks that the recipient address of each payment in this request is an address for
+    /// the network described by the given consensus parameters.
+    ///
+    /// Returns [`Zip321Error::IncorrectNetwork`] for the first payment, in order of payment
+    /// index, whose recipient address is for a different network.
+    pub fn validate_for<P: consensus::Parameters>(&self, params: &P) -> Result<(), Zip321Error> {
+        let network = params.network_type();
+        self.payments.iter().try_for_each(|(i, payment)| {
+            match payment
+                .recipient_address
+                .clone()
+                .convert_if_network::<NetworkCheck>(network)
+            {
+                Err(ConversionError::IncorrectNetwork { expected, actual }) => {
+                    Err(Zip321Error::IncorrectNetwork {
+                        payment_index: *i,
+                        address: payment.recipient_address.clone(),
+                        expected,
+                        actual,
+                    })
+                }
+                _ => Ok(()),
+            }
+        })
+    }
+
+    /// Splits this request into a sequence of single-payment requests, one for each payment
+    /// in this request, in order of payment index.
+    ///
+    /// Each payment is carried over unmodified, including any additional parameters it has;
+    /// a `req-` parameter that applied to a payment in this request therefore continues to
+    /// apply to that payment in the request that contains it. Each of the returned requests
+    /// places its payment at the empty payment index.
+    pub fn split(&self) -> Vec<TransactionRequest> {
+        self.payments
+            .values()
+            .map(|payment| TransactionRequest {
+                payments: BTreeMap::from([(0, payment.clone())]),
+            })
+            .collect()
+    }
+
     /// A utility for use in tests to help check round-trip serialization properties.
     #[cfg(any(test, feature = "test-dependencies"))]
     pub(crate) fn normalize(&mut self) {
@@ -428,6 +490,38 @@ impl TransactionRequest {
     }
 }
 
+/// A conversion target that accepts any address, used to check whether an address is for
+/// the expected network.
+struct NetworkCheck;
+
+impl TryFromRawAddress for NetworkCheck {
+    type Error = Infallible;
+
+    fn try_from_raw_sprout(_: [u8; 64]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(NetworkCheck)
+    }
+
+    fn try_from_raw_sapling(_: [u8; 43]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(NetworkCheck)
+    }
+
+    fn try_from_raw_unified(_: unified::Address) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(NetworkCheck)
+    }
+
+    fn try_from_raw_transparent_p2pkh(_: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(NetworkCheck)
+    }
+
+    fn try_from_raw_transparent_p2sh(_: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(NetworkCheck)
+    }
+
+    fn try_from_raw_tex(_: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(NetworkCheck)
+    }
+}
+
 mod render {
     use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
     use zcash_address::ZcashAddress;
@@ -871,7 +965,7 @@ mod tests {
 
     use zcash_address::{testing::arb_address, ZcashAddress};
     use zcash_protocol::{
-        consensus::NetworkType,
+        consensus::{NetworkType, MAIN_NETWORK, TEST_NETWORK},
         memo::{Memo, MemoBytes},
         value::{testing::arb_zatoshis, Zatoshis},
     };
@@ -884,7 +978,7 @@ mod tests {
         parse::{parse_amount, zcashparam, Param},
         render::{amount_str, memo_param, str_param},
         testing::{arb_addr_str, arb_valid_memo, arb_zip321_request, arb_zip321_uri},
-        Payment, TransactionRequest,
+        Payment, TransactionRequest, Zip321Error,
     };
 
     fn check_roundtrip(req: TransactionRequest) {
@@ -912,6 +1006,27 @@ mod tests {
         assert_eq!(result, Param::Message("".to_string()));
     }
 
+    #[test]
+    fn test_zip321_validate_for_network() {
+        let addr = "ztestsapling1n65uaftvs2g7075q2x2a04shfk066u3lldzxsrprfrqtzxnhc9ps73v4lhx4l9yfxj46sl0q90k";
+        let uri = format!(
+            "zcash:?address={}&amount=1&address.1={}&amount.1=2",
+            addr, addr
+        );
+        let req = TransactionRequest::from_uri(&uri).unwrap();
+
+        assert_eq!(req.validate_for(&TEST_NETWORK), Ok(()));
+        assert_eq!(
+            req.validate_for(&MAIN_NETWORK),
+            Err(Zip321Error::IncorrectNetwork {
+                payment_index: 0,
+                address: ZcashAddress::try_from_encoded(addr).unwrap(),
+                expected: NetworkType::Main,
+                actual: NetworkType::Test,
+            })
+        );
+    }
+
     #[test]
     fn test_zip321_parse_simple() {
         let uri = "zcash:ztestsapling1n65uaftvs2g7075q2x2a04shfk066u3lldzxsrprfrqtzxnhc9ps73v4lhx4l9yfxj46sl0q90k?amount=3768769.02796286&message=";
@@ -1180,6 +1295,23 @@ mod tests {
             assert!(TransactionRequest::normalize_and_eq(&mut parsed, &mut req));
         }
 
+        #[test]
+        fn prop_zip321_split(req in arb_zip321_request(NetworkType::Test)) {
+            let split = req.split();
+            assert_eq!(split.len(), req.payments().len());
+            for (single, payment) in split.iter().zip(req.payments().values()) {
+                assert_eq!(single.payments().len(), 1);
+                assert_eq!(single.payments().get(&0), Some(payment));
+                assert_eq!(single.total(), Ok(payment.amount()));
+                assert_eq!(TransactionRequest::from_uri(&single.to_uri()).as_ref(), Ok(single));
+            }
+
+            let rejoined = TransactionRequest::new(
+                split.into_iter().flat_map(|r| r.payments.into_values()).collect()
+            ).unwrap();
+            assert_eq!(rejoined.total(), req.total());
+        }
+
         #[test]
         fn prop_zip321_roundtrip_uri(uri in arb_zip321_uri(NetworkType::Test)) {
             let mut parsed = TransactionRequest::from_uri(&uri).unwrap();
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rand_core::OsRng;
+use sapling::{
+    self,
+    prover::mock::{MockOutputProver, MockSpendProver},
+    value::NoteValue,
+};
+use zcash_client_backend::{
+    decrypt_transaction, decrypt_transaction_parallel, keys::UnifiedSpendingKey,
+};
+use zcash_primitives::{
+    consensus::{BranchId, Network, NetworkUpgrade, Parameters},
+    transaction::{
+        components::{sapling::zip212_enforcement, Amount},
+        Authorized, TransactionData, TxVersion,
+    },
+    zip32::AccountId,
+};
+
+const OUTPUT_COUNT: usize = 100;
+
+fn bench_decrypt_transaction(c: &mut Criterion) {
+    let mut rng = OsRng;
+    let network = Network::TestNetwork;
+    let height = network.activation_height(NetworkUpgrade::Nu5).unwrap();
+
+    let usk = UnifiedSpendingKey::from_seed(&network, &[0; 32], AccountId::ZERO).unwrap();
+    let ufvk = usk.to_unified_full_viewing_key();
+    let (_, to) = ufvk.sapling().unwrap().default_address();
+    let ufvks = HashMap::from([(AccountId::ZERO, ufvk)]);
+
+    // Construct a transaction with many Sapling outputs to the account.
+    let tx = {
+        let mut builder = sapling::builder::Builder::new(
+            zip212_enforcement(&network, height),
+            // We use the Coinbase bundle type because we don't need to use
+            // any inputs for this benchmark.
+            sapling::builder::BundleType::Coinbase,
+            sapling::Anchor::empty_tree(),
+        );
+        for _ in 0..OUTPUT_COUNT {
+            builder
+                .add_output(None, to, NoteValue::from_raw(100), None)
+                .unwrap();
+        }
+        let (bundle, _) = builder
+            .build::<MockSpendProver, MockOutputProver, _, Amount>(&mut rng)
+            .unwrap()
+            .unwrap();
+        let bundle = bundle
+            .create_proofs(&MockSpendProver, &MockOutputProver, &mut rng, ())
+            .apply_signatures(&mut rng, [0; 32], &[])
+            .unwrap();
+
+        TransactionData::<Authorized>::from_parts(
+            TxVersion::Zip225,
+            BranchId::Nu5,
+            0,
+            height + 20,
+            None,
+            None,
+            Some(bundle),
+            None,
+        )
+        .freeze()
+        .unwrap()
+    };
+
+    let mut group = c.benchmark_group("decrypt-transaction");
+    group.throughput(Throughput::Elements(OUTPUT_COUNT as u64));
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| decrypt_transaction(&network, height, &tx, &ufvks))
+    });
+
+    group.bench_function("parallel", |b| {
+        b.iter(|| decrypt_transaction_parallel(&network, height, &tx, &ufvks))
+    });
+}
+
+criterion_group!(benches, bench_decrypt_transaction);
+criterion_main!(benches);
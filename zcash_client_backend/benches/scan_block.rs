@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use sapling::{note_encryption::SaplingDomain, Nullifier};
+use zcash_client_backend::{
+    data_api::BlockMetadata,
+    keys::UnifiedSpendingKey,
+    proto::compact_formats::ChainMetadata,
+    scanning::{
+        testing::{fake_compact_block, scan_block_batched},
+        Nullifiers, ScanningKeyOps, ScanningKeys,
+    },
+};
+use zcash_primitives::{
+    block::BlockHash,
+    consensus::{BlockHeight, Network},
+    transaction::components::amount::NonNegativeAmount,
+    zip32::AccountId,
+};
+use zip32::Scope;
+
+const NOISE_TX_COUNT: usize = 1000;
+
+fn bench_scan_block(c: &mut Criterion) {
+    let network = Network::TestNetwork;
+    let account = AccountId::ZERO;
+
+    let usk = UnifiedSpendingKey::from_seed(&network, &[0; 32], account).unwrap();
+    let ufvk = usk.to_unified_full_viewing_key();
+    let dfvk = ufvk.sapling().unwrap().clone();
+
+    // A single-account, single-scope wallet has exactly one Sapling IVK.
+    let sapling_key: Box<dyn ScanningKeyOps<SaplingDomain, AccountId, Nullifier>> =
+        Box::new((account, dfvk.to_ivk(Scope::External)));
+    let scanning_keys = ScanningKeys::new(
+        HashMap::from([(account, sapling_key)]),
+        #[cfg(feature = "orchard")]
+        HashMap::new(),
+    );
+
+    // Construct a large block by padding a block containing a payment to the account
+    // with many transactions that are not decryptable by it.
+    let mut block = fake_compact_block(
+        BlockHeight::from(1),
+        BlockHash([0; 32]),
+        Nullifier([0; 32]),
+        &dfvk,
+        NonNegativeAmount::const_from_u64(5),
+        false,
+        None,
+    );
+    let noise_tx = block.vtx[0].clone();
+    for i in 0..NOISE_TX_COUNT {
+        let mut tx = noise_tx.clone();
+        tx.hash[..8].copy_from_slice(&(i as u64).to_le_bytes());
+        tx.index = block.vtx.len() as u64;
+        block.vtx.push(tx);
+    }
+    let output_count = block.vtx.iter().map(|tx| tx.outputs.len()).sum::<usize>();
+    block.chain_metadata = Some(ChainMetadata {
+        sapling_commitment_tree_size: output_count as u32,
+        orchard_commitment_tree_size: 0,
+    });
+    let prior_block_metadata = BlockMetadata::from_parts(
+        BlockHeight::from(0),
+        BlockHash([0; 32]),
+        Some(0),
+        #[cfg(feature = "orchard")]
+        Some(0),
+    );
+
+    let mut group = c.benchmark_group("scan-block-single-ivk");
+    group.throughput(Throughput::Elements(output_count as u64));
+
+    group.bench_function("batched", |b| {
+        b.iter(|| {
+            scan_block_batched(
+                &network,
+                block.clone(),
+                &scanning_keys,
+                &Nullifiers::empty(),
+                Some(&prior_block_metadata),
+            )
+            .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_scan_block);
+criterion_main!(benches);
@@ -9,6 +9,8 @@ const PROPOSAL_PROTO: &str = "proto/proposal.proto";
 
 const SERVICE_PROTO: &str = "proto/service.proto";
 
+const WATCH_ONLY_PROTO: &str = "proto/watch_only.proto";
+
 fn main() -> io::Result<()> {
     // - We don't include the proto files in releases so that downstreams do not need to
     //  regenerate the bindings even if protoc is present.
@@ -88,5 +90,14 @@ fn build() -> io::Result<()> {
     // same package, but we've set things up so this only contains the service types.
     fs::copy(out.join("cash.z.wallet.sdk.rpc.rs"), "src/proto/service.rs")?;
 
+    // Build the watch-only account types.
+    tonic_build::compile_protos(WATCH_ONLY_PROTO)?;
+
+    // Copy the generated types into the source tree so changes can be committed.
+    fs::copy(
+        out.join("cash.z.wallet.sdk.watchonly.rs"),
+        "src/proto/watch_only.rs",
+    )?;
+
     Ok(())
 }
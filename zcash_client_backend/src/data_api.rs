@@ -61,12 +61,17 @@ use std::{
     hash::Hash,
     io,
     num::{NonZeroU32, TryFromIntError},
+    ops::Range,
 };
 
-use incrementalmerkletree::{frontier::Frontier, Retention};
+use incrementalmerkletree::{frontier::Frontier, MerklePath, Position, Retention};
 use nonempty::NonEmpty;
 use secrecy::SecretVec;
-use shardtree::{error::ShardTreeError, store::ShardStore, ShardTree};
+use shardtree::{
+    error::ShardTreeError,
+    store::{memory::MemoryShardStore, ShardStore},
+    ShardTree,
+};
 use zip32::fingerprint::SeedFingerprint;
 
 use self::{
@@ -74,36 +79,44 @@ use self::{
     scanning::ScanRange,
 };
 use crate::{
-    address::UnifiedAddress,
-    decrypt::DecryptedOutput,
+    address::{Address, AddressFormat, UnifiedAddress},
+    decrypt::{DecryptedOutput, TransferType},
     keys::{
         UnifiedAddressRequest, UnifiedFullViewingKey, UnifiedIncomingViewingKey, UnifiedSpendingKey,
     },
     proto::service::TreeState,
     wallet::{Note, NoteId, ReceivedNote, Recipient, WalletTransparentOutput, WalletTx},
-    ShieldedProtocol,
+    PoolType, ShieldedProtocol,
 };
 use zcash_primitives::{
     block::BlockHash,
     consensus::BlockHeight,
+    legacy::TransparentAddress,
     memo::{Memo, MemoBytes},
     transaction::{
-        components::amount::{BalanceError, NonNegativeAmount},
+        components::amount::{Amount, BalanceError, NonNegativeAmount},
         Transaction, TxId,
     },
 };
-use zip32::DiversifierIndex;
+use zip32::{DiversifierIndex, Scope};
 
 #[cfg(feature = "transparent-inputs")]
 use {
     crate::wallet::TransparentAddressMetadata,
-    zcash_primitives::{legacy::TransparentAddress, transaction::components::OutPoint},
+    zcash_primitives::{
+        legacy::{
+            keys::{AccountPubKey, NonHardenedChildIndex},
+            Script,
+        },
+        transaction::components::OutPoint,
+    },
 };
 
 #[cfg(any(test, feature = "test-dependencies"))]
 use zcash_primitives::consensus::NetworkUpgrade;
 
 pub mod chain;
+pub mod chain_tip;
 pub mod error;
 pub mod scanning;
 pub mod wallet;
@@ -140,12 +153,30 @@ pub struct TransparentAddressSyncInfo<AccountId> {
     pub used: bool,
 }
 
+/// The status of a transparent output received by the wallet with respect to the
+/// transactions that spend it.
+///
+/// This is returned by [`WalletRead::get_transparent_outpoints`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg(feature = "transparent-inputs")]
+pub enum SpendStatus {
+    /// The output is not spent by any transaction known to the wallet, other than
+    /// transactions that have expired.
+    Unspent,
+    /// The output is spent by the given transaction, which has not been mined but has not
+    /// yet expired.
+    PendingSpend(TxId),
+    /// The output is spent by the given transaction, which has been mined.
+    Spent(TxId),
+}
+
 /// Balance information for a value within a single pool in an account.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Balance {
     spendable_value: NonNegativeAmount,
     change_pending_confirmation: NonNegativeAmount,
     value_pending_spendability: NonNegativeAmount,
+    locked_value: NonNegativeAmount,
 }
 
 impl Balance {
@@ -154,6 +185,7 @@ impl Balance {
         spendable_value: NonNegativeAmount::ZERO,
         change_pending_confirmation: NonNegativeAmount::ZERO,
         value_pending_spendability: NonNegativeAmount::ZERO,
+        locked_value: NonNegativeAmount::ZERO,
     };
 
     fn check_total_adding(
@@ -163,6 +195,7 @@ impl Balance {
         (self.spendable_value
             + self.change_pending_confirmation
             + self.value_pending_spendability
+            + self.locked_value
             + value)
             .ok_or(BalanceError::Overflow)
     }
@@ -214,9 +247,27 @@ impl Balance {
         Ok(())
     }
 
+    /// Returns the value in the account of notes that would otherwise be spendable, but that
+    /// have been locked by the user against being selected as inputs to new transactions.
+    ///
+    /// This value is not included in [`Self::spendable_value`].
+    pub fn locked_value(&self) -> NonNegativeAmount {
+        self.locked_value
+    }
+
+    /// Adds the specified value to the locked total, checking for overflow.
+    pub fn add_locked_value(&mut self, value: NonNegativeAmount) -> Result<(), BalanceError> {
+        self.check_total_adding(value)?;
+        self.locked_value = (self.locked_value + value).unwrap();
+        Ok(())
+    }
+
     /// Returns the total value of funds represented by this [`Balance`].
     pub fn total(&self) -> NonNegativeAmount {
-        (self.spendable_value + self.change_pending_confirmation + self.value_pending_spendability)
+        (self.spendable_value
+            + self.change_pending_confirmation
+            + self.value_pending_spendability
+            + self.locked_value)
             .expect("Balance cannot overflow MAX_MONEY")
     }
 }
@@ -329,6 +380,13 @@ impl AccountBalance {
             + self.orchard_balance.value_pending_spendability)
             .expect("Account balance cannot overflow MAX_MONEY")
     }
+
+    /// Returns the value of shielded funds that would otherwise be spendable, but that have
+    /// been locked by the user against being selected as inputs to new transactions.
+    pub fn locked_value(&self) -> NonNegativeAmount {
+        (self.sapling_balance.locked_value + self.orchard_balance.locked_value)
+            .expect("Account balance cannot overflow MAX_MONEY")
+    }
 }
 
 /// The kinds of accounts supported by `zcash_client_backend`.
@@ -344,6 +402,118 @@ pub enum AccountSource {
     Imported,
 }
 
+/// Summary information about an account maintained by a wallet.
+///
+/// This is the type returned by [`WalletRead::list_accounts`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountInfo<AccountId> {
+    account_id: AccountId,
+    source: AccountSource,
+    birthday: BlockHeight,
+}
+
+impl<AccountId: Copy> AccountInfo<AccountId> {
+    /// Constructs a new [`AccountInfo`] from its constituent parts.
+    pub fn from_parts(account_id: AccountId, source: AccountSource, birthday: BlockHeight) -> Self {
+        Self {
+            account_id,
+            source,
+            birthday,
+        }
+    }
+
+    /// Returns the unique identifier for the account.
+    pub fn account_id(&self) -> AccountId {
+        self.account_id
+    }
+
+    /// Returns whether this account is derived or imported, and the derivation parameters
+    /// if applicable.
+    pub fn source(&self) -> AccountSource {
+        self.source
+    }
+
+    /// Returns `true` if the account was derived from a seed known to the wallet's user, such
+    /// that spending keys for the account can be derived; imported (viewing-key-only) accounts
+    /// return `false`.
+    pub fn is_spending_capable(&self) -> bool {
+        matches!(self.source, AccountSource::Derived { .. })
+    }
+
+    /// Returns the fingerprint of the seed from which the account was derived, if known.
+    pub fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        match &self.source {
+            AccountSource::Derived {
+                seed_fingerprint, ..
+            } => Some(seed_fingerprint),
+            AccountSource::Imported => None,
+        }
+    }
+
+    /// Returns the birthday height of the account.
+    pub fn birthday(&self) -> BlockHeight {
+        self.birthday
+    }
+}
+
+/// The data required to track an account on a watch-only device, such as a companion wallet
+/// that monitors the balance and transactions of an account whose spending keys are held
+/// elsewhere.
+///
+/// A [`WatchOnlyBundle`] never contains spending key material. It is the type returned by
+/// [`WalletRead::export_account_for_watching`], and may be serialized for transfer to the
+/// watch-only device using [`crate::proto::watch_only::WatchOnlyAccount`]. The watch-only
+/// device should then import the account using [`WalletWrite::import_account_ufvk`], with an
+/// [`AccountBirthday`] constructed from the tree state as of the block prior to
+/// [`Self::birthday_height`] (and [`Self::recover_until`], if any).
+#[derive(Clone, Debug)]
+pub struct WatchOnlyBundle {
+    ufvk: UnifiedFullViewingKey,
+    birthday_height: BlockHeight,
+    recover_until: Option<BlockHeight>,
+    addresses: Vec<(DiversifierIndex, UnifiedAddress)>,
+}
+
+impl WatchOnlyBundle {
+    /// Constructs a new [`WatchOnlyBundle`] from its constituent parts.
+    pub fn from_parts(
+        ufvk: UnifiedFullViewingKey,
+        birthday_height: BlockHeight,
+        recover_until: Option<BlockHeight>,
+        addresses: Vec<(DiversifierIndex, UnifiedAddress)>,
+    ) -> Self {
+        Self {
+            ufvk,
+            birthday_height,
+            recover_until,
+            addresses,
+        }
+    }
+
+    /// Returns the unified full viewing key for the account.
+    pub fn ufvk(&self) -> &UnifiedFullViewingKey {
+        &self.ufvk
+    }
+
+    /// Returns the birthday height of the account.
+    pub fn birthday_height(&self) -> BlockHeight {
+        self.birthday_height
+    }
+
+    /// Returns the height at which the account exits "recovery mode", if any.
+    ///
+    /// See [`AccountBirthday::recover_until`] for details.
+    pub fn recover_until(&self) -> Option<BlockHeight> {
+        self.recover_until
+    }
+
+    /// Returns the external unified addresses that have been generated for the account,
+    /// along with the diversifier indices at which they were derived from [`Self::ufvk`].
+    pub fn addresses(&self) -> &[(DiversifierIndex, UnifiedAddress)] {
+        &self.addresses
+    }
+}
+
 /// A set of capabilities that a client account must provide.
 pub trait Account<AccountId: Copy> {
     /// Returns the unique identifier for the account.
@@ -678,6 +848,19 @@ pub trait InputSource {
         exclude: &[Self::NoteRef],
     ) -> Result<SpendableNotes<Self::NoteRef>, Self::Error>;
 
+    /// Returns the identifiers of the notes belonging to the specified account that have been
+    /// locked against spending via [`WalletWrite::lock_note`].
+    ///
+    /// Input selectors use this to exclude locked notes from selection. The default
+    /// implementation returns an empty vector, which is appropriate for backends that do not
+    /// support note locking.
+    fn get_locked_note_refs(
+        &self,
+        _account: Self::AccountId,
+    ) -> Result<Vec<Self::NoteRef>, Self::Error> {
+        Ok(vec![])
+    }
+
     /// Fetches a spendable transparent output.
     ///
     /// Returns `Ok(None)` if the UTXO is not known to belong to the wallet or is not
@@ -725,6 +908,13 @@ pub trait WalletRead {
     /// Returns a vector with the IDs of all accounts known to this wallet.
     fn get_account_ids(&self) -> Result<Vec<Self::AccountId>, Self::Error>;
 
+    /// Returns summary information about each account maintained by the wallet, in order of
+    /// account creation.
+    ///
+    /// This permits callers to distinguish accounts for which spending keys can be derived
+    /// from watch-only accounts without loading the full data for each account.
+    fn list_accounts(&self) -> Result<Vec<AccountInfo<Self::AccountId>>, Self::Error>;
+
     /// Returns the account corresponding to the given ID, if any.
     fn get_account(
         &self,
@@ -754,6 +944,22 @@ pub trait WalletRead {
         seed: &SecretVec<u8>,
     ) -> Result<bool, Self::Error>;
 
+    /// Derives the [`UnifiedSpendingKey`] for the specified account from the given seed.
+    ///
+    /// This allows a wallet to retain only the seed, and derive the spending key transiently
+    /// whenever it needs to spend, rather than holding the key returned by
+    /// [`WalletWrite::create_account`] in memory. The derived key is checked against the
+    /// viewing key stored for the account before it is returned.
+    ///
+    /// Returns an error if the account is not known to the wallet, if the account has no known
+    /// ZIP 32 derivation (i.e. it was imported from a viewing key), or if the seed does not
+    /// correspond to the account's seed fingerprint.
+    fn derive_usk(
+        &self,
+        account_id: Self::AccountId,
+        seed: &SecretVec<u8>,
+    ) -> Result<UnifiedSpendingKey, Self::Error>;
+
     /// Checks whether the given seed is relevant to any of the derived accounts (where
     /// [`Account::source`] is [`AccountSource::Derived`]) in the wallet.
     ///
@@ -780,10 +986,98 @@ pub trait WalletRead {
         account: Self::AccountId,
     ) -> Result<Option<UnifiedAddress>, Self::Error>;
 
+    /// Returns the string encoding of the most recently generated unified address for the
+    /// specified account in the requested format.
+    ///
+    /// Wallets can use [`AddressFormat::SaplingLegacy`] or [`AddressFormat::TransparentLegacy`]
+    /// to present the corresponding receiver of the address to senders that do not support
+    /// unified addresses.
+    ///
+    /// This will return `Ok(None)` if the account identifier does not correspond to a known
+    /// account, or if a legacy format is requested and the address does not contain a receiver
+    /// of the corresponding type.
+    fn get_current_address_encoded(
+        &self,
+        account: Self::AccountId,
+        format: AddressFormat,
+    ) -> Result<Option<String>, Self::Error>;
+
+    /// Returns the internal (change) unified address for the specified account, if the account
+    /// identifier specified refers to a valid account for this wallet.
+    ///
+    /// The returned address contains the Sapling and Orchard receivers derived at the default
+    /// diversifier index of the account's internal-scope (ZIP 32 "change") keys, to which
+    /// [`wallet::create_proposed_transactions`] sends change outputs. It can be used to verify
+    /// that change produced by the wallet has not been sent to an externally-visible address.
+    ///
+    /// This will return `Ok(None)` if the account identifier does not correspond to a known
+    /// account, or if the wallet does not have the full viewing key for the account (and so
+    /// cannot derive its internal addresses).
+    fn get_internal_address(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Option<UnifiedAddress>, Self::Error>;
+
+    /// Returns the highest diversifier index at which the wallet has allocated an address for
+    /// the specified account in the given key scope.
+    ///
+    /// A wallet being restored from seed can use this to determine how far into the diversifier
+    /// space it must scan, and addresses allocated subsequently will use a greater index.
+    ///
+    /// This will return `Ok(None)` if the account identifier does not correspond to a known
+    /// account, or if the wallet has not allocated any addresses for the account in the given
+    /// scope.
+    fn get_max_diversifier_index(
+        &self,
+        account: Self::AccountId,
+        scope: Scope,
+    ) -> Result<Option<DiversifierIndex>, Self::Error>;
+
+    /// Returns the addresses of the specified account that the wallet added automatically
+    /// because a note was received at them during scanning, in order of increasing diversifier
+    /// index.
+    ///
+    /// When scanning decrypts a note received at one of the account's external addresses that
+    /// the wallet has not generated (as may happen after restoring from seed), the address is
+    /// persisted as if by [`WalletWrite::put_address_with_diversifier_index`], so that the note
+    /// is associated with a known address. This method reports the addresses that were added
+    /// in this way.
+    fn get_auto_discovered_addresses(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<UnifiedAddress>, Self::Error>;
+
+    /// Returns the addresses of the specified account that have received funds in more than
+    /// one transaction, along with the number of distinct transactions in which each address
+    /// received funds.
+    ///
+    /// Shielded receipts are attributed to the unified address at the diversifier index of the
+    /// receiving Sapling or Orchard receiver; transparent receipts are reported separately for
+    /// each transparent address. Addresses that have received funds in at most one transaction
+    /// are omitted. This can be used to warn the user of address reuse, which harms privacy
+    /// (particularly in the transparent pool).
+    fn get_reused_addresses(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<(Address, usize)>, Self::Error>;
+
     /// Returns the birthday height for the given account, or an error if the account is not known
     /// to the wallet.
     fn get_account_birthday(&self, account: Self::AccountId) -> Result<BlockHeight, Self::Error>;
 
+    /// Returns the data required to track the specified account on a watch-only device.
+    ///
+    /// The returned [`WatchOnlyBundle`] contains the account's unified full viewing key, its
+    /// birthday, and the external addresses that have been generated for it; it never includes
+    /// spending key material.
+    ///
+    /// Returns `Ok(None)` if the account is not known to the wallet, or if the wallet does not
+    /// have a unified full viewing key for the account.
+    fn export_account_for_watching(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Option<WatchOnlyBundle>, Self::Error>;
+
     /// Returns the birthday height for the wallet.
     ///
     /// This returns the earliest birthday height among accounts maintained by this wallet,
@@ -797,6 +1091,78 @@ pub trait WalletRead {
         min_confirmations: u32,
     ) -> Result<Option<WalletSummary<Self::AccountId>>, Self::Error>;
 
+    /// Returns the balances of all of the wallet's accounts, given the specified minimum number
+    /// of confirmations.
+    ///
+    /// The balances are identical to those returned by [`WalletRead::get_wallet_summary`], but
+    /// implementations should override the default implementation of this method in order to
+    /// avoid computing the remainder of the summary (such as scan progress). This is preferable
+    /// to calling [`WalletRead::get_wallet_summary`] once per account when displaying the
+    /// balances of many accounts. Returns an empty map if the wallet has no balance data
+    /// available.
+    fn get_account_balances(
+        &self,
+        min_confirmations: u32,
+    ) -> Result<HashMap<Self::AccountId, AccountBalance>, Self::Error> {
+        Ok(self
+            .get_wallet_summary(min_confirmations)?
+            .map_or_else(HashMap::new, |summary| summary.account_balances().clone()))
+    }
+
+    /// Returns the value of the given account's shielded change outputs that do not yet have
+    /// sufficient confirmations to be spendable, given the specified minimum number of
+    /// confirmations.
+    ///
+    /// This is the [`AccountBalance::change_pending_confirmation`] component of the account's
+    /// balance. It does not include funds received from other parties, which are reported by
+    /// [`WalletRead::get_value_pending_spendability`]. Returns `Ok(None)` if the account is
+    /// unknown or the wallet has no balance data available.
+    fn get_change_pending_confirmation(
+        &self,
+        account: Self::AccountId,
+        min_confirmations: u32,
+    ) -> Result<Option<NonNegativeAmount>, Self::Error> {
+        Ok(self
+            .get_account_balances(min_confirmations)?
+            .get(&account)
+            .map(|balance| balance.change_pending_confirmation()))
+    }
+
+    /// Returns the value of the given account's shielded notes that are not yet spendable,
+    /// other than change awaiting confirmation, given the specified minimum number of
+    /// confirmations.
+    ///
+    /// This is the [`AccountBalance::value_pending_spendability`] component of the account's
+    /// balance, and consists primarily of funds received from other parties. The wallet's own
+    /// change that is awaiting confirmation is instead reported by
+    /// [`WalletRead::get_change_pending_confirmation`]. Returns `Ok(None)` if the account is
+    /// unknown or the wallet has no balance data available.
+    fn get_value_pending_spendability(
+        &self,
+        account: Self::AccountId,
+        min_confirmations: u32,
+    ) -> Result<Option<NonNegativeAmount>, Self::Error> {
+        Ok(self
+            .get_account_balances(min_confirmations)?
+            .get(&account)
+            .map(|balance| balance.value_pending_spendability()))
+    }
+
+    /// Returns the total value of the given account's shielded notes that would be spendable
+    /// with `min_confirmations` confirmations if the chain tip were at `at_height`, assuming
+    /// that no further transactions are received or mined before then.
+    ///
+    /// This applies the same spendability rules as [`AccountBalance::spendable_value`] at a
+    /// hypothetical chain tip, and so can be used to report balance that will become available
+    /// once pending notes mature. Unmined transactions whose expiry height is below
+    /// `at_height` are treated as expired.
+    fn projected_spendable_balance(
+        &self,
+        account: Self::AccountId,
+        at_height: BlockHeight,
+        min_confirmations: NonZeroU32,
+    ) -> Result<NonNegativeAmount, Self::Error>;
+
     /// Returns the height of the chain as known to the wallet as of the most recent call to
     /// [`WalletWrite::update_chain_tip`].
     ///
@@ -811,6 +1177,19 @@ pub trait WalletRead {
     /// Returns the available block metadata for the block at the specified height, if any.
     fn block_metadata(&self, height: BlockHeight) -> Result<Option<BlockMetadata>, Self::Error>;
 
+    /// Returns the sizes of the Sapling and Orchard note commitment trees, respectively, as of
+    /// the end of the block at the specified height.
+    ///
+    /// These are the sizes recorded by the wallet when the block was scanned, and correspond
+    /// to the `sapling_commitment_tree_size` and `orchard_commitment_tree_size` fields of the
+    /// block's [`ChainMetadata`]; comparing the two allows a divergence between the wallet's
+    /// view of the chain and that of a light wallet server to be detected. Returns `Ok(None)`
+    /// if the block at the given height has not been scanned, or if the wallet did not record
+    /// the tree sizes when scanning it.
+    ///
+    /// [`ChainMetadata`]: crate::proto::compact_formats::ChainMetadata
+    fn get_tree_sizes(&self, height: BlockHeight) -> Result<Option<(u32, u32)>, Self::Error>;
+
     /// Returns the metadata for the block at the height to which the wallet has been fully
     /// scanned.
     ///
@@ -832,6 +1211,32 @@ pub trait WalletRead {
     /// due to the fact that out-of-order scanning can leave gaps.
     fn block_max_scanned(&self) -> Result<Option<BlockMetadata>, Self::Error>;
 
+    /// Returns the number of blocks by which the wallet's maximum scanned height trails the
+    /// chain tip most recently provided via [`WalletWrite::update_chain_tip`].
+    ///
+    /// This distinguishes the blocks that the wallet has scanned from the blocks that are known
+    /// to exist, and may be used to report how far behind the network the wallet is. Returns
+    /// `Ok(None)` if the chain tip is unknown or if no blocks have yet been scanned.
+    fn sync_lag(&self) -> Result<Option<u32>, Self::Error> {
+        Ok(self
+            .chain_height()?
+            .zip(self.block_max_scanned()?)
+            .map(|(network_tip, scanned)| {
+                u32::from(network_tip).saturating_sub(u32::from(scanned.block_height()))
+            }))
+    }
+
+    /// Returns the height from which the wallet must be rescanned, if scanning has detected an
+    /// inconsistency in the wallet's data (such as note commitment tree data that conflicts
+    /// with the data being scanned).
+    ///
+    /// Once set, this flag persists until it is cleared using
+    /// [`WalletWrite::clear_rescan_flag`], so that the condition is not lost if the
+    /// application is restarted. If an inconsistency is detected while the flag is already
+    /// set, the flag records the lesser of the two heights. Returns `Ok(None)` if no rescan is
+    /// required.
+    fn rescan_required(&self) -> Result<Option<BlockHeight>, Self::Error>;
+
     /// Returns a vector of suggested scan ranges based upon the current wallet state.
     ///
     /// This method should only be used in cases where the [`CompactBlock`] data that will be made
@@ -849,6 +1254,35 @@ pub trait WalletRead {
     /// [`ScanPriority::Verify`]: crate::data_api::scanning::ScanPriority
     fn suggest_scan_ranges(&self) -> Result<Vec<ScanRange>, Self::Error>;
 
+    /// Returns the ranges of blocks that must be downloaded into the caller's block cache in
+    /// order to complete scanning the wallet, in the order in which they should be downloaded
+    /// and scanned.
+    ///
+    /// The ranges are derived from [`WalletRead::suggest_scan_ranges`], which takes into account
+    /// the note commitment tree subtree roots that have been provided to the wallet, and are
+    /// returned in the same (descending priority) order. In addition:
+    ///
+    /// - No block below the wallet's birthday height is ever requested, because the wallet's note
+    ///   commitment trees are initialized from the frontier as of the birthday.
+    /// - Blocks that are already present in the block cache are omitted. `cached_tip` is called
+    ///   with each candidate scan range, and must return the height of the highest cached block
+    ///   within that range (as `BlockCache::get_tip_height` does), or `None` if no block in the
+    ///   range is cached. The block cache is expected to hold blocks contiguously from the start
+    ///   of each scan range, as is the case when blocks are downloaded one range at a time; for
+    ///   each range, only the blocks above the highest cached block are requested.
+    /// - Ranges with [`ScanPriority::Verify`] priority are always returned in full, because
+    ///   blocks cached for such ranges may have been invalidated by a chain reorganization.
+    ///
+    /// Returns an empty vector if the wallet has no accounts, or if it is fully scanned.
+    ///
+    /// [`ScanPriority::Verify`]: crate::data_api::scanning::ScanPriority
+    fn suggest_download_ranges<F, E>(
+        &self,
+        cached_tip: F,
+    ) -> Result<Vec<Range<BlockHeight>>, chain::error::Error<Self::Error, E>>
+    where
+        F: FnMut(&ScanRange) -> Result<Option<BlockHeight>, E>;
+
     /// Returns the default target height (for the block in which a new
     /// transaction would be mined) and anchor height (to use for a new
     /// transaction), given the range of block heights that the backend
@@ -867,6 +1301,14 @@ pub trait WalletRead {
     /// transaction is not in the main chain.
     fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error>;
 
+    /// Returns the time of the block in which the specified transaction was mined, as a Unix
+    /// timestamp in seconds.
+    ///
+    /// Returns `Ok(None)` if the transaction is not known to the wallet, if it has not been
+    /// mined in the main chain, or if the wallet has not stored the header data for the block
+    /// in which it was mined.
+    fn get_transaction_time(&self, txid: TxId) -> Result<Option<u32>, Self::Error>;
+
     /// Returns all unified full viewing keys known to this wallet.
     fn get_unified_full_viewing_keys(
         &self,
@@ -882,6 +1324,271 @@ pub trait WalletRead {
     /// Returns a transaction.
     fn get_transaction(&self, txid: TxId) -> Result<Option<Transaction>, Self::Error>;
 
+    /// Returns the serialized bytes of a transaction, exactly as they were stored by the wallet.
+    ///
+    /// For transactions created by the wallet, these are the bytes of the transaction as it was
+    /// constructed (and as it should have been broadcast); they can be used to re-broadcast the
+    /// transaction without the risk of a re-serialization changing its encoding. Returns
+    /// `Ok(None)` if the transaction is not known to the wallet, or if the wallet has not stored
+    /// the full data of the transaction (for example because it has only been observed in
+    /// compact blocks).
+    fn get_transaction_bytes(&self, txid: TxId) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Re-decrypts the transaction with the given ID using the wallet's current unified full
+    /// viewing keys, and compares the notes recovered to those the wallet has stored as having
+    /// been received in the transaction.
+    ///
+    /// This is an integrity check: the returned [`AuditReport`] describes each discrepancy
+    /// between the re-derived notes and the wallet's records, such as may result from storage
+    /// corruption or from notes having been attributed to the wrong keys. Stored notes whose
+    /// memo has not yet been recorded are not considered to have a mismatched memo.
+    ///
+    /// Returns an error if the transaction is unknown to the wallet, or if the wallet has not
+    /// stored the full data of the transaction.
+    fn audit_transaction(&self, txid: TxId) -> Result<AuditReport<Self::AccountId>, Self::Error>;
+
+    /// Returns the IDs of transactions created by this wallet that were previously mined, but
+    /// which have been un-mined by a chain reorg and not subsequently mined again.
+    ///
+    /// Such transactions may still be valid, and so the notes and outputs they spend remain
+    /// unavailable for spending until the transactions expire; the caller should re-broadcast
+    /// them using the data returned by [`Self::get_transaction_bytes`]. Transactions that have
+    /// expired as of the current chain tip are not returned.
+    fn get_rebroadcast_candidates(&self) -> Result<Vec<TxId>, Self::Error>;
+
+    /// Returns the IDs of transactions created by this wallet that have not been mined, and that
+    /// have not expired as of the current chain tip.
+    ///
+    /// These are the wallet's pending outgoing transactions. Each will either be mined, or will
+    /// expire and may then be removed using [`WalletWrite::abandon_transaction`].
+    fn get_unmined_transactions(&self) -> Result<Vec<TxId>, Self::Error>;
+
+    /// Returns the fee paid by the transaction with the given ID, if it can be determined.
+    ///
+    /// The fee is computed from the value balances of the transaction across all pools, and
+    /// so can only be determined if the wallet knows the value of every transparent input to
+    /// the transaction. Returns `Ok(None)` if the transaction is unknown to the wallet, or if
+    /// the value of any of its inputs is unknown (as is typically the case for transactions
+    /// that the wallet has only received funds from).
+    fn get_transaction_fee(&self, txid: TxId) -> Result<Option<NonNegativeAmount>, Self::Error>;
+
+    /// Returns the fees paid by the wallet's own transactions that were mined within the given
+    /// range of block heights, in the order in which the transactions were mined.
+    ///
+    /// A transaction is considered to have been created by the wallet if it spent any of the
+    /// wallet's notes or transparent outputs. Each fee is computed as by
+    /// [`WalletRead::get_transaction_fee`]; transactions for which the fee cannot be determined
+    /// are omitted. Transactions that have not been mined are not included.
+    fn get_fees_paid(
+        &self,
+        range: Range<BlockHeight>,
+    ) -> Result<Vec<(TxId, NonNegativeAmount)>, Self::Error>;
+
+    /// Returns the net change in the wallet's holdings in each value pool that results from the
+    /// transaction with the given ID, along with the fee paid by the transaction if it can be
+    /// determined.
+    ///
+    /// This breaks down the effect of the transaction on the wallet's balance by pool, so that
+    /// (for example) a transaction that spends Sapling notes and returns change to the Orchard
+    /// pool can be explained as such. Returns `Ok(None)` if the transaction is unknown to the
+    /// wallet.
+    fn get_transaction_pool_flows(&self, txid: TxId) -> Result<Option<PoolFlows>, Self::Error>;
+
+    /// Returns the identifiers of the wallet's notes that were spent by the transaction with the
+    /// given ID.
+    ///
+    /// A note is included if its nullifier was revealed by one of the transaction's shielded
+    /// inputs. Returns an empty vector if the transaction is unknown to the wallet or did not
+    /// spend any of the wallet's notes.
+    fn get_spent_notes(&self, txid: TxId) -> Result<Vec<NoteId>, Self::Error>;
+
+    /// Returns the identifiers of the notes belonging to the specified account that have been
+    /// locked against spending via [`WalletWrite::lock_note`], in the order in which they were
+    /// received.
+    ///
+    /// A note remains locked until it is unlocked via [`WalletWrite::unlock_note`], even if it
+    /// is subsequently spent.
+    fn get_locked_notes(&self, account: Self::AccountId) -> Result<Vec<NoteId>, Self::Error>;
+
+    /// Returns the IDs of the transactions mined within the given range of block heights that
+    /// spent any of the wallet's notes from the given shielded pool, in the order in which they
+    /// were mined.
+    ///
+    /// A transaction is included if one of the nullifiers it revealed belongs to a note that the
+    /// wallet received in `pool`. This can be used to track the wallet's migration of funds out
+    /// of a pool (for example, out of Sapling and into Orchard). Transactions that have not
+    /// been mined are not included.
+    fn get_transactions_spending_pool(
+        &self,
+        pool: ShieldedProtocol,
+        range: Range<BlockHeight>,
+    ) -> Result<Vec<TxId>, Self::Error>;
+
+    /// Returns the positions in the note commitment trees of the wallet's notes that were spent
+    /// by the transaction with the given ID, along with the shielded protocol of each note.
+    ///
+    /// This is intended to support analysis of the anonymity set of each spend. Notes for which
+    /// the wallet does not know the note commitment tree position are omitted. Returns an empty
+    /// vector if the transaction is unknown to the wallet or did not spend any of the wallet's
+    /// notes.
+    fn get_spent_note_positions(
+        &self,
+        txid: TxId,
+    ) -> Result<Vec<(ShieldedProtocol, Position)>, Self::Error>;
+
+    /// Returns the scope of the viewing key with which the wallet decrypted the given
+    /// received note.
+    ///
+    /// Notes received with the [`Scope::Internal`] key are change (or other wallet-internal
+    /// transfers), and so can be excluded from totals of funds received by an account.
+    /// Returns `Ok(None)` if the note is not known to the wallet, or if it was detected with
+    /// a viewing key whose scope is unknown (such as an imported incoming viewing key).
+    fn get_note_scope(&self, note_id: NoteId) -> Result<Option<Scope>, Self::Error>;
+
+    /// Returns the number of additional blocks that must be mined before the given received
+    /// note has at least `min_confirmations` confirmations, or `None` if it already does.
+    ///
+    /// A note mined at height `h` has `tip - h + 1` confirmations when the chain tip is at
+    /// height `tip`; a note that has not yet been mined is treated as requiring
+    /// `min_confirmations` more blocks. This does not take into account whether the note has
+    /// been spent, or whether the wallet has scanned enough of the chain to spend it.
+    ///
+    /// Returns an error if the note is not known to the wallet, or if the chain tip height is
+    /// not known.
+    fn blocks_until_spendable(
+        &self,
+        note_id: NoteId,
+        min_confirmations: NonZeroU32,
+    ) -> Result<Option<u32>, Self::Error>;
+
+    /// Returns a fingerprint of the set of mined, unspent outputs belonging to the given
+    /// account.
+    ///
+    /// The fingerprint is a hash over the sorted `(note_commitment, value, height)` tuples of
+    /// the account's unspent shielded notes, together with the `(outpoint, value, height)`
+    /// tuples of its unspent transparent outputs. Two wallets that track the same unspent
+    /// outputs for an account produce the same fingerprint, regardless of the order in which
+    /// those outputs were discovered; this allows divergence between instances of the same
+    /// wallet to be detected cheaply.
+    ///
+    /// Returns an error if the account is not known to the wallet.
+    fn unspent_set_fingerprint(&self, account: Self::AccountId) -> Result<[u8; 32], Self::Error>;
+
+    /// Returns a witness for each of the specified notes, all computed against the state of
+    /// the relevant note commitment tree as of the end of the block at `anchor_height`.
+    ///
+    /// Because every witness is computed as of the same checkpoint, all of the witnesses for
+    /// notes in a given pool commit to the same anchor, and so may be used together as the
+    /// inputs of a single transaction. Witnesses are returned in the order of `note_ids`.
+    ///
+    /// Returns an error if any of the notes is unknown to the wallet, or if a witness cannot
+    /// be computed for any of the notes as of `anchor_height` (for example, because the wallet
+    /// has not yet scanned the block containing the note, or because the checkpoint at
+    /// `anchor_height` has been pruned).
+    fn get_witnesses(
+        &self,
+        note_ids: &[NoteId],
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<(NoteId, NoteWitness)>, Self::Error>;
+
+    /// Returns the identifiers of the notes received by the given account whose memos satisfy
+    /// the given predicate, in the order in which they were received.
+    ///
+    /// This allows a merchant to match incoming payments to orders by memo without having to
+    /// retrieve and inspect every note in the wallet. The predicate is applied to the raw
+    /// [`MemoBytes`] of each note for which memo data has been populated; use [`MemoPrefix`] to
+    /// match text memos and arbitrary-data memos by prefix, or [`Memo::try_from`] to otherwise
+    /// distinguish between the memo formats. Notes received by the account's internal (change)
+    /// keys are not considered to be incoming payments, and are excluded.
+    fn get_received_notes_matching_memo<F>(
+        &self,
+        account: Self::AccountId,
+        predicate: F,
+    ) -> Result<Vec<NoteId>, Self::Error>
+    where
+        F: Fn(&MemoBytes) -> bool;
+
+    /// Returns the notes belonging to the given account that are not yet spent, but that are
+    /// used as inputs by a transaction that has not been mined and has not yet expired, along
+    /// with the ID of that transaction.
+    ///
+    /// Such notes are excluded from the account's spendable balance until the transaction
+    /// spending them is either mined or expires. If a note is used as an input by more than
+    /// one such transaction, it is returned once for each of them.
+    fn get_notes_pending_spend(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<(NoteId, TxId)>, Self::Error>;
+
+    /// Invokes the provided callback for each unspent note received by the specified account
+    /// in any of the given shielded pools, in order of increasing note commitment tree position.
+    ///
+    /// Notes are yielded one at a time, so that callers are not required to hold the entire set
+    /// of the wallet's notes in memory; this is useful when analyzing wallets that hold very
+    /// large numbers of notes. Implementations should ensure that all of the notes provided to
+    /// the callback are read from a single consistent snapshot of the wallet state. If the
+    /// callback returns an error, iteration stops and the error is returned.
+    ///
+    /// Only notes for which a note commitment tree position is known are yielded; no check
+    /// is made as to whether witnesses can currently be constructed for these notes.
+    fn iter_received_notes<F, E>(
+        &self,
+        account: Self::AccountId,
+        sources: &[ShieldedProtocol],
+        callback: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(ReceivedNote<NoteId, Note>) -> Result<(), E>,
+        E: From<Self::Error>;
+
+    /// Returns the unspent change notes belonging to the specified account.
+    ///
+    /// Change notes are those received at an address derived from the account's
+    /// [`Scope::Internal`] key, such as the change outputs of transactions created by the
+    /// wallet. As with [`WalletRead::iter_received_notes`], only notes for which a note
+    /// commitment tree position is known are returned.
+    fn get_change_notes(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<ReceivedNote<NoteId, Note>>, Self::Error>;
+
+    /// Returns the identifiers of the received notes that have become confirmed since the
+    /// chain tip was at `since_height`, in the order in which they were received.
+    ///
+    /// A note is considered confirmed once the transaction that received it has been mined
+    /// and has at least `min_confirmations` confirmations as of the current chain tip. A note
+    /// is returned if it is confirmed as of the current chain tip, but was not confirmed as of
+    /// `since_height` according to the wallet's current view of the chain. A wallet that wants
+    /// to be notified of confirmed incoming payments may therefore call this method after each
+    /// scan, passing the chain tip height as of the previous call. Notes received by the
+    /// wallet's internal (change) keys are not considered to be incoming payments, and are
+    /// excluded.
+    ///
+    /// If the wallet is truncated by a chain reorganization, a previously reported note whose
+    /// transaction is no longer mined is not returned until that transaction is mined again.
+    /// Callers that track the height passed as `since_height` should lower it to the height to
+    /// which the wallet was truncated, so that such notes are reported again once they have
+    /// been re-confirmed on the new chain.
+    fn get_newly_confirmed_notes(
+        &self,
+        since_height: BlockHeight,
+        min_confirmations: NonZeroU32,
+    ) -> Result<Vec<NoteId>, Self::Error>;
+
+    /// Returns the annotation that has been imported for the given transaction, if any.
+    ///
+    /// See [`WalletWrite::import_transaction_annotations`].
+    fn get_transaction_annotation(
+        &self,
+        txid: TxId,
+    ) -> Result<Option<TransactionAnnotation>, Self::Error>;
+
+    /// Returns the addresses that the wallet has been asked to watch, in the order in which
+    /// they were added.
+    ///
+    /// See [`WalletWrite::add_watched_address`].
+    fn get_watched_addresses(&self) -> Result<Vec<WatchedAddress>, Self::Error>;
+
     /// Returns the nullifiers for Sapling notes that the wallet is tracking, along with their
     /// associated account IDs, that are either unspent or have not yet been confirmed as spent (in
     /// that a spending transaction known to the wallet has not yet been included in a block).
@@ -912,6 +1619,50 @@ pub trait WalletRead {
         Ok(HashMap::new())
     }
 
+    /// Returns the transparent account-level extended public key of the given account.
+    ///
+    /// Transparent addresses for the account may be derived from this key on demand, without
+    /// access to the wallet's spending keys. This is available both for accounts derived from
+    /// a seed and for accounts imported from a UFVK that has a transparent component. Returns
+    /// `Ok(None)` if the account's viewing key does not include a transparent component.
+    #[cfg(feature = "transparent-inputs")]
+    fn get_transparent_account_pubkey(
+        &self,
+        _account: Self::AccountId,
+    ) -> Result<Option<AccountPubKey>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the account, and the address index within that account, of the transparent
+    /// receiver that the given output script pays to.
+    ///
+    /// All transparent receivers known to the wallet are searched, across all accounts,
+    /// including each account's legacy default transparent address. Returns `Ok(None)` if the
+    /// script does not pay to a transparent receiver that belongs to the wallet.
+    #[cfg(feature = "transparent-inputs")]
+    fn find_account_for_transparent_output(
+        &self,
+        _script: &Script,
+    ) -> Result<Option<(Self::AccountId, NonHardenedChildIndex)>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the unspent transparent outputs received at the transparent receiver of a
+    /// watched address, such that the block that included the transaction was mined at a
+    /// height less than or equal to `max_height`.
+    ///
+    /// The wallet cannot spend these outputs, so they are not returned by
+    /// [`InputSource::get_unspent_transparent_outputs`]. See
+    /// [`WalletWrite::add_watched_address`].
+    #[cfg(feature = "transparent-inputs")]
+    fn get_unspent_watched_outputs(
+        &self,
+        _address: &TransparentAddress,
+        _max_height: BlockHeight,
+    ) -> Result<Vec<WalletTransparentOutput>, Self::Error> {
+        Ok(vec![])
+    }
+
     /// Returns a mapping from transparent receiver to not-yet-shielded UTXO balance,
     /// for each address associated with a nonzero balance.
     #[cfg(feature = "transparent-inputs")]
@@ -923,6 +1674,21 @@ pub trait WalletRead {
         Ok(HashMap::new())
     }
 
+    /// Returns every transparent output that the wallet has recorded as having been received
+    /// by the given account, whether spent or unspent, along with its [`SpendStatus`].
+    ///
+    /// Unlike [`InputSource::get_unspent_transparent_outputs`], this is not restricted to a
+    /// single address or to outputs that are currently spendable, and so can be used to
+    /// reconstruct the account's full transparent history. Outputs are returned in order of
+    /// the height at which they were mined.
+    #[cfg(feature = "transparent-inputs")]
+    fn get_transparent_outpoints(
+        &self,
+        _account: Self::AccountId,
+    ) -> Result<Vec<(OutPoint, WalletTransparentOutput, SpendStatus)>, Self::Error> {
+        Ok(vec![])
+    }
+
     /// Gets the transparent addresses and their last sync heights
     /// across all accounts, for purposes of scanning for new transactions.
     ///
@@ -1156,6 +1922,7 @@ impl<A> ScannedBlock<A> {
 /// The purpose of this struct is to permit atomic updates of the
 /// wallet database when transactions are successfully decrypted.
 pub struct DecryptedTransaction<'a, AccountId> {
+    mined_height: Option<BlockHeight>,
     tx: &'a Transaction,
     sapling_outputs: Vec<DecryptedOutput<sapling::Note, AccountId>>,
     #[cfg(feature = "orchard")]
@@ -1164,7 +1931,10 @@ pub struct DecryptedTransaction<'a, AccountId> {
 
 impl<'a, AccountId> DecryptedTransaction<'a, AccountId> {
     /// Constructs a new [`DecryptedTransaction`] from its constituent parts.
+    ///
+    /// `mined_height` is the height of the block in which the transaction was mined, if known.
     pub fn new(
+        mined_height: Option<BlockHeight>,
         tx: &'a Transaction,
         sapling_outputs: Vec<DecryptedOutput<sapling::Note, AccountId>>,
         #[cfg(feature = "orchard")] orchard_outputs: Vec<
@@ -1172,6 +1942,7 @@ impl<'a, AccountId> DecryptedTransaction<'a, AccountId> {
         >,
     ) -> Self {
         Self {
+            mined_height,
             tx,
             sapling_outputs,
             #[cfg(feature = "orchard")]
@@ -1179,6 +1950,10 @@ impl<'a, AccountId> DecryptedTransaction<'a, AccountId> {
         }
     }
 
+    /// Returns the height of the block in which the transaction was mined, if known.
+    pub fn mined_height(&self) -> Option<BlockHeight> {
+        self.mined_height
+    }
     /// Returns the raw transaction data.
     pub fn tx(&self) -> &Transaction {
         self.tx
@@ -1192,30 +1967,152 @@ impl<'a, AccountId> DecryptedTransaction<'a, AccountId> {
     pub fn orchard_outputs(&self) -> &[DecryptedOutput<orchard::note::Note, AccountId>] {
         &self.orchard_outputs
     }
+
+    /// Returns the payments received by the wallet in this transaction that were not received
+    /// in the `required` pool.
+    ///
+    /// This allows a payment processor to enforce a policy on the pool in which incoming funds
+    /// must arrive; for example, a merchant that requires payments to be made to Orchard can
+    /// flag payments received by transparent or Sapling receivers as non-compliant. Only
+    /// shielded outputs decrypted with an account's external incoming viewing key
+    /// ([`TransferType::Incoming`]) are considered to be shielded payments; change and other
+    /// wallet-internal outputs are ignored.
+    ///
+    /// Transparent outputs are not decrypted, so transparent payments are identified by
+    /// matching the recipient of each transparent output against `transparent_receivers`,
+    /// which maps the wallet's transparent receivers to the accounts that they belong to (for
+    /// example, as returned by `WalletRead::get_transparent_receivers` for each account).
+    pub fn pool_requirement_violations(
+        &self,
+        required: PoolType,
+        transparent_receivers: &HashMap<TransparentAddress, AccountId>,
+    ) -> Vec<PoolRequirementViolation<AccountId>>
+    where
+        AccountId: Copy,
+    {
+        let txid = self.tx.txid();
+        let transparent = self
+            .tx
+            .transparent_bundle()
+            .into_iter()
+            .flat_map(|bundle| bundle.vout.iter().enumerate())
+            .filter_map(|(index, txout)| {
+                let account = transparent_receivers.get(&txout.recipient_address()?)?;
+                Some(PoolRequirementViolation {
+                    txid,
+                    pool: PoolType::Transparent,
+                    output_index: index,
+                    account: *account,
+                    value: txout.value,
+                })
+            });
+        let sapling = self
+            .sapling_outputs
+            .iter()
+            .filter(|output| output.transfer_type() == TransferType::Incoming)
+            .map(|output| PoolRequirementViolation {
+                txid,
+                pool: PoolType::Shielded(ShieldedProtocol::Sapling),
+                output_index: output.index(),
+                account: *output.account(),
+                value: output.note_value(),
+            });
+        #[cfg(feature = "orchard")]
+        let orchard = self
+            .orchard_outputs
+            .iter()
+            .filter(|output| output.transfer_type() == TransferType::Incoming)
+            .map(|output| PoolRequirementViolation {
+                txid,
+                pool: PoolType::Shielded(ShieldedProtocol::Orchard),
+                output_index: output.index(),
+                account: *output.account(),
+                value: output.note_value(),
+            });
+        #[cfg(not(feature = "orchard"))]
+        let orchard = std::iter::empty();
+
+        transparent
+            .chain(sapling)
+            .chain(orchard)
+            .filter(|violation| violation.pool != required)
+            .collect()
+    }
 }
 
-/// A transaction that was constructed and sent by the wallet.
+/// A payment received by the wallet in a pool other than the one required by a pool policy.
 ///
-/// The purpose of this struct is to permit atomic updates of the
-/// wallet database when transactions are created and submitted
-/// to the network.
-pub struct SentTransaction<'a, AccountId> {
-    tx: &'a Transaction,
-    created: time::OffsetDateTime,
+/// This is returned by [`DecryptedTransaction::pool_requirement_violations`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolRequirementViolation<AccountId> {
+    txid: TxId,
+    pool: PoolType,
+    output_index: usize,
     account: AccountId,
-    outputs: Vec<SentTransactionOutput<AccountId>>,
-    fee_amount: NonNegativeAmount,
-    #[cfg(feature = "transparent-inputs")]
-    utxos_spent: Vec<OutPoint>,
+    value: NonNegativeAmount,
 }
 
-impl<'a, AccountId> SentTransaction<'a, AccountId> {
-    /// Constructs a new [`SentTransaction`] from its constituent parts.
-    pub fn new(
-        tx: &'a Transaction,
-        created: time::OffsetDateTime,
-        account: AccountId,
-        outputs: Vec<SentTransactionOutput<AccountId>>,
+impl<AccountId: Copy> PoolRequirementViolation<AccountId> {
+    /// Returns the ID of the transaction in which the payment was received.
+    pub fn txid(&self) -> &TxId {
+        &self.txid
+    }
+
+    /// Returns the pool in which the payment was received.
+    pub fn pool(&self) -> PoolType {
+        self.pool
+    }
+
+    /// Returns the index of the output that received the payment, within the transaction's
+    /// outputs for [`Self::pool`].
+    pub fn output_index(&self) -> usize {
+        self.output_index
+    }
+
+    /// Returns the identifier of the received note, if the payment was received in a
+    /// shielded pool.
+    pub fn note_id(&self) -> Option<NoteId> {
+        match self.pool {
+            PoolType::Transparent => None,
+            PoolType::Shielded(protocol) => {
+                Some(NoteId::new(self.txid, protocol, self.output_index as u16))
+            }
+        }
+    }
+
+    /// Returns the account that received the payment.
+    pub fn account(&self) -> AccountId {
+        self.account
+    }
+
+    /// Returns the value of the payment.
+    pub fn value(&self) -> NonNegativeAmount {
+        self.value
+    }
+}
+
+/// A transaction that was constructed and sent by the wallet.
+///
+/// The purpose of this struct is to permit atomic updates of the
+/// wallet database when transactions are created and submitted
+/// to the network.
+pub struct SentTransaction<'a, AccountId> {
+    tx: &'a Transaction,
+    created: time::OffsetDateTime,
+    account: AccountId,
+    outputs: Vec<SentTransactionOutput<AccountId>>,
+    fee_amount: NonNegativeAmount,
+    #[cfg(feature = "transparent-inputs")]
+    utxos_spent: Vec<OutPoint>,
+}
+
+impl<'a, AccountId> SentTransaction<'a, AccountId> {
+    /// Constructs a new [`SentTransaction`] from its constituent parts.
+    pub fn new(
+        tx: &'a Transaction,
+        created: time::OffsetDateTime,
+        account: AccountId,
+        outputs: Vec<SentTransactionOutput<AccountId>>,
         fee_amount: NonNegativeAmount,
         #[cfg(feature = "transparent-inputs")] utxos_spent: Vec<OutPoint>,
     ) -> Self {
@@ -1316,6 +2213,328 @@ impl<AccountId> SentTransactionOutput<AccountId> {
     }
 }
 
+/// Externally-provided metadata describing a transaction, such as may be imported from another
+/// wallet or from an accounting system.
+///
+/// Annotations are keyed by transaction ID, and may be stored for transactions that are not (or
+/// not yet) known to the wallet. They have no effect on balance computation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionAnnotation {
+    txid: TxId,
+    date: Option<time::OffsetDateTime>,
+    label: Option<String>,
+    category: Option<String>,
+    note: Option<String>,
+}
+
+impl TransactionAnnotation {
+    /// Constructs a new [`TransactionAnnotation`] from its constituent parts.
+    pub fn from_parts(
+        txid: TxId,
+        date: Option<time::OffsetDateTime>,
+        label: Option<String>,
+        category: Option<String>,
+        note: Option<String>,
+    ) -> Self {
+        Self {
+            txid,
+            date,
+            label,
+            category,
+            note,
+        }
+    }
+
+    /// Returns the ID of the annotated transaction.
+    pub fn txid(&self) -> TxId {
+        self.txid
+    }
+    /// Returns the externally-known date of the transaction, if any.
+    pub fn date(&self) -> Option<time::OffsetDateTime> {
+        self.date
+    }
+    /// Returns the label associated with the transaction, if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+    /// Returns the accounting category associated with the transaction, if any.
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+    /// Returns the free-form note associated with the transaction, if any.
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+}
+
+/// A Merkle path from a note commitment to the root of the note commitment tree of the note's
+/// shielded pool.
+///
+/// This is the type returned by [`WalletRead::get_witnesses`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NoteWitness {
+    /// A witness for a Sapling note.
+    Sapling(MerklePath<sapling::Node, { sapling::NOTE_COMMITMENT_TREE_DEPTH }>),
+    /// A witness for an Orchard note.
+    #[cfg(feature = "orchard")]
+    Orchard(
+        MerklePath<orchard::tree::MerkleHashOrchard, { orchard::NOTE_COMMITMENT_TREE_DEPTH as u8 }>,
+    ),
+}
+
+impl NoteWitness {
+    /// Returns the shielded protocol of the note to which this witness corresponds.
+    pub fn protocol(&self) -> ShieldedProtocol {
+        match self {
+            NoteWitness::Sapling(_) => ShieldedProtocol::Sapling,
+            #[cfg(feature = "orchard")]
+            NoteWitness::Orchard(_) => ShieldedProtocol::Orchard,
+        }
+    }
+
+    /// Returns the position of the witnessed note in its note commitment tree.
+    pub fn position(&self) -> incrementalmerkletree::Position {
+        match self {
+            NoteWitness::Sapling(path) => path.position(),
+            #[cfg(feature = "orchard")]
+            NoteWitness::Orchard(path) => path.position(),
+        }
+    }
+}
+
+/// A rebuilt note commitment tree for a single shielded pool, including its checkpoints.
+///
+/// This is the type accepted by [`WalletWrite::replace_commitment_tree`].
+pub enum CommitmentTreeReplacement {
+    /// A replacement for the Sapling note commitment tree.
+    Sapling(MemoryShardStore<sapling::Node, BlockHeight>),
+    /// A replacement for the Orchard note commitment tree.
+    #[cfg(feature = "orchard")]
+    Orchard(MemoryShardStore<orchard::tree::MerkleHashOrchard, BlockHeight>),
+}
+
+impl CommitmentTreeReplacement {
+    /// Returns the shielded protocol of the note commitment tree to be replaced.
+    pub fn protocol(&self) -> ShieldedProtocol {
+        match self {
+            CommitmentTreeReplacement::Sapling(_) => ShieldedProtocol::Sapling,
+            #[cfg(feature = "orchard")]
+            CommitmentTreeReplacement::Orchard(_) => ShieldedProtocol::Orchard,
+        }
+    }
+}
+
+/// A discrepancy between a note recovered by re-decrypting a transaction and the wallet's
+/// record of that note.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditMismatch<AccountId> {
+    /// The note was recovered from the transaction, but the wallet has no record of it.
+    MissingNote(NoteId),
+    /// The wallet has a record of the note, but it could not be recovered from the transaction.
+    UnexpectedNote(NoteId),
+    /// The wallet attributes the note to a different account than the one whose keys recovered
+    /// it.
+    Account {
+        note_id: NoteId,
+        stored: AccountId,
+        recovered: AccountId,
+    },
+    /// The wallet's record of the note's value differs from the recovered value.
+    Value {
+        note_id: NoteId,
+        stored: NonNegativeAmount,
+        recovered: NonNegativeAmount,
+    },
+    /// The wallet's record of the note's memo differs from the recovered memo.
+    Memo {
+        note_id: NoteId,
+        stored: MemoBytes,
+        recovered: MemoBytes,
+    },
+}
+
+/// The result of auditing the wallet's records of a single transaction.
+///
+/// This is returned by [`WalletRead::audit_transaction`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditReport<AccountId> {
+    txid: TxId,
+    notes_recovered: usize,
+    mismatches: Vec<AuditMismatch<AccountId>>,
+}
+
+impl<AccountId> AuditReport<AccountId> {
+    /// Constructs an `AuditReport` from its constituent parts.
+    pub fn from_parts(
+        txid: TxId,
+        notes_recovered: usize,
+        mismatches: Vec<AuditMismatch<AccountId>>,
+    ) -> Self {
+        Self {
+            txid,
+            notes_recovered,
+            mismatches,
+        }
+    }
+
+    /// Returns the ID of the audited transaction.
+    pub fn txid(&self) -> TxId {
+        self.txid
+    }
+
+    /// Returns the number of notes received by the wallet that were recovered by re-decrypting
+    /// the transaction.
+    pub fn notes_recovered(&self) -> usize {
+        self.notes_recovered
+    }
+
+    /// Returns the discrepancies found between the recovered notes and the wallet's records.
+    pub fn mismatches(&self) -> &[AuditMismatch<AccountId>] {
+        &self.mismatches
+    }
+
+    /// Returns whether the wallet's records of the transaction are consistent with its
+    /// re-decryption.
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// The net flow of value into and out of the wallet's holdings in each value pool as a result of
+/// a single transaction.
+///
+/// The flow for each pool is the total value of the transaction's outputs in that pool that
+/// belong to the wallet, minus the total value of the wallet's outputs in that pool that are
+/// spent by the transaction. A negative flow therefore indicates that value left the wallet's
+/// holdings in the pool, either to another recipient, to another pool, or to pay the fee.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolFlows {
+    transparent: Amount,
+    sapling: Amount,
+    orchard: Amount,
+    fee_paid: Option<NonNegativeAmount>,
+}
+
+impl PoolFlows {
+    /// Constructs a new [`PoolFlows`] from its constituent parts.
+    pub fn from_parts(
+        transparent: Amount,
+        sapling: Amount,
+        orchard: Amount,
+        fee_paid: Option<NonNegativeAmount>,
+    ) -> Self {
+        Self {
+            transparent,
+            sapling,
+            orchard,
+            fee_paid,
+        }
+    }
+
+    /// Returns the net flow of value into the wallet's transparent holdings.
+    pub fn transparent(&self) -> Amount {
+        self.transparent
+    }
+    /// Returns the net flow of value into the wallet's Sapling holdings.
+    pub fn sapling(&self) -> Amount {
+        self.sapling
+    }
+    /// Returns the net flow of value into the wallet's Orchard holdings.
+    pub fn orchard(&self) -> Amount {
+        self.orchard
+    }
+    /// Returns the net flow of value into the wallet's holdings in the given pool.
+    pub fn for_pool(&self, pool: PoolType) -> Amount {
+        match pool {
+            PoolType::Transparent => self.transparent,
+            PoolType::Shielded(ShieldedProtocol::Sapling) => self.sapling,
+            PoolType::Shielded(ShieldedProtocol::Orchard) => self.orchard,
+        }
+    }
+    /// Returns the fee paid by the transaction, if it could be determined.
+    ///
+    /// See [`WalletRead::get_transaction_fee`].
+    pub fn fee_paid(&self) -> Option<NonNegativeAmount> {
+        self.fee_paid
+    }
+    /// Returns the net change in the wallet's holdings across all pools, or `None` if the sum
+    /// is outside the valid monetary range.
+    pub fn total(&self) -> Option<Amount> {
+        self.transparent + self.sapling + self.orchard
+    }
+}
+
+/// An address that the wallet has been asked to watch via [`WalletWrite::add_watched_address`].
+///
+/// No viewing key is associated with a watched address, so the wallet can only track activity
+/// involving its transparent receiver (if any). Funds sent to a watched address's shielded
+/// receivers cannot be detected without the corresponding viewing key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchedAddress {
+    address: Address,
+}
+
+impl WatchedAddress {
+    /// Constructs a new [`WatchedAddress`] for the given address.
+    pub fn new(address: Address) -> Self {
+        Self { address }
+    }
+
+    /// Returns the address being watched.
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Returns the transparent receiver of the watched address, if any. This is the only
+    /// receiver for which the wallet is able to track activity.
+    pub fn transparent_receiver(&self) -> Option<&TransparentAddress> {
+        match &self.address {
+            Address::Sapling(_) => None,
+            Address::Transparent(taddr) => Some(taddr),
+            Address::Unified(ua) => ua.transparent(),
+        }
+    }
+
+    /// Returns `true` if the watched address has shielded receivers, to which funds may be sent
+    /// that the wallet cannot detect without a viewing key.
+    pub fn requires_viewing_key(&self) -> bool {
+        match &self.address {
+            Address::Sapling(_) => true,
+            Address::Transparent(_) => false,
+            Address::Unified(ua) => ua.has_sapling() || ua.has_orchard(),
+        }
+    }
+}
+
+/// A prefix against which the memos of received notes can be matched, for use with
+/// [`WalletRead::get_received_notes_matching_memo`].
+///
+/// Text memos and arbitrary-data memos are matched distinctly: a [`MemoPrefix::Text`] prefix
+/// only matches memos that decode as valid UTF-8 text, and a [`MemoPrefix::Bytes`] prefix only
+/// matches [ZIP 302] arbitrary-data memos, against the data that follows the `0xFF` type byte.
+///
+/// [ZIP 302]: https://zips.z.cash/zip-0302
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MemoPrefix {
+    /// Matches text memos that begin with the given string.
+    Text(String),
+    /// Matches arbitrary-data memos whose data begins with the given bytes.
+    Bytes(Vec<u8>),
+}
+
+impl MemoPrefix {
+    /// Returns `true` if the given memo begins with this prefix.
+    pub fn matches(&self, memo: &MemoBytes) -> bool {
+        match (self, Memo::try_from(memo)) {
+            (MemoPrefix::Text(prefix), Ok(Memo::Text(text))) => text.starts_with(prefix.as_str()),
+            (MemoPrefix::Bytes(prefix), Ok(Memo::Arbitrary(data))) => {
+                data.starts_with(prefix.as_slice())
+            }
+            _ => false,
+        }
+    }
+}
+
 /// A data structure used to set the birthday height for an account, and ensure that the initial
 /// note commitment tree state is recorded at that height.
 #[derive(Clone, Debug)]
@@ -1533,6 +2752,22 @@ pub trait WalletWrite: WalletRead {
         birthday: &AccountBirthday,
     ) -> Result<(Self::Account, UnifiedSpendingKey), Self::Error>;
 
+    /// Returns the account derived from the given seed at the specified ZIP 32 account index,
+    /// creating it as if by [`WalletWrite::import_account_hd`] if it does not already exist.
+    ///
+    /// This permits callers to idempotently initialize a wallet's accounts: repeated calls
+    /// with the same seed and account index return the same account, rather than failing or
+    /// creating a duplicate account. If the account already exists, `birthday` is ignored.
+    ///
+    /// Returns the account along with the unified spending key derived from the seed for the
+    /// given account index.
+    fn get_or_create_account(
+        &mut self,
+        seed: &SecretVec<u8>,
+        account_index: zip32::AccountId,
+        birthday: &AccountBirthday,
+    ) -> Result<(Self::Account, UnifiedSpendingKey), Self::Error>;
+
     /// Tells the wallet to track an account using a unified full viewing key.
     ///
     /// Returns the account for the newly-created wallet database entry.
@@ -1592,6 +2827,27 @@ pub trait WalletWrite: WalletRead {
         diversifier_index: DiversifierIndex,
     ) -> Result<UnifiedAddress, Self::Error>;
 
+    /// Discovers diversified shielded addresses of the specified account that have received
+    /// funds, and persists them as addresses of the account.
+    ///
+    /// For each diversifier index in `index_range`, the wallet checks whether any shielded note
+    /// that it has decrypted for the account was received at the account's external Sapling or
+    /// Orchard receiver at that index. Each such address that is not already known to the wallet
+    /// is persisted as if by [`Self::put_address_with_diversifier_index`]. This is intended to be
+    /// used after restoring a wallet from seed (and scanning the chain), so that funds received
+    /// at non-default diversified addresses are attributed to the correct addresses.
+    ///
+    /// Returns the newly-persisted addresses in order of increasing diversifier index, or an
+    /// error if the account identifier does not correspond to a known account. Note that
+    /// discovering an address at a diversifier index higher than that of the current address
+    /// causes subsequent calls to [`Self::get_next_available_address`] to generate addresses
+    /// after the discovered one.
+    fn discover_shielded_addresses(
+        &mut self,
+        account: Self::AccountId,
+        index_range: Range<u32>,
+    ) -> Result<Vec<UnifiedAddress>, Self::Error>;
+
     /// Updates the wallet's view of the blockchain.
     ///
     /// This method is used to provide the wallet with information about the state of the
@@ -1615,7 +2871,35 @@ pub trait WalletWrite: WalletRead {
         blocks: Vec<ScannedBlock<Self::AccountId>>,
     ) -> Result<(), Self::Error>;
 
+    /// Updates the state of the wallet database by persisting the provided block information,
+    /// where the blocks were scanned using the viewing keys of only a subset of the wallet's
+    /// accounts.
+    ///
+    /// This behaves identically to [`Self::put_blocks`], except that the scanned range must not
+    /// be recorded as fully scanned: any part of it that had not previously been scanned must
+    /// continue to be returned by [`WalletRead::suggest_scan_ranges`], so that it will be
+    /// scanned again for the remaining accounts.
+    ///
+    /// ### Arguments
+    /// - `from_state` must be the chain state for the block height prior to the first
+    ///   block in `blocks`.
+    /// - `blocks` must be sequential, in order of increasing block height.
+    fn put_partially_scanned_blocks(
+        &mut self,
+        from_state: &ChainState,
+        blocks: Vec<ScannedBlock<Self::AccountId>>,
+    ) -> Result<(), Self::Error>;
+
     /// Adds a transparent UTXO received by the wallet to the data store.
+    ///
+    /// The UTXO is attributed to the account whose transparent receiver matches its
+    /// `script_pubkey`; an error is returned if no such account exists. This operation is
+    /// idempotent: if the UTXO is already present it is updated in place, and the same
+    /// `UtxoRef` is returned. If the UTXO has already been spent by a transaction known
+    /// to the wallet, it is stored as spent.
+    ///
+    /// This is used to import UTXOs retrieved from a light wallet server, such as via the
+    /// `GetAddressUtxos` method.
     fn put_received_transparent_utxo(
         &mut self,
         output: &WalletTransparentOutput,
@@ -1634,6 +2918,68 @@ pub trait WalletWrite: WalletRead {
         sent_tx: &SentTransaction<Self::AccountId>,
     ) -> Result<(), Self::Error>;
 
+    /// Removes an unmined transaction that was constructed by the wallet from the persistent
+    /// wallet store, so that the notes and outputs it spent become spendable again.
+    ///
+    /// The transaction's sent output records and any outputs it sent to the wallet are
+    /// deleted. A transaction that has been mined cannot be abandoned.
+    ///
+    /// The wallet does not record whether a transaction has been broadcast, and a transaction
+    /// that has been broadcast may still be mined until it expires. Abandoning such a
+    /// transaction and then spending its inputs in a new transaction may therefore result in
+    /// one of the two transactions being rejected by the network as a double-spend, and in the
+    /// wallet's view of its balance being temporarily incorrect. For this reason, unless
+    /// `force` is `true`, implementations must refuse to abandon a transaction that has not
+    /// yet expired as of the wallet's view of the chain tip. Callers should only set `force`
+    /// if they are certain that the transaction was never broadcast.
+    ///
+    /// Abandoning a transaction that is not known to the wallet has no effect.
+    fn abandon_transaction(&mut self, txid: TxId, force: bool) -> Result<(), Self::Error>;
+
+    /// Imports externally-provided annotations for a set of transactions.
+    ///
+    /// Each annotation replaces any annotation previously stored for the same transaction ID.
+    /// Annotations may refer to transactions that are not known to the wallet; they are purely
+    /// descriptive metadata and do not affect the wallet's balance.
+    fn import_transaction_annotations(
+        &mut self,
+        entries: &[TransactionAnnotation],
+    ) -> Result<(), Self::Error>;
+
+    /// Registers an address for which the wallet holds no viewing key, so that activity
+    /// involving the address's transparent receiver may be tracked.
+    ///
+    /// The shielded receivers of the address (if any) are recorded but cannot be decrypted;
+    /// the returned [`WatchedAddress`] reports whether a viewing key would be required in order
+    /// to track shielded activity. Adding an address that is already being watched has no
+    /// effect.
+    ///
+    /// Outputs received at the address's transparent receiver are recorded when they are
+    /// provided to [`WalletWrite::put_received_transparent_utxo`], or when a mined transaction
+    /// containing them is provided to [`WalletWrite::store_decrypted_tx`], and are reported by
+    /// [`WalletRead::get_unspent_watched_outputs`].
+    fn add_watched_address(&mut self, address: &Address) -> Result<WatchedAddress, Self::Error>;
+
+    /// Locks the specified note against spending.
+    ///
+    /// Input selectors will not select a locked note as an input to a new transaction unless
+    /// explicitly permitted to do so, such as via
+    /// [`GreedyInputSelector::with_respect_locks`]. This provides coin control: a wallet may
+    /// reserve specific notes for a particular purpose. Locking a note that is already locked
+    /// has no effect; an error is returned if the note is not known to the wallet.
+    ///
+    /// The value of a locked note that would otherwise be spendable is reported by
+    /// [`Balance::locked_value`] rather than by [`Balance::spendable_value`].
+    ///
+    /// [`GreedyInputSelector::with_respect_locks`]: crate::data_api::wallet::input_selection::GreedyInputSelector::with_respect_locks
+    fn lock_note(&mut self, note_id: NoteId) -> Result<(), Self::Error>;
+
+    /// Removes any lock previously placed on the specified note via [`WalletWrite::lock_note`].
+    ///
+    /// Unlocking a note that is not locked has no effect; an error is returned if the note is
+    /// not known to the wallet.
+    fn unlock_note(&mut self, note_id: NoteId) -> Result<(), Self::Error>;
+
     /// Truncates the wallet database to the specified height.
     ///
     /// This method assumes that the state of the underlying data store is
@@ -1649,6 +2995,35 @@ pub trait WalletWrite: WalletRead {
     /// There may be restrictions on heights to which it is possible to truncate.
     fn truncate_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error>;
 
+    /// Clears the flag indicating that the wallet must be rescanned.
+    ///
+    /// This should be called once the application has acted upon the height returned by
+    /// [`WalletRead::rescan_required`], for example by truncating the wallet to a height
+    /// below it and rescanning. Clearing the flag when it is not set has no effect.
+    fn clear_rescan_flag(&mut self) -> Result<(), Self::Error>;
+
+    /// Replaces the note commitment tree of a shielded pool with the given tree, and updates
+    /// the positions of the wallet's notes in that pool to correspond to it.
+    ///
+    /// This is intended for use after the tree has been rebuilt, for example by rescanning
+    /// the chain after the wallet's tree was found to be corrupted. The shards, cap, and
+    /// checkpoints of the wallet's existing tree for the pool of `new_tree` are discarded in
+    /// favor of those of `new_tree`, and the note commitment tree position of each note in
+    /// `note_positions` is set to the given position. Notes of that pool that are not included
+    /// in `note_positions` no longer have a position in the tree, and so cannot be witnessed
+    /// until a rescan recovers their positions; implementations should record that such a
+    /// rescan is required, as reported by [`WalletRead::rescan_required`].
+    ///
+    /// Implementations must apply the replacement atomically: if any part of it fails, the
+    /// wallet's existing tree and note positions must be left unchanged, and readers must
+    /// never observe the new tree alongside the old note positions or vice versa. Each note in
+    /// `note_positions` must be a note of the pool of `new_tree` that is known to the wallet.
+    fn replace_commitment_tree(
+        &mut self,
+        new_tree: CommitmentTreeReplacement,
+        note_positions: &[(NoteId, Position)],
+    ) -> Result<(), Self::Error>;
+
     /// Records the last block that was scanned for transparent transactions.
     #[cfg(feature = "transparent-inputs")]
     fn put_latest_scanned_block_for_transparent(
@@ -1672,6 +3047,13 @@ pub trait WalletCommitmentTrees {
         Error = Self::Error,
     >;
 
+    /// Returns the number of note commitment tree checkpoints retained by the wallet.
+    ///
+    /// The wallet can only be rewound to a height for which it retains a checkpoint; as a
+    /// checkpoint is created for each scanned block, this bounds the depth of a chain reorg
+    /// that the wallet can recover from by rewinding.
+    fn checkpoint_retention(&self) -> usize;
+
     /// Evaluates the given callback function with a reference to the Sapling
     /// note commitment tree maintained by the wallet.
     fn with_sapling_tree_mut<F, A, E>(&mut self, callback: F) -> Result<A, E>
@@ -1731,12 +3113,12 @@ pub trait WalletCommitmentTrees {
 
 #[cfg(feature = "test-dependencies")]
 pub mod testing {
-    use incrementalmerkletree::Address;
+    use incrementalmerkletree::{Address, Position};
     use secrecy::{ExposeSecret, SecretVec};
     use shardtree::{error::ShardTreeError, store::memory::MemoryShardStore, ShardTree};
-    use std::{collections::HashMap, convert::Infallible, num::NonZeroU32};
+    use std::{collections::HashMap, convert::Infallible, num::NonZeroU32, ops::Range};
     use zip32::fingerprint::SeedFingerprint;
-    use zip32::DiversifierIndex;
+    use zip32::{DiversifierIndex, Scope};
 
     use zcash_primitives::{
         block::BlockHash,
@@ -1746,24 +3128,27 @@ pub mod testing {
     };
 
     use crate::{
-        address::UnifiedAddress,
+        address::{Address, AddressFormat, UnifiedAddress},
         keys::{UnifiedAddressRequest, UnifiedFullViewingKey, UnifiedSpendingKey},
         wallet::{Note, NoteId, ReceivedNote, WalletTransparentOutput},
         ShieldedProtocol,
     };
 
     use super::{
-        chain::{ChainState, CommitmentTreeRoot},
+        chain::{self, ChainState, CommitmentTreeRoot},
         scanning::ScanRange,
-        AccountBirthday, BlockMetadata, DecryptedTransaction, InputSource, NullifierQuery,
-        ScannedBlock, SeedRelevance, SentTransaction, SpendableNotes, WalletCommitmentTrees,
-        WalletRead, WalletSummary, WalletWrite, SAPLING_SHARD_HEIGHT,
+        AccountBirthday, AccountInfo, AuditReport, BlockMetadata, CommitmentTreeReplacement,
+        DecryptedTransaction, InputSource, NoteWitness, NullifierQuery, PoolFlows, ScannedBlock,
+        SeedRelevance, SentTransaction, SpendableNotes, TransactionAnnotation,
+        WalletCommitmentTrees, WalletRead, WalletSummary, WalletWrite, WatchOnlyBundle,
+        WatchedAddress, SAPLING_SHARD_HEIGHT,
     };
 
     #[cfg(feature = "transparent-inputs")]
     use {
-        super::TransparentAddressSyncInfo, crate::wallet::TransparentAddressMetadata,
-        zcash_primitives::legacy::TransparentAddress,
+        super::{SpendStatus, TransparentAddressSyncInfo},
+        crate::wallet::TransparentAddressMetadata,
+        zcash_primitives::{legacy::TransparentAddress, transaction::components::OutPoint},
     };
 
     #[cfg(feature = "orchard")]
@@ -1830,6 +3215,10 @@ pub mod testing {
             Ok(Vec::new())
         }
 
+        fn list_accounts(&self) -> Result<Vec<AccountInfo<Self::AccountId>>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn get_account(
             &self,
             _account_id: Self::AccountId,
@@ -1853,6 +3242,14 @@ pub mod testing {
             Ok(false)
         }
 
+        fn derive_usk(
+            &self,
+            _account_id: Self::AccountId,
+            _seed: &SecretVec<u8>,
+        ) -> Result<UnifiedSpendingKey, Self::Error> {
+            Err(())
+        }
+
         fn seed_relevance_to_derived_accounts(
             &self,
             _seed: &SecretVec<u8>,
@@ -1874,6 +3271,43 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_current_address_encoded(
+            &self,
+            _account: Self::AccountId,
+            _format: AddressFormat,
+        ) -> Result<Option<String>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_internal_address(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Option<UnifiedAddress>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_max_diversifier_index(
+            &self,
+            _account: Self::AccountId,
+            _scope: Scope,
+        ) -> Result<Option<DiversifierIndex>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_auto_discovered_addresses(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Vec<UnifiedAddress>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_reused_addresses(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Vec<(Address, usize)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn get_account_birthday(
             &self,
             _account: Self::AccountId,
@@ -1881,6 +3315,13 @@ pub mod testing {
             Err(())
         }
 
+        fn export_account_for_watching(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Option<WatchOnlyBundle>, Self::Error> {
+            Ok(None)
+        }
+
         fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
             Ok(None)
         }
@@ -1892,6 +3333,15 @@ pub mod testing {
             Ok(None)
         }
 
+        fn projected_spendable_balance(
+            &self,
+            _account: Self::AccountId,
+            _at_height: BlockHeight,
+            _min_confirmations: NonZeroU32,
+        ) -> Result<NonNegativeAmount, Self::Error> {
+            Ok(NonNegativeAmount::ZERO)
+        }
+
         fn chain_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
             Ok(None)
         }
@@ -1903,6 +3353,10 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_tree_sizes(&self, _height: BlockHeight) -> Result<Option<(u32, u32)>, Self::Error> {
+            Ok(None)
+        }
+
         fn block_metadata(
             &self,
             _height: BlockHeight,
@@ -1922,10 +3376,24 @@ pub mod testing {
             Ok(None)
         }
 
+        fn rescan_required(&self) -> Result<Option<BlockHeight>, Self::Error> {
+            Ok(None)
+        }
+
         fn suggest_scan_ranges(&self) -> Result<Vec<ScanRange>, Self::Error> {
             Ok(vec![])
         }
 
+        fn suggest_download_ranges<F, E>(
+            &self,
+            _cached_tip: F,
+        ) -> Result<Vec<Range<BlockHeight>>, chain::error::Error<Self::Error, E>>
+        where
+            F: FnMut(&ScanRange) -> Result<Option<BlockHeight>, E>,
+        {
+            Ok(vec![])
+        }
+
         fn get_target_and_anchor_heights(
             &self,
             _min_confirmations: NonZeroU32,
@@ -1941,6 +3409,10 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_transaction_time(&self, _txid: TxId) -> Result<Option<u32>, Self::Error> {
+            Ok(None)
+        }
+
         fn get_unified_full_viewing_keys(
             &self,
         ) -> Result<HashMap<Self::AccountId, UnifiedFullViewingKey>, Self::Error> {
@@ -1955,6 +3427,153 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_transaction_bytes(&self, _txid: TxId) -> Result<Option<Vec<u8>>, Self::Error> {
+            Ok(None)
+        }
+
+        fn audit_transaction(
+            &self,
+            txid: TxId,
+        ) -> Result<AuditReport<Self::AccountId>, Self::Error> {
+            Ok(AuditReport::from_parts(txid, 0, vec![]))
+        }
+
+        fn get_rebroadcast_candidates(&self) -> Result<Vec<TxId>, Self::Error> {
+            Ok(vec![])
+        }
+
+        fn get_unmined_transactions(&self) -> Result<Vec<TxId>, Self::Error> {
+            Ok(vec![])
+        }
+
+        fn get_transaction_fee(
+            &self,
+            _txid: TxId,
+        ) -> Result<Option<NonNegativeAmount>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_transaction_pool_flows(
+            &self,
+            _txid: TxId,
+        ) -> Result<Option<PoolFlows>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_spent_notes(&self, _txid: TxId) -> Result<Vec<NoteId>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_locked_notes(&self, _account: Self::AccountId) -> Result<Vec<NoteId>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_transactions_spending_pool(
+            &self,
+            _pool: ShieldedProtocol,
+            _range: Range<BlockHeight>,
+        ) -> Result<Vec<TxId>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_fees_paid(
+            &self,
+            _range: Range<BlockHeight>,
+        ) -> Result<Vec<(TxId, NonNegativeAmount)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_spent_note_positions(
+            &self,
+            _txid: TxId,
+        ) -> Result<Vec<(ShieldedProtocol, Position)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_note_scope(&self, _note_id: NoteId) -> Result<Option<Scope>, Self::Error> {
+            Ok(None)
+        }
+
+        fn blocks_until_spendable(
+            &self,
+            _note_id: NoteId,
+            _min_confirmations: NonZeroU32,
+        ) -> Result<Option<u32>, Self::Error> {
+            Ok(None)
+        }
+
+        fn unspent_set_fingerprint(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<[u8; 32], Self::Error> {
+            Ok([0; 32])
+        }
+
+        fn get_witnesses(
+            &self,
+            _note_ids: &[NoteId],
+            _anchor_height: BlockHeight,
+        ) -> Result<Vec<(NoteId, NoteWitness)>, Self::Error> {
+            Err(())
+        }
+
+        fn get_received_notes_matching_memo<F>(
+            &self,
+            _account: Self::AccountId,
+            _predicate: F,
+        ) -> Result<Vec<NoteId>, Self::Error>
+        where
+            F: Fn(&MemoBytes) -> bool,
+        {
+            Ok(Vec::new())
+        }
+
+        fn get_notes_pending_spend(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Vec<(NoteId, TxId)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn iter_received_notes<F, E>(
+            &self,
+            _account: Self::AccountId,
+            _sources: &[ShieldedProtocol],
+            _callback: F,
+        ) -> Result<(), E>
+        where
+            F: FnMut(ReceivedNote<NoteId, Note>) -> Result<(), E>,
+            E: From<Self::Error>,
+        {
+            Ok(())
+        }
+
+        fn get_change_notes(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Vec<ReceivedNote<NoteId, Note>>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_newly_confirmed_notes(
+            &self,
+            _since_height: BlockHeight,
+            _min_confirmations: NonZeroU32,
+        ) -> Result<Vec<NoteId>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_transaction_annotation(
+            &self,
+            _txid: TxId,
+        ) -> Result<Option<TransactionAnnotation>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_watched_addresses(&self) -> Result<Vec<WatchedAddress>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn get_sapling_nullifiers(
             &self,
             _query: NullifierQuery,
@@ -1988,6 +3607,14 @@ pub mod testing {
             Ok(HashMap::new())
         }
 
+        #[cfg(feature = "transparent-inputs")]
+        fn get_transparent_outpoints(
+            &self,
+            _account: Self::AccountId,
+        ) -> Result<Vec<(OutPoint, WalletTransparentOutput, SpendStatus)>, Self::Error> {
+            Ok(vec![])
+        }
+
         #[cfg(feature = "transparent-inputs")]
         fn get_transparent_addresses_and_sync_heights(
             &mut self,
@@ -2019,6 +3646,15 @@ pub mod testing {
             todo!()
         }
 
+        fn get_or_create_account(
+            &mut self,
+            _seed: &SecretVec<u8>,
+            _account_index: zip32::AccountId,
+            _birthday: &AccountBirthday,
+        ) -> Result<(Self::Account, UnifiedSpendingKey), Self::Error> {
+            Err(())
+        }
+
         fn import_account_ufvk(
             &mut self,
             _unified_key: &UnifiedFullViewingKey,
@@ -2044,6 +3680,14 @@ pub mod testing {
             todo!()
         }
 
+        fn discover_shielded_addresses(
+            &mut self,
+            _account: Self::AccountId,
+            _index_range: Range<u32>,
+        ) -> Result<Vec<UnifiedAddress>, Self::Error> {
+            Ok(vec![])
+        }
+
         #[allow(clippy::type_complexity)]
         fn put_blocks(
             &mut self,
@@ -2053,6 +3697,14 @@ pub mod testing {
             Ok(())
         }
 
+        fn put_partially_scanned_blocks(
+            &mut self,
+            _from_state: &ChainState,
+            _blocks: Vec<ScannedBlock<Self::AccountId>>,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
         fn update_chain_tip(&mut self, _tip_height: BlockHeight) -> Result<(), Self::Error> {
             Ok(())
         }
@@ -2071,10 +3723,48 @@ pub mod testing {
             Ok(())
         }
 
+        fn abandon_transaction(&mut self, _txid: TxId, _force: bool) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn import_transaction_annotations(
+            &mut self,
+            _entries: &[TransactionAnnotation],
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn add_watched_address(
+            &mut self,
+            address: &crate::address::Address,
+        ) -> Result<WatchedAddress, Self::Error> {
+            Ok(WatchedAddress::new(address.clone()))
+        }
+
+        fn lock_note(&mut self, _note_id: NoteId) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn unlock_note(&mut self, _note_id: NoteId) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
         fn truncate_to_height(&mut self, _block_height: BlockHeight) -> Result<(), Self::Error> {
             Ok(())
         }
 
+        fn clear_rescan_flag(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn replace_commitment_tree(
+            &mut self,
+            _new_tree: CommitmentTreeReplacement,
+            _note_positions: &[(NoteId, Position)],
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
         /// Adds a transparent UTXO received by the wallet to the data store.
         fn put_received_transparent_utxo(
             &mut self,
@@ -2097,6 +3787,10 @@ pub mod testing {
         type Error = Infallible;
         type SaplingShardStore<'a> = MemoryShardStore<sapling::Node, BlockHeight>;
 
+        fn checkpoint_retention(&self) -> usize {
+            100
+        }
+
         fn with_sapling_tree_mut<F, A, E>(&mut self, mut callback: F) -> Result<A, E>
         where
             for<'a> F: FnMut(
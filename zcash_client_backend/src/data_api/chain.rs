@@ -72,7 +72,8 @@
 //!                 &mut wallet_db,
 //!                 scan_range.block_range().start,
 //!                 chain_state,
-//!                 scan_range.len()
+//!                 scan_range.len(),
+//!                 None,
 //!             );
 //!
 //!             // Check for scanning errors that indicate that the wallet's chain tip is out of
@@ -141,7 +142,8 @@
 //!         &mut wallet_db,
 //!         scan_range.block_range().start,
 //!         chain_state,
-//!         scan_range.len()
+//!         scan_range.len(),
+//!         None,
 //!     )?;
 //!
 //!     // Handle scan errors, etc.
@@ -151,7 +153,7 @@
 //! # }
 //! ```
 
-use std::ops::Range;
+use std::{num::NonZeroUsize, ops::Range};
 
 use incrementalmerkletree::frontier::Frontier;
 use subtle::ConditionallySelectable;
@@ -161,9 +163,9 @@ use zcash_primitives::{
 };
 
 use crate::{
-    data_api::{NullifierQuery, WalletWrite},
+    data_api::{NullifierQuery, ScannedBlock, WalletWrite},
     proto::compact_formats::CompactBlock,
-    scanning::{scan_block_with_runners, BatchRunners, Nullifiers, ScanningKeys},
+    scanning::{scan_block_with_runners, BatchOptions, BatchRunners, Nullifiers, ScanningKeys},
 };
 
 #[cfg(feature = "sync")]
@@ -171,6 +173,14 @@ use {
     super::scanning::ScanPriority, crate::data_api::scanning::ScanRange, async_trait::async_trait,
 };
 
+#[cfg(feature = "transparent-inputs")]
+use {
+    super::{DecryptedTransaction, InputSource},
+    crate::wallet::WalletTransparentOutput,
+    std::collections::BTreeSet,
+    zcash_primitives::transaction::{components::OutPoint, Transaction},
+};
+
 pub mod error;
 use error::Error;
 
@@ -229,6 +239,33 @@ pub trait BlockSource {
         F: FnMut(CompactBlock) -> Result<(), error::Error<WalletErrT, Self::Error>>;
 }
 
+/// This trait provides sequential access to full (non-compact) blocks via a callback-oriented
+/// API.
+///
+/// Compact blocks omit transparent inputs and outputs, so a source of full block data is
+/// required in order to detect transparent activity involving the wallet's addresses. See
+/// [`scan_transparent_blocks`].
+#[cfg(feature = "transparent-inputs")]
+pub trait FullBlockSource {
+    type Error;
+
+    /// Applies the provided callback to the height and transactions of each block within the
+    /// given range of heights, in order of increasing height.
+    ///
+    /// * `WalletErrT`: the types of errors produced by the wallet operations performed
+    ///   as part of processing each block.
+    fn with_full_blocks<F, WalletErrT>(
+        &self,
+        range: Range<BlockHeight>,
+        with_block: F,
+    ) -> Result<(), error::Error<WalletErrT, Self::Error>>
+    where
+        F: FnMut(
+            BlockHeight,
+            Vec<Transaction>,
+        ) -> Result<(), error::Error<WalletErrT, Self::Error>>;
+}
+
 /// `BlockCache` is a trait that extends `BlockSource` and defines methods for managing
 /// a cache of compact blocks.
 ///
@@ -440,13 +477,16 @@ pub struct ScanSummary {
     pub(crate) spent_orchard_note_count: usize,
     #[cfg(feature = "orchard")]
     pub(crate) received_orchard_note_count: usize,
+    pub(crate) end_state: ChainState,
 }
 
 impl ScanSummary {
-    /// Constructs a new [`ScanSummary`] for the provided block range.
-    pub(crate) fn for_range(scanned_range: Range<BlockHeight>) -> Self {
+    /// Constructs a new [`ScanSummary`] for the provided block range, starting from the given
+    /// chain state.
+    pub(crate) fn for_range(scanned_range: Range<BlockHeight>, end_state: ChainState) -> Self {
         Self {
             scanned_range,
+            end_state,
             spent_sapling_note_count: 0,
             received_sapling_note_count: 0,
             #[cfg(feature = "orchard")]
@@ -494,6 +534,16 @@ impl ScanSummary {
     pub fn received_orchard_note_count(&self) -> usize {
         self.received_orchard_note_count
     }
+
+    /// Returns the chain state as of the end of the last block in the scanned range.
+    ///
+    /// If no blocks were scanned, this is the chain state that was provided to
+    /// [`scan_cached_blocks`]. This may be passed as the `from_state` argument of a subsequent
+    /// call to [`scan_cached_blocks`] in order to resume scanning from the next block without
+    /// needing to retrieve the note commitment tree state from a light wallet server.
+    pub fn end_state(&self) -> &ChainState {
+        &self.end_state
+    }
 }
 
 /// The final note commitment tree state for each shielded pool, as of a particular block height.
@@ -574,6 +624,27 @@ impl ChainState {
 /// update their UI with scanning progress. Repeatedly calling this function with `from_height ==
 /// None` will process sequential ranges of blocks.
 ///
+/// If `to_height` is provided, no block above that height will be scanned, regardless of the
+/// blocks available from the block source. This permits a wallet to maintain a buffer of recent,
+/// potentially unstable blocks behind the chain tip that are never committed to the wallet.
+///
+/// ## Resumption
+///
+/// The results of scanning are committed to the wallet in a single call to
+/// [`WalletWrite::put_blocks`], which records the scanned blocks, the decrypted notes and the
+/// note commitment tree state as of the end of the last scanned block atomically. If this
+/// function is interrupted (for example because the application is terminated by the operating
+/// system) before that call completes, none of the blocks in the range are recorded as scanned
+/// and no partial state is persisted. Each successful return is therefore a checkpoint on a block
+/// boundary: scanning may be resumed after a restart by calling this function with
+/// `from_height` set to the end of the returned [`ScanSummary::scanned_range`] and `from_state`
+/// set to [`ScanSummary::end_state`] (or to the equivalent tree state retrieved from a light
+/// wallet server). Callers that are frequently interrupted should use a smaller `limit` in order
+/// to reduce the amount of work that may be lost.
+///
+/// To commit the results of scanning a large range at regular intervals instead, use
+/// [`scan_cached_blocks_with_commit_interval`].
+///
 /// ## Panics
 ///
 /// This method will panic if `from_height != from_state.block_height() + 1`.
@@ -586,6 +657,200 @@ pub fn scan_cached_blocks<ParamsT, DbT, BlockSourceT>(
     from_height: BlockHeight,
     from_state: &ChainState,
     limit: usize,
+    to_height: Option<BlockHeight>,
+) -> Result<ScanSummary, Error<DbT::Error, BlockSourceT::Error>>
+where
+    ParamsT: consensus::Parameters + Send + 'static,
+    BlockSourceT: BlockSource,
+    DbT: WalletWrite,
+    <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+{
+    scan_cached_blocks_internal(
+        params,
+        block_source,
+        data_db,
+        from_height,
+        from_state,
+        limit,
+        to_height,
+        None,
+        None,
+        &BatchOptions::default(),
+    )
+}
+
+/// Scans at most `limit` blocks from the provided block source, committing the results of
+/// scanning to the wallet after every `commit_interval_blocks` blocks.
+///
+/// This behaves identically to [`scan_cached_blocks`], except that instead of committing the
+/// results of scanning the entire range in a single call to [`WalletWrite::put_blocks`], the
+/// scanned blocks are buffered and committed in batches of `commit_interval_blocks` blocks (with
+/// a final, possibly smaller, batch at the end of the range). This makes it possible to scan a
+/// large range of blocks during initial sync using a large `limit`, while bounding both the
+/// memory used to buffer scan results and the amount of work that is lost if scanning is
+/// interrupted.
+///
+/// ## Crash recovery
+///
+/// Each batch is committed atomically, and batches always end on a block boundary. If this
+/// function is interrupted, every batch committed before the interruption is retained and none
+/// of the blocks of the batch in progress are recorded as scanned, so at most
+/// `commit_interval_blocks` blocks need to be rescanned after a restart. The wallet's scan queue
+/// reflects the committed batches, so [`WalletRead::suggest_scan_ranges`] will return the
+/// remaining unscanned portion of the range. Larger intervals result in fewer, larger database
+/// transactions (and therefore faster sync) at the cost of a larger window of work that may be
+/// lost.
+///
+/// If an error is returned, the batches committed before the error occurred remain in the
+/// wallet.
+///
+/// ## Panics
+///
+/// This method will panic if `from_height != from_state.block_height() + 1`.
+#[tracing::instrument(skip(params, block_source, data_db, from_state))]
+#[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
+pub fn scan_cached_blocks_with_commit_interval<ParamsT, DbT, BlockSourceT>(
+    params: &ParamsT,
+    block_source: &BlockSourceT,
+    data_db: &mut DbT,
+    from_height: BlockHeight,
+    from_state: &ChainState,
+    limit: usize,
+    to_height: Option<BlockHeight>,
+    commit_interval_blocks: NonZeroUsize,
+) -> Result<ScanSummary, Error<DbT::Error, BlockSourceT::Error>>
+where
+    ParamsT: consensus::Parameters + Send + 'static,
+    BlockSourceT: BlockSource,
+    DbT: WalletWrite,
+    <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+{
+    scan_cached_blocks_internal(
+        params,
+        block_source,
+        data_db,
+        from_height,
+        from_state,
+        limit,
+        to_height,
+        Some(commit_interval_blocks),
+        None,
+        &BatchOptions::default(),
+    )
+}
+
+/// Scans at most `limit` blocks from the provided block source in order to find transactions
+/// received by the specified subset of the accounts tracked in the provided wallet database.
+///
+/// This behaves identically to [`scan_cached_blocks`], except that only the viewing keys of the
+/// given accounts are used for trial decryption, which reduces the cost of scanning when the
+/// caller is only interested in (for example) a newly-imported account. Because the other
+/// accounts' notes are not detected, the results of scanning are committed using
+/// [`WalletWrite::put_partially_scanned_blocks`], and so the scanned range is not recorded as
+/// fully scanned; it will continue to be suggested by [`WalletRead::suggest_scan_ranges`] until
+/// it has been scanned with the keys of all of the wallet's accounts.
+///
+/// Accounts in `accounts` that are not known to the wallet are ignored.
+///
+/// ## Panics
+///
+/// This method will panic if `from_height != from_state.block_height() + 1`.
+#[tracing::instrument(skip(params, block_source, data_db, from_state))]
+#[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
+pub fn scan_cached_blocks_for_accounts<ParamsT, DbT, BlockSourceT>(
+    params: &ParamsT,
+    block_source: &BlockSourceT,
+    data_db: &mut DbT,
+    from_height: BlockHeight,
+    from_state: &ChainState,
+    limit: usize,
+    to_height: Option<BlockHeight>,
+    accounts: &[<DbT as WalletRead>::AccountId],
+) -> Result<ScanSummary, Error<DbT::Error, BlockSourceT::Error>>
+where
+    ParamsT: consensus::Parameters + Send + 'static,
+    BlockSourceT: BlockSource,
+    DbT: WalletWrite,
+    <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+{
+    scan_cached_blocks_internal(
+        params,
+        block_source,
+        data_db,
+        from_height,
+        from_state,
+        limit,
+        to_height,
+        None,
+        Some(accounts),
+        &BatchOptions::default(),
+    )
+}
+
+/// Scans at most `limit` blocks from the provided block source, using the given options to
+/// control the batched trial decryption of the blocks' outputs.
+///
+/// This behaves identically to [`scan_cached_blocks`], except that the outputs of the scanned
+/// blocks are trial-decrypted in batches configured by `batch_options`, rather than with a
+/// fixed batch size threshold of 100 outputs. This permits the caller to tune the batch size
+/// for the device on which it is running (or to tune it adaptively), to bound the duration of
+/// each decryption call, or to bound the memory used by running batches. See
+/// [`BatchOptions`] for details.
+///
+/// If a memory budget is configured, this function must not be called from a thread of the
+/// global `rayon` thread pool.
+///
+/// ## Panics
+///
+/// This method will panic if `from_height != from_state.block_height() + 1`.
+#[tracing::instrument(skip(params, block_source, data_db, from_state))]
+#[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
+pub fn scan_cached_blocks_with_batch_options<ParamsT, DbT, BlockSourceT>(
+    params: &ParamsT,
+    block_source: &BlockSourceT,
+    data_db: &mut DbT,
+    from_height: BlockHeight,
+    from_state: &ChainState,
+    limit: usize,
+    to_height: Option<BlockHeight>,
+    batch_options: &BatchOptions,
+) -> Result<ScanSummary, Error<DbT::Error, BlockSourceT::Error>>
+where
+    ParamsT: consensus::Parameters + Send + 'static,
+    BlockSourceT: BlockSource,
+    DbT: WalletWrite,
+    <DbT as WalletRead>::AccountId: ConditionallySelectable + Default + Send + 'static,
+{
+    scan_cached_blocks_internal(
+        params,
+        block_source,
+        data_db,
+        from_height,
+        from_state,
+        limit,
+        to_height,
+        None,
+        None,
+        batch_options,
+    )
+}
+
+#[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
+fn scan_cached_blocks_internal<ParamsT, DbT, BlockSourceT>(
+    params: &ParamsT,
+    block_source: &BlockSourceT,
+    data_db: &mut DbT,
+    from_height: BlockHeight,
+    from_state: &ChainState,
+    limit: usize,
+    to_height: Option<BlockHeight>,
+    commit_interval_blocks: Option<NonZeroUsize>,
+    accounts: Option<&[<DbT as WalletRead>::AccountId]>,
+    batch_options: &BatchOptions,
 ) -> Result<ScanSummary, Error<DbT::Error, BlockSourceT::Error>>
 where
     ParamsT: consensus::Parameters + Send + 'static,
@@ -595,12 +860,30 @@ where
 {
     assert_eq!(from_height, from_state.block_height + 1);
 
-    // Fetch the UnifiedFullViewingKeys we are tracking
-    let account_ufvks = data_db
+    // Restrict the number of blocks to be scanned so as not to go beyond `to_height`.
+    let limit = to_height.map_or(limit, |to_height| {
+        let capped = u32::from(to_height)
+            .saturating_add(1)
+            .saturating_sub(u32::from(from_height));
+        std::cmp::min(limit, usize::try_from(capped).unwrap_or(usize::MAX))
+    });
+    if limit == 0 {
+        return Ok(ScanSummary::for_range(
+            from_height..from_height,
+            from_state.clone(),
+        ));
+    }
+
+    // Fetch the UnifiedFullViewingKeys we are tracking, restricted to the requested accounts
+    // (if any).
+    let mut account_ufvks = data_db
         .get_unified_full_viewing_keys()
         .map_err(Error::Wallet)?;
+    if let Some(accounts) = accounts {
+        account_ufvks.retain(|account_id, _| accounts.contains(account_id));
+    }
     let scanning_keys = ScanningKeys::from_account_ufvks(account_ufvks);
-    let mut runners = BatchRunners::<_, (), ()>::for_keys(100, &scanning_keys);
+    let mut runners = BatchRunners::for_keys(batch_options, &scanning_keys);
 
     block_source.with_blocks::<_, DbT::Error>(Some(from_height), Some(limit), |block| {
         runners.add_block(params, block).map_err(|e| e.into())
@@ -627,13 +910,15 @@ where
     );
 
     let mut scanned_blocks = vec![];
-    let mut scan_summary = ScanSummary::for_range(from_height..from_height);
+    // The chain state as of the end of the last committed batch of blocks.
+    let mut commit_state = from_state.clone();
+    let mut scan_summary = ScanSummary::for_range(from_height..from_height, from_state.clone());
     block_source.with_blocks::<_, DbT::Error>(
         Some(from_height),
         Some(limit),
         |block: CompactBlock| {
             scan_summary.scanned_range.end = block.height() + 1;
-            let scanned_block = scan_block_with_runners::<_, _, _, (), ()>(
+            let scanned_block = scan_block_with_runners(
                 params,
                 block,
                 &scanning_keys,
@@ -681,19 +966,192 @@ where
                 }));
             }
 
+            // Advance the end state by the note commitments of the block, so that the caller may
+            // resume scanning from the next block.
+            let end_state = &mut scan_summary.end_state;
+            end_state.block_height = scanned_block.height();
+            end_state.block_hash = scanned_block.block_hash();
+            for (node, _) in scanned_block.sapling().commitments() {
+                end_state.final_sapling_tree.append(*node);
+            }
+            #[cfg(feature = "orchard")]
+            for (node, _) in scanned_block.orchard().commitments() {
+                end_state.final_orchard_tree.append(*node);
+            }
+
             prior_block_metadata = Some(scanned_block.to_block_metadata());
             scanned_blocks.push(scanned_block);
 
+            if commit_interval_blocks.map_or(false, |n| scanned_blocks.len() >= n.get()) {
+                put_blocks(
+                    data_db,
+                    &commit_state,
+                    std::mem::take(&mut scanned_blocks),
+                    accounts.is_none(),
+                )
+                .map_err(Error::Wallet)?;
+                commit_state = scan_summary.end_state.clone();
+            }
+
             Ok(())
         },
     )?;
 
-    data_db
-        .put_blocks(from_state, scanned_blocks)
-        .map_err(Error::Wallet)?;
+    // Commit any remaining blocks. If nothing has been committed yet, this is always called so
+    // that the wallet observes the (possibly empty) scanned range.
+    if !scanned_blocks.is_empty() || commit_state.block_height == from_state.block_height {
+        put_blocks(data_db, &commit_state, scanned_blocks, accounts.is_none())
+            .map_err(Error::Wallet)?;
+    }
     Ok(scan_summary)
 }
 
+/// Commits the given scanned blocks to the wallet, recording the scanned range as fully scanned
+/// only if the blocks were scanned with the keys of all of the wallet's accounts.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        height = u32::from(from_state.block_height) + 1,
+        count = blocks.len(),
+        fully_scanned = fully_scanned,
+    )
+)]
+fn put_blocks<DbT: WalletWrite>(
+    data_db: &mut DbT,
+    from_state: &ChainState,
+    blocks: Vec<ScannedBlock<<DbT as WalletRead>::AccountId>>,
+    fully_scanned: bool,
+) -> Result<(), <DbT as WalletRead>::Error> {
+    if fully_scanned {
+        data_db.put_blocks(from_state, blocks)
+    } else {
+        data_db.put_partially_scanned_blocks(from_state, blocks)
+    }
+}
+
+/// Scans the given range of full blocks for transparent activity involving the transparent
+/// receivers of the given account, and those of the wallet's watched addresses.
+///
+/// Each transparent output received by one of these receivers is stored via
+/// [`WalletWrite::put_received_transparent_utxo`]. Each transaction that pays to, or spends
+/// outputs received by, these receivers is stored via
+/// [`WalletWrite::store_decrypted_tx`] along with the height at which it was mined, so that
+/// the outputs it spends are marked as spent.
+/// Once the range has been scanned, the last scanned block for each of the account's
+/// transparent receivers is updated via
+/// [`WalletWrite::put_latest_scanned_block_for_transparent`].
+///
+/// This complements [`scan_cached_blocks`], which can only detect shielded activity because
+/// compact blocks do not contain transparent transaction data.
+#[cfg(feature = "transparent-inputs")]
+#[tracing::instrument(skip(full_block_source, data_db))]
+#[allow(clippy::type_complexity)]
+pub fn scan_transparent_blocks<DbT, FullBlockSourceT>(
+    full_block_source: &FullBlockSourceT,
+    data_db: &mut DbT,
+    account: <DbT as WalletRead>::AccountId,
+    range: Range<BlockHeight>,
+) -> Result<(), Error<<DbT as WalletRead>::Error, FullBlockSourceT::Error>>
+where
+    FullBlockSourceT: FullBlockSource,
+    DbT: WalletWrite + InputSource<Error = <DbT as WalletRead>::Error>,
+{
+    if range.is_empty() {
+        return Ok(());
+    }
+    let max_height = range.end - 1;
+
+    let receivers = data_db
+        .get_transparent_receivers(account)
+        .map_err(Error::Wallet)?;
+    let watched_receivers = data_db
+        .get_watched_addresses()
+        .map_err(Error::Wallet)?
+        .iter()
+        .filter_map(|watched| watched.transparent_receiver().copied())
+        .collect::<BTreeSet<_>>();
+
+    // Track the outputs that the account may spend within the range, along with those
+    // received at watched addresses, so that we can detect transactions that spend them.
+    let mut wallet_outpoints = BTreeSet::new();
+    for address in receivers.keys() {
+        wallet_outpoints.extend(
+            data_db
+                .get_unspent_transparent_outputs(address, max_height, &[])
+                .map_err(Error::Wallet)?
+                .into_iter()
+                .map(|output| output.outpoint().clone()),
+        );
+    }
+    for address in &watched_receivers {
+        wallet_outpoints.extend(
+            data_db
+                .get_unspent_watched_outputs(address, max_height)
+                .map_err(Error::Wallet)?
+                .into_iter()
+                .map(|output| output.outpoint().clone()),
+        );
+    }
+
+    full_block_source.with_full_blocks::<_, <DbT as WalletRead>::Error>(
+        range,
+        |height, transactions| {
+            for tx in transactions {
+                let mut is_relevant = false;
+                if let Some(bundle) = tx.transparent_bundle() {
+                    for txin in &bundle.vin {
+                        is_relevant |= wallet_outpoints.remove(&txin.prevout);
+                    }
+
+                    for (output_index, txout) in bundle.vout.iter().enumerate() {
+                        if !txout.recipient_address().map_or(false, |addr| {
+                            receivers.contains_key(&addr) || watched_receivers.contains(&addr)
+                        }) {
+                            continue;
+                        }
+
+                        let outpoint = OutPoint::new(tx.txid().into(), output_index as u32);
+                        if let Some(output) = WalletTransparentOutput::from_parts(
+                            outpoint.clone(),
+                            txout.clone(),
+                            height,
+                            bundle.is_coinbase(),
+                        ) {
+                            data_db
+                                .put_received_transparent_utxo(&output)
+                                .map_err(Error::Wallet)?;
+                            wallet_outpoints.insert(outpoint);
+                            is_relevant = true;
+                        }
+                    }
+                }
+
+                if is_relevant {
+                    data_db
+                        .store_decrypted_tx(DecryptedTransaction::new(
+                            Some(height),
+                            &tx,
+                            vec![],
+                            #[cfg(feature = "orchard")]
+                            vec![],
+                        ))
+                        .map_err(Error::Wallet)?;
+                }
+            }
+
+            Ok(())
+        },
+    )?;
+
+    for address in receivers.keys() {
+        data_db
+            .put_latest_scanned_block_for_transparent(address, max_height)
+            .map_err(Error::Wallet)?;
+    }
+
+    Ok(())
+}
+
 #[cfg(feature = "test-dependencies")]
 pub mod testing {
     use std::convert::Infallible;
@@ -0,0 +1,150 @@
+//! Calculations that depend upon the height of the chain tip.
+//!
+//! Transaction expiry and the confirmation depth of received outputs both depend upon how far
+//! the chain has progressed. The functions in this module obtain the chain tip height from a
+//! [`ChainTipProvider`], so that this logic can be exercised against a fixed chain tip height
+//! without having to construct a chain of blocks.
+
+use std::num::NonZeroU32;
+
+use zcash_primitives::consensus::BlockHeight;
+
+/// A source of the height of the current chain tip.
+pub trait ChainTipProvider {
+    /// The type of errors that may be produced when obtaining the chain tip height.
+    type Error;
+
+    /// Returns the height of the current chain tip, or `None` if it is not known.
+    fn chain_tip_height(&self) -> Result<Option<BlockHeight>, Self::Error>;
+}
+
+/// Returns whether a transaction with the given expiry height has expired as of the current
+/// chain tip.
+///
+/// A transaction may be mined in any block up to and including its expiry height, and so it
+/// has expired once the chain tip has reached its expiry height. A transaction with an expiry
+/// height of zero never expires. If the chain tip height is not known, the transaction is not
+/// considered to have expired.
+pub fn is_expired<C: ChainTipProvider>(
+    chain_tip: &C,
+    expiry_height: BlockHeight,
+) -> Result<bool, C::Error> {
+    if expiry_height == BlockHeight::from(0) {
+        return Ok(false);
+    }
+
+    Ok(matches!(chain_tip.chain_tip_height()?, Some(tip) if tip >= expiry_height))
+}
+
+/// Returns the number of additional blocks that must be mined before an output mined at
+/// `mined_height` has at least `min_confirmations` confirmations, or `None` if it already does.
+///
+/// An output mined at height `h` has `tip - h + 1` confirmations when the chain tip is at
+/// height `tip`. An output that has not been mined (`mined_height` is `None`), or for which
+/// the chain tip height is not known, is treated as requiring `min_confirmations` more blocks.
+pub fn blocks_until_confirmed<C: ChainTipProvider>(
+    chain_tip: &C,
+    mined_height: Option<BlockHeight>,
+    min_confirmations: NonZeroU32,
+) -> Result<Option<u32>, C::Error> {
+    Ok(match (mined_height, chain_tip.chain_tip_height()?) {
+        // The output will have `min_confirmations` confirmations once the chain reaches this
+        // height.
+        (Some(mined_height), Some(tip)) => u32::from(mined_height)
+            .saturating_add(min_confirmations.get() - 1)
+            .checked_sub(u32::from(tip))
+            .filter(|remaining| *remaining > 0),
+        _ => Some(min_confirmations.get()),
+    })
+}
+
+#[cfg(any(test, feature = "test-dependencies"))]
+pub mod testing {
+    use std::convert::Infallible;
+
+    use zcash_primitives::consensus::BlockHeight;
+
+    use super::ChainTipProvider;
+
+    /// A [`ChainTipProvider`] that reports a fixed chain tip height.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct FixedChainTip(pub Option<BlockHeight>);
+
+    impl FixedChainTip {
+        /// Constructs a `FixedChainTip` that reports the given chain tip height.
+        pub fn at(height: u32) -> Self {
+            FixedChainTip(Some(BlockHeight::from(height)))
+        }
+    }
+
+    impl ChainTipProvider for FixedChainTip {
+        type Error = Infallible;
+
+        fn chain_tip_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
+            Ok(self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use zcash_primitives::consensus::BlockHeight;
+
+    use super::{blocks_until_confirmed, is_expired, testing::FixedChainTip};
+
+    #[test]
+    fn expiry() {
+        let expiry_height = BlockHeight::from(100);
+        assert_eq!(is_expired(&FixedChainTip::at(99), expiry_height), Ok(false));
+        assert_eq!(is_expired(&FixedChainTip::at(100), expiry_height), Ok(true));
+        assert_eq!(is_expired(&FixedChainTip::at(101), expiry_height), Ok(true));
+        assert_eq!(is_expired(&FixedChainTip(None), expiry_height), Ok(false));
+
+        // A transaction with an expiry height of zero never expires.
+        assert_eq!(
+            is_expired(&FixedChainTip::at(u32::MAX), BlockHeight::from(0)),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn confirmations() {
+        let mined_height = Some(BlockHeight::from(100));
+        let three = NonZeroU32::new(3).unwrap();
+
+        // The block containing the output provides its first confirmation.
+        assert_eq!(
+            blocks_until_confirmed(&FixedChainTip::at(100), mined_height, three),
+            Ok(Some(2))
+        );
+        assert_eq!(
+            blocks_until_confirmed(&FixedChainTip::at(101), mined_height, three),
+            Ok(Some(1))
+        );
+        assert_eq!(
+            blocks_until_confirmed(&FixedChainTip::at(102), mined_height, three),
+            Ok(None)
+        );
+        assert_eq!(
+            blocks_until_confirmed(
+                &FixedChainTip::at(100),
+                mined_height,
+                NonZeroU32::new(1).unwrap()
+            ),
+            Ok(None)
+        );
+
+        // Unmined outputs, and outputs for which the chain tip is unknown, have no
+        // confirmations.
+        assert_eq!(
+            blocks_until_confirmed(&FixedChainTip::at(102), None, three),
+            Ok(Some(3))
+        );
+        assert_eq!(
+            blocks_until_confirmed(&FixedChainTip(None), mined_height, three),
+            Ok(Some(3))
+        );
+    }
+}
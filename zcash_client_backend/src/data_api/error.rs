@@ -3,13 +3,16 @@
 use std::error;
 use std::fmt::{self, Debug, Display};
 
-use shardtree::error::ShardTreeError;
-use zcash_address::ConversionError;
+use shardtree::error::{QueryError, ShardTreeError};
+use zcash_address::{ConversionError, ZcashAddress};
+use zcash_primitives::consensus::{BlockHeight, BranchId};
 use zcash_primitives::transaction::components::amount::NonNegativeAmount;
 use zcash_primitives::transaction::{
     builder,
     components::{amount::BalanceError, transparent},
 };
+use zcash_primitives::zip32::DiversifierIndex;
+use zcash_protocol::memo;
 
 use crate::address::UnifiedAddress;
 use crate::data_api::wallet::input_selection::InputSelectorError;
@@ -40,6 +43,11 @@ pub enum Error<DataSourceError, CommitmentTreeError, SelectionError, FeeError> {
     /// transaction steps is not yet supported.
     ProposalNotSupported,
 
+    /// The witnesses in a signable package do not correspond to the inputs of its proposal;
+    /// for example, a step or one of its shielded inputs has no witness. This indicates that
+    /// the package is corrupt, or was not created for the proposal that it contains.
+    SignablePackageMismatch,
+
     /// No account could be found corresponding to a provided spending key.
     KeyNotRecognized,
 
@@ -59,8 +67,36 @@ pub enum Error<DataSourceError, CommitmentTreeError, SelectionError, FeeError> {
     /// An error occurred building a new transaction.
     Builder(builder::Error<FeeError>),
 
+    /// The consensus branch ID requested for a new transaction is not that of the network
+    /// upgrade active at the anchor height used by the transaction, or of a later upgrade.
+    BranchIdInvalid {
+        branch_id: BranchId,
+        anchor_height: BlockHeight,
+    },
+
     /// It is forbidden to provide a memo when constructing a transparent output.
-    MemoForbidden,
+    ///
+    /// The argument is the address of the recipient for which a memo was provided.
+    MemoForbidden { recipient: ZcashAddress },
+
+    /// A memo provided for a payment is not a valid ZIP 302 memo; for example, it is a text
+    /// memo that is not valid UTF-8.
+    InvalidMemo { reason: memo::Error },
+
+    /// The fee override requested for a transaction is less than the fee required by the fee
+    /// rule used to construct its proposal.
+    FeeOverrideTooLow {
+        minimum: NonNegativeAmount,
+        requested: NonNegativeAmount,
+    },
+
+    /// None of the pools in the requested change pool preference can receive change for the
+    /// account from which funds are being spent.
+    NoUsableChangePool,
+
+    /// The diversifier index requested for change outputs does not yield a valid internal
+    /// address for the pool to which change is being sent.
+    ChangeDiversifierIndexInvalid(DiversifierIndex),
 
     /// Attempted to send change to an unsupported pool.
     ///
@@ -82,6 +118,19 @@ pub enum Error<DataSourceError, CommitmentTreeError, SelectionError, FeeError> {
     /// full viewing key for an account.
     NoteMismatch(NoteId),
 
+    /// The note commitment tree could not produce a witness for a note being spent, although
+    /// the wallet's records indicate that the note is unspent and positioned in the tree.
+    ///
+    /// This indicates that the wallet's note commitment tree data is inconsistent with its
+    /// record of received notes. If `rescan_from` is set, rescanning the chain from that
+    /// height (the height at which the note was mined) should restore the tree data required
+    /// to spend the note.
+    WitnessUnavailable {
+        note_id: NoteId,
+        reason: QueryError,
+        rescan_from: Option<BlockHeight>,
+    },
+
     /// An error occurred parsing the address from a payment request.
     Address(ConversionError<&'static str>),
 
@@ -120,6 +169,12 @@ where
                     "The proposal was valid, but spending shielded outputs of prior transaction steps is not yet supported."
                 )
             }
+            Error::SignablePackageMismatch => {
+                write!(
+                    f,
+                    "The witnesses of the signable package do not correspond to the inputs of its proposal."
+                )
+            }
             Error::KeyNotRecognized => {
                 write!(
                     f,
@@ -139,7 +194,21 @@ where
             ),
             Error::ScanRequired => write!(f, "Must scan blocks first"),
             Error::Builder(e) => write!(f, "An error occurred building the transaction: {}", e),
-            Error::MemoForbidden => write!(f, "It is not possible to send a memo to a transparent address."),
+            Error::BranchIdInvalid { branch_id, anchor_height } => write!(
+                f,
+                "Cannot build a transaction for consensus branch {:?}, which is not active at or after anchor height {}",
+                branch_id, anchor_height
+            ),
+            Error::MemoForbidden { recipient } => write!(f, "It is not possible to send a memo to {}, as the payment would be sent to a transparent address.", recipient),
+            Error::InvalidMemo { reason } => write!(f, "A payment's memo is invalid: {}", reason),
+            Error::FeeOverrideTooLow { minimum, requested } => write!(
+                f,
+                "The requested fee of {} zatoshis is less than the minimum fee of {} zatoshis",
+                u64::from(*requested),
+                u64::from(*minimum)
+            ),
+            Error::NoUsableChangePool => write!(f, "The account has no receiver in any of the preferred change pools."),
+            Error::ChangeDiversifierIndexInvalid(j) => write!(f, "Diversifier index {:?} does not yield a valid internal address for change outputs.", j),
             Error::UnsupportedChangeType(t) => write!(f, "Attempted to send change to an unsupported pool type: {}", t),
             Error::NoSupportedReceivers(ua) => write!(
                 f,
@@ -148,6 +217,13 @@ where
             ),
             Error::NoSpendingKey(addr) => write!(f, "No spending key available for address: {}", addr),
             Error::NoteMismatch(n) => write!(f, "A note being spent ({:?}) does not correspond to either the internal or external full viewing key for the provided spending key.", n),
+            Error::WitnessUnavailable { note_id, reason, rescan_from } => {
+                write!(f, "Unable to construct a witness for note {:?} being spent: {}.", note_id, reason)?;
+                match rescan_from {
+                    Some(h) => write!(f, " Rescanning the chain from height {} may repair the wallet's note commitment tree.", h),
+                    None => write!(f, " The wallet's note commitment tree may need to be rebuilt by rescanning."),
+                }
+            }
 
             Error::Address(e) => {
                 write!(f, "An error occurred decoding the address from a payment request: {}.", e)
@@ -174,6 +250,8 @@ where
             Error::NoteSelection(e) => Some(e),
             Error::Proposal(e) => Some(e),
             Error::Builder(e) => Some(e),
+            Error::InvalidMemo { reason } => Some(reason),
+            Error::WitnessUnavailable { reason, .. } => Some(reason),
             _ => None,
         }
     }
@@ -212,6 +290,9 @@ impl<DE, CE, SE, FE> From<InputSelectorError<DE, SE>> for Error<DE, CE, SE, FE>
             },
             InputSelectorError::SyncRequired => Error::ScanRequired,
             InputSelectorError::Address(e) => Error::Address(e),
+            InputSelectorError::FeeOverrideTooLow { minimum, requested } => {
+                Error::FeeOverrideTooLow { minimum, requested }
+            }
         }
     }
 }
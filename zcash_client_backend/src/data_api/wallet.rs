@@ -39,7 +39,14 @@ use sapling::{
     note_encryption::{try_sapling_note_decryption, PreparedIncomingViewingKey},
     prover::{OutputProver, SpendProver},
 };
-use std::num::NonZeroU32;
+use shardtree::error::ShardTreeError;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    hash::Hash,
+    num::NonZeroU32,
+};
+use tracing::warn;
 
 use super::InputSource;
 use crate::{
@@ -50,28 +57,28 @@ use crate::{
     },
     decrypt_transaction,
     fees::{self, DustOutputPolicy},
-    keys::UnifiedSpendingKey,
-    proposal::{self, Proposal, ProposalError},
-    wallet::{Note, OvkPolicy, Recipient},
+    keys::{UnifiedFullViewingKey, UnifiedSpendingKey},
+    proposal::{self, Proposal, ProposalError, SaplingMerklePath, SignablePackage},
+    wallet::{Note, NoteId, OvkPolicy, ReceivedNote, Recipient},
     zip321::{self, Payment},
     PoolType, ShieldedProtocol,
 };
 use zcash_primitives::transaction::{
     builder::{BuildConfig, BuildResult, Builder},
     components::{amount::NonNegativeAmount, sapling::zip212_enforcement},
-    fees::{zip317::FeeError as Zip317FeeError, FeeRule, StandardFeeRule},
+    fees::{transparent, zip317::FeeError as Zip317FeeError, FeeRule, StandardFeeRule},
     Transaction, TxId,
 };
 use zcash_protocol::{
-    consensus::{self, BlockHeight, NetworkUpgrade},
-    memo::MemoBytes,
+    consensus::{self, BlockHeight, BranchId, NetworkUpgrade},
+    memo::{Memo, MemoBytes},
 };
-use zip32::Scope;
+use zip32::{DiversifierIndex, Scope};
 
 #[cfg(feature = "transparent-inputs")]
 use {
+    crate::wallet::TransparentAddressMetadata,
     input_selection::ShieldingSelector,
-    std::convert::Infallible,
     zcash_keys::encoding::AddressCodec,
     zcash_primitives::legacy::TransparentAddress,
     zcash_primitives::transaction::components::{OutPoint, TxOut},
@@ -95,7 +102,50 @@ where
 {
     // Fetch the UnifiedFullViewingKeys we are tracking
     let ufvks = data.get_unified_full_viewing_keys()?;
+    store_transaction(params, data, tx, &ufvks)
+}
 
+/// Scans a [`Transaction`] from the mempool for any information that can be decrypted by
+/// the accounts in the wallet, and saves it to the wallet.
+///
+/// If a cache is provided, a transaction that has already been scanned using the cache is
+/// skipped, avoiding repeated trial decryption when the mempool is polled frequently. The
+/// cache is cleared automatically if the set of accounts in the wallet has changed since it
+/// was last used.
+pub fn decrypt_and_store_mempool_transaction<ParamsT, DbT>(
+    params: &ParamsT,
+    data: &mut DbT,
+    tx: &Transaction,
+    cache: Option<&mut MempoolDecryptionCache<DbT::AccountId>>,
+) -> Result<(), DbT::Error>
+where
+    ParamsT: consensus::Parameters,
+    DbT: WalletWrite,
+{
+    let ufvks = data.get_unified_full_viewing_keys()?;
+    match cache {
+        Some(cache) => {
+            cache.retain_if_accounts_match(ufvks.keys().copied().collect());
+            if !cache.touch(&tx.txid()) {
+                store_transaction(params, data, tx, &ufvks)?;
+                cache.insert(tx.txid());
+            }
+            Ok(())
+        }
+        None => store_transaction(params, data, tx, &ufvks),
+    }
+}
+
+fn store_transaction<ParamsT, DbT>(
+    params: &ParamsT,
+    data: &mut DbT,
+    tx: &Transaction,
+    ufvks: &HashMap<DbT::AccountId, UnifiedFullViewingKey>,
+) -> Result<(), DbT::Error>
+where
+    ParamsT: consensus::Parameters,
+    DbT: WalletWrite,
+{
     // Height is block height for mined transactions, and the "mempool height" (chain height + 1)
     // for mempool transactions.
     let height = data
@@ -104,11 +154,99 @@ where
         .or_else(|| params.activation_height(NetworkUpgrade::Sapling))
         .expect("Sapling activation height must be known.");
 
-    data.store_decrypted_tx(decrypt_transaction(params, height, tx, &ufvks))?;
+    data.store_decrypted_tx(decrypt_transaction(params, height, tx, ufvks))?;
 
     Ok(())
 }
 
+/// A least-recently-used cache of the mempool transactions that have already been scanned
+/// by [`decrypt_and_store_mempool_transaction`].
+///
+/// The cache records each transaction regardless of whether it was found to involve the
+/// wallet, as in either case scanning it again would have no further effect.
+pub struct MempoolDecryptionCache<AccountId> {
+    capacity: usize,
+    // The accounts whose viewing keys were used to scan the cached transactions.
+    accounts: HashSet<AccountId>,
+    // The value of `clock` when each cached transaction was last used.
+    last_used: HashMap<TxId, u64>,
+    clock: u64,
+}
+
+impl<AccountId: Eq + Hash> MempoolDecryptionCache<AccountId> {
+    /// Constructs an empty cache that holds at most `capacity` transactions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            accounts: HashSet::new(),
+            last_used: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Returns the number of transactions in the cache.
+    pub fn len(&self) -> usize {
+        self.last_used.len()
+    }
+
+    /// Returns `true` if the cache contains no transactions.
+    pub fn is_empty(&self) -> bool {
+        self.last_used.is_empty()
+    }
+
+    /// Returns `true` if the given transaction is in the cache.
+    pub fn contains(&self, txid: &TxId) -> bool {
+        self.last_used.contains_key(txid)
+    }
+
+    /// Removes all transactions from the cache, so that they will be scanned again.
+    pub fn clear_mempool_cache(&mut self) {
+        self.last_used.clear();
+    }
+
+    /// Clears the cache if `accounts` differs from the accounts used to scan the cached
+    /// transactions.
+    fn retain_if_accounts_match(&mut self, accounts: HashSet<AccountId>) {
+        if accounts != self.accounts {
+            self.clear_mempool_cache();
+            self.accounts = accounts;
+        }
+    }
+
+    /// Marks the given transaction as most recently used, returning `false` if it is not in
+    /// the cache.
+    fn touch(&mut self, txid: &TxId) -> bool {
+        self.clock += 1;
+        match self.last_used.get_mut(txid) {
+            Some(last_used) => {
+                *last_used = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds the given transaction to the cache, evicting the least recently used
+    /// transaction if the cache is full.
+    fn insert(&mut self, txid: TxId) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.last_used.len() >= self.capacity && !self.last_used.contains_key(&txid) {
+            if let Some(lru) = self
+                .last_used
+                .iter()
+                .min_by_key(|(_, last_used)| **last_used)
+                .map(|(txid, _)| *txid)
+            {
+                self.last_used.remove(&lru);
+            }
+        }
+        self.clock += 1;
+        self.last_used.insert(txid, self.clock);
+    }
+}
+
 #[allow(clippy::needless_doctest_main)]
 /// Creates a transaction or series of transactions paying the specified address from
 /// the given account, and the [`TxId`] corresponding to each newly-created transaction.
@@ -276,6 +414,7 @@ where
         memo,
         change_memo,
         fallback_change_pool,
+        ProposalOptions::default().change_pool_preference(),
     )?;
 
     create_proposed_transactions(
@@ -286,6 +425,8 @@ where
         usk,
         ovk_policy,
         &proposal,
+        None,
+        None,
     )
 }
 
@@ -337,6 +478,8 @@ where
 ///   received note must have in the blockchain in order to be considered for being
 ///   spent. A value of 10 confirmations is recommended and 0-conf transactions are
 ///   not supported.
+/// * `change_pool_preference`: The pools to which change may be sent, in order of
+///   preference; see [`ProposalOptions::with_change_pool_preference`].
 ///
 /// [`sapling::SpendProver`]: sapling::prover::SpendProver
 /// [`sapling::OutputProver`]: sapling::prover::OutputProver
@@ -353,6 +496,7 @@ pub fn spend<DbT, ParamsT, InputsT>(
     request: zip321::TransactionRequest,
     ovk_policy: OvkPolicy,
     min_confirmations: NonZeroU32,
+    change_pool_preference: &[ShieldedProtocol],
 ) -> Result<
     NonEmpty<TxId>,
     Error<
@@ -384,6 +528,7 @@ where
         input_selector,
         request,
         min_confirmations,
+        &ProposalOptions::default().with_change_pool_preference(change_pool_preference),
     )?;
 
     create_proposed_transactions(
@@ -394,12 +539,113 @@ where
         usk,
         ovk_policy,
         &proposal,
+        None,
+        None,
     )
 }
 
+/// Specifies how [`propose_transfer`] handles a payment that includes a memo, but that will be
+/// sent to a transparent receiver (which cannot carry a memo).
+///
+/// This can occur when paying to a Unified Address for which the wallet does not support any
+/// of the shielded receivers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoPolicy {
+    /// Reject the request with [`Error::MemoForbidden`].
+    #[default]
+    Reject,
+    /// Drop the memo from the payment, logging a warning, and proceed with the transfer.
+    Drop,
+}
+
+/// Options that control the construction of a proposal by [`propose_transfer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProposalOptions {
+    memo_policy: MemoPolicy,
+    fee_override: Option<NonNegativeAmount>,
+    change_pool_preference: Vec<ShieldedProtocol>,
+}
+
+impl Default for ProposalOptions {
+    fn default() -> Self {
+        ProposalOptions {
+            memo_policy: MemoPolicy::default(),
+            fee_override: None,
+            change_pool_preference: vec![
+                #[cfg(feature = "orchard")]
+                ShieldedProtocol::Orchard,
+                ShieldedProtocol::Sapling,
+            ],
+        }
+    }
+}
+
+impl ProposalOptions {
+    /// Sets how payments that include a memo, but that will be sent to a transparent receiver,
+    /// are handled. Defaults to [`MemoPolicy::Reject`].
+    pub fn with_memo_policy(mut self, memo_policy: MemoPolicy) -> Self {
+        self.memo_policy = memo_policy;
+        self
+    }
+
+    /// Sets the fee that the proposed transaction must pay, in place of the fee computed by
+    /// the input selector's fee rule. See [`propose_transfer`] for details.
+    pub fn with_fee_override(mut self, fee: NonNegativeAmount) -> Self {
+        self.fee_override = Some(fee);
+        self
+    }
+
+    /// Sets the order of preference of the pools to which change may be sent. Defaults to
+    /// Orchard, then Sapling.
+    ///
+    /// If the preference is empty, the change pool is instead chosen automatically for each
+    /// transaction, preferring Orchard if the transaction spends or creates any Orchard notes
+    /// and otherwise avoiding pool-crossing.
+    pub fn with_change_pool_preference(
+        mut self,
+        change_pool_preference: &[ShieldedProtocol],
+    ) -> Self {
+        self.change_pool_preference = change_pool_preference.to_vec();
+        self
+    }
+
+    /// Returns how payments that include a memo, but that will be sent to a transparent
+    /// receiver, are handled.
+    pub fn memo_policy(&self) -> MemoPolicy {
+        self.memo_policy
+    }
+
+    /// Returns the fee that the proposed transaction must pay, if it has been overridden.
+    pub fn fee_override(&self) -> Option<NonNegativeAmount> {
+        self.fee_override
+    }
+
+    /// Returns the order of preference of the pools to which change may be sent.
+    pub fn change_pool_preference(&self) -> &[ShieldedProtocol] {
+        &self.change_pool_preference
+    }
+}
+
 /// Select transaction inputs, compute fees, and construct a proposal for a transaction or series
 /// of transactions that can then be authorized and made ready for submission to the network with
 /// [`create_proposed_transactions`].
+///
+/// Each memo in `request` must be a valid ZIP 302 memo; otherwise [`Error::InvalidMemo`] is
+/// returned. Payments in `request` that carry a memo but would be sent to a transparent receiver
+/// are handled according to [`ProposalOptions::memo_policy`]; these checks are performed before
+/// any transaction is built.
+///
+/// If [`ProposalOptions::fee_override`] is set, the input selector will propose a transaction
+/// that pays that fee, paying the excess over the fee required by its fee rule out of change and
+/// selecting additional inputs if the change is insufficient. The override may not be less than
+/// the required fee, in which case [`Error::FeeOverrideTooLow`] is returned. Change that would
+/// become dust as a result is handled according to the input selector's dust output policy, so
+/// the fee paid may exceed the override by the value of that change.
+///
+/// Change is sent to the first pool in [`ProposalOptions::change_pool_preference`] for which the
+/// account has a receiver and the fee for the resulting transaction can be paid. If the account
+/// has no receiver in any of the preferred pools, [`Error::NoUsableChangePool`] is returned. If
+/// the preference is empty, the change pool is chosen automatically.
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 pub fn propose_transfer<DbT, ParamsT, InputsT, CommitmentTreeErrT>(
@@ -409,6 +655,7 @@ pub fn propose_transfer<DbT, ParamsT, InputsT, CommitmentTreeErrT>(
     input_selector: &InputsT,
     request: zip321::TransactionRequest,
     min_confirmations: NonZeroU32,
+    options: &ProposalOptions,
 ) -> Result<
     Proposal<InputsT::FeeRule, <DbT as InputSource>::NoteRef>,
     Error<
@@ -424,21 +671,165 @@ where
     ParamsT: consensus::Parameters + Clone,
     InputsT: InputSelector<InputSource = DbT>,
 {
+    // Reject malformed memos here, rather than when the notes carrying them are constructed.
+    for payment in request.payments().values() {
+        if let Some(memo) = payment.memo() {
+            Memo::try_from(memo).map_err(|reason| Error::InvalidMemo { reason })?;
+        }
+    }
+
     let (target_height, anchor_height) = wallet_db
         .get_target_and_anchor_heights(min_confirmations)
         .map_err(|e| Error::from(InputSelectorError::DataSource(e)))?
         .ok_or_else(|| Error::from(InputSelectorError::SyncRequired))?;
 
-    input_selector
+    // Change may only be sent to pools for which the account has a receiver.
+    let change_pool_preference = options.change_pool_preference();
+    let selection_options = if change_pool_preference.is_empty() {
+        options.clone()
+    } else {
+        let uivk = wallet_db
+            .get_account(spend_from_account)
+            .map_err(Error::DataSource)?
+            .ok_or(Error::KeyNotRecognized)?
+            .uivk();
+        let usable = change_pool_preference
+            .iter()
+            .copied()
+            .filter(|pool| match pool {
+                ShieldedProtocol::Sapling => uivk.sapling().is_some(),
+                #[cfg(feature = "orchard")]
+                ShieldedProtocol::Orchard => uivk.orchard().is_some(),
+                #[cfg(not(feature = "orchard"))]
+                ShieldedProtocol::Orchard => false,
+            })
+            .collect::<Vec<_>>();
+        if usable.is_empty() {
+            return Err(Error::NoUsableChangePool);
+        }
+        options.clone().with_change_pool_preference(&usable)
+    };
+
+    let proposal = input_selector
         .propose_transaction(
             params,
             wallet_db,
             target_height,
             anchor_height,
             spend_from_account,
-            request,
+            request.clone(),
+            &selection_options,
         )
-        .map_err(Error::from)
+        .map_err(Error::from)?;
+
+    // Find any payments for which a memo was provided but that will be sent to a transparent
+    // receiver; these would otherwise cause a failure only once the transaction is built.
+    let memo_forbidden = proposal
+        .steps()
+        .iter()
+        .flat_map(|step| {
+            step.payment_pools()
+                .iter()
+                .filter(|(_, pool)| **pool == PoolType::Transparent)
+                .filter_map(|(idx, _)| {
+                    step.transaction_request()
+                        .payments()
+                        .get(idx)
+                        .filter(|payment| payment.memo().is_some())
+                        .map(|payment| (*idx, payment.recipient_address().clone()))
+                })
+        })
+        .collect::<Vec<_>>();
+
+    match (options.memo_policy(), memo_forbidden.first()) {
+        (_, None) => Ok(proposal),
+        (MemoPolicy::Reject, Some((_, recipient))) => Err(Error::MemoForbidden {
+            recipient: recipient.clone(),
+        }),
+        (MemoPolicy::Drop, Some(_)) => {
+            let payments = request
+                .payments()
+                .iter()
+                .map(|(idx, payment)| {
+                    if memo_forbidden.iter().any(|(i, _)| i == idx) {
+                        warn!(
+                            "Dropping memo for payment {} to {}, which will be sent to a transparent receiver.",
+                            idx,
+                            payment.recipient_address()
+                        );
+                        Payment::new(
+                            payment.recipient_address().clone(),
+                            payment.amount(),
+                            None,
+                            payment.label().cloned(),
+                            payment.message().cloned(),
+                            payment.other_params().to_vec(),
+                        )
+                        .map(|p| (*idx, p))
+                        .expect("A payment without a memo is always valid.")
+                    } else {
+                        (*idx, payment.clone())
+                    }
+                })
+                .collect();
+            let request = zip321::TransactionRequest::from_indexed(payments).expect(
+                "Payment indices are unchanged, so this cannot violate ZIP 321 request invariants.",
+            );
+
+            input_selector
+                .propose_transaction(
+                    params,
+                    wallet_db,
+                    target_height,
+                    anchor_height,
+                    spend_from_account,
+                    request,
+                    &selection_options,
+                )
+                .map_err(Error::from)
+        }
+    }
+}
+
+/// A [`FeeRule`] that requires the fee recorded in a proposal step, provided that it is at least
+/// the fee required by the rule with which the proposal was constructed.
+///
+/// This permits proposals that pay a fee in excess of that required by their fee rule (as
+/// produced by [`propose_transfer`] with a fee override, or when dust change is added to the
+/// fee under [`DustAction::AddDustToFee`]) to be built, while ensuring that a transaction never
+/// pays less than the required fee.
+///
+/// [`DustAction::AddDustToFee`]: crate::fees::DustAction::AddDustToFee
+struct ProposedFee<'a, FeeRuleT> {
+    fee_rule: &'a FeeRuleT,
+    fee: NonNegativeAmount,
+}
+
+impl<FeeRuleT: FeeRule> FeeRule for ProposedFee<'_, FeeRuleT> {
+    type Error = FeeRuleT::Error;
+
+    fn fee_required<P: consensus::Parameters>(
+        &self,
+        params: &P,
+        target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::InputView],
+        transparent_outputs: &[impl transparent::OutputView],
+        sapling_input_count: usize,
+        sapling_output_count: usize,
+        orchard_action_count: usize,
+    ) -> Result<NonNegativeAmount, Self::Error> {
+        self.fee_rule
+            .fee_required(
+                params,
+                target_height,
+                transparent_inputs,
+                transparent_outputs,
+                sapling_input_count,
+                sapling_output_count,
+                orchard_action_count,
+            )
+            .map(|required| std::cmp::max(required, self.fee))
+    }
 }
 
 /// Proposes making a payment to the specified address from the given account.
@@ -466,6 +857,8 @@ where
 /// * `change_memo`: A memo to be included in any change output that is created.
 /// * `fallback_change_pool`: The shielded pool to which change should be sent if
 ///   automatic change pool determination fails.
+/// * `change_pool_preference`: The pools to which change may be sent, in order of
+///   preference; see [`ProposalOptions::with_change_pool_preference`].
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 pub fn propose_standard_transfer_to_address<DbT, ParamsT, CommitmentTreeErrT>(
@@ -479,6 +872,7 @@ pub fn propose_standard_transfer_to_address<DbT, ParamsT, CommitmentTreeErrT>(
     memo: Option<MemoBytes>,
     change_memo: Option<MemoBytes>,
     fallback_change_pool: ShieldedProtocol,
+    change_pool_preference: &[ShieldedProtocol],
 ) -> Result<
     Proposal<StandardFeeRule, DbT::NoteRef>,
     Error<
@@ -497,15 +891,16 @@ where
     >,
     DbT::NoteRef: Copy + Eq + Ord,
 {
+    let recipient = to.to_zcash_address(params);
     let request = zip321::TransactionRequest::new(vec![Payment::new(
-        to.to_zcash_address(params),
+        recipient.clone(),
         amount,
         memo,
         None,
         None,
         vec![],
     )
-    .ok_or(Error::MemoForbidden)?])
+    .ok_or(Error::MemoForbidden { recipient })?])
     .expect(
         "It should not be possible for this to violate ZIP 321 request construction invariants.",
     );
@@ -525,11 +920,15 @@ where
         &input_selector,
         request,
         min_confirmations,
+        &ProposalOptions::default().with_change_pool_preference(change_pool_preference),
     )
 }
 
 /// Constructs a proposal to shield all of the funds belonging to the provided set of
 /// addresses.
+///
+/// If `change_memo` is provided, it is attached to the shielded output(s) that receive the
+/// shielded funds.
 #[cfg(feature = "transparent-inputs")]
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
@@ -540,6 +939,7 @@ pub fn propose_shielding<DbT, ParamsT, InputsT, CommitmentTreeErrT>(
     shielding_threshold: NonNegativeAmount,
     from_addrs: &[TransparentAddress],
     min_confirmations: u32,
+    change_memo: Option<MemoBytes>,
 ) -> Result<
     Proposal<InputsT::FeeRule, Infallible>,
     Error<
@@ -567,6 +967,7 @@ where
             from_addrs,
             chain_tip_height + 1,
             min_confirmations,
+            change_memo,
         )
         .map_err(Error::from)
 }
@@ -582,6 +983,19 @@ where
 /// step is not supported, because the ultimate positions of those notes in the global note
 /// commitment tree cannot be known until the transaction that produces those notes is mined,
 /// and therefore the required spend proofs for such notes cannot be constructed.
+///
+/// By default, each transaction is built under the consensus rules of the network upgrade that
+/// is active at the proposal's target height. If `branch_id` is provided, the transactions are
+/// instead built for that consensus branch; this is primarily useful for constructing
+/// transactions close to a network upgrade activation height. An error is returned if the
+/// given branch is not active on this network at or after the anchor height of a step (or its
+/// target height, for steps that do not spend shielded notes).
+///
+/// Change outputs are sent to the internal (change) address of the spending account at
+/// diversifier index zero, unless `change_diversifier_index` is provided; in that case, change
+/// is sent to the internal address at the given index. An error is returned if the index does
+/// not yield a valid internal address for a pool to which change is sent. Note that all
+/// transactions in the proposal will send change to the same address.
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 pub fn create_proposed_transactions<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
@@ -592,6 +1006,8 @@ pub fn create_proposed_transactions<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
     usk: &UnifiedSpendingKey,
     ovk_policy: OvkPolicy,
     proposal: &Proposal<FeeRuleT, N>,
+    branch_id: Option<BranchId>,
+    change_diversifier_index: Option<DiversifierIndex>,
 ) -> Result<
     NonEmpty<TxId>,
     Error<
@@ -617,9 +1033,12 @@ where
             ovk_policy.clone(),
             proposal.fee_rule(),
             proposal.min_target_height(),
+            branch_id,
+            change_diversifier_index,
             &step_results,
             step,
-        )?;
+        )
+        .map_err(|e| with_rescan_height(wallet_db, e))?;
         step_results.push((step, step_result));
     }
 
@@ -632,6 +1051,156 @@ where
     .expect("proposal.steps is NonEmpty"))
 }
 
+/// Construct, prove, and sign the transaction or series of transactions described by the given
+/// [`SignablePackage`], without access to the wallet database.
+///
+/// A signable package carries the notes to be spent along with the note commitment tree anchors
+/// and witnesses required to spend them, so this function can be used on an offline
+/// (air-gapped) device that holds the spending key for the account that funds the package.
+/// The resulting transactions are returned to the caller rather than being stored; they should
+/// be transferred back to the online wallet, which can record them using
+/// [`decrypt_and_store_transaction`] before submitting them for inclusion in the blockchain.
+///
+/// Packages that spend transparent inputs, or that spend the outputs of prior steps, are not
+/// supported, because the spending metadata for transparent addresses is not included in the
+/// package.
+#[allow(clippy::type_complexity)]
+pub fn create_signable_package_transactions<ParamsT, FeeRuleT>(
+    params: &ParamsT,
+    spend_prover: &impl SpendProver,
+    output_prover: &impl OutputProver,
+    usk: &UnifiedSpendingKey,
+    ovk_policy: OvkPolicy,
+    package: &SignablePackage<FeeRuleT>,
+) -> Result<NonEmpty<BuildResult>, Error<Infallible, Infallible, Infallible, FeeRuleT::Error>>
+where
+    ParamsT: consensus::Parameters + Clone,
+    FeeRuleT: FeeRule,
+{
+    let proposal = package.proposal();
+    // Every step, and every shielded input of each step, must have a witness; otherwise the
+    // transactions built would spend less than the proposal describes.
+    if proposal.steps().len() != package.step_witnesses().len() {
+        return Err(Error::SignablePackageMismatch);
+    }
+
+    let mut step_results = Vec::with_capacity(proposal.steps().len());
+    for (step, witnesses) in proposal.steps().iter().zip(package.step_witnesses().iter()) {
+        if !(step.transparent_inputs().is_empty() && step.prior_step_inputs().is_empty()) {
+            return Err(Error::ProposalNotSupported);
+        }
+
+        let sapling_notes: Vec<_> = step
+            .shielded_inputs()
+            .iter()
+            .flat_map(|inputs| inputs.notes().iter())
+            .filter_map(|selected| match selected.note() {
+                Note::Sapling(note) => Some((selected.spending_key_scope(), note)),
+                #[cfg(feature = "orchard")]
+                Note::Orchard(_) => None,
+            })
+            .collect();
+        if sapling_notes.len() != witnesses.sapling_witnesses().len() {
+            return Err(Error::SignablePackageMismatch);
+        }
+        let sapling_inputs = sapling_notes
+            .into_iter()
+            .zip(witnesses.sapling_witnesses().iter().cloned())
+            .map(|((scope, note), merkle_path)| (scope, note, merkle_path))
+            .collect();
+
+        #[cfg(feature = "orchard")]
+        let orchard_notes: Vec<_> = step
+            .shielded_inputs()
+            .iter()
+            .flat_map(|inputs| inputs.notes().iter())
+            .filter_map(|selected| match selected.note() {
+                Note::Orchard(note) => Some(note),
+                Note::Sapling(_) => None,
+            })
+            .collect();
+        #[cfg(feature = "orchard")]
+        if orchard_notes.len() != witnesses.orchard_witnesses().len() {
+            return Err(Error::SignablePackageMismatch);
+        }
+        #[cfg(feature = "orchard")]
+        let orchard_inputs = orchard_notes
+            .into_iter()
+            .zip(witnesses.orchard_witnesses().iter().cloned())
+            .collect();
+
+        let built = build_proposed_transaction(
+            params,
+            spend_prover,
+            output_prover,
+            usk,
+            ovk_policy.clone(),
+            proposal.fee_rule(),
+            proposal.min_target_height(),
+            None,
+            None,
+            &step_results,
+            step,
+            (),
+            &HashMap::new(),
+            witnesses.sapling_anchor(),
+            sapling_inputs,
+            #[cfg(feature = "orchard")]
+            witnesses.orchard_anchor(),
+            #[cfg(feature = "orchard")]
+            orchard_inputs,
+            #[cfg(feature = "transparent-inputs")]
+            &HashMap::new(),
+        )?;
+        step_results.push((step, built.build_result));
+    }
+
+    let build_results = step_results.into_iter().map(|(_, r)| r).collect();
+    Ok(NonEmpty::from_vec(build_results).expect("proposal.steps is NonEmpty"))
+}
+
+/// Returns a function that converts a failure to compute the witness for the given note into an
+/// error that identifies the note. The height from which the wallet should be rescanned is filled
+/// in afterwards by [`with_rescan_height`], as the wallet's note commitment trees are borrowed
+/// while witnesses are being computed.
+fn witness_error<DbErrT, CommitmentTreeErrT, SelectionErrT, FeeErrT, NoteRef, NoteT>(
+    selected: &ReceivedNote<NoteRef, NoteT>,
+    protocol: ShieldedProtocol,
+) -> impl FnOnce(
+    ShardTreeError<CommitmentTreeErrT>,
+) -> Error<DbErrT, CommitmentTreeErrT, SelectionErrT, FeeErrT> {
+    let note_id = NoteId::new(*selected.txid(), protocol, selected.output_index());
+    move |e| match e {
+        ShardTreeError::Query(reason) => Error::WitnessUnavailable {
+            note_id,
+            reason,
+            rescan_from: None,
+        },
+        e => Error::CommitmentTree(e),
+    }
+}
+
+/// Adds the mined height of the affected note to an [`Error::WitnessUnavailable`] error, so that
+/// the caller knows where to begin rescanning in order to repair the note commitment tree.
+fn with_rescan_height<DbT: WalletRead, CommitmentTreeErrT, SelectionErrT, FeeErrT>(
+    wallet_db: &DbT,
+    e: Error<DbT::Error, CommitmentTreeErrT, SelectionErrT, FeeErrT>,
+) -> Error<DbT::Error, CommitmentTreeErrT, SelectionErrT, FeeErrT> {
+    match e {
+        Error::WitnessUnavailable {
+            note_id,
+            reason,
+            rescan_from: None,
+        } => Error::WitnessUnavailable {
+            // A failure to look up the height should not mask the witness error itself.
+            rescan_from: wallet_db.get_tx_height(*note_id.txid()).ok().flatten(),
+            note_id,
+            reason,
+        },
+        e => e,
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 fn create_proposed_transaction<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
@@ -643,6 +1212,8 @@ fn create_proposed_transaction<DbT, ParamsT, InputsErrT, FeeRuleT, N>(
     ovk_policy: OvkPolicy,
     fee_rule: &FeeRuleT,
     min_target_height: BlockHeight,
+    branch_id: Option<BranchId>,
+    change_diversifier_index: Option<DiversifierIndex>,
     prior_step_results: &[(&proposal::Step<N>, BuildResult)],
     proposal_step: &proposal::Step<N>,
 ) -> Result<
@@ -659,39 +1230,6 @@ where
     ParamsT: consensus::Parameters + Clone,
     FeeRuleT: FeeRule,
 {
-    // TODO: Spending shielded outputs of prior multi-step transaction steps is not yet
-    // supported. Maybe support this at some point? Doing so would require a higher-level
-    // approach in the wallet that waits for transactions with shielded outputs to be
-    // mined and only then attempts to perform the next step.
-    for s_ref in proposal_step.prior_step_inputs() {
-        prior_step_results.get(s_ref.step_index()).map_or_else(
-            || {
-                // Return an error in case the step index doesn't match up with a step
-                Err(Error::Proposal(ProposalError::ReferenceError(*s_ref)))
-            },
-            |step| match s_ref.output_index() {
-                proposal::StepOutputIndex::Payment(i) => {
-                    let prior_pool = step
-                        .0
-                        .payment_pools()
-                        .get(&i)
-                        .ok_or(Error::Proposal(ProposalError::ReferenceError(*s_ref)))?;
-
-                    if matches!(prior_pool, PoolType::Shielded(_)) {
-                        Err(Error::ProposalNotSupported)
-                    } else {
-                        Ok(())
-                    }
-                }
-                proposal::StepOutputIndex::Change(_) => {
-                    // Only shielded change is supported by zcash_client_backend, so multi-step
-                    // transactions cannot yet spend prior transactions' change outputs.
-                    Err(Error::ProposalNotSupported)
-                }
-            },
-        )?;
-    }
-
     let account = wallet_db
         .get_account_for_ufvk(&usk.to_unified_full_viewing_key())
         .map_err(Error::DataSource)?
@@ -713,18 +1251,15 @@ where
                             .iter()
                             .filter_map(|selected| match selected.note() {
                                 Note::Sapling(note) => {
-                                    let key = match selected.spending_key_scope() {
-                                        Scope::External => usk.sapling().clone(),
-                                        Scope::Internal => usk.sapling().derive_internal(),
-                                    };
+                                    let scope = selected.spending_key_scope();
 
                                     sapling_tree
                                         .witness_at_checkpoint_id_caching(
                                             selected.note_commitment_tree_position(),
                                             &inputs.anchor_height(),
                                         )
-                                        .map(|merkle_path| Some((key, note, merkle_path)))
-                                        .map_err(Error::from)
+                                        .map(|merkle_path| Some((scope, note, merkle_path)))
+                                        .map_err(witness_error(selected, ShieldedProtocol::Sapling))
                                         .transpose()
                                 }
                                 #[cfg(feature = "orchard")]
@@ -762,7 +1297,7 @@ where
                                         &inputs.anchor_height(),
                                     )
                                     .map(|merkle_path| Some((note, merkle_path)))
-                                    .map_err(Error::from)
+                                    .map_err(witness_error(selected, ShieldedProtocol::Orchard))
                                     .transpose(),
                                 Note::Sapling(_) => None,
                             })
@@ -775,6 +1310,144 @@ where
         } else {
             (None, vec![])
         };
+
+    // Payments to shielded addresses belonging to other accounts in this wallet are recorded
+    // as received by those accounts when the transaction is stored, so that the recipient
+    // account can observe the pending note before the transaction is mined.
+    let ufvks = wallet_db
+        .get_unified_full_viewing_keys()
+        .map_err(Error::DataSource)?;
+
+    #[cfg(feature = "transparent-inputs")]
+    let known_addrs = wallet_db
+        .get_transparent_receivers(account)
+        .map_err(Error::DataSource)?;
+
+    let built = build_proposed_transaction(
+        params,
+        spend_prover,
+        output_prover,
+        usk,
+        ovk_policy,
+        fee_rule,
+        min_target_height,
+        branch_id,
+        change_diversifier_index,
+        prior_step_results,
+        proposal_step,
+        account,
+        &ufvks,
+        sapling_anchor,
+        sapling_inputs,
+        #[cfg(feature = "orchard")]
+        orchard_anchor,
+        #[cfg(feature = "orchard")]
+        orchard_inputs,
+        #[cfg(feature = "transparent-inputs")]
+        &known_addrs,
+    )?;
+
+    wallet_db
+        .store_sent_tx(&SentTransaction {
+            tx: built.build_result.transaction(),
+            created: time::OffsetDateTime::now_utc(),
+            account,
+            outputs: built.outputs,
+            fee_amount: proposal_step.balance().fee_required(),
+            #[cfg(feature = "transparent-inputs")]
+            utxos_spent: built.utxos_spent,
+        })
+        .map_err(Error::DataSource)?;
+
+    Ok(built.build_result)
+}
+
+/// The result of building a single step of a proposal, along with the metadata required to
+/// record the resulting transaction in the wallet.
+struct BuiltStep<AccountIdT> {
+    build_result: BuildResult,
+    outputs: Vec<SentTransactionOutput<AccountIdT>>,
+    #[cfg(feature = "transparent-inputs")]
+    utxos_spent: Vec<OutPoint>,
+}
+
+/// Constructs, proves, and signs the transaction for a single proposal step, using
+/// already-resolved note commitment tree anchors and witnesses for its shielded inputs.
+///
+/// This does not access the wallet database, so that it can be used both when creating
+/// transactions from a wallet and when creating them from a [`SignablePackage`] on an
+/// offline device.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn build_proposed_transaction<
+    ParamsT,
+    AccountIdT,
+    DbErrT,
+    CommitmentTreeErrT,
+    InputsErrT,
+    FeeRuleT,
+    N,
+>(
+    params: &ParamsT,
+    spend_prover: &impl SpendProver,
+    output_prover: &impl OutputProver,
+    usk: &UnifiedSpendingKey,
+    ovk_policy: OvkPolicy,
+    fee_rule: &FeeRuleT,
+    min_target_height: BlockHeight,
+    branch_id: Option<BranchId>,
+    change_diversifier_index: Option<DiversifierIndex>,
+    prior_step_results: &[(&proposal::Step<N>, BuildResult)],
+    proposal_step: &proposal::Step<N>,
+    account: AccountIdT,
+    ufvks: &HashMap<AccountIdT, UnifiedFullViewingKey>,
+    sapling_anchor: Option<sapling::Anchor>,
+    sapling_inputs: Vec<(Scope, &sapling::Note, SaplingMerklePath)>,
+    #[cfg(feature = "orchard")] orchard_anchor: Option<orchard::Anchor>,
+    #[cfg(feature = "orchard")] orchard_inputs: Vec<(&orchard::Note, proposal::OrchardMerklePath)>,
+    #[cfg(feature = "transparent-inputs")] known_addrs: &HashMap<
+        TransparentAddress,
+        Option<TransparentAddressMetadata>,
+    >,
+) -> Result<BuiltStep<AccountIdT>, Error<DbErrT, CommitmentTreeErrT, InputsErrT, FeeRuleT::Error>>
+where
+    ParamsT: consensus::Parameters + Clone,
+    AccountIdT: Copy + PartialEq,
+    FeeRuleT: FeeRule,
+{
+    // TODO: Spending shielded outputs of prior multi-step transaction steps is not yet
+    // supported. Maybe support this at some point? Doing so would require a higher-level
+    // approach in the wallet that waits for transactions with shielded outputs to be
+    // mined and only then attempts to perform the next step.
+    for s_ref in proposal_step.prior_step_inputs() {
+        prior_step_results.get(s_ref.step_index()).map_or_else(
+            || {
+                // Return an error in case the step index doesn't match up with a step
+                Err(Error::Proposal(ProposalError::ReferenceError(*s_ref)))
+            },
+            |step| match s_ref.output_index() {
+                proposal::StepOutputIndex::Payment(i) => {
+                    let prior_pool = step
+                        .0
+                        .payment_pools()
+                        .get(&i)
+                        .ok_or(Error::Proposal(ProposalError::ReferenceError(*s_ref)))?;
+
+                    if matches!(prior_pool, PoolType::Shielded(_)) {
+                        Err(Error::ProposalNotSupported)
+                    } else {
+                        Ok(())
+                    }
+                }
+                proposal::StepOutputIndex::Change(_) => {
+                    // Only shielded change is supported by zcash_client_backend, so multi-step
+                    // transactions cannot yet spend prior transactions' change outputs.
+                    Err(Error::ProposalNotSupported)
+                }
+            },
+        )?;
+    }
+
     #[cfg(not(feature = "orchard"))]
     let orchard_anchor = None;
 
@@ -789,7 +1462,30 @@ where
         },
     );
 
-    for (sapling_key, sapling_note, merkle_path) in sapling_inputs.into_iter() {
+    if let Some(branch_id) = branch_id {
+        // An explicitly requested branch must not have been superseded by the time of the
+        // anchor; otherwise, the transaction could never be mined.
+        let anchor_height = proposal_step
+            .shielded_inputs()
+            .map_or(min_target_height, |inputs| inputs.anchor_height());
+        match branch_id.height_bounds(params) {
+            Some((_, upper)) if upper.map_or(true, |upper| upper > anchor_height) => {
+                builder = builder.with_consensus_branch_id(branch_id);
+            }
+            _ => {
+                return Err(Error::BranchIdInvalid {
+                    branch_id,
+                    anchor_height,
+                });
+            }
+        }
+    }
+
+    for (scope, sapling_note, merkle_path) in sapling_inputs.into_iter() {
+        let sapling_key = match scope {
+            Scope::External => usk.sapling().clone(),
+            Scope::Internal => usk.sapling().derive_internal(),
+        };
         builder.add_sapling_spend(&sapling_key, sapling_note.clone(), merkle_path)?;
     }
 
@@ -800,22 +1496,13 @@ where
 
     #[cfg(feature = "transparent-inputs")]
     let utxos_spent = {
-        let known_addrs = wallet_db
-            .get_transparent_receivers(account)
-            .map_err(Error::DataSource)?;
-
         let mut utxos_spent: Vec<OutPoint> = vec![];
         let mut add_transparent_input = |addr: &TransparentAddress,
                                          outpoint: OutPoint,
                                          utxo: TxOut|
          -> Result<
             (),
-            Error<
-                <DbT as WalletRead>::Error,
-                <DbT as WalletCommitmentTrees>::Error,
-                InputsErrT,
-                FeeRuleT::Error,
-            >,
+            Error<DbErrT, CommitmentTreeErrT, InputsErrT, FeeRuleT::Error>,
         > {
             let address_metadata = known_addrs
                 .get(addr)
@@ -939,6 +1626,48 @@ where
         Some(sapling_dfvk.to_ovk(Scope::Internal))
     };
 
+    let sapling_recipient = |payment: &Payment, addr: &sapling::PaymentAddress| {
+        ufvks
+            .iter()
+            .filter(|(id, _)| **id != account)
+            .find_map(|(id, ufvk)| {
+                let dfvk = ufvk.sapling()?;
+                let (_, scope) = dfvk.decrypt_diversifier(addr)?;
+                Some(Recipient::InternalAccount {
+                    receiving_account: *id,
+                    external_address: Some(payment.recipient_address().clone()),
+                    note: PreparedIncomingViewingKey::new(&dfvk.to_ivk(scope)),
+                })
+            })
+            .unwrap_or_else(|| {
+                Recipient::External(payment.recipient_address().clone(), PoolType::SAPLING)
+            })
+    };
+
+    #[cfg(feature = "orchard")]
+    let orchard_recipient = |payment: &Payment, addr: &orchard::Address| {
+        ufvks
+            .iter()
+            .filter(|(id, _)| **id != account)
+            .find_map(|(id, ufvk)| {
+                let fvk = ufvk.orchard()?;
+                let scope = fvk.scope_for_address(addr)?;
+                Some(Recipient::InternalAccount {
+                    receiving_account: *id,
+                    external_address: Some(payment.recipient_address().clone()),
+                    note: fvk.to_ivk(scope),
+                })
+            })
+            .unwrap_or_else(|| {
+                Recipient::External(payment.recipient_address().clone(), PoolType::ORCHARD)
+            })
+    };
+
+    #[cfg(feature = "orchard")]
+    let orchard_internal_ivk = orchard_fvk.to_ivk(orchard::keys::Scope::Internal);
+    let sapling_internal_ivk =
+        PreparedIncomingViewingKey::new(&sapling_dfvk.to_ivk(Scope::Internal));
+
     #[cfg(feature = "orchard")]
     let mut orchard_output_meta = vec![];
     let mut sapling_output_meta = vec![];
@@ -973,34 +1702,30 @@ where
                     }
                     #[cfg(feature = "orchard")]
                     PoolType::Shielded(ShieldedProtocol::Orchard) => {
+                        let to = *ua.orchard().expect("The mapping between payment pool and receiver is checked in step construction");
                         builder.add_orchard_output(
                             orchard_external_ovk.clone(),
-                            *ua.orchard().expect("The mapping between payment pool and receiver is checked in step construction"),
+                            to,
                             payment.amount().into(),
                             memo.clone(),
                         )?;
                         orchard_output_meta.push((
-                            Recipient::External(
-                                payment.recipient_address().clone(),
-                                PoolType::Shielded(ShieldedProtocol::Orchard),
-                            ),
+                            orchard_recipient(payment, &to),
                             payment.amount(),
                             Some(memo),
                         ));
                     }
 
                     PoolType::Shielded(ShieldedProtocol::Sapling) => {
+                        let to = *ua.sapling().expect("The mapping between payment pool and receiver is checked in step construction");
                         builder.add_sapling_output(
                             sapling_external_ovk,
-                            *ua.sapling().expect("The mapping between payment pool and receiver is checked in step construction"),
+                            to,
                             payment.amount(),
                             memo.clone(),
                         )?;
                         sapling_output_meta.push((
-                            Recipient::External(
-                                payment.recipient_address().clone(),
-                                PoolType::Shielded(ShieldedProtocol::Sapling),
-                            ),
+                            sapling_recipient(payment, &to),
                             payment.amount(),
                             Some(memo),
                         ));
@@ -1008,7 +1733,9 @@ where
 
                     PoolType::Transparent => {
                         if payment.memo().is_some() {
-                            return Err(Error::MemoForbidden);
+                            return Err(Error::MemoForbidden {
+                                recipient: payment.recipient_address().clone(),
+                            });
                         } else {
                             builder.add_transparent_output(
                                 ua.transparent().expect("The mapping between payment pool and receiver is checked in step construction."),
@@ -1027,14 +1754,16 @@ where
                     memo.clone(),
                 )?;
                 sapling_output_meta.push((
-                    Recipient::External(payment.recipient_address().clone(), PoolType::SAPLING),
+                    sapling_recipient(payment, &addr),
                     payment.amount(),
                     Some(memo),
                 ));
             }
             Address::Transparent(to) => {
                 if payment.memo().is_some() {
-                    return Err(Error::MemoForbidden);
+                    return Err(Error::MemoForbidden {
+                        recipient: payment.recipient_address().clone(),
+                    });
                 } else {
                     builder.add_transparent_output(&to, payment.amount())?;
                 }
@@ -1053,9 +1782,18 @@ where
             .map_or_else(MemoBytes::empty, |m| m.clone());
         match change_value.output_pool() {
             ShieldedProtocol::Sapling => {
+                let change_address = match change_diversifier_index {
+                    Some(j) => usk
+                        .sapling()
+                        .derive_internal()
+                        .to_diversifiable_full_viewing_key()
+                        .address(j)
+                        .ok_or(Error::ChangeDiversifierIndexInvalid(j))?,
+                    None => sapling_dfvk.change_address().1,
+                };
                 builder.add_sapling_output(
                     sapling_internal_ovk(),
-                    sapling_dfvk.change_address().1,
+                    change_address,
                     change_value.value(),
                     memo.clone(),
                 )?;
@@ -1063,7 +1801,7 @@ where
                     Recipient::InternalAccount {
                         receiving_account: account,
                         external_address: None,
-                        note: PoolType::Shielded(ShieldedProtocol::Sapling),
+                        note: sapling_internal_ivk.clone(),
                     },
                     change_value.value(),
                     Some(memo),
@@ -1077,9 +1815,15 @@ where
 
                 #[cfg(feature = "orchard")]
                 {
+                    // Every diversifier index yields a valid Orchard address.
+                    let change_j = orchard::keys::DiversifierIndex::from(
+                        *change_diversifier_index
+                            .unwrap_or_else(DiversifierIndex::new)
+                            .as_bytes(),
+                    );
                     builder.add_orchard_output(
                         orchard_internal_ovk(),
-                        orchard_fvk.address_at(0u32, orchard::keys::Scope::Internal),
+                        orchard_fvk.address_at(change_j, orchard::keys::Scope::Internal),
                         change_value.value().into(),
                         memo.clone(),
                     )?;
@@ -1087,7 +1831,7 @@ where
                         Recipient::InternalAccount {
                             receiving_account: account,
                             external_address: None,
-                            note: PoolType::Shielded(ShieldedProtocol::Orchard),
+                            note: orchard_internal_ivk.clone(),
                         },
                         change_value.value(),
                         Some(memo),
@@ -1098,10 +1842,16 @@ where
     }
 
     // Build the transaction with the specified fee rule
-    let build_result = builder.build(OsRng, spend_prover, output_prover, fee_rule)?;
+    let build_result = builder.build(
+        OsRng,
+        spend_prover,
+        output_prover,
+        &ProposedFee {
+            fee_rule,
+            fee: proposal_step.balance().fee_required(),
+        },
+    )?;
 
-    #[cfg(feature = "orchard")]
-    let orchard_internal_ivk = orchard_fvk.to_ivk(orchard::keys::Scope::Internal);
     #[cfg(feature = "orchard")]
     let orchard_outputs =
         orchard_output_meta
@@ -1114,14 +1864,13 @@ where
                     .expect("An action should exist in the transaction for each Orchard output.");
 
                 let recipient = recipient
-                    .map_internal_account_note(|pool| {
-                        assert!(pool == PoolType::Shielded(ShieldedProtocol::Orchard));
+                    .map_internal_account_note(|ivk| {
                         build_result
                             .transaction()
                             .orchard_bundle()
                             .and_then(|bundle| {
                                 bundle
-                                    .decrypt_output_with_key(output_index, &orchard_internal_ivk)
+                                    .decrypt_output_with_key(output_index, &ivk)
                                     .map(|(note, _, _)| Note::Orchard(note))
                             })
                     })
@@ -1131,8 +1880,6 @@ where
                 SentTransactionOutput::from_parts(output_index, recipient, value, memo)
             });
 
-    let sapling_internal_ivk =
-        PreparedIncomingViewingKey::new(&sapling_dfvk.to_ivk(Scope::Internal));
     let sapling_outputs =
         sapling_output_meta
             .into_iter()
@@ -1144,14 +1891,13 @@ where
                     .expect("An output should exist in the transaction for each Sapling payment.");
 
                 let recipient = recipient
-                    .map_internal_account_note(|pool| {
-                        assert!(pool == PoolType::Shielded(ShieldedProtocol::Sapling));
+                    .map_internal_account_note(|ivk| {
                         build_result
                             .transaction()
                             .sapling_bundle()
                             .and_then(|bundle| {
                                 try_sapling_note_decryption(
-                                    &sapling_internal_ivk,
+                                    &ivk,
                                     &bundle.shielded_outputs()[output_index],
                                     zip212_enforcement(params, min_target_height),
                                 )
@@ -1192,19 +1938,12 @@ where
     outputs.extend(sapling_outputs);
     outputs.extend(transparent_outputs);
 
-    wallet_db
-        .store_sent_tx(&SentTransaction {
-            tx: build_result.transaction(),
-            created: time::OffsetDateTime::now_utc(),
-            account,
-            outputs,
-            fee_amount: proposal_step.balance().fee_required(),
-            #[cfg(feature = "transparent-inputs")]
-            utxos_spent,
-        })
-        .map_err(Error::DataSource)?;
-
-    Ok(build_result)
+    Ok(BuiltStep {
+        build_result,
+        outputs,
+        #[cfg(feature = "transparent-inputs")]
+        utxos_spent,
+    })
 }
 
 /// Constructs a transaction that consumes available transparent UTXOs belonging to the specified
@@ -1237,6 +1976,9 @@ where
 ///   received note must have in the blockchain in order to be considered for being
 ///   spent. A value of 10 confirmations is recommended and 0-conf transactions are
 ///   not supported.
+/// * `change_memo`: A memo to be attached to the shielded output(s) that receive the
+///   shielded funds. Since these outputs are sent to the wallet's internal address, this
+///   memo is recorded as the change memo of the shielding transaction.
 ///
 /// [`sapling::SpendProver`]: sapling::prover::SpendProver
 /// [`sapling::OutputProver`]: sapling::prover::OutputProver
@@ -1253,6 +1995,7 @@ pub fn shield_transparent_funds<DbT, ParamsT, InputsT>(
     usk: &UnifiedSpendingKey,
     from_addrs: &[TransparentAddress],
     min_confirmations: u32,
+    change_memo: Option<MemoBytes>,
 ) -> Result<
     NonEmpty<TxId>,
     Error<
@@ -1274,6 +2017,7 @@ where
         shielding_threshold,
         from_addrs,
         min_confirmations,
+        change_memo,
     )?;
 
     create_proposed_transactions(
@@ -1284,5 +2028,50 @@ where
         usk,
         OvkPolicy::Sender,
         &proposal,
+        None,
+        None,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use zcash_primitives::transaction::TxId;
+
+    use super::MempoolDecryptionCache;
+
+    #[test]
+    fn mempool_cache_evicts_least_recently_used() {
+        let txid = |n: u8| TxId::from_bytes([n; 32]);
+        let mut cache = MempoolDecryptionCache::<u32>::new(2);
+        cache.retain_if_accounts_match(HashSet::from([0]));
+
+        cache.insert(txid(1));
+        cache.insert(txid(2));
+        assert!(cache.touch(&txid(1)));
+        assert!(!cache.touch(&txid(3)));
+
+        // The transaction that was used least recently is evicted.
+        cache.insert(txid(3));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&txid(1)));
+        assert!(!cache.contains(&txid(2)));
+        assert!(cache.contains(&txid(3)));
+
+        // The cache is unaffected if the accounts have not changed, and cleared if they have.
+        cache.retain_if_accounts_match(HashSet::from([0]));
+        assert_eq!(cache.len(), 2);
+        cache.retain_if_accounts_match(HashSet::from([0, 1]));
+        assert!(cache.is_empty());
+
+        cache.insert(txid(1));
+        cache.clear_mempool_cache();
+        assert!(cache.is_empty());
+
+        // A cache with no capacity holds nothing.
+        let mut cache = MempoolDecryptionCache::<u32>::new(0);
+        cache.insert(txid(1));
+        assert!(cache.is_empty());
+    }
+}
@@ -16,14 +16,17 @@ use zcash_primitives::{
             amount::{BalanceError, NonNegativeAmount},
             TxOut,
         },
-        fees::FeeRule,
+        fees::{zip317::MARGINAL_FEE, FeeRule},
     },
 };
 
 use crate::{
     address::{Address, UnifiedAddress},
-    data_api::{InputSource, SimpleNoteRetention, SpendableNotes},
-    fees::{sapling, ChangeError, ChangeStrategy, DustOutputPolicy},
+    data_api::{wallet::ProposalOptions, InputSource, SimpleNoteRetention, SpendableNotes},
+    fees::{
+        sapling, ChangeError, ChangeStrategy, ChangeValue, DustAction, DustOutputPolicy,
+        TransactionBalance,
+    },
     proposal::{Proposal, ProposalError, ShieldedInputs},
     wallet::WalletTransparentOutput,
     zip321::TransactionRequest,
@@ -33,7 +36,7 @@ use crate::{
 #[cfg(feature = "transparent-inputs")]
 use {
     std::collections::BTreeSet, std::convert::Infallible,
-    zcash_primitives::legacy::TransparentAddress,
+    zcash_primitives::legacy::TransparentAddress, zcash_primitives::memo::MemoBytes,
     zcash_primitives::transaction::components::OutPoint,
 };
 
@@ -60,6 +63,12 @@ pub enum InputSelectorError<DbErrT, SelectorErrT> {
     /// The data source does not have enough information to choose an expiry height
     /// for the transaction.
     SyncRequired,
+    /// The fee override requested for the transaction is less than the fee required by the
+    /// input selector's fee rule.
+    FeeOverrideTooLow {
+        minimum: NonNegativeAmount,
+        requested: NonNegativeAmount,
+    },
 }
 
 impl<E, S> From<ConversionError<&'static str>> for InputSelectorError<E, S> {
@@ -107,6 +116,12 @@ impl<DE: fmt::Display, SE: fmt::Display> fmt::Display for InputSelectorError<DE,
             InputSelectorError::SyncRequired => {
                 write!(f, "Insufficient chain data is available, sync required.")
             }
+            InputSelectorError::FeeOverrideTooLow { minimum, requested } => write!(
+                f,
+                "The requested fee of {} zatoshis is less than the minimum fee of {} zatoshis",
+                u64::from(*requested),
+                u64::from(*minimum)
+            ),
         }
     }
 }
@@ -157,7 +172,16 @@ pub trait InputSelector {
     ///
     /// If insufficient funds are available to satisfy the required outputs for the shielding
     /// request, this operation must fail and return [`InputSelectorError::InsufficientFunds`].
+    ///
+    /// Change is sent to the first pool in [`ProposalOptions::change_pool_preference`] for which
+    /// the fee can be covered; if the preference is empty, the change pool is chosen
+    /// automatically.
+    ///
+    /// If [`ProposalOptions::fee_override`] is set, the proposed transaction must pay that fee,
+    /// selecting additional inputs if necessary; if it is less than the fee required by the fee
+    /// rule, this operation must fail and return [`InputSelectorError::FeeOverrideTooLow`].
     #[allow(clippy::type_complexity)]
+    #[allow(clippy::too_many_arguments)]
     fn propose_transaction<ParamsT>(
         &self,
         params: &ParamsT,
@@ -166,6 +190,7 @@ pub trait InputSelector {
         anchor_height: BlockHeight,
         account: <Self::InputSource as InputSource>::AccountId,
         transaction_request: TransactionRequest,
+        options: &ProposalOptions,
     ) -> Result<
         Proposal<Self::FeeRule, <Self::InputSource as InputSource>::NoteRef>,
         InputSelectorError<<Self::InputSource as InputSource>::Error, Self::Error>,
@@ -198,7 +223,12 @@ pub trait ShieldingSelector {
     /// specified source addresses. If insufficient funds are available to satisfy the required
     /// outputs for the shielding request, this operation must fail and return
     /// [`InputSelectorError::InsufficientFunds`].
+    ///
+    /// If `change_memo` is provided, it is attached to each of the proposed shielded change
+    /// outputs (which receive the shielded funds), replacing any memo that would otherwise have
+    /// been provided by the change strategy.
     #[allow(clippy::type_complexity)]
+    #[allow(clippy::too_many_arguments)]
     fn propose_shielding<ParamsT>(
         &self,
         params: &ParamsT,
@@ -207,6 +237,7 @@ pub trait ShieldingSelector {
         source_addrs: &[TransparentAddress],
         target_height: BlockHeight,
         min_confirmations: u32,
+        change_memo: Option<MemoBytes>,
     ) -> Result<
         Proposal<Self::FeeRule, Infallible>,
         InputSelectorError<<Self::InputSource as InputSource>::Error, Self::Error>,
@@ -271,6 +302,77 @@ impl<DbErrT, ChangeStrategyErrT, NoteRefT> From<BalanceError>
     }
 }
 
+/// The result of applying a fee override to a balance computed by a change strategy.
+enum FeeOverride {
+    /// The balance, adjusted to pay the overridden fee.
+    Balanced(TransactionBalance),
+    /// The selected inputs are insufficient to pay the overridden fee; at least the given
+    /// additional amount must be selected.
+    Shortfall(NonNegativeAmount),
+}
+
+/// Adjusts `balance` so that the transaction pays `fee_override`, paying the excess over the
+/// required fee out of the proposed change and applying `dust_output_policy` to whatever change
+/// remains.
+fn override_fee<DbErrT, ChangeStrategyErrT, NoteRefT>(
+    balance: &TransactionBalance,
+    fee_override: NonNegativeAmount,
+    dust_output_policy: &DustOutputPolicy,
+) -> Result<
+    FeeOverride,
+    InputSelectorError<DbErrT, GreedyInputSelectorError<ChangeStrategyErrT, NoteRefT>>,
+> {
+    let minimum = balance.fee_required();
+    let mut excess = (fee_override - minimum).ok_or(InputSelectorError::FeeOverrideTooLow {
+        minimum,
+        requested: fee_override,
+    })?;
+
+    let change_total = (balance.total() - minimum).ok_or(BalanceError::Underflow)?;
+    if change_total < excess {
+        let shortfall = (excess - change_total).ok_or(BalanceError::Underflow)?;
+        return Ok(FeeOverride::Shortfall(shortfall));
+    }
+
+    // Pay the excess from the change outputs in order, then apply the dust policy to any change
+    // that remains.
+    let dust_threshold = dust_output_policy.dust_threshold().unwrap_or(MARGINAL_FEE);
+    let mut fee = fee_override;
+    let mut proposed_change = vec![];
+    for change in balance.proposed_change() {
+        let reduction = std::cmp::min(change.value(), excess);
+        excess = (excess - reduction).ok_or(BalanceError::Underflow)?;
+        let value = (change.value() - reduction).ok_or(BalanceError::Underflow)?;
+        if value.is_zero() {
+            continue;
+        }
+
+        if value < dust_threshold {
+            match dust_output_policy.action() {
+                DustAction::Reject => {
+                    let shortfall = (dust_threshold - value).ok_or(BalanceError::Underflow)?;
+                    return Ok(FeeOverride::Shortfall(shortfall));
+                }
+                DustAction::AllowDustChange => {}
+                DustAction::AddDustToFee => {
+                    fee = (fee + value).ok_or(BalanceError::Overflow)?;
+                    continue;
+                }
+            }
+        }
+
+        proposed_change.push(ChangeValue::new(
+            change.output_pool(),
+            value,
+            change.memo().cloned(),
+        ));
+    }
+
+    TransactionBalance::new(proposed_change, fee)
+        .map(FeeOverride::Balanced)
+        .map_err(|_| BalanceError::Overflow.into())
+}
+
 pub(crate) struct SaplingPayment(NonNegativeAmount);
 
 #[cfg(test)]
@@ -312,6 +414,8 @@ impl orchard_fees::OutputView for OrchardPayment {
 pub struct GreedyInputSelector<DbT, ChangeT> {
     change_strategy: ChangeT,
     dust_output_policy: DustOutputPolicy,
+    change_consolidation: bool,
+    respect_locks: bool,
     _ds_type: PhantomData<DbT>,
 }
 
@@ -322,9 +426,118 @@ impl<DbT, ChangeT: ChangeStrategy> GreedyInputSelector<DbT, ChangeT> {
         GreedyInputSelector {
             change_strategy,
             dust_output_policy,
+            change_consolidation: false,
+            respect_locks: true,
             _ds_type: PhantomData,
         }
     }
+
+    /// Sets whether this selector should attempt to consolidate change into an existing note.
+    ///
+    /// When enabled, if the inputs selected for a transaction would produce change, the selector
+    /// will attempt to spend one additional note from the same pools so that the single change
+    /// output replaces two of the wallet's notes, limiting the growth of the wallet's note set
+    /// over repeated sends. The additional note is the next note yielded by
+    /// [`InputSource::select_spendable_notes`]; if no such note is available, or if spending it
+    /// would not produce a valid balance, the selection is made as though this option were not
+    /// set. Disabled by default.
+    pub fn with_change_consolidation(mut self, change_consolidation: bool) -> Self {
+        self.change_consolidation = change_consolidation;
+        self
+    }
+
+    /// Sets whether this selector should refuse to spend notes that have been locked via
+    /// [`WalletWrite::lock_note`].
+    ///
+    /// Locked notes, as reported by [`InputSource::get_locked_note_refs`], are never selected
+    /// while the wallet's unlocked notes are sufficient to fund the transaction. When this
+    /// option is set to `false`, the selector will fall back to also selecting locked notes if
+    /// the unlocked notes are insufficient; otherwise, selection fails with
+    /// [`InputSelectorError::InsufficientFunds`]. Enabled by default.
+    ///
+    /// [`WalletWrite::lock_note`]: crate::data_api::WalletWrite::lock_note
+    pub fn with_respect_locks(mut self, respect_locks: bool) -> Self {
+        self.respect_locks = respect_locks;
+        self
+    }
+}
+
+impl<DbT, ChangeT> GreedyInputSelector<DbT, ChangeT>
+where
+    DbT: InputSource,
+    ChangeT: ChangeStrategy,
+{
+    /// Selects one additional note from the pools retained by `retention` that is not among
+    /// `selected` or `exclude`, returning the union of `selected` and that note if one exists.
+    #[allow(clippy::type_complexity)]
+    fn select_consolidation_note(
+        &self,
+        wallet_db: &DbT,
+        account: DbT::AccountId,
+        anchor_height: BlockHeight,
+        selected: &SpendableNotes<DbT::NoteRef>,
+        retention: &SimpleNoteRetention,
+        exclude: &[DbT::NoteRef],
+    ) -> Result<
+        Option<SpendableNotes<DbT::NoteRef>>,
+        InputSelectorError<DbT::Error, GreedyInputSelectorError<ChangeT::Error, DbT::NoteRef>>,
+    > {
+        let mut pools = vec![];
+        if retention.sapling {
+            pools.push(ShieldedProtocol::Sapling);
+        }
+        #[cfg(feature = "orchard")]
+        if retention.orchard {
+            pools.push(ShieldedProtocol::Orchard);
+        }
+
+        let selected_ids = selected.sapling().iter().map(|n| *n.internal_note_id());
+        #[cfg(feature = "orchard")]
+        let selected_ids =
+            selected_ids.chain(selected.orchard().iter().map(|n| *n.internal_note_id()));
+        let exclude = exclude
+            .iter()
+            .copied()
+            .chain(selected_ids)
+            .collect::<Vec<_>>();
+
+        // Requesting the smallest possible target value causes at most one note to be selected
+        // from each pool.
+        let candidates = wallet_db
+            .select_spendable_notes(
+                account,
+                NonNegativeAmount::const_from_u64(1),
+                &pools,
+                anchor_height,
+                &exclude,
+            )
+            .map_err(InputSelectorError::DataSource)?;
+
+        let mut sapling_notes = selected.sapling().to_vec();
+        #[cfg(feature = "orchard")]
+        let mut orchard_notes = selected.orchard().to_vec();
+
+        if let Some(note) = candidates.sapling().first() {
+            sapling_notes.push(note.clone());
+        } else {
+            #[cfg(feature = "orchard")]
+            if let Some(note) = candidates.orchard().first() {
+                orchard_notes.push(note.clone());
+            }
+        }
+
+        let found = sapling_notes.len() > selected.sapling().len();
+        #[cfg(feature = "orchard")]
+        let found = found || orchard_notes.len() > selected.orchard().len();
+
+        Ok(found.then(|| {
+            SpendableNotes::new(
+                sapling_notes,
+                #[cfg(feature = "orchard")]
+                orchard_notes,
+            )
+        }))
+    }
 }
 
 impl<DbT, ChangeT> InputSelector for GreedyInputSelector<DbT, ChangeT>
@@ -346,6 +559,7 @@ where
         anchor_height: BlockHeight,
         account: <DbT as InputSource>::AccountId,
         transaction_request: TransactionRequest,
+        options: &ProposalOptions,
     ) -> Result<
         Proposal<Self::FeeRule, DbT::NoteRef>,
         InputSelectorError<<DbT as InputSource>::Error, Self::Error>,
@@ -410,7 +624,13 @@ where
         let mut shielded_inputs = SpendableNotes::empty();
         let mut prior_available = NonNegativeAmount::ZERO;
         let mut amount_required = NonNegativeAmount::ZERO;
-        let mut exclude: Vec<DbT::NoteRef> = vec![];
+        let locked = wallet_db
+            .get_locked_note_refs(account)
+            .map_err(InputSelectorError::DataSource)?;
+        let mut exclude: Vec<DbT::NoteRef> = locked.clone();
+        let mut locks_released = false;
+        let mut consolidation_attempted = false;
+        let mut consolidation_fallback = None;
         // This loop is guaranteed to terminate because on each iteration we check that the amount
         // of funds selected is strictly increasing. The loop will either return a successful
         // result or the wallet will eventually run out of funds to select.
@@ -473,27 +693,44 @@ where
                     &orchard_outputs[..],
                 ),
                 &self.dust_output_policy,
+                options.change_pool_preference(),
             );
 
-            match balance {
+            let retention = SimpleNoteRetention {
+                sapling: use_sapling,
+                #[cfg(feature = "orchard")]
+                orchard: use_orchard,
+            };
+
+            let selected = match balance {
                 Ok(balance) => {
-                    return Proposal::single_step(
-                        transaction_request,
-                        payment_pools,
-                        vec![],
-                        NonEmpty::from_vec(shielded_inputs.into_vec(&SimpleNoteRetention {
-                            sapling: use_sapling,
-                            #[cfg(feature = "orchard")]
-                            orchard: use_orchard,
-                        }))
-                        .map(|notes| ShieldedInputs::from_parts(anchor_height, notes)),
-                        balance,
-                        (*self.change_strategy.fee_rule()).clone(),
-                        target_height,
-                        false,
-                    )
-                    .map_err(InputSelectorError::Proposal);
+                    if self.change_consolidation
+                        && !consolidation_attempted
+                        && balance
+                            .proposed_change()
+                            .iter()
+                            .any(|c| c.value().is_positive())
+                    {
+                        consolidation_attempted = true;
+                        if let Some(consolidated) = self.select_consolidation_note(
+                            wallet_db,
+                            account,
+                            anchor_height,
+                            &shielded_inputs,
+                            &retention,
+                            &exclude,
+                        )? {
+                            // Retry with the additional note, falling back to the current
+                            // selection if the resulting balance is invalid.
+                            consolidation_fallback = Some((shielded_inputs, balance, retention));
+                            shielded_inputs = consolidated;
+                            continue;
+                        }
+                    }
+
+                    Some((shielded_inputs, balance, retention))
                 }
+                Err(_) if consolidation_fallback.is_some() => consolidation_fallback.take(),
                 Err(ChangeError::DustInputs {
                     mut sapling,
                     #[cfg(feature = "orchard")]
@@ -503,11 +740,44 @@ where
                     exclude.append(&mut sapling);
                     #[cfg(feature = "orchard")]
                     exclude.append(&mut orchard);
+                    None
                 }
                 Err(ChangeError::InsufficientFunds { required, .. }) => {
                     amount_required = required;
+                    None
                 }
                 Err(other) => return Err(other.into()),
+            };
+
+            let selected = match (selected, options.fee_override()) {
+                (Some((inputs, balance, retention)), Some(fee_override)) => {
+                    match override_fee(&balance, fee_override, &self.dust_output_policy)? {
+                        FeeOverride::Balanced(balance) => Some((inputs, balance, retention)),
+                        FeeOverride::Shortfall(shortfall) => {
+                            // Select additional inputs to cover the overridden fee.
+                            amount_required = (inputs.total_value()? + shortfall)
+                                .ok_or(BalanceError::Overflow)?;
+                            shielded_inputs = inputs;
+                            None
+                        }
+                    }
+                }
+                (selected, _) => selected,
+            };
+
+            if let Some((inputs, balance, retention)) = selected {
+                return Proposal::single_step(
+                    transaction_request,
+                    payment_pools,
+                    vec![],
+                    NonEmpty::from_vec(inputs.into_vec(&retention))
+                        .map(|notes| ShieldedInputs::from_parts(anchor_height, notes)),
+                    balance,
+                    (*self.change_strategy.fee_rule()).clone(),
+                    target_height,
+                    false,
+                )
+                .map_err(InputSelectorError::Proposal);
             }
 
             #[cfg(not(feature = "orchard"))]
@@ -527,6 +797,14 @@ where
 
             let new_available = shielded_inputs.total_value()?;
             if new_available <= prior_available {
+                // The unlocked notes are insufficient; if permitted, retry with locked notes
+                // made available for selection.
+                if !self.respect_locks && !locks_released && !locked.is_empty() {
+                    locks_released = true;
+                    exclude.retain(|note_ref| !locked.contains(note_ref));
+                    continue;
+                }
+
                 return Err(InputSelectorError::InsufficientFunds {
                     required: amount_required,
                     available: new_available,
@@ -560,6 +838,7 @@ where
         source_addrs: &[TransparentAddress],
         target_height: BlockHeight,
         min_confirmations: u32,
+        change_memo: Option<MemoBytes>,
     ) -> Result<
         Proposal<Self::FeeRule, Infallible>,
         InputSelectorError<<DbT as InputSource>::Error, Self::Error>,
@@ -580,6 +859,8 @@ where
             .map_err(InputSelectorError::DataSource)?
             .into_iter()
             .flat_map(|v| v.into_iter())
+            // Coinbase outputs may not be spent until they have matured.
+            .filter(|output| output.is_mature_at(target_height))
             .collect();
 
         let trial_balance = self.change_strategy.compute_balance(
@@ -599,6 +880,7 @@ where
                 &Vec::<Infallible>::new()[..],
             ),
             &self.dust_output_policy,
+            &[],
         );
 
         let balance = match trial_balance {
@@ -624,6 +906,7 @@ where
                         &Vec::<Infallible>::new()[..],
                     ),
                     &self.dust_output_policy,
+                    &[],
                 )?
             }
             Err(other) => {
@@ -631,6 +914,20 @@ where
             }
         };
 
+        // Attach the requested memo to the change outputs that receive the shielded funds.
+        let balance = match change_memo {
+            Some(memo) => TransactionBalance::new(
+                balance
+                    .proposed_change()
+                    .iter()
+                    .map(|c| ChangeValue::new(c.output_pool(), c.value(), Some(memo.clone())))
+                    .collect(),
+                balance.fee_required(),
+            )
+            .expect("Change and fee values are unchanged, so their sum cannot overflow."),
+            None => balance,
+        };
+
         if balance.total() >= shielding_threshold {
             Proposal::single_step(
                 TransactionRequest::empty(),
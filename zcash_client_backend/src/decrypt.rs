@@ -1,7 +1,12 @@
 use std::collections::HashMap;
 
+use incrementalmerkletree::Position;
+use rayon::prelude::*;
 use sapling::note_encryption::{PreparedIncomingViewingKey, SaplingDomain};
-use zcash_note_encryption::{try_note_decryption, try_output_recovery_with_ovk};
+use zcash_note_encryption::{
+    batch, try_note_decryption, try_output_recovery_with_ovk, BatchDomain, ShieldedOutput,
+    ENC_CIPHERTEXT_SIZE,
+};
 use zcash_primitives::{
     consensus::{self, BlockHeight},
     memo::MemoBytes,
@@ -215,9 +220,319 @@ pub fn decrypt_transaction<'a, P: consensus::Parameters, AccountId: Copy>(
         .collect();
 
     DecryptedTransaction::new(
+        None,
+        tx,
+        sapling_outputs,
+        #[cfg(feature = "orchard")]
+        orchard_outputs,
+    )
+}
+
+/// The number of outputs that are trial-decrypted together in each batch by
+/// [`decrypt_transaction_parallel`].
+const DECRYPTION_BATCH_SIZE: usize = 16;
+
+/// Scans a [`Transaction`] for any information that can be decrypted by the set of
+/// [`UnifiedFullViewingKey`]s, using batched trial decryption of the transaction's outputs in
+/// parallel across the rayon thread pool.
+///
+/// This returns the same result as [`decrypt_transaction`], including the precedence of
+/// decryption with an account's external incoming viewing key over its internal incoming
+/// viewing key and its outgoing viewing key, but is considerably faster for transactions with
+/// many shielded outputs.
+pub fn decrypt_transaction_parallel<'a, P: consensus::Parameters, AccountId: Copy + Send + Sync>(
+    params: &P,
+    height: BlockHeight,
+    tx: &'a Transaction,
+    ufvks: &HashMap<AccountId, UnifiedFullViewingKey>,
+) -> DecryptedTransaction<'a, AccountId> {
+    let zip212_enforcement = zip212_enforcement(params, height);
+    let sapling_outputs = tx.sapling_bundle().map_or_else(Vec::new, |bundle| {
+        let keys = ufvks
+            .iter()
+            .flat_map(|(account, ufvk)| {
+                ufvk.sapling().into_iter().map(move |dfvk| {
+                    (
+                        *account,
+                        [
+                            PreparedIncomingViewingKey::new(&dfvk.to_ivk(Scope::External)),
+                            PreparedIncomingViewingKey::new(&dfvk.to_ivk(Scope::Internal)),
+                        ],
+                        dfvk.fvk().ovk,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let outputs = bundle
+            .shielded_outputs()
+            .iter()
+            .map(|output| (SaplingDomain::new(zip212_enforcement), output.clone()))
+            .collect::<Vec<_>>();
+
+        batch_decrypt_outputs(&keys, &outputs, |domain, ovk, output| {
+            try_output_recovery_with_ovk(domain, ovk, output, output.cv(), output.out_ciphertext())
+        })
+    });
+
+    #[cfg(feature = "orchard")]
+    let orchard_outputs = tx.orchard_bundle().map_or_else(Vec::new, |bundle| {
+        let keys = ufvks
+            .iter()
+            .flat_map(|(account, ufvk)| {
+                ufvk.orchard().into_iter().map(move |fvk| {
+                    (
+                        *account,
+                        [
+                            orchard::keys::PreparedIncomingViewingKey::new(
+                                &fvk.to_ivk(Scope::External),
+                            ),
+                            orchard::keys::PreparedIncomingViewingKey::new(
+                                &fvk.to_ivk(Scope::Internal),
+                            ),
+                        ],
+                        fvk.to_ovk(Scope::External),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let outputs = bundle
+            .actions()
+            .iter()
+            .map(|action| (OrchardDomain::for_action(action), action.clone()))
+            .collect::<Vec<_>>();
+
+        batch_decrypt_outputs(&keys, &outputs, |domain, ovk, action| {
+            try_output_recovery_with_ovk(
+                domain,
+                ovk,
+                action,
+                action.cv_net(),
+                &action.encrypted_note().out_ciphertext,
+            )
+        })
+    });
+
+    DecryptedTransaction::new(
+        None,
         tx,
         sapling_outputs,
         #[cfg(feature = "orchard")]
         orchard_outputs,
     )
 }
+
+/// Trial-decrypts the given outputs with the keys of each account, in batches of
+/// [`DECRYPTION_BATCH_SIZE`] outputs that are processed in parallel.
+///
+/// Each entry of `keys` holds an account's external and internal incoming viewing keys, in that
+/// order, along with its outgoing viewing key. Outputs that cannot be decrypted with either
+/// incoming viewing key are recovered using `recover_with_ovk`, if possible. The results are
+/// ordered by account (in the order of `keys`) and then by output index.
+fn batch_decrypt_outputs<D, Output, AccountId>(
+    keys: &[(AccountId, [D::IncomingViewingKey; 2], D::OutgoingViewingKey)],
+    outputs: &[(D, Output)],
+    recover_with_ovk: impl Fn(&D, &D::OutgoingViewingKey, &Output) -> Option<(D::Note, D::Recipient, D::Memo)>
+        + Sync,
+) -> Vec<DecryptedOutput<D::Note, AccountId>>
+where
+    D: BatchDomain + Sync,
+    D::IncomingViewingKey: Sync,
+    D::OutgoingViewingKey: Sync,
+    D::Note: Send,
+    D::Memo: AsRef<[u8]>,
+    Output: ShieldedOutput<D, ENC_CIPHERTEXT_SIZE> + Sync,
+    AccountId: Copy + Send + Sync,
+{
+    let recover_with_ovk = &recover_with_ovk;
+    keys.par_iter()
+        .flat_map(|(account, ivks, ovk)| {
+            outputs
+                .par_chunks(DECRYPTION_BATCH_SIZE)
+                .enumerate()
+                .flat_map_iter(move |(chunk_index, chunk)| {
+                    batch::try_note_decryption(&ivks[..], chunk)
+                        .into_iter()
+                        .zip(chunk)
+                        .enumerate()
+                        .filter_map(move |(i, (decrypted, (domain, output)))| {
+                            decrypted
+                                .map(|((note, _, memo), ivk_index)| {
+                                    let transfer_type = if ivk_index == 0 {
+                                        TransferType::Incoming
+                                    } else {
+                                        TransferType::WalletInternal
+                                    };
+                                    (note, memo, transfer_type)
+                                })
+                                .or_else(|| {
+                                    recover_with_ovk(domain, ovk, output)
+                                        .map(|(note, _, memo)| (note, memo, TransferType::Outgoing))
+                                })
+                                .map(|(note, memo, transfer_type)| {
+                                    DecryptedOutput::new(
+                                        chunk_index * DECRYPTION_BATCH_SIZE + i,
+                                        note,
+                                        *account,
+                                        MemoBytes::from_bytes(memo.as_ref())
+                                            .expect("correct length"),
+                                        transfer_type,
+                                    )
+                                })
+                        })
+                })
+        })
+        .collect()
+}
+
+/// Computes the position within the note commitment tree of the note in each of the given
+/// decrypted outputs.
+///
+/// [`decrypt_transaction`] has no knowledge of the note commitment tree, so the outputs it
+/// returns do not carry their positions. `tree_size` must be the size of the note commitment tree
+/// for the pool of the outputs' notes immediately prior to the transaction from which they were
+/// decrypted; that is, the tree size at the end of the preceding block (as reported by its
+/// [`BlockMetadata`]) plus the number of note commitments added by earlier transactions within
+/// the same block.
+///
+/// The returned positions are in the same order as `outputs`.
+///
+/// [`BlockMetadata`]: crate::data_api::BlockMetadata
+pub fn locate_decrypted_notes<Note, AccountId>(
+    outputs: &[DecryptedOutput<Note, AccountId>],
+    tree_size: u32,
+) -> Vec<Position> {
+    outputs
+        .iter()
+        .map(|output| Position::from(u64::from(tree_size) + output.index as u64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use incrementalmerkletree::Position;
+    use rand_core::OsRng;
+    use sapling::{
+        prover::mock::{MockOutputProver, MockSpendProver},
+        value::NoteValue,
+    };
+    use zcash_primitives::{
+        consensus::{BranchId, Network, NetworkUpgrade, Parameters},
+        memo::MemoBytes,
+        transaction::{
+            components::{sapling::zip212_enforcement, Amount},
+            Authorized, TransactionData, TxVersion,
+        },
+        zip32::{AccountId, Scope},
+    };
+
+    use super::{
+        decrypt_transaction, decrypt_transaction_parallel, locate_decrypted_notes, DecryptedOutput,
+        TransferType,
+    };
+    use crate::keys::UnifiedSpendingKey;
+
+    #[test]
+    fn decrypt_transaction_parallel_matches_decrypt_transaction() {
+        let mut rng = OsRng;
+        let network = Network::TestNetwork;
+        let height = network.activation_height(NetworkUpgrade::Nu5).unwrap();
+
+        let ufvk = UnifiedSpendingKey::from_seed(&network, &[0; 32], AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key();
+        let dfvk = ufvk.sapling().unwrap().clone();
+        let (_, external) = dfvk.default_address();
+        let (_, internal) = dfvk.change_address();
+        let ovk = Some(dfvk.to_ovk(Scope::External));
+        let (_, other) = UnifiedSpendingKey::from_seed(&network, &[1; 32], AccountId::ZERO)
+            .unwrap()
+            .to_unified_full_viewing_key()
+            .sapling()
+            .unwrap()
+            .default_address();
+        let ufvks = HashMap::from([(AccountId::ZERO, ufvk)]);
+
+        // Construct a transaction with outputs that are decrypted with each of the account's
+        // keys, and one that the account cannot decrypt.
+        let tx = {
+            let mut builder = sapling::builder::Builder::new(
+                zip212_enforcement(&network, height),
+                sapling::builder::BundleType::Coinbase,
+                sapling::Anchor::empty_tree(),
+            );
+            for (ovk, to, memo) in [
+                (ovk, external, [1; 512]),
+                (ovk, internal, [2; 512]),
+                (ovk, other, [3; 512]),
+                (None, other, [4; 512]),
+            ] {
+                builder
+                    .add_output(ovk, to, NoteValue::from_raw(100), Some(memo))
+                    .unwrap();
+            }
+            let (bundle, _) = builder
+                .build::<MockSpendProver, MockOutputProver, _, Amount>(&mut rng)
+                .unwrap()
+                .unwrap();
+            let bundle = bundle
+                .create_proofs(&MockSpendProver, &MockOutputProver, &mut rng, ())
+                .apply_signatures(&mut rng, [0; 32], &[])
+                .unwrap();
+
+            TransactionData::<Authorized>::from_parts(
+                TxVersion::Zip225,
+                BranchId::Nu5,
+                0,
+                height + 20,
+                None,
+                None,
+                Some(bundle),
+                None,
+            )
+            .freeze()
+            .unwrap()
+        };
+
+        let summarize = |outputs: &[DecryptedOutput<sapling::Note, AccountId>]| {
+            let mut summary = outputs
+                .iter()
+                .map(|output| {
+                    (
+                        output.index(),
+                        output.note().clone(),
+                        *output.account(),
+                        output.memo().clone(),
+                        output.transfer_type(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            summary.sort_by_key(|(index, _, _, _, _)| *index);
+            summary
+        };
+
+        let d_tx = decrypt_transaction(&network, height, &tx, &ufvks);
+        let d_tx_parallel = decrypt_transaction_parallel(&network, height, &tx, &ufvks);
+        assert_eq!(d_tx.sapling_outputs().len(), 3);
+        assert_eq!(
+            summarize(d_tx_parallel.sapling_outputs()),
+            summarize(d_tx.sapling_outputs())
+        );
+    }
+
+    #[test]
+    fn locate_decrypted_notes_offsets_output_indices() {
+        let outputs = [0, 3]
+            .into_iter()
+            .map(|index| {
+                DecryptedOutput::new(index, (), 0u32, MemoBytes::empty(), TransferType::Incoming)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            locate_decrypted_notes(&outputs, 100),
+            vec![Position::from(100), Position::from(103)]
+        );
+    }
+}
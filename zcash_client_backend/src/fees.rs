@@ -8,13 +8,20 @@ use zcash_primitives::{
             amount::{BalanceError, NonNegativeAmount},
             OutPoint,
         },
-        fees::{transparent, FeeRule},
+        fees::{
+            dynamic::FeeRule as DynamicFeeRule,
+            fixed::FeeRule as FixedFeeRule,
+            transparent,
+            zip317::{FeeRule as Zip317FeeRule, MINIMUM_FEE},
+            FeeRule, StandardFeeRule,
+        },
     },
 };
 
 use crate::ShieldedProtocol;
 
 pub(crate) mod common;
+pub mod dynamic;
 pub mod fixed;
 #[cfg(feature = "orchard")]
 pub mod orchard;
@@ -245,7 +252,9 @@ pub enum DustAction {
     Reject,
     /// Explicitly allow the creation of dust change amounts greater than the specified value.
     AllowDustChange,
-    /// Allow dust amounts to be added to the transaction fee
+    /// Allow dust amounts to be added to the transaction fee. This is the default action, so
+    /// that dust change is paid to miners rather than creating a note that is uneconomic to
+    /// spend.
     AddDustToFee,
 }
 
@@ -284,7 +293,102 @@ impl DustOutputPolicy {
 
 impl Default for DustOutputPolicy {
     fn default() -> Self {
-        DustOutputPolicy::new(DustAction::Reject, None)
+        DustOutputPolicy::new(DustAction::AddDustToFee, None)
+    }
+}
+
+/// A description of a fee rule and the values of its parameters.
+///
+/// This allows the fee rule used by a proposal to be displayed to the user, or to be
+/// reconstructed by a party (such as an offline signer) that did not create the proposal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeeRuleParams {
+    /// A fee rule that requires the same fee for every transaction.
+    Fixed { fee: NonNegativeAmount },
+    /// The [ZIP 317] fee rule, with the given parameters.
+    ///
+    /// [ZIP 317]: https//zips.z.cash/zip-0317
+    Zip317 {
+        marginal_fee: NonNegativeAmount,
+        grace_actions: usize,
+        p2pkh_standard_input_size: usize,
+        p2pkh_standard_output_size: usize,
+    },
+    /// A fee rule that scales the fee required by a base fee rule by a multiplier expressed in
+    /// basis points.
+    Scaled {
+        base: Box<FeeRuleParams>,
+        multiplier_bps: u16,
+    },
+}
+
+impl fmt::Display for FeeRuleParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeRuleParams::Fixed { fee } => write!(f, "fixed ({} zat)", u64::from(*fee)),
+            FeeRuleParams::Zip317 {
+                marginal_fee,
+                grace_actions,
+                ..
+            } => write!(
+                f,
+                "ZIP-317 ({} zat/action, {} grace)",
+                u64::from(*marginal_fee),
+                grace_actions
+            ),
+            FeeRuleParams::Scaled {
+                base,
+                multiplier_bps,
+            } => write!(f, "{} scaled by {} bps", base, multiplier_bps),
+        }
+    }
+}
+
+/// A trait for fee rules that can describe themselves in terms of [`FeeRuleParams`].
+pub trait DescribeFeeRule {
+    /// Returns a description of this fee rule and its parameters.
+    fn fee_rule_params(&self) -> FeeRuleParams;
+}
+
+impl DescribeFeeRule for StandardFeeRule {
+    fn fee_rule_params(&self) -> FeeRuleParams {
+        #[allow(deprecated)]
+        match self {
+            StandardFeeRule::PreZip313 => FixedFeeRule::non_standard(MINIMUM_FEE).fee_rule_params(),
+            StandardFeeRule::Zip313 => {
+                FixedFeeRule::non_standard(NonNegativeAmount::const_from_u64(1000))
+                    .fee_rule_params()
+            }
+            StandardFeeRule::Zip317 => Zip317FeeRule::standard().fee_rule_params(),
+        }
+    }
+}
+
+impl DescribeFeeRule for FixedFeeRule {
+    fn fee_rule_params(&self) -> FeeRuleParams {
+        FeeRuleParams::Fixed {
+            fee: self.fixed_fee(),
+        }
+    }
+}
+
+impl DescribeFeeRule for Zip317FeeRule {
+    fn fee_rule_params(&self) -> FeeRuleParams {
+        FeeRuleParams::Zip317 {
+            marginal_fee: self.marginal_fee(),
+            grace_actions: self.grace_actions(),
+            p2pkh_standard_input_size: self.p2pkh_standard_input_size(),
+            p2pkh_standard_output_size: self.p2pkh_standard_output_size(),
+        }
+    }
+}
+
+impl<R: DescribeFeeRule> DescribeFeeRule for DynamicFeeRule<R> {
+    fn fee_rule_params(&self) -> FeeRuleParams {
+        FeeRuleParams::Scaled {
+            base: Box::new(self.base().fee_rule_params()),
+            multiplier_bps: self.multiplier_bps(),
+        }
     }
 }
 
@@ -305,6 +409,11 @@ pub trait ChangeStrategy {
     /// change outputs recommended by this operation. If insufficient funds are available to
     /// supply the requested outputs and required fees, implementations should return
     /// [`ChangeError::InsufficientFunds`].
+    ///
+    /// Change is sent to the first pool in `change_pool_preference` for which the fee can be
+    /// covered by the provided inputs. If `change_pool_preference` is empty, the strategy
+    /// chooses the change pool itself, preferring Orchard whenever the transaction has any
+    /// Orchard inputs or outputs.
     #[allow(clippy::too_many_arguments)]
     fn compute_balance<P: consensus::Parameters, NoteRefT: Clone>(
         &self,
@@ -315,6 +424,7 @@ pub trait ChangeStrategy {
         sapling: &impl sapling::BundleView<NoteRefT>,
         #[cfg(feature = "orchard")] orchard: &impl orchard::BundleView<NoteRefT>,
         dust_output_policy: &DustOutputPolicy,
+        change_pool_preference: &[ShieldedProtocol],
     ) -> Result<TransactionBalance, ChangeError<Self::Error, NoteRefT>>;
 }
 
@@ -325,10 +435,10 @@ pub(crate) mod tests {
             amount::NonNegativeAmount,
             transparent::{OutPoint, TxOut},
         },
-        fees::transparent,
+        fees::{dynamic, fixed, transparent, zip317, StandardFeeRule},
     };
 
-    use super::sapling;
+    use super::{sapling, DescribeFeeRule, FeeRuleParams};
 
     #[derive(Debug)]
     pub(crate) struct TestTransparentInput {
@@ -358,4 +468,39 @@ pub(crate) mod tests {
             self.value
         }
     }
+
+    #[test]
+    fn fee_rule_params() {
+        let zip317_params = FeeRuleParams::Zip317 {
+            marginal_fee: NonNegativeAmount::const_from_u64(5000),
+            grace_actions: 2,
+            p2pkh_standard_input_size: 150,
+            p2pkh_standard_output_size: 34,
+        };
+        assert_eq!(StandardFeeRule::Zip317.fee_rule_params(), zip317_params);
+        assert_eq!(
+            zip317::FeeRule::standard().fee_rule_params().to_string(),
+            "ZIP-317 (5000 zat/action, 2 grace)"
+        );
+
+        assert_eq!(
+            fixed::FeeRule::non_standard(NonNegativeAmount::const_from_u64(1000)).fee_rule_params(),
+            FeeRuleParams::Fixed {
+                fee: NonNegativeAmount::const_from_u64(1000)
+            }
+        );
+
+        let scaled = dynamic::FeeRule::new(zip317::FeeRule::standard(), 15_000);
+        assert_eq!(
+            scaled.fee_rule_params(),
+            FeeRuleParams::Scaled {
+                base: Box::new(zip317_params),
+                multiplier_bps: 15_000,
+            }
+        );
+        assert_eq!(
+            scaled.fee_rule_params().to_string(),
+            "ZIP-317 (5000 zat/action, 2 grace) scaled by 15000 bps"
+        );
+    }
 }
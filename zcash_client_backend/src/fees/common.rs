@@ -91,35 +91,83 @@ where
     })
 }
 
+/// Decides which pool to send change to, and how many change outputs that adds to each bundle.
+///
+/// Change is sent to the first supported pool in `change_pool_preference`; if the preference is
+/// empty, it is sent to Orchard if the transaction has any Orchard flows, otherwise to Sapling
+/// if it has any Sapling flows, and otherwise to `fallback_change_pool`.
 pub(crate) fn single_change_output_policy<NoteRefT: Clone, F: FeeRule, E>(
-    _net_flows: &NetFlows,
-    _fallback_change_pool: ShieldedProtocol,
+    net_flows: &NetFlows,
+    fallback_change_pool: ShieldedProtocol,
+    change_pool_preference: &[ShieldedProtocol],
 ) -> Result<(ShieldedProtocol, usize, usize), ChangeError<E, NoteRefT>>
 where
     E: From<F::Error> + From<BalanceError>,
 {
+    let change_pool = match change_pool_candidates(change_pool_preference)?.first() {
+        Some(pool) => *pool,
+        None => default_change_pool(net_flows, fallback_change_pool),
+    };
+
+    Ok(change_output_counts(change_pool))
+}
+
+/// Returns the pools in `change_pool_preference` that change may be sent to, in order of
+/// preference and without duplicates.
+///
+/// Returns an error if a preference is given but none of its pools is supported.
+fn change_pool_candidates<E, NoteRefT>(
+    change_pool_preference: &[ShieldedProtocol],
+) -> Result<Vec<ShieldedProtocol>, ChangeError<E, NoteRefT>> {
+    let mut candidates = Vec::with_capacity(change_pool_preference.len());
+    for pool in change_pool_preference {
+        #[cfg(not(feature = "orchard"))]
+        if *pool == ShieldedProtocol::Orchard {
+            continue;
+        }
+        if !candidates.contains(pool) {
+            candidates.push(*pool);
+        }
+    }
+
+    if candidates.is_empty() && !change_pool_preference.is_empty() {
+        return Err(ChangeError::BundleError(
+            "None of the preferred change pools is supported.",
+        ));
+    }
+    Ok(candidates)
+}
+
+/// Returns the pool to which change is sent when no change pool preference is given.
+fn default_change_pool(
+    _net_flows: &NetFlows,
+    _fallback_change_pool: ShieldedProtocol,
+) -> ShieldedProtocol {
     // TODO: implement a less naive strategy for selecting the pool to which change will be sent.
     #[cfg(feature = "orchard")]
-    let (change_pool, sapling_change, orchard_change) =
-        if _net_flows.orchard_in.is_positive() || _net_flows.orchard_out.is_positive() {
-            // Send change to Orchard if we're spending any Orchard inputs or creating any Orchard outputs
-            (ShieldedProtocol::Orchard, 0, 1)
-        } else if _net_flows.sapling_in.is_positive() || _net_flows.sapling_out.is_positive() {
-            // Otherwise, send change to Sapling if we're spending any Sapling inputs or creating any
-            // Sapling outputs, so that we avoid pool-crossing.
-            (ShieldedProtocol::Sapling, 1, 0)
-        } else {
-            // This is a fully-transparent transaction, so the caller gets to decide
-            // where to shield change.
-            match _fallback_change_pool {
-                ShieldedProtocol::Orchard => (_fallback_change_pool, 0, 1),
-                ShieldedProtocol::Sapling => (_fallback_change_pool, 1, 0),
-            }
-        };
+    if _net_flows.orchard_in.is_positive() || _net_flows.orchard_out.is_positive() {
+        // Send change to Orchard if we're spending any Orchard inputs or creating any Orchard outputs
+        ShieldedProtocol::Orchard
+    } else if _net_flows.sapling_in.is_positive() || _net_flows.sapling_out.is_positive() {
+        // Otherwise, send change to Sapling if we're spending any Sapling inputs or creating any
+        // Sapling outputs, so that we avoid pool-crossing.
+        ShieldedProtocol::Sapling
+    } else {
+        // This is a fully-transparent transaction, so the caller gets to decide
+        // where to shield change.
+        _fallback_change_pool
+    }
     #[cfg(not(feature = "orchard"))]
-    let (change_pool, sapling_change, orchard_change) = (ShieldedProtocol::Sapling, 1, 0);
+    ShieldedProtocol::Sapling
+}
 
-    Ok((change_pool, sapling_change, orchard_change))
+/// Returns the given change pool along with the number of change outputs that sending change
+/// to it adds to the Sapling and Orchard bundles respectively.
+fn change_output_counts(change_pool: ShieldedProtocol) -> (ShieldedProtocol, usize, usize) {
+    match change_pool {
+        ShieldedProtocol::Sapling => (change_pool, 1, 0),
+        ShieldedProtocol::Orchard => (change_pool, 0, 1),
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -139,14 +187,12 @@ pub(crate) fn single_change_output_balance<
     dust_output_policy: &DustOutputPolicy,
     default_dust_threshold: NonNegativeAmount,
     change_memo: Option<MemoBytes>,
-    _fallback_change_pool: ShieldedProtocol,
+    fallback_change_pool: ShieldedProtocol,
+    change_pool_preference: &[ShieldedProtocol],
 ) -> Result<TransactionBalance, ChangeError<E, NoteRefT>>
 where
     E: From<F::Error> + From<BalanceError>,
 {
-    let overflow = || ChangeError::StrategyError(E::from(BalanceError::Overflow));
-    let underflow = || ChangeError::StrategyError(E::from(BalanceError::Underflow));
-
     let net_flows = calculate_net_flows::<NoteRefT, F, E>(
         transparent_inputs,
         transparent_outputs,
@@ -154,53 +200,143 @@ where
         #[cfg(feature = "orchard")]
         orchard,
     )?;
-    let (change_pool, sapling_change, _orchard_change) =
-        single_change_output_policy::<NoteRefT, F, E>(&net_flows, _fallback_change_pool)?;
-
-    let sapling_input_count = sapling
-        .bundle_type()
-        .num_spends(sapling.inputs().len())
-        .map_err(ChangeError::BundleError)?;
-    let sapling_output_count = sapling
-        .bundle_type()
-        .num_outputs(
-            sapling.inputs().len(),
-            sapling.outputs().len() + sapling_change,
-        )
-        .map_err(ChangeError::BundleError)?;
 
-    #[cfg(feature = "orchard")]
-    let orchard_action_count = orchard
-        .bundle_type()
-        .num_actions(
-            orchard.inputs().len(),
-            orchard.outputs().len() + _orchard_change,
-        )
-        .map_err(ChangeError::BundleError)?;
-    #[cfg(not(feature = "orchard"))]
-    let orchard_action_count = 0;
+    // Try each preferred change pool in turn, moving on to the next pool only if the fee
+    // for sending change to a pool cannot be covered by the inputs.
+    let candidates = change_pool_candidates(change_pool_preference)?;
+    if candidates.is_empty() {
+        return change_output_balance(
+            params,
+            fee_rule,
+            target_height,
+            transparent_inputs,
+            transparent_outputs,
+            sapling,
+            #[cfg(feature = "orchard")]
+            orchard,
+            dust_output_policy,
+            default_dust_threshold,
+            change_memo,
+            &net_flows,
+            default_change_pool(&net_flows, fallback_change_pool),
+        );
+    }
 
-    let fee_amount = fee_rule
-        .fee_required(
+    let mut result = None;
+    for change_pool in candidates {
+        let balance = change_output_balance(
             params,
+            fee_rule,
             target_height,
             transparent_inputs,
             transparent_outputs,
-            sapling_input_count,
-            sapling_output_count,
-            orchard_action_count,
-        )
-        .map_err(|fee_error| ChangeError::StrategyError(E::from(fee_error)))?;
+            sapling,
+            #[cfg(feature = "orchard")]
+            orchard,
+            dust_output_policy,
+            default_dust_threshold,
+            change_memo.clone(),
+            &net_flows,
+            change_pool,
+        );
+        match balance {
+            Err(ChangeError::InsufficientFunds { .. }) => result = Some(balance),
+            _ => return balance,
+        }
+    }
+    result.expect("There is at least one candidate change pool.")
+}
+
+/// Computes the balance of a transaction that sends any change to `change_pool`.
+#[allow(clippy::too_many_arguments)]
+fn change_output_balance<P: consensus::Parameters, NoteRefT: Clone, F: FeeRule, E>(
+    params: &P,
+    fee_rule: &F,
+    target_height: BlockHeight,
+    transparent_inputs: &[impl transparent::InputView],
+    transparent_outputs: &[impl transparent::OutputView],
+    sapling: &impl sapling_fees::BundleView<NoteRefT>,
+    #[cfg(feature = "orchard")] orchard: &impl orchard_fees::BundleView<NoteRefT>,
+    dust_output_policy: &DustOutputPolicy,
+    default_dust_threshold: NonNegativeAmount,
+    change_memo: Option<MemoBytes>,
+    net_flows: &NetFlows,
+    change_pool: ShieldedProtocol,
+) -> Result<TransactionBalance, ChangeError<E, NoteRefT>>
+where
+    E: From<F::Error> + From<BalanceError>,
+{
+    let overflow = || ChangeError::StrategyError(E::from(BalanceError::Overflow));
+    let underflow = || ChangeError::StrategyError(E::from(BalanceError::Underflow));
+
+    let (change_pool, sapling_change, _orchard_change) = change_output_counts(change_pool);
+
+    // Computes the fee for the transaction, given the number of change outputs that will be
+    // added to each shielded pool.
+    let fee_for_change_outputs = |sapling_change: usize,
+                                  _orchard_change: usize|
+     -> Result<NonNegativeAmount, ChangeError<E, NoteRefT>> {
+        let sapling_input_count = sapling
+            .bundle_type()
+            .num_spends(sapling.inputs().len())
+            .map_err(ChangeError::BundleError)?;
+        let sapling_output_count = sapling
+            .bundle_type()
+            .num_outputs(
+                sapling.inputs().len(),
+                sapling.outputs().len() + sapling_change,
+            )
+            .map_err(ChangeError::BundleError)?;
+
+        #[cfg(feature = "orchard")]
+        let orchard_action_count = orchard
+            .bundle_type()
+            .num_actions(
+                orchard.inputs().len(),
+                orchard.outputs().len() + _orchard_change,
+            )
+            .map_err(ChangeError::BundleError)?;
+        #[cfg(not(feature = "orchard"))]
+        let orchard_action_count = 0;
+
+        fee_rule
+            .fee_required(
+                params,
+                target_height,
+                transparent_inputs,
+                transparent_outputs,
+                sapling_input_count,
+                sapling_output_count,
+                orchard_action_count,
+            )
+            .map_err(|fee_error| ChangeError::StrategyError(E::from(fee_error)))
+    };
+
+    let fee_amount = fee_for_change_outputs(sapling_change, _orchard_change)?;
 
     let total_in =
         (net_flows.t_in + net_flows.sapling_in + net_flows.orchard_in).ok_or_else(overflow)?;
-    let total_out = (net_flows.t_out + net_flows.sapling_out + net_flows.orchard_out + fee_amount)
-        .ok_or_else(overflow)?;
+    let total_payments =
+        (net_flows.t_out + net_flows.sapling_out + net_flows.orchard_out).ok_or_else(overflow)?;
+    let total_out = (total_payments + fee_amount).ok_or_else(overflow)?;
 
-    let proposed_change = (total_in - total_out).ok_or(ChangeError::InsufficientFunds {
-        available: total_in,
-        required: total_out,
-    })?;
+    let proposed_change = match total_in - total_out {
+        Some(change) => change,
+        None => {
+            // The fee computed above accounts for a change output, which may increase the
+            // fee. If the inputs exactly cover the payments and the fee for a transaction
+            // with no change output, we can construct that transaction instead.
+            let fee_without_change = fee_for_change_outputs(0, 0)?;
+            return if (total_payments + fee_without_change) == Some(total_in) {
+                TransactionBalance::new(vec![], fee_without_change).map_err(|_| overflow())
+            } else {
+                Err(ChangeError::InsufficientFunds {
+                    available: total_in,
+                    required: total_out,
+                })
+            };
+        }
+    };
 
     if proposed_change.is_zero() {
         TransactionBalance::new(vec![], fee_amount).map_err(|_| overflow())
@@ -0,0 +1,182 @@
+//! Change strategies designed for use with a fee rule that scales the [ZIP 317] fee by a
+//! multiplier, for example in response to mempool congestion.
+//!
+//! [ZIP 317]: https//zips.z.cash/zip-0317
+
+use zcash_primitives::{
+    consensus::{self, BlockHeight},
+    memo::MemoBytes,
+    transaction::fees::{
+        dynamic::{FeeError as DynamicFeeError, FeeRule as DynamicFeeRule},
+        transparent,
+        zip317::{FeeError as Zip317FeeError, FeeRule as Zip317FeeRule},
+    },
+};
+
+use crate::ShieldedProtocol;
+
+use super::{
+    common::single_change_output_balance, sapling as sapling_fees, ChangeError, ChangeStrategy,
+    DustOutputPolicy, TransactionBalance,
+};
+
+#[cfg(feature = "orchard")]
+use super::orchard as orchard_fees;
+
+/// A change strategy that proposes change as a single output to the most current supported
+/// shielded pool and delegates fee calculation to the provided scaled ZIP 317 fee rule.
+///
+/// The fee recorded in the resulting [`TransactionBalance`] is the effective (scaled) fee.
+pub struct SingleOutputChangeStrategy {
+    fee_rule: DynamicFeeRule<Zip317FeeRule>,
+    change_memo: Option<MemoBytes>,
+    fallback_change_pool: ShieldedProtocol,
+}
+
+impl SingleOutputChangeStrategy {
+    /// Constructs a new [`SingleOutputChangeStrategy`] with the specified fee rule
+    /// and change memo.
+    ///
+    /// `fallback_change_pool` is used when more than one shielded pool is enabled via
+    /// feature flags, and the transaction has no shielded inputs.
+    pub fn new(
+        fee_rule: DynamicFeeRule<Zip317FeeRule>,
+        change_memo: Option<MemoBytes>,
+        fallback_change_pool: ShieldedProtocol,
+    ) -> Self {
+        Self {
+            fee_rule,
+            change_memo,
+            fallback_change_pool,
+        }
+    }
+}
+
+impl ChangeStrategy for SingleOutputChangeStrategy {
+    type FeeRule = DynamicFeeRule<Zip317FeeRule>;
+    type Error = DynamicFeeError<Zip317FeeError>;
+
+    fn fee_rule(&self) -> &Self::FeeRule {
+        &self.fee_rule
+    }
+
+    fn compute_balance<P: consensus::Parameters, NoteRefT: Clone>(
+        &self,
+        params: &P,
+        target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::InputView],
+        transparent_outputs: &[impl transparent::OutputView],
+        sapling: &impl sapling_fees::BundleView<NoteRefT>,
+        #[cfg(feature = "orchard")] orchard: &impl orchard_fees::BundleView<NoteRefT>,
+        dust_output_policy: &DustOutputPolicy,
+        change_pool_preference: &[ShieldedProtocol],
+    ) -> Result<TransactionBalance, ChangeError<Self::Error, NoteRefT>> {
+        single_change_output_balance(
+            params,
+            &self.fee_rule,
+            target_height,
+            transparent_inputs,
+            transparent_outputs,
+            sapling,
+            #[cfg(feature = "orchard")]
+            orchard,
+            dust_output_policy,
+            self.fee_rule.base().marginal_fee(),
+            self.change_memo.clone(),
+            self.fallback_change_pool,
+            change_pool_preference,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "orchard")]
+    use std::convert::Infallible;
+
+    use zcash_primitives::{
+        consensus::{Network, NetworkUpgrade, Parameters},
+        transaction::{
+            components::{amount::NonNegativeAmount, transparent::TxOut},
+            fees::{dynamic::FeeRule as DynamicFeeRule, zip317::FeeRule as Zip317FeeRule},
+        },
+    };
+
+    use super::SingleOutputChangeStrategy;
+    use crate::{
+        data_api::wallet::input_selection::SaplingPayment,
+        fees::{
+            tests::{TestSaplingInput, TestTransparentInput},
+            ChangeStrategy, ChangeValue, DustOutputPolicy,
+        },
+        ShieldedProtocol,
+    };
+
+    fn balance_with_multiplier(multiplier_bps: u16) -> (Vec<ChangeValue>, NonNegativeAmount) {
+        let change_strategy = SingleOutputChangeStrategy::new(
+            DynamicFeeRule::new(Zip317FeeRule::standard(), multiplier_bps),
+            None,
+            ShieldedProtocol::Sapling,
+        );
+
+        // spend a single Sapling note that is sufficient to pay the fee
+        let balance = change_strategy
+            .compute_balance(
+                &Network::TestNetwork,
+                Network::TestNetwork
+                    .activation_height(NetworkUpgrade::Nu5)
+                    .unwrap(),
+                &Vec::<TestTransparentInput>::new(),
+                &Vec::<TxOut>::new(),
+                &(
+                    sapling::builder::BundleType::DEFAULT,
+                    &[TestSaplingInput {
+                        note_id: 0,
+                        value: NonNegativeAmount::const_from_u64(60000),
+                    }][..],
+                    &[SaplingPayment::new(NonNegativeAmount::const_from_u64(
+                        40000,
+                    ))][..],
+                ),
+                #[cfg(feature = "orchard")]
+                &(
+                    orchard::builder::BundleType::DEFAULT,
+                    &Vec::<Infallible>::new()[..],
+                    &Vec::<Infallible>::new()[..],
+                ),
+                &DustOutputPolicy::default(),
+                &[],
+            )
+            .unwrap();
+
+        (balance.proposed_change().to_vec(), balance.fee_required())
+    }
+
+    #[test]
+    fn change_with_scaled_fee() {
+        assert_eq!(
+            balance_with_multiplier(15_000),
+            (
+                vec![ChangeValue::sapling(
+                    NonNegativeAmount::const_from_u64(5000),
+                    None
+                )],
+                NonNegativeAmount::const_from_u64(15000)
+            )
+        );
+    }
+
+    #[test]
+    fn scaled_fee_respects_zip317_floor() {
+        assert_eq!(
+            balance_with_multiplier(5_000),
+            (
+                vec![ChangeValue::sapling(
+                    NonNegativeAmount::const_from_u64(10000),
+                    None
+                )],
+                NonNegativeAmount::const_from_u64(10000)
+            )
+        );
+    }
+}
@@ -63,6 +63,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
         sapling: &impl sapling_fees::BundleView<NoteRefT>,
         #[cfg(feature = "orchard")] orchard: &impl orchard_fees::BundleView<NoteRefT>,
         dust_output_policy: &DustOutputPolicy,
+        change_pool_preference: &[ShieldedProtocol],
     ) -> Result<TransactionBalance, ChangeError<Self::Error, NoteRefT>> {
         single_change_output_balance(
             params,
@@ -77,6 +78,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
             self.fee_rule().fixed_fee(),
             self.change_memo.clone(),
             self.fallback_change_pool,
+            change_pool_preference,
         )
     }
 }
@@ -99,7 +101,7 @@ mod tests {
         data_api::wallet::input_selection::SaplingPayment,
         fees::{
             tests::{TestSaplingInput, TestTransparentInput},
-            ChangeError, ChangeStrategy, ChangeValue, DustOutputPolicy,
+            ChangeError, ChangeStrategy, ChangeValue, DustAction, DustOutputPolicy,
         },
         ShieldedProtocol,
     };
@@ -136,6 +138,7 @@ mod tests {
                 &[] as &[Infallible],
             ),
             &DustOutputPolicy::default(),
+            &[],
         );
 
         assert_matches!(
@@ -184,7 +187,8 @@ mod tests {
                 &[] as &[Infallible],
                 &[] as &[Infallible],
             ),
-            &DustOutputPolicy::default(),
+            &DustOutputPolicy::new(DustAction::Reject, None),
+            &[],
         );
 
         assert_matches!(
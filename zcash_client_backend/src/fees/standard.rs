@@ -68,6 +68,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
         sapling: &impl sapling_fees::BundleView<NoteRefT>,
         #[cfg(feature = "orchard")] orchard: &impl orchard_fees::BundleView<NoteRefT>,
         dust_output_policy: &DustOutputPolicy,
+        change_pool_preference: &[ShieldedProtocol],
     ) -> Result<TransactionBalance, ChangeError<Self::Error, NoteRefT>> {
         #[allow(deprecated)]
         match self.fee_rule() {
@@ -85,6 +86,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
                 #[cfg(feature = "orchard")]
                 orchard,
                 dust_output_policy,
+                change_pool_preference,
             )
             .map_err(|e| e.map(Zip317FeeError::Balance)),
             StandardFeeRule::Zip313 => fixed::SingleOutputChangeStrategy::new(
@@ -101,6 +103,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
                 #[cfg(feature = "orchard")]
                 orchard,
                 dust_output_policy,
+                change_pool_preference,
             )
             .map_err(|e| e.map(Zip317FeeError::Balance)),
             StandardFeeRule::Zip317 => zip317::SingleOutputChangeStrategy::new(
@@ -117,6 +120,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
                 #[cfg(feature = "orchard")]
                 orchard,
                 dust_output_policy,
+                change_pool_preference,
             ),
         }
     }
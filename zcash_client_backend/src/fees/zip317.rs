@@ -67,6 +67,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
         sapling: &impl sapling_fees::BundleView<NoteRefT>,
         #[cfg(feature = "orchard")] orchard: &impl orchard_fees::BundleView<NoteRefT>,
         dust_output_policy: &DustOutputPolicy,
+        change_pool_preference: &[ShieldedProtocol],
     ) -> Result<TransactionBalance, ChangeError<Self::Error, NoteRefT>> {
         let mut transparent_dust: Vec<_> = transparent_inputs
             .iter()
@@ -129,6 +130,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
                 single_change_output_policy::<NoteRefT, Self::FeeRule, Self::Error>(
                     &net_flows,
                     self.fallback_change_pool,
+                    change_pool_preference,
                 )?;
 
             let s_non_dust = sapling.inputs().len() - sapling_dust.len();
@@ -209,6 +211,7 @@ impl ChangeStrategy for SingleOutputChangeStrategy {
             self.fee_rule.marginal_fee(),
             self.change_memo.clone(),
             self.fallback_change_pool,
+            change_pool_preference,
         )
     }
 }
@@ -273,6 +276,7 @@ mod tests {
                 &Vec::<Infallible>::new()[..],
             ),
             &DustOutputPolicy::default(),
+            &[],
         );
 
         assert_matches!(
@@ -283,6 +287,153 @@ mod tests {
         );
     }
 
+    #[test]
+    fn dust_change_added_to_fee() {
+        let change_strategy = SingleOutputChangeStrategy::new(
+            Zip317FeeRule::standard(),
+            None,
+            ShieldedProtocol::Sapling,
+        );
+
+        // spend a single Sapling note that leaves exactly 1 zatoshi of change
+        let result = change_strategy.compute_balance(
+            &Network::TestNetwork,
+            Network::TestNetwork
+                .activation_height(NetworkUpgrade::Nu5)
+                .unwrap(),
+            &Vec::<TestTransparentInput>::new(),
+            &Vec::<TxOut>::new(),
+            &(
+                sapling::builder::BundleType::DEFAULT,
+                &[TestSaplingInput {
+                    note_id: 0,
+                    value: NonNegativeAmount::const_from_u64(50001),
+                }][..],
+                &[SaplingPayment::new(NonNegativeAmount::const_from_u64(
+                    40000,
+                ))][..],
+            ),
+            #[cfg(feature = "orchard")]
+            &(
+                orchard::builder::BundleType::DEFAULT,
+                &Vec::<Infallible>::new()[..],
+                &Vec::<Infallible>::new()[..],
+            ),
+            &DustOutputPolicy::default(),
+            &[],
+        );
+
+        // Under the default policy, the dust change is added to the fee.
+        assert_matches!(
+            result,
+            Ok(balance) if
+                balance.proposed_change().is_empty() &&
+                balance.fee_required() == NonNegativeAmount::const_from_u64(10001)
+        );
+    }
+
+    #[test]
+    fn no_change_for_exact_inputs() {
+        let change_strategy = SingleOutputChangeStrategy::new(
+            Zip317FeeRule::standard(),
+            None,
+            ShieldedProtocol::Sapling,
+        );
+
+        // spend a single Sapling note that exactly covers the payment and the fee
+        let result = change_strategy.compute_balance(
+            &Network::TestNetwork,
+            Network::TestNetwork
+                .activation_height(NetworkUpgrade::Nu5)
+                .unwrap(),
+            &Vec::<TestTransparentInput>::new(),
+            &Vec::<TxOut>::new(),
+            &(
+                sapling::builder::BundleType::DEFAULT,
+                &[TestSaplingInput {
+                    note_id: 0,
+                    value: NonNegativeAmount::const_from_u64(50000),
+                }][..],
+                &[SaplingPayment::new(NonNegativeAmount::const_from_u64(
+                    40000,
+                ))][..],
+            ),
+            #[cfg(feature = "orchard")]
+            &(
+                orchard::builder::BundleType::DEFAULT,
+                &Vec::<Infallible>::new()[..],
+                &Vec::<Infallible>::new()[..],
+            ),
+            &DustOutputPolicy::default(),
+            &[],
+        );
+
+        assert_matches!(
+            result,
+            Ok(balance) if
+                balance.proposed_change().is_empty() &&
+                balance.fee_required() == NonNegativeAmount::const_from_u64(10000)
+        );
+    }
+
+    #[test]
+    fn no_change_when_inputs_cover_fee_without_change() {
+        let change_strategy = SingleOutputChangeStrategy::new(
+            Zip317FeeRule::standard(),
+            None,
+            ShieldedProtocol::Sapling,
+        );
+
+        // Spend three Sapling notes that exactly cover three payments and the fee for a
+        // transaction with three logical actions. Adding a change output would require a
+        // fourth logical action, and so a higher fee than the inputs can cover.
+        let result = change_strategy.compute_balance(
+            &Network::TestNetwork,
+            Network::TestNetwork
+                .activation_height(NetworkUpgrade::Nu5)
+                .unwrap(),
+            &Vec::<TestTransparentInput>::new(),
+            &Vec::<TxOut>::new(),
+            &(
+                sapling::builder::BundleType::DEFAULT,
+                &[
+                    TestSaplingInput {
+                        note_id: 0,
+                        value: NonNegativeAmount::const_from_u64(20000),
+                    },
+                    TestSaplingInput {
+                        note_id: 1,
+                        value: NonNegativeAmount::const_from_u64(20000),
+                    },
+                    TestSaplingInput {
+                        note_id: 2,
+                        value: NonNegativeAmount::const_from_u64(20000),
+                    },
+                ][..],
+                &[
+                    SaplingPayment::new(NonNegativeAmount::const_from_u64(15000)),
+                    SaplingPayment::new(NonNegativeAmount::const_from_u64(15000)),
+                    SaplingPayment::new(NonNegativeAmount::const_from_u64(15000)),
+                ][..],
+            ),
+            #[cfg(feature = "orchard")]
+            &(
+                orchard::builder::BundleType::DEFAULT,
+                &Vec::<Infallible>::new()[..],
+                &Vec::<Infallible>::new()[..],
+            ),
+            &DustOutputPolicy::default(),
+            &[],
+        );
+
+        assert_matches!(
+            result,
+            Ok(balance) if
+                balance.proposed_change().is_empty() &&
+                balance.fee_required() == NonNegativeAmount::const_from_u64(15000)
+        );
+    }
+
     #[test]
     #[cfg(feature = "orchard")]
     fn cross_pool_change_without_dust() {
@@ -316,6 +467,7 @@ mod tests {
                 ))][..],
             ),
             &DustOutputPolicy::default(),
+            &[],
         );
 
         assert_matches!(
@@ -360,6 +512,7 @@ mod tests {
                 &Vec::<Infallible>::new()[..],
             ),
             &DustOutputPolicy::default(),
+            &[],
         );
 
         assert_matches!(
@@ -408,6 +561,7 @@ mod tests {
                 &Vec::<Infallible>::new()[..],
             ),
             &DustOutputPolicy::default(),
+            &[],
         );
 
         assert_matches!(
@@ -460,6 +614,7 @@ mod tests {
                 &Vec::<Infallible>::new()[..],
             ),
             &DustOutputPolicy::default(),
+            &[],
         );
 
         // We will get an error here, because the dust input now isn't free to add
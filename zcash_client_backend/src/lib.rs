@@ -80,7 +80,10 @@ pub mod sync;
 #[cfg(feature = "unstable-serialization")]
 pub mod serialization;
 
-pub use decrypt::{decrypt_transaction, DecryptedOutput, TransferType};
+pub use decrypt::{
+    decrypt_transaction, decrypt_transaction_parallel, locate_decrypted_notes, DecryptedOutput,
+    TransferType,
+};
 pub use zcash_protocol::{PoolType, ShieldedProtocol};
 
 #[cfg(test)]
@@ -5,19 +5,35 @@ use std::{
     fmt::{self, Debug, Display},
 };
 
+use incrementalmerkletree::MerklePath;
 use nonempty::NonEmpty;
+use zcash_address::ZcashAddress;
 use zcash_primitives::{
     consensus::BlockHeight,
-    transaction::{components::amount::NonNegativeAmount, TxId},
+    transaction::{
+        components::{amount::NonNegativeAmount, transparent::OutPoint},
+        fees::zip317::{P2PKH_STANDARD_INPUT_SIZE, P2PKH_STANDARD_OUTPUT_SIZE},
+        TxId,
+    },
 };
 
 use crate::{
-    fees::TransactionBalance,
-    wallet::{Note, ReceivedNote, WalletTransparentOutput},
+    data_api::WalletRead,
+    fees::{ChangeValue, DescribeFeeRule, FeeRuleParams, TransactionBalance},
+    wallet::{Note, NoteId, ReceivedNote, WalletTransparentOutput},
     zip321::TransactionRequest,
     PoolType, ShieldedProtocol,
 };
 
+pub(crate) type SaplingMerklePath =
+    MerklePath<sapling::Node, { sapling::NOTE_COMMITMENT_TREE_DEPTH }>;
+#[cfg(feature = "orchard")]
+pub(crate) type OrchardMerklePath =
+    MerklePath<orchard::tree::MerkleHashOrchard, { orchard::NOTE_COMMITMENT_TREE_DEPTH as u8 }>;
+
+/// The maximum size, in bytes, of a transaction that `zcashd` nodes will relay.
+pub const MAX_STANDARD_TX_SIZE: usize = 100_000;
+
 /// Errors that can occur in construction of a [`Step`].
 #[derive(Debug, Clone)]
 pub enum ProposalError {
@@ -47,6 +63,19 @@ pub enum ProposalError {
     /// There was a mismatch between the payments in the proposal's transaction request
     /// and the payment pool selection values.
     PaymentPoolsMismatch,
+    /// The transaction that would be created by a step of the proposal is estimated to exceed the
+    /// maximum permitted transaction size.
+    SizeLimitExceeded {
+        step_index: usize,
+        estimated_size: usize,
+        max_size: usize,
+    },
+    /// The proposal attempts to spend a coinbase output that will not yet have matured at the
+    /// proposal's minimum target height.
+    CoinbaseImmature(TxId, u32),
+    /// The proposal attempts to spend a coinbase output in a transaction that has transparent
+    /// outputs. Coinbase outputs may only be spent to shielded outputs.
+    CoinbaseSpentToTransparent,
 }
 
 impl Display for ProposalError {
@@ -90,12 +119,52 @@ impl Display for ProposalError {
                 f,
                 "The chosen payment pools did not match the payments of the transaction request."
             ),
+            ProposalError::SizeLimitExceeded {
+                step_index,
+                estimated_size,
+                max_size,
+            } => write!(
+                f,
+                "The transaction for step {} of the proposal is estimated to be {} bytes, which exceeds the limit of {} bytes by {} bytes. Consider splitting the payment into multiple transactions.",
+                step_index,
+                estimated_size,
+                max_size,
+                estimated_size.saturating_sub(*max_size)
+            ),
+            ProposalError::CoinbaseImmature(txid, index) => write!(
+                f,
+                "The proposal attempts to spend coinbase output {}, {} before it has matured.",
+                txid, index
+            ),
+            ProposalError::CoinbaseSpentToTransparent => write!(
+                f,
+                "Coinbase outputs may only be spent in transactions having no transparent outputs."
+            ),
         }
     }
 }
 
 impl std::error::Error for ProposalError {}
 
+/// Checks that every coinbase output spent by the given step will have matured by the time a
+/// transaction for the step can be mined at `min_target_height`.
+fn check_coinbase_maturity<NoteRef>(
+    step: &Step<NoteRef>,
+    min_target_height: BlockHeight,
+) -> Result<(), ProposalError> {
+    match step
+        .transparent_inputs()
+        .iter()
+        .find(|t_out| !t_out.is_mature_at(min_target_height))
+    {
+        Some(t_out) => Err(ProposalError::CoinbaseImmature(
+            TxId::from_bytes(*t_out.outpoint().hash()),
+            t_out.outpoint().n(),
+        )),
+        None => Ok(()),
+    }
+}
+
 /// The Sapling inputs to a proposed transaction.
 #[derive(Clone, PartialEq, Eq)]
 pub struct ShieldedInputs<NoteRef> {
@@ -184,6 +253,7 @@ impl<FeeRuleT, NoteRef> Proposal<FeeRuleT, NoteRef> {
                 }
             }
 
+            check_coinbase_maturity(step, min_target_height)?;
             for t_out in step.transparent_inputs() {
                 let key = (
                     PoolType::Transparent,
@@ -245,19 +315,22 @@ impl<FeeRuleT, NoteRef> Proposal<FeeRuleT, NoteRef> {
         min_target_height: BlockHeight,
         is_shielding: bool,
     ) -> Result<Self, ProposalError> {
+        let step = Step::from_parts(
+            &[],
+            transaction_request,
+            payment_pools,
+            transparent_inputs,
+            shielded_inputs,
+            vec![],
+            balance,
+            is_shielding,
+        )?;
+        check_coinbase_maturity(&step, min_target_height)?;
+
         Ok(Self {
             fee_rule,
             min_target_height,
-            steps: NonEmpty::singleton(Step::from_parts(
-                &[],
-                transaction_request,
-                payment_pools,
-                transparent_inputs,
-                shielded_inputs,
-                vec![],
-                balance,
-                is_shielding,
-            )?),
+            steps: NonEmpty::singleton(step),
         })
     }
 
@@ -266,6 +339,18 @@ impl<FeeRuleT, NoteRef> Proposal<FeeRuleT, NoteRef> {
         &self.fee_rule
     }
 
+    /// Returns a description of the fee rule to be used by the transaction builder, including
+    /// the values of its parameters.
+    ///
+    /// This may be used to display the fee rule to the user when confirming the proposal, or
+    /// by an offline signer to reconstruct the fee rule used to create the proposal.
+    pub fn fee_rule_description(&self) -> FeeRuleParams
+    where
+        FeeRuleT: DescribeFeeRule,
+    {
+        self.fee_rule.fee_rule_params()
+    }
+
     /// Returns the target height for which the proposal was prepared.
     ///
     /// The chain must contain at least this many blocks in order for the proposal to
@@ -279,6 +364,178 @@ impl<FeeRuleT, NoteRef> Proposal<FeeRuleT, NoteRef> {
     pub fn steps(&self) -> &NonEmpty<Step<NoteRef>> {
         &self.steps
     }
+
+    /// Returns an estimate of the total serialized size, in bytes, of the transactions that
+    /// will be created as a result of this proposal.
+    ///
+    /// This is the sum of [`Step::estimated_size`] over all steps of the proposal.
+    pub fn estimated_size(&self) -> usize {
+        self.steps.iter().map(|step| step.estimated_size()).sum()
+    }
+
+    /// Checks that none of the transactions that will be created as a result of this proposal
+    /// is estimated to exceed `max_size` bytes.
+    ///
+    /// [`MAX_STANDARD_TX_SIZE`] is the limit above which transactions will not be relayed by
+    /// `zcashd` nodes. Returns [`ProposalError::SizeLimitExceeded`] for the first step of the
+    /// proposal whose [`Step::estimated_size`] exceeds the limit; in this case, the payments
+    /// should be split across multiple proposals, or fewer inputs should be spent.
+    pub fn validate_size(&self, max_size: usize) -> Result<(), ProposalError> {
+        self.steps
+            .iter()
+            .enumerate()
+            .try_for_each(|(step_index, step)| {
+                let estimated_size = step.estimated_size();
+                if estimated_size > max_size {
+                    Err(ProposalError::SizeLimitExceeded {
+                        step_index,
+                        estimated_size,
+                        max_size,
+                    })
+                } else {
+                    Ok(())
+                }
+            })
+    }
+
+    /// Returns a description of the transactions that are expected to be created as a result
+    /// of this proposal.
+    ///
+    /// The summary lists the inputs, outputs, change, fee, and anchor height of each step, so
+    /// that a transaction built from the proposal can be checked against it before it is
+    /// signed or broadcast.
+    pub fn describe(&self) -> ProposalSummary {
+        let steps =
+            self.steps.iter().map(|step| {
+                let mut inputs = step
+                    .transparent_inputs
+                    .iter()
+                    .map(|utxo| InputSummary::Transparent {
+                        outpoint: utxo.outpoint().clone(),
+                        value: utxo.txout().value,
+                    })
+                    .collect::<Vec<_>>();
+                inputs.extend(
+                    step.shielded_inputs
+                        .iter()
+                        .flat_map(|s_in| s_in.notes().iter())
+                        .map(|note| InputSummary::Shielded {
+                            note_id: NoteId::new(
+                                *note.txid(),
+                                note.note().protocol(),
+                                note.output_index(),
+                            ),
+                            value: note.note().value(),
+                        }),
+                );
+                inputs.extend(step.prior_step_inputs.iter().map(|output| {
+                    InputSummary::PriorStep {
+                        output: *output,
+                        value: self.prior_step_output_value(output),
+                    }
+                }));
+
+                let outputs = step
+                    .payment_pools
+                    .iter()
+                    .map(|(payment_index, pool)| {
+                        let payment = step
+                            .transaction_request
+                            .payments()
+                            .get(payment_index)
+                            .expect("Payment pools correspond to payments of the request.");
+                        OutputSummary {
+                            payment_index: *payment_index,
+                            recipient: payment.recipient_address().clone(),
+                            value: payment.amount(),
+                            pool: *pool,
+                        }
+                    })
+                    .collect();
+
+                StepSummary {
+                    anchor_height: step.shielded_inputs.as_ref().map(|s_in| s_in.anchor_height),
+                    inputs,
+                    outputs,
+                    change: step.balance.proposed_change().to_vec(),
+                    fee: step.balance.fee_required(),
+                }
+            });
+
+        ProposalSummary {
+            min_target_height: self.min_target_height,
+            steps: NonEmpty::from_vec(steps.collect()).expect("Proposal steps are nonempty."),
+        }
+    }
+
+    /// Returns information about the anchor that will be used to spend the shielded inputs of
+    /// this proposal, or `Ok(None)` if the proposal does not spend any shielded notes.
+    ///
+    /// The age of the anchor is measured relative to the chain tip at the time the proposal
+    /// was created, that is, the block preceding [`Self::min_target_height`]. The number of
+    /// shielded outputs created after the anchor is computed from the note commitment tree
+    /// sizes recorded by the wallet for the anchor and tip blocks; it is `None` if either of
+    /// these blocks has not been scanned.
+    ///
+    /// A more recent anchor reduces the number of notes that the spent notes may be confused
+    /// with, and so these values may be used to indicate the tradeoff between confirmation
+    /// depth and privacy to the user.
+    ///
+    /// If the steps of the proposal use different anchors, the information returned is for
+    /// the anchor of the first step that spends shielded notes.
+    pub fn anchor_info<DbT: WalletRead>(
+        &self,
+        wallet_db: &DbT,
+    ) -> Result<Option<AnchorInfo>, DbT::Error> {
+        let anchor_height = match self
+            .steps
+            .iter()
+            .find_map(|step| step.shielded_inputs.as_ref())
+        {
+            Some(shielded_inputs) => shielded_inputs.anchor_height,
+            None => return Ok(None),
+        };
+
+        let tip_height = self.min_target_height - 1;
+        let total_size = |height| {
+            wallet_db.get_tree_sizes(height).map(|sizes| {
+                sizes.map(|(sapling_size, orchard_size)| {
+                    u64::from(sapling_size) + u64::from(orchard_size)
+                })
+            })
+        };
+        let outputs_after = total_size(anchor_height)?
+            .zip(total_size(tip_height)?)
+            .map(|(anchor_size, tip_size)| tip_size.saturating_sub(anchor_size));
+
+        Ok(Some(AnchorInfo {
+            height: anchor_height,
+            age_blocks: u32::from(tip_height).saturating_sub(u32::from(anchor_height)),
+            outputs_after,
+        }))
+    }
+
+    /// Returns the value of the given output of a step of this proposal.
+    fn prior_step_output_value(&self, output: &StepOutput) -> NonNegativeAmount {
+        let step = self
+            .steps
+            .get(output.step_index())
+            .expect("Prior step references are validated on proposal construction.");
+        match output.output_index() {
+            StepOutputIndex::Payment(i) => step
+                .transaction_request
+                .payments()
+                .get(&i)
+                .expect("Prior step references are validated on proposal construction.")
+                .amount(),
+            StepOutputIndex::Change(i) => step
+                .balance
+                .proposed_change()
+                .get(i)
+                .expect("Prior step references are validated on proposal construction.")
+                .value(),
+        }
+    }
 }
 
 impl<FeeRuleT: Debug, NoteRef> Debug for Proposal<FeeRuleT, NoteRef> {
@@ -326,6 +583,36 @@ impl StepOutput {
     }
 }
 
+/// The size of the fixed-length v5 transaction header fields: `header`, `nVersionGroupId`,
+/// `nConsensusBranchId`, `lock_time`, and `nExpiryHeight`.
+const V5_TX_HEADER_SIZE: usize = 20;
+
+/// The size of a serialized Sapling spend description, including its proof and spend
+/// authorization signature.
+const SAPLING_SPEND_SIZE: usize = 96 + 192 + 64;
+
+/// The size of a serialized Sapling output description, including its proof.
+const SAPLING_OUTPUT_SIZE: usize = 756 + 192;
+
+/// The size of a serialized Orchard action description, including its spend authorization
+/// signature and its share of the aggregated proof.
+const ORCHARD_ACTION_SIZE: usize = 820 + 64 + 2272;
+
+/// The size of the fixed-length portion of a nonempty Orchard bundle: `flagsOrchard`,
+/// `valueBalanceOrchard`, `anchorOrchard`, the length prefix and fixed part of `proofsOrchard`,
+/// and `bindingSigOrchard`.
+const ORCHARD_BUNDLE_OVERHEAD: usize = 1 + 8 + 32 + 3 + 2720 + 64;
+
+/// Returns the length of the `CompactSize` encoding of the given value.
+fn compact_size_len(n: usize) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x10000..=0xffffffff => 5,
+        _ => 9,
+    }
+}
+
 /// The inputs to be consumed and outputs to be produced in a proposed transaction.
 #[derive(Clone, PartialEq, Eq)]
 pub struct Step<NoteRef> {
@@ -383,6 +670,15 @@ impl<NoteRef> Step<NoteRef> {
             }
         }
 
+        // Coinbase outputs may only be spent in transactions having no transparent outputs.
+        if transparent_inputs.iter().any(|i| i.is_coinbase())
+            && payment_pools
+                .values()
+                .any(|pool| *pool == PoolType::Transparent)
+        {
+            return Err(ProposalError::CoinbaseSpentToTransparent);
+        }
+
         let transparent_input_total = transparent_inputs
             .iter()
             .map(|out| out.txout().value)
@@ -490,6 +786,93 @@ impl<NoteRef> Step<NoteRef> {
         self.is_shielding
     }
 
+    /// Returns an estimate of the serialized size, in bytes, of the v5 transaction that will be
+    /// created for this step.
+    ///
+    /// The estimate is computed from the number of inputs and outputs in each pool, after
+    /// the padding applied by the transaction builder's default bundle types. Transparent inputs
+    /// are assumed to be P2PKH inputs, and transparent outputs P2PKH outputs; the estimate
+    /// should agree with the length of the built transaction to within a few bytes.
+    pub fn estimated_size(&self) -> usize {
+        let mut sapling_spends = 0;
+        #[cfg(feature = "orchard")]
+        let mut orchard_spends = 0;
+        for note in self
+            .shielded_inputs
+            .iter()
+            .flat_map(|s_in| s_in.notes().iter())
+        {
+            match note.note() {
+                Note::Sapling(_) => sapling_spends += 1,
+                #[cfg(feature = "orchard")]
+                Note::Orchard(_) => orchard_spends += 1,
+            }
+        }
+
+        let mut transparent_outputs = 0;
+        let mut sapling_outputs = 0;
+        #[cfg(feature = "orchard")]
+        let mut orchard_outputs = 0;
+        for pool in self.payment_pools.values() {
+            match pool {
+                PoolType::Transparent => transparent_outputs += 1,
+                PoolType::Shielded(ShieldedProtocol::Sapling) => sapling_outputs += 1,
+                #[cfg(feature = "orchard")]
+                PoolType::Shielded(ShieldedProtocol::Orchard) => orchard_outputs += 1,
+                #[cfg(not(feature = "orchard"))]
+                PoolType::Shielded(ShieldedProtocol::Orchard) => (),
+            }
+        }
+        for change in self.balance.proposed_change() {
+            match change.output_pool() {
+                ShieldedProtocol::Sapling => sapling_outputs += 1,
+                #[cfg(feature = "orchard")]
+                ShieldedProtocol::Orchard => orchard_outputs += 1,
+                #[cfg(not(feature = "orchard"))]
+                ShieldedProtocol::Orchard => (),
+            }
+        }
+
+        // At present, only transparent outputs of prior steps may be spent by later steps.
+        let transparent_inputs = self.transparent_inputs.len() + self.prior_step_inputs.len();
+        let transparent_size = compact_size_len(transparent_inputs)
+            + transparent_inputs * P2PKH_STANDARD_INPUT_SIZE
+            + compact_size_len(transparent_outputs)
+            + transparent_outputs * P2PKH_STANDARD_OUTPUT_SIZE;
+
+        let sapling_bundle_type = sapling::builder::BundleType::DEFAULT;
+        let sapling_spends = sapling_bundle_type
+            .num_spends(sapling_spends)
+            .expect("The default Sapling bundle type does not require a bundle.");
+        let sapling_outputs = sapling_bundle_type
+            .num_outputs(sapling_spends, sapling_outputs)
+            .expect("The default Sapling bundle type does not require a bundle.");
+        let sapling_size = compact_size_len(sapling_spends)
+            + sapling_spends * SAPLING_SPEND_SIZE
+            + compact_size_len(sapling_outputs)
+            + sapling_outputs * SAPLING_OUTPUT_SIZE
+            // valueBalanceSapling and bindingSigSapling
+            + if sapling_spends + sapling_outputs > 0 { 8 + 64 } else { 0 }
+            // anchorSapling
+            + if sapling_spends > 0 { 32 } else { 0 };
+
+        #[cfg(feature = "orchard")]
+        let orchard_actions = orchard::builder::BundleType::DEFAULT
+            .num_actions(orchard_spends, orchard_outputs)
+            .expect("The default Orchard bundle type does not require a bundle.");
+        #[cfg(not(feature = "orchard"))]
+        let orchard_actions = 0;
+        let orchard_size = compact_size_len(orchard_actions)
+            + orchard_actions * ORCHARD_ACTION_SIZE
+            + if orchard_actions > 0 {
+                ORCHARD_BUNDLE_OVERHEAD
+            } else {
+                0
+            };
+
+        V5_TX_HEADER_SIZE + transparent_size + sapling_size + orchard_size
+    }
+
     /// Returns whether or not this proposal requires interaction with the specified pool
     pub fn involves(&self, pool_type: PoolType) -> bool {
         match pool_type {
@@ -563,3 +946,347 @@ impl<NoteRef> Debug for Step<NoteRef> {
             .finish_non_exhaustive()
     }
 }
+
+/// An input that is expected to be spent by a transaction, as described by
+/// [`Proposal::describe`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputSummary {
+    /// A transparent output belonging to the wallet.
+    Transparent {
+        outpoint: OutPoint,
+        value: NonNegativeAmount,
+    },
+    /// A shielded note belonging to the wallet.
+    Shielded {
+        note_id: NoteId,
+        value: NonNegativeAmount,
+    },
+    /// An output of an earlier step of the same proposal.
+    PriorStep {
+        output: StepOutput,
+        value: NonNegativeAmount,
+    },
+}
+
+impl InputSummary {
+    /// Returns the value of the input.
+    pub fn value(&self) -> NonNegativeAmount {
+        match self {
+            InputSummary::Transparent { value, .. }
+            | InputSummary::Shielded { value, .. }
+            | InputSummary::PriorStep { value, .. } => *value,
+        }
+    }
+}
+
+/// A payment output that is expected to be created by a transaction, as described by
+/// [`Proposal::describe`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputSummary {
+    payment_index: usize,
+    recipient: ZcashAddress,
+    value: NonNegativeAmount,
+    pool: PoolType,
+}
+
+impl OutputSummary {
+    /// Returns the index of the payment within the step's transaction request.
+    pub fn payment_index(&self) -> usize {
+        self.payment_index
+    }
+
+    /// Returns the address to which the payment will be sent.
+    pub fn recipient(&self) -> &ZcashAddress {
+        &self.recipient
+    }
+
+    /// Returns the value of the payment.
+    pub fn value(&self) -> NonNegativeAmount {
+        self.value
+    }
+
+    /// Returns the pool to which the payment will be sent.
+    pub fn pool(&self) -> PoolType {
+        self.pool
+    }
+}
+
+/// A description of the transaction that is expected to be created for a single step of a
+/// proposal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepSummary {
+    anchor_height: Option<BlockHeight>,
+    inputs: Vec<InputSummary>,
+    outputs: Vec<OutputSummary>,
+    change: Vec<ChangeValue>,
+    fee: NonNegativeAmount,
+}
+
+impl StepSummary {
+    /// Returns the anchor height for the step's shielded inputs, or `None` if the step does
+    /// not spend any shielded notes.
+    pub fn anchor_height(&self) -> Option<BlockHeight> {
+        self.anchor_height
+    }
+
+    /// Returns the inputs to be spent: transparent outputs first, then shielded notes, then
+    /// outputs of prior steps.
+    pub fn inputs(&self) -> &[InputSummary] {
+        &self.inputs
+    }
+
+    /// Returns the payment outputs to be created, in order of payment index.
+    pub fn outputs(&self) -> &[OutputSummary] {
+        &self.outputs
+    }
+
+    /// Returns the change outputs to be created.
+    pub fn change(&self) -> &[ChangeValue] {
+        &self.change
+    }
+
+    /// Returns the fee to be paid.
+    pub fn fee(&self) -> NonNegativeAmount {
+        self.fee
+    }
+}
+
+/// A description of the transactions that are expected to be created as a result of a
+/// proposal, as returned by [`Proposal::describe`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProposalSummary {
+    min_target_height: BlockHeight,
+    steps: NonEmpty<StepSummary>,
+}
+
+impl ProposalSummary {
+    /// Returns the target height for which the proposal was prepared.
+    pub fn min_target_height(&self) -> BlockHeight {
+        self.min_target_height
+    }
+
+    /// Returns a description of each step of the proposal, in order.
+    pub fn steps(&self) -> &NonEmpty<StepSummary> {
+        &self.steps
+    }
+}
+
+/// Information about the anchor used to spend the shielded inputs of a proposal, as returned
+/// by [`Proposal::anchor_info`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnchorInfo {
+    height: BlockHeight,
+    age_blocks: u32,
+    outputs_after: Option<u64>,
+}
+
+impl AnchorInfo {
+    /// Returns the height of the anchor.
+    pub fn height(&self) -> BlockHeight {
+        self.height
+    }
+
+    /// Returns the number of blocks between the anchor and the chain tip at the time the
+    /// proposal was created.
+    pub fn age_blocks(&self) -> u32 {
+        self.age_blocks
+    }
+
+    /// Returns the number of Sapling and Orchard outputs created after the anchor and up to
+    /// the chain tip at the time the proposal was created, if known.
+    pub fn outputs_after(&self) -> Option<u64> {
+        self.outputs_after
+    }
+}
+
+/// The note commitment tree anchors and witnesses required to spend the shielded inputs of a
+/// single step of a [`SignablePackage`].
+#[derive(Clone, Debug)]
+pub struct StepWitnesses {
+    sapling_anchor: Option<sapling::Anchor>,
+    sapling_witnesses: Vec<SaplingMerklePath>,
+    #[cfg(feature = "orchard")]
+    orchard_anchor: Option<orchard::Anchor>,
+    #[cfg(feature = "orchard")]
+    orchard_witnesses: Vec<OrchardMerklePath>,
+}
+
+impl StepWitnesses {
+    /// Constructs a [`StepWitnesses`] from its constituent parts.
+    pub(crate) fn from_parts(
+        sapling_anchor: Option<sapling::Anchor>,
+        sapling_witnesses: Vec<SaplingMerklePath>,
+        #[cfg(feature = "orchard")] orchard_anchor: Option<orchard::Anchor>,
+        #[cfg(feature = "orchard")] orchard_witnesses: Vec<OrchardMerklePath>,
+    ) -> Self {
+        Self {
+            sapling_anchor,
+            sapling_witnesses,
+            #[cfg(feature = "orchard")]
+            orchard_anchor,
+            #[cfg(feature = "orchard")]
+            orchard_witnesses,
+        }
+    }
+
+    /// Returns the Sapling anchor to be used in constructing the step's transaction, or `None`
+    /// if the transaction will not involve the Sapling pool.
+    pub fn sapling_anchor(&self) -> Option<sapling::Anchor> {
+        self.sapling_anchor
+    }
+
+    /// Returns the witnesses for the step's Sapling inputs, in the order in which those notes
+    /// appear in the step's [`ShieldedInputs`].
+    pub fn sapling_witnesses(&self) -> &[SaplingMerklePath] {
+        &self.sapling_witnesses
+    }
+
+    /// Returns the Orchard anchor to be used in constructing the step's transaction, or `None`
+    /// if the transaction will not involve the Orchard pool.
+    #[cfg(feature = "orchard")]
+    pub fn orchard_anchor(&self) -> Option<orchard::Anchor> {
+        self.orchard_anchor
+    }
+
+    /// Returns the witnesses for the step's Orchard inputs, in the order in which those notes
+    /// appear in the step's [`ShieldedInputs`].
+    #[cfg(feature = "orchard")]
+    pub fn orchard_witnesses(&self) -> &[OrchardMerklePath] {
+        &self.orchard_witnesses
+    }
+}
+
+/// A [`Proposal`] bundled with all of the wallet data required to construct its transactions.
+///
+/// In addition to the proposal itself, a signable package carries the plaintexts of the notes
+/// to be spent, along with the note commitment tree anchors and witnesses required to spend
+/// them. This allows an offline device that holds the spending key to prove and sign the
+/// proposed transactions without access to the wallet database.
+#[derive(Clone, Debug)]
+pub struct SignablePackage<FeeRuleT> {
+    proposal: Proposal<FeeRuleT, NoteId>,
+    step_witnesses: NonEmpty<StepWitnesses>,
+}
+
+impl<FeeRuleT> SignablePackage<FeeRuleT> {
+    /// Constructs a [`SignablePackage`] from its constituent parts.
+    ///
+    /// The caller must ensure that `step_witnesses` contains one element for each step of the
+    /// proposal, in the same order.
+    pub(crate) fn from_parts(
+        proposal: Proposal<FeeRuleT, NoteId>,
+        step_witnesses: NonEmpty<StepWitnesses>,
+    ) -> Self {
+        assert_eq!(proposal.steps().len(), step_witnesses.len());
+        Self {
+            proposal,
+            step_witnesses,
+        }
+    }
+
+    /// Returns the proposal for which transactions are to be constructed.
+    pub fn proposal(&self) -> &Proposal<FeeRuleT, NoteId> {
+        &self.proposal
+    }
+
+    /// Returns the anchors and witnesses for each step of the proposal, in step order.
+    pub fn step_witnesses(&self) -> &NonEmpty<StepWitnesses> {
+        &self.step_witnesses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use incrementalmerkletree::Position;
+    use nonempty::NonEmpty;
+    use sapling::{value::NoteValue, zip32::ExtendedSpendingKey, Rseed};
+    use zcash_primitives::{
+        consensus::{BlockHeight, Network},
+        transaction::{components::amount::NonNegativeAmount, fees::StandardFeeRule, TxId},
+        zip32::Scope,
+    };
+
+    use super::{InputSummary, Proposal, ShieldedInputs};
+    use crate::{
+        address::Address,
+        fees::{ChangeValue, TransactionBalance},
+        wallet::{Note, NoteId, ReceivedNote},
+        zip321::{Payment, TransactionRequest},
+        PoolType, ShieldedProtocol,
+    };
+
+    #[test]
+    fn describe_single_step() {
+        let (_, to) = ExtendedSpendingKey::master(&[0; 32]).default_address();
+        let recipient = Address::Sapling(to).to_zcash_address(&Network::TestNetwork);
+
+        let txid = TxId::from_bytes([1; 32]);
+        let input_value = NonNegativeAmount::const_from_u64(60000);
+        let note = ReceivedNote::from_parts(
+            0u32,
+            txid,
+            3,
+            Note::Sapling(sapling::Note::from_parts(
+                to,
+                NoteValue::from_raw(input_value.into_u64()),
+                Rseed::AfterZip212([0; 32]),
+            )),
+            Scope::External,
+            Position::from(7),
+        );
+
+        let sent_value = NonNegativeAmount::const_from_u64(10000);
+        let fee = NonNegativeAmount::const_from_u64(10000);
+        let balance = TransactionBalance::new(
+            vec![ChangeValue::sapling(
+                NonNegativeAmount::const_from_u64(40000),
+                None,
+            )],
+            fee,
+        )
+        .unwrap();
+
+        let anchor_height = BlockHeight::from_u32(100);
+        let proposal = Proposal::single_step(
+            TransactionRequest::new(vec![Payment::without_memo(recipient.clone(), sent_value)])
+                .unwrap(),
+            BTreeMap::from([(0, PoolType::Shielded(ShieldedProtocol::Sapling))]),
+            vec![],
+            Some(ShieldedInputs::from_parts(
+                anchor_height,
+                NonEmpty::singleton(note),
+            )),
+            balance.clone(),
+            StandardFeeRule::Zip317,
+            anchor_height + 10,
+            false,
+        )
+        .unwrap();
+
+        let summary = proposal.describe();
+        assert_eq!(summary.min_target_height(), anchor_height + 10);
+        assert_eq!(summary.steps().len(), 1);
+
+        let step = &summary.steps().head;
+        assert_eq!(step.anchor_height(), Some(anchor_height));
+        assert_eq!(
+            step.inputs(),
+            &[InputSummary::Shielded {
+                note_id: NoteId::new(txid, ShieldedProtocol::Sapling, 3),
+                value: input_value,
+            }]
+        );
+        assert_eq!(step.outputs().len(), 1);
+        assert_eq!(step.outputs()[0].payment_index(), 0);
+        assert_eq!(step.outputs()[0].recipient(), &recipient);
+        assert_eq!(step.outputs()[0].value(), sent_value);
+        assert_eq!(
+            step.outputs()[0].pool(),
+            PoolType::Shielded(ShieldedProtocol::Sapling)
+        );
+        assert_eq!(step.change(), balance.proposed_change());
+        assert_eq!(step.fee(), fee);
+    }
+}
@@ -1,10 +1,13 @@
 //! Generated code for handling light client protobuf structs.
 
-use incrementalmerkletree::frontier::CommitmentTree;
+use group::ff::PrimeField;
+use incrementalmerkletree::{frontier::CommitmentTree, MerklePath, Position};
 use nonempty::NonEmpty;
+use shardtree::error::ShardTreeError;
 use std::{
     array::TryFromSliceError,
     collections::BTreeMap,
+    convert::Infallible,
     fmt::{self, Display},
     io,
 };
@@ -13,25 +16,33 @@ use sapling::{self, note::ExtractedNoteCommitment, Node};
 use zcash_note_encryption::{EphemeralKeyBytes, COMPACT_NOTE_SIZE};
 use zcash_primitives::{
     block::{BlockHash, BlockHeader},
-    consensus::BlockHeight,
+    consensus::{self, BlockHeight},
     memo::{self, MemoBytes},
-    merkle_tree::read_commitment_tree,
+    merkle_tree::{read_commitment_tree, HashSer},
     transaction::{components::amount::NonNegativeAmount, fees::StandardFeeRule, TxId},
 };
 
+use zip32::DiversifierIndex;
+
 use crate::{
-    data_api::{chain::ChainState, InputSource},
+    address::Address,
+    data_api::{chain::ChainState, InputSource, WalletCommitmentTrees, WatchOnlyBundle},
     fees::{ChangeValue, TransactionBalance},
-    proposal::{Proposal, ProposalError, ShieldedInputs, Step, StepOutput, StepOutputIndex},
+    keys::UnifiedFullViewingKey,
+    proposal::{
+        Proposal, ProposalError, SaplingMerklePath, ShieldedInputs, SignablePackage, Step,
+        StepOutput, StepOutputIndex, StepWitnesses,
+    },
+    wallet::{Note, NoteId, ReceivedNote},
     zip321::{TransactionRequest, Zip321Error},
     PoolType, ShieldedProtocol,
 };
 
 #[cfg(feature = "transparent-inputs")]
-use zcash_primitives::transaction::components::OutPoint;
+use {crate::wallet::WalletTransparentOutput, zcash_primitives::transaction::components::OutPoint};
 
 #[cfg(feature = "orchard")]
-use orchard::tree::MerkleHashOrchard;
+use {crate::proposal::OrchardMerklePath, orchard::tree::MerkleHashOrchard};
 
 #[rustfmt::skip]
 #[allow(unknown_lints)]
@@ -48,6 +59,11 @@ pub mod proposal;
 #[allow(clippy::derive_partial_eq_without_eq)]
 pub mod service;
 
+#[rustfmt::skip]
+#[allow(unknown_lints)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+pub mod watch_only;
+
 impl compact_formats::CompactBlock {
     /// Returns the [`BlockHash`] for this block.
     ///
@@ -354,8 +370,14 @@ pub enum ProposalDecodingError<DbError> {
     BalanceInvalid,
     /// Failed to decode a ZIP-302-compliant memo from the provided memo bytes.
     MemoInvalid(memo::Error),
-    /// The serialization version returned by the protobuf was not recognized.
-    VersionInvalid(u32),
+    /// The serialization version of the encoded proposal is not supported by this library.
+    ///
+    /// This occurs when a proposal is serialized by a different version of this library than
+    /// the one that is decoding it, such as when an online wallet and an offline signing
+    /// device are running incompatible software. `found` is the version of the encoded
+    /// proposal, and `supported` is the latest serialization version that this library can
+    /// decode.
+    UnsupportedProposalVersion { found: u32, supported: u32 },
     /// The proposal did not correctly specify a standard fee rule.
     FeeRuleNotSpecified,
     /// The proposal violated balance or structural constraints.
@@ -366,6 +388,13 @@ pub enum ProposalDecodingError<DbError> {
     TransparentMemo,
     /// Change outputs to the specified pool are not supported.
     InvalidChangeRecipient(PoolType),
+    /// The data required to spend the input with the given txid, pool and index without access
+    /// to the wallet database was not present.
+    SpendDataMissing(TxId, PoolType, u32),
+    /// The data required to spend the input with the given txid, pool and index was invalid.
+    SpendDataInvalid(TxId, PoolType, u32),
+    /// The note commitment tree anchor for the given protocol was missing or invalid.
+    AnchorInvalid(ShieldedProtocol),
 }
 
 impl<E> From<Zip321Error> for ProposalDecodingError<E> {
@@ -404,9 +433,11 @@ impl<E: Display> Display for ProposalDecodingError<E> {
             ProposalDecodingError::MemoInvalid(err) => {
                 write!(f, "An error occurred decoding a proposed memo: {}", err)
             }
-            ProposalDecodingError::VersionInvalid(v) => {
-                write!(f, "Unrecognized proposal version {}", v)
-            }
+            ProposalDecodingError::UnsupportedProposalVersion { found, supported } => write!(
+                f,
+                "Unsupported proposal serialization version {}; the latest supported version is {}.",
+                found, supported
+            ),
             ProposalDecodingError::FeeRuleNotSpecified => {
                 write!(f, "Proposal did not specify a known fee rule.")
             }
@@ -424,6 +455,19 @@ impl<E: Display> Display for ProposalDecodingError<E> {
                 "Change outputs to the {} pool are not supported.",
                 pool_type
             ),
+            ProposalDecodingError::SpendDataMissing(txid, pool, idx) => write!(
+                f,
+                "No spend data was provided for the {} input with txid {}, index {}",
+                pool, txid, idx
+            ),
+            ProposalDecodingError::SpendDataInvalid(txid, pool, idx) => write!(
+                f,
+                "Invalid spend data for the {} input with txid {}, index {}",
+                pool, txid, idx
+            ),
+            ProposalDecodingError::AnchorInvalid(protocol) => {
+                write!(f, "The {:?} anchor was missing or invalid.", protocol)
+            }
         }
     }
 }
@@ -448,6 +492,32 @@ fn pool_type<T>(pool_id: i32) -> Result<PoolType, ProposalDecodingError<T>> {
     }
 }
 
+fn write_node<H: HashSer>(node: &H) -> Vec<u8> {
+    let mut bytes = vec![];
+    node.write(&mut bytes)
+        .expect("Writing to a Vec should never fail.");
+    bytes
+}
+
+fn read_node<H: HashSer>(bytes: &[u8]) -> Option<H> {
+    H::read(bytes).ok()
+}
+
+fn write_merkle_path<H: HashSer, const DEPTH: u8>(path: &MerklePath<H, DEPTH>) -> Vec<Vec<u8>> {
+    path.path_elems().iter().map(write_node).collect()
+}
+
+fn read_merkle_path<H: HashSer, const DEPTH: u8>(
+    position: Position,
+    path_elems: &[Vec<u8>],
+) -> Option<MerklePath<H, DEPTH>> {
+    let path_elems = path_elems
+        .iter()
+        .map(|bytes| read_node(bytes))
+        .collect::<Option<Vec<_>>>()?;
+    MerklePath::from_parts(path_elems, position).ok()
+}
+
 impl proposal::ReceivedOutput {
     pub fn parse_txid(&self) -> Result<TxId, TryFromSliceError> {
         Ok(TxId::from_bytes(self.txid[..].try_into()?))
@@ -507,6 +577,7 @@ impl proposal::Proposal {
                             value_pool: proposal::ValuePool::Transparent.into(),
                             index: utxo.outpoint().n(),
                             value: utxo.txout().value.into(),
+                            spend_data: None,
                         })),
                     })
                     .chain(step.shielded_inputs().iter().flat_map(|s_in| {
@@ -517,6 +588,7 @@ impl proposal::Proposal {
                                     .into(),
                                 index: rec_note.output_index().into(),
                                 value: rec_note.note().value().into(),
+                                spend_data: None,
                             })),
                         })
                     }))
@@ -584,6 +656,8 @@ impl proposal::Proposal {
                     inputs,
                     balance,
                     is_shielding: step.is_shielding(),
+                    sapling_anchor: vec![],
+                    orchard_anchor: vec![],
                 }
             })
             .collect();
@@ -602,6 +676,132 @@ impl proposal::Proposal {
         }
     }
 
+    /// Serializes a [`Proposal`] based upon a supported [`StandardFeeRule`] to a self-contained
+    /// signable package.
+    ///
+    /// In addition to the data included by [`Self::from_standard_proposal`], the resulting
+    /// protobuf includes the plaintext of each shielded note to be spent along with its witness,
+    /// and the note commitment tree anchor for each step that spends shielded notes, as computed
+    /// from the wallet's note commitment trees. The result can be decoded with
+    /// [`Self::try_into_signable_package`] on a device that has no access to the wallet database.
+    pub fn signable_package_from_standard_proposal<DbT, NoteRef>(
+        wallet_db: &mut DbT,
+        value: &Proposal<StandardFeeRule, NoteRef>,
+    ) -> Result<Self, ShardTreeError<DbT::Error>>
+    where
+        DbT: WalletCommitmentTrees,
+    {
+        use proposal::proposed_input;
+
+        let mut result = Self::from_standard_proposal(value);
+        for (proto_step, step) in result.steps.iter_mut().zip(value.steps().iter()) {
+            let inputs = match step.shielded_inputs() {
+                Some(inputs) => inputs,
+                None => continue,
+            };
+            let anchor_height = inputs.anchor_height();
+
+            let mut sapling_witnesses = vec![];
+            if step.involves(PoolType::Shielded(ShieldedProtocol::Sapling)) {
+                wallet_db.with_sapling_tree_mut::<_, _, ShardTreeError<DbT::Error>>(|tree| {
+                    proto_step.sapling_anchor =
+                        write_node(&tree.root_at_checkpoint_id(&anchor_height)?);
+                    for note in inputs.notes() {
+                        if matches!(note.note(), Note::Sapling(_)) {
+                            sapling_witnesses.push(write_merkle_path(
+                                &tree.witness_at_checkpoint_id_caching(
+                                    note.note_commitment_tree_position(),
+                                    &anchor_height,
+                                )?,
+                            ));
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+
+            #[cfg(feature = "orchard")]
+            let mut orchard_witnesses = vec![];
+            #[cfg(feature = "orchard")]
+            if step.involves(PoolType::Shielded(ShieldedProtocol::Orchard)) {
+                wallet_db.with_orchard_tree_mut::<_, _, ShardTreeError<DbT::Error>>(|tree| {
+                    proto_step.orchard_anchor =
+                        write_node(&tree.root_at_checkpoint_id(&anchor_height)?);
+                    for note in inputs.notes() {
+                        if matches!(note.note(), Note::Orchard(_)) {
+                            orchard_witnesses.push(write_merkle_path(
+                                &tree.witness_at_checkpoint_id_caching(
+                                    note.note_commitment_tree_position(),
+                                    &anchor_height,
+                                )?,
+                            ));
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+
+            // Shielded inputs are serialized immediately after the transparent inputs, in the
+            // same order as the notes of the step's shielded inputs.
+            let mut sapling_witnesses = sapling_witnesses.into_iter();
+            #[cfg(feature = "orchard")]
+            let mut orchard_witnesses = orchard_witnesses.into_iter();
+            for (proto_input, note) in proto_step
+                .inputs
+                .iter_mut()
+                .skip(step.transparent_inputs().len())
+                .zip(inputs.notes().iter())
+            {
+                if let Some(proposed_input::Value::ReceivedOutput(out)) = &mut proto_input.value {
+                    let (recipient, rseed, rseed_type, rho, merkle_path) = match note.note() {
+                        Note::Sapling(n) => {
+                            let (rseed, rseed_type) = match n.rseed() {
+                                sapling::Rseed::BeforeZip212(rcm) => {
+                                    (rcm.to_repr().to_vec(), proposal::RseedType::BeforeZip212)
+                                }
+                                sapling::Rseed::AfterZip212(rseed) => {
+                                    (rseed.to_vec(), proposal::RseedType::AfterZip212)
+                                }
+                            };
+                            (
+                                n.recipient().to_bytes().to_vec(),
+                                rseed,
+                                rseed_type,
+                                vec![],
+                                sapling_witnesses.next(),
+                            )
+                        }
+                        #[cfg(feature = "orchard")]
+                        Note::Orchard(n) => (
+                            n.recipient().to_raw_address_bytes().to_vec(),
+                            n.rseed().as_bytes().to_vec(),
+                            proposal::RseedType::NotSpecified,
+                            n.rho().to_bytes().to_vec(),
+                            orchard_witnesses.next(),
+                        ),
+                    };
+
+                    out.spend_data = Some(proposal::NoteSpendData {
+                        recipient,
+                        rseed,
+                        rho,
+                        key_scope: match note.spending_key_scope() {
+                            Scope::External => proposal::KeyScope::External,
+                            Scope::Internal => proposal::KeyScope::Internal,
+                        }
+                        .into(),
+                        commitment_tree_position: note.note_commitment_tree_position().into(),
+                        merkle_path: merkle_path
+                            .expect("A witness was computed for each shielded input."),
+                        rseed_type: rseed_type.into(),
+                    });
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
     /// Attempts to parse a [`Proposal`] based upon a supported [`StandardFeeRule`] from its
     /// protobuf representation.
     pub fn try_into_standard_proposal<DbT, DbError>(
@@ -611,6 +811,223 @@ impl proposal::Proposal {
     where
         DbT: InputSource<Error = DbError>,
     {
+        self.decode_standard_proposal(
+            #[cfg(feature = "transparent-inputs")]
+            |txid, index| {
+                let outpoint = OutPoint::new(txid.into(), index);
+                wallet_db
+                    .get_unspent_transparent_output(&outpoint)
+                    .map_err(ProposalDecodingError::InputRetrieval)?
+                    .ok_or(ProposalDecodingError::InputNotFound(
+                        txid,
+                        PoolType::Transparent,
+                        index,
+                    ))
+            },
+            |_, out, txid, protocol| {
+                wallet_db
+                    .get_spendable_note(&txid, protocol, out.index)
+                    .map_err(ProposalDecodingError::InputRetrieval)?
+                    .ok_or(ProposalDecodingError::InputNotFound(
+                        txid,
+                        PoolType::Shielded(protocol),
+                        out.index,
+                    ))
+            },
+        )
+    }
+
+    /// Attempts to parse a [`SignablePackage`] based upon a supported [`StandardFeeRule`] from
+    /// its protobuf representation, as produced by
+    /// [`Self::signable_package_from_standard_proposal`].
+    ///
+    /// This does not require access to the wallet database; all of the notes to be spent, and
+    /// the anchors and witnesses required to spend them, are read from the protobuf. Proposals
+    /// that spend transparent inputs cannot be represented as signable packages.
+    pub fn try_into_signable_package(
+        &self,
+    ) -> Result<SignablePackage<StandardFeeRule>, ProposalDecodingError<Infallible>> {
+        let mut sapling_witnesses: Vec<Vec<SaplingMerklePath>> = vec![vec![]; self.steps.len()];
+        #[cfg(feature = "orchard")]
+        let mut orchard_witnesses: Vec<Vec<OrchardMerklePath>> = vec![vec![]; self.steps.len()];
+
+        let proposal = self.decode_standard_proposal(
+            #[cfg(feature = "transparent-inputs")]
+            |txid, index| {
+                Err(ProposalDecodingError::SpendDataMissing(
+                    txid,
+                    PoolType::Transparent,
+                    index,
+                ))
+            },
+            |step_index, out, txid, protocol| {
+                let pool = PoolType::Shielded(protocol);
+                let spend_data =
+                    out.spend_data
+                        .as_ref()
+                        .ok_or(ProposalDecodingError::SpendDataMissing(
+                            txid, pool, out.index,
+                        ))?;
+                let invalid = || ProposalDecodingError::SpendDataInvalid(txid, pool, out.index);
+
+                let output_index = u16::try_from(out.index).map_err(|_| invalid())?;
+                let spending_key_scope = match spend_data.key_scope() {
+                    proposal::KeyScope::External => Scope::External,
+                    proposal::KeyScope::Internal => Scope::Internal,
+                    proposal::KeyScope::NotSpecified => return Err(invalid()),
+                };
+                let position = Position::from(spend_data.commitment_tree_position);
+                let recipient =
+                    <[u8; 43]>::try_from(&spend_data.recipient[..]).map_err(|_| invalid())?;
+                let rseed = <[u8; 32]>::try_from(&spend_data.rseed[..]).map_err(|_| invalid())?;
+
+                let note = match protocol {
+                    ShieldedProtocol::Sapling => {
+                        let recipient =
+                            sapling::PaymentAddress::from_bytes(&recipient).ok_or_else(invalid)?;
+                        let rseed = match spend_data.rseed_type() {
+                            proposal::RseedType::BeforeZip212 => sapling::Rseed::BeforeZip212(
+                                Option::from(jubjub::Fr::from_repr(rseed)).ok_or_else(invalid)?,
+                            ),
+                            proposal::RseedType::AfterZip212 => sapling::Rseed::AfterZip212(rseed),
+                            proposal::RseedType::NotSpecified => return Err(invalid()),
+                        };
+                        sapling_witnesses[step_index].push(
+                            read_merkle_path(position, &spend_data.merkle_path)
+                                .ok_or_else(invalid)?,
+                        );
+
+                        Note::Sapling(sapling::Note::from_parts(
+                            recipient,
+                            sapling::value::NoteValue::from_raw(out.value),
+                            rseed,
+                        ))
+                    }
+                    #[cfg(feature = "orchard")]
+                    ShieldedProtocol::Orchard => {
+                        let recipient =
+                            Option::from(orchard::Address::from_raw_address_bytes(&recipient))
+                                .ok_or_else(invalid)?;
+                        let rho = <[u8; 32]>::try_from(&spend_data.rho[..])
+                            .ok()
+                            .and_then(|rho| Option::from(orchard::note::Rho::from_bytes(&rho)))
+                            .ok_or_else(invalid)?;
+                        let rseed =
+                            Option::from(orchard::note::RandomSeed::from_bytes(rseed, &rho))
+                                .ok_or_else(invalid)?;
+                        orchard_witnesses[step_index].push(
+                            read_merkle_path(position, &spend_data.merkle_path)
+                                .ok_or_else(invalid)?,
+                        );
+
+                        Note::Orchard(
+                            Option::from(orchard::Note::from_parts(
+                                recipient,
+                                orchard::value::NoteValue::from_raw(out.value),
+                                rho,
+                                rseed,
+                            ))
+                            .ok_or_else(invalid)?,
+                        )
+                    }
+                    #[cfg(not(feature = "orchard"))]
+                    ShieldedProtocol::Orchard => {
+                        return Err(ProposalDecodingError::ValuePoolNotSupported(
+                            proposal::ValuePool::Orchard.into(),
+                        ));
+                    }
+                };
+
+                Ok(ReceivedNote::from_parts(
+                    NoteId::new(txid, protocol, output_index),
+                    txid,
+                    output_index,
+                    note,
+                    spending_key_scope,
+                    position,
+                ))
+            },
+        )?;
+
+        // The anchor for each pool is determined in the same fashion as when transactions are
+        // created directly from the wallet: the empty tree is used for steps that involve a pool
+        // but have no shielded inputs.
+        let mut sapling_witnesses = sapling_witnesses.into_iter();
+        #[cfg(feature = "orchard")]
+        let mut orchard_witnesses = orchard_witnesses.into_iter();
+        let step_witnesses = proposal
+            .steps()
+            .iter()
+            .zip(self.steps.iter())
+            .map(|(step, proto_step)| {
+                let sapling_anchor = step
+                    .involves(PoolType::Shielded(ShieldedProtocol::Sapling))
+                    .then(|| match step.shielded_inputs() {
+                        Some(_) => read_node::<sapling::Node>(&proto_step.sapling_anchor)
+                            .map(sapling::Anchor::from)
+                            .ok_or(ProposalDecodingError::AnchorInvalid(
+                                ShieldedProtocol::Sapling,
+                            )),
+                        None => Ok(sapling::Anchor::empty_tree()),
+                    })
+                    .transpose()?;
+
+                #[cfg(feature = "orchard")]
+                let orchard_anchor = step
+                    .involves(PoolType::Shielded(ShieldedProtocol::Orchard))
+                    .then(|| match step.shielded_inputs() {
+                        Some(_) => read_node::<MerkleHashOrchard>(&proto_step.orchard_anchor)
+                            .map(orchard::Anchor::from)
+                            .ok_or(ProposalDecodingError::AnchorInvalid(
+                                ShieldedProtocol::Orchard,
+                            )),
+                        None => Ok(orchard::Anchor::empty_tree()),
+                    })
+                    .transpose()?;
+
+                Ok(StepWitnesses::from_parts(
+                    sapling_anchor,
+                    sapling_witnesses
+                        .next()
+                        .expect("There is a witness list for each proposal step."),
+                    #[cfg(feature = "orchard")]
+                    orchard_anchor,
+                    #[cfg(feature = "orchard")]
+                    orchard_witnesses
+                        .next()
+                        .expect("There is a witness list for each proposal step."),
+                ))
+            })
+            .collect::<Result<Vec<_>, ProposalDecodingError<Infallible>>>()?;
+
+        Ok(SignablePackage::from_parts(
+            proposal,
+            NonEmpty::from_vec(step_witnesses).expect("Proposal steps are nonempty."),
+        ))
+    }
+
+    /// Decodes a [`Proposal`] based upon a supported [`StandardFeeRule`], using the provided
+    /// functions to resolve each transparent and shielded input.
+    ///
+    /// The shielded input resolver is provided with the index of the step in which the input
+    /// is spent.
+    fn decode_standard_proposal<NoteRef, E>(
+        &self,
+        #[cfg(feature = "transparent-inputs")] mut get_utxo: impl FnMut(
+            TxId,
+            u32,
+        ) -> Result<
+            WalletTransparentOutput,
+            ProposalDecodingError<E>,
+        >,
+        mut get_note: impl FnMut(
+            usize,
+            &proposal::ReceivedOutput,
+            TxId,
+            ShieldedProtocol,
+        )
+            -> Result<ReceivedNote<NoteRef, Note>, ProposalDecodingError<E>>,
+    ) -> Result<Proposal<StandardFeeRule, NoteRef>, ProposalDecodingError<E>> {
         use self::proposal::proposed_input::Value::*;
         match self.proto_version {
             PROPOSAL_SER_V1 => {
@@ -625,7 +1042,7 @@ impl proposal::Proposal {
                 };
 
                 let mut steps = Vec::with_capacity(self.steps.len());
-                for step in &self.steps {
+                for (step_index, step) in self.steps.iter().enumerate() {
                     let transaction_request =
                         TransactionRequest::from_uri(&step.transaction_request)?;
 
@@ -639,7 +1056,7 @@ impl proposal::Proposal {
                                 pool_type(pop.value_pool)?,
                             ))
                         })
-                        .collect::<Result<BTreeMap<usize, PoolType>, ProposalDecodingError<DbError>>>()?;
+                        .collect::<Result<BTreeMap<usize, PoolType>, ProposalDecodingError<E>>>()?;
 
                     #[cfg(not(feature = "transparent-inputs"))]
                     let transparent_inputs = vec![];
@@ -666,36 +1083,10 @@ impl proposal::Proposal {
                                         ));
 
                                         #[cfg(feature = "transparent-inputs")]
-                                        {
-                                            let outpoint = OutPoint::new(txid.into(), out.index);
-                                            transparent_inputs.push(
-                                                wallet_db
-                                                    .get_unspent_transparent_output(&outpoint)
-                                                    .map_err(ProposalDecodingError::InputRetrieval)?
-                                                    .ok_or({
-                                                        ProposalDecodingError::InputNotFound(
-                                                            txid,
-                                                            PoolType::Transparent,
-                                                            out.index,
-                                                        )
-                                                    })?,
-                                            );
-                                        }
+                                        transparent_inputs.push(get_utxo(txid, out.index)?);
                                     }
-                                    PoolType::Shielded(protocol) => received_notes.push(
-                                        wallet_db
-                                            .get_spendable_note(&txid, protocol, out.index)
-                                            .map_err(ProposalDecodingError::InputRetrieval)
-                                            .and_then(|opt| {
-                                                opt.ok_or({
-                                                    ProposalDecodingError::InputNotFound(
-                                                        txid,
-                                                        PoolType::Shielded(protocol),
-                                                        out.index,
-                                                    )
-                                                })
-                                            })?,
-                                    ),
+                                    PoolType::Shielded(protocol) => received_notes
+                                        .push(get_note(step_index, out, txid, protocol)?),
                                 }
                             }
                             PriorStepOutput(s_ref) => {
@@ -793,7 +1184,107 @@ impl proposal::Proposal {
                 )
                 .map_err(ProposalDecodingError::ProposalInvalid)
             }
-            other => Err(ProposalDecodingError::VersionInvalid(other)),
+            other => Err(ProposalDecodingError::UnsupportedProposalVersion {
+                found: other,
+                supported: PROPOSAL_SER_V1,
+            }),
+        }
+    }
+}
+
+/// Constant for the V1 watch-only account serialization version.
+pub const WATCH_ONLY_SER_V1: u32 = 1;
+
+/// Errors that can occur in the process of decoding a [`WatchOnlyBundle`] from its protobuf
+/// representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchOnlyDecodingError {
+    /// The serialization version of the protobuf was not recognized.
+    VersionInvalid(u32),
+    /// The unified full viewing key could not be decoded.
+    UfvkInvalid(String),
+    /// The diversifier index at the given position in the address list was not 11 bytes in
+    /// length.
+    DiversifierIndexInvalid(usize),
+    /// The address at the given position in the address list was not a valid unified address
+    /// for the network.
+    AddressInvalid(usize),
+}
+
+impl Display for WatchOnlyDecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchOnlyDecodingError::VersionInvalid(v) => {
+                write!(f, "Unrecognized watch-only account version {}", v)
+            }
+            WatchOnlyDecodingError::UfvkInvalid(err) => {
+                write!(f, "Invalid unified full viewing key: {}", err)
+            }
+            WatchOnlyDecodingError::DiversifierIndexInvalid(i) => {
+                write!(f, "Invalid diversifier index for address at position {}", i)
+            }
+            WatchOnlyDecodingError::AddressInvalid(i) => {
+                write!(f, "Invalid unified address at position {}", i)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WatchOnlyDecodingError {}
+
+impl watch_only::WatchOnlyAccount {
+    /// Serializes a [`WatchOnlyBundle`] to its protobuf representation.
+    pub fn from_bundle<P: consensus::Parameters>(params: &P, bundle: &WatchOnlyBundle) -> Self {
+        watch_only::WatchOnlyAccount {
+            proto_version: WATCH_ONLY_SER_V1,
+            ufvk: bundle.ufvk().encode(params),
+            birthday_height: bundle.birthday_height().into(),
+            recover_until_height: bundle.recover_until().map_or(0, u32::from),
+            addresses: bundle
+                .addresses()
+                .iter()
+                .map(|(di, ua)| watch_only::WatchOnlyAddress {
+                    diversifier_index: di.as_bytes().to_vec(),
+                    address: ua.encode(params),
+                })
+                .collect(),
+        }
+    }
+
+    /// Attempts to parse a [`WatchOnlyBundle`] from its protobuf representation.
+    pub fn try_into_bundle<P: consensus::Parameters>(
+        &self,
+        params: &P,
+    ) -> Result<WatchOnlyBundle, WatchOnlyDecodingError> {
+        match self.proto_version {
+            WATCH_ONLY_SER_V1 => {
+                let ufvk = UnifiedFullViewingKey::decode(params, &self.ufvk)
+                    .map_err(WatchOnlyDecodingError::UfvkInvalid)?;
+
+                let addresses = self
+                    .addresses
+                    .iter()
+                    .enumerate()
+                    .map(|(i, addr)| {
+                        let di = <[u8; 11]>::try_from(&addr.diversifier_index[..])
+                            .map_err(|_| WatchOnlyDecodingError::DiversifierIndexInvalid(i))?;
+                        match Address::decode(params, &addr.address) {
+                            Some(Address::Unified(ua)) => Ok((DiversifierIndex::from(di), ua)),
+                            _ => Err(WatchOnlyDecodingError::AddressInvalid(i)),
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(WatchOnlyBundle::from_parts(
+                    ufvk,
+                    self.birthday_height.into(),
+                    Some(self.recover_until_height)
+                        .filter(|h| *h != 0)
+                        .map(BlockHeight::from),
+                    addresses,
+                ))
+            }
+            other => Err(WatchOnlyDecodingError::VersionInvalid(other)),
         }
     }
 }
@@ -46,6 +46,16 @@ pub struct ProposalStep {
     /// used for determining which OVK to select for wallet-internal outputs.
     #[prost(bool, tag = "6")]
     pub is_shielding: bool,
+    /// The root of the Sapling note commitment tree at the anchor height, encoded
+    /// as a 32-byte tree node. This is only present in signable packages for steps
+    /// that spend shielded inputs and involve the Sapling pool.
+    #[prost(bytes = "vec", tag = "7")]
+    pub sapling_anchor: ::prost::alloc::vec::Vec<u8>,
+    /// The root of the Orchard note commitment tree at the anchor height, encoded
+    /// as a 32-byte tree node. This is only present in signable packages for steps
+    /// that spend shielded inputs and involve the Orchard pool.
+    #[prost(bytes = "vec", tag = "8")]
+    pub orchard_anchor: ::prost::alloc::vec::Vec<u8>,
 }
 /// A mapping from ZIP 321 payment index to the output pool that has been chosen
 /// for that payment, based upon the payment address and the selected inputs to
@@ -71,6 +81,42 @@ pub struct ReceivedOutput {
     pub index: u32,
     #[prost(uint64, tag = "4")]
     pub value: u64,
+    /// The data required to spend this output without access to the wallet
+    /// database. This is only present in signable packages, and only for
+    /// shielded outputs.
+    #[prost(message, optional, tag = "5")]
+    pub spend_data: ::core::option::Option<NoteSpendData>,
+}
+/// The plaintext of a received note, along with the witness required to spend
+/// it against the anchor of the proposal step in which it is spent.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NoteSpendData {
+    /// The raw 43-byte encoding of the address to which the note was sent.
+    #[prost(bytes = "vec", tag = "1")]
+    pub recipient: ::prost::alloc::vec::Vec<u8>,
+    /// The bytes of the note's random seed. For Sapling notes, the form of the
+    /// seed is given by `rseedType`.
+    #[prost(bytes = "vec", tag = "2")]
+    pub rseed: ::prost::alloc::vec::Vec<u8>,
+    /// For Orchard notes, the `rho` value of the note. Empty for Sapling notes.
+    #[prost(bytes = "vec", tag = "3")]
+    pub rho: ::prost::alloc::vec::Vec<u8>,
+    /// The scope of the spending key required to spend the note.
+    #[prost(enumeration = "KeyScope", tag = "4")]
+    pub key_scope: i32,
+    /// The position of the note's commitment in the note commitment tree.
+    #[prost(uint64, tag = "5")]
+    pub commitment_tree_position: u64,
+    /// The authentication path from the note commitment to the anchor of the
+    /// proposal step, as a sequence of 32-byte tree nodes ordered from the leaf
+    /// level upwards.
+    #[prost(bytes = "vec", repeated, tag = "6")]
+    pub merkle_path: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    /// For Sapling notes, the form of the note's random seed. Unused for Orchard
+    /// notes.
+    #[prost(enumeration = "RseedType", tag = "7")]
+    pub rseed_type: i32,
 }
 /// A reference a payment in a prior step of the proposal. This payment must
 /// belong to the wallet.
@@ -185,6 +231,80 @@ impl ValuePool {
         }
     }
 }
+/// The scope of the spending key that is able to spend a note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum KeyScope {
+    /// Protobuf requires that enums have a zero discriminant as the default
+    /// value. However, we need to require that a known scope is selected,
+    /// and we do not want to fall back to any default, so sending the
+    /// KeyScopeNotSpecified value will be treated as an error.
+    NotSpecified = 0,
+    /// The external scope, used for addresses given out to other parties.
+    External = 1,
+    /// The internal scope, used for change and wallet-internal operations.
+    Internal = 2,
+}
+impl KeyScope {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            KeyScope::NotSpecified => "KeyScopeNotSpecified",
+            KeyScope::External => "External",
+            KeyScope::Internal => "Internal",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "KeyScopeNotSpecified" => Some(Self::NotSpecified),
+            "External" => Some(Self::External),
+            "Internal" => Some(Self::Internal),
+            _ => None,
+        }
+    }
+}
+/// The form of the random seed of a Sapling note, as determined by whether the
+/// note was created before or after ZIP 212 activation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum RseedType {
+    /// Protobuf requires that enums have a zero discriminant as the default
+    /// value. However, we need to require that the form of a Sapling note's
+    /// random seed is known, so sending the RseedTypeNotSpecified value for a
+    /// Sapling note will be treated as an error.
+    NotSpecified = 0,
+    /// The `rseed` field contains the note commitment randomness `rcm`.
+    BeforeZip212 = 1,
+    /// The `rseed` field contains the 32-byte seed from which `rcm` and `esk`
+    /// are derived.
+    AfterZip212 = 2,
+}
+impl RseedType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            RseedType::NotSpecified => "RseedTypeNotSpecified",
+            RseedType::BeforeZip212 => "BeforeZip212",
+            RseedType::AfterZip212 => "AfterZip212",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "RseedTypeNotSpecified" => Some(Self::NotSpecified),
+            "BeforeZip212" => Some(Self::BeforeZip212),
+            "AfterZip212" => Some(Self::AfterZip212),
+            _ => None,
+        }
+    }
+}
 /// The fee rule used in constructing a Proposal
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
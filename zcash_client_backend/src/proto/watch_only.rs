@@ -0,0 +1,34 @@
+/// The data required to track an account on a watch-only device. This never
+/// contains spending key material.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatchOnlyAccount {
+    /// The version of this serialization format.
+    #[prost(uint32, tag = "1")]
+    pub proto_version: u32,
+    /// The ZIP 316 encoding of the account's unified full viewing key.
+    #[prost(string, tag = "2")]
+    pub ufvk: ::prost::alloc::string::String,
+    /// The birthday height of the account.
+    #[prost(uint32, tag = "3")]
+    pub birthday_height: u32,
+    /// The height at which the account exits recovery mode. A value of zero
+    /// indicates that no such height was set.
+    #[prost(uint32, tag = "4")]
+    pub recover_until_height: u32,
+    /// The external unified addresses that have been generated for the account.
+    #[prost(message, repeated, tag = "5")]
+    pub addresses: ::prost::alloc::vec::Vec<WatchOnlyAddress>,
+}
+/// A unified address generated for an account, along with the diversifier
+/// index at which it was derived from the account's viewing key.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatchOnlyAddress {
+    /// The 11-byte little-endian encoding of the diversifier index.
+    #[prost(bytes = "vec", tag = "1")]
+    pub diversifier_index: ::prost::alloc::vec::Vec<u8>,
+    /// The ZIP 316 encoding of the unified address.
+    #[prost(string, tag = "2")]
+    pub address: ::prost::alloc::string::String,
+}
@@ -2,10 +2,13 @@ use crossbeam_channel as channel;
 use std::collections::HashMap;
 use std::fmt;
 use std::mem;
+use std::num::NonZeroUsize;
+use std::slice;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
-    Arc,
+    Arc, Condvar, Mutex, PoisonError,
 };
+use std::time::{Duration, Instant};
 
 use memuse::DynamicUsage;
 use zcash_note_encryption::{
@@ -14,7 +17,7 @@ use zcash_note_encryption::{
 use zcash_primitives::{block::BlockHash, transaction::TxId};
 
 /// A decrypted transaction output.
-pub(crate) struct DecryptedOutput<IvkTag, D: Domain, M> {
+pub(crate) struct DecryptedOutput<IvkTag, D: Domain, M, Ctx = ()> {
     /// The tag corresponding to the incoming viewing key used to decrypt the note.
     pub(crate) ivk_tag: IvkTag,
     /// The recipient of the note.
@@ -23,15 +26,32 @@ pub(crate) struct DecryptedOutput<IvkTag, D: Domain, M> {
     pub(crate) note: D::Note,
     /// The memo field, or `()` if this is a decrypted compact output.
     pub(crate) memo: M,
+    /// The context that was provided for the output when it was added to a
+    /// [`BatchRunner`], or `()` if the output was decrypted directly.
+    pub(crate) ctx: Ctx,
 }
 
-impl<IvkTag, D: Domain, M> fmt::Debug for DecryptedOutput<IvkTag, D, M>
+impl<IvkTag, D: Domain, M> DecryptedOutput<IvkTag, D, M> {
+    /// Attaches the given output context to this decrypted output.
+    fn with_ctx<Ctx>(self, ctx: Ctx) -> DecryptedOutput<IvkTag, D, M, Ctx> {
+        DecryptedOutput {
+            ivk_tag: self.ivk_tag,
+            recipient: self.recipient,
+            note: self.note,
+            memo: self.memo,
+            ctx,
+        }
+    }
+}
+
+impl<IvkTag, D: Domain, M, Ctx> fmt::Debug for DecryptedOutput<IvkTag, D, M, Ctx>
 where
     IvkTag: fmt::Debug,
     D::IncomingViewingKey: fmt::Debug,
     D::Recipient: fmt::Debug,
     D::Note: fmt::Debug,
     M: fmt::Debug,
+    Ctx: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("DecryptedOutput")
@@ -39,6 +59,7 @@ where
             .field("recipient", &self.recipient)
             .field("note", &self.note)
             .field("memo", &self.memo)
+            .field("ctx", &self.ctx)
             .finish()
     }
 }
@@ -53,6 +74,15 @@ pub(crate) trait Decryptor<D: BatchDomain, Output> {
         ivks: &[D::IncomingViewingKey],
         outputs: &[(D, Output)],
     ) -> Vec<Option<DecryptedOutput<IvkTag, D, Self::Memo>>>;
+
+    /// Trial-decrypts the given outputs with a single incoming viewing key.
+    ///
+    /// This avoids looking up the tag for each decrypted output by index.
+    fn batch_decrypt_single<IvkTag: Clone>(
+        tag: &IvkTag,
+        ivk: &D::IncomingViewingKey,
+        outputs: &[(D, Output)],
+    ) -> Vec<Option<DecryptedOutput<IvkTag, D, Self::Memo>>>;
 }
 
 /// A decryptor of outputs as encoded in transactions.
@@ -76,6 +106,26 @@ impl<D: BatchDomain, Output: ShieldedOutput<D, ENC_CIPHERTEXT_SIZE>> Decryptor<D
                     recipient,
                     note,
                     memo,
+                    ctx: (),
+                })
+            })
+            .collect()
+    }
+
+    fn batch_decrypt_single<IvkTag: Clone>(
+        tag: &IvkTag,
+        ivk: &D::IncomingViewingKey,
+        outputs: &[(D, Output)],
+    ) -> Vec<Option<DecryptedOutput<IvkTag, D, Self::Memo>>> {
+        batch::try_note_decryption(slice::from_ref(ivk), outputs)
+            .into_iter()
+            .map(|res| {
+                res.map(|((note, recipient, memo), _)| DecryptedOutput {
+                    ivk_tag: tag.clone(),
+                    recipient,
+                    note,
+                    memo,
+                    ctx: (),
                 })
             })
             .collect()
@@ -103,6 +153,26 @@ impl<D: BatchDomain, Output: ShieldedOutput<D, COMPACT_NOTE_SIZE>> Decryptor<D,
                     recipient,
                     note,
                     memo: (),
+                    ctx: (),
+                })
+            })
+            .collect()
+    }
+
+    fn batch_decrypt_single<IvkTag: Clone>(
+        tag: &IvkTag,
+        ivk: &D::IncomingViewingKey,
+        outputs: &[(D, Output)],
+    ) -> Vec<Option<DecryptedOutput<IvkTag, D, Self::Memo>>> {
+        batch::try_compact_note_decryption(slice::from_ref(ivk), outputs)
+            .into_iter()
+            .map(|res| {
+                res.map(|((note, recipient), _)| DecryptedOutput {
+                    ivk_tag: tag.clone(),
+                    recipient,
+                    note,
+                    memo: (),
+                    ctx: (),
                 })
             })
             .collect()
@@ -135,13 +205,77 @@ impl<IvkTag, D: Domain, M> DynamicUsage for OutputReplier<IvkTag, D, M> {
     }
 }
 
+/// The sender for the results of batch scanning all of the outputs of a specific
+/// transaction with a single incoming viewing key.
+struct TransactionReplier<IvkTag, D: Domain, M> {
+    /// The number of outputs of the transaction within the batch.
+    output_count: usize,
+    sender: channel::Sender<OutputItem<IvkTag, D, M>>,
+}
+
+impl<IvkTag, D: Domain, M> DynamicUsage for TransactionReplier<IvkTag, D, M> {
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        // We count the memory usage of items in the channel on the receiver side.
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+/// The senders for the results of a batch.
+enum Repliers<IvkTag, D: Domain, M> {
+    /// One sender per output, used when trial-decrypting with several incoming viewing
+    /// keys.
+    PerOutput(Vec<OutputReplier<IvkTag, D, M>>),
+    /// One sender per transaction, used by the single incoming viewing key fast path.
+    PerTransaction(Vec<TransactionReplier<IvkTag, D, M>>),
+}
+
+impl<IvkTag, D: Domain, M> Repliers<IvkTag, D, M> {
+    /// Returns the number of outputs for which results will be sent.
+    fn output_count(&self) -> usize {
+        match self {
+            Repliers::PerOutput(repliers) => repliers.len(),
+            Repliers::PerTransaction(repliers) => repliers.iter().map(|r| r.output_count).sum(),
+        }
+    }
+}
+
+impl<IvkTag, D: Domain, M> DynamicUsage for Repliers<IvkTag, D, M> {
+    fn dynamic_usage(&self) -> usize {
+        match self {
+            Repliers::PerOutput(repliers) => repliers.dynamic_usage(),
+            Repliers::PerTransaction(repliers) => repliers.dynamic_usage(),
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        match self {
+            Repliers::PerOutput(repliers) => repliers.dynamic_usage_bounds(),
+            Repliers::PerTransaction(repliers) => repliers.dynamic_usage_bounds(),
+        }
+    }
+}
+
 /// The receiver for the result of batch scanning a specific transaction.
-struct BatchReceiver<IvkTag, D: Domain, M>(channel::Receiver<OutputItem<IvkTag, D, M>>);
+///
+/// The caller-supplied context for each output of the transaction is held here rather
+/// than being sent to the batch, as it is not needed for trial decryption.
+struct BatchReceiver<IvkTag, D: Domain, M, Ctx> {
+    receiver: channel::Receiver<OutputItem<IvkTag, D, M>>,
+    /// The context for each output, indexed by output index. An entry is taken when the
+    /// corresponding decryption result is collected.
+    contexts: Vec<Option<Ctx>>,
+}
 
-impl<IvkTag, D: Domain, M> DynamicUsage for BatchReceiver<IvkTag, D, M> {
+impl<IvkTag, D: Domain, M, Ctx> DynamicUsage for BatchReceiver<IvkTag, D, M, Ctx> {
     fn dynamic_usage(&self) -> usize {
         // We count the memory usage of items in the channel on the receiver side.
-        let num_items = self.0.len();
+        let num_items = self.receiver.len();
 
         // We know we use unbounded channels, so the items in the channel are stored as a
         // linked list. `crossbeam_channel` allocates memory for the linked list in blocks
@@ -159,7 +293,11 @@ impl<IvkTag, D: Domain, M> DynamicUsage for BatchReceiver<IvkTag, D, M> {
         const ATOMIC_USIZE_SIZE: usize = std::mem::size_of::<AtomicUsize>();
         let block_size = PTR_SIZE + ITEMS_PER_BLOCK * (item_size + ATOMIC_USIZE_SIZE);
 
-        num_blocks * block_size
+        // We don't require contexts to implement `DynamicUsage`, so we only count the
+        // inline size of each context.
+        let contexts_size = self.contexts.capacity() * mem::size_of::<Option<Ctx>>();
+
+        num_blocks * block_size + contexts_size
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
@@ -179,6 +317,12 @@ pub(crate) trait Tasks<Item> {
         let task = self.add_task(item);
         rayon::spawn_fifo(|| task.run());
     }
+
+    /// Returns `true` if the running tasks are using enough of the memory available to them
+    /// that starting further tasks is likely to block.
+    fn under_memory_pressure(&self) -> bool {
+        false
+    }
 }
 
 /// A batch scanning task.
@@ -196,18 +340,51 @@ impl<Item: Task> Tasks<Item> for () {
     }
 }
 
-/// A task tracker that measures heap usage.
+/// The heap usage of the tasks that are currently running, shared between a [`WithUsage`]
+/// tracker and its tasks.
+struct RunningUsage {
+    usage: Mutex<usize>,
+    // Notified whenever a running task completes and frees its heap usage.
+    freed: Condvar,
+}
+
+/// A task tracker that measures heap usage, and optionally limits it.
+///
+/// If a memory budget is configured, starting a new task blocks until the tasks that are
+/// already running have freed enough memory for the new task to fit within the budget. A
+/// task is always started if no other tasks are running, so a budget smaller than the
+/// usage of a single task causes tasks to run one at a time.
 ///
 /// This struct implements `DynamicUsage` without any item bounds, but that works because
 /// it only implements `Tasks` for items that implement `DynamicUsage`.
 pub(crate) struct WithUsage {
     // The current heap usage for all running tasks.
-    running_usage: Arc<AtomicUsize>,
+    running_usage: Arc<RunningUsage>,
+    // The heap usage that running tasks may not exceed, except when only one is running.
+    memory_budget: usize,
+}
+
+impl WithUsage {
+    /// Constructs a task tracker that blocks the starting of new tasks while their heap
+    /// usage would cause the total usage of running tasks to exceed `memory_budget`.
+    ///
+    /// Tasks are started by the thread that calls [`BatchRunner::add_outputs`] or
+    /// [`BatchRunner::flush`], which must therefore not be a thread of the global `rayon`
+    /// threadpool on which the tasks run.
+    pub(crate) fn with_memory_budget(memory_budget: usize) -> Self {
+        Self {
+            running_usage: Arc::new(RunningUsage {
+                usage: Mutex::new(0),
+                freed: Condvar::new(),
+            }),
+            memory_budget,
+        }
+    }
 }
 
 impl DynamicUsage for WithUsage {
     fn dynamic_usage(&self) -> usize {
-        self.running_usage.load(Ordering::Relaxed)
+        *self.running_usage.usage.lock().unwrap()
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
@@ -222,9 +399,7 @@ impl<Item: Task + DynamicUsage> Tasks<Item> for WithUsage {
     type Task = WithUsageTask<Item>;
 
     fn new() -> Self {
-        Self {
-            running_usage: Arc::new(AtomicUsize::new(0)),
-        }
+        Self::with_memory_budget(usize::MAX)
     }
 
     fn add_task(&self, item: Item) -> Self::Task {
@@ -244,14 +419,30 @@ impl<Item: Task + DynamicUsage> Tasks<Item> for WithUsage {
         task.own_usage =
             mem::size_of::<Arc<()>>() + mem::size_of_val(&task) + task.item.dynamic_usage();
 
+        // Wait until the running tasks have freed enough memory for this task to fit
+        // within the budget, unless there are no running tasks to wait for.
+        let own_usage = task.own_usage;
+        let mut running_usage = self
+            .running_usage
+            .freed
+            .wait_while(self.running_usage.usage.lock().unwrap(), |usage| {
+                *usage != 0 && usage.saturating_add(own_usage) > self.memory_budget
+            })
+            .unwrap();
+
         // Approximate now as when the heap cost of this running batch begins. In practice
         // this is fine, because `Self::add_task` is called from `Self::run_task` which
         // immediately moves the task to the heap.
-        self.running_usage
-            .fetch_add(task.own_usage, Ordering::SeqCst);
+        *running_usage += own_usage;
 
         task
     }
+
+    fn under_memory_pressure(&self) -> bool {
+        // Treat three quarters of the budget as the point at which new tasks are likely to
+        // have to wait for running tasks to complete.
+        self.dynamic_usage() > self.memory_budget - self.memory_budget / 4
+    }
 }
 
 /// A task that will clean up its own heap usage from the overall running usage once it is
@@ -264,17 +455,193 @@ pub(crate) struct WithUsageTask<Item> {
     /// between [`WithUsage`] and its tasks.
     own_usage: usize,
     /// Pointer to the parent [`WithUsage`]'s heap usage tracker for running tasks.
-    running_usage: Arc<AtomicUsize>,
+    running_usage: Arc<RunningUsage>,
 }
 
 impl<Item: Task> Task for WithUsageTask<Item> {
     fn run(self) {
+        let WithUsageTask {
+            item,
+            own_usage,
+            running_usage,
+        } = self;
+
+        // Release the heap usage of this task once it completes, even if the item panics;
+        // otherwise tasks waiting on the memory budget would block forever.
+        let _guard = RunningUsageGuard {
+            own_usage,
+            running_usage,
+        };
+
         // Run the item.
-        self.item.run();
+        item.run();
+    }
+}
+
+/// Removes the heap usage of a task from the overall running usage when dropped.
+struct RunningUsageGuard {
+    own_usage: usize,
+    running_usage: Arc<RunningUsage>,
+}
 
-        // Signal that the heap memory for this task has been freed.
-        self.running_usage
-            .fetch_sub(self.own_usage, Ordering::SeqCst);
+impl Drop for RunningUsageGuard {
+    fn drop(&mut self) {
+        // Signal that the heap memory for this task has been freed. The lock is never held
+        // while a task runs, but we avoid panicking here regardless, as this may run during
+        // unwinding.
+        *self
+            .running_usage
+            .usage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) -= self.own_usage;
+        self.running_usage.freed.notify_all();
+    }
+}
+
+/// Measurements of the batches run by a [`BatchRunner`] that adaptively tunes its batch size
+/// threshold, shared between the runner and its batches.
+#[derive(Default)]
+struct BatchTimings {
+    /// The number of batches that are currently running.
+    running: AtomicUsize,
+    /// The total number of outputs in, and the total time taken to run, the batches that
+    /// have completed since the runner last adjusted its threshold.
+    completed: Mutex<(usize, Duration)>,
+}
+
+impl BatchTimings {
+    /// Returns the number of outputs in, and the time taken to run, the batches that have
+    /// completed since this method was last called.
+    fn take_completed(&self) -> (usize, Duration) {
+        mem::take(&mut *self.completed.lock().unwrap())
+    }
+}
+
+/// Records the running time of a batch in its runner's [`BatchTimings`] when dropped.
+struct BatchTimer {
+    timings: Arc<BatchTimings>,
+    outputs: usize,
+    start: Instant,
+}
+
+impl BatchTimer {
+    fn start(timings: Arc<BatchTimings>, outputs: usize) -> Self {
+        timings.running.fetch_add(1, Ordering::SeqCst);
+        Self {
+            timings,
+            outputs,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for BatchTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        {
+            let mut completed = self.timings.completed.lock().unwrap();
+            completed.0 += self.outputs;
+            completed.1 += elapsed;
+        }
+        self.timings.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The duration that an adaptively-sized batch aims to take to run.
+///
+/// Batches that run for much less time than this spend a significant fraction of it on
+/// per-batch overhead (spawning the task and creating its result channels), while batches
+/// that run for much longer delay the availability of their results.
+const ADAPTIVE_TARGET_BATCH_DURATION: Duration = Duration::from_millis(20);
+
+/// The weight given to the latest observation when updating the moving average of the
+/// per-output decryption latency.
+const ADAPTIVE_LATENCY_WEIGHT: f64 = 0.25;
+
+/// The control loop used by a [`BatchRunner`] to adjust its batch size threshold.
+///
+/// Each time the runner flushes a batch, the threshold is adjusted as follows:
+///
+/// - If the runner's tasks are under memory pressure (see [`Tasks::under_memory_pressure`]),
+///   the threshold is halved, so that each running batch holds less memory.
+/// - Otherwise, the per-output latency of the batches that have completed since the last
+///   adjustment is folded into an exponentially-weighted moving average, from which a target
+///   threshold is derived: the number of outputs that would take
+///   [`ADAPTIVE_TARGET_BATCH_DURATION`] to decrypt.
+/// - If the target is above the current threshold and fewer batches are running than there
+///   are threads in the pool (i.e. the pool is underutilized), the threshold grows towards
+///   the target, at most doubling. The threshold is not grown while the pool is saturated,
+///   because larger batches would then only delay the availability of results.
+/// - If the target is below the current threshold, the threshold shrinks towards the target,
+///   at most halving.
+///
+/// The threshold always remains within the configured bounds, and is left unchanged until a
+/// latency has been observed.
+struct AdaptiveThreshold {
+    min: usize,
+    max: usize,
+    /// The moving average of the observed decryption latency per output, in nanoseconds.
+    nanos_per_output: Option<f64>,
+    timings: Arc<BatchTimings>,
+}
+
+impl AdaptiveThreshold {
+    fn new(min: usize, max: usize) -> Self {
+        assert!(min > 0 && min <= max);
+        Self {
+            min,
+            max,
+            nanos_per_output: None,
+            timings: Arc::new(BatchTimings::default()),
+        }
+    }
+
+    /// Returns the threshold that should replace `current`, given the measurements of the
+    /// batches that completed since the last adjustment.
+    fn adjust(&mut self, current: usize, memory_pressure: bool) -> usize {
+        let completed = self.timings.take_completed();
+        let running = self.timings.running.load(Ordering::SeqCst);
+        self.next_threshold(
+            current,
+            completed,
+            running,
+            rayon::current_num_threads(),
+            memory_pressure,
+        )
+    }
+
+    fn next_threshold(
+        &mut self,
+        current: usize,
+        (outputs, elapsed): (usize, Duration),
+        running: usize,
+        parallelism: usize,
+        memory_pressure: bool,
+    ) -> usize {
+        if outputs > 0 {
+            let observed = elapsed.as_nanos() as f64 / outputs as f64;
+            self.nanos_per_output = Some(self.nanos_per_output.map_or(observed, |average| {
+                average + ADAPTIVE_LATENCY_WEIGHT * (observed - average)
+            }));
+        }
+
+        let next = if memory_pressure {
+            current / 2
+        } else if let Some(nanos_per_output) = self.nanos_per_output {
+            let target = (ADAPTIVE_TARGET_BATCH_DURATION.as_nanos() as f64
+                / nanos_per_output.max(1.0)) as usize;
+            if target > current && running < parallelism {
+                target.min(current.saturating_mul(2))
+            } else if target < current {
+                target.max(current / 2)
+            } else {
+                current
+            }
+        } else {
+            current
+        };
+
+        next.clamp(self.min, self.max)
     }
 }
 
@@ -289,38 +656,49 @@ pub(crate) struct Batch<IvkTag, D: BatchDomain, Output, Dec: Decryptor<D, Output
     /// batch decryption. Ideally the domain, output, and output replier would
     /// all be part of the same struct, which would also track the output index
     /// (that is captured in the outer `OutputIndex` of each `OutputReplier`).
+    ///
+    /// When the batch has a single incoming viewing key, there is instead one replier
+    /// per transaction, covering that transaction's contiguous run of outputs.
     outputs: Vec<(D, Output)>,
-    repliers: Vec<OutputReplier<IvkTag, D, Dec::Memo>>,
+    repliers: Repliers<IvkTag, D, Dec::Memo>,
+    /// The maximum number of outputs to trial-decrypt in a single call to the decryptor.
+    max_chunk: usize,
+    /// The measurements to which this batch reports its running time, if its runner is
+    /// adaptively tuning its batch size threshold.
+    timings: Option<Arc<BatchTimings>>,
+}
+
+/// Returns the heap usage of the allocation backing the given vector, without counting any
+/// heap memory owned by its elements.
+fn vec_allocation_usage<T>(v: &Vec<T>) -> usize {
+    v.capacity() * mem::size_of::<T>()
 }
 
 impl<IvkTag, D, Output, Dec> DynamicUsage for Batch<IvkTag, D, Output, Dec>
 where
-    IvkTag: DynamicUsage,
-    D: BatchDomain + DynamicUsage,
-    D::IncomingViewingKey: DynamicUsage,
-    Output: DynamicUsage,
+    D: BatchDomain,
     Dec: Decryptor<D, Output>,
 {
     fn dynamic_usage(&self) -> usize {
-        self.tags.dynamic_usage()
-            + self.ivks.dynamic_usage()
-            + self.outputs.dynamic_usage()
+        // The tags, keys, domains and outputs of the batches that we scan do not own any
+        // heap memory, so we only count the vectors holding them. This lets any batch be
+        // run by a `WithUsage` task tracker, without requiring every type parameter to
+        // implement `DynamicUsage`.
+        vec_allocation_usage(&self.tags)
+            + vec_allocation_usage(&self.ivks)
+            + vec_allocation_usage(&self.outputs)
             + self.repliers.dynamic_usage()
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
-        let (tags_lower, tags_upper) = self.tags.dynamic_usage_bounds();
-        let (ivks_lower, ivks_upper) = self.ivks.dynamic_usage_bounds();
-        let (outputs_lower, outputs_upper) = self.outputs.dynamic_usage_bounds();
         let (repliers_lower, repliers_upper) = self.repliers.dynamic_usage_bounds();
+        let vectors = vec_allocation_usage(&self.tags)
+            + vec_allocation_usage(&self.ivks)
+            + vec_allocation_usage(&self.outputs);
 
         (
-            tags_lower + ivks_lower + outputs_lower + repliers_lower,
-            tags_upper
-                .zip(ivks_upper)
-                .zip(outputs_upper)
-                .zip(repliers_upper)
-                .map(|(((a, b), c), d)| a + b + c + d),
+            vectors + repliers_lower,
+            repliers_upper.map(|upper| vectors + upper),
         )
     }
 }
@@ -332,16 +710,56 @@ where
     Dec: Decryptor<D, Output>,
 {
     /// Constructs a new batch.
-    fn new(tags: Vec<IvkTag>, ivks: Vec<D::IncomingViewingKey>) -> Self {
+    ///
+    /// If `single_ivk_fast_path` is `true` and exactly one incoming viewing key is
+    /// provided, the batch reports its results with one channel sender per transaction
+    /// rather than one per output.
+    ///
+    /// When run, the batch trial-decrypts its outputs in chunks of at most `max_chunk`
+    /// outputs, reporting the results of each chunk before decrypting the next.
+    fn new(
+        tags: Vec<IvkTag>,
+        ivks: Vec<D::IncomingViewingKey>,
+        single_ivk_fast_path: bool,
+        max_chunk: usize,
+    ) -> Self {
         assert_eq!(tags.len(), ivks.len());
+        assert!(max_chunk > 0);
+        let repliers = if single_ivk_fast_path && ivks.len() == 1 {
+            Repliers::PerTransaction(vec![])
+        } else {
+            Repliers::PerOutput(vec![])
+        };
         Self {
             tags,
             ivks,
             outputs: vec![],
-            repliers: vec![],
+            repliers,
+            max_chunk,
+            timings: None,
         }
     }
 
+    /// Returns a new empty batch with the same keys and configuration as this batch.
+    fn empty_like(&self) -> Self
+    where
+        D::IncomingViewingKey: Clone,
+    {
+        let mut batch = Self::new(
+            self.tags.clone(),
+            self.ivks.clone(),
+            self.is_single_ivk(),
+            self.max_chunk,
+        );
+        batch.timings = self.timings.clone();
+        batch
+    }
+
+    /// Returns `true` if this batch uses the single incoming viewing key fast path.
+    fn is_single_ivk(&self) -> bool {
+        matches!(self.repliers, Repliers::PerTransaction(_))
+    }
+
     /// Returns `true` if the batch is currently empty.
     fn is_empty(&self) -> bool {
         self.outputs.is_empty()
@@ -368,25 +786,76 @@ where
             ivks,
             outputs,
             repliers,
+            max_chunk,
+            timings,
         } = self;
 
-        assert_eq!(outputs.len(), repliers.len());
+        assert_eq!(outputs.len(), repliers.output_count());
 
-        let decryption_results = Dec::batch_decrypt(&tags, &ivks, &outputs);
-        for (decryption_result, OutputReplier(replier)) in
-            decryption_results.into_iter().zip(repliers.into_iter())
-        {
-            // If `decryption_result` is `None` then we will just drop `replier`,
-            // indicating to the parent `BatchRunner` that this output was not for us.
-            if let Some(value) = decryption_result {
-                let result = OutputIndex {
-                    output_index: replier.output_index,
-                    value,
-                };
-
-                if replier.value.send(result).is_err() {
-                    tracing::debug!("BatchRunner was dropped before batch finished");
-                    break;
+        // This runs on a worker thread, so the span has no parent; its fields identify the
+        // work being done.
+        let _span = tracing::debug_span!(
+            "batch_decrypt",
+            outputs = outputs.len(),
+            ivks = ivks.len(),
+            single_ivk = matches!(repliers, Repliers::PerTransaction(_)),
+        )
+        .entered();
+        let _timer = timings.map(|timings| BatchTimer::start(timings, outputs.len()));
+
+        // Each chunk of outputs is only decrypted once the results of the previous chunk
+        // have been reported, so that the repliers for the earlier outputs are dropped (and
+        // their results become available to the parent `BatchRunner`) as soon as possible.
+        let chunks = outputs.chunks(max_chunk);
+        match repliers {
+            Repliers::PerOutput(repliers) => {
+                let decryption_results =
+                    chunks.flat_map(|chunk| Dec::batch_decrypt(&tags, &ivks, chunk));
+                for (decryption_result, OutputReplier(replier)) in
+                    decryption_results.zip(repliers.into_iter())
+                {
+                    // If `decryption_result` is `None` then we will just drop `replier`,
+                    // indicating to the parent `BatchRunner` that this output was not for us.
+                    if let Some(value) = decryption_result {
+                        let result = OutputIndex {
+                            output_index: replier.output_index,
+                            value,
+                        };
+
+                        if replier.value.send(result).is_err() {
+                            tracing::debug!("BatchRunner was dropped before batch finished");
+                            break;
+                        }
+                    }
+                }
+            }
+            Repliers::PerTransaction(repliers) => {
+                let mut decryption_results =
+                    chunks.flat_map(|chunk| Dec::batch_decrypt_single(&tags[0], &ivks[0], chunk));
+                for TransactionReplier {
+                    output_count,
+                    sender,
+                } in repliers
+                {
+                    // Each transaction's outputs are contiguous within the batch. Once we
+                    // have sent the successful decryptions for a transaction, `sender` is
+                    // dropped, indicating to the parent `BatchRunner` that the results for
+                    // that transaction are complete.
+                    for (output_index, decryption_result) in
+                        decryption_results.by_ref().take(output_count).enumerate()
+                    {
+                        if let Some(value) = decryption_result {
+                            let result = OutputIndex {
+                                output_index,
+                                value,
+                            };
+
+                            if sender.send(result).is_err() {
+                                tracing::debug!("BatchRunner was dropped before batch finished");
+                                return;
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -401,25 +870,39 @@ where
 {
     /// Adds the given outputs to this batch.
     ///
-    /// `replier` will be called with the result of every output.
-    fn add_outputs(
+    /// `replier` will be called with the result of every output. The output contexts are
+    /// ignored here; they are retained by the [`BatchRunner`] that owns the receiver.
+    fn add_outputs<Ctx>(
         &mut self,
         domain: impl Fn(&Output) -> D,
-        outputs: &[Output],
+        outputs: &[(Output, Ctx)],
         replier: channel::Sender<OutputItem<IvkTag, D, Dec::Memo>>,
     ) {
         self.outputs.extend(
             outputs
                 .iter()
-                .cloned()
-                .map(|output| (domain(&output), output)),
+                .map(|(output, _)| (domain(output), output.clone())),
         );
-        self.repliers.extend((0..outputs.len()).map(|output_index| {
-            OutputReplier(OutputIndex {
-                output_index,
-                value: replier.clone(),
-            })
-        }));
+        match &mut self.repliers {
+            Repliers::PerOutput(repliers) => {
+                repliers.extend((0..outputs.len()).map(|output_index| {
+                    OutputReplier(OutputIndex {
+                        output_index,
+                        value: replier.clone(),
+                    })
+                }));
+            }
+            Repliers::PerTransaction(repliers) => {
+                // If there are no outputs, we drop `replier` immediately, as we do for the
+                // per-output repliers.
+                if !outputs.is_empty() {
+                    repliers.push(TransactionReplier {
+                        output_count: outputs.len(),
+                        sender: replier,
+                    });
+                }
+            }
+        }
     }
 }
 
@@ -440,22 +923,45 @@ impl DynamicUsage for ResultKey {
 }
 
 /// Logic to run batches of trial decryptions on the global threadpool.
-pub(crate) struct BatchRunner<IvkTag, D, Output, Dec, T>
+///
+/// Each output added to the runner is accompanied by a context value of type `Ctx`, which
+/// is returned alongside the output's decryption result by [`Self::collect_results`].
+pub(crate) struct BatchRunner<IvkTag, D, Output, Dec, T, Ctx = ()>
 where
     D: BatchDomain,
     Dec: Decryptor<D, Output>,
     T: Tasks<Batch<IvkTag, D, Output, Dec>>,
 {
     batch_size_threshold: usize,
+    // The control loop adjusting `batch_size_threshold`, if it is adaptive.
+    adaptive_threshold: Option<AdaptiveThreshold>,
+    // The maximum number of outputs that a batch trial-decrypts at once.
+    max_batch_chunk: usize,
     // The batch currently being accumulated.
     acc: Batch<IvkTag, D, Output, Dec>,
     // The running batches.
     running_tasks: T,
     // Receivers for the results of the running batches.
-    pending_results: HashMap<ResultKey, BatchReceiver<IvkTag, D, Dec::Memo>>,
+    pending_results: HashMap<ResultKey, BatchReceiver<IvkTag, D, Dec::Memo, Ctx>>,
 }
 
-impl<IvkTag, D, Output, Dec, T> DynamicUsage for BatchRunner<IvkTag, D, Output, Dec, T>
+/// The outputs that have been added to a [`BatchRunner`] but whose results have not yet been
+/// collected, as returned by [`BatchRunner::take_pending`].
+///
+/// This includes both the outputs that have not yet been flushed, and the receivers for the
+/// results of batches that are already running. It may be passed to
+/// [`BatchRunner::restore`] in order to continue scanning with another runner, such as one
+/// that runs its batches on a different thread pool.
+pub(crate) struct PendingBatches<IvkTag, D, Output, Dec, Ctx = ()>
+where
+    D: BatchDomain,
+    Dec: Decryptor<D, Output>,
+{
+    acc: Batch<IvkTag, D, Output, Dec>,
+    pending_results: HashMap<ResultKey, BatchReceiver<IvkTag, D, Dec::Memo, Ctx>>,
+}
+
+impl<IvkTag, D, Output, Dec, T, Ctx> DynamicUsage for BatchRunner<IvkTag, D, Output, Dec, T, Ctx>
 where
     IvkTag: DynamicUsage,
     D: BatchDomain + DynamicUsage,
@@ -488,7 +994,7 @@ where
     }
 }
 
-impl<IvkTag, D, Output, Dec, T> BatchRunner<IvkTag, D, Output, Dec, T>
+impl<IvkTag, D, Output, Dec, T, Ctx> BatchRunner<IvkTag, D, Output, Dec, T, Ctx>
 where
     IvkTag: Clone,
     D: BatchDomain,
@@ -496,6 +1002,9 @@ where
     T: Tasks<Batch<IvkTag, D, Output, Dec>>,
 {
     /// Constructs a new batch runner for the given incoming viewing keys.
+    ///
+    /// If exactly one incoming viewing key is provided, the runner will use a fast path
+    /// that avoids per-output bookkeeping.
     pub(crate) fn new(
         batch_size_threshold: usize,
         ivks: impl Iterator<Item = (IvkTag, D::IncomingViewingKey)>,
@@ -503,14 +1012,104 @@ where
         let (tags, ivks) = ivks.unzip();
         Self {
             batch_size_threshold,
-            acc: Batch::new(tags, ivks),
+            adaptive_threshold: None,
+            max_batch_chunk: usize::MAX,
+            acc: Batch::new(tags, ivks, true, usize::MAX),
             running_tasks: T::new(),
             pending_results: HashMap::default(),
         }
     }
+
+    /// Enables or disables the fast path used when this runner has exactly one incoming
+    /// viewing key.
+    ///
+    /// The fast path reports decryption results over a single channel sender per
+    /// transaction, rather than one per output, and does not look up the tag of the
+    /// decrypting key for each output. It has no effect when the runner has more than one
+    /// incoming viewing key.
+    ///
+    /// This must be called before any outputs are added to the runner.
+    #[cfg(test)]
+    pub(crate) fn with_single_ivk_fast_path(mut self, enabled: bool) -> Self {
+        assert!(self.acc.is_empty());
+        let tags = mem::take(&mut self.acc.tags);
+        let ivks = mem::take(&mut self.acc.ivks);
+        let timings = self.acc.timings.take();
+        self.acc = Batch::new(tags, ivks, enabled, self.max_batch_chunk);
+        self.acc.timings = timings;
+        self
+    }
+
+    /// Limits the number of outputs that each batch trial-decrypts at once to
+    /// `max_batch_chunk`.
+    ///
+    /// Batches larger than this are decrypted in chunks, and the results for each chunk are
+    /// reported before the next chunk is decrypted. This bounds the duration of each
+    /// decryption call when a single batch is very large (for example, when a block contains
+    /// an unusually large number of outputs), without changing the size threshold at which
+    /// batches are flushed. By default, batches are decrypted in a single chunk.
+    ///
+    /// This must be called before any outputs are added to the runner.
+    pub(crate) fn with_max_batch_chunk(mut self, max_batch_chunk: NonZeroUsize) -> Self {
+        assert!(self.acc.is_empty());
+        let tags = mem::take(&mut self.acc.tags);
+        let ivks = mem::take(&mut self.acc.ivks);
+        let single_ivk_fast_path = self.acc.is_single_ivk();
+        let timings = self.acc.timings.take();
+        self.acc = Batch::new(tags, ivks, single_ivk_fast_path, max_batch_chunk.get());
+        self.acc.timings = timings;
+        self.max_batch_chunk = max_batch_chunk.get();
+        self
+    }
+
+    /// Enables adaptive tuning of the batch size threshold within the bounds `min` and `max`.
+    ///
+    /// The threshold that was set via [`Self::new`] is used (clamped to the given bounds)
+    /// until the first batches complete. Thereafter, each time a batch is flushed the
+    /// threshold is adjusted based on the observed per-output decryption latency, the number
+    /// of batches running relative to the size of the thread pool, and whether the running
+    /// batches are under memory pressure (as reported by [`WithUsage`] when a memory budget is
+    /// configured via [`Self::with_memory_budget`]). See [`AdaptiveThreshold`] for details of
+    /// the control loop.
+    ///
+    /// This must be called before any outputs are added to the runner.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub(crate) fn with_adaptive_threshold(mut self, min: NonZeroUsize, max: NonZeroUsize) -> Self {
+        assert!(self.acc.is_empty());
+        let (min, max) = (min.get(), max.get());
+        let adaptive_threshold = AdaptiveThreshold::new(min, max);
+        self.acc.timings = Some(adaptive_threshold.timings.clone());
+        self.batch_size_threshold = self.batch_size_threshold.clamp(min, max);
+        self.adaptive_threshold = Some(adaptive_threshold);
+        self
+    }
+}
+
+impl<IvkTag, D, Output, Dec, Ctx> BatchRunner<IvkTag, D, Output, Dec, WithUsage, Ctx>
+where
+    D: BatchDomain,
+    Dec: Decryptor<D, Output>,
+    WithUsage: Tasks<Batch<IvkTag, D, Output, Dec>>,
+{
+    /// Limits the heap memory used by this runner's running batches to `memory_budget`
+    /// bytes.
+    ///
+    /// Once the budget is reached, flushing a batch blocks until enough of the running
+    /// batches have completed for the new batch to fit within the budget. See
+    /// [`WithUsage::with_memory_budget`] for details.
+    ///
+    /// This must be called before any outputs are added to the runner.
+    pub(crate) fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        assert!(self.acc.is_empty() && self.running_tasks.dynamic_usage() == 0);
+        self.running_tasks = WithUsage::with_memory_budget(memory_budget);
+        self
+    }
 }
 
-impl<IvkTag, D, Output, Dec, T> BatchRunner<IvkTag, D, Output, Dec, T>
+impl<IvkTag, D, Output, Dec, T, Ctx> BatchRunner<IvkTag, D, Output, Dec, T, Ctx>
 where
     IvkTag: Clone + Send + 'static,
     D: BatchDomain + Send + 'static,
@@ -521,27 +1120,37 @@ where
     Output: Clone + Send + 'static,
     Dec: Decryptor<D, Output>,
     T: Tasks<Batch<IvkTag, D, Output, Dec>>,
+    Ctx: Clone,
 {
     /// Batches the given outputs for trial decryption.
     ///
+    /// Each output is paired with a context value, which is returned with the output's
+    /// decryption result (if any) by [`Self::collect_results`].
+    ///
     /// `block_tag` is the hash of the block that triggered this txid being added to the
     /// batch, or the all-zeros hash to indicate that no block triggered it (i.e. it was a
     /// mempool change).
     ///
     /// If after adding the given outputs, the accumulated batch size is at least the size
-    /// threshold that was set via `Self::new`, `Self::flush` is called. Subsequent calls
-    /// to `Self::add_outputs` will be accumulated into a new batch.
+    /// threshold that was set via `Self::new` (or its current adaptively-tuned value; see
+    /// `Self::with_adaptive_threshold`), `Self::flush` is called. Subsequent calls to
+    /// `Self::add_outputs` will be accumulated into a new batch.
     pub(crate) fn add_outputs(
         &mut self,
         block_tag: BlockHash,
         txid: TxId,
         domain: impl Fn(&Output) -> D,
-        outputs: &[Output],
+        outputs: &[(Output, Ctx)],
     ) {
         let (tx, rx) = channel::unbounded();
         self.acc.add_outputs(domain, outputs, tx);
-        self.pending_results
-            .insert(ResultKey(block_tag, txid), BatchReceiver(rx));
+        self.pending_results.insert(
+            ResultKey(block_tag, txid),
+            BatchReceiver {
+                receiver: rx,
+                contexts: outputs.iter().map(|(_, ctx)| Some(ctx.clone())).collect(),
+            },
+        );
 
         if self.acc.outputs.len() >= self.batch_size_threshold {
             self.flush();
@@ -551,44 +1160,262 @@ where
     /// Runs the currently accumulated batch on the global threadpool.
     ///
     /// Subsequent calls to `Self::add_outputs` will be accumulated into a new batch.
+    #[tracing::instrument(level = "debug", skip_all, fields(outputs = self.acc.outputs.len()))]
     pub(crate) fn flush(&mut self) {
         if !self.acc.is_empty() {
-            let mut batch = Batch::new(self.acc.tags.clone(), self.acc.ivks.clone());
+            if let Some(adaptive_threshold) = &mut self.adaptive_threshold {
+                self.batch_size_threshold = adaptive_threshold.adjust(
+                    self.batch_size_threshold,
+                    self.running_tasks.under_memory_pressure(),
+                );
+            }
+
+            let mut batch = self.acc.empty_like();
             mem::swap(&mut batch, &mut self.acc);
             self.running_tasks.run_task(batch);
         }
     }
 
+    /// Removes and returns the outputs that have been added to this runner but whose results
+    /// have not yet been collected.
+    ///
+    /// Batches that are already running continue to run, and their results may be collected
+    /// by whichever runner the returned [`PendingBatches`] are restored into. This runner
+    /// is left empty, and may continue to be used.
+    pub(crate) fn take_pending(&mut self) -> PendingBatches<IvkTag, D, Output, Dec, Ctx> {
+        let empty = self.acc.empty_like();
+        PendingBatches {
+            acc: mem::replace(&mut self.acc, empty),
+            pending_results: mem::take(&mut self.pending_results),
+        }
+    }
+
+    /// Adds the given pending outputs, taken from another runner with
+    /// [`Self::take_pending`], to this runner.
+    ///
+    /// The results for the restored outputs may subsequently be collected with
+    /// [`Self::collect_results`], exactly as if they had been added to this runner. Outputs
+    /// that had not been flushed by the other runner are trial-decrypted with the incoming
+    /// viewing keys of that runner; they are run immediately as a batch of their own, after
+    /// flushing any outputs accumulated by this runner.
+    ///
+    /// Returns an error containing the ID of the first transaction found, without restoring
+    /// any outputs, if the restored outputs include outputs of a transaction for which this
+    /// runner already has pending results (see [`Self::conflicting_txid`]).
+    pub(crate) fn restore(
+        &mut self,
+        pending: PendingBatches<IvkTag, D, Output, Dec, Ctx>,
+    ) -> Result<(), TxId> {
+        if let Some(txid) = self.conflicting_txid(&pending) {
+            return Err(txid);
+        }
+
+        let PendingBatches {
+            acc,
+            pending_results,
+        } = pending;
+
+        self.flush();
+        if !acc.is_empty() {
+            self.running_tasks.run_task(acc);
+        }
+        self.pending_results.extend(pending_results);
+
+        Ok(())
+    }
+
+    /// Returns the ID of a transaction for which both this runner and the given pending
+    /// outputs have results pending, if any.
+    ///
+    /// Such pending outputs cannot be restored into this runner, because the results for the
+    /// transaction would be ambiguous when collected.
+    pub(crate) fn conflicting_txid(
+        &self,
+        pending: &PendingBatches<IvkTag, D, Output, Dec, Ctx>,
+    ) -> Option<TxId> {
+        pending
+            .pending_results
+            .keys()
+            .find(|key| self.pending_results.contains_key(key))
+            .map(|ResultKey(_, txid)| *txid)
+    }
+
     /// Collects the pending decryption results for the given transaction.
     ///
     /// `block_tag` is the hash of the block that triggered this txid being added to the
     /// batch, or the all-zeros hash to indicate that no block triggered it (i.e. it was a
     /// mempool change).
+    #[tracing::instrument(level = "debug", skip_all, fields(%txid, decrypted))]
     pub(crate) fn collect_results(
         &mut self,
         block_tag: BlockHash,
         txid: TxId,
-    ) -> HashMap<(TxId, usize), DecryptedOutput<IvkTag, D, Dec::Memo>> {
-        self.pending_results
+    ) -> HashMap<(TxId, usize), DecryptedOutput<IvkTag, D, Dec::Memo, Ctx>> {
+        let results: HashMap<_, _> = self
+            .pending_results
             .remove(&ResultKey(block_tag, txid))
             // We won't have a pending result if the transaction didn't have outputs of
             // this runner's kind.
-            .map(|BatchReceiver(rx)| {
-                // This iterator will end once the channel becomes empty and disconnected.
-                // We created one sender per output, and each sender is dropped after the
-                // batch it is in completes (and in the case of successful decryptions,
-                // after the decrypted note has been sent to the channel). Completion of
-                // the iterator therefore corresponds to complete knowledge of the outputs
-                // of this transaction that could be decrypted.
-                rx.into_iter()
-                    .map(
-                        |OutputIndex {
-                             output_index,
-                             value,
-                         }| { ((txid, output_index), value) },
-                    )
-                    .collect()
-            })
-            .unwrap_or_default()
+            .map(
+                |BatchReceiver {
+                     receiver,
+                     mut contexts,
+                 }| {
+                    // This iterator will end once the channel becomes empty and disconnected.
+                    // We created one sender per output, and each sender is dropped after the
+                    // batch it is in completes (and in the case of successful decryptions,
+                    // after the decrypted note has been sent to the channel). Completion of
+                    // the iterator therefore corresponds to complete knowledge of the outputs
+                    // of this transaction that could be decrypted.
+                    receiver
+                        .into_iter()
+                        .map(
+                            |OutputIndex {
+                                 output_index,
+                                 value,
+                             }| {
+                                let ctx = contexts[output_index]
+                                    .take()
+                                    .expect("Each output's result is sent at most once.");
+                                ((txid, output_index), value.with_ctx(ctx))
+                            },
+                        )
+                        .collect()
+                },
+            )
+            .unwrap_or_default();
+
+        tracing::Span::current().record("decrypted", results.len());
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::thread;
+    use std::time::Duration;
+
+    use crossbeam_channel as channel;
+    use memuse::DynamicUsage;
+
+    use super::{AdaptiveThreshold, Task, Tasks, WithUsage};
+
+    /// A task that records the number of tasks running concurrently with it.
+    struct CountingTask {
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+        done: channel::Sender<()>,
+    }
+
+    impl DynamicUsage for CountingTask {
+        fn dynamic_usage(&self) -> usize {
+            0
+        }
+
+        fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+            (0, Some(0))
+        }
+    }
+
+    impl Task for CountingTask {
+        fn run(self) {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(10));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.done.send(()).unwrap();
+        }
+    }
+
+    #[test]
+    fn tiny_memory_budget_serializes_tasks() {
+        const TASK_COUNT: usize = 8;
+
+        let tasks = WithUsage::with_memory_budget(1);
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let (done_tx, done_rx) = channel::unbounded();
+
+        for _ in 0..TASK_COUNT {
+            tasks.run_task(CountingTask {
+                running: running.clone(),
+                max_running: max_running.clone(),
+                done: done_tx.clone(),
+            });
+        }
+        for _ in 0..TASK_COUNT {
+            done_rx.recv().unwrap();
+        }
+
+        // Every task's usage exceeds the budget, so each waited for the previous one.
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
+
+    /// A task that panics when run.
+    struct PanickingTask(Vec<u8>);
+
+    impl DynamicUsage for PanickingTask {
+        fn dynamic_usage(&self) -> usize {
+            self.0.dynamic_usage()
+        }
+
+        fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+            self.0.dynamic_usage_bounds()
+        }
+    }
+
+    impl Task for PanickingTask {
+        fn run(self) {
+            panic!("task failed");
+        }
+    }
+
+    #[test]
+    fn panicking_task_frees_its_usage() {
+        let tasks = WithUsage::with_memory_budget(1);
+        let task = tasks.add_task(PanickingTask(vec![0; 100]));
+        assert!(tasks.dynamic_usage() > 0);
+
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| task.run())).is_err());
+
+        // The usage of the task was released, so later tasks do not wait for it.
+        assert_eq!(tasks.dynamic_usage(), 0);
+    }
+
+    #[test]
+    fn adaptive_threshold_control_loop() {
+        let mut adaptive = AdaptiveThreshold::new(16, 4096);
+        let idle = (0, Duration::ZERO);
+
+        // The threshold is unchanged until a latency has been observed.
+        assert_eq!(adaptive.next_threshold(100, idle, 0, 4, false), 100);
+
+        // At 10us per output, the target threshold is 2000 outputs. While the pool is
+        // underutilized, the threshold grows towards it, at most doubling each time.
+        let fast = (100, Duration::from_micros(1000));
+        assert_eq!(adaptive.next_threshold(100, fast, 0, 4, false), 200);
+        assert_eq!(adaptive.next_threshold(200, idle, 1, 4, false), 400);
+        assert_eq!(adaptive.next_threshold(1600, idle, 1, 4, false), 2000);
+
+        // The threshold does not grow while the pool is saturated.
+        assert_eq!(adaptive.next_threshold(400, idle, 4, 4, false), 400);
+
+        // Memory pressure halves the threshold, but not below the minimum.
+        assert_eq!(adaptive.next_threshold(400, idle, 0, 4, true), 200);
+        assert_eq!(adaptive.next_threshold(20, idle, 0, 4, true), 16);
+
+        // Slower decryption lowers the target, shrinking the threshold at most by half.
+        let slow = (100, Duration::from_millis(1000));
+        assert_eq!(adaptive.next_threshold(2000, slow, 4, 4, false), 1000);
+        assert_eq!(adaptive.next_threshold(64, idle, 4, 4, false), 32);
+
+        // The threshold never exceeds the maximum.
+        let mut adaptive = AdaptiveThreshold::new(16, 256);
+        assert_eq!(adaptive.next_threshold(200, fast, 0, 4, false), 256);
     }
 }
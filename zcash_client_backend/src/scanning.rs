@@ -4,6 +4,7 @@ use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt::{self, Debug};
 use std::hash::Hash;
+use std::num::NonZeroUsize;
 
 use incrementalmerkletree::{Position, Retention};
 use sapling::{
@@ -12,7 +13,7 @@ use sapling::{
 };
 use subtle::{ConditionallySelectable, ConstantTimeEq, CtOption};
 
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 use zcash_keys::keys::UnifiedFullViewingKey;
 use zcash_note_encryption::{batch, BatchDomain, Domain, ShieldedOutput, COMPACT_NOTE_SIZE};
 use zcash_primitives::{
@@ -23,21 +24,23 @@ use zip32::Scope;
 
 use crate::{
     data_api::{BlockMetadata, ScannedBlock, ScannedBundles},
-    proto::compact_formats::CompactBlock,
-    scan::{Batch, BatchRunner, CompactDecryptor, DecryptedOutput, Tasks},
+    proto::compact_formats::{CompactBlock, CompactSaplingOutput},
+    scan::{
+        Batch, BatchRunner, CompactDecryptor, DecryptedOutput, PendingBatches, Tasks, WithUsage,
+    },
     wallet::{WalletOutput, WalletSpend, WalletTx},
     ShieldedProtocol,
 };
 
 #[cfg(feature = "orchard")]
-use orchard::{
-    note_encryption::{CompactAction, OrchardDomain},
-    tree::MerkleHashOrchard,
+use {
+    crate::proto::compact_formats::CompactOrchardAction,
+    orchard::{
+        note_encryption::{CompactAction, OrchardDomain},
+        tree::MerkleHashOrchard,
+    },
 };
 
-#[cfg(not(feature = "orchard"))]
-use std::marker::PhantomData;
-
 /// A key that can be used to perform trial decryption and nullifier
 /// computation for a [`CompactSaplingOutput`] or [`CompactOrchardAction`].
 ///
@@ -380,6 +383,16 @@ pub enum ScanError {
         index: usize,
     },
 
+    /// The ciphertext of a compact Sapling output or compact Orchard action did not have the
+    /// length required for compact note decryption.
+    CiphertextLengthInvalid {
+        at_height: BlockHeight,
+        txid: TxId,
+        pool_type: ShieldedProtocol,
+        index: usize,
+        length: usize,
+    },
+
     /// The hash of the parent block given by a proposed new chain tip does not match the hash of
     /// the current chain tip.
     PrevHashMismatch { at_height: BlockHeight },
@@ -423,6 +436,7 @@ impl ScanError {
         use ScanError::*;
         match self {
             EncodingInvalid { .. } => false,
+            CiphertextLengthInvalid { .. } => false,
             PrevHashMismatch { .. } => true,
             BlockHeightDiscontinuity { .. } => true,
             TreeSizeMismatch { .. } => true,
@@ -436,6 +450,7 @@ impl ScanError {
         use ScanError::*;
         match self {
             EncodingInvalid { at_height, .. } => *at_height,
+            CiphertextLengthInvalid { at_height, .. } => *at_height,
             PrevHashMismatch { at_height } => *at_height,
             BlockHeightDiscontinuity { new_height, .. } => *new_height,
             TreeSizeMismatch { at_height, .. } => *at_height,
@@ -454,6 +469,11 @@ impl fmt::Display for ScanError {
                 "{:?} output {} of transaction {} was improperly encoded.",
                 pool_type, index, txid
             ),
+            CiphertextLengthInvalid { txid, pool_type, index, length, .. } => write!(
+                f,
+                "{:?} output {} of transaction {} has a ciphertext of length {}; expected {}.",
+                pool_type, index, txid, length, COMPACT_NOTE_SIZE
+            ),
             PrevHashMismatch { at_height } => write!(
                 f,
                 "The parent hash of proposed block does not correspond to the block hash at height {}.",
@@ -475,6 +495,75 @@ impl fmt::Display for ScanError {
     }
 }
 
+/// Checks that the ciphertext of a compact output has the length required for compact note
+/// decryption.
+fn check_ciphertext_length(
+    ciphertext: &[u8],
+    at_height: BlockHeight,
+    txid: TxId,
+    pool_type: ShieldedProtocol,
+    index: usize,
+) -> Result<(), ScanError> {
+    if ciphertext.len() == COMPACT_NOTE_SIZE {
+        Ok(())
+    } else {
+        Err(ScanError::CiphertextLengthInvalid {
+            at_height,
+            txid,
+            pool_type,
+            index,
+            length: ciphertext.len(),
+        })
+    }
+}
+
+/// Parses the compact Sapling output at the given index of a transaction, rejecting outputs
+/// that are malformed rather than passing them to trial decryption.
+fn parse_compact_output(
+    output: &CompactSaplingOutput,
+    at_height: BlockHeight,
+    txid: TxId,
+    index: usize,
+) -> Result<CompactOutputDescription, ScanError> {
+    check_ciphertext_length(
+        &output.ciphertext,
+        at_height,
+        txid,
+        ShieldedProtocol::Sapling,
+        index,
+    )?;
+    CompactOutputDescription::try_from(output).map_err(|_| ScanError::EncodingInvalid {
+        at_height,
+        txid,
+        pool_type: ShieldedProtocol::Sapling,
+        index,
+    })
+}
+
+/// Parses the compact Orchard action at the given index of a transaction, rejecting actions
+/// that are malformed rather than passing them to trial decryption.
+#[cfg(feature = "orchard")]
+fn parse_compact_action(
+    action: &CompactOrchardAction,
+    at_height: BlockHeight,
+    txid: TxId,
+    index: usize,
+) -> Result<CompactAction, ScanError> {
+    check_ciphertext_length(
+        &action.ciphertext,
+        at_height,
+        txid,
+        ShieldedProtocol::Orchard,
+        index,
+    )?;
+    CompactAction::try_from(action).map_err(|_| ScanError::EncodingInvalid {
+        at_height,
+        txid,
+        pool_type: ShieldedProtocol::Orchard,
+        index,
+    })
+}
+
 /// Scans a [`CompactBlock`] with a set of [`ScanningKeys`].
 ///
 /// Returns a vector of [`WalletTx`]s decryptable by any of the given keys. If an output is
@@ -494,7 +583,7 @@ where
     AccountId: Default + Eq + Hash + ConditionallySelectable + Send + 'static,
     IvkTag: Copy + std::hash::Hash + Eq + Send + 'static,
 {
-    scan_block_with_runners::<_, _, _, (), ()>(
+    scan_block_with_runners(
         params,
         block,
         scanning_keys,
@@ -504,92 +593,303 @@ where
     )
 }
 
-type TaggedSaplingBatch<IvkTag> = Batch<
+/// Options controlling the batched trial decryption performed by [`BatchRunners`].
+///
+/// The outputs of the blocks added to a [`BatchRunners`] are accumulated into a batch, which
+/// is trial-decrypted on the global `rayon` thread pool once it contains at least the batch
+/// size threshold number of outputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchOptions {
+    batch_size_threshold: usize,
+    adaptive_threshold: Option<(NonZeroUsize, NonZeroUsize)>,
+    max_batch_chunk: Option<NonZeroUsize>,
+    memory_budget: Option<usize>,
+}
+
+impl Default for BatchOptions {
+    /// Returns options with a batch size threshold of 100 outputs, and with no adaptive
+    /// tuning, chunking, or memory budget.
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl BatchOptions {
+    /// Constructs options that flush a batch once it contains at least
+    /// `batch_size_threshold` outputs, with no adaptive tuning, chunking, or memory budget.
+    pub fn new(batch_size_threshold: usize) -> Self {
+        BatchOptions {
+            batch_size_threshold,
+            adaptive_threshold: None,
+            max_batch_chunk: None,
+            memory_budget: None,
+        }
+    }
+
+    /// Enables adaptive tuning of the batch size threshold within the bounds `min` and `max`.
+    ///
+    /// The threshold given to [`Self::new`] (clamped to these bounds) is used until the first
+    /// batches complete. Thereafter, the threshold is adjusted each time a batch is flushed,
+    /// based on the observed per-output decryption latency and on the number of running
+    /// batches relative to the size of the thread pool. The threshold is also reduced while
+    /// the running batches are close to the budget set with [`Self::with_memory_budget`].
+    ///
+    /// Returns an error if `min` is greater than `max`.
+    pub fn with_adaptive_threshold(
+        self,
+        min: NonZeroUsize,
+        max: NonZeroUsize,
+    ) -> Result<Self, BatchOptionsError> {
+        if min > max {
+            return Err(BatchOptionsError::AdaptiveThresholdBoundsInvalid { min, max });
+        }
+
+        Ok(BatchOptions {
+            adaptive_threshold: Some((min, max)),
+            ..self
+        })
+    }
+
+    /// Limits the number of outputs that each batch trial-decrypts at once to
+    /// `max_batch_chunk`.
+    ///
+    /// Batches larger than this are decrypted in chunks, and the results for each chunk are
+    /// made available before the next chunk is decrypted. This bounds the duration of each
+    /// decryption call when a block contains an unusually large number of outputs, without
+    /// changing the threshold at which batches are flushed.
+    pub fn with_max_batch_chunk(self, max_batch_chunk: NonZeroUsize) -> Self {
+        BatchOptions {
+            max_batch_chunk: Some(max_batch_chunk),
+            ..self
+        }
+    }
+
+    /// Limits the heap memory used by the running batches of each shielded protocol to
+    /// `memory_budget` bytes.
+    ///
+    /// Once the budget is reached, flushing a batch blocks until enough of the running
+    /// batches have completed for the new batch to fit within the budget. A batch is always
+    /// run if no other batches are running, so a budget smaller than a single batch causes
+    /// batches to run one at a time. Because flushing may block, blocks must not be added to
+    /// a [`BatchRunners`] from a thread of the global `rayon` thread pool when a budget is
+    /// set.
+    pub fn with_memory_budget(self, memory_budget: usize) -> Self {
+        BatchOptions {
+            memory_budget: Some(memory_budget),
+            ..self
+        }
+    }
+
+    /// Returns the batch size threshold, or the initial threshold if adaptive tuning is
+    /// enabled.
+    pub fn batch_size_threshold(&self) -> usize {
+        self.batch_size_threshold
+    }
+
+    /// Returns the bounds within which the batch size threshold is tuned, if adaptive
+    /// tuning is enabled.
+    pub fn adaptive_threshold(&self) -> Option<(NonZeroUsize, NonZeroUsize)> {
+        self.adaptive_threshold
+    }
+
+    /// Returns the maximum number of outputs that each batch trial-decrypts at once, if
+    /// limited.
+    pub fn max_batch_chunk(&self) -> Option<NonZeroUsize> {
+        self.max_batch_chunk
+    }
+
+    /// Returns the heap memory budget for the running batches of each shielded protocol, if
+    /// limited.
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// Constructs a batch runner for the given incoming viewing keys that is configured
+    /// with these options.
+    fn runner<IvkTag, D, Output>(
+        &self,
+        ivks: impl Iterator<Item = (IvkTag, D::IncomingViewingKey)>,
+    ) -> BatchRunner<IvkTag, D, Output, CompactDecryptor, WithUsage>
+    where
+        IvkTag: Clone,
+        D: BatchDomain,
+        Output: ShieldedOutput<D, COMPACT_NOTE_SIZE>,
+        WithUsage: Tasks<Batch<IvkTag, D, Output, CompactDecryptor>>,
+    {
+        let mut runner = BatchRunner::new(self.batch_size_threshold, ivks);
+        if let Some(max_batch_chunk) = self.max_batch_chunk {
+            runner = runner.with_max_batch_chunk(max_batch_chunk);
+        }
+        if let Some((min, max)) = self.adaptive_threshold {
+            runner = runner.with_adaptive_threshold(min, max);
+        }
+        if let Some(memory_budget) = self.memory_budget {
+            runner = runner.with_memory_budget(memory_budget);
+        }
+        runner
+    }
+}
+
+/// Errors that may occur in constructing [`BatchOptions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchOptionsError {
+    /// The minimum bound for adaptive tuning of the batch size threshold was greater than
+    /// the maximum bound.
+    AdaptiveThresholdBoundsInvalid {
+        min: NonZeroUsize,
+        max: NonZeroUsize,
+    },
+}
+
+impl fmt::Display for BatchOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatchOptionsError::AdaptiveThresholdBoundsInvalid { min, max } => write!(
+                f,
+                "The minimum adaptive batch size threshold {} is greater than the maximum {}.",
+                min, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BatchOptionsError {}
+
+/// Errors that may occur in restoring [`PendingOutputs`] into a [`BatchRunners`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RestoreError {
+    /// The pending outputs include outputs of the given transaction, for which the runners
+    /// already have results pending. Restoring them would make the results collected for
+    /// the transaction ambiguous.
+    TransactionPending(TxId),
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RestoreError::TransactionPending(txid) => write!(
+                f,
+                "Decryption results for transaction {} are already pending.",
+                txid
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+type TaggedSaplingBatchRunner<IvkTag> = BatchRunner<
     IvkTag,
     SaplingDomain,
     sapling::note_encryption::CompactOutputDescription,
     CompactDecryptor,
+    WithUsage,
 >;
-type TaggedSaplingBatchRunner<IvkTag, Tasks> = BatchRunner<
+type TaggedSaplingPendingBatches<IvkTag> = PendingBatches<
     IvkTag,
     SaplingDomain,
     sapling::note_encryption::CompactOutputDescription,
     CompactDecryptor,
-    Tasks,
 >;
 
 #[cfg(feature = "orchard")]
-type TaggedOrchardBatch<IvkTag> =
-    Batch<IvkTag, OrchardDomain, orchard::note_encryption::CompactAction, CompactDecryptor>;
+type TaggedOrchardBatchRunner<IvkTag> = BatchRunner<
+    IvkTag,
+    OrchardDomain,
+    orchard::note_encryption::CompactAction,
+    CompactDecryptor,
+    WithUsage,
+>;
 #[cfg(feature = "orchard")]
-type TaggedOrchardBatchRunner<IvkTag, Tasks> = BatchRunner<
+type TaggedOrchardPendingBatches<IvkTag> = PendingBatches<
     IvkTag,
     OrchardDomain,
     orchard::note_encryption::CompactAction,
     CompactDecryptor,
-    Tasks,
 >;
 
-pub(crate) trait SaplingTasks<IvkTag>: Tasks<TaggedSaplingBatch<IvkTag>> {}
-impl<IvkTag, T: Tasks<TaggedSaplingBatch<IvkTag>>> SaplingTasks<IvkTag> for T {}
-
-#[cfg(not(feature = "orchard"))]
-pub(crate) trait OrchardTasks<IvkTag> {}
-#[cfg(not(feature = "orchard"))]
-impl<IvkTag, T> OrchardTasks<IvkTag> for T {}
-
-#[cfg(feature = "orchard")]
-pub(crate) trait OrchardTasks<IvkTag>: Tasks<TaggedOrchardBatch<IvkTag>> {}
-#[cfg(feature = "orchard")]
-impl<IvkTag, T: Tasks<TaggedOrchardBatch<IvkTag>>> OrchardTasks<IvkTag> for T {}
+/// Runners that trial-decrypt the Sapling outputs and Orchard actions of compact blocks in
+/// batches on the global `rayon` thread pool, for use with [`scan_block_with_runners`].
+///
+/// Blocks are added to the runners with [`Self::add_block`] before they are scanned; the
+/// decryption results for each block are then collected as it is scanned.
+pub struct BatchRunners<IvkTag: Clone + Send + 'static> {
+    sapling: TaggedSaplingBatchRunner<IvkTag>,
+    #[cfg(feature = "orchard")]
+    orchard: TaggedOrchardBatchRunner<IvkTag>,
+}
 
-pub(crate) struct BatchRunners<IvkTag, TS: SaplingTasks<IvkTag>, TO: OrchardTasks<IvkTag>> {
-    sapling: TaggedSaplingBatchRunner<IvkTag, TS>,
+/// The outputs that have been added to a [`BatchRunners`] but whose decryption results have
+/// not yet been collected, as returned by [`BatchRunners::take_pending`].
+///
+/// This includes both the outputs that have not yet been flushed, and the receivers for the
+/// results of batches that are already running. It may be sent to another thread and
+/// restored into another [`BatchRunners`] with [`BatchRunners::restore`], in order to
+/// continue scanning without losing the outputs that have already been added.
+pub struct PendingOutputs<IvkTag> {
+    sapling: TaggedSaplingPendingBatches<IvkTag>,
     #[cfg(feature = "orchard")]
-    orchard: TaggedOrchardBatchRunner<IvkTag, TO>,
-    #[cfg(not(feature = "orchard"))]
-    orchard: PhantomData<TO>,
+    orchard: TaggedOrchardPendingBatches<IvkTag>,
 }
 
-impl<IvkTag, TS, TO> BatchRunners<IvkTag, TS, TO>
+impl<IvkTag> BatchRunners<IvkTag>
 where
     IvkTag: Clone + Send + 'static,
-    TS: SaplingTasks<IvkTag>,
-    TO: OrchardTasks<IvkTag>,
 {
-    pub(crate) fn for_keys<AccountId>(
-        batch_size_threshold: usize,
+    /// Constructs runners that trial-decrypt with the incoming viewing keys of the given
+    /// scanning keys, configured with the given options.
+    pub fn for_keys<AccountId>(
+        options: &BatchOptions,
         scanning_keys: &ScanningKeys<AccountId, IvkTag>,
     ) -> Self {
         BatchRunners {
-            sapling: BatchRunner::new(
-                batch_size_threshold,
+            sapling: options.runner(
                 scanning_keys
                     .sapling()
                     .iter()
                     .map(|(id, key)| (id.clone(), key.prepare())),
             ),
             #[cfg(feature = "orchard")]
-            orchard: BatchRunner::new(
-                batch_size_threshold,
+            orchard: options.runner(
                 scanning_keys
                     .orchard()
                     .iter()
                     .map(|(id, key)| (id.clone(), key.prepare())),
             ),
-            #[cfg(not(feature = "orchard"))]
-            orchard: PhantomData,
         }
     }
 
-    pub(crate) fn flush(&mut self) {
+    /// Enables or disables the fast path used by each runner when it has exactly one
+    /// incoming viewing key.
+    #[cfg(test)]
+    pub(crate) fn with_single_ivk_fast_path(self, enabled: bool) -> Self {
+        BatchRunners {
+            sapling: self.sapling.with_single_ivk_fast_path(enabled),
+            #[cfg(feature = "orchard")]
+            orchard: self.orchard.with_single_ivk_fast_path(enabled),
+        }
+    }
+
+    /// Runs the outputs that have been accumulated by each runner on the global threadpool.
+    #[tracing::instrument(skip_all)]
+    pub fn flush(&mut self) {
         self.sapling.flush();
         #[cfg(feature = "orchard")]
         self.orchard.flush();
     }
 
-    #[tracing::instrument(skip_all, fields(height = block.height))]
-    pub(crate) fn add_block<P>(&mut self, params: &P, block: CompactBlock) -> Result<(), ScanError>
+    /// Adds the Sapling outputs and Orchard actions of the given block to the runners.
+    ///
+    /// The decryption results for the block are collected when the block is subsequently
+    /// passed to [`scan_block_with_runners`] with these runners.
+    #[tracing::instrument(
+        skip_all,
+        fields(
+            height = block.height,
+            outputs = compact_output_count(&block),
+        )
+    )]
+    pub fn add_block<P>(&mut self, params: &P, block: CompactBlock) -> Result<(), ScanError>
     where
         P: consensus::Parameters + Send + 'static,
         IvkTag: Copy + Send + 'static,
@@ -609,14 +909,7 @@ where
                     .iter()
                     .enumerate()
                     .map(|(i, output)| {
-                        CompactOutputDescription::try_from(output).map_err(|_| {
-                            ScanError::EncodingInvalid {
-                                at_height: block_height,
-                                txid,
-                                pool_type: ShieldedProtocol::Sapling,
-                                index: i,
-                            }
-                        })
+                        parse_compact_output(output, block_height, txid, i).map(|o| (o, ()))
                     })
                     .collect::<Result<Vec<_>, _>>()?,
             );
@@ -630,12 +923,7 @@ where
                     .iter()
                     .enumerate()
                     .map(|(i, action)| {
-                        CompactAction::try_from(action).map_err(|_| ScanError::EncodingInvalid {
-                            at_height: block_height,
-                            txid,
-                            pool_type: ShieldedProtocol::Sapling,
-                            index: i,
-                        })
+                        parse_compact_action(action, block_height, txid, i).map(|a| (a, ()))
                     })
                     .collect::<Result<Vec<_>, _>>()?,
             );
@@ -643,23 +931,89 @@ where
 
         Ok(())
     }
+
+    /// Removes and returns the outputs that have been added to these runners but whose
+    /// decryption results have not yet been collected.
+    ///
+    /// Batches that are already running continue to run. These runners are left empty, and
+    /// may continue to be used.
+    pub fn take_pending(&mut self) -> PendingOutputs<IvkTag> {
+        PendingOutputs {
+            sapling: self.sapling.take_pending(),
+            #[cfg(feature = "orchard")]
+            orchard: self.orchard.take_pending(),
+        }
+    }
+
+    /// Adds the given pending outputs, taken from other runners with [`Self::take_pending`],
+    /// to these runners.
+    ///
+    /// The decryption results for the restored outputs are collected by
+    /// [`scan_block_with_runners`] exactly as if their blocks had been added to these
+    /// runners. Outputs that had not been flushed by the other runners are trial-decrypted
+    /// with the incoming viewing keys of those runners.
+    ///
+    /// Returns an error, without restoring any outputs, if the pending outputs include
+    /// outputs of a transaction for which these runners already have results pending.
+    pub fn restore(&mut self, pending: PendingOutputs<IvkTag>) -> Result<(), RestoreError> {
+        // Check every pool before restoring any of them, so that nothing is restored if an
+        // error is returned.
+        if let Some(txid) = self.sapling.conflicting_txid(&pending.sapling) {
+            return Err(RestoreError::TransactionPending(txid));
+        }
+        #[cfg(feature = "orchard")]
+        if let Some(txid) = self.orchard.conflicting_txid(&pending.orchard) {
+            return Err(RestoreError::TransactionPending(txid));
+        }
+
+        self.sapling
+            .restore(pending.sapling)
+            .map_err(RestoreError::TransactionPending)?;
+        #[cfg(feature = "orchard")]
+        self.orchard
+            .restore(pending.orchard)
+            .map_err(RestoreError::TransactionPending)?;
+
+        Ok(())
+    }
 }
 
-#[tracing::instrument(skip_all, fields(height = block.height))]
-pub(crate) fn scan_block_with_runners<P, AccountId, IvkTag, TS, TO>(
+/// Returns the total number of Sapling outputs and Orchard actions in the given block.
+fn compact_output_count(block: &CompactBlock) -> usize {
+    block
+        .vtx
+        .iter()
+        .map(|tx| tx.outputs.len() + tx.actions.len())
+        .sum()
+}
+
+/// Scans a [`CompactBlock`] with a set of [`ScanningKeys`], collecting the results of trial
+/// decryption from the given [`BatchRunners`] if provided.
+///
+/// This behaves identically to [`scan_block`], except that if `batch_runners` is provided, the
+/// block must previously have been added to them with [`BatchRunners::add_block`] (or restored
+/// into them with [`BatchRunners::restore`]), and the results of its trial decryption are
+/// collected from them rather than by decrypting the block's outputs on the calling thread.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        height = block.height,
+        txs = block.vtx.len(),
+        outputs = compact_output_count(&block),
+    )
+)]
+pub fn scan_block_with_runners<P, AccountId, IvkTag>(
     params: &P,
     block: CompactBlock,
     scanning_keys: &ScanningKeys<AccountId, IvkTag>,
     nullifiers: &Nullifiers<AccountId>,
     prior_block_metadata: Option<&BlockMetadata>,
-    mut batch_runners: Option<&mut BatchRunners<IvkTag, TS, TO>>,
+    mut batch_runners: Option<&mut BatchRunners<IvkTag>>,
 ) -> Result<ScannedBlock<AccountId>, ScanError>
 where
     P: consensus::Parameters + Send + 'static,
     AccountId: Default + Eq + Hash + ConditionallySelectable + Send + 'static,
     IvkTag: Copy + std::hash::Hash + Eq + Send + 'static,
-    TS: SaplingTasks<IvkTag> + Sync,
-    TO: OrchardTasks<IvkTag> + Sync,
 {
     fn check_hash_continuity(
         block: &CompactBlock,
@@ -869,14 +1223,7 @@ where
                 .map(|(i, output)| {
                     Ok((
                         SaplingDomain::new(zip212_enforcement),
-                        CompactOutputDescription::try_from(output).map_err(|_| {
-                            ScanError::EncodingInvalid {
-                                at_height: cur_height,
-                                txid,
-                                pool_type: ShieldedProtocol::Sapling,
-                                index: i,
-                            }
-                        })?,
+                        parse_compact_output(output, cur_height, txid, i)?,
                     ))
                 })
                 .collect::<Result<Vec<_>, _>>()?,
@@ -901,14 +1248,7 @@ where
                 .iter()
                 .enumerate()
                 .map(|(i, action)| {
-                    let action = CompactAction::try_from(action).map_err(|_| {
-                        ScanError::EncodingInvalid {
-                            at_height: cur_height,
-                            txid,
-                            pool_type: ShieldedProtocol::Orchard,
-                            index: i,
-                        }
-                    })?;
+                    let action = parse_compact_action(action, cur_height, txid, i)?;
                     Ok((OrchardDomain::for_compact_action(&action), action))
                 })
                 .collect::<Result<Vec<_>, _>>()?,
@@ -1100,6 +1440,23 @@ fn find_received<
     {
         // Collect block note commitments
         let node = extract_note_commitment(output);
+
+        // Trial decryption should only succeed for notes that are consistent with the
+        // output's note commitment, but as a defense against malformed data from an
+        // untrusted server, we recompute the commitment and discard the note if it does not
+        // match rather than recording a note that could never be spent.
+        let decrypted_note = decrypted_note.filter(|(_, note)| {
+            let cmstar_bytes: D::ExtractedCommitmentBytes = (&D::cmstar(note)).into();
+            let is_valid = cmstar_bytes == output.cmstar_bytes();
+            if !is_valid {
+                warn!(
+                    "Ignoring note decrypted from output {} of transaction {} at height {}: its note commitment does not match the output",
+                    output_idx, txid, block_height,
+                );
+            }
+            is_valid
+        });
+
         // If the commitment is the last in the block, ensure that is retained as a checkpoint
         let is_checkpoint = output_idx + 1 == decoded.len() && last_commitments_in_block;
         let retention = match (decrypted_note.is_some(), is_checkpoint) {
@@ -1148,6 +1505,8 @@ fn find_received<
 
 #[cfg(any(test, feature = "test-dependencies"))]
 pub mod testing {
+    use std::hash::Hash;
+
     use group::{
         ff::{Field, PrimeField},
         GroupEncoding,
@@ -1161,16 +1520,24 @@ pub mod testing {
         zip32::DiversifiableFullViewingKey,
         Nullifier,
     };
+    use subtle::ConditionallySelectable;
     use zcash_note_encryption::{Domain, COMPACT_NOTE_SIZE};
     use zcash_primitives::{
         block::BlockHash,
-        consensus::{BlockHeight, Network},
+        consensus::{self, BlockHeight, Network},
         memo::MemoBytes,
         transaction::components::{amount::NonNegativeAmount, sapling::zip212_enforcement},
     };
 
-    use crate::proto::compact_formats::{
-        self as compact, CompactBlock, CompactSaplingOutput, CompactSaplingSpend, CompactTx,
+    use crate::{
+        data_api::{BlockMetadata, ScannedBlock},
+        proto::compact_formats::{
+            self as compact, CompactBlock, CompactSaplingOutput, CompactSaplingSpend, CompactTx,
+        },
+    };
+
+    use super::{
+        scan_block_with_runners, BatchOptions, BatchRunners, Nullifiers, ScanError, ScanningKeys,
     };
 
     fn random_compact_tx(mut rng: impl RngCore) -> CompactTx {
@@ -1290,29 +1657,75 @@ pub mod testing {
 
         cb
     }
+
+    /// Scans a [`CompactBlock`] with a set of [`ScanningKeys`], performing trial decryption
+    /// in batches on the global thread pool in the same way as
+    /// [`scan_cached_blocks`](crate::data_api::chain::scan_cached_blocks).
+    pub fn scan_block_batched<P, AccountId, IvkTag>(
+        params: &P,
+        block: CompactBlock,
+        scanning_keys: &ScanningKeys<AccountId, IvkTag>,
+        nullifiers: &Nullifiers<AccountId>,
+        prior_block_metadata: Option<&BlockMetadata>,
+    ) -> Result<ScannedBlock<AccountId>, ScanError>
+    where
+        P: consensus::Parameters + Send + 'static,
+        AccountId: Default + Eq + Hash + ConditionallySelectable + Send + 'static,
+        IvkTag: Copy + Hash + Eq + Send + 'static,
+    {
+        let mut runners = BatchRunners::for_keys(&BatchOptions::default(), scanning_keys);
+        runners.add_block(params, block.clone())?;
+        runners.flush();
+
+        scan_block_with_runners(
+            params,
+            block,
+            scanning_keys,
+            nullifiers,
+            prior_block_metadata,
+            Some(&mut runners),
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashMap;
     use std::convert::Infallible;
+    use std::num::NonZeroUsize;
 
+    use assert_matches::assert_matches;
     use incrementalmerkletree::{Position, Retention};
-    use sapling::Nullifier;
+    use sapling::{
+        note_encryption::{CompactOutputDescription, SaplingDomain},
+        Nullifier,
+    };
     use zcash_keys::keys::UnifiedSpendingKey;
     use zcash_primitives::{
         block::BlockHash,
         consensus::{BlockHeight, Network},
-        transaction::components::amount::NonNegativeAmount,
+        transaction::components::{amount::NonNegativeAmount, sapling::zip212_enforcement},
         zip32::AccountId,
     };
+    use zip32::Scope;
 
     use crate::{
         data_api::BlockMetadata,
-        scanning::{BatchRunners, ScanningKeys},
+        proto::compact_formats::CompactBlock,
+        scan::{BatchRunner, CompactDecryptor},
+        scanning::{
+            BatchOptions, BatchOptionsError, BatchRunners, RestoreError, ScanError, ScanningKeyOps,
+            ScanningKeys,
+        },
+        ShieldedProtocol,
     };
 
-    use super::{scan_block, scan_block_with_runners, testing::fake_compact_block, Nullifiers};
+    use super::{
+        parse_compact_output, scan_block, scan_block_with_runners,
+        testing::{fake_compact_block, scan_block_batched},
+        Nullifiers,
+    };
 
     #[test]
     fn scan_block_with_my_tx() {
@@ -1337,7 +1750,7 @@ mod tests {
             assert_eq!(cb.vtx.len(), 2);
 
             let mut batch_runners = if scan_multithreaded {
-                let mut runners = BatchRunners::<_, (), ()>::for_keys(10, &scanning_keys);
+                let mut runners = BatchRunners::for_keys(&BatchOptions::new(10), &scanning_keys);
                 runners
                     .add_block(&Network::TestNetwork, cb.clone())
                     .unwrap();
@@ -1423,7 +1836,7 @@ mod tests {
             assert_eq!(cb.vtx.len(), 3);
 
             let mut batch_runners = if scan_multithreaded {
-                let mut runners = BatchRunners::<_, (), ()>::for_keys(10, &scanning_keys);
+                let mut runners = BatchRunners::for_keys(&BatchOptions::new(10), &scanning_keys);
                 runners
                     .add_block(&Network::TestNetwork, cb.clone())
                     .unwrap();
@@ -1530,4 +1943,467 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn scan_block_with_tampered_cmu() {
+        fn go(scan_multithreaded: bool) {
+            let network = Network::TestNetwork;
+            let account = AccountId::ZERO;
+            let usk =
+                UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+            let ufvk = usk.to_unified_full_viewing_key();
+            let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+            let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
+
+            let mut cb = fake_compact_block(
+                1u32.into(),
+                BlockHash([0; 32]),
+                Nullifier([0; 32]),
+                &sapling_dfvk,
+                NonNegativeAmount::const_from_u64(5),
+                false,
+                Some((0, 0)),
+            );
+            assert_eq!(cb.vtx.len(), 2);
+
+            // Replace the note commitment of our output with the (valid) commitment of the
+            // random output that precedes it.
+            cb.vtx[1].outputs[0].cmu = cb.vtx[0].outputs[0].cmu.clone();
+
+            let scanned_block = if scan_multithreaded {
+                scan_block_batched(&network, cb, &scanning_keys, &Nullifiers::empty(), None)
+            } else {
+                scan_block(&network, cb, &scanning_keys, &Nullifiers::empty(), None)
+            }
+            .unwrap();
+
+            // The note is not recorded, and its commitment is not marked for witnessing.
+            assert!(scanned_block.transactions().is_empty());
+            assert_eq!(
+                scanned_block
+                    .sapling()
+                    .commitments()
+                    .iter()
+                    .map(|(_, retention)| *retention)
+                    .collect::<Vec<_>>(),
+                vec![
+                    Retention::Ephemeral,
+                    Retention::Checkpoint {
+                        id: scanned_block.height(),
+                        is_marked: false
+                    }
+                ]
+            );
+        }
+
+        go(false);
+        go(true);
+    }
+
+    #[test]
+    fn scan_block_with_truncated_ciphertext() {
+        fn go(scan_multithreaded: bool) {
+            let network = Network::TestNetwork;
+            let account = AccountId::ZERO;
+            let usk =
+                UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+            let ufvk = usk.to_unified_full_viewing_key();
+            let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+            let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
+
+            let mut cb = fake_compact_block(
+                1u32.into(),
+                BlockHash([0; 32]),
+                Nullifier([0; 32]),
+                &sapling_dfvk,
+                NonNegativeAmount::const_from_u64(5),
+                false,
+                Some((0, 0)),
+            );
+            assert_eq!(cb.vtx.len(), 2);
+            let txid = cb.vtx[1].txid();
+
+            // Truncate the ciphertext of our output by one byte.
+            cb.vtx[1].outputs[0].ciphertext.pop();
+
+            let result = if scan_multithreaded {
+                scan_block_batched(&network, cb, &scanning_keys, &Nullifiers::empty(), None)
+            } else {
+                scan_block(&network, cb, &scanning_keys, &Nullifiers::empty(), None)
+            };
+
+            assert_matches!(
+                result,
+                Err(ScanError::CiphertextLengthInvalid {
+                    at_height,
+                    txid: t,
+                    pool_type: ShieldedProtocol::Sapling,
+                    index: 0,
+                    length: 51,
+                }) if at_height == BlockHeight::from(1u32) && t == txid
+            );
+        }
+
+        go(false);
+        go(true);
+    }
+
+    #[test]
+    fn scan_block_with_single_ivk() {
+        fn go(single_ivk_fast_path: bool) {
+            let network = Network::TestNetwork;
+            let account = AccountId::ZERO;
+            let usk =
+                UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+            let ufvk = usk.to_unified_full_viewing_key();
+            let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+
+            // Scan with only the external Sapling IVK of the account.
+            let sapling_key: Box<dyn ScanningKeyOps<SaplingDomain, AccountId, Nullifier>> =
+                Box::new((account, sapling_dfvk.to_ivk(Scope::External)));
+            let scanning_keys = ScanningKeys::new(
+                HashMap::from([(account, sapling_key)]),
+                #[cfg(feature = "orchard")]
+                HashMap::new(),
+            );
+
+            let cb = fake_compact_block(
+                1u32.into(),
+                BlockHash([0; 32]),
+                Nullifier([0; 32]),
+                &sapling_dfvk,
+                NonNegativeAmount::const_from_u64(5),
+                true,
+                Some((0, 0)),
+            );
+            assert_eq!(cb.vtx.len(), 3);
+
+            let mut runners = BatchRunners::for_keys(&BatchOptions::default(), &scanning_keys)
+                .with_single_ivk_fast_path(single_ivk_fast_path);
+            runners.add_block(&network, cb.clone()).unwrap();
+            runners.flush();
+            let scanned_block = scan_block_with_runners(
+                &network,
+                cb,
+                &scanning_keys,
+                &Nullifiers::empty(),
+                None,
+                Some(&mut runners),
+            )
+            .unwrap();
+            let txs = scanned_block.transactions();
+            assert_eq!(txs.len(), 1);
+
+            let tx = &txs[0];
+            assert_eq!(tx.block_index(), 1);
+            assert_eq!(tx.sapling_outputs().len(), 1);
+            assert_eq!(tx.sapling_outputs()[0].index(), 0);
+            assert_eq!(tx.sapling_outputs()[0].account_id(), &account);
+            assert_eq!(tx.sapling_outputs()[0].note().value().inner(), 5);
+            assert_eq!(
+                tx.sapling_outputs()[0].note_commitment_tree_position(),
+                Position::from(1)
+            );
+        }
+
+        go(false);
+        go(true);
+    }
+
+    #[test]
+    fn batch_runner_returns_output_context() {
+        let network = Network::TestNetwork;
+        let account = AccountId::ZERO;
+        let usk = UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+        let ufvk = usk.to_unified_full_viewing_key();
+        let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+        let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
+
+        let cb = fake_compact_block(
+            1u32.into(),
+            BlockHash([0; 32]),
+            Nullifier([0; 32]),
+            &sapling_dfvk,
+            NonNegativeAmount::const_from_u64(5),
+            true,
+            None,
+        );
+        assert_eq!(cb.vtx.len(), 3);
+        let block_hash = cb.hash();
+        let block_height = cb.height();
+        let zip212_enforcement = zip212_enforcement(&network, block_height);
+
+        // Tag each output with the index of its transaction within the block.
+        let mut runner = BatchRunner::<
+            _,
+            SaplingDomain,
+            CompactOutputDescription,
+            CompactDecryptor,
+            (),
+            u64,
+        >::new(
+            10,
+            scanning_keys
+                .sapling()
+                .iter()
+                .map(|(id, key)| (*id, key.prepare())),
+        );
+        for tx in &cb.vtx {
+            let txid = tx.txid();
+            let outputs = tx
+                .outputs
+                .iter()
+                .enumerate()
+                .map(|(i, output)| {
+                    (
+                        parse_compact_output(output, block_height, txid, i).unwrap(),
+                        tx.index,
+                    )
+                })
+                .collect::<Vec<_>>();
+            runner.add_outputs(
+                block_hash,
+                txid,
+                |_| SaplingDomain::new(zip212_enforcement),
+                &outputs,
+            );
+        }
+        runner.flush();
+
+        let txid = cb.vtx[1].txid();
+        let results = runner.collect_results(block_hash, txid);
+        assert_eq!(results.len(), 1);
+        let decrypted = &results[&(txid, 0)];
+        assert_eq!(decrypted.ctx, 1);
+        assert_eq!(decrypted.note.value().inner(), 5);
+    }
+
+    #[test]
+    fn batch_runner_decrypts_in_chunks() {
+        fn go(single_ivk_fast_path: bool) {
+            let network = Network::TestNetwork;
+            let account = AccountId::ZERO;
+            let usk =
+                UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+            let ufvk = usk.to_unified_full_viewing_key();
+            let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+            let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
+
+            let cb = fake_compact_block(
+                1u32.into(),
+                BlockHash([0; 32]),
+                Nullifier([0; 32]),
+                &sapling_dfvk,
+                NonNegativeAmount::const_from_u64(5),
+                true,
+                None,
+            );
+            assert_eq!(cb.vtx.len(), 3);
+            let block_hash = cb.hash();
+            let block_height = cb.height();
+            let zip212_enforcement = zip212_enforcement(&network, block_height);
+
+            // Every output of the block is accumulated into a single batch, which is then
+            // decrypted one output at a time.
+            let mut runner = BatchRunner::<
+                _,
+                SaplingDomain,
+                CompactOutputDescription,
+                CompactDecryptor,
+                (),
+            >::new(
+                10,
+                scanning_keys
+                    .sapling()
+                    .iter()
+                    .map(|(id, key)| (*id, key.prepare())),
+            )
+            .with_single_ivk_fast_path(single_ivk_fast_path)
+            .with_max_batch_chunk(NonZeroUsize::new(1).unwrap());
+            for tx in &cb.vtx {
+                let txid = tx.txid();
+                let outputs = tx
+                    .outputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, output)| {
+                        (
+                            parse_compact_output(output, block_height, txid, i).unwrap(),
+                            (),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                runner.add_outputs(
+                    block_hash,
+                    txid,
+                    |_| SaplingDomain::new(zip212_enforcement),
+                    &outputs,
+                );
+            }
+            runner.flush();
+
+            for (i, tx) in cb.vtx.iter().enumerate() {
+                let txid = tx.txid();
+                let results = runner.collect_results(block_hash, txid);
+                if i == 1 {
+                    assert_eq!(results.len(), 1);
+                    assert_eq!(results[&(txid, 0)].note.value().inner(), 5);
+                } else {
+                    assert!(results.is_empty());
+                }
+            }
+        }
+
+        go(false);
+        go(true);
+    }
+
+    #[test]
+    fn batch_runner_pending_outputs_can_be_restored() {
+        let network = Network::TestNetwork;
+        let account = AccountId::ZERO;
+        let usk = UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+        let ufvk = usk.to_unified_full_viewing_key();
+        let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+        let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
+        let new_runner = || {
+            BatchRunner::<_, SaplingDomain, CompactOutputDescription, CompactDecryptor, (), u64>::new(
+                10,
+                scanning_keys
+                    .sapling()
+                    .iter()
+                    .map(|(id, key)| (*id, key.prepare())),
+            )
+        };
+
+        let value = NonNegativeAmount::const_from_u64(5);
+        let blocks = [1u32, 2].map(|height| {
+            fake_compact_block(
+                height.into(),
+                BlockHash([0; 32]),
+                Nullifier([0; 32]),
+                &sapling_dfvk,
+                value,
+                false,
+                None,
+            )
+        });
+
+        let add_block = |runner: &mut BatchRunner<_, _, _, _, (), u64>, cb: &CompactBlock| {
+            let zip212_enforcement = zip212_enforcement(&network, cb.height());
+            for tx in &cb.vtx {
+                let txid = tx.txid();
+                let outputs = tx
+                    .outputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, output)| {
+                        (
+                            parse_compact_output(output, cb.height(), txid, i).unwrap(),
+                            u64::from(cb.height()),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                runner.add_outputs(
+                    cb.hash(),
+                    txid,
+                    |_| SaplingDomain::new(zip212_enforcement),
+                    &outputs,
+                );
+            }
+        };
+
+        // The first block's outputs are flushed, and so are running when they are handed off;
+        // the second block's outputs have only been accumulated.
+        let mut runner = new_runner();
+        add_block(&mut runner, &blocks[0]);
+        runner.flush();
+        add_block(&mut runner, &blocks[1]);
+        let pending = runner.take_pending();
+
+        // Nothing remains to be collected from the original runner.
+        let txid = blocks[0].vtx[1].txid();
+        assert!(runner.collect_results(blocks[0].hash(), txid).is_empty());
+
+        let mut restored = new_runner();
+        restored.restore(pending).unwrap();
+        for cb in &blocks {
+            let txid = cb.vtx[1].txid();
+            let results = restored.collect_results(cb.hash(), txid);
+            assert_eq!(results.len(), 1);
+            let decrypted = &results[&(txid, 0)];
+            assert_eq!(decrypted.ctx, u64::from(cb.height()));
+            assert_eq!(decrypted.note.value().inner(), 5);
+        }
+    }
+
+    #[test]
+    fn batch_options_reject_invalid_adaptive_bounds() {
+        let min = NonZeroUsize::new(64).unwrap();
+        let max = NonZeroUsize::new(16).unwrap();
+        assert_eq!(
+            BatchOptions::default().with_adaptive_threshold(min, max),
+            Err(BatchOptionsError::AdaptiveThresholdBoundsInvalid { min, max })
+        );
+
+        let options = BatchOptions::new(10)
+            .with_adaptive_threshold(max, min)
+            .unwrap()
+            .with_max_batch_chunk(NonZeroUsize::new(4).unwrap())
+            .with_memory_budget(1 << 20);
+        assert_eq!(options.batch_size_threshold(), 10);
+        assert_eq!(options.adaptive_threshold(), Some((max, min)));
+        assert_eq!(options.max_batch_chunk(), NonZeroUsize::new(4));
+        assert_eq!(options.memory_budget(), Some(1 << 20));
+    }
+
+    #[test]
+    fn batch_runners_reject_duplicate_pending_outputs() {
+        let network = Network::TestNetwork;
+        let account = AccountId::ZERO;
+        let usk = UnifiedSpendingKey::from_seed(&network, &[0u8; 32], account).expect("Valid USK");
+        let ufvk = usk.to_unified_full_viewing_key();
+        let sapling_dfvk = ufvk.sapling().expect("Sapling key is present").clone();
+        let scanning_keys = ScanningKeys::from_account_ufvks([(account, ufvk)]);
+
+        let cb = fake_compact_block(
+            1u32.into(),
+            BlockHash([0; 32]),
+            Nullifier([0; 32]),
+            &sapling_dfvk,
+            NonNegativeAmount::const_from_u64(5),
+            false,
+            None,
+        );
+        let options = BatchOptions::new(10).with_memory_budget(1 << 20);
+
+        // Both runners have results pending for the same transactions.
+        let mut runners = BatchRunners::for_keys(&options, &scanning_keys);
+        runners.add_block(&network, cb.clone()).unwrap();
+        let mut other = BatchRunners::for_keys(&options, &scanning_keys);
+        other.add_block(&network, cb.clone()).unwrap();
+        other.flush();
+
+        let pending = runners.take_pending();
+        assert_matches!(
+            other.restore(pending),
+            Err(RestoreError::TransactionPending(txid))
+                if cb.vtx.iter().any(|tx| tx.txid() == txid)
+        );
+
+        // The runners that rejected the pending outputs are unaffected.
+        let scanned_block = scan_block_with_runners(
+            &network,
+            cb,
+            &scanning_keys,
+            &Nullifiers::empty(),
+            None,
+            Some(&mut other),
+        )
+        .unwrap();
+        let txs = scanned_block.transactions();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].sapling_outputs().len(), 1);
+        assert_eq!(txs[0].sapling_outputs()[0].note().value().inner(), 5);
+    }
 }
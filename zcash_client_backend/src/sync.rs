@@ -19,9 +19,9 @@ use tonic::{
     client::GrpcService,
     codegen::{Body, Bytes, StdError},
 };
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use zcash_primitives::{
-    consensus::{BlockHeight, Parameters},
+    consensus::{BlockHeight, NetworkUpgrade, Parameters},
     merkle_tree::HashSer,
 };
 
@@ -41,13 +41,58 @@ use crate::{
 #[cfg(feature = "orchard")]
 use orchard::tree::MerkleHashOrchard;
 
+/// Options that control the behaviour of [`run`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncOptions {
+    batch_size: u32,
+    max_reorg_depth: Option<u32>,
+}
+
+impl SyncOptions {
+    /// Constructs options for a sync that downloads and scans at most `batch_size` blocks at a
+    /// time.
+    pub fn new(batch_size: u32) -> Self {
+        SyncOptions {
+            batch_size,
+            max_reorg_depth: None,
+        }
+    }
+
+    /// Sets the maximum depth of a chain reorg that the wallet will attempt to recover from by
+    /// rewinding, measured from the start of the scan range in which the reorg was detected.
+    ///
+    /// By default, this is the number of note commitment tree checkpoints retained by the
+    /// wallet, as reported by [`WalletCommitmentTrees::checkpoint_retention`]; as the wallet
+    /// cannot be rewound any further than that, a larger value has no effect.
+    pub fn with_max_reorg_depth(mut self, max_reorg_depth: u32) -> Self {
+        self.max_reorg_depth = Some(max_reorg_depth);
+        self
+    }
+
+    /// Returns the maximum number of blocks to download and scan at a time.
+    pub fn batch_size(&self) -> u32 {
+        self.batch_size
+    }
+
+    /// Returns the maximum depth of a chain reorg that the wallet will attempt to recover from
+    /// by rewinding, if it has been set.
+    pub fn max_reorg_depth(&self) -> Option<u32> {
+        self.max_reorg_depth
+    }
+}
+
 /// Scans the chain until the wallet is up-to-date.
+///
+/// If a chain reorg is detected that is deeper than the maximum reorg depth given by
+/// [`SyncOptions::max_reorg_depth`] (or the wallet's checkpoint retention, whichever is
+/// smaller), the wallet cannot recover from it by rewinding, and [`Error::RescanRequired`] is
+/// returned.
 pub async fn run<P, ChT, CaT, DbT>(
     client: &mut CompactTxStreamerClient<ChT>,
     params: &P,
     db_cache: &CaT,
     db_data: &mut DbT,
-    batch_size: u32,
+    options: &SyncOptions,
 ) -> Result<(), Error<CaT::Error, <DbT as WalletRead>::Error, <DbT as WalletCommitmentTrees>::Error>>
 where
     P: Parameters + Send + 'static,
@@ -66,7 +111,25 @@ where
     // 2) Pass the commitment tree data to the database.
     update_subtree_roots(client, db_data).await?;
 
-    while running(client, params, db_cache, db_data, batch_size).await? {}
+    let checkpoint_retention = u32::try_from(db_data.checkpoint_retention()).unwrap_or(u32::MAX);
+    let max_reorg_depth = options
+        .max_reorg_depth()
+        .map_or(checkpoint_retention, |depth| {
+            depth.min(checkpoint_retention)
+        });
+
+    let mut reorg_recovery = ReorgRecovery::default();
+    while running(
+        client,
+        params,
+        db_cache,
+        db_data,
+        options.batch_size(),
+        max_reorg_depth,
+        &mut reorg_recovery,
+    )
+    .await?
+    {}
 
     Ok(())
 }
@@ -77,6 +140,8 @@ async fn running<P, ChT, CaT, DbT, TrErr>(
     db_cache: &CaT,
     db_data: &mut DbT,
     batch_size: u32,
+    max_reorg_depth: u32,
+    reorg_recovery: &mut ReorgRecovery,
 ) -> Result<bool, Error<CaT::Error, <DbT as WalletRead>::Error, TrErr>>
 where
     P: Parameters + Send + 'static,
@@ -118,8 +183,16 @@ where
                 // Scan the downloaded blocks and check for scanning errors that
                 // indicate the wallet's chain tip is out of sync with blockchain
                 // history.
-                let scan_ranges_updated =
-                    scan_blocks(params, db_cache, db_data, &chain_state, scan_range).await?;
+                let scan_ranges_updated = scan_blocks(
+                    params,
+                    db_cache,
+                    db_data,
+                    &chain_state,
+                    scan_range,
+                    max_reorg_depth,
+                    reorg_recovery,
+                )
+                .await?;
 
                 // Delete the now-scanned blocks, because keeping the entire chain
                 // in CompactBlock files on disk is horrendous for the filesystem.
@@ -170,8 +243,16 @@ where
         let chain_state = download_chain_state(client, scan_range.block_range().start - 1).await?;
 
         // Scan the downloaded blocks.
-        let scan_ranges_updated =
-            scan_blocks(params, db_cache, db_data, &chain_state, &scan_range).await?;
+        let scan_ranges_updated = scan_blocks(
+            params,
+            db_cache,
+            db_data,
+            &chain_state,
+            &scan_range,
+            max_reorg_depth,
+            reorg_recovery,
+        )
+        .await?;
 
         // Delete the now-scanned blocks.
         block_deletions.push(db_cache.delete(scan_range));
@@ -357,6 +438,8 @@ async fn scan_blocks<P, CaT, DbT, TrErr>(
     db_data: &mut DbT,
     initial_chain_state: &ChainState,
     scan_range: &ScanRange,
+    max_reorg_depth: u32,
+    reorg_recovery: &mut ReorgRecovery,
 ) -> Result<bool, Error<CaT::Error, <DbT as WalletRead>::Error, TrErr>>
 where
     P: Parameters + Send + 'static,
@@ -374,15 +457,36 @@ where
         scan_range.block_range().start,
         initial_chain_state,
         scan_range.len(),
+        None,
     );
 
     match scan_result {
         Err(ChainError::Scan(err)) if err.is_continuity_error() => {
-            // Pick a height to rewind to, which must be at least one block before the
-            // height at which the error occurred, but may be an earlier height determined
-            // based on heuristics such as the platform, available bandwidth, size of
-            // recent CompactBlocks, etc.
-            let rewind_height = err.at_height().saturating_sub(10);
+            // The wallet cannot be truncated to below its oldest retained checkpoint, so if
+            // the reorg is too deep the only way to recover is to rescan from the wallet's
+            // birthday.
+            let rewind_height =
+                match reorg_recovery.rewind_height(scan_range, err.at_height(), max_reorg_depth) {
+                    Some(rewind_height) => rewind_height,
+                    None => {
+                        warn!(
+                            "Chain reorg detected at {} exceeds the maximum reorg depth of {}",
+                            err.at_height(),
+                            max_reorg_depth,
+                        );
+                        // A wallet without a birthday has no accounts, and so can be rescanned
+                        // from any height.
+                        let from_height =
+                            match db_data.get_wallet_birthday().map_err(Error::Wallet)? {
+                                Some(birthday) => birthday,
+                                None => params
+                                    .activation_height(NetworkUpgrade::Sapling)
+                                    .expect("Sapling activation height must be known."),
+                            };
+                        return Err(Error::RescanRequired { from_height });
+                    }
+                };
+
             info!(
                 "Chain reorg detected at {}, rewinding to {}",
                 err.at_height(),
@@ -408,6 +512,8 @@ where
             Ok(true)
         }
         Ok(_) => {
+            reorg_recovery.scan_succeeded();
+
             // If scanning these blocks caused a suggested range to be added that has a
             // higher priority than the current range, invalidate the current ranges.
             let latest_ranges = db_data.suggest_scan_ranges().map_err(Error::Wallet)?;
@@ -422,6 +528,48 @@ where
     }
 }
 
+/// The number of blocks below the height of a detected chain reorg to which the wallet is
+/// rewound.
+const REORG_REWIND_DEPTH: u32 = 10;
+
+/// The state of recovery from a chain reorg, which may require the wallet to be rewound
+/// repeatedly until the fork point is found.
+#[derive(Debug, Default)]
+struct ReorgRecovery {
+    /// The start of the scan range in which the reorg was first detected.
+    detected_at: Option<BlockHeight>,
+}
+
+impl ReorgRecovery {
+    /// Returns the height to which the wallet should be rewound in response to a continuity
+    /// error at `error_height` while scanning `failed_range`, or `None` if this would rewind the
+    /// wallet more than `max_reorg_depth` blocks below the start of the scan range in which the
+    /// reorg was first detected.
+    fn rewind_height(
+        &mut self,
+        failed_range: &ScanRange,
+        error_height: BlockHeight,
+        max_reorg_depth: u32,
+    ) -> Option<BlockHeight> {
+        // Pick a height to rewind to, which must be at least one block before the height at
+        // which the error occurred, but may be an earlier height determined based on heuristics
+        // such as the platform, available bandwidth, size of recent CompactBlocks, etc.
+        let rewind_height = error_height.saturating_sub(REORG_REWIND_DEPTH);
+        let detected_at = *self
+            .detected_at
+            .get_or_insert(failed_range.block_range().start);
+
+        (u64::from(rewind_height) + u64::from(max_reorg_depth) >= u64::from(detected_at))
+            .then_some(rewind_height)
+    }
+
+    /// Records that a range of blocks was scanned successfully, completing recovery from any
+    /// reorg.
+    fn scan_succeeded(&mut self) {
+        self.detected_at = None;
+    }
+}
+
 /// Errors that can occur while syncing.
 #[derive(Debug)]
 pub enum Error<CaErr, DbErr, TrErr> {
@@ -429,6 +577,10 @@ pub enum Error<CaErr, DbErr, TrErr> {
     Cache(CaErr),
     /// The lightwalletd server returned invalid information, and is misbehaving.
     MisbehavingServer,
+    /// A chain reorg was detected that is deeper than the configured maximum reorg depth,
+    /// and so cannot be recovered from by truncating the wallet. The wallet must be
+    /// rescanned from the given height.
+    RescanRequired { from_height: BlockHeight },
     /// An error while scanning blocks.
     Scan(ScanError),
     /// An error while communicating with the lightwalletd server.
@@ -450,6 +602,11 @@ where
         match self {
             Error::Cache(e) => write!(f, "Error while interacting with block cache: {}", e),
             Error::MisbehavingServer => write!(f, "lightwalletd server is misbehaving"),
+            Error::RescanRequired { from_height } => write!(
+                f,
+                "A chain reorg exceeded the maximum reorg depth; the wallet must be rescanned from height {}",
+                from_height
+            ),
             Error::Scan(e) => write!(f, "Error while scanning blocks: {}", e),
             Error::Server(e) => write!(
                 f,
@@ -489,3 +646,84 @@ impl<CaErr, DbErr, TrErr> From<tonic::Status> for Error<CaErr, DbErr, TrErr> {
         Error::Server(status)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use zcash_primitives::consensus::BlockHeight;
+
+    use super::ReorgRecovery;
+    use crate::data_api::scanning::{ScanPriority, ScanRange};
+
+    /// Simulates recovery from a reorg of a wallet that has scanned up to height 1000, where
+    /// the last block shared with the new chain is at `fork_height`. Each rewind is followed by
+    /// a scan of the blocks above the rewind height, which fails with a continuity error at the
+    /// start of the range until the wallet has been rewound to at or below the fork point.
+    ///
+    /// Returns the height to which the wallet was rewound, or `None` if a rescan is required.
+    fn recover_from_reorg(fork_height: u32, max_reorg_depth: u32) -> Option<BlockHeight> {
+        let fork_height = BlockHeight::from_u32(fork_height);
+        let mut recovery = ReorgRecovery::default();
+        let mut range_start = BlockHeight::from_u32(1001);
+        while range_start - 1 > fork_height {
+            let failed_range =
+                ScanRange::from_parts(range_start..range_start + 10, ScanPriority::Verify);
+            range_start = recovery.rewind_height(&failed_range, range_start, max_reorg_depth)? + 1;
+        }
+        recovery.scan_succeeded();
+
+        Some(range_start - 1)
+    }
+
+    #[test]
+    fn deep_reorg_requires_rescan() {
+        // A reorg within the maximum reorg depth is recovered from by rewinding, even when it
+        // takes several rewinds to reach the fork point.
+        assert_eq!(
+            recover_from_reorg(995, 100),
+            Some(BlockHeight::from_u32(991))
+        );
+        assert_eq!(
+            recover_from_reorg(905, 100),
+            Some(BlockHeight::from_u32(901))
+        );
+
+        // A reorg deeper than the maximum reorg depth requires a rescan.
+        assert_eq!(recover_from_reorg(850, 100), None);
+        assert_eq!(
+            recover_from_reorg(850, 200),
+            Some(BlockHeight::from_u32(847))
+        );
+
+        // The maximum reorg depth may be large enough to permit rewinding to any height.
+        assert_eq!(
+            recover_from_reorg(850, u32::MAX),
+            Some(BlockHeight::from_u32(847))
+        );
+    }
+
+    #[test]
+    fn reorg_depth_is_measured_from_failed_range() {
+        // A reorg detected in a range far below the wallet's scanned tip (as can happen when
+        // scanning out of order) is measured from that range.
+        let mut recovery = ReorgRecovery::default();
+        let failed_range = ScanRange::from_parts(
+            BlockHeight::from_u32(500)..BlockHeight::from_u32(600),
+            ScanPriority::Historic,
+        );
+        assert_eq!(
+            recovery.rewind_height(&failed_range, BlockHeight::from_u32(500), 100),
+            Some(BlockHeight::from_u32(490))
+        );
+
+        // Once a scan succeeds, a subsequent reorg is measured afresh.
+        recovery.scan_succeeded();
+        let failed_range = ScanRange::from_parts(
+            BlockHeight::from_u32(300)..BlockHeight::from_u32(310),
+            ScanPriority::Verify,
+        );
+        assert_eq!(
+            recovery.rewind_height(&failed_range, BlockHeight::from_u32(300), 100),
+            Some(BlockHeight::from_u32(290))
+        );
+    }
+}
@@ -187,12 +187,17 @@ impl<AccountId> WalletTx<AccountId> {
     }
 }
 
+/// The number of confirmations required before the transparent outputs of a coinbase
+/// transaction may be spent.
+pub const COINBASE_MATURITY: u32 = 100;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WalletTransparentOutput {
     outpoint: OutPoint,
     txout: TxOut,
     height: BlockHeight,
     recipient_address: TransparentAddress,
+    is_coinbase: bool,
 }
 
 impl WalletTransparentOutput {
@@ -200,6 +205,7 @@ impl WalletTransparentOutput {
         outpoint: OutPoint,
         txout: TxOut,
         height: BlockHeight,
+        is_coinbase: bool,
     ) -> Option<WalletTransparentOutput> {
         txout
             .recipient_address()
@@ -208,6 +214,7 @@ impl WalletTransparentOutput {
                 txout,
                 height,
                 recipient_address,
+                is_coinbase,
             })
     }
 
@@ -230,6 +237,32 @@ impl WalletTransparentOutput {
     pub fn value(&self) -> NonNegativeAmount {
         self.txout.value
     }
+
+    /// Returns whether this output was created by a coinbase transaction.
+    ///
+    /// Coinbase outputs may only be spent once they have matured, and only in transactions
+    /// that have no transparent outputs.
+    pub fn is_coinbase(&self) -> bool {
+        self.is_coinbase
+    }
+
+    /// Returns the height of the first block in which a transaction spending this output may
+    /// be mined.
+    ///
+    /// For coinbase outputs, this is [`COINBASE_MATURITY`] blocks after the height at which
+    /// the output was mined; other outputs may be spent in any later block.
+    pub fn maturity_height(&self) -> BlockHeight {
+        if self.is_coinbase {
+            self.height + COINBASE_MATURITY
+        } else {
+            self.height
+        }
+    }
+
+    /// Returns whether this output may be spent by a transaction mined at `target_height`.
+    pub fn is_mature_at(&self, target_height: BlockHeight) -> bool {
+        target_height >= self.maturity_height()
+    }
 }
 
 impl transparent_fees::InputView for WalletTransparentOutput {
@@ -384,6 +384,50 @@ mod tests {
         testing::pool::scan_cached_blocks_finds_received_notes::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn scan_cached_blocks_respects_to_height_sapling() {
+        testing::pool::scan_cached_blocks_respects_to_height::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn scan_cached_blocks_respects_to_height_orchard() {
+        testing::pool::scan_cached_blocks_respects_to_height::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn scan_cached_blocks_resumes_after_restart_sapling() {
+        testing::pool::scan_cached_blocks_resumes_after_restart::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn scan_cached_blocks_resumes_after_restart_orchard() {
+        testing::pool::scan_cached_blocks_resumes_after_restart::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn scan_cached_blocks_with_commit_interval_sapling() {
+        testing::pool::scan_cached_blocks_with_commit_interval::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn scan_cached_blocks_with_commit_interval_orchard() {
+        testing::pool::scan_cached_blocks_with_commit_interval::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn scan_cached_blocks_for_accounts_sapling() {
+        testing::pool::scan_cached_blocks_for_accounts::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn scan_cached_blocks_for_accounts_orchard() {
+        testing::pool::scan_cached_blocks_for_accounts::<OrchardPoolTester>()
+    }
+
     #[test]
     fn scan_cached_blocks_finds_change_notes_sapling() {
         testing::pool::scan_cached_blocks_finds_change_notes::<SaplingPoolTester>()
@@ -1,14 +1,18 @@
 //! Error types for problems that may arise when reading or storing wallet data to SQLite.
 
+use std::convert::Infallible;
 use std::error;
 use std::fmt;
 
 use shardtree::error::ShardTreeError;
 use zcash_address::ParseError;
-use zcash_client_backend::PoolType;
+use zcash_client_backend::{wallet::NoteId, PoolType};
 use zcash_keys::keys::AddressGenerationError;
 use zcash_primitives::zip32;
-use zcash_primitives::{consensus::BlockHeight, transaction::components::amount::BalanceError};
+use zcash_primitives::{
+    consensus::BlockHeight,
+    transaction::{components::amount::BalanceError, TxId},
+};
 
 use crate::wallet::commitment_tree;
 use crate::AccountId;
@@ -82,6 +86,9 @@ pub enum SqliteClientError {
     /// The account was imported, and ZIP-32 derivation information is not known for it.
     UnknownZip32Derivation,
 
+    /// The seed provided for an account does not match the account's seed fingerprint.
+    SeedMismatch,
+
     /// An error occurred deriving a spending key from a seed and a ZIP-32 account index.
     KeyDerivationError(zip32::AccountId),
 
@@ -119,6 +126,27 @@ pub enum SqliteClientError {
 
     /// An error occurred in computing wallet balance
     BalanceError(BalanceError),
+
+    /// The transaction with the given ID cannot be abandoned because it has been mined.
+    TransactionMined(TxId),
+
+    /// The transaction with the given ID cannot be abandoned without being forced, because it
+    /// has not yet expired and so may still be mined if it was broadcast.
+    TransactionMayHaveBeenBroadcast(TxId),
+
+    /// A witness could not be computed for the note with the given ID, because the note is not
+    /// known to the wallet or its position in the note commitment tree is not known.
+    NoteWitnessUnavailable(NoteId),
+
+    /// The note with the given ID is not known to the wallet.
+    NoteNotFound(NoteId),
+
+    /// The transaction with the given ID is not known to the wallet.
+    TransactionNotFound(TxId),
+
+    /// The requested number of note commitment tree checkpoints to retain is smaller than
+    /// the number of checkpoints the wallet relies upon.
+    CheckpointRetentionTooSmall(usize),
 }
 
 impl error::Error for SqliteClientError {
@@ -159,6 +187,7 @@ impl fmt::Display for SqliteClientError {
             SqliteClientError::AddressGeneration(e) => write!(f, "{}", e),
             SqliteClientError::AccountUnknown => write!(f, "The account with the given ID does not belong to this wallet."),
             SqliteClientError::UnknownZip32Derivation => write!(f, "ZIP-32 derivation information is not known for this account."),
+            SqliteClientError::SeedMismatch => write!(f, "The provided seed does not correspond to this account."),
             SqliteClientError::KeyDerivationError(acct_id) => write!(f, "Key derivation failed for account {}", u32::from(*acct_id)),
             SqliteClientError::BadAccountData(e) => write!(f, "Failed to add account: {}", e),
             SqliteClientError::AccountIdDiscontinuity => write!(f, "Wallet account identifiers must be sequential."),
@@ -171,10 +200,22 @@ impl fmt::Display for SqliteClientError {
             SqliteClientError::ChainHeightUnknown => write!(f, "Chain height unknown; please call `update_chain_tip`"),
             SqliteClientError::UnsupportedPoolType(t) => write!(f, "Pool type is not currently supported: {}", t),
             SqliteClientError::BalanceError(e) => write!(f, "Balance error: {}", e),
+            SqliteClientError::TransactionMined(txid) => write!(f, "Transaction {} has been mined and cannot be abandoned.", txid),
+            SqliteClientError::TransactionMayHaveBeenBroadcast(txid) => write!(f, "Transaction {} has not expired and may have been broadcast; abandoning it requires `force`.", txid),
+            SqliteClientError::NoteWitnessUnavailable(note_id) => write!(f, "No witness can be computed for note {:?}, because its position in the note commitment tree is not known.", note_id),
+            SqliteClientError::NoteNotFound(note_id) => write!(f, "Note {:?} is not known to the wallet.", note_id),
+            SqliteClientError::TransactionNotFound(txid) => write!(f, "Transaction {} is not known to the wallet.", txid),
+            SqliteClientError::CheckpointRetentionTooSmall(n) => write!(f, "At least {} note commitment tree checkpoints must be retained; {} were requested.", PRUNING_DEPTH, n),
         }
     }
 }
 
+impl From<Infallible> for SqliteClientError {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
 impl From<rusqlite::Error> for SqliteClientError {
     fn from(e: rusqlite::Error) -> Self {
         SqliteClientError::DbError(e)
@@ -42,21 +42,29 @@ use rusqlite::{self, Connection};
 use secrecy::{ExposeSecret, SecretVec};
 use shardtree::{error::ShardTreeError, ShardTree};
 use std::{
-    borrow::Borrow, collections::HashMap, convert::AsRef, fmt, num::NonZeroU32, ops::Range,
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    convert::AsRef,
+    fmt,
+    num::NonZeroU32,
+    ops::Range,
     path::Path,
 };
 use subtle::ConditionallySelectable;
 use tracing::{debug, trace, warn};
 
 use zcash_client_backend::{
-    address::UnifiedAddress,
+    address::{Address, AddressFormat, UnifiedAddress},
     data_api::{
         self,
         chain::{BlockSource, ChainState, CommitmentTreeRoot},
+        chain_tip::ChainTipProvider,
         scanning::{ScanPriority, ScanRange},
-        Account, AccountBirthday, AccountSource, BlockMetadata, DecryptedTransaction, InputSource,
-        NullifierQuery, ScannedBlock, SeedRelevance, SentTransaction, SpendableNotes,
-        WalletCommitmentTrees, WalletRead, WalletSummary, WalletWrite, SAPLING_SHARD_HEIGHT,
+        Account, AccountBalance, AccountBirthday, AccountInfo, AccountSource, AuditReport,
+        BlockMetadata, CommitmentTreeReplacement, DecryptedTransaction, InputSource, NoteWitness,
+        NullifierQuery, PoolFlows, ScannedBlock, SeedRelevance, SentTransaction, SpendableNotes,
+        TransactionAnnotation, WalletCommitmentTrees, WalletRead, WalletSummary, WalletWrite,
+        WatchOnlyBundle, WatchedAddress, SAPLING_SHARD_HEIGHT,
     },
     keys::{
         AddressGenerationError, UnifiedAddressRequest, UnifiedFullViewingKey, UnifiedSpendingKey,
@@ -71,7 +79,7 @@ use zcash_primitives::{
     consensus::{self, BlockHeight},
     memo::{Memo, MemoBytes},
     transaction::{components::amount::NonNegativeAmount, Transaction, TxId},
-    zip32::{self, DiversifierIndex},
+    zip32::{self, DiversifierIndex, Scope},
 };
 use zip32::fingerprint::SeedFingerprint;
 
@@ -87,9 +95,15 @@ use {
 
 #[cfg(feature = "transparent-inputs")]
 use {
-    zcash_client_backend::data_api::TransparentAddressSyncInfo,
+    zcash_client_backend::data_api::{SpendStatus, TransparentAddressSyncInfo},
     zcash_client_backend::wallet::TransparentAddressMetadata,
-    zcash_primitives::{legacy::TransparentAddress, transaction::components::OutPoint},
+    zcash_primitives::{
+        legacy::{
+            keys::{AccountPubKey, NonHardenedChildIndex},
+            Script, TransparentAddress,
+        },
+        transaction::components::OutPoint,
+    },
 };
 
 #[cfg(feature = "unstable")]
@@ -173,7 +187,8 @@ impl fmt::Display for ReceivedNoteId {
 }
 
 /// A newtype wrapper for sqlite primary key values for the utxos
-/// table.
+/// table, or for the watched_address_utxos table in the case of
+/// outputs received at watched addresses.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct UtxoId(pub i64);
 
@@ -181,6 +196,7 @@ pub struct UtxoId(pub i64);
 pub struct WalletDb<C, P> {
     conn: C,
     params: P,
+    checkpoint_retention: usize,
 }
 
 /// A wrapper for a SQLite transaction affecting the wallet database.
@@ -192,15 +208,89 @@ impl Borrow<rusqlite::Connection> for SqlTransaction<'_> {
     }
 }
 
+/// A read transaction providing a consistent, point-in-time view of the wallet database.
+///
+/// A `WalletDb` wrapping a `WalletSnapshot` is obtained via [`WalletDb::snapshot`]. All
+/// [`WalletRead`] and [`InputSource`] queries made through it observe the state of the
+/// database as of the snapshot's creation, regardless of any writes committed by other
+/// connections in the meantime. The read transaction is released when the snapshot is
+/// dropped.
+pub struct WalletSnapshot<'conn>(rusqlite::Transaction<'conn>);
+
+impl Borrow<rusqlite::Connection> for WalletSnapshot<'_> {
+    fn borrow(&self) -> &rusqlite::Connection {
+        &self.0
+    }
+}
+
+/// Runs the given read operation against a consistent view of the wallet database.
+///
+/// If a transaction is already open on the connection, as is the case for a
+/// [`WalletDb::snapshot`], the operation reads through that transaction. Otherwise, a read
+/// transaction is opened for the duration of the operation.
+fn with_read_transaction<A, E: From<SqliteClientError>>(
+    conn: &rusqlite::Connection,
+    f: impl FnOnce(&rusqlite::Connection) -> Result<A, E>,
+) -> Result<A, E> {
+    if conn.is_autocommit() {
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(SqliteClientError::from)?;
+        f(&tx)
+    } else {
+        f(conn)
+    }
+}
+
 impl<P: consensus::Parameters + Clone> WalletDb<Connection, P> {
     /// Construct a connection to the wallet database stored at the specified path.
     pub fn for_path<F: AsRef<Path>>(path: F, params: P) -> Result<Self, rusqlite::Error> {
         Connection::open(path).and_then(move |conn| {
             rusqlite::vtab::array::load_module(&conn)?;
-            Ok(WalletDb { conn, params })
+            Ok(WalletDb {
+                conn,
+                params,
+                checkpoint_retention: PRUNING_DEPTH as usize,
+            })
         })
     }
 
+    /// Sets the number of note commitment tree checkpoints to retain when checkpoints are
+    /// pruned during scanning. One checkpoint is created per scanned block.
+    ///
+    /// Retaining more checkpoints allows the wallet to recover from deeper chain reorgs, at
+    /// the cost of additional storage. The wallet relies on checkpoints being available at
+    /// least 100 blocks below the chain tip for rewinds and anchor selection, so values
+    /// smaller than this default are rejected with
+    /// [`SqliteClientError::CheckpointRetentionTooSmall`].
+    pub fn with_checkpoint_retention(
+        mut self,
+        checkpoint_retention: usize,
+    ) -> Result<Self, SqliteClientError> {
+        if checkpoint_retention < PRUNING_DEPTH as usize {
+            return Err(SqliteClientError::CheckpointRetentionTooSmall(
+                checkpoint_retention,
+            ));
+        }
+        self.checkpoint_retention = checkpoint_retention;
+        Ok(self)
+    }
+
+    /// Re-derives the viewing keys of the accounts derived from the given seed, and returns
+    /// the IDs of those accounts whose stored UFVK does not match the derived key.
+    ///
+    /// This may be used to detect corruption of the stored account data when the wallet is
+    /// unlocked, before the user attempts to spend funds. Each key component present in the
+    /// stored UFVK is compared against the corresponding derived component. Imported accounts,
+    /// and accounts derived from a different seed, are not checked; if no account was derived
+    /// from the given seed, [`SqliteClientError::SeedMismatch`] is returned.
+    pub fn verify_derived_keys(
+        &self,
+        seed: &SecretVec<u8>,
+    ) -> Result<Vec<AccountId>, SqliteClientError> {
+        wallet::verify_derived_keys(&self.conn, &self.params, seed)
+    }
+
     pub fn transactionally<F, A, E: From<rusqlite::Error>>(&mut self, f: F) -> Result<A, E>
     where
         F: FnOnce(&mut WalletDb<SqlTransaction<'_>, P>) -> Result<A, E>,
@@ -209,11 +299,33 @@ impl<P: consensus::Parameters + Clone> WalletDb<Connection, P> {
         let mut wdb = WalletDb {
             conn: SqlTransaction(&tx),
             params: self.params.clone(),
+            checkpoint_retention: self.checkpoint_retention,
         };
         let result = f(&mut wdb)?;
         tx.commit()?;
         Ok(result)
     }
+
+    /// Opens a read transaction on the wallet database, returning a [`WalletDb`] through
+    /// which all reads observe the state of the database at the time of this call.
+    ///
+    /// This allows callers such as UIs to query balances and notes without observing the
+    /// partially-applied effects of a scan being committed concurrently by another
+    /// connection. For the snapshot not to block such writers, the database must be in
+    /// [WAL mode](https://www.sqlite.org/wal.html).
+    pub fn snapshot(&self) -> Result<WalletDb<WalletSnapshot<'_>, P>, rusqlite::Error> {
+        let tx = self.conn.unchecked_transaction()?;
+        // A deferred transaction does not acquire its read snapshot until the first read, so
+        // read from the database immediately to fix the point-in-time view here.
+        tx.query_row("SELECT COUNT(*) FROM sqlite_schema", [], |row| {
+            row.get::<_, i64>(0)
+        })?;
+        Ok(WalletDb {
+            conn: WalletSnapshot(tx),
+            params: self.params.clone(),
+            checkpoint_retention: self.checkpoint_retention,
+        })
+    }
 }
 
 impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> InputSource for WalletDb<C, P> {
@@ -282,6 +394,10 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> InputSource for
         ))
     }
 
+    fn get_locked_note_refs(&self, account: AccountId) -> Result<Vec<Self::NoteRef>, Self::Error> {
+        wallet::get_locked_note_refs(self.conn.borrow(), account)
+    }
+
     #[cfg(feature = "transparent-inputs")]
     fn get_unspent_transparent_output(
         &self,
@@ -316,6 +432,10 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::get_account_ids(self.conn.borrow())
     }
 
+    fn list_accounts(&self) -> Result<Vec<AccountInfo<AccountId>>, Self::Error> {
+        wallet::list_accounts(self.conn.borrow())
+    }
+
     fn get_account(
         &self,
         account_id: Self::AccountId,
@@ -358,6 +478,32 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         }
     }
 
+    fn derive_usk(
+        &self,
+        account_id: Self::AccountId,
+        seed: &SecretVec<u8>,
+    ) -> Result<UnifiedSpendingKey, Self::Error> {
+        let account = self
+            .get_account(account_id)?
+            .ok_or(SqliteClientError::AccountUnknown)?;
+
+        if let AccountSource::Derived {
+            seed_fingerprint,
+            account_index,
+        } = account.source()
+        {
+            wallet::derive_usk(
+                &self.params,
+                seed,
+                &seed_fingerprint,
+                account_index,
+                &account.uivk(),
+            )
+        } else {
+            Err(SqliteClientError::UnknownZip32Derivation)
+        }
+    }
+
     fn seed_relevance_to_derived_accounts(
         &self,
         seed: &SecretVec<u8>,
@@ -422,10 +568,55 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
             .map(|res| res.map(|(addr, _)| addr))
     }
 
+    fn get_current_address_encoded(
+        &self,
+        account: AccountId,
+        format: AddressFormat,
+    ) -> Result<Option<String>, Self::Error> {
+        wallet::get_current_address(self.conn.borrow(), &self.params, account)
+            .map(|res| res.and_then(|(addr, _)| addr.encode_as(&self.params, format)))
+    }
+
+    fn get_internal_address(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<UnifiedAddress>, Self::Error> {
+        wallet::get_internal_address(self.conn.borrow(), &self.params, account)
+    }
+
+    fn get_max_diversifier_index(
+        &self,
+        account: AccountId,
+        scope: Scope,
+    ) -> Result<Option<DiversifierIndex>, Self::Error> {
+        wallet::get_max_diversifier_index(self.conn.borrow(), account, scope)
+    }
+
+    fn get_auto_discovered_addresses(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<UnifiedAddress>, Self::Error> {
+        wallet::get_auto_discovered_addresses(self.conn.borrow(), &self.params, account)
+    }
+
+    fn get_reused_addresses(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(Address, usize)>, Self::Error> {
+        wallet::get_reused_addresses(self.conn.borrow(), &self.params, account)
+    }
+
     fn get_account_birthday(&self, account: AccountId) -> Result<BlockHeight, Self::Error> {
         wallet::account_birthday(self.conn.borrow(), account).map_err(SqliteClientError::from)
     }
 
+    fn export_account_for_watching(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<WatchOnlyBundle>, Self::Error> {
+        wallet::export_account_for_watching(self.conn.borrow(), &self.params, account)
+    }
+
     fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
         wallet::wallet_birthday(self.conn.borrow()).map_err(SqliteClientError::from)
     }
@@ -436,12 +627,29 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
     ) -> Result<Option<WalletSummary<Self::AccountId>>, Self::Error> {
         // This will return a runtime error if we call `get_wallet_summary` from two
         // threads at the same time, as transactions cannot nest.
-        wallet::get_wallet_summary(
-            &self.conn.borrow().unchecked_transaction()?,
-            &self.params,
-            min_confirmations,
-            &SubtreeScanProgress,
-        )
+        with_read_transaction(self.conn.borrow(), |tx| {
+            wallet::get_wallet_summary(tx, &self.params, min_confirmations, &SubtreeScanProgress)
+        })
+    }
+
+    fn get_account_balances(
+        &self,
+        min_confirmations: u32,
+    ) -> Result<HashMap<Self::AccountId, AccountBalance>, Self::Error> {
+        with_read_transaction(self.conn.borrow(), |tx| {
+            wallet::get_account_balances(tx, min_confirmations)
+        })
+    }
+
+    fn projected_spendable_balance(
+        &self,
+        account: Self::AccountId,
+        at_height: BlockHeight,
+        min_confirmations: NonZeroU32,
+    ) -> Result<NonNegativeAmount, Self::Error> {
+        with_read_transaction(self.conn.borrow(), |tx| {
+            wallet::projected_spendable_balance(tx, account, at_height, min_confirmations)
+        })
     }
 
     fn chain_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
@@ -458,6 +666,10 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::block_metadata(self.conn.borrow(), &self.params, height)
     }
 
+    fn get_tree_sizes(&self, height: BlockHeight) -> Result<Option<(u32, u32)>, Self::Error> {
+        wallet::get_tree_sizes(self.conn.borrow(), &self.params, height)
+    }
+
     fn block_fully_scanned(&self) -> Result<Option<BlockMetadata>, Self::Error> {
         wallet::block_fully_scanned(self.conn.borrow(), &self.params)
     }
@@ -470,11 +682,25 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::block_max_scanned(self.conn.borrow(), &self.params)
     }
 
+    fn rescan_required(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        wallet::get_rescan_required(self.conn.borrow())
+    }
+
     fn suggest_scan_ranges(&self) -> Result<Vec<ScanRange>, Self::Error> {
         wallet::scanning::suggest_scan_ranges(self.conn.borrow(), ScanPriority::Historic)
             .map_err(SqliteClientError::from)
     }
 
+    fn suggest_download_ranges<F, E>(
+        &self,
+        cached_tip: F,
+    ) -> Result<Vec<Range<BlockHeight>>, data_api::chain::error::Error<Self::Error, E>>
+    where
+        F: FnMut(&ScanRange) -> Result<Option<BlockHeight>, E>,
+    {
+        wallet::scanning::suggest_download_ranges(self.conn.borrow(), cached_tip)
+    }
+
     fn get_target_and_anchor_heights(
         &self,
         min_confirmations: NonZeroU32,
@@ -491,6 +717,10 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::get_tx_height(self.conn.borrow(), txid).map_err(SqliteClientError::from)
     }
 
+    fn get_transaction_time(&self, txid: TxId) -> Result<Option<u32>, Self::Error> {
+        wallet::get_transaction_time(self.conn.borrow(), txid).map_err(SqliteClientError::from)
+    }
+
     fn get_unified_full_viewing_keys(
         &self,
     ) -> Result<HashMap<AccountId, UnifiedFullViewingKey>, Self::Error> {
@@ -511,6 +741,188 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
             .map(|res| res.map(|(_, tx)| tx))
     }
 
+    fn get_transaction_bytes(&self, txid: TxId) -> Result<Option<Vec<u8>>, Self::Error> {
+        wallet::get_transaction_bytes(self.conn.borrow(), txid)
+    }
+
+    fn audit_transaction(&self, txid: TxId) -> Result<AuditReport<AccountId>, Self::Error> {
+        wallet::audit_transaction(self.conn.borrow(), &self.params, txid)
+    }
+
+    fn get_rebroadcast_candidates(&self) -> Result<Vec<TxId>, Self::Error> {
+        wallet::get_rebroadcast_candidates(self.conn.borrow())
+    }
+
+    fn get_unmined_transactions(&self) -> Result<Vec<TxId>, Self::Error> {
+        wallet::get_unmined_transactions(self.conn.borrow())
+    }
+
+    fn get_transaction_fee(&self, txid: TxId) -> Result<Option<NonNegativeAmount>, Self::Error> {
+        wallet::get_transaction_fee(self.conn.borrow(), &self.params, txid)
+    }
+
+    fn get_fees_paid(
+        &self,
+        range: Range<BlockHeight>,
+    ) -> Result<Vec<(TxId, NonNegativeAmount)>, Self::Error> {
+        wallet::get_fees_paid(self.conn.borrow(), range)
+    }
+
+    fn get_transaction_pool_flows(&self, txid: TxId) -> Result<Option<PoolFlows>, Self::Error> {
+        wallet::get_transaction_pool_flows(self.conn.borrow(), &self.params, txid)
+    }
+
+    fn get_spent_notes(&self, txid: TxId) -> Result<Vec<NoteId>, Self::Error> {
+        wallet::get_spent_notes(self.conn.borrow(), txid)
+    }
+
+    fn get_locked_notes(&self, account: AccountId) -> Result<Vec<NoteId>, Self::Error> {
+        wallet::get_locked_notes(self.conn.borrow(), account)
+    }
+
+    fn get_transactions_spending_pool(
+        &self,
+        pool: ShieldedProtocol,
+        range: Range<BlockHeight>,
+    ) -> Result<Vec<TxId>, Self::Error> {
+        wallet::get_transactions_spending_pool(self.conn.borrow(), pool, range)
+    }
+
+    fn get_spent_note_positions(
+        &self,
+        txid: TxId,
+    ) -> Result<Vec<(ShieldedProtocol, Position)>, Self::Error> {
+        wallet::get_spent_note_positions(self.conn.borrow(), txid)
+    }
+
+    fn get_note_scope(&self, note_id: NoteId) -> Result<Option<Scope>, Self::Error> {
+        wallet::get_note_scope(self.conn.borrow(), note_id)
+    }
+
+    fn blocks_until_spendable(
+        &self,
+        note_id: NoteId,
+        min_confirmations: NonZeroU32,
+    ) -> Result<Option<u32>, Self::Error> {
+        wallet::blocks_until_spendable(self.conn.borrow(), self, note_id, min_confirmations)
+    }
+
+    fn unspent_set_fingerprint(&self, account: Self::AccountId) -> Result<[u8; 32], Self::Error> {
+        wallet::unspent_set_fingerprint(self.conn.borrow(), &self.params, account)
+    }
+
+    fn get_witnesses(
+        &self,
+        note_ids: &[NoteId],
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<(NoteId, NoteWitness)>, Self::Error> {
+        with_read_transaction(self.conn.borrow(), |tx| {
+            wallet::get_witnesses(tx, note_ids, anchor_height)
+        })
+    }
+
+    fn get_received_notes_matching_memo<F>(
+        &self,
+        account: Self::AccountId,
+        predicate: F,
+    ) -> Result<Vec<NoteId>, Self::Error>
+    where
+        F: Fn(&MemoBytes) -> bool,
+    {
+        wallet::get_received_notes_matching_memo(self.conn.borrow(), account, predicate)
+    }
+
+    fn get_notes_pending_spend(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<(NoteId, TxId)>, Self::Error> {
+        wallet::get_notes_pending_spend(self.conn.borrow(), account)
+    }
+
+    fn iter_received_notes<F, E>(
+        &self,
+        account: Self::AccountId,
+        sources: &[ShieldedProtocol],
+        mut callback: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(ReceivedNote<NoteId, Note>) -> Result<(), E>,
+        E: From<Self::Error>,
+    {
+        let with_note_id = |note: ReceivedNote<ReceivedNoteId, Note>| {
+            ReceivedNote::from_parts(
+                NoteId::new(*note.txid(), note.note().protocol(), note.output_index()),
+                *note.txid(),
+                note.output_index(),
+                note.note().clone(),
+                note.spending_key_scope(),
+                note.note_commitment_tree_position(),
+            )
+        };
+
+        // Hold a read transaction for the duration of the iteration, so that all notes are
+        // read from a consistent snapshot of the wallet.
+        with_read_transaction(self.conn.borrow(), |tx| {
+            if sources.contains(&ShieldedProtocol::Sapling) {
+                wallet::sapling::for_each_unspent_sapling_note(
+                    tx,
+                    &self.params,
+                    account,
+                    |note| callback(with_note_id(note.map_note(Note::Sapling))),
+                )?;
+            }
+
+            #[cfg(feature = "orchard")]
+            if sources.contains(&ShieldedProtocol::Orchard) {
+                wallet::orchard::for_each_unspent_orchard_note(
+                    tx,
+                    &self.params,
+                    account,
+                    |note| callback(with_note_id(note.map_note(Note::Orchard))),
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_change_notes(
+        &self,
+        account: Self::AccountId,
+    ) -> Result<Vec<ReceivedNote<NoteId, Note>>, Self::Error> {
+        let mut notes = vec![];
+        self.iter_received_notes(
+            account,
+            &[ShieldedProtocol::Sapling, ShieldedProtocol::Orchard],
+            |note| {
+                if note.spending_key_scope() == Scope::Internal {
+                    notes.push(note);
+                }
+                Ok::<_, SqliteClientError>(())
+            },
+        )?;
+        Ok(notes)
+    }
+
+    fn get_newly_confirmed_notes(
+        &self,
+        since_height: BlockHeight,
+        min_confirmations: NonZeroU32,
+    ) -> Result<Vec<NoteId>, Self::Error> {
+        wallet::get_newly_confirmed_notes(self.conn.borrow(), since_height, min_confirmations)
+    }
+
+    fn get_transaction_annotation(
+        &self,
+        txid: TxId,
+    ) -> Result<Option<TransactionAnnotation>, Self::Error> {
+        wallet::get_transaction_annotation(self.conn.borrow(), txid)
+    }
+
+    fn get_watched_addresses(&self) -> Result<Vec<WatchedAddress>, Self::Error> {
+        wallet::get_watched_addresses(self.conn.borrow(), &self.params)
+    }
+
     fn get_sapling_nullifiers(
         &self,
         query: NullifierQuery,
@@ -534,6 +946,31 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::get_transparent_receivers(self.conn.borrow(), &self.params, account)
     }
 
+    #[cfg(feature = "transparent-inputs")]
+    fn get_transparent_account_pubkey(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<AccountPubKey>, Self::Error> {
+        wallet::get_transparent_account_pubkey(self.conn.borrow(), &self.params, account)
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn find_account_for_transparent_output(
+        &self,
+        script: &Script,
+    ) -> Result<Option<(AccountId, NonHardenedChildIndex)>, Self::Error> {
+        wallet::find_account_for_transparent_output(self.conn.borrow(), &self.params, script)
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn get_unspent_watched_outputs(
+        &self,
+        address: &TransparentAddress,
+        max_height: BlockHeight,
+    ) -> Result<Vec<WalletTransparentOutput>, Self::Error> {
+        wallet::get_unspent_watched_outputs(self.conn.borrow(), &self.params, address, max_height)
+    }
+
     #[cfg(feature = "transparent-inputs")]
     fn get_transparent_balances(
         &self,
@@ -543,6 +980,14 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
         wallet::get_transparent_balances(self.conn.borrow(), &self.params, account, max_height)
     }
 
+    #[cfg(feature = "transparent-inputs")]
+    fn get_transparent_outpoints(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(OutPoint, WalletTransparentOutput, SpendStatus)>, Self::Error> {
+        wallet::get_transparent_outpoints(self.conn.borrow(), account)
+    }
+
     #[cfg(feature = "transparent-inputs")]
     fn get_transparent_addresses_and_sync_heights(
         &mut self,
@@ -560,6 +1005,14 @@ impl<C: Borrow<rusqlite::Connection>, P: consensus::Parameters> WalletRead for W
     }
 }
 
+impl<C: Borrow<rusqlite::Connection>, P> ChainTipProvider for WalletDb<C, P> {
+    type Error = SqliteClientError;
+
+    fn chain_tip_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        Ok(wallet::scan_queue_extrema(self.conn.borrow())?.map(|range| *range.end()))
+    }
+}
+
 impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P> {
     type UtxoRef = UtxoId;
 
@@ -594,6 +1047,7 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
                 },
                 wallet::ViewingKey::Full(Box::new(ufvk)),
                 birthday,
+                wdb.checkpoint_retention,
             )?;
 
             Ok((account.id(), usk))
@@ -628,12 +1082,55 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
                 },
                 wallet::ViewingKey::Full(Box::new(ufvk)),
                 birthday,
+                wdb.checkpoint_retention,
             )?;
 
             Ok((account, usk))
         })
     }
 
+    fn get_or_create_account(
+        &mut self,
+        seed: &SecretVec<u8>,
+        account_index: zip32::AccountId,
+        birthday: &AccountBirthday,
+    ) -> Result<(Self::Account, UnifiedSpendingKey), Self::Error> {
+        self.transactionally(|wdb| {
+            let seed_fingerprint =
+                SeedFingerprint::from_seed(seed.expose_secret()).ok_or_else(|| {
+                    SqliteClientError::BadAccountData(
+                        "Seed must be between 32 and 252 bytes in length.".to_owned(),
+                    )
+                })?;
+
+            let usk =
+                UnifiedSpendingKey::from_seed(&wdb.params, seed.expose_secret(), account_index)
+                    .map_err(|_| SqliteClientError::KeyDerivationError(account_index))?;
+
+            let account = match wallet::get_derived_account(
+                wdb.conn.0,
+                &wdb.params,
+                &seed_fingerprint,
+                account_index,
+            )? {
+                Some(account) => account,
+                None => wallet::add_account(
+                    wdb.conn.0,
+                    &wdb.params,
+                    AccountSource::Derived {
+                        seed_fingerprint,
+                        account_index,
+                    },
+                    wallet::ViewingKey::Full(Box::new(usk.to_unified_full_viewing_key())),
+                    birthday,
+                    wdb.checkpoint_retention,
+                )?,
+            };
+
+            Ok((account, usk))
+        })
+    }
+
     fn import_account_ufvk(
         &mut self,
         ufvk: &UnifiedFullViewingKey,
@@ -647,6 +1144,7 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
                 AccountSource::Imported,
                 wallet::ViewingKey::Full(Box::new(ufvk.to_owned())),
                 birthday,
+                wdb.checkpoint_retention,
             )
         })
     }
@@ -672,7 +1170,7 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
 
                     let (addr, diversifier_index) = ufvk.find_address(search_from, request)?;
 
-                    wallet::insert_address(
+                    wallet::put_generated_address(
                         wdb.conn.0,
                         &wdb.params,
                         account,
@@ -705,858 +1203,1028 @@ impl<P: consensus::Parameters> WalletWrite for WalletDb<rusqlite::Connection, P>
                 .get(account_id)
                 .ok_or(SqliteClientError::AccountUnknown)?;
 
-            let has_orchard = true;
-            let mut has_sapling = true;
-            let mut has_transparent = true;
-
-            // Get the most comprehensive UA available for the given diversifier index.
-            // We may have to drop the sapling and/or the transparent receiver if the diversifier index is invalid or out of range.
-            let addr = loop {
-                if let Some(addr) = match ufvk.address(
-                    diversifier_index,
-                    UnifiedAddressRequest::unsafe_new(has_orchard, has_sapling, has_transparent),
-                ) {
-                    Ok(addr) => Some(addr),
-                    Err(AddressGenerationError::InvalidSaplingDiversifierIndex(_)) => {
-                        has_sapling = false;
-                        None
-                    }
-                    #[cfg(feature = "transparent-inputs")]
-                    Err(AddressGenerationError::InvalidTransparentChildIndex(_)) => {
-                        has_transparent = false;
-                        None
-                    }
-                    Err(_) => return Err(SqliteClientError::DiversifierIndexOutOfRange),
-                } {
-                    break addr;
-                }
-            };
-
-            match wallet::insert_address(
+            wallet::put_address_with_diversifier_index(
                 wdb.conn.0,
                 &wdb.params,
                 *account_id,
+                ufvk,
                 diversifier_index,
-                &addr,
-            ) {
-                Ok(_) => Ok(addr),
-                Err(rusqlite::Error::SqliteFailure(
-                    libsqlite3_sys::Error {
-                        code: libsqlite3_sys::ErrorCode::ConstraintViolation,
-                        ..
-                    },
-                    _,
-                )) => Ok(addr), // conflicts are ignorable
-                Err(e) => Err(e.into()),
-            }
+            )
+        })
+    }
+
+    fn discover_shielded_addresses(
+        &mut self,
+        account: AccountId,
+        index_range: Range<u32>,
+    ) -> Result<Vec<UnifiedAddress>, Self::Error> {
+        self.transactionally(|wdb| {
+            let keys = wdb.get_unified_full_viewing_keys()?;
+            let ufvk = keys
+                .get(&account)
+                .ok_or(SqliteClientError::AccountUnknown)?;
+
+            wallet::discover_shielded_addresses(wdb.conn.0, &wdb.params, account, ufvk, index_range)
         })
     }
 
     #[tracing::instrument(skip_all, fields(height = blocks.first().map(|b| u32::from(b.height())), count = blocks.len()))]
-    #[allow(clippy::type_complexity)]
     fn put_blocks(
         &mut self,
         from_state: &ChainState,
         blocks: Vec<ScannedBlock<Self::AccountId>>,
     ) -> Result<(), Self::Error> {
-        struct BlockPositions {
-            height: BlockHeight,
-            sapling_start_position: Position,
-            #[cfg(feature = "orchard")]
-            orchard_start_position: Position,
-        }
+        self.put_blocks_internal(from_state, blocks, true)
+    }
 
-        self.transactionally(|wdb| {
-            let start_positions = blocks.first().map(|block| BlockPositions {
-                height: block.height(),
-                sapling_start_position: Position::from(
-                    u64::from(block.sapling().final_tree_size())
-                        - u64::try_from(block.sapling().commitments().len()).unwrap(),
-                ),
-                #[cfg(feature = "orchard")]
-                orchard_start_position: Position::from(
-                    u64::from(block.orchard().final_tree_size())
-                        - u64::try_from(block.orchard().commitments().len()).unwrap(),
-                ),
-            });
-            let mut sapling_commitments = vec![];
-            #[cfg(feature = "orchard")]
-            let mut orchard_commitments = vec![];
-            let mut last_scanned_height = None;
-            let mut note_positions = vec![];
-            for block in blocks.into_iter() {
-                if last_scanned_height
-                    .iter()
-                    .any(|prev| block.height() != *prev + 1)
-                {
-                    return Err(SqliteClientError::NonSequentialBlocks);
-                }
+    #[tracing::instrument(skip_all, fields(height = blocks.first().map(|b| u32::from(b.height())), count = blocks.len()))]
+    fn put_partially_scanned_blocks(
+        &mut self,
+        from_state: &ChainState,
+        blocks: Vec<ScannedBlock<Self::AccountId>>,
+    ) -> Result<(), Self::Error> {
+        self.put_blocks_internal(from_state, blocks, false)
+    }
 
-                // Insert the block into the database.
-                wallet::put_block(
-                    wdb.conn.0,
-                    block.height(),
-                    block.block_hash(),
-                    block.block_time(),
-                    block.sapling().final_tree_size(),
-                    block.sapling().commitments().len().try_into().unwrap(),
-                    #[cfg(feature = "orchard")]
-                    block.orchard().final_tree_size(),
-                    #[cfg(feature = "orchard")]
-                    block.orchard().commitments().len().try_into().unwrap(),
-                )?;
+    fn put_received_transparent_utxo(
+        &mut self,
+        _output: &WalletTransparentOutput,
+    ) -> Result<Self::UtxoRef, Self::Error> {
+        #[cfg(feature = "transparent-inputs")]
+        return wallet::put_received_transparent_utxo(&self.conn, &self.params, _output);
 
-                for tx in block.transactions() {
-                    let tx_row = wallet::put_tx_meta(wdb.conn.0, tx, block.height())?;
+        #[cfg(not(feature = "transparent-inputs"))]
+        panic!(
+            "The wallet must be compiled with the transparent-inputs feature to use this method."
+        );
+    }
 
-                    // Mark notes as spent and remove them from the scanning cache
-                    for spend in tx.sapling_spends() {
-                        wallet::sapling::mark_sapling_note_spent(wdb.conn.0, tx_row, spend.nf())?;
-                    }
-                    #[cfg(feature = "orchard")]
-                    for spend in tx.orchard_spends() {
-                        wallet::orchard::mark_orchard_note_spent(wdb.conn.0, tx_row, spend.nf())?;
+    fn store_decrypted_tx(
+        &mut self,
+        d_tx: DecryptedTransaction<AccountId>,
+    ) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| {
+            let tx_ref = wallet::put_tx_data(wdb.conn.0, d_tx.tx(), None, None)?;
+            if let Some(height) = d_tx.mined_height() {
+                wallet::set_tx_mined_height(wdb.conn.0, tx_ref, height)?;
+            }
+            let funding_accounts = wallet::get_funding_accounts(wdb.conn.0, d_tx.tx())?;
+            let funding_account = funding_accounts.iter().next().copied();
+            if funding_accounts.len() > 1 {
+                warn!(
+                    "More than one wallet account detected as funding transaction {:?}, selecting {:?}",
+                    d_tx.tx().txid(),
+                    funding_account.unwrap()
+                )
+            }
+
+            for output in d_tx.sapling_outputs() {
+                match output.transfer_type() {
+                    TransferType::Outgoing => {
+                        let recipient = {
+                            let receiver = Receiver::Sapling(output.note().recipient());
+                            let wallet_address = wallet::select_receiving_address(
+                                &wdb.params,
+                                wdb.conn.0,
+                                *output.account(),
+                                &receiver
+                            )?.unwrap_or_else(||
+                                receiver.to_zcash_address(wdb.params.network_type())
+                            );
+
+                            Recipient::External(wallet_address, PoolType::Shielded(ShieldedProtocol::Sapling))
+                        };
+
+                        wallet::put_sent_output(
+                            wdb.conn.0,
+                            *output.account(),
+                            tx_ref,
+                            output.index(),
+                            &recipient,
+                            output.note_value(),
+                            Some(output.memo()),
+                        )?;
                     }
+                    TransferType::WalletInternal => {
+                        wallet::sapling::put_received_note(wdb.conn.0, output, tx_ref, None)?;
 
-                    for output in tx.sapling_outputs() {
-                        // Check whether this note was spent in a later block range that
-                        // we previously scanned.
-                        let spent_in = output
-                            .nf()
-                            .map(|nf| {
-                                wallet::query_nullifier_map(
-                                    wdb.conn.0,
-                                    ShieldedProtocol::Sapling,
-                                    nf,
-                                )
-                            })
-                            .transpose()?
-                            .flatten();
+                        let recipient = Recipient::InternalAccount {
+                            receiving_account: *output.account(),
+                            external_address: None,
+                            note: Note::Sapling(output.note().clone()),
+                        };
 
-                        wallet::sapling::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
+                        wallet::put_sent_output(
+                            wdb.conn.0,
+                            *output.account(),
+                            tx_ref,
+                            output.index(),
+                            &recipient,
+                            output.note_value(),
+                            Some(output.memo()),
+                        )?;
                     }
-                    #[cfg(feature = "orchard")]
-                    for output in tx.orchard_outputs() {
-                        // Check whether this note was spent in a later block range that
-                        // we previously scanned.
-                        let spent_in = output
-                            .nf()
-                            .map(|nf| {
-                                wallet::query_nullifier_map(
-                                    wdb.conn.0,
-                                    ShieldedProtocol::Orchard,
-                                    &nf.to_bytes(),
-                                )
-                            })
-                            .transpose()?
-                            .flatten();
+                    TransferType::Incoming => {
+                        wallet::sapling::put_received_note(wdb.conn.0, output, tx_ref, None)?;
 
-                        wallet::orchard::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
+                        if let Some(account_id) = funding_account {
+                            let recipient = Recipient::InternalAccount {
+                                receiving_account: *output.account(),
+                                external_address: {
+                                    let receiver = Receiver::Sapling(output.note().recipient());
+                                    Some(wallet::select_receiving_address(
+                                        &wdb.params,
+                                        wdb.conn.0,
+                                        *output.account(),
+                                        &receiver
+                                    )?.unwrap_or_else(||
+                                        receiver.to_zcash_address(wdb.params.network_type())
+                                    ))
+                                },
+                                note: Note::Sapling(output.note().clone()),
+                            };
+
+                            wallet::put_sent_output(
+                                wdb.conn.0,
+                                account_id,
+                                tx_ref,
+                                output.index(),
+                                &recipient,
+                                output.note_value(),
+                                Some(output.memo()),
+                            )?;
+                        }
                     }
                 }
+            }
 
-                // Insert the new nullifiers from this block into the nullifier map.
-                wallet::insert_nullifier_map(
-                    wdb.conn.0,
-                    block.height(),
-                    ShieldedProtocol::Sapling,
-                    block.sapling().nullifier_map(),
-                )?;
-                #[cfg(feature = "orchard")]
-                wallet::insert_nullifier_map(
-                    wdb.conn.0,
-                    block.height(),
-                    ShieldedProtocol::Orchard,
-                    &block
-                        .orchard()
-                        .nullifier_map()
-                        .iter()
-                        .map(|(txid, idx, nfs)| {
-                            (*txid, *idx, nfs.iter().map(|nf| nf.to_bytes()).collect())
-                        })
-                        .collect::<Vec<_>>(),
-                )?;
+            #[cfg(feature = "orchard")]
+            for output in d_tx.orchard_outputs() {
+                match output.transfer_type() {
+                    TransferType::Outgoing => {
+                        let recipient = {
+                            let receiver = Receiver::Orchard(output.note().recipient());
+                            let wallet_address = wallet::select_receiving_address(
+                                &wdb.params,
+                                wdb.conn.0,
+                                *output.account(),
+                                &receiver
+                            )?.unwrap_or_else(||
+                                receiver.to_zcash_address(wdb.params.network_type())
+                            );
 
-                note_positions.extend(block.transactions().iter().flat_map(|wtx| {
-                    let iter = wtx.sapling_outputs().iter().map(|out| {
-                        (
-                            ShieldedProtocol::Sapling,
-                            out.note_commitment_tree_position(),
-                        )
-                    });
-                    #[cfg(feature = "orchard")]
-                    let iter = iter.chain(wtx.orchard_outputs().iter().map(|out| {
-                        (
-                            ShieldedProtocol::Orchard,
-                            out.note_commitment_tree_position(),
-                        )
-                    }));
+                            Recipient::External(wallet_address, PoolType::Shielded(ShieldedProtocol::Orchard))
+                        };
 
-                    iter
-                }));
+                        wallet::put_sent_output(
+                            wdb.conn.0,
+                            *output.account(),
+                            tx_ref,
+                            output.index(),
+                            &recipient,
+                            output.note_value(),
+                            Some(output.memo()),
+                        )?;
+                    }
+                    TransferType::WalletInternal => {
+                        wallet::orchard::put_received_note(wdb.conn.0, output, tx_ref, None)?;
 
-                last_scanned_height = Some(block.height());
-                let block_commitments = block.into_commitments();
-                trace!(
-                    "Sapling commitments for {:?}: {:?}",
-                    last_scanned_height,
-                    block_commitments
-                        .sapling
-                        .iter()
-                        .map(|(_, r)| *r)
-                        .collect::<Vec<_>>()
-                );
-                #[cfg(feature = "orchard")]
-                trace!(
-                    "Orchard commitments for {:?}: {:?}",
-                    last_scanned_height,
-                    block_commitments
-                        .orchard
-                        .iter()
-                        .map(|(_, r)| *r)
-                        .collect::<Vec<_>>()
-                );
+                        let recipient = Recipient::InternalAccount {
+                            receiving_account: *output.account(),
+                            external_address: None,
+                            note: Note::Orchard(*output.note()),
+                        };
 
-                sapling_commitments.extend(block_commitments.sapling.into_iter().map(Some));
-                #[cfg(feature = "orchard")]
-                orchard_commitments.extend(block_commitments.orchard.into_iter().map(Some));
-            }
+                        wallet::put_sent_output(
+                            wdb.conn.0,
+                            *output.account(),
+                            tx_ref,
+                            output.index(),
+                            &recipient,
+                            output.note_value(),
+                            Some(output.memo()),
+                        )?;
+                    }
+                    TransferType::Incoming => {
+                        wallet::orchard::put_received_note(wdb.conn.0, output, tx_ref, None)?;
 
-            // Prune the nullifier map of entries we no longer need.
-            if let Some(meta) = wdb.block_fully_scanned()? {
-                wallet::prune_nullifier_map(
-                    wdb.conn.0,
-                    meta.block_height().saturating_sub(PRUNING_DEPTH),
-                )?;
+                        if let Some(account_id) = funding_account {
+                            // Even if the recipient address is external, record the send as internal.
+                            let recipient = Recipient::InternalAccount {
+                                receiving_account: *output.account(),
+                                external_address: {
+                                    let receiver = Receiver::Orchard(output.note().recipient());
+                                    Some(wallet::select_receiving_address(
+                                        &wdb.params,
+                                        wdb.conn.0,
+                                        *output.account(),
+                                        &receiver
+                                    )?.unwrap_or_else(||
+                                        receiver.to_zcash_address(wdb.params.network_type())
+                                    ))
+                                },
+                                note: Note::Orchard(*output.note()),
+                            };
+
+                            wallet::put_sent_output(
+                                wdb.conn.0,
+                                account_id,
+                                tx_ref,
+                                output.index(),
+                                &recipient,
+                                output.note_value(),
+                                Some(output.memo()),
+                            )?;
+                        }
+                    }
+                }
             }
 
-            // We will have a start position and a last scanned height in all cases where
-            // `blocks` is non-empty.
-            if let Some((start_positions, last_scanned_height)) =
-                start_positions.zip(last_scanned_height)
+            // If any of the utxos spent in the transaction are ours, mark them as spent.
+            #[cfg(feature = "transparent-inputs")]
+            for txin in d_tx
+                .tx()
+                .transparent_bundle()
+                .iter()
+                .flat_map(|b| b.vin.iter())
             {
-                // Create subtrees from the note commitments in parallel.
-                const CHUNK_SIZE: usize = 1024;
-                let sapling_subtrees = sapling_commitments
-                    .par_chunks_mut(CHUNK_SIZE)
-                    .enumerate()
-                    .filter_map(|(i, chunk)| {
-                        let start =
-                            start_positions.sapling_start_position + (i * CHUNK_SIZE) as u64;
-                        let end = start + chunk.len() as u64;
+                wallet::mark_transparent_input_spent(wdb.conn.0, &wdb.params, tx_ref, txin)?;
+                wallet::mark_watched_utxo_spent(wdb.conn.0, tx_ref, &txin.prevout)?;
+            }
 
-                        shardtree::LocatedTree::from_iter(
-                            start..end,
-                            SAPLING_SHARD_HEIGHT.into(),
-                            chunk.iter_mut().map(|n| n.take().expect("always Some")),
+            // Record any outputs of the transaction that were received at watched addresses.
+            #[cfg(feature = "transparent-inputs")]
+            wallet::put_watched_tx_outputs(wdb.conn.0, &wdb.params, d_tx.tx())?;
+
+            // If we have some transparent outputs:
+            if d_tx
+                .tx()
+                .transparent_bundle()
+                .iter()
+                .any(|b| !b.vout.is_empty())
+            {
+                // If the transaction contains spends from our wallet, we will store z->t
+                // transactions we observe in the same way they would be stored by
+                // create_spend_to_address.
+                let funding_accounts = wallet::get_funding_accounts(wdb.conn.0, d_tx.tx())?;
+                let funding_account = funding_accounts.iter().next().copied();
+                if let Some(account_id) = funding_account {
+                    if funding_accounts.len() > 1 {
+                        warn!(
+                            "More than one wallet account detected as funding transaction {:?}, selecting {:?}",
+                            d_tx.tx().txid(),
+                            account_id
                         )
-                    })
-                    .map(|res| (res.subtree, res.checkpoints))
-                    .collect::<Vec<_>>();
+                    }
 
-                #[cfg(feature = "orchard")]
-                let orchard_subtrees = orchard_commitments
-                    .par_chunks_mut(CHUNK_SIZE)
-                    .enumerate()
-                    .filter_map(|(i, chunk)| {
-                        let start =
-                            start_positions.orchard_start_position + (i * CHUNK_SIZE) as u64;
-                        let end = start + chunk.len() as u64;
+                    for (output_index, txout) in d_tx
+                        .tx()
+                        .transparent_bundle()
+                        .iter()
+                        .flat_map(|b| b.vout.iter())
+                        .enumerate()
+                    {
+                        if let Some(address) = txout.recipient_address() {
+                            let receiver = Receiver::Transparent(address);
 
-                        shardtree::LocatedTree::from_iter(
-                            start..end,
-                            ORCHARD_SHARD_HEIGHT.into(),
-                            chunk.iter_mut().map(|n| n.take().expect("always Some")),
-                        )
-                    })
-                    .map(|res| (res.subtree, res.checkpoints))
-                    .collect::<Vec<_>>();
+                            #[cfg(feature = "transparent-inputs")]
+                            let recipient_addr = wallet::select_receiving_address(
+                                &wdb.params,
+                                wdb.conn.0,
+                                account_id,
+                                &receiver
+                            )?.unwrap_or_else(||
+                                receiver.to_zcash_address(wdb.params.network_type())
+                            );
 
-                // Collect the complete set of Sapling checkpoints
+                            #[cfg(not(feature = "transparent-inputs"))]
+                            let recipient_addr = receiver.to_zcash_address(wdb.params.network_type());
+
+                            let recipient = Recipient::External(recipient_addr, PoolType::Transparent);
+
+                            wallet::put_sent_output(
+                                wdb.conn.0,
+                                account_id,
+                                tx_ref,
+                                output_index,
+                                &recipient,
+                                txout.value,
+                                None,
+                            )?;
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn store_sent_tx(&mut self, sent_tx: &SentTransaction<AccountId>) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| {
+            let tx_ref = wallet::put_tx_data(
+                wdb.conn.0,
+                sent_tx.tx(),
+                Some(sent_tx.fee_amount()),
+                Some(sent_tx.created()),
+            )?;
+
+            // Mark notes as spent.
+            //
+            // This locks the notes so they aren't selected again by a subsequent call to
+            // create_spend_to_address() before this transaction has been mined (at which point the notes
+            // get re-marked as spent).
+            //
+            // Assumes that create_spend_to_address() will never be called in parallel, which is a
+            // reasonable assumption for a light client such as a mobile phone.
+            if let Some(bundle) = sent_tx.tx().sapling_bundle() {
+                for spend in bundle.shielded_spends() {
+                    wallet::sapling::mark_sapling_note_spent(
+                        wdb.conn.0,
+                        tx_ref,
+                        spend.nullifier(),
+                    )?;
+                }
+            }
+            if let Some(_bundle) = sent_tx.tx().orchard_bundle() {
                 #[cfg(feature = "orchard")]
-                let sapling_checkpoint_positions: BTreeMap<BlockHeight, Position> =
-                    sapling_subtrees
-                        .iter()
-                        .flat_map(|(_, checkpoints)| checkpoints.iter())
-                        .map(|(k, v)| (*k, *v))
-                        .collect();
+                for action in _bundle.actions() {
+                    wallet::orchard::mark_orchard_note_spent(
+                        wdb.conn.0,
+                        tx_ref,
+                        action.nullifier(),
+                    )?;
+                }
+
+                #[cfg(not(feature = "orchard"))]
+                panic!("Sent a transaction with Orchard Actions without `orchard` enabled?");
+            }
 
-                #[cfg(feature = "orchard")]
-                let orchard_checkpoint_positions: BTreeMap<BlockHeight, Position> =
-                    orchard_subtrees
-                        .iter()
-                        .flat_map(|(_, checkpoints)| checkpoints.iter())
-                        .map(|(k, v)| (*k, *v))
-                        .collect();
+            #[cfg(feature = "transparent-inputs")]
+            for utxo_outpoint in sent_tx.utxos_spent() {
+                wallet::mark_transparent_utxo_spent(wdb.conn.0, tx_ref, utxo_outpoint)?;
+            }
 
-                #[cfg(feature = "orchard")]
-                fn ensure_checkpoints<
-                    'a,
-                    H,
-                    I: Iterator<Item = &'a BlockHeight>,
-                    const DEPTH: u8,
-                >(
-                    // An iterator of checkpoints heights for which we wish to ensure that
-                    // checkpoints exists.
-                    ensure_heights: I,
-                    // The map of checkpoint positions from which we will draw note commitment tree
-                    // position information for the newly created checkpoints.
-                    existing_checkpoint_positions: &BTreeMap<BlockHeight, Position>,
-                    // The frontier whose position will be used for an inserted checkpoint when
-                    // there is no preceding checkpoint in existing_checkpoint_positions.
-                    state_final_tree: &Frontier<H, DEPTH>,
-                ) -> Vec<(BlockHeight, Checkpoint)> {
-                    ensure_heights
-                        .flat_map(|ensure_height| {
-                            existing_checkpoint_positions
-                                .range::<BlockHeight, _>(..=*ensure_height)
-                                .last()
-                                .map_or_else(
-                                    || {
-                                        Some((
-                                            *ensure_height,
-                                            state_final_tree
-                                                .value()
-                                                .map_or_else(Checkpoint::tree_empty, |t| {
-                                                    Checkpoint::at_position(t.position())
-                                                }),
-                                        ))
-                                    },
-                                    |(existing_checkpoint_height, position)| {
-                                        if *existing_checkpoint_height < *ensure_height {
-                                            Some((
-                                                *ensure_height,
-                                                Checkpoint::at_position(*position),
-                                            ))
-                                        } else {
-                                            // The checkpoint already exists, so we don't need to
-                                            // do anything.
-                                            None
-                                        }
-                                    },
-                                )
-                                .into_iter()
-                        })
-                        .collect::<Vec<_>>()
+            // Outputs sent to an external address of another account in the wallet are
+            // received by that account; all other wallet-internal outputs are change.
+            let transfer_type = |external_address: &Option<_>| {
+                if external_address.is_some() {
+                    TransferType::Incoming
+                } else {
+                    TransferType::WalletInternal
                 }
+            };
 
-                #[cfg(feature = "orchard")]
-                let (missing_sapling_checkpoints, missing_orchard_checkpoints) = (
-                    ensure_checkpoints(
-                        orchard_checkpoint_positions.keys(),
-                        &sapling_checkpoint_positions,
-                        from_state.final_sapling_tree(),
-                    ),
-                    ensure_checkpoints(
-                        sapling_checkpoint_positions.keys(),
-                        &orchard_checkpoint_positions,
-                        from_state.final_orchard_tree(),
-                    ),
-                );
+            for output in sent_tx.outputs() {
+                wallet::insert_sent_output(wdb.conn.0, tx_ref, *sent_tx.account_id(), output)?;
 
-                // Update the Sapling note commitment tree with all newly read note commitments
-                {
-                    let mut sapling_subtrees_iter = sapling_subtrees.into_iter();
-                    wdb.with_sapling_tree_mut::<_, _, Self::Error>(|sapling_tree| {
-                        debug!(
-                            "Sapling initial tree size at {:?}: {:?}",
-                            from_state.block_height(),
-                            from_state.final_sapling_tree().tree_size()
-                        );
-                        sapling_tree.insert_frontier(
-                            from_state.final_sapling_tree().clone(),
-                            Retention::Checkpoint {
-                                id: from_state.block_height(),
-                                is_marked: false,
-                            },
+                match output.recipient() {
+                    Recipient::InternalAccount {
+                        receiving_account,
+                        external_address,
+                        note: Note::Sapling(note),
+                    } => {
+                        wallet::sapling::put_received_note(
+                            wdb.conn.0,
+                            &DecryptedOutput::new(
+                                output.output_index(),
+                                note.clone(),
+                                *receiving_account,
+                                output
+                                    .memo()
+                                    .map_or_else(MemoBytes::empty, |memo| memo.clone()),
+                                transfer_type(external_address),
+                            ),
+                            tx_ref,
+                            None,
+                        )?;
+                    }
+                    #[cfg(feature = "orchard")]
+                    Recipient::InternalAccount {
+                        receiving_account,
+                        external_address,
+                        note: Note::Orchard(note),
+                    } => {
+                        wallet::orchard::put_received_note(
+                            wdb.conn.0,
+                            &DecryptedOutput::new(
+                                output.output_index(),
+                                *note,
+                                *receiving_account,
+                                output
+                                    .memo()
+                                    .map_or_else(MemoBytes::empty, |memo| memo.clone()),
+                                transfer_type(external_address),
+                            ),
+                            tx_ref,
+                            None,
                         )?;
+                    }
+                    _ => (),
+                }
+            }
 
-                        for (tree, checkpoints) in &mut sapling_subtrees_iter {
-                            sapling_tree.insert_tree(tree, checkpoints)?;
-                        }
+            Ok(())
+        })
+    }
 
-                        // Ensure we have a Sapling checkpoint for each checkpointed Orchard block height.
-                        // We skip all checkpoints below the minimum retained checkpoint in the
-                        // Sapling tree, because branches below this height may be pruned.
-                        #[cfg(feature = "orchard")]
-                        {
-                            let min_checkpoint_height = sapling_tree
-                                .store()
-                                .min_checkpoint_id()
-                                .map_err(ShardTreeError::Storage)?
-                                .expect(
-                                    "At least one checkpoint was inserted (by insert_frontier)",
-                                );
+    fn abandon_transaction(&mut self, txid: TxId, force: bool) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| wallet::abandon_transaction(wdb.conn.0, &*wdb, txid, force))
+    }
 
-                            for (height, checkpoint) in &missing_sapling_checkpoints {
-                                if *height > min_checkpoint_height {
-                                    sapling_tree
-                                        .store_mut()
-                                        .add_checkpoint(*height, checkpoint.clone())
-                                        .map_err(ShardTreeError::Storage)?;
-                                }
-                            }
-                        }
+    fn import_transaction_annotations(
+        &mut self,
+        entries: &[TransactionAnnotation],
+    ) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| {
+            for entry in entries {
+                wallet::put_transaction_annotation(wdb.conn.0, entry)?;
+            }
+            Ok(())
+        })
+    }
 
-                        Ok(())
-                    })?;
-                }
+    fn add_watched_address(&mut self, address: &Address) -> Result<WatchedAddress, Self::Error> {
+        self.transactionally(|wdb| wallet::put_watched_address(wdb.conn.0, &wdb.params, address))
+    }
 
-                // Update the Orchard note commitment tree with all newly read note commitments
-                #[cfg(feature = "orchard")]
-                {
-                    let mut orchard_subtrees = orchard_subtrees.into_iter();
-                    wdb.with_orchard_tree_mut::<_, _, Self::Error>(|orchard_tree| {
-                        debug!(
-                            "Orchard initial tree size at {:?}: {:?}",
-                            from_state.block_height(),
-                            from_state.final_orchard_tree().tree_size()
-                        );
-                        orchard_tree.insert_frontier(
-                            from_state.final_orchard_tree().clone(),
-                            Retention::Checkpoint {
-                                id: from_state.block_height(),
-                                is_marked: false,
-                            },
-                        )?;
+    fn lock_note(&mut self, note_id: NoteId) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| wallet::set_note_locked(wdb.conn.0, note_id, true))
+    }
 
-                        for (tree, checkpoints) in &mut orchard_subtrees {
-                            orchard_tree.insert_tree(tree, checkpoints)?;
-                        }
+    fn unlock_note(&mut self, note_id: NoteId) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| wallet::set_note_locked(wdb.conn.0, note_id, false))
+    }
 
-                        // Ensure we have an Orchard checkpoint for each checkpointed Sapling block height.
-                        // We skip all checkpoints below the minimum retained checkpoint in the
-                        // Orchard tree, because branches below this height may be pruned.
-                        {
-                            let min_checkpoint_height = orchard_tree
-                                .store()
-                                .min_checkpoint_id()
-                                .map_err(ShardTreeError::Storage)?
-                                .expect(
-                                    "At least one checkpoint was inserted (by insert_frontier)",
-                                );
+    fn truncate_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| {
+            wallet::truncate_to_height(wdb.conn.0, &wdb.params, block_height)
+        })
+    }
 
-                            for (height, checkpoint) in &missing_orchard_checkpoints {
-                                if *height > min_checkpoint_height {
-                                    debug!(
-                                        "Adding missing Orchard checkpoint for height: {:?}: {:?}",
-                                        height,
-                                        checkpoint.position()
-                                    );
-                                    orchard_tree
-                                        .store_mut()
-                                        .add_checkpoint(*height, checkpoint.clone())
-                                        .map_err(ShardTreeError::Storage)?;
-                                }
-                            }
-                        }
-                        Ok(())
-                    })?;
-                }
+    fn clear_rescan_flag(&mut self) -> Result<(), Self::Error> {
+        wallet::clear_rescan_required(&self.conn)
+    }
 
-                wallet::scanning::scan_complete(
+    fn replace_commitment_tree(
+        &mut self,
+        new_tree: CommitmentTreeReplacement,
+        note_positions: &[(NoteId, Position)],
+    ) -> Result<(), Self::Error> {
+        self.transactionally(|wdb| {
+            let protocol = new_tree.protocol();
+            match new_tree {
+                CommitmentTreeReplacement::Sapling(store) => commitment_tree::replace_tree(
                     wdb.conn.0,
-                    &wdb.params,
-                    Range {
-                        start: start_positions.height,
-                        end: last_scanned_height + 1,
-                    },
-                    &note_positions,
-                )?;
+                    SAPLING_TABLES_PREFIX,
+                    SAPLING_SHARD_HEIGHT.into(),
+                    store,
+                ),
+                #[cfg(feature = "orchard")]
+                CommitmentTreeReplacement::Orchard(store) => commitment_tree::replace_tree(
+                    wdb.conn.0,
+                    ORCHARD_TABLES_PREFIX,
+                    ORCHARD_SHARD_HEIGHT.into(),
+                    store,
+                ),
             }
+            .map_err(|e| SqliteClientError::CommitmentTree(ShardTreeError::Storage(e)))?;
 
-            Ok(())
+            wallet::replace_note_commitment_tree_positions(wdb.conn.0, protocol, note_positions)
         })
     }
 
-    fn put_received_transparent_utxo(
+    #[cfg(feature = "transparent-inputs")]
+    fn put_latest_scanned_block_for_transparent(
         &mut self,
-        _output: &WalletTransparentOutput,
-    ) -> Result<Self::UtxoRef, Self::Error> {
+        _address: &TransparentAddress,
+        _block_height: BlockHeight,
+    ) -> Result<(), Self::Error> {
         #[cfg(feature = "transparent-inputs")]
-        return wallet::put_received_transparent_utxo(&self.conn, &self.params, _output);
+        return wallet::put_latest_scanned_block_for_transparent(
+            &self.conn,
+            &self.params,
+            _address,
+            _block_height,
+        );
 
         #[cfg(not(feature = "transparent-inputs"))]
         panic!(
             "The wallet must be compiled with the transparent-inputs feature to use this method."
-        );
+        )
     }
+}
 
-    fn store_decrypted_tx(
+impl<P: consensus::Parameters> WalletDb<rusqlite::Connection, P> {
+    #[allow(clippy::type_complexity)]
+    fn put_blocks_internal(
         &mut self,
-        d_tx: DecryptedTransaction<AccountId>,
-    ) -> Result<(), Self::Error> {
-        self.transactionally(|wdb| {
-            let tx_ref = wallet::put_tx_data(wdb.conn.0, d_tx.tx(), None, None)?;
-            let funding_accounts = wallet::get_funding_accounts(wdb.conn.0, d_tx.tx())?;
-            let funding_account = funding_accounts.iter().next().copied();
-            if funding_accounts.len() > 1 {
-                warn!(
-                    "More than one wallet account detected as funding transaction {:?}, selecting {:?}",
-                    d_tx.tx().txid(),
-                    funding_account.unwrap()
-                )
-            }
-
-            for output in d_tx.sapling_outputs() {
-                match output.transfer_type() {
-                    TransferType::Outgoing => {
-                        let recipient = {
-                            let receiver = Receiver::Sapling(output.note().recipient());
-                            let wallet_address = wallet::select_receiving_address(
-                                &wdb.params,
-                                wdb.conn.0,
-                                *output.account(),
-                                &receiver
-                            )?.unwrap_or_else(||
-                                receiver.to_zcash_address(wdb.params.network_type())
-                            );
+        from_state: &ChainState,
+        blocks: Vec<ScannedBlock<AccountId>>,
+        fully_scanned: bool,
+    ) -> Result<(), SqliteClientError> {
+        struct BlockPositions {
+            height: BlockHeight,
+            sapling_start_position: Position,
+            #[cfg(feature = "orchard")]
+            orchard_start_position: Position,
+        }
 
-                            Recipient::External(wallet_address, PoolType::Shielded(ShieldedProtocol::Sapling))
-                        };
+        let first_height = blocks.first().map(|block| block.height());
+        let result = self.transactionally(|wdb| {
+            let start_positions = blocks.first().map(|block| BlockPositions {
+                height: block.height(),
+                sapling_start_position: Position::from(
+                    u64::from(block.sapling().final_tree_size())
+                        - u64::try_from(block.sapling().commitments().len()).unwrap(),
+                ),
+                #[cfg(feature = "orchard")]
+                orchard_start_position: Position::from(
+                    u64::from(block.orchard().final_tree_size())
+                        - u64::try_from(block.orchard().commitments().len()).unwrap(),
+                ),
+            });
+            let mut sapling_commitments = vec![];
+            #[cfg(feature = "orchard")]
+            let mut orchard_commitments = vec![];
+            let mut last_scanned_height = None;
+            let mut note_positions = vec![];
+            // The external receivers at which notes in this batch were received, keyed by their
+            // raw diversifiers so that each receiver is checked against the addresses table only
+            // once. The accounts' viewing keys are only loaded if there is a receiver to check.
+            let mut sapling_receivers = HashSet::new();
+            #[cfg(feature = "orchard")]
+            let mut orchard_receivers = HashSet::new();
+            let mut ufvks = None;
+            for block in blocks.into_iter() {
+                if last_scanned_height
+                    .iter()
+                    .any(|prev| block.height() != *prev + 1)
+                {
+                    return Err(SqliteClientError::NonSequentialBlocks);
+                }
 
-                        wallet::put_sent_output(
-                            wdb.conn.0,
-                            *output.account(),
-                            tx_ref,
-                            output.index(),
-                            &recipient,
-                            output.note_value(),
-                            Some(output.memo()),
-                        )?;
-                    }
-                    TransferType::WalletInternal => {
-                        wallet::sapling::put_received_note(wdb.conn.0, output, tx_ref, None)?;
+                // Insert the block into the database.
+                wallet::put_block(
+                    wdb.conn.0,
+                    block.height(),
+                    block.block_hash(),
+                    block.block_time(),
+                    block.sapling().final_tree_size(),
+                    block.sapling().commitments().len().try_into().unwrap(),
+                    #[cfg(feature = "orchard")]
+                    block.orchard().final_tree_size(),
+                    #[cfg(feature = "orchard")]
+                    block.orchard().commitments().len().try_into().unwrap(),
+                )?;
 
-                        let recipient = Recipient::InternalAccount {
-                            receiving_account: *output.account(),
-                            external_address: None,
-                            note: Note::Sapling(output.note().clone()),
-                        };
+                for tx in block.transactions() {
+                    let tx_row = wallet::put_tx_meta(wdb.conn.0, tx, block.height())?;
 
-                        wallet::put_sent_output(
-                            wdb.conn.0,
-                            *output.account(),
-                            tx_ref,
-                            output.index(),
-                            &recipient,
-                            output.note_value(),
-                            Some(output.memo()),
-                        )?;
+                    // Mark notes as spent and remove them from the scanning cache
+                    for spend in tx.sapling_spends() {
+                        wallet::sapling::mark_sapling_note_spent(wdb.conn.0, tx_row, spend.nf())?;
                     }
-                    TransferType::Incoming => {
-                        wallet::sapling::put_received_note(wdb.conn.0, output, tx_ref, None)?;
-
-                        if let Some(account_id) = funding_account {
-                            let recipient = Recipient::InternalAccount {
-                                receiving_account: *output.account(),
-                                external_address: {
-                                    let receiver = Receiver::Sapling(output.note().recipient());
-                                    Some(wallet::select_receiving_address(
-                                        &wdb.params,
-                                        wdb.conn.0,
-                                        *output.account(),
-                                        &receiver
-                                    )?.unwrap_or_else(||
-                                        receiver.to_zcash_address(wdb.params.network_type())
-                                    ))
-                                },
-                                note: Note::Sapling(output.note().clone()),
-                            };
-
-                            wallet::put_sent_output(
-                                wdb.conn.0,
-                                account_id,
-                                tx_ref,
-                                output.index(),
-                                &recipient,
-                                output.note_value(),
-                                Some(output.memo()),
-                            )?;
-                        }
+                    #[cfg(feature = "orchard")]
+                    for spend in tx.orchard_spends() {
+                        wallet::orchard::mark_orchard_note_spent(wdb.conn.0, tx_row, spend.nf())?;
                     }
-                }
-            }
-
-            #[cfg(feature = "orchard")]
-            for output in d_tx.orchard_outputs() {
-                match output.transfer_type() {
-                    TransferType::Outgoing => {
-                        let recipient = {
-                            let receiver = Receiver::Orchard(output.note().recipient());
-                            let wallet_address = wallet::select_receiving_address(
-                                &wdb.params,
-                                wdb.conn.0,
-                                *output.account(),
-                                &receiver
-                            )?.unwrap_or_else(||
-                                receiver.to_zcash_address(wdb.params.network_type())
-                            );
 
-                            Recipient::External(wallet_address, PoolType::Shielded(ShieldedProtocol::Orchard))
-                        };
-
-                        wallet::put_sent_output(
-                            wdb.conn.0,
-                            *output.account(),
-                            tx_ref,
-                            output.index(),
-                            &recipient,
-                            output.note_value(),
-                            Some(output.memo()),
-                        )?;
-                    }
-                    TransferType::WalletInternal => {
-                        wallet::orchard::put_received_note(wdb.conn.0, output, tx_ref, None)?;
+                    for output in tx.sapling_outputs() {
+                        // Check whether this note was spent in a later block range that
+                        // we previously scanned.
+                        let spent_in = output
+                            .nf()
+                            .map(|nf| {
+                                wallet::query_nullifier_map(
+                                    wdb.conn.0,
+                                    ShieldedProtocol::Sapling,
+                                    nf,
+                                )
+                            })
+                            .transpose()?
+                            .flatten();
 
-                        let recipient = Recipient::InternalAccount {
-                            receiving_account: *output.account(),
-                            external_address: None,
-                            note: Note::Orchard(*output.note()),
-                        };
+                        wallet::sapling::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
 
-                        wallet::put_sent_output(
-                            wdb.conn.0,
-                            *output.account(),
-                            tx_ref,
-                            output.index(),
-                            &recipient,
-                            output.note_value(),
-                            Some(output.memo()),
-                        )?;
+                        // Register the address at which the note was received, in case the
+                        // wallet never generated it (as may happen after restoring from seed).
+                        if output.recipient_key_scope() == Some(Scope::External)
+                            && sapling_receivers.insert((
+                                *output.account_id(),
+                                output.note().recipient().diversifier().0,
+                            ))
+                        {
+                            if ufvks.is_none() {
+                                ufvks = Some(wallet::get_unified_full_viewing_keys(
+                                    wdb.conn.0,
+                                    &wdb.params,
+                                )?);
+                            }
+                            if let Some((ufvk, diversifier_index)) = ufvks
+                                .as_ref()
+                                .and_then(|ufvks| ufvks.get(output.account_id()))
+                                .and_then(|ufvk| {
+                                    wallet::sapling::external_diversifier_index(ufvk, output)
+                                        .map(|idx| (ufvk, idx))
+                                })
+                            {
+                                wallet::put_discovered_address(
+                                    wdb.conn.0,
+                                    &wdb.params,
+                                    *output.account_id(),
+                                    ufvk,
+                                    diversifier_index,
+                                )?;
+                            }
+                        }
                     }
-                    TransferType::Incoming => {
-                        wallet::orchard::put_received_note(wdb.conn.0, output, tx_ref, None)?;
+                    #[cfg(feature = "orchard")]
+                    for output in tx.orchard_outputs() {
+                        // Check whether this note was spent in a later block range that
+                        // we previously scanned.
+                        let spent_in = output
+                            .nf()
+                            .map(|nf| {
+                                wallet::query_nullifier_map(
+                                    wdb.conn.0,
+                                    ShieldedProtocol::Orchard,
+                                    &nf.to_bytes(),
+                                )
+                            })
+                            .transpose()?
+                            .flatten();
 
-                        if let Some(account_id) = funding_account {
-                            // Even if the recipient address is external, record the send as internal.
-                            let recipient = Recipient::InternalAccount {
-                                receiving_account: *output.account(),
-                                external_address: {
-                                    let receiver = Receiver::Orchard(output.note().recipient());
-                                    Some(wallet::select_receiving_address(
-                                        &wdb.params,
-                                        wdb.conn.0,
-                                        *output.account(),
-                                        &receiver
-                                    )?.unwrap_or_else(||
-                                        receiver.to_zcash_address(wdb.params.network_type())
-                                    ))
-                                },
-                                note: Note::Orchard(*output.note()),
-                            };
+                        wallet::orchard::put_received_note(wdb.conn.0, output, tx_row, spent_in)?;
 
-                            wallet::put_sent_output(
-                                wdb.conn.0,
-                                account_id,
-                                tx_ref,
-                                output.index(),
-                                &recipient,
-                                output.note_value(),
-                                Some(output.memo()),
-                            )?;
+                        if output.recipient_key_scope() == Some(Scope::External)
+                            && orchard_receivers.insert((
+                                *output.account_id(),
+                                *output.note().recipient().diversifier().as_array(),
+                            ))
+                        {
+                            if ufvks.is_none() {
+                                ufvks = Some(wallet::get_unified_full_viewing_keys(
+                                    wdb.conn.0,
+                                    &wdb.params,
+                                )?);
+                            }
+                            if let Some((ufvk, diversifier_index)) = ufvks
+                                .as_ref()
+                                .and_then(|ufvks| ufvks.get(output.account_id()))
+                                .and_then(|ufvk| {
+                                    wallet::orchard::external_diversifier_index(ufvk, output)
+                                        .map(|idx| (ufvk, idx))
+                                })
+                            {
+                                wallet::put_discovered_address(
+                                    wdb.conn.0,
+                                    &wdb.params,
+                                    *output.account_id(),
+                                    ufvk,
+                                    diversifier_index,
+                                )?;
+                            }
                         }
                     }
                 }
+
+                // Insert the new nullifiers from this block into the nullifier map.
+                wallet::insert_nullifier_map(
+                    wdb.conn.0,
+                    block.height(),
+                    ShieldedProtocol::Sapling,
+                    block.sapling().nullifier_map(),
+                )?;
+                #[cfg(feature = "orchard")]
+                wallet::insert_nullifier_map(
+                    wdb.conn.0,
+                    block.height(),
+                    ShieldedProtocol::Orchard,
+                    &block
+                        .orchard()
+                        .nullifier_map()
+                        .iter()
+                        .map(|(txid, idx, nfs)| {
+                            (*txid, *idx, nfs.iter().map(|nf| nf.to_bytes()).collect())
+                        })
+                        .collect::<Vec<_>>(),
+                )?;
+
+                note_positions.extend(block.transactions().iter().flat_map(|wtx| {
+                    let iter = wtx.sapling_outputs().iter().map(|out| {
+                        (
+                            ShieldedProtocol::Sapling,
+                            out.note_commitment_tree_position(),
+                        )
+                    });
+                    #[cfg(feature = "orchard")]
+                    let iter = iter.chain(wtx.orchard_outputs().iter().map(|out| {
+                        (
+                            ShieldedProtocol::Orchard,
+                            out.note_commitment_tree_position(),
+                        )
+                    }));
+
+                    iter
+                }));
+
+                last_scanned_height = Some(block.height());
+                let block_commitments = block.into_commitments();
+                trace!(
+                    "Sapling commitments for {:?}: {:?}",
+                    last_scanned_height,
+                    block_commitments
+                        .sapling
+                        .iter()
+                        .map(|(_, r)| *r)
+                        .collect::<Vec<_>>()
+                );
+                #[cfg(feature = "orchard")]
+                trace!(
+                    "Orchard commitments for {:?}: {:?}",
+                    last_scanned_height,
+                    block_commitments
+                        .orchard
+                        .iter()
+                        .map(|(_, r)| *r)
+                        .collect::<Vec<_>>()
+                );
+
+                sapling_commitments.extend(block_commitments.sapling.into_iter().map(Some));
+                #[cfg(feature = "orchard")]
+                orchard_commitments.extend(block_commitments.orchard.into_iter().map(Some));
             }
 
-            // If any of the utxos spent in the transaction are ours, mark them as spent.
-            #[cfg(feature = "transparent-inputs")]
-            for txin in d_tx
-                .tx()
-                .transparent_bundle()
-                .iter()
-                .flat_map(|b| b.vin.iter())
-            {
-                wallet::mark_transparent_utxo_spent(wdb.conn.0, tx_ref, &txin.prevout)?;
+            // Prune the nullifier map and the transparent spend map of entries we no longer
+            // need.
+            if let Some(meta) = wdb.block_fully_scanned()? {
+                let pruning_height = meta.block_height().saturating_sub(PRUNING_DEPTH);
+                wallet::prune_nullifier_map(wdb.conn.0, pruning_height)?;
+                #[cfg(feature = "transparent-inputs")]
+                wallet::prune_transparent_spend_map(wdb.conn.0, pruning_height)?;
             }
 
-            // If we have some transparent outputs:
-            if d_tx
-                .tx()
-                .transparent_bundle()
-                .iter()
-                .any(|b| !b.vout.is_empty())
+            // We will have a start position and a last scanned height in all cases where
+            // `blocks` is non-empty.
+            if let Some((start_positions, last_scanned_height)) =
+                start_positions.zip(last_scanned_height)
             {
-                // If the transaction contains spends from our wallet, we will store z->t
-                // transactions we observe in the same way they would be stored by
-                // create_spend_to_address.
-                let funding_accounts = wallet::get_funding_accounts(wdb.conn.0, d_tx.tx())?;
-                let funding_account = funding_accounts.iter().next().copied();
-                if let Some(account_id) = funding_account {
-                    if funding_accounts.len() > 1 {
-                        warn!(
-                            "More than one wallet account detected as funding transaction {:?}, selecting {:?}",
-                            d_tx.tx().txid(),
-                            account_id
+                // Create subtrees from the note commitments in parallel.
+                const CHUNK_SIZE: usize = 1024;
+                let sapling_subtrees = sapling_commitments
+                    .par_chunks_mut(CHUNK_SIZE)
+                    .enumerate()
+                    .filter_map(|(i, chunk)| {
+                        let start =
+                            start_positions.sapling_start_position + (i * CHUNK_SIZE) as u64;
+                        let end = start + chunk.len() as u64;
+
+                        shardtree::LocatedTree::from_iter(
+                            start..end,
+                            SAPLING_SHARD_HEIGHT.into(),
+                            chunk.iter_mut().map(|n| n.take().expect("always Some")),
                         )
-                    }
+                    })
+                    .map(|res| (res.subtree, res.checkpoints))
+                    .collect::<Vec<_>>();
 
-                    for (output_index, txout) in d_tx
-                        .tx()
-                        .transparent_bundle()
+                #[cfg(feature = "orchard")]
+                let orchard_subtrees = orchard_commitments
+                    .par_chunks_mut(CHUNK_SIZE)
+                    .enumerate()
+                    .filter_map(|(i, chunk)| {
+                        let start =
+                            start_positions.orchard_start_position + (i * CHUNK_SIZE) as u64;
+                        let end = start + chunk.len() as u64;
+
+                        shardtree::LocatedTree::from_iter(
+                            start..end,
+                            ORCHARD_SHARD_HEIGHT.into(),
+                            chunk.iter_mut().map(|n| n.take().expect("always Some")),
+                        )
+                    })
+                    .map(|res| (res.subtree, res.checkpoints))
+                    .collect::<Vec<_>>();
+
+                // Collect the complete set of Sapling checkpoints
+                #[cfg(feature = "orchard")]
+                let sapling_checkpoint_positions: BTreeMap<BlockHeight, Position> =
+                    sapling_subtrees
                         .iter()
-                        .flat_map(|b| b.vout.iter())
-                        .enumerate()
-                    {
-                        if let Some(address) = txout.recipient_address() {
-                            let receiver = Receiver::Transparent(address);
+                        .flat_map(|(_, checkpoints)| checkpoints.iter())
+                        .map(|(k, v)| (*k, *v))
+                        .collect();
 
-                            #[cfg(feature = "transparent-inputs")]
-                            let recipient_addr = wallet::select_receiving_address(
-                                &wdb.params,
-                                wdb.conn.0,
-                                account_id,
-                                &receiver
-                            )?.unwrap_or_else(||
-                                receiver.to_zcash_address(wdb.params.network_type())
-                            );
+                #[cfg(feature = "orchard")]
+                let orchard_checkpoint_positions: BTreeMap<BlockHeight, Position> =
+                    orchard_subtrees
+                        .iter()
+                        .flat_map(|(_, checkpoints)| checkpoints.iter())
+                        .map(|(k, v)| (*k, *v))
+                        .collect();
 
-                            #[cfg(not(feature = "transparent-inputs"))]
-                            let recipient_addr = receiver.to_zcash_address(wdb.params.network_type());
+                #[cfg(feature = "orchard")]
+                fn ensure_checkpoints<
+                    'a,
+                    H,
+                    I: Iterator<Item = &'a BlockHeight>,
+                    const DEPTH: u8,
+                >(
+                    // An iterator of checkpoints heights for which we wish to ensure that
+                    // checkpoints exists.
+                    ensure_heights: I,
+                    // The map of checkpoint positions from which we will draw note commitment tree
+                    // position information for the newly created checkpoints.
+                    existing_checkpoint_positions: &BTreeMap<BlockHeight, Position>,
+                    // The frontier whose position will be used for an inserted checkpoint when
+                    // there is no preceding checkpoint in existing_checkpoint_positions.
+                    state_final_tree: &Frontier<H, DEPTH>,
+                ) -> Vec<(BlockHeight, Checkpoint)> {
+                    ensure_heights
+                        .flat_map(|ensure_height| {
+                            existing_checkpoint_positions
+                                .range::<BlockHeight, _>(..=*ensure_height)
+                                .last()
+                                .map_or_else(
+                                    || {
+                                        Some((
+                                            *ensure_height,
+                                            state_final_tree
+                                                .value()
+                                                .map_or_else(Checkpoint::tree_empty, |t| {
+                                                    Checkpoint::at_position(t.position())
+                                                }),
+                                        ))
+                                    },
+                                    |(existing_checkpoint_height, position)| {
+                                        if *existing_checkpoint_height < *ensure_height {
+                                            Some((
+                                                *ensure_height,
+                                                Checkpoint::at_position(*position),
+                                            ))
+                                        } else {
+                                            // The checkpoint already exists, so we don't need to
+                                            // do anything.
+                                            None
+                                        }
+                                    },
+                                )
+                                .into_iter()
+                        })
+                        .collect::<Vec<_>>()
+                }
 
-                            let recipient = Recipient::External(recipient_addr, PoolType::Transparent);
+                #[cfg(feature = "orchard")]
+                let (missing_sapling_checkpoints, missing_orchard_checkpoints) = (
+                    ensure_checkpoints(
+                        orchard_checkpoint_positions.keys(),
+                        &sapling_checkpoint_positions,
+                        from_state.final_sapling_tree(),
+                    ),
+                    ensure_checkpoints(
+                        sapling_checkpoint_positions.keys(),
+                        &orchard_checkpoint_positions,
+                        from_state.final_orchard_tree(),
+                    ),
+                );
 
-                            wallet::put_sent_output(
-                                wdb.conn.0,
-                                account_id,
-                                tx_ref,
-                                output_index,
-                                &recipient,
-                                txout.value,
-                                None,
-                            )?;
+                // Update the Sapling note commitment tree with all newly read note commitments
+                {
+                    let mut sapling_subtrees_iter = sapling_subtrees.into_iter();
+                    wdb.with_sapling_tree_mut::<_, _, SqliteClientError>(|sapling_tree| {
+                        debug!(
+                            "Sapling initial tree size at {:?}: {:?}",
+                            from_state.block_height(),
+                            from_state.final_sapling_tree().tree_size()
+                        );
+                        sapling_tree.insert_frontier(
+                            from_state.final_sapling_tree().clone(),
+                            Retention::Checkpoint {
+                                id: from_state.block_height(),
+                                is_marked: false,
+                            },
+                        )?;
+
+                        for (tree, checkpoints) in &mut sapling_subtrees_iter {
+                            sapling_tree.insert_tree(tree, checkpoints)?;
                         }
-                    }
-                }
-            }
 
-            Ok(())
-        })
-    }
+                        // Ensure we have a Sapling checkpoint for each checkpointed Orchard block height.
+                        // We skip all checkpoints below the minimum retained checkpoint in the
+                        // Sapling tree, because branches below this height may be pruned.
+                        #[cfg(feature = "orchard")]
+                        {
+                            let min_checkpoint_height = sapling_tree
+                                .store()
+                                .min_checkpoint_id()
+                                .map_err(ShardTreeError::Storage)?
+                                .expect(
+                                    "At least one checkpoint was inserted (by insert_frontier)",
+                                );
 
-    fn store_sent_tx(&mut self, sent_tx: &SentTransaction<AccountId>) -> Result<(), Self::Error> {
-        self.transactionally(|wdb| {
-            let tx_ref = wallet::put_tx_data(
-                wdb.conn.0,
-                sent_tx.tx(),
-                Some(sent_tx.fee_amount()),
-                Some(sent_tx.created()),
-            )?;
+                            for (height, checkpoint) in &missing_sapling_checkpoints {
+                                if *height > min_checkpoint_height {
+                                    sapling_tree
+                                        .store_mut()
+                                        .add_checkpoint(*height, checkpoint.clone())
+                                        .map_err(ShardTreeError::Storage)?;
+                                }
+                            }
+                        }
 
-            // Mark notes as spent.
-            //
-            // This locks the notes so they aren't selected again by a subsequent call to
-            // create_spend_to_address() before this transaction has been mined (at which point the notes
-            // get re-marked as spent).
-            //
-            // Assumes that create_spend_to_address() will never be called in parallel, which is a
-            // reasonable assumption for a light client such as a mobile phone.
-            if let Some(bundle) = sent_tx.tx().sapling_bundle() {
-                for spend in bundle.shielded_spends() {
-                    wallet::sapling::mark_sapling_note_spent(
-                        wdb.conn.0,
-                        tx_ref,
-                        spend.nullifier(),
-                    )?;
-                }
-            }
-            if let Some(_bundle) = sent_tx.tx().orchard_bundle() {
-                #[cfg(feature = "orchard")]
-                for action in _bundle.actions() {
-                    wallet::orchard::mark_orchard_note_spent(
-                        wdb.conn.0,
-                        tx_ref,
-                        action.nullifier(),
-                    )?;
+                        Ok(())
+                    })?;
                 }
 
-                #[cfg(not(feature = "orchard"))]
-                panic!("Sent a transaction with Orchard Actions without `orchard` enabled?");
-            }
+                // Update the Orchard note commitment tree with all newly read note commitments
+                #[cfg(feature = "orchard")]
+                {
+                    let mut orchard_subtrees = orchard_subtrees.into_iter();
+                    wdb.with_orchard_tree_mut::<_, _, SqliteClientError>(|orchard_tree| {
+                        debug!(
+                            "Orchard initial tree size at {:?}: {:?}",
+                            from_state.block_height(),
+                            from_state.final_orchard_tree().tree_size()
+                        );
+                        orchard_tree.insert_frontier(
+                            from_state.final_orchard_tree().clone(),
+                            Retention::Checkpoint {
+                                id: from_state.block_height(),
+                                is_marked: false,
+                            },
+                        )?;
 
-            #[cfg(feature = "transparent-inputs")]
-            for utxo_outpoint in sent_tx.utxos_spent() {
-                wallet::mark_transparent_utxo_spent(wdb.conn.0, tx_ref, utxo_outpoint)?;
-            }
+                        for (tree, checkpoints) in &mut orchard_subtrees {
+                            orchard_tree.insert_tree(tree, checkpoints)?;
+                        }
 
-            for output in sent_tx.outputs() {
-                wallet::insert_sent_output(wdb.conn.0, tx_ref, *sent_tx.account_id(), output)?;
+                        // Ensure we have an Orchard checkpoint for each checkpointed Sapling block height.
+                        // We skip all checkpoints below the minimum retained checkpoint in the
+                        // Orchard tree, because branches below this height may be pruned.
+                        {
+                            let min_checkpoint_height = orchard_tree
+                                .store()
+                                .min_checkpoint_id()
+                                .map_err(ShardTreeError::Storage)?
+                                .expect(
+                                    "At least one checkpoint was inserted (by insert_frontier)",
+                                );
 
-                match output.recipient() {
-                    Recipient::InternalAccount {
-                        receiving_account,
-                        note: Note::Sapling(note),
-                        ..
-                    } => {
-                        wallet::sapling::put_received_note(
-                            wdb.conn.0,
-                            &DecryptedOutput::new(
-                                output.output_index(),
-                                note.clone(),
-                                *receiving_account,
-                                output
-                                    .memo()
-                                    .map_or_else(MemoBytes::empty, |memo| memo.clone()),
-                                TransferType::WalletInternal,
-                            ),
-                            tx_ref,
-                            None,
-                        )?;
-                    }
-                    #[cfg(feature = "orchard")]
-                    Recipient::InternalAccount {
-                        receiving_account,
-                        note: Note::Orchard(note),
-                        ..
-                    } => {
-                        wallet::orchard::put_received_note(
-                            wdb.conn.0,
-                            &DecryptedOutput::new(
-                                output.output_index(),
-                                *note,
-                                *receiving_account,
-                                output
-                                    .memo()
-                                    .map_or_else(MemoBytes::empty, |memo| memo.clone()),
-                                TransferType::WalletInternal,
-                            ),
-                            tx_ref,
-                            None,
-                        )?;
-                    }
-                    _ => (),
+                            for (height, checkpoint) in &missing_orchard_checkpoints {
+                                if *height > min_checkpoint_height {
+                                    debug!(
+                                        "Adding missing Orchard checkpoint for height: {:?}: {:?}",
+                                        height,
+                                        checkpoint.position()
+                                    );
+                                    orchard_tree
+                                        .store_mut()
+                                        .add_checkpoint(*height, checkpoint.clone())
+                                        .map_err(ShardTreeError::Storage)?;
+                                }
+                            }
+                        }
+                        Ok(())
+                    })?;
                 }
+
+                wallet::scanning::scan_complete(
+                    wdb.conn.0,
+                    &wdb.params,
+                    Range {
+                        start: start_positions.height,
+                        end: last_scanned_height + 1,
+                    },
+                    &note_positions,
+                    fully_scanned,
+                )?;
             }
 
             Ok(())
-        })
-    }
-
-    fn truncate_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error> {
-        self.transactionally(|wdb| {
-            wallet::truncate_to_height(wdb.conn.0, &wdb.params, block_height)
-        })
-    }
+        });
 
-    #[cfg(feature = "transparent-inputs")]
-    fn put_latest_scanned_block_for_transparent(
-        &mut self,
-        _address: &TransparentAddress,
-        _block_height: BlockHeight,
-    ) -> Result<(), Self::Error> {
-        #[cfg(feature = "transparent-inputs")]
-        return wallet::put_latest_scanned_block_for_transparent(
-            &self.conn,
-            &self.params,
-            _address,
-            _block_height,
-        );
+        // A failure to insert the scanned note commitments indicates that the wallet's note
+        // commitment tree data is inconsistent with the chain being scanned. Rescanning the same
+        // range will fail in the same way, so record that a rescan is required; this is done
+        // outside of the (rolled back) database transaction so that the flag persists.
+        if let (Err(SqliteClientError::CommitmentTree(ShardTreeError::Insert(e))), Some(height)) =
+            (&result, first_height)
+        {
+            warn!(
+                "Note commitment tree inconsistency detected at height {}: {}",
+                height, e
+            );
+            // Report the original error even if the flag could not be set.
+            if let Err(flag_err) = wallet::set_rescan_required(&self.conn, height) {
+                warn!(
+                    "Unable to record that a rescan is required from height {}: {}",
+                    height, flag_err
+                );
+            }
+        }
 
-        #[cfg(not(feature = "transparent-inputs"))]
-        panic!(
-            "The wallet must be compiled with the transparent-inputs feature to use this method."
-        )
+        result
     }
 }
 
@@ -1565,6 +2233,10 @@ impl<P: consensus::Parameters> WalletCommitmentTrees for WalletDb<rusqlite::Conn
     type SaplingShardStore<'a> =
         SqliteShardStore<&'a rusqlite::Transaction<'a>, sapling::Node, SAPLING_SHARD_HEIGHT>;
 
+    fn checkpoint_retention(&self) -> usize {
+        self.checkpoint_retention
+    }
+
     fn with_sapling_tree_mut<F, A, E>(&mut self, mut callback: F) -> Result<A, E>
     where
         for<'a> F: FnMut(
@@ -1583,7 +2255,7 @@ impl<P: consensus::Parameters> WalletCommitmentTrees for WalletDb<rusqlite::Conn
         let shard_store = SqliteShardStore::from_connection(&tx, SAPLING_TABLES_PREFIX)
             .map_err(|e| ShardTreeError::Storage(commitment_tree::Error::Query(e)))?;
         let result = {
-            let mut shardtree = ShardTree::new(shard_store, PRUNING_DEPTH.try_into().unwrap());
+            let mut shardtree = ShardTree::new(shard_store, self.checkpoint_retention);
             callback(&mut shardtree)?
         };
 
@@ -1638,7 +2310,7 @@ impl<P: consensus::Parameters> WalletCommitmentTrees for WalletDb<rusqlite::Conn
         let shard_store = SqliteShardStore::from_connection(&tx, ORCHARD_TABLES_PREFIX)
             .map_err(|e| ShardTreeError::Storage(commitment_tree::Error::Query(e)))?;
         let result = {
-            let mut shardtree = ShardTree::new(shard_store, PRUNING_DEPTH.try_into().unwrap());
+            let mut shardtree = ShardTree::new(shard_store, self.checkpoint_retention);
             callback(&mut shardtree)?
         };
 
@@ -1674,6 +2346,10 @@ impl<'conn, P: consensus::Parameters> WalletCommitmentTrees for WalletDb<SqlTran
     type SaplingShardStore<'a> =
         SqliteShardStore<&'a rusqlite::Transaction<'a>, sapling::Node, SAPLING_SHARD_HEIGHT>;
 
+    fn checkpoint_retention(&self) -> usize {
+        self.checkpoint_retention
+    }
+
     fn with_sapling_tree_mut<F, A, E>(&mut self, mut callback: F) -> Result<A, E>
     where
         for<'a> F: FnMut(
@@ -1688,7 +2364,7 @@ impl<'conn, P: consensus::Parameters> WalletCommitmentTrees for WalletDb<SqlTran
         let mut shardtree = ShardTree::new(
             SqliteShardStore::from_connection(self.conn.0, SAPLING_TABLES_PREFIX)
                 .map_err(|e| ShardTreeError::Storage(commitment_tree::Error::Query(e)))?,
-            PRUNING_DEPTH.try_into().unwrap(),
+            self.checkpoint_retention,
         );
         let result = callback(&mut shardtree)?;
 
@@ -1730,7 +2406,7 @@ impl<'conn, P: consensus::Parameters> WalletCommitmentTrees for WalletDb<SqlTran
         let mut shardtree = ShardTree::new(
             SqliteShardStore::from_connection(self.conn.0, ORCHARD_TABLES_PREFIX)
                 .map_err(|e| ShardTreeError::Storage(commitment_tree::Error::Query(e)))?,
-            PRUNING_DEPTH.try_into().unwrap(),
+            self.checkpoint_retention,
         );
         let result = callback(&mut shardtree)?;
 
@@ -2016,14 +2692,22 @@ extern crate assert_matches;
 
 #[cfg(test)]
 mod tests {
-    use secrecy::{Secret, SecretVec};
+    use secrecy::{ExposeSecret, Secret, SecretVec};
     use zcash_client_backend::data_api::{
-        chain::ChainState, Account, AccountBirthday, AccountSource, WalletRead, WalletWrite,
+        chain::ChainState, Account, AccountBirthday, AccountSource, TransactionAnnotation,
+        WalletRead, WalletWrite,
+    };
+    use zcash_keys::{
+        address::{Address, AddressFormat},
+        keys::{UnifiedFullViewingKey, UnifiedSpendingKey},
     };
-    use zcash_keys::keys::UnifiedSpendingKey;
-    use zcash_primitives::block::BlockHash;
+    use zcash_primitives::{block::BlockHash, legacy::TransparentAddress, transaction::TxId};
 
-    use crate::{error::SqliteClientError, testing::TestBuilder, AccountId, DEFAULT_UA_REQUEST};
+    use zip32::Scope;
+
+    use crate::{
+        error::SqliteClientError, testing::TestBuilder, wallet, AccountId, DEFAULT_UA_REQUEST,
+    };
 
     #[cfg(feature = "unstable")]
     use {
@@ -2060,6 +2744,119 @@ mod tests {
         });
     }
 
+    #[test]
+    fn derive_usk() {
+        let st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().unwrap();
+        let network = st.network();
+
+        let usk = st
+            .wallet()
+            .derive_usk(account.account_id(), st.test_seed().unwrap())
+            .unwrap();
+        assert_eq!(
+            usk.to_unified_full_viewing_key().encode(&network),
+            account.usk().to_unified_full_viewing_key().encode(&network)
+        );
+
+        // An unknown account cannot be used.
+        assert_matches!(
+            st.wallet()
+                .derive_usk(AccountId(3), st.test_seed().unwrap()),
+            Err(SqliteClientError::AccountUnknown)
+        );
+
+        // A seed that does not match the account's seed fingerprint is rejected.
+        assert_matches!(
+            st.wallet()
+                .derive_usk(account.account_id(), &SecretVec::new(vec![1u8; 32])),
+            Err(SqliteClientError::SeedMismatch)
+        );
+    }
+
+    #[test]
+    fn verify_derived_keys() {
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().cloned().unwrap();
+        let seed = SecretVec::new(st.test_seed().unwrap().expose_secret().clone());
+        let network = st.network();
+
+        // An imported account is not checked, even if it was derived from the seed.
+        let usk = UnifiedSpendingKey::from_seed(
+            &network,
+            seed.expose_secret(),
+            zip32::AccountId::try_from(1).unwrap(),
+        )
+        .unwrap();
+        st.wallet_mut()
+            .import_account_ufvk(
+                &usk.to_unified_full_viewing_key(),
+                account.birthday(),
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(st.wallet().verify_derived_keys(&seed).unwrap(), vec![]);
+
+        // A seed from which no account in the wallet was derived is rejected.
+        assert_matches!(
+            st.wallet()
+                .verify_derived_keys(&SecretVec::new(vec![1u8; 32])),
+            Err(SqliteClientError::SeedMismatch)
+        );
+
+        // A stored UFVK that omits some of the derived key's components still matches.
+        let derived_usk =
+            UnifiedSpendingKey::from_seed(&network, seed.expose_secret(), zip32::AccountId::ZERO)
+                .unwrap();
+        let shielded_ufvk = UnifiedFullViewingKey::new(
+            #[cfg(feature = "transparent-inputs")]
+            None,
+            Some(derived_usk.sapling().to_diversifiable_full_viewing_key()),
+            None,
+        )
+        .unwrap();
+        st.wallet()
+            .conn
+            .execute(
+                "UPDATE accounts SET ufvk = :ufvk WHERE id = :account_id",
+                rusqlite::named_params![
+                    ":ufvk": shielded_ufvk.encode(&network),
+                    ":account_id": account.account_id().0,
+                ],
+            )
+            .unwrap();
+        assert_eq!(st.wallet().verify_derived_keys(&seed).unwrap(), vec![]);
+
+        // Replace the derived account's stored UFVK with one for a different account index.
+        let other_ufvk = UnifiedSpendingKey::from_seed(
+            &network,
+            seed.expose_secret(),
+            zip32::AccountId::try_from(2).unwrap(),
+        )
+        .unwrap()
+        .to_unified_full_viewing_key();
+        st.wallet()
+            .conn
+            .execute(
+                "UPDATE accounts SET ufvk = :ufvk WHERE id = :account_id",
+                rusqlite::named_params![
+                    ":ufvk": other_ufvk.encode(&network),
+                    ":account_id": account.account_id().0,
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(
+            st.wallet().verify_derived_keys(&seed).unwrap(),
+            vec![account.account_id()]
+        );
+    }
+
     #[test]
     pub(crate) fn get_next_available_address() {
         let mut st = TestBuilder::new()
@@ -2087,6 +2884,151 @@ mod tests {
         assert_eq!(addr2, addr2_cur);
     }
 
+    #[test]
+    fn get_current_address_encoded() {
+        let st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account_id = st.test_account().unwrap().account_id();
+
+        let current_addr = st
+            .wallet()
+            .get_current_address(account_id)
+            .unwrap()
+            .unwrap();
+        let encoded = |format| {
+            st.wallet()
+                .get_current_address_encoded(account_id, format)
+                .unwrap()
+        };
+
+        assert_eq!(
+            encoded(AddressFormat::Unified),
+            Some(current_addr.encode(&st.network()))
+        );
+
+        // The legacy formats encode the corresponding receiver of the current address.
+        assert_eq!(
+            encoded(AddressFormat::SaplingLegacy)
+                .and_then(|addr| Address::decode(&st.network(), &addr)),
+            current_addr.sapling().map(|pa| Address::Sapling(*pa))
+        );
+        assert_eq!(
+            encoded(AddressFormat::TransparentLegacy)
+                .and_then(|addr| Address::decode(&st.network(), &addr)),
+            current_addr
+                .transparent()
+                .map(|taddr| Address::Transparent(*taddr))
+        );
+
+        // An unknown account has no current address.
+        assert_eq!(
+            st.wallet()
+                .get_current_address_encoded(AccountId(3), AddressFormat::Unified)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn get_internal_address() {
+        let st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().cloned().unwrap();
+
+        let internal_addr = st
+            .wallet()
+            .get_internal_address(account.account_id())
+            .unwrap()
+            .unwrap();
+
+        // The internal address contains the account's change receivers.
+        let sapling_dfvk = account.usk().sapling().to_diversifiable_full_viewing_key();
+        assert_eq!(
+            internal_addr.sapling(),
+            Some(&sapling_dfvk.change_address().1)
+        );
+        #[cfg(feature = "orchard")]
+        {
+            let orchard_fvk = orchard::keys::FullViewingKey::from(account.usk().orchard());
+            assert_eq!(
+                internal_addr.orchard(),
+                Some(&orchard_fvk.address_at(0u32, zip32::Scope::Internal))
+            );
+        }
+        assert_eq!(internal_addr.transparent(), None);
+
+        // The internal address is distinct from the account's external addresses.
+        let current_addr = st
+            .wallet()
+            .get_current_address(account.account_id())
+            .unwrap();
+        assert_ne!(current_addr, Some(internal_addr));
+
+        // An unknown account has no internal address.
+        assert_eq!(
+            st.wallet().get_internal_address(AccountId(3)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn get_max_diversifier_index() {
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().cloned().unwrap();
+
+        let (_, default_idx) = wallet::get_current_address(
+            &st.wallet().conn,
+            &st.wallet().params,
+            account.account_id(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            st.wallet()
+                .get_max_diversifier_index(account.account_id(), Scope::External)
+                .unwrap(),
+            Some(default_idx)
+        );
+
+        // Allocating a new address advances the maximum index.
+        st.wallet_mut()
+            .get_next_available_address(account.account_id(), DEFAULT_UA_REQUEST)
+            .unwrap()
+            .unwrap();
+        let (_, next_idx) = wallet::get_current_address(
+            &st.wallet().conn,
+            &st.wallet().params,
+            account.account_id(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_ne!(next_idx, default_idx);
+        assert_eq!(
+            st.wallet()
+                .get_max_diversifier_index(account.account_id(), Scope::External)
+                .unwrap(),
+            Some(next_idx)
+        );
+
+        // No internal-scope addresses are allocated, and unknown accounts have none.
+        assert_eq!(
+            st.wallet()
+                .get_max_diversifier_index(account.account_id(), Scope::Internal)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            st.wallet()
+                .get_max_diversifier_index(AccountId(3), Scope::External)
+                .unwrap(),
+            None
+        );
+    }
+
     #[test]
     pub(crate) fn import_account_hd_0() {
         let st = TestBuilder::new()
@@ -2173,6 +3115,334 @@ mod tests {
         );
     }
 
+    #[test]
+    fn get_or_create_account() {
+        let mut st = TestBuilder::new().build();
+
+        let birthday = AccountBirthday::from_parts(
+            ChainState::empty(st.wallet().params.sapling.unwrap() - 1, BlockHash([0; 32])),
+            None,
+        );
+
+        let seed = Secret::new(vec![0u8; 32]);
+        let zip32_index = zip32::AccountId::ZERO.next().unwrap();
+        let (first, first_usk) = st
+            .wallet_mut()
+            .get_or_create_account(&seed, zip32_index, &birthday)
+            .unwrap();
+
+        // A second call returns the existing account rather than creating a new one.
+        let (second, second_usk) = st
+            .wallet_mut()
+            .get_or_create_account(&seed, zip32_index, &birthday)
+            .unwrap();
+        assert_eq!(first.id(), second.id());
+        assert_eq!(
+            first_usk
+                .to_unified_full_viewing_key()
+                .encode(&st.wallet().params),
+            second_usk
+                .to_unified_full_viewing_key()
+                .encode(&st.wallet().params)
+        );
+        assert_eq!(st.wallet().get_account_ids().unwrap(), vec![first.id()]);
+
+        // An account created via `import_account_hd` is also returned.
+        let next_index = zip32_index.next().unwrap();
+        let (imported, _) = st
+            .wallet_mut()
+            .import_account_hd(&seed, next_index, &birthday)
+            .unwrap();
+        let (existing, _) = st
+            .wallet_mut()
+            .get_or_create_account(&seed, next_index, &birthday)
+            .unwrap();
+        assert_eq!(existing.id(), imported.id());
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn get_transparent_account_pubkey() {
+        let mut st = TestBuilder::new().build();
+
+        let birthday = AccountBirthday::from_parts(
+            ChainState::empty(st.wallet().params.sapling.unwrap() - 1, BlockHash([0; 32])),
+            None,
+        );
+
+        // A seed-derived account exposes the transparent key derived from its seed.
+        let seed = Secret::new(vec![0u8; 32]);
+        let (derived_id, usk) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+        assert_eq!(
+            st.wallet()
+                .get_transparent_account_pubkey(derived_id)
+                .unwrap()
+                .map(|pubkey| pubkey.serialize()),
+            Some(usk.transparent().to_account_pubkey().serialize())
+        );
+
+        // A watch-only account exposes the transparent component of its UFVK.
+        let other_seed = vec![1u8; 32];
+        let ufvk =
+            UnifiedSpendingKey::from_seed(&st.wallet().params, &other_seed, zip32::AccountId::ZERO)
+                .unwrap()
+                .to_unified_full_viewing_key();
+        let imported = st
+            .wallet_mut()
+            .import_account_ufvk(&ufvk, &birthday, false)
+            .unwrap();
+        assert_eq!(
+            st.wallet()
+                .get_transparent_account_pubkey(imported.id())
+                .unwrap()
+                .map(|pubkey| pubkey.serialize()),
+            ufvk.transparent().map(|pubkey| pubkey.serialize())
+        );
+        assert!(ufvk.transparent().is_some());
+
+        assert_matches!(
+            st.wallet()
+                .get_transparent_account_pubkey(AccountId(imported.id().0 + 1)),
+            Err(SqliteClientError::AccountUnknown)
+        );
+    }
+
+    #[test]
+    fn list_accounts() {
+        let mut st = TestBuilder::new().build();
+        assert_eq!(st.wallet().list_accounts().unwrap(), vec![]);
+
+        let birthday = AccountBirthday::from_parts(
+            ChainState::empty(st.wallet().params.sapling.unwrap() - 1, BlockHash([0; 32])),
+            None,
+        );
+
+        // A seed-derived account.
+        let seed = Secret::new(vec![0u8; 32]);
+        let (derived_id, _) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+
+        // A UFVK-only account, derived from an unrelated seed.
+        let other_seed = vec![1u8; 32];
+        let usk =
+            UnifiedSpendingKey::from_seed(&st.wallet().params, &other_seed, zip32::AccountId::ZERO)
+                .unwrap();
+        let imported = st
+            .wallet_mut()
+            .import_account_ufvk(&usk.to_unified_full_viewing_key(), &birthday, true)
+            .unwrap();
+
+        let accounts = st.wallet().list_accounts().unwrap();
+        assert_eq!(accounts.len(), 2);
+
+        assert_eq!(accounts[0].account_id(), derived_id);
+        assert!(accounts[0].is_spending_capable());
+        assert!(accounts[0].seed_fingerprint().is_some());
+        assert_eq!(accounts[0].birthday(), birthday.height());
+
+        assert_eq!(accounts[1].account_id(), imported.id());
+        assert!(!accounts[1].is_spending_capable());
+        assert_eq!(accounts[1].seed_fingerprint(), None);
+        assert_eq!(accounts[1].birthday(), birthday.height());
+    }
+
+    #[test]
+    fn export_account_for_watching() {
+        use prost::Message;
+        use zcash_client_backend::proto::watch_only::WatchOnlyAccount;
+
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().cloned().unwrap();
+        let params = st.wallet().params;
+
+        // Unknown accounts cannot be exported.
+        assert!(st
+            .wallet()
+            .export_account_for_watching(AccountId(3))
+            .unwrap()
+            .is_none());
+
+        let addr2 = st
+            .wallet_mut()
+            .get_next_available_address(account.account_id(), DEFAULT_UA_REQUEST)
+            .unwrap()
+            .unwrap();
+
+        let bundle = st
+            .wallet()
+            .export_account_for_watching(account.account_id())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            bundle.ufvk().encode(&params),
+            account.usk().to_unified_full_viewing_key().encode(&params)
+        );
+        assert_eq!(bundle.birthday_height(), account.birthday().height());
+        assert_eq!(bundle.recover_until(), account.birthday().recover_until());
+        assert_eq!(bundle.addresses().len(), 2);
+        assert_eq!(bundle.addresses()[1].1, addr2);
+        for (di, ua) in bundle.addresses() {
+            assert_eq!(
+                bundle.ufvk().address(*di, DEFAULT_UA_REQUEST).ok().as_ref(),
+                Some(ua)
+            );
+        }
+
+        // The bundle survives a round trip through its protobuf encoding.
+        let encoded = WatchOnlyAccount::from_bundle(&params, &bundle).encode_to_vec();
+        let decoded = WatchOnlyAccount::decode(&encoded[..])
+            .unwrap()
+            .try_into_bundle(&params)
+            .unwrap();
+        assert_eq!(
+            decoded.ufvk().encode(&params),
+            bundle.ufvk().encode(&params)
+        );
+        assert_eq!(decoded.birthday_height(), bundle.birthday_height());
+        assert_eq!(decoded.recover_until(), bundle.recover_until());
+        assert_eq!(decoded.addresses(), bundle.addresses());
+
+        // The bundle can be used to import the account into a watch-only wallet.
+        let mut companion = TestBuilder::new().build();
+        let birthday = AccountBirthday::from_parts(
+            ChainState::empty(decoded.birthday_height() - 1, BlockHash([0; 32])),
+            decoded.recover_until(),
+        );
+        let imported = companion
+            .wallet_mut()
+            .import_account_ufvk(decoded.ufvk(), &birthday, false)
+            .unwrap();
+        assert_eq!(
+            imported.ufvk().unwrap().encode(&params),
+            bundle.ufvk().encode(&params)
+        );
+        assert_eq!(
+            companion
+                .wallet()
+                .get_account_birthday(imported.id())
+                .unwrap(),
+            bundle.birthday_height()
+        );
+    }
+
+    #[test]
+    fn snapshot_isolated_from_concurrent_writes() {
+        use tempfile::NamedTempFile;
+        use zcash_primitives::consensus::{Network, NetworkUpgrade, Parameters};
+
+        use crate::{wallet::init::init_wallet_db, WalletDb};
+
+        let network = Network::TestNetwork;
+        let data_file = NamedTempFile::new().unwrap();
+        let mut writer = WalletDb::for_path(data_file.path(), network).unwrap();
+        init_wallet_db(&mut writer, None).unwrap();
+        let journal_mode: String = writer
+            .conn
+            .query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        let reader = WalletDb::for_path(data_file.path(), network).unwrap();
+        let snapshot = reader.snapshot().unwrap();
+        assert_eq!(snapshot.get_account_ids().unwrap(), vec![]);
+
+        // Commit a write via another connection while the snapshot is held.
+        let birthday = AccountBirthday::from_parts(
+            ChainState::empty(
+                network.activation_height(NetworkUpgrade::Sapling).unwrap() - 1,
+                BlockHash([0; 32]),
+            ),
+            None,
+        );
+        let (account_id, _) = writer
+            .create_account(&Secret::new(vec![0u8; 32]), &birthday)
+            .unwrap();
+        assert_eq!(writer.get_account_ids().unwrap(), vec![account_id]);
+
+        // The snapshot does not observe the write.
+        assert_eq!(snapshot.get_account_ids().unwrap(), vec![]);
+        assert_matches!(
+            snapshot.get_account_birthday(account_id),
+            Err(SqliteClientError::AccountUnknown)
+        );
+
+        // Once the snapshot is released, the write becomes visible to the reader.
+        drop(snapshot);
+        assert_eq!(reader.get_account_ids().unwrap(), vec![account_id]);
+        assert_eq!(
+            reader.get_account_birthday(account_id).unwrap(),
+            birthday.height()
+        );
+    }
+
+    #[test]
+    fn checkpoint_retention_prunes_old_checkpoints() {
+        use tempfile::NamedTempFile;
+        use zcash_client_backend::data_api::WalletCommitmentTrees;
+        use zcash_primitives::consensus::{BlockHeight, Network};
+
+        use crate::{wallet::init::init_wallet_db, WalletDb, PRUNING_DEPTH};
+
+        let data_file = NamedTempFile::new().unwrap();
+
+        // Retaining fewer checkpoints than the wallet relies upon is not permitted.
+        assert_matches!(
+            WalletDb::for_path(data_file.path(), Network::TestNetwork)
+                .unwrap()
+                .with_checkpoint_retention(PRUNING_DEPTH as usize - 1)
+                .err(),
+            Some(SqliteClientError::CheckpointRetentionTooSmall(_))
+        );
+
+        let retention = PRUNING_DEPTH as usize + 5;
+        let mut db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork)
+            .unwrap()
+            .with_checkpoint_retention(retention)
+            .unwrap();
+        init_wallet_db(&mut db_data, None).unwrap();
+
+        db_data
+            .with_sapling_tree_mut::<_, _, SqliteClientError>(|tree| {
+                for height in 1..=120 {
+                    tree.checkpoint(BlockHeight::from_u32(height))?;
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        // Only the checkpoints for the most recent 105 blocks are retained.
+        let checkpoint_ids = db_data
+            .conn
+            .prepare("SELECT checkpoint_id FROM sapling_tree_checkpoints ORDER BY checkpoint_id")
+            .unwrap()
+            .query_map([], |row| row.get::<_, u32>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(checkpoint_ids, (16..=120).collect::<Vec<_>>());
+
+        // Checkpoints created through a transaction are subject to the same window.
+        db_data
+            .transactionally(|wdb| {
+                wdb.with_sapling_tree_mut::<_, _, SqliteClientError>(|tree| {
+                    tree.checkpoint(BlockHeight::from_u32(121))?;
+                    Ok(())
+                })
+                .map_err(SqliteClientError::from)
+            })
+            .unwrap();
+        let (count, min_id): (u32, u32) = db_data
+            .conn
+            .query_row(
+                "SELECT COUNT(*), MIN(checkpoint_id) FROM sapling_tree_checkpoints",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((count, min_id), (retention as u32, 17));
+    }
+
     #[test]
     pub(crate) fn create_account_then_conflicting_import_account_ufvk() {
         let mut st = TestBuilder::new().build();
@@ -2192,6 +3462,85 @@ mod tests {
             Err(SqliteClientError::AccountCollision(id)) if id == seed_based.0);
     }
 
+    #[test]
+    fn import_transaction_annotations() {
+        let mut st = TestBuilder::new().build();
+
+        let txid = TxId::from_bytes([1; 32]);
+        assert_eq!(st.wallet().get_transaction_annotation(txid).unwrap(), None);
+
+        let annotation = TransactionAnnotation::from_parts(
+            txid,
+            Some(time::OffsetDateTime::from_unix_timestamp(1_704_164_645).unwrap()),
+            Some("Rent".to_string()),
+            Some("Expenses".to_string()),
+            None,
+        );
+        st.wallet_mut()
+            .import_transaction_annotations(&[annotation.clone()])
+            .unwrap();
+        assert_eq!(
+            st.wallet().get_transaction_annotation(txid).unwrap(),
+            Some(annotation)
+        );
+
+        // Re-importing an annotation for the same txid replaces the existing one.
+        let replacement = TransactionAnnotation::from_parts(
+            txid,
+            None,
+            Some("Rent (January)".to_string()),
+            None,
+            Some("Paid late".to_string()),
+        );
+        st.wallet_mut()
+            .import_transaction_annotations(&[replacement.clone()])
+            .unwrap();
+        assert_eq!(
+            st.wallet().get_transaction_annotation(txid).unwrap(),
+            Some(replacement)
+        );
+    }
+
+    #[test]
+    fn add_watched_address() {
+        let mut st = TestBuilder::new().build();
+        assert_eq!(st.wallet().get_watched_addresses().unwrap(), vec![]);
+
+        let seed = vec![0u8; 32];
+        let usk = UnifiedSpendingKey::from_seed(&st.wallet().params, &seed, zip32::AccountId::ZERO)
+            .unwrap();
+        let (ua, _) = usk
+            .to_unified_full_viewing_key()
+            .default_address(DEFAULT_UA_REQUEST)
+            .unwrap();
+
+        // Shielded activity for a UA cannot be tracked without a viewing key.
+        let watched_ua = st
+            .wallet_mut()
+            .add_watched_address(&Address::Unified(ua.clone()))
+            .unwrap();
+        assert!(watched_ua.requires_viewing_key());
+        assert_eq!(watched_ua.transparent_receiver(), ua.transparent());
+
+        // A bare transparent address can be fully tracked.
+        let taddr = TransparentAddress::PublicKeyHash([7; 20]);
+        let watched_taddr = st
+            .wallet_mut()
+            .add_watched_address(&Address::Transparent(taddr))
+            .unwrap();
+        assert!(!watched_taddr.requires_viewing_key());
+        assert_eq!(watched_taddr.transparent_receiver(), Some(&taddr));
+
+        // Watching the same address again has no effect.
+        st.wallet_mut()
+            .add_watched_address(&Address::Unified(ua))
+            .unwrap();
+        assert_eq!(
+            st.wallet().get_watched_addresses().unwrap(),
+            vec![watched_ua, watched_taddr]
+        );
+    }
+
     #[cfg(feature = "transparent-inputs")]
     #[test]
     fn transparent_receivers() {
@@ -1,5 +1,5 @@
 use std::fmt;
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::{collections::BTreeMap, convert::Infallible};
 
 #[cfg(feature = "unstable")]
@@ -32,11 +32,14 @@ use zcash_client_backend::{
     address::Address,
     data_api::{
         self,
-        chain::{scan_cached_blocks, BlockSource, CommitmentTreeRoot, ScanSummary},
+        chain::{
+            scan_cached_blocks, scan_cached_blocks_for_accounts,
+            scan_cached_blocks_with_commit_interval, BlockSource, CommitmentTreeRoot, ScanSummary,
+        },
         wallet::{
             create_proposed_transactions, create_spend_to_address,
             input_selection::{GreedyInputSelector, GreedyInputSelectorError, InputSelector},
-            propose_standard_transfer_to_address, propose_transfer, spend,
+            propose_standard_transfer_to_address, propose_transfer, spend, ProposalOptions,
         },
         AccountBalance, AccountBirthday, WalletCommitmentTrees, WalletRead, WalletSummary,
         WalletWrite,
@@ -58,7 +61,7 @@ use zcash_client_backend::{
 use zcash_note_encryption::Domain;
 use zcash_primitives::{
     block::BlockHash,
-    consensus::{self, BlockHeight, NetworkUpgrade, Parameters},
+    consensus::{self, BlockHeight, BranchId, NetworkUpgrade, Parameters},
     memo::{Memo, MemoBytes},
     transaction::{
         components::{amount::NonNegativeAmount, sapling::zip212_enforcement},
@@ -90,10 +93,18 @@ use {
 
 #[cfg(feature = "transparent-inputs")]
 use {
-    zcash_client_backend::data_api::wallet::{
-        input_selection::ShieldingSelector, propose_shielding, shield_transparent_funds,
+    std::ops::Range,
+    zcash_client_backend::data_api::{
+        chain::{error::Error as ChainError, FullBlockSource},
+        wallet::{input_selection::ShieldingSelector, propose_shielding, shield_transparent_funds},
+    },
+    zcash_primitives::{
+        legacy::{Script, TransparentAddress},
+        transaction::{
+            components::transparent::{self, OutPoint, TxIn, TxOut},
+            TransactionData, TxVersion,
+        },
     },
-    zcash_primitives::legacy::TransparentAddress,
 };
 
 #[cfg(feature = "unstable")]
@@ -325,6 +336,8 @@ impl<Cache> TestBuilder<Cache> {
             _data_file: data_file,
             db_data,
             test_account,
+            #[cfg(feature = "transparent-inputs")]
+            full_blocks: FullBlockCache::default(),
             rng: self.rng,
         }
     }
@@ -442,6 +455,8 @@ pub(crate) struct TestState<Cache> {
     _data_file: NamedTempFile,
     db_data: WalletDb<Connection, LocalNetwork>,
     test_account: Option<(SecretVec<u8>, TestAccount)>,
+    #[cfg(feature = "transparent-inputs")]
+    full_blocks: FullBlockCache,
     rng: ChaChaRng,
 }
 
@@ -716,13 +731,45 @@ where
         (height, res)
     }
 
+    /// Creates a fake block at the expected next height containing a single transaction with
+    /// a P2PKH output of the given value to the test account's transparent address, and
+    /// inserts it into the cache.
+    ///
+    /// Compact blocks do not contain transparent data, so the full transaction is also
+    /// recorded for use by [`Self::scan_transparent_blocks`]. Returns the outpoint of the
+    /// transparent output.
+    ///
+    /// This generated block will be treated as the latest block, and subsequent calls to
+    /// [`Self::generate_next_block`] will build on it.
+    #[cfg(feature = "transparent-inputs")]
+    pub(crate) fn generate_next_block_with_taddr_output(
+        &mut self,
+        value: NonNegativeAmount,
+    ) -> (BlockHeight, Cache::InsertResult, OutPoint) {
+        let account_id = self.test_account().unwrap().account_id();
+        let taddr = self
+            .wallet()
+            .get_current_address(account_id)
+            .unwrap()
+            .and_then(|ua| ua.transparent().copied())
+            .expect("The test account should have a transparent receiver.");
+
+        let tx = fake_transparent_tx(&mut self.rng, &taddr, value);
+        let outpoint = OutPoint::new(tx.txid().into(), 0);
+
+        let (height, res) = self.generate_next_block_from_tx(1, &tx);
+        self.full_blocks.insert(height, &tx);
+
+        (height, res, outpoint)
+    }
+
     /// Invokes [`scan_cached_blocks`] with the given arguments, expecting success.
     pub(crate) fn scan_cached_blocks(
         &mut self,
         from_height: BlockHeight,
         limit: usize,
     ) -> ScanSummary {
-        let result = self.try_scan_cached_blocks(from_height, limit);
+        let result = self.try_scan_cached_blocks(from_height, limit, None);
         assert_matches!(result, Ok(_));
         result.unwrap()
     }
@@ -732,6 +779,7 @@ where
         &mut self,
         from_height: BlockHeight,
         limit: usize,
+        to_height: Option<BlockHeight>,
     ) -> Result<
         ScanSummary,
         data_api::chain::error::Error<
@@ -751,10 +799,82 @@ where
             from_height,
             &prior_cached_block.chain_state,
             limit,
+            to_height,
         );
         result
     }
 
+    /// Invokes [`scan_cached_blocks_with_commit_interval`] with the given arguments, expecting
+    /// success.
+    pub(crate) fn scan_cached_blocks_with_commit_interval(
+        &mut self,
+        from_height: BlockHeight,
+        limit: usize,
+        commit_interval_blocks: NonZeroUsize,
+    ) -> ScanSummary {
+        let prior_cached_block = self
+            .latest_cached_block_below_height(from_height)
+            .cloned()
+            .unwrap_or_else(|| CachedBlock::none(from_height - 1));
+
+        let result = scan_cached_blocks_with_commit_interval(
+            &self.network(),
+            self.cache.block_source(),
+            &mut self.db_data,
+            from_height,
+            &prior_cached_block.chain_state,
+            limit,
+            None,
+            commit_interval_blocks,
+        );
+        assert_matches!(result, Ok(_));
+        result.unwrap()
+    }
+
+    /// Invokes [`scan_cached_blocks_for_accounts`] with the given arguments, expecting success.
+    pub(crate) fn scan_cached_blocks_for_accounts(
+        &mut self,
+        from_height: BlockHeight,
+        limit: usize,
+        accounts: &[AccountId],
+    ) -> ScanSummary {
+        let prior_cached_block = self
+            .latest_cached_block_below_height(from_height)
+            .cloned()
+            .unwrap_or_else(|| CachedBlock::none(from_height - 1));
+
+        let result = scan_cached_blocks_for_accounts(
+            &self.network(),
+            self.cache.block_source(),
+            &mut self.db_data,
+            from_height,
+            &prior_cached_block.chain_state,
+            limit,
+            None,
+            accounts,
+        );
+        assert_matches!(result, Ok(_));
+        result.unwrap()
+    }
+
+    /// Invokes [`chain::scan_transparent_blocks`] for the test account over the full
+    /// transactions of blocks generated by [`Self::generate_next_block_with_taddr_output`],
+    /// expecting success.
+    ///
+    /// [`chain::scan_transparent_blocks`]: data_api::chain::scan_transparent_blocks
+    #[cfg(feature = "transparent-inputs")]
+    pub(crate) fn scan_transparent_blocks(&mut self, from_height: BlockHeight, limit: usize) {
+        let account_id = self.test_account().unwrap().account_id();
+        let limit = u32::try_from(limit).unwrap();
+        let result = data_api::chain::scan_transparent_blocks(
+            &self.full_blocks,
+            &mut self.db_data,
+            account_id,
+            from_height..from_height + limit,
+        );
+        assert_matches!(result, Ok(()));
+    }
+
     /// Resets the wallet using a new wallet database but with the same cache of blocks,
     /// and returns the old wallet database file.
     ///
@@ -771,23 +891,54 @@ where
         tf
     }
 
-    //    /// Reset the latest cached block to the most recent one in the cache database.
-    //    #[allow(dead_code)]
-    //    pub(crate) fn reset_latest_cached_block(&mut self) {
-    //        self.cache
-    //            .block_source()
-    //            .with_blocks::<_, Infallible>(None, None, |block: CompactBlock| {
-    //                let chain_metadata = block.chain_metadata.unwrap();
-    //                self.latest_cached_block = Some(CachedBlock::at(
-    //                    BlockHash::from_slice(block.hash.as_slice()),
-    //                    BlockHeight::from_u32(block.height.try_into().unwrap()),
-    //                    chain_metadata.sapling_commitment_tree_size,
-    //                    chain_metadata.orchard_commitment_tree_size,
-    //                ));
-    //                Ok(())
-    //            })
-    //            .unwrap();
-    //    }
+    /// Closes and reopens the wallet database, simulating an application restart.
+    ///
+    /// The cache of blocks and the test account are retained.
+    pub(crate) fn reopen_wallet(&mut self) {
+        let network = self.network();
+        self.db_data = WalletDb::for_path(self._data_file.path(), network).unwrap();
+    }
+
+    /// Resets the latest cached block to the most recent one in the cache database.
+    ///
+    /// The final Sapling and Orchard note commitment tree states are recomputed for any block
+    /// in the cache that is not already tracked, and the tracked tree sizes are checked against
+    /// those recorded in each block's chain metadata, so that subsequently generated blocks
+    /// extend both trees correctly.
+    pub(crate) fn reset_latest_cached_block(&mut self) {
+        let mut blocks = vec![];
+        self.cache
+            .block_source()
+            .with_blocks::<_, Infallible>(None, None, |block: CompactBlock| {
+                blocks.push(block);
+                Ok(())
+            })
+            .unwrap();
+
+        for block in blocks {
+            let height = block.height();
+            if !self.cached_blocks.contains_key(&height) {
+                let cached_block = self
+                    .latest_cached_block_below_height(height)
+                    .cloned()
+                    .unwrap_or_else(|| CachedBlock::none(height - 1))
+                    .roll_forward(&block);
+                self.cached_blocks.insert(height, cached_block);
+            }
+
+            let chain_metadata = block.chain_metadata.unwrap();
+            let cached_block = &self.cached_blocks[&height];
+            assert_eq!(
+                cached_block.sapling_end_size,
+                chain_metadata.sapling_commitment_tree_size
+            );
+            assert_eq!(
+                cached_block.orchard_end_size,
+                chain_metadata.orchard_commitment_tree_size
+            );
+            self.latest_block_height = Some(height);
+        }
+    }
 }
 
 impl<Cache> TestState<Cache> {
@@ -937,6 +1088,7 @@ impl<Cache> TestState<Cache> {
             request,
             ovk_policy,
             min_confirmations,
+            &[],
         )
     }
 
@@ -948,6 +1100,7 @@ impl<Cache> TestState<Cache> {
         input_selector: &InputsT,
         request: zip321::TransactionRequest,
         min_confirmations: NonZeroU32,
+        options: &ProposalOptions,
     ) -> Result<
         Proposal<InputsT::FeeRule, ReceivedNoteId>,
         data_api::error::Error<
@@ -968,6 +1121,7 @@ impl<Cache> TestState<Cache> {
             input_selector,
             request,
             min_confirmations,
+            options,
         )
     }
 
@@ -1005,6 +1159,7 @@ impl<Cache> TestState<Cache> {
             memo,
             change_memo,
             fallback_change_pool,
+            &[],
         );
 
         if let Ok(proposal) = &result {
@@ -1024,6 +1179,7 @@ impl<Cache> TestState<Cache> {
         shielding_threshold: NonNegativeAmount,
         from_addrs: &[TransparentAddress],
         min_confirmations: u32,
+        change_memo: Option<MemoBytes>,
     ) -> Result<
         Proposal<InputsT::FeeRule, Infallible>,
         data_api::error::Error<
@@ -1044,6 +1200,7 @@ impl<Cache> TestState<Cache> {
             shielding_threshold,
             from_addrs,
             min_confirmations,
+            change_memo,
         )
     }
 
@@ -1062,6 +1219,30 @@ impl<Cache> TestState<Cache> {
             FeeRuleT::Error,
         >,
     >
+    where
+        FeeRuleT: FeeRule,
+    {
+        self.create_proposed_transactions_with_options(usk, ovk_policy, proposal, None, None)
+    }
+
+    /// Invokes [`create_proposed_transactions`] with the given arguments, including the
+    /// optional consensus branch ID and change diversifier index overrides.
+    pub(crate) fn create_proposed_transactions_with_options<InputsErrT, FeeRuleT>(
+        &mut self,
+        usk: &UnifiedSpendingKey,
+        ovk_policy: OvkPolicy,
+        proposal: &Proposal<FeeRuleT, ReceivedNoteId>,
+        branch_id: Option<BranchId>,
+        change_diversifier_index: Option<DiversifierIndex>,
+    ) -> Result<
+        NonEmpty<TxId>,
+        data_api::error::Error<
+            SqliteClientError,
+            commitment_tree::Error,
+            InputsErrT,
+            FeeRuleT::Error,
+        >,
+    >
     where
         FeeRuleT: FeeRule,
     {
@@ -1075,6 +1256,8 @@ impl<Cache> TestState<Cache> {
             usk,
             ovk_policy,
             proposal,
+            branch_id,
+            change_diversifier_index,
         )
     }
 
@@ -1088,6 +1271,7 @@ impl<Cache> TestState<Cache> {
         usk: &UnifiedSpendingKey,
         from_addrs: &[TransparentAddress],
         min_confirmations: u32,
+        change_memo: Option<MemoBytes>,
     ) -> Result<
         NonEmpty<TxId>,
         data_api::error::Error<
@@ -1112,6 +1296,7 @@ impl<Cache> TestState<Cache> {
             usk,
             from_addrs,
             min_confirmations,
+            change_memo,
         )
     }
 
@@ -1121,8 +1306,11 @@ impl<Cache> TestState<Cache> {
         min_confirmations: u32,
         f: F,
     ) -> T {
-        let binding = self.get_wallet_summary(min_confirmations).unwrap();
-        f(binding.account_balances().get(&account).unwrap())
+        let balances = self
+            .wallet()
+            .get_account_balances(min_confirmations)
+            .unwrap();
+        f(balances.get(&account).unwrap())
     }
 
     pub(crate) fn get_total_balance(&self, account: AccountId) -> NonNegativeAmount {
@@ -1150,6 +1338,14 @@ impl<Cache> TestState<Cache> {
         .unwrap()
     }
 
+    pub(crate) fn get_locked_balance(
+        &self,
+        account: AccountId,
+        min_confirmations: u32,
+    ) -> NonNegativeAmount {
+        self.with_account_balance(account, min_confirmations, |balance| balance.locked_value())
+    }
+
     #[allow(dead_code)]
     pub(crate) fn get_pending_change(
         &self,
@@ -1625,6 +1821,42 @@ fn fake_compact_block<P: consensus::Parameters, Fvk: TestFvk>(
     (cb, nf)
 }
 
+/// Create a fake transparent-only transaction with a single P2PKH output of the given value to
+/// the given address. The transaction spends a random outpoint, so that its txid is unique.
+#[cfg(feature = "transparent-inputs")]
+fn fake_transparent_tx<R: RngCore>(
+    rng: &mut R,
+    to: &TransparentAddress,
+    value: NonNegativeAmount,
+) -> Transaction {
+    let mut prevout_hash = [0u8; 32];
+    rng.fill_bytes(&mut prevout_hash);
+
+    TransactionData::from_parts(
+        TxVersion::Sapling,
+        BranchId::Canopy,
+        0,
+        BlockHeight::from(0),
+        Some(transparent::Bundle {
+            vin: vec![TxIn {
+                prevout: OutPoint::new(prevout_hash, 0),
+                script_sig: Script(vec![]),
+                sequence: 0,
+            }],
+            vout: vec![TxOut {
+                value,
+                script_pubkey: to.script(),
+            }],
+            authorization: transparent::Authorized,
+        }),
+        None,
+        None,
+        None,
+    )
+    .freeze()
+    .unwrap()
+}
+
 /// Create a fake CompactBlock at the given height containing only the given transaction.
 fn fake_compact_block_from_tx(
     height: BlockHeight,
@@ -1808,6 +2040,45 @@ pub(crate) trait TestCache {
     fn insert(&self, cb: &CompactBlock) -> Self::InsertResult;
 }
 
+/// A source of the full transactions of generated blocks, for use in scanning for transparent
+/// outputs (which compact blocks omit).
+#[cfg(feature = "transparent-inputs")]
+#[derive(Default)]
+pub(crate) struct FullBlockCache(BTreeMap<BlockHeight, Vec<Vec<u8>>>);
+
+#[cfg(feature = "transparent-inputs")]
+impl FullBlockCache {
+    fn insert(&mut self, height: BlockHeight, tx: &Transaction) {
+        let mut raw = vec![];
+        tx.write(&mut raw).unwrap();
+        self.0.entry(height).or_default().push(raw);
+    }
+}
+
+#[cfg(feature = "transparent-inputs")]
+impl FullBlockSource for FullBlockCache {
+    type Error = Infallible;
+
+    fn with_full_blocks<F, WalletErrT>(
+        &self,
+        range: Range<BlockHeight>,
+        mut with_block: F,
+    ) -> Result<(), ChainError<WalletErrT, Infallible>>
+    where
+        F: FnMut(BlockHeight, Vec<Transaction>) -> Result<(), ChainError<WalletErrT, Infallible>>,
+    {
+        for (height, txs) in self.0.range(range) {
+            with_block(
+                *height,
+                txs.iter()
+                    .map(|raw| Transaction::read(&raw[..], BranchId::Canopy).unwrap())
+                    .collect(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
 pub(crate) struct BlockCache {
     _cache_file: NamedTempFile,
     db_cache: BlockDb,
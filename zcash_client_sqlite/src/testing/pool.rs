@@ -3,28 +3,32 @@
 //! Generalised for sharing across the Sapling and Orchard implementations.
 
 use std::{
+    collections::HashMap,
     convert::Infallible,
-    num::{NonZeroU32, NonZeroU8},
+    num::{NonZeroU32, NonZeroU8, NonZeroUsize},
 };
 
-use incrementalmerkletree::{frontier::Frontier, Level};
+use incrementalmerkletree::{frontier::Frontier, Level, Position};
 use rand_core::RngCore;
 use rusqlite::params;
-use secrecy::Secret;
-use shardtree::error::ShardTreeError;
+use secrecy::{ExposeSecret, Secret};
+use shardtree::{
+    error::{QueryError, ShardTreeError},
+    store::{memory::MemoryShardStore, ShardStore},
+};
 use zcash_primitives::{
     block::BlockHash,
     consensus::{BranchId, NetworkUpgrade, Parameters},
     legacy::TransparentAddress,
     memo::{Memo, MemoBytes},
     transaction::{
-        components::amount::NonNegativeAmount,
+        components::amount::{Amount, NonNegativeAmount},
         fees::{
             fixed::FeeRule as FixedFeeRule, zip317::FeeError as Zip317FeeError, StandardFeeRule,
         },
-        Transaction,
+        Transaction, TxId,
     },
-    zip32::Scope,
+    zip32::{DiversifierIndex, Scope},
 };
 
 use zcash_client_backend::{
@@ -32,20 +36,25 @@ use zcash_client_backend::{
     data_api::{
         self,
         chain::{self, ChainState, CommitmentTreeRoot, ScanSummary},
+        chain_tip::testing::FixedChainTip,
         error::Error,
         wallet::{
-            decrypt_and_store_transaction,
+            create_signable_package_transactions, decrypt_and_store_transaction,
             input_selection::{GreedyInputSelector, GreedyInputSelectorError},
+            ProposalOptions,
         },
-        AccountBirthday, DecryptedTransaction, Ratio, WalletRead, WalletSummary, WalletWrite,
+        AccountBirthday, AuditMismatch, CommitmentTreeReplacement, DecryptedTransaction,
+        InputSource, MemoPrefix, Ratio, WalletRead, WalletSummary, WalletWrite,
     },
     decrypt_transaction,
     fees::{fixed, standard, DustOutputPolicy},
     keys::UnifiedSpendingKey,
+    proposal::{ProposalError, MAX_STANDARD_TX_SIZE},
+    proto::{proposal, ProposalDecodingError, PROPOSAL_SER_V1},
     scanning::ScanError,
     wallet::{Note, OvkPolicy, ReceivedNote},
     zip321::{self, Payment, TransactionRequest},
-    ShieldedProtocol,
+    PoolType, ShieldedProtocol,
 };
 use zcash_protocol::consensus::BlockHeight;
 
@@ -53,14 +62,17 @@ use super::TestFvk;
 use crate::{
     error::SqliteClientError,
     testing::{input_selector, AddressType, BlockCache, InitialChainState, TestBuilder, TestState},
-    wallet::{block_max_scanned, commitment_tree, parse_scope, truncate_to_height},
-    AccountId, NoteId, ReceivedNoteId,
+    wallet::{
+        self, block_max_scanned, commitment_tree, parse_scope, sapling::tests::test_prover,
+        truncate_to_height,
+    },
+    AccountId, NoteId, ReceivedNoteId, WalletDb, DEFAULT_UA_REQUEST,
 };
 
 #[cfg(feature = "transparent-inputs")]
 use {
     zcash_client_backend::{
-        fees::TransactionBalance, proposal::Step, wallet::WalletTransparentOutput, PoolType,
+        fees::TransactionBalance, proposal::Step, wallet::WalletTransparentOutput,
     },
     zcash_primitives::transaction::components::{OutPoint, TxOut},
 };
@@ -90,6 +102,9 @@ pub(crate) trait ShieldedPoolTester {
     fn sk_default_address(sk: &Self::Sk) -> Address;
     fn fvk_default_address(fvk: &Self::Fvk) -> Address;
     fn fvks_equal(a: &Self::Fvk, b: &Self::Fvk) -> bool;
+    /// Returns the diversifier of the internal address derived from the given spending key at
+    /// the given diversifier index, or `None` if the index does not yield a valid address.
+    fn sk_internal_diversifier(sk: &Self::Sk, j: DiversifierIndex) -> Option<[u8; 11]>;
 
     fn random_fvk(mut rng: impl RngCore) -> Self::Fvk {
         let sk = {
@@ -115,6 +130,9 @@ pub(crate) trait ShieldedPoolTester {
 
     fn next_subtree_index(s: &WalletSummary<AccountId>) -> u64;
 
+    /// Copies the wallet's note commitment tree for this pool, for use as a replacement tree.
+    fn copy_tree<Cache>(st: &mut TestState<Cache>) -> CommitmentTreeReplacement;
+
     fn select_spendable_notes<Cache>(
         st: &TestState<Cache>,
         account: AccountId,
@@ -140,6 +158,34 @@ pub(crate) trait ShieldedPoolTester {
     fn received_note_count(summary: &ScanSummary) -> usize;
 }
 
+/// Copies the shards, cap, and checkpoints of the given note commitment tree store into memory.
+pub(crate) fn copy_shard_store<S>(
+    store: &mut S,
+) -> Result<MemoryShardStore<S::H, BlockHeight>, S::Error>
+where
+    S: ShardStore<CheckpointId = BlockHeight>,
+    S::H: Clone,
+{
+    let mut copy = MemoryShardStore::empty();
+    for addr in store.get_shard_roots()? {
+        if let Some(shard) = store.get_shard(addr)? {
+            copy.put_shard(shard).unwrap();
+        }
+    }
+    copy.put_cap(store.get_cap()?).unwrap();
+
+    let mut checkpoints = vec![];
+    store.with_checkpoints(usize::MAX, |checkpoint_id, checkpoint| {
+        checkpoints.push((*checkpoint_id, checkpoint.clone()));
+        Ok(())
+    })?;
+    for (checkpoint_id, checkpoint) in checkpoints {
+        copy.add_checkpoint(checkpoint_id, checkpoint).unwrap();
+    }
+
+    Ok(copy)
+}
+
 pub(crate) fn send_single_step_proposed_transfer<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
@@ -193,6 +239,7 @@ pub(crate) fn send_single_step_proposed_transfer<T: ShieldedPoolTester>() {
             input_selector,
             request,
             NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
         )
         .unwrap();
 
@@ -296,12 +343,7 @@ pub(crate) fn send_single_step_proposed_transfer<T: ShieldedPoolTester>() {
     );
 }
 
-#[cfg(feature = "transparent-inputs")]
-pub(crate) fn send_multi_step_proposed_transfer<T: ShieldedPoolTester>() {
-    use nonempty::NonEmpty;
-    use zcash_client_backend::proposal::{Proposal, StepOutput, StepOutputIndex};
-    use zcash_primitives::legacy::keys::IncomingViewingKey;
-
+pub(crate) fn get_note_scope<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
@@ -310,350 +352,440 @@ pub(crate) fn send_multi_step_proposed_transfer<T: ShieldedPoolTester>() {
     let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
 
-    // Add funds to the wallet in a single note
-    let value = NonNegativeAmount::const_from_u64(65000);
+    let value = NonNegativeAmount::const_from_u64(60000);
     let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
     st.scan_cached_blocks(h, 1);
 
-    // Spendable balance matches total balance
-    assert_eq!(st.get_total_balance(account.account_id()), value);
-    assert_eq!(st.get_spendable_balance(account.account_id(), 1), value);
-
+    // A note received at the account's default address was received by its external key.
+    let received = T::select_spendable_notes(&st, account.account_id(), value, h, &[]).unwrap();
+    assert_eq!(received.len(), 1);
     assert_eq!(
-        block_max_scanned(&st.wallet().conn, &st.wallet().params)
-            .unwrap()
-            .unwrap()
-            .block_height(),
-        h
+        st.wallet()
+            .get_note_scope(NoteId::new(
+                *received[0].txid(),
+                T::SHIELDED_PROTOCOL,
+                received[0].output_index(),
+            ))
+            .unwrap(),
+        Some(Scope::External)
     );
 
-    // Generate a single-step proposal. Then, instead of executing that proposal,
-    // we will use its only step as the first step in a multi-step proposal that
-    // spends the first step's output.
-
-    // The first step will deshield to the wallet's default transparent address
-    let to0 = Address::Transparent(account.usk().default_transparent_address().0);
-    let request0 = zip321::TransactionRequest::new(vec![Payment::without_memo(
-        to0.to_zcash_address(&st.network()),
-        NonNegativeAmount::const_from_u64(50000),
-    )])
-    .unwrap();
-
-    let fee_rule = StandardFeeRule::Zip317;
-    let input_selector = GreedyInputSelector::new(
-        standard::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL),
-        DustOutputPolicy::default(),
-    );
-    let proposal0 = st
-        .propose_transfer(
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
             account.account_id(),
-            &input_selector,
-            request0,
+            StandardFeeRule::Zip317,
             NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
         )
         .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    // The change output was received by the account's internal key, and the output to the
+    // external recipient is not a note that the wallet has received.
+    let scopes = (0..2)
+        .map(|output_index| {
+            st.wallet()
+                .get_note_scope(NoteId::new(txid, T::SHIELDED_PROTOCOL, output_index))
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert!(scopes.contains(&Some(Scope::Internal)));
+    assert!(scopes.contains(&None));
+}
 
-    let min_target_height = proposal0.min_target_height();
-    let step0 = &proposal0.steps().head;
-
-    assert!(step0.balance().proposed_change().is_empty());
-    assert_eq!(
-        step0.balance().fee_required(),
-        NonNegativeAmount::const_from_u64(15000)
-    );
-
-    // We'll use an internal transparent address that hasn't been added to the wallet
-    // to simulate an external transparent recipient.
-    let to1 = Address::Transparent(
-        account
-            .usk()
-            .transparent()
-            .to_account_pubkey()
-            .derive_internal_ivk()
-            .unwrap()
-            .default_address()
-            .0,
-    );
-    let request1 = zip321::TransactionRequest::new(vec![Payment::without_memo(
-        to1.to_zcash_address(&st.network()),
-        NonNegativeAmount::const_from_u64(40000),
-    )])
-    .unwrap();
+pub(crate) fn get_spent_note_positions<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    let step1 = Step::from_parts(
-        &[step0.clone()],
-        request1,
-        [(0, PoolType::Transparent)].into_iter().collect(),
-        vec![],
-        None,
-        vec![StepOutput::new(0, StepOutputIndex::Payment(0))],
-        TransactionBalance::new(vec![], NonNegativeAmount::const_from_u64(10000)).unwrap(),
-        false,
-    )
-    .unwrap();
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
 
-    let proposal = Proposal::multi_step(
-        fee_rule,
-        min_target_height,
-        NonEmpty::from_vec(vec![step0.clone(), step1]).unwrap(),
-    )
-    .unwrap();
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
-        account.usk(),
-        OvkPolicy::Sender,
-        &proposal,
-    );
-    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 2);
-    let txids = create_proposed_result.unwrap();
+    // Record the note commitment tree position of the note that will be spent.
+    let spendable_positions = T::select_spendable_notes(&st, account.account_id(), value, h, &[])
+        .unwrap()
+        .iter()
+        .map(|n| (T::SHIELDED_PROTOCOL, n.note_commitment_tree_position()))
+        .collect::<Vec<_>>();
+    assert_eq!(spendable_positions.len(), 1);
 
-    // Verify that the stored sent outputs match what we're expecting
-    let mut stmt_sent = st
-        .wallet()
-        .conn
-        .prepare(
-            "SELECT value
-            FROM sent_notes
-            JOIN transactions ON transactions.id_tx = sent_notes.tx
-            WHERE transactions.txid = ?",
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
         )
         .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
 
-    let confirmed_sent = txids
-        .iter()
-        .map(|sent_txid| {
-            // check that there's a sent output with the correct value corresponding to
-            stmt_sent
-                .query(rusqlite::params![sent_txid.as_ref()])
-                .unwrap()
-                .mapped(|row| {
-                    let value: u32 = row.get(0)?;
-                    Ok((sent_txid, value))
-                })
-                .collect::<Result<Vec<_>, _>>()
-                .unwrap()
-        })
-        .collect::<Vec<_>>();
-
+    // The position of the spent note is reported, and no positions are reported for a
+    // transaction that the wallet does not know about.
     assert_eq!(
-        confirmed_sent.get(0).and_then(|v| v.get(0)),
-        Some(&(&txids[0], 50000))
+        st.wallet().get_spent_note_positions(txid).unwrap(),
+        spendable_positions
     );
     assert_eq!(
-        confirmed_sent.get(1).and_then(|v| v.get(0)),
-        Some(&(&txids[1], 40000))
+        st.wallet()
+            .get_spent_note_positions(TxId::from_bytes([0xff; 32]))
+            .unwrap(),
+        vec![]
     );
 }
 
-#[allow(deprecated)]
-pub(crate) fn create_to_address_fails_on_incorrect_usk<T: ShieldedPoolTester>() {
+pub(crate) fn get_spent_notes<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
+        .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
+
+    let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
-    let to = T::fvk_default_address(&dfvk);
 
-    // Create a USK that doesn't exist in the wallet
-    let acct1 = zip32::AccountId::try_from(1).unwrap();
-    let usk1 = UnifiedSpendingKey::from_seed(&st.network(), &[1u8; 32], acct1).unwrap();
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    // Attempting to spend with a USK that is not in the wallet results in an error
-    assert_matches!(
-        st.create_spend_to_address(
-            &usk1,
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
             &to,
-            NonNegativeAmount::const_from_u64(1),
+            NonNegativeAmount::const_from_u64(10000),
             None,
-            OvkPolicy::Sender,
-            NonZeroU32::new(1).unwrap(),
             None,
             T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::KeyNotRecognized)
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    // The transaction spent the single note that was received at height `h`.
+    let spent_notes = st.wallet().get_spent_notes(txid).unwrap();
+    assert_eq!(spent_notes.len(), 1);
+    assert_eq!(spent_notes[0].protocol(), T::SHIELDED_PROTOCOL);
+    assert_eq!(
+        st.wallet().get_tx_height(*spent_notes[0].txid()).unwrap(),
+        Some(h)
+    );
+
+    // No notes are reported for a transaction that the wallet does not know about.
+    assert_eq!(
+        st.wallet()
+            .get_spent_notes(TxId::from_bytes([0xff; 32]))
+            .unwrap(),
+        vec![]
     );
 }
 
-#[allow(deprecated)]
-pub(crate) fn proposal_fails_with_no_blocks<T: ShieldedPoolTester>() {
+pub(crate) fn get_transaction_fee<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
+        .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
-    let account_id = st.test_account().unwrap().account_id();
+    let account = st.test_account().cloned().unwrap();
     let dfvk = T::test_account_fvk(&st);
-    let to = T::fvk_default_address(&dfvk);
 
-    // Wallet summary is not yet available
-    assert_eq!(st.get_wallet_summary(0), None);
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    // We cannot do anything if we aren't synchronised
-    assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            StandardFeeRule::PreZip313,
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
             NonZeroU32::new(1).unwrap(),
             &to,
-            NonNegativeAmount::const_from_u64(1),
+            NonNegativeAmount::const_from_u64(10000),
             None,
             None,
             T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::ScanRequired)
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    // The fee paid by the transaction is the fee required by the proposal.
+    let expected_fee = proposal.steps().head.balance().fee_required();
+    assert_eq!(
+        st.wallet().get_transaction_fee(txid).unwrap(),
+        Some(expected_fee)
+    );
+
+    // The fee can also be computed from the transaction's value balances when it was not
+    // recorded at the time the transaction was stored.
+    st.wallet()
+        .conn
+        .execute(
+            "UPDATE transactions SET fee = NULL WHERE txid = ?",
+            rusqlite::params![txid.as_ref()],
+        )
+        .unwrap();
+    assert_eq!(
+        st.wallet().get_transaction_fee(txid).unwrap(),
+        Some(expected_fee)
+    );
+
+    // The fee of a transaction that the wallet does not know about cannot be determined.
+    assert_eq!(
+        st.wallet()
+            .get_transaction_fee(TxId::from_bytes([0xff; 32]))
+            .unwrap(),
+        None
     );
 }
 
-pub(crate) fn spend_fails_on_unverified_notes<T: ShieldedPoolTester>() {
+pub(crate) fn proposal_validate_size<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
         .build();
 
     let account = st.test_account().cloned().unwrap();
-    let account_id = account.account_id();
     let dfvk = T::test_account_fvk(&st);
 
-    // Add funds to the wallet in a single note
-    let value = NonNegativeAmount::const_from_u64(50000);
-    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h1, 1);
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
-    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
 
-    // Value is considered pending at 10 confirmations.
-    assert_eq!(st.get_pending_shielded_balance(account_id, 10), value);
-    assert_eq!(
-        st.get_spendable_balance(account_id, 10),
-        NonNegativeAmount::ZERO
-    );
+    // A small transaction is within the standard size limit, and a limit equal to the
+    // estimated size is not exceeded.
+    let estimated_size = proposal.estimated_size();
+    assert_matches!(proposal.validate_size(MAX_STANDARD_TX_SIZE), Ok(()));
+    assert_matches!(proposal.validate_size(estimated_size), Ok(()));
 
-    // Wallet is fully scanned
-    let summary = st.get_wallet_summary(1);
-    assert_eq!(
-        summary.and_then(|s| s.scan_progress()),
-        Some(Ratio::new(1, 1))
+    // The proposal is rejected if its transaction would exceed a size limit.
+    let err = proposal.validate_size(estimated_size - 1).unwrap_err();
+    assert_matches!(
+        err,
+        ProposalError::SizeLimitExceeded { step_index: 0, estimated_size: e, max_size }
+            if e == estimated_size && max_size == estimated_size - 1
     );
+    assert!(err.to_string().contains("by 1 bytes"));
+}
 
-    // Add more funds to the wallet in a second note
-    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h2, 1);
+pub(crate) fn get_transaction_bytes<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    // Verified balance does not include the second note
-    let total = (value + value).unwrap();
-    assert_eq!(st.get_spendable_balance(account_id, 2), value);
-    assert_eq!(st.get_pending_shielded_balance(account_id, 2), value);
-    assert_eq!(st.get_total_balance(account_id), total);
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
 
-    // Wallet is still fully scanned
-    let summary = st.get_wallet_summary(1);
-    assert_eq!(
-        summary.and_then(|s| s.scan_progress()),
-        Some(Ratio::new(2, 2))
-    );
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
 
-    // Spend fails because there are insufficient verified notes
-    let extsk2 = T::sk(&[0xf5; 32]);
-    let to = T::sk_default_address(&extsk2);
-    assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
             StandardFeeRule::Zip317,
-            NonZeroU32::new(2).unwrap(),
+            NonZeroU32::new(1).unwrap(),
             &to,
-            NonNegativeAmount::const_from_u64(70000),
+            NonNegativeAmount::const_from_u64(10000),
             None,
             None,
             T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::const_from_u64(50000)
-            && required == NonNegativeAmount::const_from_u64(80000)
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    let tx = st
+        .wallet()
+        .get_transaction(txid)
+        .unwrap()
+        .expect("Created transaction was stored.");
+    let mut tx_bytes = vec![];
+    tx.write(&mut tx_bytes).unwrap();
+
+    // The raw bytes of the transaction are available for re-broadcast, and re-parsing them
+    // yields the same transaction ID.
+    let stored_bytes = st
+        .wallet()
+        .get_transaction_bytes(txid)
+        .unwrap()
+        .expect("Created transaction bytes were stored.");
+    assert_eq!(stored_bytes, tx_bytes);
+    assert_eq!(
+        Transaction::read(&stored_bytes[..], tx.consensus_branch_id())
+            .unwrap()
+            .txid(),
+        txid
     );
 
-    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 2 to 9 until just before the second
-    // note is verified
-    for _ in 2..10 {
-        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    }
-    st.scan_cached_blocks(h2 + 1, 8);
+    // No bytes are available for a transaction that the wallet does not know about.
+    assert_eq!(
+        st.wallet()
+            .get_transaction_bytes(TxId::from_bytes([0xff; 32]))
+            .unwrap(),
+        None
+    );
+}
 
-    // Total balance is value * number of blocks scanned (10).
-    assert_eq!(st.get_total_balance(account_id), (value * 10).unwrap());
+pub(crate) fn proposal_estimated_size<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    // Spend still fails
-    assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
             StandardFeeRule::Zip317,
-            NonZeroU32::new(10).unwrap(),
+            NonZeroU32::new(1).unwrap(),
             &to,
-            NonNegativeAmount::const_from_u64(70000),
+            NonNegativeAmount::const_from_u64(10000),
             None,
             None,
             T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::const_from_u64(50000)
-            && required == NonNegativeAmount::const_from_u64(80000)
-    );
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
 
-    // Mine block 11 so that the second note becomes verified
-    let (h11, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h11, 1);
+    // The size estimate for the proposal should closely match the size of the built transaction.
+    let tx = st
+        .wallet()
+        .get_transaction(txid)
+        .unwrap()
+        .expect("Created transaction was stored.");
+    let mut tx_bytes = vec![];
+    tx.write(&mut tx_bytes).unwrap();
+    assert!(tx_bytes.len().abs_diff(proposal.estimated_size()) <= 16);
+}
 
-    // Total balance is value * number of blocks scanned (11).
-    assert_eq!(st.get_total_balance(account_id), (value * 11).unwrap());
-    // Spendable balance at 10 confirmations is value * 2.
-    assert_eq!(
-        st.get_spendable_balance(account_id, 10),
-        (value * 2).unwrap()
-    );
-    assert_eq!(
-        st.get_pending_shielded_balance(account_id, 10),
-        (value * 9).unwrap()
-    );
+pub(crate) fn proposal_serialization_version<T: ShieldedPoolTester>() {
+    use prost::Message;
 
-    // Should now be able to generate a proposal
-    let amount_sent = NonNegativeAmount::from_u64(70000).unwrap();
-    let min_confirmations = NonZeroU32::new(10).unwrap();
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account_id = st.test_account().unwrap().account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
     let proposal = st
         .propose_standard_transfer::<Infallible>(
             account_id,
             StandardFeeRule::Zip317,
-            min_confirmations,
+            NonZeroU32::new(1).unwrap(),
             &to,
-            amount_sent,
+            NonNegativeAmount::const_from_u64(10000),
             None,
             None,
             T::SHIELDED_PROTOCOL,
         )
         .unwrap();
 
-    // Executing the proposal should succeed
-    let txid = st
-        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
-        .unwrap()[0];
+    // Both serialized forms carry the current serialization version, and round-trip through
+    // their byte encoding.
+    let proposal_bytes = proposal::Proposal::from_standard_proposal(&proposal).encode_to_vec();
+    let package_bytes =
+        proposal::Proposal::signable_package_from_standard_proposal(st.wallet_mut(), &proposal)
+            .unwrap()
+            .encode_to_vec();
 
-    let (h, _) = st.generate_next_block_including(txid);
-    st.scan_cached_blocks(h, 1);
+    let proposal_proto = proposal::Proposal::decode(&proposal_bytes[..]).unwrap();
+    assert_eq!(proposal_proto.proto_version, PROPOSAL_SER_V1);
+    assert_matches!(
+        proposal_proto.try_into_standard_proposal(st.wallet()),
+        Ok(p) if p == proposal
+    );
 
-    // TODO: send to an account so that we can check its balance.
-    assert_eq!(
-        st.get_total_balance(account_id),
-        ((value * 11).unwrap()
-            - (amount_sent + NonNegativeAmount::from_u64(10000).unwrap()).unwrap())
-        .unwrap()
+    let package_proto = proposal::Proposal::decode(&package_bytes[..]).unwrap();
+    assert_eq!(package_proto.proto_version, PROPOSAL_SER_V1);
+    assert_matches!(
+        package_proto.try_into_signable_package(),
+        Ok(package)
+            if package.proposal().steps().head.balance() == proposal.steps().head.balance()
     );
+
+    // A proposal serialized with a version that this library does not support is rejected,
+    // rather than being decoded under the assumptions of the supported version.
+    for found in [0, PROPOSAL_SER_V1 + 1] {
+        let mut proposal_proto = proposal_proto.clone();
+        proposal_proto.proto_version = found;
+        assert_matches!(
+            proposal_proto.try_into_standard_proposal(st.wallet()),
+            Err(ProposalDecodingError::UnsupportedProposalVersion { found: f, supported })
+                if f == found && supported == PROPOSAL_SER_V1
+        );
+
+        let mut package_proto = package_proto.clone();
+        package_proto.proto_version = found;
+        assert_matches!(
+            package_proto.try_into_signable_package(),
+            Err(ProposalDecodingError::UnsupportedProposalVersion { found: f, supported })
+                if f == found && supported == PROPOSAL_SER_V1
+        );
+    }
 }
 
-pub(crate) fn spend_fails_on_locked_notes<T: ShieldedPoolTester>() {
+pub(crate) fn send_proposed_transfer_from_signable_package<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
         .with_account_from_sapling_activation(BlockHash([0; 32]))
@@ -663,64 +795,2571 @@ pub(crate) fn spend_fails_on_locked_notes<T: ShieldedPoolTester>() {
     let account_id = account.account_id();
     let dfvk = T::test_account_fvk(&st);
 
-    // TODO: This test was originally written to use the pre-zip-313 fee rule
-    // and has not yet been updated.
-    #[allow(deprecated)]
-    let fee_rule = StandardFeeRule::PreZip313;
-
     // Add funds to the wallet in a single note
-    let value = NonNegativeAmount::const_from_u64(50000);
-    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
-    st.scan_cached_blocks(h1, 1);
-
-    // Spendable balance matches total balance at 1 confirmation.
-    assert_eq!(st.get_total_balance(account_id), value);
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
     assert_eq!(st.get_spendable_balance(account_id, 1), value);
 
-    // Send some of the funds to another address, but don't mine the tx.
-    let extsk2 = T::sk(&[0xf5; 32]);
-    let to = T::sk_default_address(&extsk2);
-    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let to_extsk = T::sk(&[0xf5; 32]);
+    let to: Address = T::sk_default_address(&to_extsk);
     let proposal = st
         .propose_standard_transfer::<Infallible>(
             account_id,
-            fee_rule,
-            min_confirmations,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
             &to,
-            NonNegativeAmount::const_from_u64(15000),
+            NonNegativeAmount::const_from_u64(10000),
             None,
             None,
             T::SHIELDED_PROTOCOL,
         )
         .unwrap();
 
-    // Executing the proposal should succeed
+    // The online wallet serializes the proposal along with the notes, witnesses and anchor
+    // required to spend its inputs.
+    let package_proto =
+        proposal::Proposal::signable_package_from_standard_proposal(st.wallet_mut(), &proposal)
+            .unwrap();
+
+    // The package remains a valid serialized proposal.
     assert_matches!(
-        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal,),
-        Ok(txids) if txids.len() == 1
+        package_proto.try_into_standard_proposal(st.wallet()),
+        Ok(p) if p == proposal
     );
 
-    // A second proposal fails because there are no usable notes
+    // A proposal serialized without spend data cannot be used as a signable package.
     assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            fee_rule,
-            NonZeroU32::new(1).unwrap(),
-            &to,
-            NonNegativeAmount::const_from_u64(2000),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+        proposal::Proposal::from_standard_proposal(&proposal).try_into_signable_package(),
+        Err(ProposalDecodingError::SpendDataMissing(_, _, _))
+    );
+
+    // The offline device constructs the transaction from the package alone.
+    let package = package_proto.try_into_signable_package().unwrap();
+    assert_eq!(
+        package.proposal().steps().head.balance(),
+        proposal.steps().head.balance()
+    );
+
+    let network = st.network();
+    let prover = test_prover();
+    let build_results = create_signable_package_transactions(
+        &network,
+        &prover,
+        &prover,
+        account.usk(),
+        OvkPolicy::Sender,
+        &package,
+    )
+    .unwrap();
+    assert_eq!(build_results.len(), 1);
+    let tx = build_results.head.transaction();
+
+    // The online wallet records the transaction, after which the spent note is no longer
+    // spendable.
+    assert_matches!(
+        decrypt_and_store_transaction(&network, st.wallet_mut(), tx),
+        Ok(_)
     );
+    assert_eq!(
+        st.get_spendable_balance(account_id, 1),
+        NonNegativeAmount::ZERO
+    );
+}
+
+pub(crate) fn received_notes_matching_memo<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note, which has no memo.
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // Pay the account's own external address with an order memo.
+    let to = T::fvk_default_address(&dfvk);
+    let order_memo = "order-42".parse::<Memo>().unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            Some(order_memo.encode()),
+            Some("order-43".parse::<Memo>().unwrap().encode()),
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
+
+    let received = |st: &TestState<_>, prefix: MemoPrefix| {
+        st.wallet()
+            .get_received_notes_matching_memo(account_id, |memo| prefix.matches(memo))
+            .unwrap()
+    };
+
+    // Only the payment matches; the change output is excluded even though its memo has the
+    // requested prefix.
+    let matching = received(&st, MemoPrefix::Text("order-".to_owned()));
+    assert_eq!(matching.len(), 1);
+    assert_eq!(matching[0].txid(), &txid);
+    assert_eq!(
+        st.wallet().get_memo(matching[0]).unwrap(),
+        Some(order_memo.clone())
+    );
+    assert_eq!(
+        st.wallet().get_note_scope(matching[0]).unwrap(),
+        Some(Scope::External)
+    );
+
+    assert!(received(&st, MemoPrefix::Text("order-43".to_owned())).is_empty());
+
+    // A text memo does not match a byte prefix, even if the prefix is its encoding.
+    assert!(received(&st, MemoPrefix::Bytes(b"order-".to_vec())).is_empty());
+
+    // An arbitrary predicate is applied to every received note that has a memo.
+    assert_eq!(
+        st.wallet()
+            .get_received_notes_matching_memo(account_id, |_| true)
+            .unwrap(),
+        matching
+    );
+}
+
+pub(crate) fn change_consolidation_keeps_note_count_flat<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    let count_notes = |st: &TestState<BlockCache>| {
+        let mut count = 0;
+        st.wallet()
+            .iter_received_notes(account_id, &[T::SHIELDED_PROTOCOL], |_| {
+                count += 1;
+                Ok::<_, SqliteClientError>(())
+            })
+            .unwrap();
+        count
+    };
+
+    // Add funds to the wallet in a single large note.
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(500000),
+    );
+    st.scan_cached_blocks(h, 1);
+    assert_eq!(count_notes(&st), 1);
+
+    let change_strategy = standard::SingleOutputChangeStrategy::new(
+        StandardFeeRule::Zip317,
+        None,
+        T::SHIELDED_PROTOCOL,
+    );
+    let input_selector = GreedyInputSelector::new(change_strategy, DustOutputPolicy::default())
+        .with_change_consolidation(true);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    for _ in 0..3 {
+        // Each round, the wallet receives a small note and then makes a payment that can be
+        // funded from a single note.
+        let (h, _, _) = st.generate_next_block(
+            &dfvk,
+            AddressType::DefaultExternal,
+            NonNegativeAmount::const_from_u64(20000),
+        );
+        st.scan_cached_blocks(h, 1);
+        assert_eq!(count_notes(&st), 2);
+
+        let request = zip321::TransactionRequest::new(vec![Payment::without_memo(
+            to.to_zcash_address(&st.network()),
+            NonNegativeAmount::const_from_u64(10000),
+        )])
+        .unwrap();
+        let txid = st
+            .spend(
+                &input_selector,
+                account.usk(),
+                request,
+                OvkPolicy::Sender,
+                NonZeroU32::new(1).unwrap(),
+            )
+            .unwrap()[0];
+
+        // The small note was spent alongside the note funding the payment, so that the change
+        // output replaces both of them.
+        assert_eq!(st.wallet().get_spent_notes(txid).unwrap().len(), 2);
+
+        let (h, _) = st.generate_next_block_including(txid);
+        st.scan_cached_blocks(h, 1);
+        assert_eq!(count_notes(&st), 1);
+    }
+}
+
+pub(crate) fn propose_transfer_with_fee_override<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let amount = NonNegativeAmount::const_from_u64(10000);
+    let request = zip321::TransactionRequest::new(vec![Payment::without_memo(
+        to.to_zcash_address(&st.network()),
+        amount,
+    )])
+    .unwrap();
+
+    let change_strategy = standard::SingleOutputChangeStrategy::new(
+        StandardFeeRule::Zip317,
+        None,
+        T::SHIELDED_PROTOCOL,
+    );
+    let input_selector = GreedyInputSelector::new(change_strategy, DustOutputPolicy::default());
+
+    let minimum = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request.clone(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
+        )
+        .unwrap()
+        .steps()
+        .head
+        .balance()
+        .fee_required();
+
+    // An override below the fee required by the fee rule is rejected.
+    let too_low = (minimum - NonNegativeAmount::const_from_u64(1)).unwrap();
+    assert_matches!(
+        st.propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request.clone(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default()
+                .with_change_pool_preference(&[T::SHIELDED_PROTOCOL])
+                .with_fee_override(too_low),
+        ),
+        Err(Error::FeeOverrideTooLow { minimum: m, requested })
+            if m == minimum && requested == too_low
+    );
+
+    // An override above the minimum is paid out of the change.
+    let fee = (minimum + NonNegativeAmount::const_from_u64(15000)).unwrap();
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request.clone(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default()
+                .with_change_pool_preference(&[T::SHIELDED_PROTOCOL])
+                .with_fee_override(fee),
+        )
+        .unwrap();
+    let balance = proposal.steps().head.balance();
+    assert_eq!(balance.fee_required(), fee);
+    assert_eq!(balance.proposed_change().len(), 1);
+    assert_eq!(
+        balance.proposed_change()[0].value(),
+        ((value - amount).unwrap() - fee).unwrap()
+    );
+
+    // An override that exceeds the change requires additional inputs to be selected, and fails
+    // if none are available.
+    let fee = (value + NonNegativeAmount::const_from_u64(5000)).unwrap();
+    let propose_with_fee = |st: &mut TestState<_>| {
+        st.propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request.clone(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default()
+                .with_change_pool_preference(&[T::SHIELDED_PROTOCOL])
+                .with_fee_override(fee),
+        )
+    };
+    assert_matches!(
+        propose_with_fee(&mut st),
+        Err(Error::InsufficientFunds { available, .. }) if available == value
+    );
+
+    let value2 = NonNegativeAmount::const_from_u64(40000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value2);
+    st.scan_cached_blocks(h, 1);
+
+    let proposal = propose_with_fee(&mut st).unwrap();
+    assert_eq!(
+        proposal
+            .steps()
+            .head
+            .shielded_inputs()
+            .unwrap()
+            .notes()
+            .len(),
+        2
+    );
+    let balance = proposal.steps().head.balance();
+    assert_eq!(balance.fee_required(), fee);
+    assert_eq!(balance.proposed_change().len(), 1);
+    assert_eq!(
+        balance.proposed_change()[0].value(),
+        (((value + value2).unwrap() - amount).unwrap() - fee).unwrap()
+    );
+
+    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
+        account.usk(),
+        OvkPolicy::Sender,
+        &proposal,
+    );
+    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 1);
+    let txid = create_proposed_result.unwrap()[0];
+
+    // The value balance of the built transaction pays the overridden fee.
+    let flows = st
+        .wallet()
+        .get_transaction_pool_flows(txid)
+        .unwrap()
+        .expect("Created transaction was stored.");
+    assert_eq!(
+        flows.for_pool(PoolType::Shielded(T::SHIELDED_PROTOCOL)),
+        -Amount::from((amount + fee).unwrap())
+    );
+    assert_eq!(flows.fee_paid(), Some(fee));
+    assert_eq!(st.wallet().get_transaction_fee(txid).unwrap(), Some(fee));
+}
+
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn send_multi_step_proposed_transfer<T: ShieldedPoolTester>() {
+    use nonempty::NonEmpty;
+    use zcash_client_backend::proposal::{Proposal, StepOutput, StepOutputIndex};
+    use zcash_primitives::legacy::keys::IncomingViewingKey;
+
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(65000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // Spendable balance matches total balance
+    assert_eq!(st.get_total_balance(account.account_id()), value);
+    assert_eq!(st.get_spendable_balance(account.account_id(), 1), value);
+
+    assert_eq!(
+        block_max_scanned(&st.wallet().conn, &st.wallet().params)
+            .unwrap()
+            .unwrap()
+            .block_height(),
+        h
+    );
+
+    // Generate a single-step proposal. Then, instead of executing that proposal,
+    // we will use its only step as the first step in a multi-step proposal that
+    // spends the first step's output.
+
+    // The first step will deshield to the wallet's default transparent address
+    let to0 = Address::Transparent(account.usk().default_transparent_address().0);
+    let request0 = zip321::TransactionRequest::new(vec![Payment::without_memo(
+        to0.to_zcash_address(&st.network()),
+        NonNegativeAmount::const_from_u64(50000),
+    )])
+    .unwrap();
+
+    let fee_rule = StandardFeeRule::Zip317;
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+    let proposal0 = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request0,
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
+        )
+        .unwrap();
+
+    let min_target_height = proposal0.min_target_height();
+    let step0 = &proposal0.steps().head;
+
+    assert!(step0.balance().proposed_change().is_empty());
+    assert_eq!(
+        step0.balance().fee_required(),
+        NonNegativeAmount::const_from_u64(15000)
+    );
+
+    // We'll use an internal transparent address that hasn't been added to the wallet
+    // to simulate an external transparent recipient.
+    let to1 = Address::Transparent(
+        account
+            .usk()
+            .transparent()
+            .to_account_pubkey()
+            .derive_internal_ivk()
+            .unwrap()
+            .default_address()
+            .0,
+    );
+    let request1 = zip321::TransactionRequest::new(vec![Payment::without_memo(
+        to1.to_zcash_address(&st.network()),
+        NonNegativeAmount::const_from_u64(40000),
+    )])
+    .unwrap();
+
+    let step1 = Step::from_parts(
+        &[step0.clone()],
+        request1,
+        [(0, PoolType::Transparent)].into_iter().collect(),
+        vec![],
+        None,
+        vec![StepOutput::new(0, StepOutputIndex::Payment(0))],
+        TransactionBalance::new(vec![], NonNegativeAmount::const_from_u64(10000)).unwrap(),
+        false,
+    )
+    .unwrap();
+
+    let proposal = Proposal::multi_step(
+        fee_rule,
+        min_target_height,
+        NonEmpty::from_vec(vec![step0.clone(), step1]).unwrap(),
+    )
+    .unwrap();
+
+    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
+        account.usk(),
+        OvkPolicy::Sender,
+        &proposal,
+    );
+    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 2);
+    let txids = create_proposed_result.unwrap();
+
+    // Verify that the stored sent outputs match what we're expecting
+    let mut stmt_sent = st
+        .wallet()
+        .conn
+        .prepare(
+            "SELECT value
+            FROM sent_notes
+            JOIN transactions ON transactions.id_tx = sent_notes.tx
+            WHERE transactions.txid = ?",
+        )
+        .unwrap();
+
+    let confirmed_sent = txids
+        .iter()
+        .map(|sent_txid| {
+            // check that there's a sent output with the correct value corresponding to
+            stmt_sent
+                .query(rusqlite::params![sent_txid.as_ref()])
+                .unwrap()
+                .mapped(|row| {
+                    let value: u32 = row.get(0)?;
+                    Ok((sent_txid, value))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        confirmed_sent.get(0).and_then(|v| v.get(0)),
+        Some(&(&txids[0], 50000))
+    );
+    assert_eq!(
+        confirmed_sent.get(1).and_then(|v| v.get(0)),
+        Some(&(&txids[1], 40000))
+    );
+}
+
+/// Without Orchard support, a payment to a Unified Address having only Orchard and
+/// transparent receivers is sent to the transparent receiver, which cannot carry a memo.
+#[cfg(not(feature = "orchard"))]
+pub(crate) fn propose_transfer_respects_memo_policy<T: ShieldedPoolTester>() {
+    use zcash_address::{
+        unified::{self, Encoding},
+        ZcashAddress,
+    };
+    use zcash_client_backend::data_api::wallet::MemoPolicy;
+
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let ua = unified::Address::try_from_items(vec![
+        unified::Receiver::Orchard([0x42; 43]),
+        unified::Receiver::P2pkh([0x17; 20]),
+    ])
+    .unwrap();
+    let to = ZcashAddress::from_unified(st.network().network_type(), ua);
+    let request = zip321::TransactionRequest::new(vec![Payment::new(
+        to.clone(),
+        NonNegativeAmount::const_from_u64(10000),
+        Some("Test memo".parse::<Memo>().unwrap().into()),
+        None,
+        None,
+        vec![],
+    )
+    .unwrap()])
+    .unwrap();
+
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(
+            StandardFeeRule::Zip317,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        DustOutputPolicy::default(),
+    );
+
+    // In strict mode, the memo causes the proposal to be rejected.
+    assert_matches!(
+        st.propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request.clone(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
+        ),
+        Err(data_api::error::Error::MemoForbidden { recipient }) if recipient == to
+    );
+
+    // In lenient mode, the memo is dropped and the payment is made without it.
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request,
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default()
+                .with_change_pool_preference(&[T::SHIELDED_PROTOCOL])
+                .with_memo_policy(MemoPolicy::Drop),
+        )
+        .unwrap();
+    let step = proposal.steps().first();
+    assert_eq!(step.payment_pools().get(&0), Some(&PoolType::Transparent));
+    let payment = step.transaction_request().payments().get(&0).unwrap();
+    assert_eq!(payment.recipient_address(), &to);
+    assert_eq!(payment.memo(), None);
+}
+
+pub(crate) fn propose_transfer_rejects_invalid_memos<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32])).to_zcash_address(&st.network());
+
+    // A memo longer than 512 bytes cannot be represented, and so is rejected when the payment
+    // request is assembled, whether directly or from a ZIP 321 URI.
+    let too_long = [0x61; 513];
+    assert_matches!(
+        MemoBytes::from_bytes(&too_long),
+        Err(zcash_primitives::memo::Error::TooLong(513))
+    );
+    // "YWFh" is the base64 encoding of "aaa", so this is the encoding of `too_long`.
+    let uri = format!(
+        "zcash:{}?amount=0.0001&memo={}",
+        to.encode(),
+        "YWFh".repeat(171)
+    );
+    assert_matches!(TransactionRequest::from_uri(&uri), Err(_));
+
+    // A text memo that is not valid UTF-8 is rejected before any transaction is built.
+    let request = TransactionRequest::new(vec![Payment::new(
+        to,
+        NonNegativeAmount::const_from_u64(10000),
+        Some(MemoBytes::from_bytes(&[0xc3, 0x28]).unwrap()),
+        None,
+        None,
+        vec![],
+    )
+    .unwrap()])
+    .unwrap();
+
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(
+            StandardFeeRule::Zip317,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        DustOutputPolicy::default(),
+    );
+    assert_matches!(
+        st.propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request,
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
+        ),
+        Err(Error::InvalidMemo {
+            reason: zcash_primitives::memo::Error::InvalidUtf8(_)
+        })
+    );
+}
+
+#[allow(deprecated)]
+pub(crate) fn create_to_address_fails_on_incorrect_usk<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+    let dfvk = T::test_account_fvk(&st);
+    let to = T::fvk_default_address(&dfvk);
+
+    // Create a USK that doesn't exist in the wallet
+    let acct1 = zip32::AccountId::try_from(1).unwrap();
+    let usk1 = UnifiedSpendingKey::from_seed(&st.network(), &[1u8; 32], acct1).unwrap();
+
+    // Attempting to spend with a USK that is not in the wallet results in an error
+    assert_matches!(
+        st.create_spend_to_address(
+            &usk1,
+            &to,
+            NonNegativeAmount::const_from_u64(1),
+            None,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::KeyNotRecognized)
+    );
+}
+
+#[allow(deprecated)]
+pub(crate) fn proposal_fails_with_no_blocks<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account_id = st.test_account().unwrap().account_id();
+    let dfvk = T::test_account_fvk(&st);
+    let to = T::fvk_default_address(&dfvk);
+
+    // Wallet summary is not yet available
+    assert_eq!(st.get_wallet_summary(0), None);
+
+    // We cannot do anything if we aren't synchronised
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::PreZip313,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(1),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::ScanRequired)
+    );
+}
+
+pub(crate) fn spend_fails_on_unverified_notes<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Value is considered pending at 10 confirmations.
+    assert_eq!(st.get_pending_shielded_balance(account_id, 10), value);
+    assert_eq!(
+        st.get_spendable_balance(account_id, 10),
+        NonNegativeAmount::ZERO
+    );
+
+    // Wallet is fully scanned
+    let summary = st.get_wallet_summary(1);
+    assert_eq!(
+        summary.and_then(|s| s.scan_progress()),
+        Some(Ratio::new(1, 1))
+    );
+
+    // Add more funds to the wallet in a second note
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h2, 1);
+
+    // Verified balance does not include the second note
+    let total = (value + value).unwrap();
+    assert_eq!(st.get_spendable_balance(account_id, 2), value);
+    assert_eq!(st.get_pending_shielded_balance(account_id, 2), value);
+    assert_eq!(st.get_total_balance(account_id), total);
+
+    // Wallet is still fully scanned
+    let summary = st.get_wallet_summary(1);
+    assert_eq!(
+        summary.and_then(|s| s.scan_progress()),
+        Some(Ratio::new(2, 2))
+    );
+
+    // Spend fails because there are insufficient verified notes
+    let extsk2 = T::sk(&[0xf5; 32]);
+    let to = T::sk_default_address(&extsk2);
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(2).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(70000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::const_from_u64(50000)
+            && required == NonNegativeAmount::const_from_u64(80000)
+    );
+
+    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 2 to 9 until just before the second
+    // note is verified
+    for _ in 2..10 {
+        st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    }
+    st.scan_cached_blocks(h2 + 1, 8);
+
+    // Total balance is value * number of blocks scanned (10).
+    assert_eq!(st.get_total_balance(account_id), (value * 10).unwrap());
+
+    // Spend still fails
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(10).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(70000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::const_from_u64(50000)
+            && required == NonNegativeAmount::const_from_u64(80000)
+    );
+
+    // Mine block 11 so that the second note becomes verified
+    let (h11, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h11, 1);
+
+    // Total balance is value * number of blocks scanned (11).
+    assert_eq!(st.get_total_balance(account_id), (value * 11).unwrap());
+    // Spendable balance at 10 confirmations is value * 2.
+    assert_eq!(
+        st.get_spendable_balance(account_id, 10),
+        (value * 2).unwrap()
+    );
+    assert_eq!(
+        st.get_pending_shielded_balance(account_id, 10),
+        (value * 9).unwrap()
+    );
+
+    // Should now be able to generate a proposal
+    let amount_sent = NonNegativeAmount::from_u64(70000).unwrap();
+    let min_confirmations = NonZeroU32::new(10).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            min_confirmations,
+            &to,
+            amount_sent,
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // Executing the proposal should succeed
+    let txid = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
+
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+
+    // TODO: send to an account so that we can check its balance.
+    assert_eq!(
+        st.get_total_balance(account_id),
+        ((value * 11).unwrap()
+            - (amount_sent + NonNegativeAmount::from_u64(10000).unwrap()).unwrap())
+        .unwrap()
+    );
+}
+
+pub(crate) fn spend_fails_on_locked_notes<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // TODO: This test was originally written to use the pre-zip-313 fee rule
+    // and has not yet been updated.
+    #[allow(deprecated)]
+    let fee_rule = StandardFeeRule::PreZip313;
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Send some of the funds to another address, but don't mine the tx.
+    let extsk2 = T::sk(&[0xf5; 32]);
+    let to = T::sk_default_address(&extsk2);
+    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            min_confirmations,
+            &to,
+            NonNegativeAmount::const_from_u64(15000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // Executing the proposal should succeed
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal,),
+        Ok(txids) if txids.len() == 1
+    );
+
+    // A second proposal fails because there are no usable notes
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(2000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+    );
+
+    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 1 to 41 (that don't send us funds)
+    // until just before the first transaction expires
+    for i in 1..42 {
+        st.generate_next_block(
+            &T::sk_to_fvk(&T::sk(&[i as u8; 32])),
+            AddressType::DefaultExternal,
+            value,
+        );
+    }
+    st.scan_cached_blocks(h1 + 1, 40);
+
+    // Second proposal still fails
+    assert_matches!(
+        st.propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(2000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        Err(data_api::error::Error::InsufficientFunds {
+            available,
+            required
+        })
+        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+    );
+
+    // Mine block SAPLING_ACTIVATION_HEIGHT + 42 so that the first transaction expires
+    let (h43, _, _) = st.generate_next_block(
+        &T::sk_to_fvk(&T::sk(&[42; 32])),
+        AddressType::DefaultExternal,
+        value,
+    );
+    st.scan_cached_blocks(h43, 1);
+
+    // Spendable balance matches total balance at 1 confirmation.
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Second spend should now succeed
+    let amount_sent2 = NonNegativeAmount::const_from_u64(2000);
+    let min_confirmations = NonZeroU32::new(1).unwrap();
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            fee_rule,
+            min_confirmations,
+            &to,
+            amount_sent2,
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    let txid2 = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
+
+    let (h, _) = st.generate_next_block_including(txid2);
+    st.scan_cached_blocks(h, 1);
+
+    // TODO: send to an account so that we can check its balance.
+    assert_eq!(
+        st.get_total_balance(account_id),
+        (value - (amount_sent2 + NonNegativeAmount::from_u64(10000).unwrap()).unwrap()).unwrap()
+    );
+}
+
+pub(crate) fn get_notes_pending_spend<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // No notes are pending spend before any transaction has been created.
+    assert!(st
+        .wallet()
+        .get_notes_pending_spend(account_id)
+        .unwrap()
+        .is_empty());
+
+    // Send some of the funds to another address, but don't mine the tx.
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(15000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    // The received note is reported as locked by the unmined transaction.
+    let pending_spends = st.wallet().get_notes_pending_spend(account_id).unwrap();
+    assert_eq!(pending_spends.len(), 1);
+    assert_eq!(pending_spends[0].0.protocol(), T::SHIELDED_PROTOCOL);
+    assert_eq!(
+        st.wallet()
+            .get_tx_height(*pending_spends[0].0.txid())
+            .unwrap(),
+        Some(h1)
+    );
+    assert_eq!(pending_spends[0].1, txid);
+
+    // Mine blocks (that don't send us funds) until just before the transaction expires.
+    for i in 1..42 {
+        st.generate_next_block(
+            &T::sk_to_fvk(&T::sk(&[i as u8; 32])),
+            AddressType::DefaultExternal,
+            value,
+        );
+    }
+    st.scan_cached_blocks(h1 + 1, 40);
+
+    // The note is still locked by the unexpired transaction.
+    assert_eq!(
+        st.wallet()
+            .get_notes_pending_spend(account_id)
+            .unwrap()
+            .into_iter()
+            .map(|(_, txid)| txid)
+            .collect::<Vec<_>>(),
+        vec![txid]
+    );
+
+    // Mine a block so that the transaction expires.
+    let (h, _, _) = st.generate_next_block(
+        &T::sk_to_fvk(&T::sk(&[42; 32])),
+        AddressType::DefaultExternal,
+        value,
+    );
+    st.scan_cached_blocks(h, 1);
+
+    // Once the transaction has expired, the note is no longer locked.
+    assert!(st
+        .wallet()
+        .get_notes_pending_spend(account_id)
+        .unwrap()
+        .is_empty());
+}
+
+pub(crate) fn abandon_transaction<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let propose_and_create = |st: &mut TestState<BlockCache>| {
+        let proposal = st
+            .propose_standard_transfer::<Infallible>(
+                account_id,
+                StandardFeeRule::Zip317,
+                NonZeroU32::new(1).unwrap(),
+                &to,
+                NonNegativeAmount::const_from_u64(15000),
+                None,
+                None,
+                T::SHIELDED_PROTOCOL,
+            )
+            .unwrap();
+        let txids = st
+            .create_proposed_transactions::<Infallible, _>(
+                account.usk(),
+                OvkPolicy::Sender,
+                &proposal,
+            )
+            .unwrap();
+        txids[0]
+    };
+
+    // Build a transaction, but do not broadcast it. The received note is now locked.
+    let txid = propose_and_create(&mut st);
+    assert_eq!(
+        st.get_spendable_balance(account_id, 1),
+        NonNegativeAmount::ZERO
+    );
+    assert_eq!(
+        st.wallet()
+            .get_notes_pending_spend(account_id)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // The transaction has not expired, so abandoning it must be forced.
+    assert_matches!(
+        st.wallet_mut().abandon_transaction(txid, false),
+        Err(SqliteClientError::TransactionMayHaveBeenBroadcast(t)) if t == txid
+    );
+    st.wallet_mut().abandon_transaction(txid, true).unwrap();
+
+    // The wallet no longer knows about the transaction, and its balance has been restored.
+    assert_matches!(st.wallet().get_transaction(txid), Ok(None));
+    assert!(st
+        .wallet()
+        .get_notes_pending_spend(account_id)
+        .unwrap()
+        .is_empty());
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_spendable_balance(account_id, 1), value);
+
+    // Abandoning a transaction that the wallet does not know about has no effect.
+    st.wallet_mut().abandon_transaction(txid, true).unwrap();
+
+    // The note can be spent again, and once that transaction is mined it cannot be abandoned.
+    let txid2 = propose_and_create(&mut st);
+    let (h2, _) = st.generate_next_block_including(txid2);
+    st.scan_cached_blocks(h2, 1);
+    assert_matches!(
+        st.wallet_mut().abandon_transaction(txid2, true),
+        Err(SqliteClientError::TransactionMined(t)) if t == txid2
+    );
+}
+
+pub(crate) fn orphaned_transaction_is_rebroadcast_candidate<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Send some of the funds, and mine the transaction.
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(15000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
+    let (h2, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h2, 1);
+    assert_eq!(st.wallet().get_tx_height(txid).unwrap(), Some(h2));
+    assert!(st.wallet().get_rebroadcast_candidates().unwrap().is_empty());
+
+    // Reorg the transaction out of the chain. It becomes a candidate for re-broadcast, and the
+    // note that it spends remains locked.
+    st.wallet_mut()
+        .transactionally(|wdb| truncate_to_height(wdb.conn.0, &wdb.params, h1))
+        .unwrap();
+    assert_eq!(st.wallet().get_tx_height(txid).unwrap(), None);
+    assert_eq!(
+        st.wallet().get_rebroadcast_candidates().unwrap(),
+        vec![txid]
+    );
+    assert!(st.wallet().get_transaction_bytes(txid).unwrap().is_some());
+    assert_eq!(
+        st.wallet()
+            .get_notes_pending_spend(account_id)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        st.get_spendable_balance(account_id, 1),
+        NonNegativeAmount::ZERO
+    );
+
+    // Once the transaction has been mined again, it is no longer a candidate.
+    st.scan_cached_blocks(h2, 1);
+    assert_eq!(st.wallet().get_tx_height(txid).unwrap(), Some(h2));
+    assert!(st.wallet().get_rebroadcast_candidates().unwrap().is_empty());
+}
+
+pub(crate) fn get_unmined_transactions<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Received transactions are not pending sends.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+    assert!(st.wallet().get_unmined_transactions().unwrap().is_empty());
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let send = |st: &mut TestState<BlockCache>| {
+        let proposal = st
+            .propose_standard_transfer::<Infallible>(
+                account_id,
+                StandardFeeRule::Zip317,
+                NonZeroU32::new(1).unwrap(),
+                &to,
+                NonNegativeAmount::const_from_u64(15000),
+                None,
+                None,
+                T::SHIELDED_PROTOCOL,
+            )
+            .unwrap();
+        st.create_proposed_transactions::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal,
+        )
+        .unwrap()[0]
+    };
+
+    // A transaction created by the wallet is pending until it is mined.
+    let txid = send(&mut st);
+    assert_eq!(st.wallet().get_unmined_transactions().unwrap(), vec![txid]);
+
+    let (h2, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h2, 1);
+    assert!(st.wallet().get_unmined_transactions().unwrap().is_empty());
+
+    // An unmined transaction that has expired as of the chain tip is no longer pending.
+    let txid = send(&mut st);
+    assert_eq!(st.wallet().get_unmined_transactions().unwrap(), vec![txid]);
+    st.wallet()
+        .conn
+        .execute(
+            "UPDATE transactions SET expiry_height = ? WHERE txid = ?",
+            params![u32::from(h2), txid.as_ref()],
+        )
+        .unwrap();
+    assert!(st.wallet().get_unmined_transactions().unwrap().is_empty());
+}
+
+pub(crate) fn get_tree_sizes<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let dfvk = T::test_account_fvk(&st);
+    let value = NonNegativeAmount::const_from_u64(50000);
+
+    let mut expected = vec![];
+    for _ in 0..2 {
+        let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+        let cached = st.latest_cached_block().unwrap();
+        expected.push((h, (cached.sapling_end_size, cached.orchard_end_size)));
+    }
+    let (h1, _) = expected[0];
+
+    // Tree sizes are not available for blocks that have not been scanned.
+    assert_eq!(st.wallet().get_tree_sizes(h1).unwrap(), None);
+
+    st.scan_cached_blocks(h1, 2);
+
+    for (h, sizes) in expected {
+        // The size of the Orchard tree is only recorded when Orchard support is enabled.
+        let expected_sizes = cfg!(feature = "orchard").then_some(sizes);
+        assert_eq!(st.wallet().get_tree_sizes(h).unwrap(), expected_sizes);
+    }
+    assert_eq!(st.wallet().get_tree_sizes(h1 + 2).unwrap(), None);
+}
+
+pub(crate) fn get_witnesses<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account_id = st.test_account().unwrap().account_id();
+    let dfvk = T::test_account_fvk(&st);
+    let one = NonZeroU32::new(1).unwrap();
+
+    // Create two blocks, each of which sends a note to the wallet.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 2);
+
+    let note_ids = st.wallet().get_newly_confirmed_notes(h1 - 1, one).unwrap();
+    assert_eq!(note_ids.len(), 2);
+
+    // Witnesses for both notes are produced as of the same anchor, in the requested order.
+    let witnesses = st.wallet().get_witnesses(&note_ids, h2).unwrap();
+    assert_eq!(
+        witnesses.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        note_ids
+    );
+    assert!(witnesses
+        .iter()
+        .all(|(_, witness)| witness.protocol() == T::SHIELDED_PROTOCOL));
+
+    let mut expected_positions =
+        T::select_spendable_notes(&st, account_id, (value + value).unwrap(), h2, &[])
+            .unwrap()
+            .iter()
+            .map(|n| n.note_commitment_tree_position())
+            .collect::<Vec<_>>();
+    expected_positions.sort();
+    let mut positions = witnesses
+        .iter()
+        .map(|(_, witness)| witness.position())
+        .collect::<Vec<_>>();
+    positions.sort();
+    assert_eq!(positions, expected_positions);
+
+    // The second note was not in the tree as of the first block, so no witness can be
+    // computed for it at that anchor.
+    assert!(st.wallet().get_witnesses(&note_ids, h1).is_err());
+    assert_eq!(
+        st.wallet().get_witnesses(&note_ids[..1], h1).unwrap().len(),
+        1
+    );
+
+    // Notes unknown to the wallet cannot be witnessed.
+    let unknown = NoteId::new(TxId::from_bytes([0; 32]), T::SHIELDED_PROTOCOL, 0);
+    assert_matches!(
+        st.wallet().get_witnesses(&[unknown], h2),
+        Err(SqliteClientError::NoteWitnessUnavailable(id)) if id == unknown
+    );
+}
+
+pub(crate) fn replace_commitment_tree<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let seed = Secret::new(st.test_seed().unwrap().expose_secret().clone());
+    let dfvk = T::test_account_fvk(&st);
+    let one = NonZeroU32::new(1).unwrap();
+
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 2);
+
+    let note_ids = st.wallet().get_newly_confirmed_notes(h1 - 1, one).unwrap();
+    assert_eq!(note_ids.len(), 2);
+    let witnesses = st.wallet().get_witnesses(&note_ids, h2).unwrap();
+    let note_positions = witnesses
+        .iter()
+        .map(|(note_id, witness)| (*note_id, witness.position()))
+        .collect::<Vec<_>>();
+
+    // Rebuild the tree by scanning the cached blocks into a new wallet, retaining the
+    // original wallet's database.
+    let wallet_file = st.reset();
+    st.wallet_mut()
+        .create_account(&seed, account.birthday())
+        .unwrap();
+    st.scan_cached_blocks(h1, 2);
+    let rejected_tree = T::copy_tree(&mut st);
+    let partial_tree = T::copy_tree(&mut st);
+    let rebuilt_tree = T::copy_tree(&mut st);
+
+    // Discard the original wallet's tree and note positions.
+    let mut wallet = WalletDb::for_path(wallet_file.path(), st.network()).unwrap();
+    wallet
+        .conn
+        .execute_batch(&format!(
+            "DELETE FROM {prefix}_tree_shards;
+            DELETE FROM {prefix}_tree_cap;
+            DELETE FROM {prefix}_tree_checkpoints;
+            UPDATE {prefix}_received_notes SET commitment_tree_position = NULL;",
+            prefix = T::TABLES_PREFIX
+        ))
+        .unwrap();
+    assert!(wallet.get_witnesses(&note_ids, h2).is_err());
+
+    // A replacement that refers to a note unknown to the wallet is rejected, and leaves the
+    // wallet unchanged.
+    let unknown = NoteId::new(TxId::from_bytes([0; 32]), T::SHIELDED_PROTOCOL, 0);
+    let mut unknown_positions = note_positions.clone();
+    unknown_positions.push((unknown, Position::from(0)));
+    assert_matches!(
+        wallet.replace_commitment_tree(rejected_tree, &unknown_positions),
+        Err(SqliteClientError::NoteNotFound(id)) if id == unknown
+    );
+    assert!(wallet.get_witnesses(&note_ids, h2).is_err());
+
+    // A note that is not positioned in the replacement tree has no position, and a rescan is
+    // required from the height at which it was mined so that its position can be recovered.
+    let (positioned, unpositioned) = (note_positions[0].0, note_positions[1].0);
+    wallet
+        .replace_commitment_tree(partial_tree, &note_positions[..1])
+        .unwrap();
+    assert_eq!(
+        wallet.get_witnesses(&[positioned], h2).unwrap(),
+        witnesses[..1]
+    );
+    assert!(wallet.get_witnesses(&[unpositioned], h2).is_err());
+    assert_eq!(
+        wallet.rescan_required().unwrap(),
+        wallet.get_tx_height(*unpositioned.txid()).unwrap()
+    );
+    wallet.clear_rescan_flag().unwrap();
+
+    // Once the tree is replaced, every unspent note has a witness consistent with the tree.
+    wallet
+        .replace_commitment_tree(rebuilt_tree, &note_positions)
+        .unwrap();
+    assert_eq!(wallet.get_witnesses(&note_ids, h2).unwrap(), witnesses);
+    assert_eq!(wallet.rescan_required().unwrap(), None);
+    assert_eq!(
+        wallet
+            .select_spendable_notes(
+                account_id,
+                (value + value).unwrap(),
+                &[T::SHIELDED_PROTOCOL],
+                h2,
+                &[],
+            )
+            .unwrap()
+            .total_value()
+            .unwrap(),
+        (value + value).unwrap()
+    );
+}
+
+pub(crate) fn blocks_until_spendable<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let dfvk = T::test_account_fvk(&st);
+    let one = NonZeroU32::new(1).unwrap();
+    let three = NonZeroU32::new(3).unwrap();
+
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let note_ids = st.wallet().get_newly_confirmed_notes(h - 1, one).unwrap();
+    assert_eq!(note_ids.len(), 1);
+    let note_id = note_ids[0];
+
+    // The note was mined in the chain tip block, so it has a single confirmation.
+    assert_eq!(
+        st.wallet().blocks_until_spendable(note_id, one).unwrap(),
+        None
+    );
+    assert_eq!(
+        st.wallet().blocks_until_spendable(note_id, three).unwrap(),
+        Some(2)
+    );
+
+    // Each new block brings the note one confirmation closer to being spendable.
+    let (h, _) = st.generate_empty_block();
+    st.scan_cached_blocks(h, 1);
+    assert_eq!(
+        st.wallet().blocks_until_spendable(note_id, three).unwrap(),
+        Some(1)
+    );
+
+    let (h, _) = st.generate_empty_block();
+    st.scan_cached_blocks(h, 1);
+    assert_eq!(
+        st.wallet().blocks_until_spendable(note_id, three).unwrap(),
+        None
+    );
+
+    // The remaining blocks are computed with respect to the chain tip reported by the given
+    // provider, so they can be checked at chain tips that have not been scanned.
+    let mined_height = h - 2;
+    let ten = NonZeroU32::new(10).unwrap();
+    let at_tip = |tip: Option<BlockHeight>| {
+        wallet::blocks_until_spendable(&st.wallet().conn, &FixedChainTip(tip), note_id, ten)
+    };
+    assert_eq!(at_tip(Some(mined_height + 8)).unwrap(), Some(1));
+    assert_eq!(at_tip(Some(mined_height + 9)).unwrap(), None);
+    assert_matches!(at_tip(None), Err(SqliteClientError::ChainHeightUnknown));
+
+    let unknown = NoteId::new(TxId::from_bytes([0; 32]), T::SHIELDED_PROTOCOL, 0);
+    assert_matches!(
+        st.wallet().blocks_until_spendable(unknown, one),
+        Err(SqliteClientError::NoteNotFound(id)) if id == unknown
+    );
+}
+
+pub(crate) fn projected_spendable_balance<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account_id = st.test_account().unwrap().account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let projected = |st: &TestState<BlockCache>, at_height: BlockHeight, min_confirmations| {
+        st.wallet()
+            .projected_spendable_balance(
+                account_id,
+                at_height,
+                NonZeroU32::new(min_confirmations).unwrap(),
+            )
+            .unwrap()
+    };
+
+    // At the current chain tip, the projection agrees with the account balance.
+    assert_eq!(projected(&st, h, 1), value);
+    assert_eq!(projected(&st, h, 3), NonNegativeAmount::ZERO);
+    assert_eq!(
+        st.get_spendable_balance(account_id, 3),
+        NonNegativeAmount::ZERO
+    );
+
+    // The note becomes spendable with three confirmations two blocks from now, without any
+    // further blocks being scanned.
+    assert_eq!(projected(&st, h + 1, 3), NonNegativeAmount::ZERO);
+    assert_eq!(projected(&st, h + 2, 3), value);
+
+    assert_matches!(
+        st.wallet()
+            .projected_spendable_balance(AccountId(42), h, NonZeroU32::new(1).unwrap()),
+        Err(SqliteClientError::AccountUnknown)
+    );
+}
+
+pub(crate) fn balances_readable_from_snapshot<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account_id = st.test_account().unwrap().account_id();
+    let dfvk = T::test_account_fvk(&st);
+    let one = NonZeroU32::new(1).unwrap();
+
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let summary = st.wallet().get_wallet_summary(1).unwrap().unwrap();
+    let note_ids = st.wallet().get_newly_confirmed_notes(h - 1, one).unwrap();
+    assert_eq!(note_ids.len(), 1);
+
+    // Each of the read operations that holds its own read transaction can also be performed
+    // within the read transaction of a snapshot.
+    let snapshot = st.wallet().snapshot().unwrap();
+    assert_eq!(
+        snapshot
+            .get_wallet_summary(1)
+            .unwrap()
+            .unwrap()
+            .account_balances(),
+        summary.account_balances()
+    );
+    assert_eq!(
+        snapshot
+            .get_account_balances(1)
+            .unwrap()
+            .get(&account_id)
+            .map(|balance| balance.spendable_value()),
+        Some(value)
+    );
+    assert_eq!(
+        snapshot
+            .projected_spendable_balance(account_id, h, one)
+            .unwrap(),
+        value
+    );
+    assert_eq!(snapshot.get_witnesses(&note_ids, h).unwrap().len(), 1);
+
+    let mut notes = 0;
+    snapshot
+        .iter_received_notes(account_id, &[T::SHIELDED_PROTOCOL], |_| {
+            notes += 1;
+            Ok::<_, SqliteClientError>(())
+        })
+        .unwrap();
+    assert_eq!(notes, 1);
+}
+
+pub(crate) fn witness_unavailable<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let note_ids = st
+        .wallet()
+        .get_newly_confirmed_notes(h - 1, NonZeroU32::new(1).unwrap())
+        .unwrap();
+    assert_eq!(note_ids.len(), 1);
+
+    // Corrupt the wallet's record of the note's position, so that it refers to a leaf that the
+    // note commitment tree does not contain as of the anchor height.
+    st.wallet()
+        .conn
+        .execute(
+            &format!(
+                "UPDATE {}_received_notes
+                SET commitment_tree_position = commitment_tree_position + 1",
+                T::TABLES_PREFIX
+            ),
+            [],
+        )
+        .unwrap();
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // The spend fails with an error that identifies the note and where to rescan from.
+    assert_matches!(
+        st.create_proposed_transactions::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal
+        ),
+        Err(Error::WitnessUnavailable {
+            note_id,
+            reason: QueryError::NotContained(_),
+            rescan_from: Some(height),
+        }) if note_id == note_ids[0] && height == h
+    );
+}
+
+pub(crate) fn unspent_set_fingerprint<T: ShieldedPoolTester>() {
+    let build = || {
+        TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build()
+    };
+    let mut st_a = build();
+    let mut st_b = build();
+
+    let account_id = st_a.test_account().unwrap().account_id();
+    assert_eq!(account_id, st_b.test_account().unwrap().account_id());
+    let fingerprint =
+        |st: &TestState<BlockCache>| st.wallet().unspent_set_fingerprint(account_id).unwrap();
+    let empty = fingerprint(&st_a);
+    assert_eq!(fingerprint(&st_b), empty);
+
+    // Both wallets receive the same note.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    for st in [&mut st_a, &mut st_b] {
+        let dfvk = T::test_account_fvk(st);
+        let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+        st.scan_cached_blocks(h, 1);
+    }
+    let one_note = fingerprint(&st_a);
+    assert_ne!(one_note, empty);
+    assert_eq!(fingerprint(&st_b), one_note);
+
+    // The fingerprints diverge when only one of the wallets receives a second note.
+    let dfvk = T::test_account_fvk(&st_a);
+    let (h, _, _) = st_a.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st_a.scan_cached_blocks(h, 1);
+    assert_ne!(fingerprint(&st_a), one_note);
+    assert_eq!(fingerprint(&st_b), one_note);
+
+    // Once the other wallet receives the same note, they converge again.
+    let dfvk = T::test_account_fvk(&st_b);
+    let (h, _, _) = st_b.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st_b.scan_cached_blocks(h, 1);
+    assert_eq!(fingerprint(&st_a), fingerprint(&st_b));
+
+    // Blocks that do not affect the unspent set do not change the fingerprint.
+    let two_notes = fingerprint(&st_a);
+    let (h, _) = st_a.generate_empty_block();
+    st_a.scan_cached_blocks(h, 1);
+    assert_eq!(fingerprint(&st_a), two_notes);
+}
+
+pub(crate) fn get_change_notes<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // Notes received from outside the wallet are not change.
+    assert_eq!(
+        st.wallet()
+            .get_change_notes(account.account_id())
+            .unwrap()
+            .len(),
+        0
+    );
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txids = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap();
+
+    // The change note is returned once its position in the note commitment tree is known.
+    let (h, _) = st.generate_next_block_including(*txids.first());
+    st.scan_cached_blocks(h, 1);
+
+    // Receive another external note, which should again be excluded.
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let change_notes = st.wallet().get_change_notes(account.account_id()).unwrap();
+    assert_eq!(change_notes.len(), 1);
+    let change = &change_notes[0];
+    assert_eq!(change.txid(), txids.first());
+    assert_eq!(change.spending_key_scope(), Scope::Internal);
+    assert_eq!(
+        change.note().value(),
+        NonNegativeAmount::const_from_u64(40000)
+    );
+}
+
+pub(crate) fn get_transactions_spending_pool<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    // The spending transaction is not reported until it has been mined.
+    assert_eq!(
+        st.wallet()
+            .get_transactions_spending_pool(T::SHIELDED_PROTOCOL, h1..(h1 + 10))
+            .unwrap(),
+        vec![]
+    );
+
+    let (h2, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h2, 1);
+
+    assert_eq!(
+        st.wallet()
+            .get_transactions_spending_pool(T::SHIELDED_PROTOCOL, h1..(h2 + 1))
+            .unwrap(),
+        vec![txid]
+    );
+    assert_eq!(
+        st.wallet()
+            .get_transactions_spending_pool(T::SHIELDED_PROTOCOL, h1..h2)
+            .unwrap(),
+        vec![]
+    );
+}
+
+pub(crate) fn get_fees_paid<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+    let fee = proposal.steps().head.balance().fee_required();
+    assert_eq!(st.wallet().get_transaction_fee(txid).unwrap(), Some(fee));
+
+    // The fee is not reported until the transaction has been mined.
+    assert_eq!(st.wallet().get_fees_paid(h1..(h1 + 10)).unwrap(), vec![]);
+
+    let (h2, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h2, 1);
+
+    // The transaction that paid funds to the wallet is not one of the wallet's own transactions.
+    assert_eq!(
+        st.wallet().get_fees_paid(h1..(h2 + 1)).unwrap(),
+        vec![(txid, fee)]
+    );
+    assert_eq!(st.wallet().get_fees_paid(h1..h2).unwrap(), vec![]);
+
+    // Without a recorded fee, the fee is computed from the wallet's spent inputs and the
+    // transaction's recorded outputs.
+    st.wallet()
+        .conn
+        .execute(
+            "UPDATE transactions SET fee = NULL WHERE txid = ?",
+            params![txid.as_ref()],
+        )
+        .unwrap();
+    assert_eq!(
+        st.wallet().get_fees_paid(h1..(h2 + 1)).unwrap(),
+        vec![(txid, fee)]
+    );
+}
+
+pub(crate) fn locked_note_skipped_by_selection<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    let locked_value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, locked_value);
+    st.scan_cached_blocks(h, 1);
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DefaultExternal,
+        NonNegativeAmount::const_from_u64(50000),
+    );
+    st.scan_cached_blocks(h, 1);
+
+    let mut locked = None;
+    st.wallet()
+        .iter_received_notes(account_id, &[T::SHIELDED_PROTOCOL], |note| {
+            if note.note().value() == locked_value {
+                locked = Some(NoteId::new(
+                    *note.txid(),
+                    T::SHIELDED_PROTOCOL,
+                    note.output_index(),
+                ));
+            }
+            Ok::<_, SqliteClientError>(())
+        })
+        .unwrap();
+    let locked = locked.unwrap();
+
+    st.wallet_mut().lock_note(locked).unwrap();
+    assert_eq!(
+        st.wallet().get_locked_notes(account_id).unwrap(),
+        vec![locked]
+    );
+
+    // The locked note's value is reported separately from the spendable balance.
+    let unlocked_value = NonNegativeAmount::const_from_u64(50000);
+    assert_eq!(st.get_spendable_balance(account_id, 1), unlocked_value);
+    assert_eq!(st.get_locked_balance(account_id, 1), locked_value);
+    assert_eq!(
+        st.get_total_balance(account_id),
+        (locked_value + unlocked_value).unwrap()
+    );
+
+    let is_locked = |note: &ReceivedNote<ReceivedNoteId, Note>| {
+        NoteId::new(*note.txid(), T::SHIELDED_PROTOCOL, note.output_index()) == locked
+    };
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32])).to_zcash_address(&st.network());
+    let request = |value| {
+        zip321::TransactionRequest::new(vec![Payment::without_memo(
+            to.clone(),
+            NonNegativeAmount::const_from_u64(value),
+        )])
+        .unwrap()
+    };
+
+    let change_strategy = standard::SingleOutputChangeStrategy::new(
+        StandardFeeRule::Zip317,
+        None,
+        T::SHIELDED_PROTOCOL,
+    );
+    let input_selector = GreedyInputSelector::new(change_strategy, DustOutputPolicy::default());
+
+    // The locked note is not selected, even though it alone could fund the payment.
+    let proposal = st
+        .propose_transfer(
+            account_id,
+            &input_selector,
+            request(10000),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
+        )
+        .unwrap();
+    let inputs = proposal.steps().head.shielded_inputs().unwrap().notes();
+    assert!(!inputs.iter().any(is_locked));
+
+    // A payment that can only be funded using the locked note fails by default.
+    assert_matches!(
+        st.propose_transfer(
+            account_id,
+            &input_selector,
+            request(80000),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
+        ),
+        Err(Error::InsufficientFunds { .. })
+    );
+
+    // If the selector is permitted to ignore locks, the locked note is used as a last resort.
+    let change_strategy = standard::SingleOutputChangeStrategy::new(
+        StandardFeeRule::Zip317,
+        None,
+        T::SHIELDED_PROTOCOL,
+    );
+    let input_selector = GreedyInputSelector::new(change_strategy, DustOutputPolicy::default())
+        .with_respect_locks(false);
+    let proposal = st
+        .propose_transfer(
+            account_id,
+            &input_selector,
+            request(80000),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
+        )
+        .unwrap();
+    let inputs = proposal.steps().head.shielded_inputs().unwrap().notes();
+    assert!(inputs.iter().any(is_locked));
+
+    st.wallet_mut().unlock_note(locked).unwrap();
+    assert_eq!(st.wallet().get_locked_notes(account_id).unwrap(), vec![]);
+    assert_eq!(
+        st.get_spendable_balance(account_id, 1),
+        (locked_value + unlocked_value).unwrap()
+    );
+    assert_eq!(
+        st.get_locked_balance(account_id, 1),
+        NonNegativeAmount::ZERO
+    );
+
+    let unknown = NoteId::new(TxId::from_bytes([0; 32]), T::SHIELDED_PROTOCOL, 0);
+    assert_matches!(
+        st.wallet_mut().lock_note(unknown),
+        Err(SqliteClientError::NoteNotFound(id)) if id == unknown
+    );
+}
+
+pub(crate) fn pending_change_reported_separately<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let amount = NonNegativeAmount::const_from_u64(10000);
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            amount,
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+    let fee = proposal.steps().head.balance().fee_required();
+    let change = (value - amount - fee).unwrap();
+
+    let (h2, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h2, 1);
+
+    // Funds are received from another party after the change output was mined.
+    let incoming = NonNegativeAmount::const_from_u64(20000);
+    let (h3, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, incoming);
+    st.scan_cached_blocks(h3, 1);
+
+    // With three confirmations required, neither the change nor the incoming funds are
+    // spendable, and each is reported separately.
+    assert_eq!(
+        st.wallet()
+            .get_change_pending_confirmation(account_id, 3)
+            .unwrap(),
+        Some(change)
+    );
+    assert_eq!(
+        st.wallet()
+            .get_value_pending_spendability(account_id, 3)
+            .unwrap(),
+        Some(incoming)
+    );
+    assert_eq!(
+        st.get_pending_shielded_balance(account_id, 3),
+        (change + incoming).unwrap()
+    );
+
+    // Once both outputs have sufficient confirmations, nothing is pending.
+    assert_eq!(
+        st.wallet()
+            .get_change_pending_confirmation(account_id, 1)
+            .unwrap(),
+        Some(NonNegativeAmount::ZERO)
+    );
+    assert_eq!(
+        st.wallet()
+            .get_value_pending_spendability(account_id, 1)
+            .unwrap(),
+        Some(NonNegativeAmount::ZERO)
+    );
+}
+
+pub(crate) fn pool_requirement_violations<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let p0_fvk = P0::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // Pay the account's own external P0 address, so that the payment is decrypted as incoming.
+    let payment = NonNegativeAmount::const_from_u64(10000);
+    #[allow(unused_mut)]
+    let mut payments = vec![Payment::without_memo(
+        P0::fvk_default_address(&p0_fvk).to_zcash_address(&st.network()),
+        payment,
+    )];
+    #[allow(unused_mut)]
+    let mut transparent_receivers = HashMap::new();
+
+    // Also pay the account's own transparent address, which is one of the wallet's
+    // transparent receivers.
+    #[cfg(feature = "transparent-inputs")]
+    {
+        let (taddr, _) = account.usk().default_transparent_address();
+        payments.push(Payment::without_memo(
+            Address::Transparent(taddr).to_zcash_address(&st.network()),
+            payment,
+        ));
+        transparent_receivers.insert(taddr, account.account_id());
+    }
+
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(
+            StandardFeeRule::Zip317,
+            None,
+            P0::SHIELDED_PROTOCOL,
+        ),
+        DustOutputPolicy::default(),
+    );
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            TransactionRequest::new(payments).unwrap(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[P0::SHIELDED_PROTOCOL]),
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    let tx = st.wallet().get_transaction(txid).unwrap().unwrap();
+    let ufvks = [(
+        account.account_id(),
+        account.usk().to_unified_full_viewing_key(),
+    )]
+    .into_iter()
+    .collect();
+    let d_tx = decrypt_transaction(&st.network(), h + 1, &tx, &ufvks);
+
+    // Under a P0 policy, the shielded payment is compliant and the change output is not a
+    // payment. Only the transparent payment (if any) is flagged.
+    let violations = d_tx.pool_requirement_violations(
+        PoolType::Shielded(P0::SHIELDED_PROTOCOL),
+        &transparent_receivers,
+    );
+    #[cfg(not(feature = "transparent-inputs"))]
+    assert_eq!(violations, vec![]);
+    #[cfg(feature = "transparent-inputs")]
+    {
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].txid(), &txid);
+        assert_eq!(violations[0].pool(), PoolType::Transparent);
+        assert_eq!(violations[0].note_id(), None);
+        assert_eq!(violations[0].account(), account.account_id());
+        assert_eq!(violations[0].value(), payment);
+    }
+
+    // Under a P1 policy, the payment received in P0 is flagged as well.
+    let violations = d_tx.pool_requirement_violations(
+        PoolType::Shielded(P1::SHIELDED_PROTOCOL),
+        &transparent_receivers,
+    );
+    assert_eq!(
+        violations.len(),
+        if cfg!(feature = "transparent-inputs") {
+            2
+        } else {
+            1
+        }
+    );
+    let shielded = violations
+        .iter()
+        .find(|v| v.pool() == PoolType::Shielded(P0::SHIELDED_PROTOCOL))
+        .unwrap();
+    assert_eq!(
+        shielded.note_id().map(|note_id| *note_id.txid()),
+        Some(txid)
+    );
+    assert_eq!(shielded.account(), account.account_id());
+    assert_eq!(shielded.value(), payment);
+}
+
+pub(crate) fn audit_transaction<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    // The wallet's record of the change note matches the note recovered from the transaction.
+    let report = st.wallet().audit_transaction(txid).unwrap();
+    assert_eq!(report.txid(), txid);
+    assert_eq!(report.notes_recovered(), 1);
+    assert!(report.is_consistent());
+
+    let corrupt = |st: &TestState<BlockCache>, sql: &str| {
+        st.wallet()
+            .conn
+            .execute(
+                &format!(
+                    "UPDATE {}_received_notes SET {}
+                    WHERE tx = (SELECT id_tx FROM transactions WHERE txid = ?)",
+                    T::TABLES_PREFIX,
+                    sql
+                ),
+                [txid.as_ref()],
+            )
+            .unwrap();
+    };
+
+    corrupt(&st, "value = 1");
+    assert_matches!(
+        st.wallet().audit_transaction(txid).unwrap().mismatches(),
+        [AuditMismatch::Value { note_id, stored, recovered }]
+            if note_id.txid() == &txid
+                && stored == &NonNegativeAmount::const_from_u64(1)
+                && recovered == &NonNegativeAmount::const_from_u64(40000)
+    );
+
+    corrupt(&st, "value = 40000, memo = X'01'");
+    assert_matches!(
+        st.wallet().audit_transaction(txid).unwrap().mismatches(),
+        [AuditMismatch::Memo { recovered, .. }] if recovered == &MemoBytes::empty()
+    );
+
+    st.wallet()
+        .conn
+        .execute(
+            &format!(
+                "DELETE FROM {}_received_notes
+                WHERE tx = (SELECT id_tx FROM transactions WHERE txid = ?)",
+                T::TABLES_PREFIX
+            ),
+            [txid.as_ref()],
+        )
+        .unwrap();
+    assert_matches!(
+        st.wallet().audit_transaction(txid).unwrap().mismatches(),
+        [AuditMismatch::MissingNote(note_id)] if note_id.txid() == &txid
+    );
+
+    // A transaction that is unknown to the wallet cannot be audited.
+    let unknown = TxId::from_bytes([0; 32]);
+    assert_matches!(
+        st.wallet().audit_transaction(unknown),
+        Err(SqliteClientError::TransactionNotFound(id)) if id == unknown
+    );
+}
+
+pub(crate) fn proposal_anchor_info<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Outputs to another wallet are created in the blocks after the note is received.
+    let not_our_fvk = T::sk_to_fvk(&T::sk(&[42; 32]));
+    st.generate_next_block(&not_our_fvk, AddressType::DefaultExternal, value);
+    let (h3, _, _) = st.generate_next_block(&not_our_fvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1 + 1, 2);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let propose = |st: &mut TestState<_>, min_confirmations| {
+        st.propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(min_confirmations).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap()
+    };
+
+    // With three confirmations required, the anchor is the block in which the note was
+    // received, and all of the outputs created since then are after the anchor.
+    let proposal = propose(&mut st, 3);
+    let anchor_info = proposal.anchor_info(st.wallet()).unwrap().unwrap();
+    assert_eq!(anchor_info.height(), h1);
+    assert_eq!(anchor_info.age_blocks(), 2);
+
+    let total_size = |height| {
+        let (sapling_size, orchard_size) = st.wallet().get_tree_sizes(height).unwrap().unwrap();
+        u64::from(sapling_size) + u64::from(orchard_size)
+    };
+    let outputs_after = total_size(h3) - total_size(h1);
+    assert!(outputs_after >= 2);
+    assert_eq!(anchor_info.outputs_after(), Some(outputs_after));
+
+    // With a single confirmation required, the anchor is at the chain tip.
+    let proposal = propose(&mut st, 1);
+    let anchor_info = proposal.anchor_info(st.wallet()).unwrap().unwrap();
+    assert_eq!(anchor_info.height(), h3);
+    assert_eq!(anchor_info.age_blocks(), 0);
+    assert_eq!(anchor_info.outputs_after(), Some(0));
+}
+
+pub(crate) fn create_proposed_transactions_for_branch<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+
+    // In the test network, Sapling through NU5 activate at the same height, so the Canopy
+    // branch was never active at the anchor height.
+    assert_matches!(
+        st.create_proposed_transactions_with_options::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal,
+            Some(BranchId::Canopy),
+            None,
+        ),
+        Err(Error::BranchIdInvalid { branch_id: BranchId::Canopy, anchor_height }) if anchor_height <= h
+    );
+
+    let txids = st
+        .create_proposed_transactions_with_options::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal,
+            Some(BranchId::Nu5),
+            None,
+        )
+        .unwrap();
+    let tx = st.wallet().get_transaction(txids[0]).unwrap().unwrap();
+    assert_eq!(tx.consensus_branch_id(), BranchId::Nu5);
+}
+
+pub(crate) fn send_change_to_diversifier_index<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+    let sk = T::usk_to_sk(account.usk());
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account.account_id(),
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(10000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    assert!(!proposal.steps().head.balance().proposed_change().is_empty());
+
+    // An index that does not yield a valid internal address is rejected. (Every index is valid
+    // for Orchard.)
+    if let Some(invalid_j) = (0u32..100)
+        .map(DiversifierIndex::from)
+        .find(|j| T::sk_internal_diversifier(sk, *j).is_none())
+    {
+        assert_matches!(
+            st.create_proposed_transactions_with_options::<Infallible, _>(
+                account.usk(),
+                OvkPolicy::Sender,
+                &proposal,
+                None,
+                Some(invalid_j),
+            ),
+            Err(Error::ChangeDiversifierIndexInvalid(j)) if j == invalid_j
+        );
+    }
+
+    let (change_j, expected_diversifier) = (5u32..)
+        .map(DiversifierIndex::from)
+        .find_map(|j| T::sk_internal_diversifier(sk, j).map(|d| (j, d)))
+        .unwrap();
+    let txids = st
+        .create_proposed_transactions_with_options::<Infallible, _>(
+            account.usk(),
+            OvkPolicy::Sender,
+            &proposal,
+            None,
+            Some(change_j),
+        )
+        .unwrap();
+
+    // The change note was sent to the internal address at the requested index.
+    let change_diversifier: Vec<u8> = st
+        .wallet()
+        .conn
+        .query_row(
+            &format!(
+                "SELECT n.diversifier
+                 FROM {}_received_notes n
+                 JOIN transactions t ON t.id_tx = n.tx
+                 WHERE t.txid = ? AND n.is_change = 1",
+                T::TABLES_PREFIX,
+            ),
+            params![txids[0].as_ref()],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(change_diversifier, expected_diversifier);
+}
+
+pub(crate) fn abandon_expired_transaction<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Build a transaction, but do not mine it.
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(15000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    let txid = st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()[0];
 
-    // Mine blocks SAPLING_ACTIVATION_HEIGHT + 1 to 41 (that don't send us funds)
-    // until just before the first transaction expires
+    // Mine blocks (that don't send us funds) until just before the transaction expires.
     for i in 1..42 {
         st.generate_next_block(
             &T::sk_to_fvk(&T::sk(&[i as u8; 32])),
@@ -728,28 +3367,16 @@ pub(crate) fn spend_fails_on_locked_notes<T: ShieldedPoolTester>() {
             value,
         );
     }
-    st.scan_cached_blocks(h1 + 1, 40);
+    st.scan_cached_blocks(h1 + 1, 41);
 
-    // Second proposal still fails
+    // The unexpired transaction may have been broadcast, so it cannot be abandoned unless
+    // forced.
     assert_matches!(
-        st.propose_standard_transfer::<Infallible>(
-            account_id,
-            fee_rule,
-            NonZeroU32::new(1).unwrap(),
-            &to,
-            NonNegativeAmount::const_from_u64(2000),
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
-        ),
-        Err(data_api::error::Error::InsufficientFunds {
-            available,
-            required
-        })
-        if available == NonNegativeAmount::ZERO && required == NonNegativeAmount::const_from_u64(12000)
+        st.wallet_mut().abandon_transaction(txid, false),
+        Err(SqliteClientError::TransactionMayHaveBeenBroadcast(t)) if t == txid
     );
 
-    // Mine block SAPLING_ACTIVATION_HEIGHT + 42 so that the first transaction expires
+    // Mine one more block so that the transaction expires.
     let (h43, _, _) = st.generate_next_block(
         &T::sk_to_fvk(&T::sk(&[42; 32])),
         AddressType::DefaultExternal,
@@ -757,38 +3384,58 @@ pub(crate) fn spend_fails_on_locked_notes<T: ShieldedPoolTester>() {
     );
     st.scan_cached_blocks(h43, 1);
 
-    // Spendable balance matches total balance at 1 confirmation.
+    // The expired transaction can now be abandoned without being forced.
+    st.wallet_mut().abandon_transaction(txid, false).unwrap();
+    assert_matches!(st.wallet().get_transaction(txid), Ok(None));
     assert_eq!(st.get_total_balance(account_id), value);
     assert_eq!(st.get_spendable_balance(account_id, 1), value);
+}
 
-    // Second spend should now succeed
-    let amount_sent2 = NonNegativeAmount::const_from_u64(2000);
-    let min_confirmations = NonZeroU32::new(1).unwrap();
-    let proposal = st
-        .propose_standard_transfer::<Infallible>(
-            account_id,
-            fee_rule,
-            min_confirmations,
-            &to,
-            amount_sent2,
-            None,
-            None,
-            T::SHIELDED_PROTOCOL,
-        )
-        .unwrap();
+pub(crate) fn inconsistent_tree_sets_rescan_required<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
 
-    let txid2 = st
-        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
-        .unwrap()[0];
+    let dfvk = T::test_account_fvk(&st);
+    let value = NonNegativeAmount::const_from_u64(50000);
 
-    let (h, _) = st.generate_next_block_including(txid2);
-    st.scan_cached_blocks(h, 1);
+    // Scan a block containing a note for the wallet; its commitment is at position 0.
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+    assert_eq!(st.wallet().rescan_required().unwrap(), None);
+
+    // Replace the cached block with a different block at the same height, so that a different
+    // note commitment is placed at the same position in the note commitment tree.
+    st.cache
+        .db_cache
+        .0
+        .execute(
+            "DELETE FROM compactblocks WHERE height >= ?",
+            [u32::from(h1)],
+        )
+        .unwrap();
+    st.cached_blocks.split_off(&h1);
+    st.generate_block_at(
+        h1,
+        BlockHash([0; 32]),
+        &dfvk,
+        AddressType::DefaultExternal,
+        value,
+        0,
+        0,
+        false,
+    );
 
-    // TODO: send to an account so that we can check its balance.
-    assert_eq!(
-        st.get_total_balance(account_id),
-        (value - (amount_sent2 + NonNegativeAmount::from_u64(10000).unwrap()).unwrap()).unwrap()
+    // Scanning the replacement block fails because the tree data is inconsistent, and the
+    // wallet records that a rescan is required from that height.
+    assert_matches!(
+        st.try_scan_cached_blocks(h1, 1, None),
+        Err(chain::error::Error::Wallet(
+            SqliteClientError::CommitmentTree(ShardTreeError::Insert(_))
+        ))
     );
+    assert_eq!(st.wallet().rescan_required().unwrap(), Some(h1));
 }
 
 pub(crate) fn ovk_policy_prevents_recovery_from_chain<T: ShieldedPoolTester>() {
@@ -927,11 +3574,36 @@ pub(crate) fn spend_succeeds_to_t_addr_zero_change<T: ShieldedPoolTester>() {
         )
         .unwrap();
 
+    // The note exactly covers the payment and the fee, so no change output is proposed.
+    assert!(proposal.steps().head.balance().proposed_change().is_empty());
+
     // Executing the proposal should succeed
-    assert_matches!(
-        st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal),
-        Ok(txids) if txids.len() == 1
+    let create_proposed_result = st.create_proposed_transactions::<Infallible, _>(
+        account.usk(),
+        OvkPolicy::Sender,
+        &proposal,
     );
+    assert_matches!(&create_proposed_result, Ok(txids) if txids.len() == 1);
+    let sent_tx_id = create_proposed_result.unwrap()[0];
+
+    // No change note was stored for the transaction.
+    let change_note_count: i64 = st
+        .wallet()
+        .conn
+        .query_row(
+            &format!(
+                "SELECT COUNT(*)
+                 FROM {}_received_notes
+                 JOIN transactions ON transactions.id_tx = {}_received_notes.tx
+                 WHERE transactions.txid = ?",
+                T::TABLES_PREFIX,
+                T::TABLES_PREFIX,
+            ),
+            params![sent_tx_id.as_ref()],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(change_note_count, 0);
 }
 
 pub(crate) fn change_note_spends_succeed<T: ShieldedPoolTester>() {
@@ -1068,6 +3740,8 @@ pub(crate) fn external_address_change_spends_detected_in_restore_from_seed<
     assert_eq!(st.get_total_balance(account_id), amount_left);
 
     st.reset();
+    st.reset_latest_cached_block();
+    assert_eq!(st.latest_cached_block().map(|b| b.height()), Some(h));
 
     // Account creation and DFVK derivation should be deterministic.
     let (_, restored_usk) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
@@ -1088,6 +3762,233 @@ pub(crate) fn external_address_change_spends_detected_in_restore_from_seed<
     assert_eq!(st.get_total_balance(account_id), amount_left);
 }
 
+pub(crate) fn send_to_other_account_records_pending_note<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new().with_block_cache().build();
+
+    // Add two accounts to the wallet.
+    let seed = Secret::new([0u8; 32].to_vec());
+    let birthday = AccountBirthday::from_sapling_activation(&st.network(), BlockHash([0; 32]));
+    let (account_id, usk) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+    let dfvk = T::sk_to_fvk(T::usk_to_sk(&usk));
+
+    let (account2, usk2) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+    let dfvk2 = T::sk_to_fvk(T::usk_to_sk(&usk2));
+
+    // Add funds to the first account in a single note
+    let value = NonNegativeAmount::const_from_u64(60000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+    assert_eq!(st.get_total_balance(account2), NonNegativeAmount::ZERO);
+
+    // Send funds from the first account to the second account's default address.
+    let amount_sent = NonNegativeAmount::const_from_u64(20000);
+    let addr2 = T::fvk_default_address(&dfvk2);
+    let req = TransactionRequest::new(vec![Payment::without_memo(
+        addr2.to_zcash_address(&st.network()),
+        amount_sent,
+    )])
+    .unwrap();
+
+    let change_strategy = standard::SingleOutputChangeStrategy::new(
+        StandardFeeRule::Zip317,
+        None,
+        T::SHIELDED_PROTOCOL,
+    );
+    let input_selector = GreedyInputSelector::new(change_strategy, DustOutputPolicy::default());
+
+    let txid = st
+        .spend(
+            &input_selector,
+            &usk,
+            req,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap()[0];
+
+    // The receiving account sees the payment as pending before the transaction is mined,
+    // and it is not treated as change.
+    assert_eq!(st.get_total_balance(account2), amount_sent);
+    assert_eq!(st.get_pending_shielded_balance(account2, 1), amount_sent);
+    assert_eq!(st.get_pending_change(account2, 1), NonNegativeAmount::ZERO);
+    assert_eq!(
+        st.get_spendable_balance(account2, 1),
+        NonNegativeAmount::ZERO
+    );
+
+    // The sent note is attributed to the receiving account.
+    let to_account_id: Option<u32> = st
+        .wallet()
+        .conn
+        .query_row(
+            "SELECT to_account_id FROM sent_notes
+             JOIN transactions ON transactions.id_tx = sent_notes.tx
+             WHERE transactions.txid = ? AND to_address IS NOT NULL",
+            rusqlite::params![txid.as_ref()],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(to_account_id, Some(account2.0));
+
+    // Once the transaction is mined and scanned, the note is not double-counted and
+    // becomes spendable.
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+
+    assert_eq!(st.get_total_balance(account2), amount_sent);
+    assert_eq!(st.get_spendable_balance(account2, 1), amount_sent);
+    assert!(st.get_total_balance(account_id) < (value - amount_sent).unwrap());
+}
+
+pub(crate) fn discover_diversified_addresses<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+    let default_addr = st
+        .wallet()
+        .get_current_address(account.account_id())
+        .unwrap()
+        .unwrap();
+
+    // Receive funds at the default address and at a diversified address that the wallet has
+    // not generated, as would be the case after restoring from seed.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(
+        &dfvk,
+        AddressType::DiversifiedExternal(DiversifierIndex::from(5u32)),
+        value,
+    );
+    st.scan_cached_blocks(h1, 2);
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value + value).unwrap()
+    );
+
+    // Scanning registers the diversified address automatically. Remove it again, as would be
+    // the case for a wallet that scanned these blocks before addresses were registered during
+    // scanning.
+    st.wallet()
+        .conn
+        .execute("DELETE FROM addresses WHERE is_auto_discovered = 1", [])
+        .unwrap();
+
+    // Only the diversified address is newly discovered; the default address is already known.
+    let discovered = st
+        .wallet_mut()
+        .discover_shielded_addresses(account.account_id(), 0..20)
+        .unwrap();
+    assert_eq!(discovered.len(), 1);
+    assert_ne!(discovered[0], default_addr);
+    assert_eq!(
+        st.wallet()
+            .get_current_address(account.account_id())
+            .unwrap(),
+        Some(discovered[0].clone())
+    );
+
+    // Discovery is idempotent.
+    assert_eq!(
+        st.wallet_mut()
+            .discover_shielded_addresses(account.account_id(), 0..20)
+            .unwrap(),
+        vec![]
+    );
+
+    // An unknown account is rejected.
+    assert_matches!(
+        st.wallet_mut()
+            .discover_shielded_addresses(AccountId(42), 0..20),
+        Err(SqliteClientError::AccountUnknown)
+    );
+}
+
+pub(crate) fn auto_discovered_addresses<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+    let (default_addr, default_idx) =
+        wallet::get_current_address(&st.wallet().conn, &st.network(), account.account_id())
+            .unwrap()
+            .unwrap();
+
+    // A note received at the default address does not register a new address.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+    assert_eq!(
+        st.wallet()
+            .get_auto_discovered_addresses(account.account_id())
+            .unwrap(),
+        vec![]
+    );
+
+    // A note received at a diversified address that the wallet has not generated causes the
+    // address to be registered.
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DiversifiedExternal(DiversifierIndex::from(5u32)),
+        value,
+    );
+    st.scan_cached_blocks(h, 1);
+    let discovered = st
+        .wallet()
+        .get_auto_discovered_addresses(account.account_id())
+        .unwrap();
+    assert_eq!(discovered.len(), 1);
+
+    // The discovered address does not change the wallet's current receiving address.
+    assert_eq!(
+        st.wallet()
+            .get_current_address(account.account_id())
+            .unwrap(),
+        Some(default_addr)
+    );
+
+    // Receiving at the same address again does not register it a second time.
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::DiversifiedExternal(DiversifierIndex::from(5u32)),
+        value,
+    );
+    st.scan_cached_blocks(h, 1);
+    assert_eq!(
+        st.wallet()
+            .get_auto_discovered_addresses(account.account_id())
+            .unwrap(),
+        discovered
+    );
+
+    // New addresses are generated following the default address, rather than the discovered one.
+    let next_addr = st
+        .wallet_mut()
+        .get_next_available_address(account.account_id(), DEFAULT_UA_REQUEST)
+        .unwrap()
+        .unwrap();
+    let mut search_from = default_idx;
+    search_from.increment().unwrap();
+    let (expected_next_addr, _) = account
+        .usk()
+        .to_unified_full_viewing_key()
+        .find_address(search_from, DEFAULT_UA_REQUEST)
+        .unwrap();
+    assert_eq!(next_addr, expected_next_addr);
+    assert_eq!(
+        st.wallet()
+            .get_current_address(account.account_id())
+            .unwrap(),
+        Some(next_addr)
+    );
+}
+
 #[allow(dead_code)]
 pub(crate) fn zip317_spend<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
@@ -1155,22 +4056,81 @@ pub(crate) fn zip317_spend<T: ShieldedPoolTester>() {
     let txid = st
         .spend(
             &input_selector,
-            account.usk(),
-            req,
-            OvkPolicy::Sender,
+            account.usk(),
+            req,
+            OvkPolicy::Sender,
+            NonZeroU32::new(1).unwrap(),
+        )
+        .unwrap()[0];
+
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+
+    // TODO: send to an account so that we can check its balance.
+    // We sent back to the same account so the amount_sent should be included
+    // in the total balance.
+    assert_eq!(
+        st.get_total_balance(account_id),
+        (total - NonNegativeAmount::const_from_u64(10000)).unwrap()
+    );
+}
+
+pub(crate) fn dust_change_added_to_fee<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Add funds to the wallet in a single note
+    let value = NonNegativeAmount::const_from_u64(50001);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 1);
+
+    // Spending the note to pay 40000 ZAT leaves exactly 1 ZAT of change after the 10000 ZAT
+    // fee, which under the default dust output policy is added to the fee.
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let input_selector = input_selector(StandardFeeRule::Zip317, None, T::SHIELDED_PROTOCOL);
+    let request = TransactionRequest::new(vec![Payment::without_memo(
+        to.to_zcash_address(&st.network()),
+        NonNegativeAmount::const_from_u64(40000),
+    )])
+    .unwrap();
+
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request,
             NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[T::SHIELDED_PROTOCOL]),
         )
-        .unwrap()[0];
+        .unwrap();
+    let step = &proposal.steps().head;
+    assert!(step.balance().proposed_change().is_empty());
+    let fee = NonNegativeAmount::const_from_u64(10001);
+    assert_eq!(step.balance().fee_required(), fee);
 
-    let (h, _) = st.generate_next_block_including(txid);
-    st.scan_cached_blocks(h, 1);
+    // The built transaction pays the proposed fee, including the dust.
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+    let tx = st.wallet().get_transaction(txid).unwrap().unwrap();
+    assert_eq!(
+        tx.fee_paid(|_| Ok::<_, SqliteClientError>(Amount::zero()))
+            .unwrap(),
+        Amount::from(fee)
+    );
 
-    // TODO: send to an account so that we can check its balance.
-    // We sent back to the same account so the amount_sent should be included
-    // in the total balance.
+    // No change note is created.
+    let (h2, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h2, 1);
     assert_eq!(
-        st.get_total_balance(account_id),
-        (total - NonNegativeAmount::const_from_u64(10000)).unwrap()
+        st.get_total_balance(account.account_id()),
+        NonNegativeAmount::ZERO
     );
 }
 
@@ -1206,6 +4166,7 @@ pub(crate) fn shield_transparent<T: ShieldedPoolTester>() {
             script_pubkey: taddr.script(),
         },
         h,
+        false,
     )
     .unwrap();
 
@@ -1222,16 +4183,318 @@ pub(crate) fn shield_transparent<T: ShieldedPoolTester>() {
         DustOutputPolicy::default(),
     );
 
-    assert_matches!(
-        st.shield_transparent_funds(
+    let change_memo = "Test shielding memo".parse::<Memo>().unwrap();
+    let txids = st.shield_transparent_funds(
+        &input_selector,
+        NonNegativeAmount::from_u64(10000).unwrap(),
+        account.usk(),
+        &[*taddr],
+        1,
+        Some(change_memo.clone().into()),
+    );
+    assert_matches!(txids, Ok(_));
+    let txid = txids.unwrap()[0];
+
+    // The shielded note received by the wallet carries the provided change memo.
+    let (memo, is_change): (Vec<u8>, bool) = st
+        .wallet()
+        .conn
+        .query_row(
+            &format!(
+                "SELECT n.memo, n.is_change
+                 FROM {}_received_notes n
+                 JOIN transactions t ON t.id_tx = n.tx
+                 WHERE t.txid = ?",
+                T::TABLES_PREFIX,
+            ),
+            rusqlite::params![txid.as_ref()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert!(is_change);
+    assert_eq!(
+        Memo::try_from(MemoBytes::from_bytes(&memo).unwrap()).unwrap(),
+        change_memo
+    );
+}
+
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn transparent_spend_recorded_before_utxo<T: ShieldedPoolTester>() {
+    use zcash_primitives::{
+        legacy::Script,
+        transaction::components::transparent::{self, TxIn},
+    };
+
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let uaddr = st
+        .wallet()
+        .get_current_address(account.account_id())
+        .unwrap()
+        .unwrap();
+    let taddr = uaddr.transparent().unwrap();
+
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::Internal,
+        NonNegativeAmount::const_from_u64(50000),
+    );
+    st.scan_cached_blocks(h, 1);
+
+    let outpoint = OutPoint::new([1u8; 32], 1);
+    let utxo = WalletTransparentOutput::from_parts(
+        outpoint.clone(),
+        TxOut {
+            value: NonNegativeAmount::const_from_u64(20000),
+            script_pubkey: taddr.script(),
+        },
+        h,
+        false,
+    )
+    .unwrap();
+    st.wallet_mut()
+        .put_received_transparent_utxo(&utxo)
+        .unwrap();
+
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(
+            StandardFeeRule::Zip317,
+            None,
+            T::SHIELDED_PROTOCOL,
+        ),
+        DustOutputPolicy::default(),
+    );
+    let txid = st
+        .shield_transparent_funds(
             &input_selector,
             NonNegativeAmount::from_u64(10000).unwrap(),
             account.usk(),
             &[*taddr],
-            1
-        ),
+            1,
+            None,
+        )
+        .unwrap()[0];
+    let tx = st.wallet().get_transaction(txid).unwrap().unwrap();
+
+    // Simulate the wallet having observed the shielding transaction before the UTXO it spends.
+    st.wallet()
+        .conn
+        .execute_batch(
+            "DELETE FROM transparent_received_output_spends;
+             DELETE FROM utxos;",
+        )
+        .unwrap();
+    assert_matches!(
+        decrypt_and_store_transaction(&st.network(), st.wallet_mut(), &tx),
+        Ok(_)
+    );
+    let pending_spends = |st: &TestState<_>| -> i64 {
+        st.wallet()
+            .conn
+            .query_row("SELECT COUNT(*) FROM transparent_spend_map", [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    };
+    assert_eq!(pending_spends(&st), 1);
+
+    // Spends of outputs that cannot belong to the wallet are not recorded.
+    let tx_ref: i64 = st
+        .wallet()
+        .conn
+        .query_row(
+            "SELECT id_tx FROM transactions WHERE txid = ?",
+            params![txid.as_ref()],
+            |row| row.get(0),
+        )
+        .unwrap();
+    let foreign_input = TxIn::<transparent::Authorized> {
+        prevout: OutPoint::new([3u8; 32], 0),
+        script_sig: Script::default() << &[0x30; 71][..] << &[2; 33][..],
+        sequence: 0,
+    };
+    wallet::mark_transparent_input_spent(&st.wallet().conn, &st.network(), tx_ref, &foreign_input)
+        .unwrap();
+    assert_eq!(pending_spends(&st), 1);
+
+    // Receiving the UTXO afterwards applies the recorded spend.
+    st.wallet_mut()
+        .put_received_transparent_utxo(&utxo)
+        .unwrap();
+    assert_matches!(
+        st.wallet().get_unspent_transparent_output(&outpoint),
+        Ok(None)
+    );
+    assert_eq!(pending_spends(&st), 0);
+
+    // Recorded spends are pruned once the spending transaction is mined and stable.
+    st.wallet()
+        .conn
+        .execute_batch(
+            "DELETE FROM transparent_received_output_spends;
+             DELETE FROM utxos;",
+        )
+        .unwrap();
+    assert_matches!(
+        decrypt_and_store_transaction(&st.network(), st.wallet_mut(), &tx),
         Ok(_)
     );
+    assert_eq!(pending_spends(&st), 1);
+    st.wallet()
+        .conn
+        .execute(
+            "UPDATE transactions SET block = ? WHERE id_tx = ?",
+            params![u32::from(h), tx_ref],
+        )
+        .unwrap();
+    wallet::prune_transparent_spend_map(&st.wallet().conn, h).unwrap();
+    assert_eq!(pending_spends(&st), 1);
+    wallet::prune_transparent_spend_map(&st.wallet().conn, h + 1).unwrap();
+    assert_eq!(pending_spends(&st), 0);
+}
+
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn shield_coinbase_transparent<T: ShieldedPoolTester>() {
+    use std::collections::BTreeMap;
+    use zcash_client_backend::{fees::ChangeValue, proposal::Proposal, wallet::COINBASE_MATURITY};
+
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    let uaddr = st
+        .wallet()
+        .get_current_address(account.account_id())
+        .unwrap()
+        .unwrap();
+    let taddr = uaddr.transparent().unwrap();
+
+    let (h, _, _) = st.generate_next_block(
+        &dfvk,
+        AddressType::Internal,
+        NonNegativeAmount::const_from_u64(50000),
+    );
+    st.scan_cached_blocks(h, 1);
+
+    // Advance the chain so that a coinbase output mined at `h` may be spent in the next block.
+    for _ in 1..COINBASE_MATURITY {
+        st.generate_empty_block();
+    }
+    st.scan_cached_blocks(h + 1, (COINBASE_MATURITY - 1) as usize);
+    let target_height = h + COINBASE_MATURITY;
+
+    let value = NonNegativeAmount::const_from_u64(100000);
+    let coinbase_utxo = |index, height| {
+        WalletTransparentOutput::from_parts(
+            OutPoint::new([1u8; 32], index),
+            TxOut {
+                value,
+                script_pubkey: taddr.script(),
+            },
+            height,
+            true,
+        )
+        .unwrap()
+    };
+    let mature = coinbase_utxo(0, h);
+    let immature = coinbase_utxo(1, target_height - 1);
+    assert!(mature.is_mature_at(target_height));
+    assert!(!immature.is_mature_at(target_height));
+    for utxo in [&mature, &immature] {
+        assert_matches!(st.wallet_mut().put_received_transparent_utxo(utxo), Ok(_));
+    }
+
+    let fee_rule = StandardFeeRule::Zip317;
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, T::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+
+    // Only the matured coinbase output is selected for shielding.
+    let proposal = st
+        .propose_shielding(
+            &input_selector,
+            NonNegativeAmount::const_from_u64(10000),
+            &[*taddr],
+            1,
+            None,
+        )
+        .unwrap();
+    assert_eq!(proposal.min_target_height(), target_height);
+    let step = &proposal.steps().head;
+    assert_eq!(step.transparent_inputs(), &[mature.clone()]);
+
+    // A proposal that spends the immature output is rejected.
+    let fee = step.balance().fee_required();
+    let shielded_value = (value - fee).unwrap();
+    assert_matches!(
+        Proposal::<_, Infallible>::single_step(
+            TransactionRequest::empty(),
+            BTreeMap::new(),
+            vec![immature],
+            None,
+            TransactionBalance::new(
+                vec![ChangeValue::new(T::SHIELDED_PROTOCOL, shielded_value, None)],
+                fee,
+            )
+            .unwrap(),
+            fee_rule,
+            target_height,
+            true,
+        ),
+        Err(ProposalError::CoinbaseImmature(txid, 1)) if txid == TxId::from_bytes([1u8; 32])
+    );
+
+    // Even once matured, a coinbase output may not be spent to a transparent recipient.
+    let request = TransactionRequest::new(vec![Payment::without_memo(
+        Address::Transparent(*taddr).to_zcash_address(&st.network()),
+        shielded_value,
+    )])
+    .unwrap();
+    assert_matches!(
+        Proposal::<_, Infallible>::single_step(
+            request,
+            [(0, PoolType::Transparent)].into_iter().collect(),
+            vec![mature],
+            None,
+            TransactionBalance::new(vec![], fee).unwrap(),
+            fee_rule,
+            target_height,
+            false,
+        ),
+        Err(ProposalError::CoinbaseSpentToTransparent)
+    );
+
+    // The matured coinbase output can be shielded.
+    let txids = st
+        .shield_transparent_funds(
+            &input_selector,
+            NonNegativeAmount::const_from_u64(10000),
+            account.usk(),
+            &[*taddr],
+            1,
+            None,
+        )
+        .unwrap();
+    let tx = st.wallet().get_transaction(txids[0]).unwrap().unwrap();
+    let spent_outpoints = tx.transparent_bundle().map(|b| {
+        b.vin
+            .iter()
+            .map(|txin| txin.prevout.clone())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(spent_outpoints, Some(vec![OutPoint::new([1u8; 32], 0)]));
+    assert!(tx.transparent_bundle().unwrap().vout.is_empty());
 }
 
 // FIXME: This requires fixes to the test framework.
@@ -1462,6 +4725,7 @@ pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTes
             &input_selector,
             p0_to_p1,
             NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[]),
         )
         .unwrap();
 
@@ -1495,12 +4759,141 @@ pub(crate) fn pool_crossing_required<P0: ShieldedPoolTester, P1: ShieldedPoolTes
     st.scan_cached_blocks(h, 1);
 
     assert_eq!(
-        st.get_total_balance(account.account_id()),
-        (initial_balance - expected_fee).unwrap()
+        st.get_total_balance(account.account_id()),
+        (initial_balance - expected_fee).unwrap()
+    );
+    assert_eq!(
+        st.get_spendable_balance(account.account_id(), 1),
+        (initial_balance - expected_fee).unwrap()
+    );
+}
+
+#[cfg(feature = "orchard")]
+pub(crate) fn get_transaction_pool_flows<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let p0_fvk = P0::test_account_fvk(&st);
+    let p1_fvk = P1::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(350000);
+    let (h, _, _) = st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    // A transfer within the P0 pool moves the sent value and the fee out of that pool.
+    let fee_rule = StandardFeeRule::Zip317;
+    let p0_input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, P0::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+    let p0_to = P0::sk_default_address(&P0::sk(&[0xf5; 32]));
+    let sent_value = NonNegativeAmount::const_from_u64(10000);
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &p0_input_selector,
+            zip321::TransactionRequest::new(vec![Payment::without_memo(
+                p0_to.to_zcash_address(&st.network()),
+                sent_value,
+            )])
+            .unwrap(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[P0::SHIELDED_PROTOCOL]),
+        )
+        .unwrap();
+    let fee = proposal.steps().head.balance().fee_required();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    let flows = st
+        .wallet()
+        .get_transaction_pool_flows(txid)
+        .unwrap()
+        .expect("Created transaction was stored.");
+    let expected_flow = -Amount::from((sent_value + fee).unwrap());
+    assert_eq!(
+        flows.for_pool(PoolType::Shielded(P0::SHIELDED_PROTOCOL)),
+        expected_flow
+    );
+    assert_eq!(
+        flows.for_pool(PoolType::Shielded(P1::SHIELDED_PROTOCOL)),
+        Amount::zero()
+    );
+    assert_eq!(flows.transparent(), Amount::zero());
+    assert_eq!(flows.fee_paid(), Some(fee));
+    assert_eq!(flows.total(), Some(expected_flow));
+
+    let (h, _) = st.generate_next_block_including(txid);
+    st.scan_cached_blocks(h, 1);
+
+    // A transfer from P0 to P1 moves value out of the P0 pool and into the P1 pool, with
+    // change going to the preferred pool.
+    let note_value = st.get_spendable_balance(account.account_id(), 1);
+    let p1_to = P1::fvk_default_address(&p1_fvk);
+    let transfer_amount = NonNegativeAmount::const_from_u64(200000);
+    let p1_input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(fee_rule, None, P1::SHIELDED_PROTOCOL),
+        DustOutputPolicy::default(),
+    );
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &p1_input_selector,
+            zip321::TransactionRequest::new(vec![Payment::without_memo(
+                p1_to.to_zcash_address(&st.network()),
+                transfer_amount,
+            )])
+            .unwrap(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[]),
+        )
+        .unwrap();
+    let fee = proposal.steps().head.balance().fee_required();
+    let expected_change = (note_value - transfer_amount - fee).unwrap();
+    let txid = *st
+        .create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap()
+        .first();
+
+    let (p0_flow, p1_flow) = if P1::SHIELDED_PROTOCOL == ShieldedProtocol::Orchard {
+        (
+            Some(-Amount::from(note_value)),
+            Amount::from(transfer_amount) + Amount::from(expected_change),
+        )
+    } else {
+        (
+            Amount::from(expected_change) - Amount::from(note_value),
+            Some(Amount::from(transfer_amount)),
+        )
+    };
+    let flows = st
+        .wallet()
+        .get_transaction_pool_flows(txid)
+        .unwrap()
+        .expect("Created transaction was stored.");
+    assert_eq!(
+        Some(flows.for_pool(PoolType::Shielded(P0::SHIELDED_PROTOCOL))),
+        p0_flow
     );
     assert_eq!(
-        st.get_spendable_balance(account.account_id(), 1),
-        (initial_balance - expected_fee).unwrap()
+        Some(flows.for_pool(PoolType::Shielded(P1::SHIELDED_PROTOCOL))),
+        p1_flow
+    );
+    assert_eq!(flows.transparent(), Amount::zero());
+    assert_eq!(flows.fee_paid(), Some(fee));
+    assert_eq!(flows.total(), Some(-Amount::from(fee)));
+
+    // No flows are reported for a transaction that the wallet does not know about.
+    assert_eq!(
+        st.wallet()
+            .get_transaction_pool_flows(TxId::from_bytes([0xff; 32]))
+            .unwrap(),
+        None
     );
 }
 
@@ -1551,6 +4944,7 @@ pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoo
             &input_selector,
             p0_to_p1,
             NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[]),
         )
         .unwrap();
 
@@ -1592,6 +4986,115 @@ pub(crate) fn fully_funded_fully_private<P0: ShieldedPoolTester, P1: ShieldedPoo
     );
 }
 
+#[cfg(feature = "orchard")]
+pub(crate) fn change_pool_preference_is_respected<
+    P0: ShieldedPoolTester,
+    P1: ShieldedPoolTester,
+>() {
+    use zcash_client_backend::{data_api::Account as _, keys::UnifiedFullViewingKey};
+
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32])) // TODO: Allow for Orchard
+        // activation after Sapling
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let p0_fvk = P0::test_account_fvk(&st);
+
+    let note_value = NonNegativeAmount::const_from_u64(350000);
+    let (h, _, _) = st.generate_next_block(&p0_fvk, AddressType::DefaultExternal, note_value);
+    st.scan_cached_blocks(h, 1);
+
+    let to_extsk = P0::sk(&[0xf5; 32]);
+    let to: Address = P0::sk_default_address(&to_extsk);
+    let transfer_amount = NonNegativeAmount::const_from_u64(200000);
+    let request = zip321::TransactionRequest::new(vec![Payment::without_memo(
+        to.to_zcash_address(&st.network()),
+        transfer_amount,
+    )])
+    .unwrap();
+
+    let input_selector = GreedyInputSelector::new(
+        standard::SingleOutputChangeStrategy::new(
+            StandardFeeRule::Zip317,
+            None,
+            P0::SHIELDED_PROTOCOL,
+        ),
+        DustOutputPolicy::default(),
+    );
+
+    // The account has receivers in both pools, so change is sent to whichever pool is
+    // preferred, regardless of the pool from which the transaction is funded.
+    for change_pool_preference in [
+        [P0::SHIELDED_PROTOCOL, P1::SHIELDED_PROTOCOL],
+        [P1::SHIELDED_PROTOCOL, P0::SHIELDED_PROTOCOL],
+    ] {
+        let proposal = st
+            .propose_transfer(
+                account.account_id(),
+                &input_selector,
+                request.clone(),
+                NonZeroU32::new(1).unwrap(),
+                &ProposalOptions::default().with_change_pool_preference(&change_pool_preference),
+            )
+            .unwrap();
+
+        assert_eq!(proposal.steps().len(), 1);
+        let balance = proposal.steps().head.balance();
+        let proposed_change = balance.proposed_change();
+        assert_eq!(proposed_change.len(), 1);
+        assert_eq!(proposed_change[0].output_pool(), change_pool_preference[0]);
+        assert_eq!(
+            Some(proposed_change[0].value()),
+            note_value - transfer_amount - balance.fee_required()
+        );
+    }
+
+    // By default, change is sent to Orchard whenever the fee for doing so can be paid.
+    let proposal = st
+        .propose_transfer(
+            account.account_id(),
+            &input_selector,
+            request.clone(),
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        proposal.steps().head.balance().proposed_change()[0].output_pool(),
+        ShieldedProtocol::Orchard
+    );
+
+    // Proposing fails if the account has no receiver in any of the preferred pools.
+    let sapling_only_ufvk = UnifiedFullViewingKey::new(
+        #[cfg(feature = "transparent-inputs")]
+        None,
+        Some(
+            UnifiedSpendingKey::from_seed(&st.network(), &[0x7b; 32], zip32::AccountId::ZERO)
+                .unwrap()
+                .sapling()
+                .to_diversifiable_full_viewing_key(),
+        ),
+        None,
+    )
+    .unwrap();
+    let sapling_only_account = st
+        .wallet_mut()
+        .import_account_ufvk(&sapling_only_ufvk, account.birthday(), true)
+        .unwrap();
+    assert_matches!(
+        st.propose_transfer(
+            sapling_only_account.id(),
+            &input_selector,
+            request,
+            NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[ShieldedProtocol::Orchard]),
+        ),
+        Err(Error::NoUsableChangePool)
+    );
+}
+
 #[cfg(all(feature = "orchard", feature = "transparent-inputs"))]
 pub(crate) fn fully_funded_send_to_t<P0: ShieldedPoolTester, P1: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
@@ -1638,6 +5141,7 @@ pub(crate) fn fully_funded_send_to_t<P0: ShieldedPoolTester, P1: ShieldedPoolTes
             &input_selector,
             p0_to_p1,
             NonZeroU32::new(1).unwrap(),
+            &ProposalOptions::default().with_change_pool_preference(&[]),
         )
         .unwrap();
 
@@ -1972,7 +5476,8 @@ pub(crate) fn invalid_chain_cache_disconnected<T: ShieldedPoolTester>() {
     assert_matches!(
         st.try_scan_cached_blocks(
             disconnect_height,
-            2
+            2,
+            None
         ),
         Err(chain::error::Error::Scan(ScanError::PrevHashMismatch { at_height }))
             if at_height == disconnect_height
@@ -2040,6 +5545,69 @@ pub(crate) fn data_db_truncation<T: ShieldedPoolTester>() {
     );
 }
 
+pub(crate) fn newly_confirmed_notes<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let dfvk = T::test_account_fvk(&st);
+    let one = NonZeroU32::new(1).unwrap();
+    let two = NonZeroU32::new(2).unwrap();
+
+    // Create two blocks, each of which sends a note to the wallet.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h1, 2);
+
+    // With a single confirmation required, both notes have become confirmed since the block
+    // prior to the first one.
+    let confirmed = st.wallet().get_newly_confirmed_notes(h1 - 1, one).unwrap();
+    assert_eq!(confirmed.len(), 2);
+    assert!(confirmed
+        .iter()
+        .all(|note_id| note_id.protocol() == T::SHIELDED_PROTOCOL));
+    let (note1, note2) = (confirmed[0], confirmed[1]);
+
+    // Nothing new has been confirmed as of the current chain tip.
+    assert_eq!(
+        st.wallet().get_newly_confirmed_notes(h2, one).unwrap(),
+        vec![]
+    );
+
+    // With two confirmations required, only the first note is confirmed.
+    assert_eq!(
+        st.wallet().get_newly_confirmed_notes(h1 - 1, two).unwrap(),
+        vec![note1]
+    );
+
+    // Once another block has been scanned, the second note crosses the threshold.
+    let (h3, _) = st.generate_empty_block();
+    st.scan_cached_blocks(h3, 1);
+    assert_eq!(
+        st.wallet().get_newly_confirmed_notes(h2, two).unwrap(),
+        vec![note2]
+    );
+
+    // Rewind so that the second block is dropped; the second note is no longer confirmed.
+    st.wallet_mut()
+        .transactionally(|wdb| truncate_to_height(wdb.conn.0, &wdb.params, h1))
+        .unwrap();
+    assert_eq!(
+        st.wallet().get_newly_confirmed_notes(h1, one).unwrap(),
+        vec![]
+    );
+
+    // Once the dropped blocks have been scanned again, the second note is reported again
+    // relative to the height to which the wallet was truncated.
+    st.scan_cached_blocks(h2, 2);
+    assert_eq!(
+        st.wallet().get_newly_confirmed_notes(h1, one).unwrap(),
+        vec![note2]
+    );
+}
+
 pub(crate) fn scan_cached_blocks_allows_blocks_out_of_order<T: ShieldedPoolTester>() {
     let mut st = TestBuilder::new()
         .with_block_cache()
@@ -2136,6 +5704,278 @@ pub(crate) fn scan_cached_blocks_finds_received_notes<T: ShieldedPoolTester>() {
         st.get_total_balance(account.account_id()),
         (value + value2).unwrap()
     );
+
+    // Both notes should be yielded when streaming the account's received notes
+    let mut streamed_values = vec![];
+    st.wallet()
+        .iter_received_notes(account.account_id(), &[T::SHIELDED_PROTOCOL], |note| {
+            streamed_values.push(note.note().value());
+            Ok::<_, SqliteClientError>(())
+        })
+        .unwrap();
+    assert_eq!(streamed_values, vec![value, value2]);
+}
+
+pub(crate) fn iter_received_notes_excludes_pending_spends<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let account_id = account.account_id();
+    let dfvk = T::test_account_fvk(&st);
+
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.scan_cached_blocks(h, 1);
+
+    let streamed_values = |st: &TestState<BlockCache>| {
+        let mut values = vec![];
+        st.wallet()
+            .iter_received_notes(account_id, &[T::SHIELDED_PROTOCOL], |note| {
+                values.push(note.note().value());
+                Ok::<_, SqliteClientError>(())
+            })
+            .unwrap();
+        values
+    };
+    assert_eq!(streamed_values(&st), vec![value]);
+
+    // Spend the note in a transaction that is not mined, but has not expired.
+    let to = T::sk_default_address(&T::sk(&[0xf5; 32]));
+    let proposal = st
+        .propose_standard_transfer::<Infallible>(
+            account_id,
+            StandardFeeRule::Zip317,
+            NonZeroU32::new(1).unwrap(),
+            &to,
+            NonNegativeAmount::const_from_u64(15000),
+            None,
+            None,
+            T::SHIELDED_PROTOCOL,
+        )
+        .unwrap();
+    st.create_proposed_transactions::<Infallible, _>(account.usk(), OvkPolicy::Sender, &proposal)
+        .unwrap();
+
+    // The note is no longer selectable, and so is not yielded as unspent either.
+    assert!(T::select_spendable_notes(&st, account_id, value, h, &[])
+        .unwrap()
+        .is_empty());
+    assert_eq!(streamed_values(&st), vec![]);
+}
+
+pub(crate) fn scan_cached_blocks_respects_to_height<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let dfvk = T::test_account_fvk(&st);
+
+    // Create three fake CompactBlocks sending value to the address
+    let value = NonNegativeAmount::const_from_u64(5);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (h2, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+
+    // Scan the cache, with a limit that would include all three blocks but capped at `h2`
+    let summary = st.try_scan_cached_blocks(h1, 10, Some(h2)).unwrap();
+    assert_eq!(summary.scanned_range(), h1..h2 + 1);
+    assert_eq!(
+        st.wallet()
+            .block_max_scanned()
+            .unwrap()
+            .map(|m| m.block_height()),
+        Some(h2)
+    );
+
+    // Only the notes received at or below the cap are reflected in the balance
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (value + value).unwrap()
+    );
+
+    // A cap below the starting height results in nothing being scanned
+    let summary = st.try_scan_cached_blocks(h2 + 1, 10, Some(h2)).unwrap();
+    assert!(summary.scanned_range().is_empty());
+    assert_eq!(
+        st.wallet()
+            .block_max_scanned()
+            .unwrap()
+            .map(|m| m.block_height()),
+        Some(h2)
+    );
+}
+
+pub(crate) fn scan_cached_blocks_resumes_after_restart<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let seed = Secret::new(st.test_seed().unwrap().expose_secret().clone());
+    let dfvk = T::test_account_fvk(&st);
+
+    // Create four fake CompactBlocks, the third of which spends the note received in the first.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, nf) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let not_our_key = T::sk_to_fvk(&T::sk(&[0xf5; 32]));
+    let to2 = T::fvk_default_address(&not_our_key);
+    let value2 = NonNegativeAmount::const_from_u64(20000);
+    st.generate_next_block_spending(&dfvk, (nf, value), to2, value2);
+    let (h4, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+
+    // Scan the first two blocks, then simulate the application being restarted.
+    let summary = st.scan_cached_blocks(h1, 2);
+    assert_eq!(summary.scanned_range(), h1..h1 + 2);
+    let end_state = summary.end_state();
+    let cached = st.latest_cached_block_below_height(h1 + 2).unwrap();
+    assert_eq!(end_state.block_height(), cached.chain_state.block_height());
+    assert_eq!(end_state.block_hash(), cached.chain_state.block_hash());
+    assert_eq!(
+        end_state.final_sapling_tree(),
+        cached.chain_state.final_sapling_tree()
+    );
+    #[cfg(feature = "orchard")]
+    assert_eq!(
+        end_state.final_orchard_tree(),
+        cached.chain_state.final_orchard_tree()
+    );
+    st.reopen_wallet();
+
+    // The scanned-to height was persisted, so scanning resumes from the next block.
+    assert_eq!(
+        st.wallet()
+            .block_max_scanned()
+            .unwrap()
+            .map(|m| m.block_height()),
+        Some(h1 + 1)
+    );
+    st.scan_cached_blocks(summary.scanned_range().end, 2);
+    let interrupted_summary = st.get_wallet_summary(1);
+    let interrupted_fully_scanned = st.wallet().block_fully_scanned().unwrap();
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        (((value + value).unwrap() - value2).unwrap() + value).unwrap()
+    );
+
+    // Scanning the same blocks in a single pass with a fresh wallet produces the same state.
+    st.reset();
+    let (account_id, _) = st
+        .wallet_mut()
+        .create_account(&seed, account.birthday())
+        .unwrap();
+    assert_eq!(account_id, account.account_id());
+    st.scan_cached_blocks(h1, 4);
+    assert_eq!(st.get_wallet_summary(1), interrupted_summary);
+    assert_eq!(
+        st.wallet().block_fully_scanned().unwrap(),
+        interrupted_fully_scanned
+    );
+    assert_eq!(
+        st.wallet()
+            .block_max_scanned()
+            .unwrap()
+            .map(|m| m.block_height()),
+        Some(h4)
+    );
+}
+
+pub(crate) fn scan_cached_blocks_with_commit_interval<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new()
+        .with_block_cache()
+        .with_account_from_sapling_activation(BlockHash([0; 32]))
+        .build();
+
+    let account = st.test_account().cloned().unwrap();
+    let seed = Secret::new(st.test_seed().unwrap().expose_secret().clone());
+    let dfvk = T::test_account_fvk(&st);
+
+    // Create seven fake CompactBlocks, the fourth of which spends the note received in the
+    // first, so that the spend is detected in a different batch than the one in which the note
+    // was received.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let (h1, _, nf) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let not_our_key = T::sk_to_fvk(&T::sk(&[0xf5; 32]));
+    let to2 = T::fvk_default_address(&not_our_key);
+    let value2 = NonNegativeAmount::const_from_u64(20000);
+    st.generate_next_block_spending(&dfvk, (nf, value), to2, value2);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    let (h7, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+
+    // Scan all of the blocks, committing every three blocks.
+    let summary = st.scan_cached_blocks_with_commit_interval(h1, 7, NonZeroUsize::new(3).unwrap());
+    assert_eq!(summary.scanned_range(), h1..h7 + 1);
+    assert_eq!(summary.end_state().block_height(), h7);
+
+    let batched_summary = st.get_wallet_summary(1);
+    let batched_fully_scanned = st.wallet().block_fully_scanned().unwrap();
+    // Five unspent received notes, plus the change from the spend of the first note.
+    assert_eq!(
+        st.get_total_balance(account.account_id()),
+        NonNegativeAmount::const_from_u64(5 * 50000 + 30000)
+    );
+
+    // Scanning the same blocks in a single batch with a fresh wallet produces the same state.
+    st.reset();
+    let (account_id, _) = st
+        .wallet_mut()
+        .create_account(&seed, account.birthday())
+        .unwrap();
+    assert_eq!(account_id, account.account_id());
+    st.scan_cached_blocks(h1, 7);
+    assert_eq!(st.get_wallet_summary(1), batched_summary);
+    assert_eq!(
+        st.wallet().block_fully_scanned().unwrap(),
+        batched_fully_scanned
+    );
+}
+
+pub(crate) fn scan_cached_blocks_for_accounts<T: ShieldedPoolTester>() {
+    let mut st = TestBuilder::new().with_block_cache().build();
+
+    // Add two accounts to the wallet.
+    let seed = Secret::new([0u8; 32].to_vec());
+    let birthday = AccountBirthday::from_sapling_activation(&st.network(), BlockHash([0; 32]));
+    let (account_id, usk) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+    let dfvk = T::sk_to_fvk(T::usk_to_sk(&usk));
+    let (account2, usk2) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+    let dfvk2 = T::sk_to_fvk(T::usk_to_sk(&usk2));
+
+    // Create a block paying the first account, and a block paying the second.
+    let value = NonNegativeAmount::const_from_u64(50000);
+    let value2 = NonNegativeAmount::const_from_u64(70000);
+    let (h1, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+    st.generate_next_block(&dfvk2, AddressType::DefaultExternal, value2);
+    st.wallet_mut().update_chain_tip(h1 + 1).unwrap();
+
+    // Scanning with only the first account's keys detects only its note; the second account's
+    // balance is unchanged.
+    let summary = st.scan_cached_blocks_for_accounts(h1, 2, &[account_id]);
+    assert_eq!(summary.scanned_range(), h1..h1 + 2);
+    assert_eq!(T::received_note_count(&summary), 1);
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_total_balance(account2), NonNegativeAmount::ZERO);
+
+    // The range was not recorded as fully scanned, so a subsequent scan with all of the
+    // wallet's keys detects the second account's note without double-counting the first.
+    assert!(st
+        .wallet()
+        .suggest_scan_ranges()
+        .unwrap()
+        .iter()
+        .any(|range| range.block_range().contains(&(h1 + 1))));
+    st.scan_cached_blocks(h1, 2);
+    assert_eq!(st.get_total_balance(account_id), value);
+    assert_eq!(st.get_total_balance(account2), value2);
 }
 
 // TODO: This test can probably be entirely removed, as the following test duplicates it entirely.
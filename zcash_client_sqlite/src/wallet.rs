@@ -64,30 +64,33 @@
 //!   wallet.
 //! - `memo` the shielded memo associated with the output, if any.
 
-use incrementalmerkletree::Retention;
+use incrementalmerkletree::{Position, Retention};
 use rusqlite::{self, named_params, params, OptionalExtension};
 use secrecy::{ExposeSecret, SecretVec};
 use shardtree::{error::ShardTreeError, store::ShardStore, ShardTree};
 use zip32::fingerprint::SeedFingerprint;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::io::{self, Cursor};
 use std::num::NonZeroU32;
-use std::ops::RangeInclusive;
+use std::ops::{Range, RangeInclusive};
 use tracing::debug;
 
 use zcash_address::ZcashAddress;
 use zcash_client_backend::{
     data_api::{
+        chain_tip::{self, ChainTipProvider},
         scanning::{ScanPriority, ScanRange},
-        AccountBalance, AccountBirthday, AccountSource, BlockMetadata, Ratio,
-        SentTransactionOutput, WalletSummary, SAPLING_SHARD_HEIGHT,
+        AccountBalance, AccountBirthday, AccountInfo, AccountSource, AuditMismatch, AuditReport,
+        BlockMetadata, NoteWitness, PoolFlows, Ratio, SentTransactionOutput, TransactionAnnotation,
+        WalletSummary, WatchOnlyBundle, WatchedAddress, SAPLING_SHARD_HEIGHT,
     },
+    decrypt_transaction,
     encoding::AddressCodec,
     keys::UnifiedFullViewingKey,
     wallet::{Note, NoteId, Recipient, WalletTx},
-    PoolType, ShieldedProtocol,
+    PoolType, ShieldedProtocol, TransferType,
 };
 use zcash_keys::{
     address::{Address, Receiver, UnifiedAddress},
@@ -102,7 +105,7 @@ use zcash_primitives::{
     memo::{Memo, MemoBytes},
     merkle_tree::read_commitment_tree,
     transaction::{
-        components::{amount::NonNegativeAmount, Amount},
+        components::{amount::NonNegativeAmount, Amount, OutPoint},
         Transaction, TransactionData, TxId,
     },
 };
@@ -111,8 +114,8 @@ use zip32::{self, DiversifierIndex, Scope};
 use crate::{
     error::SqliteClientError,
     wallet::commitment_tree::{get_max_checkpointed_height, SqliteShardStore},
-    AccountId, SqlTransaction, WalletCommitmentTrees, WalletDb, DEFAULT_UA_REQUEST, PRUNING_DEPTH,
-    SAPLING_TABLES_PREFIX,
+    AccountId, ReceivedNoteId, SqlTransaction, WalletCommitmentTrees, WalletDb, DEFAULT_UA_REQUEST,
+    PRUNING_DEPTH, SAPLING_TABLES_PREFIX,
 };
 
 use self::scanning::{parse_priority_code, priority_code, replace_queue_entries};
@@ -127,15 +130,18 @@ use {
     std::collections::BTreeSet,
     zcash_address::unified::{Encoding, Ivk, Uivk},
     zcash_client_backend::{
-        data_api::TransparentAddressSyncInfo,
+        data_api::{SpendStatus, TransparentAddressSyncInfo},
         wallet::{TransparentAddressMetadata, WalletTransparentOutput},
     },
     zcash_primitives::{
         legacy::{
-            keys::{IncomingViewingKey, NonHardenedChildIndex},
+            keys::{AccountPubKey, IncomingViewingKey, NonHardenedChildIndex},
             Script, TransparentAddress,
         },
-        transaction::components::{OutPoint, TxOut},
+        transaction::components::{
+            transparent::{self, TxIn},
+            TxOut,
+        },
     },
 };
 
@@ -304,6 +310,145 @@ pub(crate) fn seed_matches_derived_account<P: consensus::Parameters>(
     }
 }
 
+/// Derives the spending key for a derived account from the given seed, verifying that the seed
+/// matches the account's seed fingerprint and that the derived key corresponds to the account's
+/// stored viewing key.
+pub(crate) fn derive_usk<P: consensus::Parameters>(
+    params: &P,
+    seed: &SecretVec<u8>,
+    seed_fingerprint: &SeedFingerprint,
+    account_index: zip32::AccountId,
+    uivk: &UnifiedIncomingViewingKey,
+) -> Result<UnifiedSpendingKey, SqliteClientError> {
+    let fingerprint = SeedFingerprint::from_seed(seed.expose_secret()).ok_or_else(|| {
+        SqliteClientError::BadAccountData(
+            "Seed must be between 32 and 252 bytes in length.".to_owned(),
+        )
+    })?;
+    if &fingerprint != seed_fingerprint {
+        return Err(SqliteClientError::SeedMismatch);
+    }
+
+    let usk = UnifiedSpendingKey::from_seed(params, seed.expose_secret(), account_index)
+        .map_err(|_| SqliteClientError::KeyDerivationError(account_index))?;
+
+    // The seed fingerprint matched, so a key mismatch suggests database corruption.
+    let derived_uivk = usk
+        .to_unified_full_viewing_key()
+        .to_unified_incoming_viewing_key();
+    if derived_uivk.encode(params) != uivk.encode(params) {
+        return Err(SqliteClientError::CorruptedData(
+            "Viewing key derived from the account's seed does not match the stored viewing key."
+                .to_owned(),
+        ));
+    }
+
+    Ok(usk)
+}
+
+/// Returns whether each key component present in the stored viewing key is equal to the
+/// corresponding component of the viewing key derived from the account's seed.
+///
+/// Components are compared individually because the encoding of a stored key depends upon the
+/// features that were enabled when it was written, so that a key may omit components that the
+/// derived key includes.
+fn viewing_key_matches_derived(stored: &ViewingKey, derived: &UnifiedFullViewingKey) -> bool {
+    match stored.ufvk() {
+        Some(ufvk) => {
+            #[cfg(feature = "transparent-inputs")]
+            let transparent_matches = ufvk.transparent().map_or(true, |k| {
+                derived.transparent().map(|d| d.serialize()) == Some(k.serialize())
+            });
+            #[cfg(not(feature = "transparent-inputs"))]
+            let transparent_matches = true;
+
+            let sapling_matches = ufvk.sapling().map_or(true, |k| {
+                derived.sapling().map(|d| d.to_bytes()) == Some(k.to_bytes())
+            });
+
+            let orchard_matches = ufvk.orchard().map_or(true, |k| {
+                derived.orchard().map(|d| d.to_bytes()) == Some(k.to_bytes())
+            });
+
+            transparent_matches && sapling_matches && orchard_matches
+        }
+        None => {
+            let uivk = stored.uivk();
+            let derived = derived.to_unified_incoming_viewing_key();
+
+            #[cfg(feature = "transparent-inputs")]
+            let transparent_matches = uivk.transparent().as_ref().map_or(true, |k| {
+                derived.transparent().as_ref().map(|d| d.serialize()) == Some(k.serialize())
+            });
+            #[cfg(not(feature = "transparent-inputs"))]
+            let transparent_matches = true;
+
+            let sapling_matches = uivk.sapling().as_ref().map_or(true, |k| {
+                derived.sapling().as_ref().map(|d| d.to_bytes()) == Some(k.to_bytes())
+            });
+
+            let orchard_matches = uivk.orchard().as_ref().map_or(true, |k| {
+                derived.orchard().as_ref().map(|d| d.to_bytes()) == Some(k.to_bytes())
+            });
+
+            transparent_matches && sapling_matches && orchard_matches
+        }
+    }
+}
+
+/// Returns the IDs of the accounts derived from the given seed whose stored viewing key does
+/// not match the viewing key derived from the seed.
+///
+/// Only accounts whose seed fingerprint matches that of the given seed are checked; imported
+/// accounts, and accounts derived from other seeds, are skipped. If no account in the wallet
+/// was derived from the given seed, this returns [`SqliteClientError::SeedMismatch`].
+pub(crate) fn verify_derived_keys<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    seed: &SecretVec<u8>,
+) -> Result<Vec<AccountId>, SqliteClientError> {
+    let fingerprint = SeedFingerprint::from_seed(seed.expose_secret()).ok_or_else(|| {
+        SqliteClientError::BadAccountData(
+            "Seed must be between 32 and 252 bytes in length.".to_owned(),
+        )
+    })?;
+
+    let mut seed_is_relevant = false;
+    let mut mismatched = vec![];
+    for account_id in get_account_ids(conn)? {
+        let account = get_account(conn, params, account_id)?.expect("account ID exists");
+        let account_index = match account.kind {
+            AccountSource::Derived {
+                seed_fingerprint,
+                account_index,
+            } if seed_fingerprint == fingerprint => account_index,
+            _ => continue,
+        };
+        seed_is_relevant = true;
+
+        let matches =
+            match UnifiedSpendingKey::from_seed(params, seed.expose_secret(), account_index) {
+                // The account's viewing key was derived from this seed, so failing to re-derive
+                // it means that the stored account data cannot be correct.
+                Err(_) => false,
+                Ok(usk) => viewing_key_matches_derived(
+                    &account.viewing_key,
+                    &usk.to_unified_full_viewing_key(),
+                ),
+            };
+
+        if !matches {
+            mismatched.push(account_id);
+        }
+    }
+
+    if seed_is_relevant {
+        Ok(mismatched)
+    } else {
+        Err(SqliteClientError::SeedMismatch)
+    }
+}
+
 pub(crate) fn pool_code(pool_type: PoolType) -> i64 {
     // These constants are *incidentally* shared with the typecodes
     // for unified addresses, but this is exclusively an internal
@@ -365,6 +510,7 @@ pub(crate) fn add_account<P: consensus::Parameters>(
     kind: AccountSource,
     viewing_key: ViewingKey,
     birthday: &AccountBirthday,
+    checkpoint_retention: usize,
 ) -> Result<Account, SqliteClientError> {
     let (hd_seed_fingerprint, hd_account_index) = match kind {
         AccountSource::Derived {
@@ -470,7 +616,7 @@ pub(crate) fn add_account<P: consensus::Parameters>(
             _,
             { ::sapling::NOTE_COMMITMENT_TREE_DEPTH },
             SAPLING_SHARD_HEIGHT,
-        > = ShardTree::new(shard_store, PRUNING_DEPTH.try_into().unwrap());
+        > = ShardTree::new(shard_store, checkpoint_retention);
         shard_tree.insert_frontier_nodes(
             frontier.clone(),
             Retention::Checkpoint {
@@ -497,7 +643,7 @@ pub(crate) fn add_account<P: consensus::Parameters>(
             _,
             { ::orchard::NOTE_COMMITMENT_TREE_DEPTH as u8 },
             ORCHARD_SHARD_HEIGHT,
-        > = ShardTree::new(shard_store, PRUNING_DEPTH.try_into().unwrap());
+        > = ShardTree::new(shard_store, checkpoint_retention);
         shard_tree.insert_frontier_nodes(
             frontier.clone(),
             Retention::Checkpoint {
@@ -569,11 +715,13 @@ pub(crate) fn get_current_address<P: consensus::Parameters>(
     params: &P,
     account_id: AccountId,
 ) -> Result<Option<(UnifiedAddress, DiversifierIndex)>, SqliteClientError> {
-    // This returns the most recently generated address.
+    // This returns the most recently generated address. Addresses that were registered because
+    // a note was received at them are not generated by the wallet, and so are not considered.
     let addr: Option<(String, Vec<u8>)> = conn
         .query_row(
             "SELECT address, diversifier_index_be
             FROM addresses WHERE account_id = :account_id
+            AND is_auto_discovered = 0
             ORDER BY diversifier_index_be DESC
             LIMIT 1",
             named_params![":account_id": account_id.0],
@@ -603,6 +751,77 @@ pub(crate) fn get_current_address<P: consensus::Parameters>(
     .transpose()
 }
 
+/// Returns the internal (change) unified address for the given account.
+///
+/// This is the address to which change outputs are sent by
+/// [`zcash_client_backend::data_api::wallet::create_proposed_transactions`]; it contains the
+/// Sapling and (if the `orchard` feature is enabled) Orchard receivers at the default
+/// internal-scope diversifier of the account's full viewing key. Returns `Ok(None)` if the
+/// account is not known, or if the wallet only holds an incoming viewing key for the account.
+pub(crate) fn get_internal_address<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+) -> Result<Option<UnifiedAddress>, SqliteClientError> {
+    let account = get_account(conn, params, account_id)?;
+    Ok(account
+        .as_ref()
+        .and_then(|account| account.viewing_key.ufvk())
+        .and_then(|ufvk| {
+            #[cfg(feature = "orchard")]
+            let orchard = ufvk
+                .orchard()
+                .map(|fvk| fvk.address_at(0u32, Scope::Internal));
+            #[cfg(not(feature = "orchard"))]
+            let orchard = None;
+
+            UnifiedAddress::from_receivers(
+                orchard,
+                ufvk.sapling().map(|dfvk| dfvk.change_address().1),
+                None,
+            )
+        }))
+}
+
+/// Returns the highest diversifier index at which an address has been allocated for the given
+/// account in the given scope.
+///
+/// Only external-scope addresses are recorded in the `addresses` table; change is always sent to
+/// the address at the default internal-scope diversifier (see [`get_internal_address`]), so no
+/// internal-scope addresses are ever allocated and `Ok(None)` is returned for
+/// [`Scope::Internal`].
+pub(crate) fn get_max_diversifier_index(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+    scope: Scope,
+) -> Result<Option<DiversifierIndex>, SqliteClientError> {
+    if scope == Scope::Internal {
+        return Ok(None);
+    }
+
+    // Diversifier indices are stored in big-endian order so that their lexicographic
+    // ordering as blobs matches their numeric ordering.
+    let di_vec: Option<Vec<u8>> = conn.query_row(
+        "SELECT MAX(diversifier_index_be)
+        FROM addresses WHERE account_id = :account_id",
+        named_params![":account_id": account_id.0],
+        |row| row.get(0),
+    )?;
+
+    di_vec
+        .map(|di_vec| {
+            let mut di: [u8; 11] = di_vec.try_into().map_err(|_| {
+                SqliteClientError::CorruptedData(
+                    "Diversifier index is not an 11-byte value".to_owned(),
+                )
+            })?;
+            di.reverse(); // BE -> LE conversion
+
+            Ok(DiversifierIndex::from(di))
+        })
+        .transpose()
+}
+
 /// Adds the given address and diversifier index to the addresses table.
 ///
 /// Returns the database row for the newly-inserted address.
@@ -641,6 +860,348 @@ pub(crate) fn insert_address<P: consensus::Parameters>(
     Ok(())
 }
 
+/// Adds an address generated by the wallet at the given diversifier index to the addresses table.
+///
+/// If an address at that index was registered because a note was received at it (see
+/// [`put_discovered_address`]), it is replaced by the generated address.
+pub(crate) fn put_generated_address<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account: AccountId,
+    diversifier_index: DiversifierIndex,
+    address: &UnifiedAddress,
+) -> Result<(), SqliteClientError> {
+    // the diversifier index is stored in big-endian order to allow sorting
+    let mut di_be = *diversifier_index.as_bytes();
+    di_be.reverse();
+    conn.execute(
+        "DELETE FROM addresses
+        WHERE account_id = :account_id
+        AND diversifier_index_be = :diversifier_index_be
+        AND is_auto_discovered = 1",
+        named_params![
+            ":account_id": account.0,
+            ":diversifier_index_be": &di_be[..],
+        ],
+    )?;
+    insert_address(conn, params, account, diversifier_index, address)?;
+
+    Ok(())
+}
+
+/// Generates the most comprehensive unified address available for the given account at the
+/// specified diversifier index, and adds it to the addresses table.
+///
+/// The Sapling and/or transparent receivers are omitted if the diversifier index is not valid
+/// for them. A conflict with an existing row in the addresses table is not treated as an error.
+pub(crate) fn put_address_with_diversifier_index<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+    ufvk: &UnifiedFullViewingKey,
+    diversifier_index: DiversifierIndex,
+) -> Result<UnifiedAddress, SqliteClientError> {
+    let has_orchard = true;
+    let mut has_sapling = true;
+    let mut has_transparent = true;
+
+    // Get the most comprehensive UA available for the given diversifier index.
+    // We may have to drop the sapling and/or the transparent receiver if the diversifier index is invalid or out of range.
+    let addr = loop {
+        if let Some(addr) = match ufvk.address(
+            diversifier_index,
+            UnifiedAddressRequest::unsafe_new(has_orchard, has_sapling, has_transparent),
+        ) {
+            Ok(addr) => Some(addr),
+            Err(AddressGenerationError::InvalidSaplingDiversifierIndex(_)) => {
+                has_sapling = false;
+                None
+            }
+            #[cfg(feature = "transparent-inputs")]
+            Err(AddressGenerationError::InvalidTransparentChildIndex(_)) => {
+                has_transparent = false;
+                None
+            }
+            Err(_) => return Err(SqliteClientError::DiversifierIndexOutOfRange),
+        } {
+            break addr;
+        }
+    };
+
+    match insert_address(conn, params, account_id, diversifier_index, &addr) {
+        Ok(_) => Ok(addr),
+        Err(rusqlite::Error::SqliteFailure(
+            libsqlite3_sys::Error {
+                code: libsqlite3_sys::ErrorCode::ConstraintViolation,
+                ..
+            },
+            _,
+        )) => Ok(addr), // conflicts are ignorable
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns whether the wallet has received a shielded note belonging to the given account at
+/// one of the account's external shielded receivers at the specified diversifier index.
+fn has_shielded_receiver_activity(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+    ufvk: &UnifiedFullViewingKey,
+    diversifier_index: DiversifierIndex,
+) -> Result<bool, SqliteClientError> {
+    let received_at = |table_prefix: &'static str, diversifier: &[u8]| {
+        conn.query_row(
+            &format!(
+                "SELECT EXISTS (
+                    SELECT 1 FROM {table_prefix}_received_notes
+                    WHERE account_id = :account_id
+                    AND diversifier = :diversifier
+                )"
+            ),
+            named_params![
+                ":account_id": account_id.0,
+                ":diversifier": diversifier,
+            ],
+            |row| row.get::<_, bool>(0),
+        )
+    };
+
+    if let Some(addr) = ufvk
+        .sapling()
+        .and_then(|dfvk| dfvk.address(diversifier_index))
+    {
+        if received_at(SAPLING_TABLES_PREFIX, &addr.diversifier().0[..])? {
+            return Ok(true);
+        }
+    }
+
+    #[cfg(feature = "orchard")]
+    if let Some(fvk) = ufvk.orchard() {
+        let addr = fvk.address_at(
+            orchard::keys::DiversifierIndex::from(*diversifier_index.as_bytes()),
+            Scope::External,
+        );
+        if received_at(ORCHARD_TABLES_PREFIX, &addr.diversifier().as_array()[..])? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Checks each diversifier index in the given range for shielded notes received by the given
+/// account at that index, and adds the address for each such index that is not already known to
+/// the addresses table.
+///
+/// Returns the newly-added addresses, in order of increasing diversifier index.
+pub(crate) fn discover_shielded_addresses<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+    ufvk: &UnifiedFullViewingKey,
+    index_range: Range<u32>,
+) -> Result<Vec<UnifiedAddress>, SqliteClientError> {
+    let mut stmt_address_exists = conn.prepare_cached(
+        "SELECT EXISTS (
+            SELECT 1 FROM addresses
+            WHERE account_id = :account_id
+            AND diversifier_index_be = :diversifier_index_be
+        )",
+    )?;
+
+    let mut discovered = vec![];
+    for i in index_range {
+        let diversifier_index = DiversifierIndex::from(i);
+        if !has_shielded_receiver_activity(conn, account_id, ufvk, diversifier_index)? {
+            continue;
+        }
+
+        // the diversifier index is stored in big-endian order to allow sorting
+        let mut di_be = *diversifier_index.as_bytes();
+        di_be.reverse();
+        let known = stmt_address_exists.query_row(
+            named_params![
+                ":account_id": account_id.0,
+                ":diversifier_index_be": &di_be[..],
+            ],
+            |row| row.get::<_, bool>(0),
+        )?;
+
+        if !known {
+            discovered.push(put_address_with_diversifier_index(
+                conn,
+                params,
+                account_id,
+                ufvk,
+                diversifier_index,
+            )?);
+        }
+    }
+
+    Ok(discovered)
+}
+
+/// Adds the external address of the given account at the specified diversifier index to the
+/// addresses table, if no address at that index is already known to the wallet.
+///
+/// This is used when scanning decrypts a note received at a diversifier index for which the
+/// wallet never generated an address, as may happen after restoring a wallet from seed. An
+/// address added in this way is flagged as auto-discovered; see [`get_auto_discovered_addresses`].
+pub(crate) fn put_discovered_address<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+    ufvk: &UnifiedFullViewingKey,
+    diversifier_index: DiversifierIndex,
+) -> Result<(), SqliteClientError> {
+    // the diversifier index is stored in big-endian order to allow sorting
+    let mut di_be = *diversifier_index.as_bytes();
+    di_be.reverse();
+    let known = conn.query_row(
+        "SELECT EXISTS (
+            SELECT 1 FROM addresses
+            WHERE account_id = :account_id
+            AND diversifier_index_be = :diversifier_index_be
+        )",
+        named_params![
+            ":account_id": account_id.0,
+            ":diversifier_index_be": &di_be[..],
+        ],
+        |row| row.get::<_, bool>(0),
+    )?;
+
+    if !known {
+        put_address_with_diversifier_index(conn, params, account_id, ufvk, diversifier_index)?;
+        conn.execute(
+            "UPDATE addresses SET is_auto_discovered = 1
+            WHERE account_id = :account_id
+            AND diversifier_index_be = :diversifier_index_be",
+            named_params![
+                ":account_id": account_id.0,
+                ":diversifier_index_be": &di_be[..],
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the addresses of the given account that were added to the wallet because scanning
+/// detected a note received at them, in order of increasing diversifier index.
+pub(crate) fn get_auto_discovered_addresses<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+) -> Result<Vec<UnifiedAddress>, SqliteClientError> {
+    let mut stmt = conn.prepare(
+        "SELECT address FROM addresses
+        WHERE account_id = :account_id
+        AND is_auto_discovered = 1
+        ORDER BY diversifier_index_be",
+    )?;
+
+    let mut rows = stmt.query(named_params![":account_id": account_id.0])?;
+    let mut addresses = vec![];
+    while let Some(row) = rows.next()? {
+        let addr_str: String = row.get(0)?;
+        match Address::decode(params, &addr_str) {
+            Some(Address::Unified(ua)) => addresses.push(ua),
+            _ => {
+                return Err(SqliteClientError::CorruptedData(format!(
+                    "Addresses table contains {} which is not a unified address",
+                    addr_str,
+                )))
+            }
+        }
+    }
+
+    Ok(addresses)
+}
+
+/// Returns the addresses of the given account that have received funds in more than one
+/// transaction, along with the number of distinct transactions in which each received funds.
+///
+/// Shielded receipts are counted per diversifier index, by matching the diversifiers of received
+/// notes against the Sapling and Orchard receivers of each of the account's unified addresses.
+/// Transparent receipts are counted per transparent address.
+pub(crate) fn get_reused_addresses<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+) -> Result<Vec<(Address, usize)>, SqliteClientError> {
+    let mut stmt_addresses = conn.prepare(
+        "SELECT address FROM addresses
+        WHERE account_id = :account_id
+        ORDER BY diversifier_index_be",
+    )?;
+    let mut stmt_shielded_receipts = conn.prepare(
+        "SELECT COUNT(DISTINCT tx) FROM (
+            SELECT tx FROM sapling_received_notes
+            WHERE account_id = :account_id
+            AND diversifier = :sapling_diversifier
+            UNION
+            SELECT tx FROM orchard_received_notes
+            WHERE account_id = :account_id
+            AND diversifier = :orchard_diversifier
+        )",
+    )?;
+
+    let mut reused = vec![];
+    let mut rows = stmt_addresses.query(named_params![":account_id": account_id.0])?;
+    while let Some(row) = rows.next()? {
+        let addr_str: String = row.get(0)?;
+        let ua = match Address::decode(params, &addr_str) {
+            Some(Address::Unified(ua)) => ua,
+            _ => {
+                return Err(SqliteClientError::CorruptedData(format!(
+                    "Addresses table contains {} which is not a unified address",
+                    addr_str,
+                )))
+            }
+        };
+
+        // A missing receiver is matched against a NULL diversifier, which never matches.
+        let sapling_diversifier = ua.sapling().map(|addr| addr.diversifier().0.to_vec());
+        #[cfg(feature = "orchard")]
+        let orchard_diversifier = ua
+            .orchard()
+            .map(|addr| addr.diversifier().as_array().to_vec());
+        #[cfg(not(feature = "orchard"))]
+        let orchard_diversifier: Option<Vec<u8>> = None;
+
+        let receipts = stmt_shielded_receipts.query_row(
+            named_params![
+                ":account_id": account_id.0,
+                ":sapling_diversifier": sapling_diversifier,
+                ":orchard_diversifier": orchard_diversifier,
+            ],
+            |row| row.get::<_, usize>(0),
+        )?;
+        if receipts > 1 {
+            reused.push((Address::Unified(ua), receipts));
+        }
+    }
+
+    let mut stmt_transparent_receipts = conn.prepare(
+        "SELECT address, COUNT(DISTINCT prevout_txid)
+        FROM utxos
+        WHERE received_by_account_id = :account_id
+        GROUP BY address
+        HAVING COUNT(DISTINCT prevout_txid) > 1
+        ORDER BY address",
+    )?;
+    let mut rows = stmt_transparent_receipts.query(named_params![":account_id": account_id.0])?;
+    while let Some(row) = rows.next()? {
+        let addr_str: String = row.get(0)?;
+        let addr = Address::decode(params, &addr_str).ok_or_else(|| {
+            SqliteClientError::CorruptedData("Not a valid Zcash recipient address".to_owned())
+        })?;
+        reused.push((addr, row.get(1)?));
+    }
+
+    Ok(reused)
+}
+
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn get_transparent_receivers<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
@@ -714,37 +1275,115 @@ pub(crate) fn get_transparent_receivers<P: consensus::Parameters>(
     Ok(ret)
 }
 
+/// Returns the transparent account-level public key of the given account, if its viewing key
+/// includes a transparent component.
 #[cfg(feature = "transparent-inputs")]
-pub(crate) fn get_legacy_transparent_address<P: consensus::Parameters>(
-    params: &P,
+pub(crate) fn get_transparent_account_pubkey<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
+    params: &P,
     account_id: AccountId,
-) -> Result<Option<(TransparentAddress, NonHardenedChildIndex)>, SqliteClientError> {
-    use zcash_address::unified::Container;
-    use zcash_primitives::legacy::keys::ExternalIvk;
+) -> Result<Option<AccountPubKey>, SqliteClientError> {
+    let account =
+        get_account(conn, params, account_id)?.ok_or(SqliteClientError::AccountUnknown)?;
+    Ok(account
+        .viewing_key
+        .ufvk()
+        .and_then(|ufvk| ufvk.transparent())
+        .cloned())
+}
 
-    // Get the UIVK for the account.
-    let uivk_str: Option<String> = conn
+/// Returns the account, and the address index within that account, of the transparent
+/// receiver that the given script pays to.
+///
+/// The receiver is looked up directly in the cached transparent receivers of the `addresses`
+/// table; only if that fails are the accounts' legacy transparent addresses checked.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn find_account_for_transparent_output<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    script: &Script,
+) -> Result<Option<(AccountId, NonHardenedChildIndex)>, SqliteClientError> {
+    let taddr = match script.address() {
+        Some(taddr) => taddr,
+        None => return Ok(None),
+    };
+
+    let found = conn
         .query_row(
-            "SELECT uivk FROM accounts WHERE id = :account",
-            [account_id.0],
-            |row| row.get(0),
+            "SELECT account_id, diversifier_index_be
+             FROM addresses
+             WHERE cached_transparent_receiver_address = :address",
+            named_params![":address": &taddr.encode(params)],
+            |row| Ok((AccountId(row.get(0)?), row.get::<_, Vec<u8>>(1)?)),
         )
         .optional()?;
 
-    if let Some(uivk_str) = uivk_str {
-        let (network, uivk) = Uivk::decode(&uivk_str)
-            .map_err(|e| SqliteClientError::CorruptedData(format!("Unable to parse UIVK: {e}")))?;
-        if params.network_type() != network {
-            return Err(SqliteClientError::CorruptedData(
-                "Network type mismatch".to_owned(),
-            ));
-        }
+    if let Some((account_id, di_vec)) = found {
+        let mut di: [u8; 11] = di_vec.try_into().map_err(|_| {
+            SqliteClientError::CorruptedData("Diversifier index is not an 11-byte value".to_owned())
+        })?;
+        di.reverse(); // BE -> LE conversion
 
-        // Derive the default transparent address (if it wasn't already part of a derived UA).
-        for item in uivk.items() {
-            if let Ivk::P2pkh(tivk_bytes) = item {
-                let tivk = ExternalIvk::deserialize(&tivk_bytes)?;
+        let index = NonHardenedChildIndex::from_index(
+            DiversifierIndex::from(di).try_into().map_err(|_| {
+                SqliteClientError::CorruptedData(
+                    "Unable to get diversifier for transparent address.".to_string(),
+                )
+            })?,
+        )
+        .ok_or_else(|| {
+            SqliteClientError::CorruptedData(
+                "Unexpected hardened index for transparent address.".to_string(),
+            )
+        })?;
+
+        return Ok(Some((account_id, index)));
+    }
+
+    for account_id in get_account_ids(conn)? {
+        if let Some((legacy_taddr, index)) =
+            get_legacy_transparent_address(params, conn, account_id)?
+        {
+            if legacy_taddr == taddr {
+                return Ok(Some((account_id, index)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn get_legacy_transparent_address<P: consensus::Parameters>(
+    params: &P,
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+) -> Result<Option<(TransparentAddress, NonHardenedChildIndex)>, SqliteClientError> {
+    use zcash_address::unified::Container;
+    use zcash_primitives::legacy::keys::ExternalIvk;
+
+    // Get the UIVK for the account.
+    let uivk_str: Option<String> = conn
+        .query_row(
+            "SELECT uivk FROM accounts WHERE id = :account",
+            [account_id.0],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if let Some(uivk_str) = uivk_str {
+        let (network, uivk) = Uivk::decode(&uivk_str)
+            .map_err(|e| SqliteClientError::CorruptedData(format!("Unable to parse UIVK: {e}")))?;
+        if params.network_type() != network {
+            return Err(SqliteClientError::CorruptedData(
+                "Network type mismatch".to_owned(),
+            ));
+        }
+
+        // Derive the default transparent address (if it wasn't already part of a derived UA).
+        for item in uivk.items() {
+            if let Ivk::P2pkh(tivk_bytes) = item {
+                let tivk = ExternalIvk::deserialize(&tivk_bytes)?;
                 return Ok(Some(tivk.default_address()));
             }
         }
@@ -1111,7 +1750,7 @@ impl ScanProgress for SubtreeScanProgress {
 /// `min_confirmations == 1` for shielded notes. This behaviour may change in the future.
 #[tracing::instrument(skip(tx, params, progress))]
 pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
-    tx: &rusqlite::Transaction,
+    tx: &rusqlite::Connection,
     params: &P,
     min_confirmations: u32,
     progress: &impl ScanProgress,
@@ -1128,7 +1767,6 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
 
     let fully_scanned_height =
         block_fully_scanned(tx, params)?.map_or(birthday_height - 1, |m| m.block_height());
-    let summary_height = (chain_tip_height + 1).saturating_sub(std::cmp::max(min_confirmations, 1));
 
     let sapling_scan_progress = progress.sapling_scan_progress(
         tx,
@@ -1159,6 +1797,106 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
         .or(sapling_scan_progress)
         .or(orchard_scan_progress);
 
+    let account_balances = account_balances(tx, chain_tip_height, min_confirmations)?;
+
+    // The approach used here for Sapling and Orchard subtree indexing was a quick hack
+    // that has not yet been replaced. TODO: Make less hacky.
+    // https://github.com/zcash/librustzcash/issues/1249
+    let next_sapling_subtree_index = {
+        let shard_store =
+            SqliteShardStore::<_, ::sapling::Node, SAPLING_SHARD_HEIGHT>::from_connection(
+                tx,
+                SAPLING_TABLES_PREFIX,
+            )?;
+
+        // The last shard will be incomplete, and we want the next range to overlap with
+        // the last complete shard, so return the index of the second-to-last shard root.
+        shard_store
+            .get_shard_roots()
+            .map_err(ShardTreeError::Storage)?
+            .iter()
+            .rev()
+            .nth(1)
+            .map(|addr| addr.index())
+            .unwrap_or(0)
+    };
+
+    #[cfg(feature = "orchard")]
+    let next_orchard_subtree_index = {
+        let shard_store = SqliteShardStore::<
+            _,
+            ::orchard::tree::MerkleHashOrchard,
+            ORCHARD_SHARD_HEIGHT,
+        >::from_connection(tx, ORCHARD_TABLES_PREFIX)?;
+
+        // The last shard will be incomplete, and we want the next range to overlap with
+        // the last complete shard, so return the index of the second-to-last shard root.
+        shard_store
+            .get_shard_roots()
+            .map_err(ShardTreeError::Storage)?
+            .iter()
+            .rev()
+            .nth(1)
+            .map(|addr| addr.index())
+            .unwrap_or(0)
+    };
+
+    let summary = WalletSummary::new(
+        account_balances,
+        chain_tip_height,
+        fully_scanned_height,
+        scan_progress,
+        next_sapling_subtree_index,
+        #[cfg(feature = "orchard")]
+        next_orchard_subtree_index,
+    );
+
+    Ok(Some(summary))
+}
+
+/// Returns the balances of all of the accounts in the wallet, computed as of the current chain
+/// tip with the given number of confirmations.
+///
+/// Returns an empty map if the chain tip is not yet known. See [`get_wallet_summary`] for how
+/// `min_confirmations` is interpreted.
+#[tracing::instrument(skip(tx))]
+pub(crate) fn get_account_balances(
+    tx: &rusqlite::Connection,
+    min_confirmations: u32,
+) -> Result<HashMap<AccountId, AccountBalance>, SqliteClientError> {
+    match scan_queue_extrema(tx)? {
+        Some(range) => account_balances(tx, *range.end(), min_confirmations),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// Returns the value of the given account's shielded notes that would be spendable if the chain
+/// tip were at `at_height`, assuming no further wallet activity.
+///
+/// This evaluates the same spendability rules as [`get_account_balances`], treating `at_height`
+/// as the chain tip.
+#[tracing::instrument(skip(tx))]
+pub(crate) fn projected_spendable_balance(
+    tx: &rusqlite::Connection,
+    account: AccountId,
+    at_height: BlockHeight,
+    min_confirmations: NonZeroU32,
+) -> Result<NonNegativeAmount, SqliteClientError> {
+    account_balances(tx, at_height, min_confirmations.get())?
+        .get(&account)
+        .map(|balance| balance.spendable_value())
+        .ok_or(SqliteClientError::AccountUnknown)
+}
+
+/// Computes the balances of all of the accounts in the wallet, using a single query over each
+/// of the received notes tables.
+fn account_balances(
+    tx: &rusqlite::Connection,
+    chain_tip_height: BlockHeight,
+    min_confirmations: u32,
+) -> Result<HashMap<AccountId, AccountBalance>, SqliteClientError> {
+    let summary_height = (chain_tip_height + 1).saturating_sub(std::cmp::max(min_confirmations, 1));
+
     let mut stmt_accounts = tx.prepare_cached("SELECT id FROM accounts")?;
     let mut account_balances = stmt_accounts
         .query([])?
@@ -1168,7 +1906,7 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
         .collect::<Result<HashMap<AccountId, AccountBalance>, _>>()?;
 
     fn count_notes<F>(
-        tx: &rusqlite::Transaction,
+        tx: &rusqlite::Connection,
         summary_height: BlockHeight,
         account_balances: &mut HashMap<AccountId, AccountBalance>,
         table_prefix: &'static str,
@@ -1180,6 +1918,7 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
             NonNegativeAmount,
             NonNegativeAmount,
             NonNegativeAmount,
+            NonNegativeAmount,
         ) -> Result<(), SqliteClientError>,
     {
         // If the shard containing the summary height contains any unscanned ranges that start below or
@@ -1208,7 +1947,8 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
 
         let any_spendable = is_any_spendable(tx, summary_height, table_prefix)?;
         let mut stmt_select_notes = tx.prepare_cached(&format!(
-            "SELECT n.account_id, n.value, n.is_change, scan_state.max_priority, t.block
+            "SELECT n.account_id, n.value, n.is_change, scan_state.max_priority, t.block,
+                    n.is_locked
              FROM {table_prefix}_received_notes n
              JOIN transactions t ON t.id_tx = n.tx
              LEFT OUTER JOIN v_{table_prefix}_shards_scan_state scan_state
@@ -1270,14 +2010,25 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
             let is_pending_change =
                 is_change && received_height.iter().all(|h| h > &summary_height);
 
-            let (spendable_value, change_pending_confirmation, value_pending_spendability) = {
+            // Locked notes are not selected as inputs, so their value is reported separately
+            // from the spendable value.
+            let is_locked = row.get::<_, bool>(5)?;
+
+            let (
+                spendable_value,
+                change_pending_confirmation,
+                value_pending_spendability,
+                locked_value,
+            ) = {
                 let zero = NonNegativeAmount::ZERO;
-                if is_spendable {
-                    (value, zero, zero)
+                if is_spendable && is_locked {
+                    (zero, zero, zero, value)
+                } else if is_spendable {
+                    (value, zero, zero, zero)
                 } else if is_pending_change {
-                    (zero, value, zero)
+                    (zero, value, zero, zero)
                 } else {
-                    (zero, zero, value)
+                    (zero, zero, value, zero)
                 }
             };
 
@@ -1287,6 +2038,7 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
                     spendable_value,
                     change_pending_confirmation,
                     value_pending_spendability,
+                    locked_value,
                 )?;
             }
         }
@@ -1301,11 +2053,16 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
             summary_height,
             &mut account_balances,
             ORCHARD_TABLES_PREFIX,
-            |balances, spendable_value, change_pending_confirmation, value_pending_spendability| {
+            |balances,
+             spendable_value,
+             change_pending_confirmation,
+             value_pending_spendability,
+             locked_value| {
                 balances.with_orchard_balance_mut::<_, SqliteClientError>(|bal| {
                     bal.add_spendable_value(spendable_value)?;
                     bal.add_pending_change_value(change_pending_confirmation)?;
                     bal.add_pending_spendable_value(value_pending_spendability)?;
+                    bal.add_locked_value(locked_value)?;
                     Ok(())
                 })
             },
@@ -1319,11 +2076,16 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
         summary_height,
         &mut account_balances,
         SAPLING_TABLES_PREFIX,
-        |balances, spendable_value, change_pending_confirmation, value_pending_spendability| {
+        |balances,
+         spendable_value,
+         change_pending_confirmation,
+         value_pending_spendability,
+         locked_value| {
             balances.with_sapling_balance_mut::<_, SqliteClientError>(|bal| {
                 bal.add_spendable_value(spendable_value)?;
                 bal.add_pending_change_value(change_pending_confirmation)?;
                 bal.add_pending_spendable_value(value_pending_spendability)?;
+                bal.add_locked_value(locked_value)?;
                 Ok(())
             })
         },
@@ -1371,59 +2133,7 @@ pub(crate) fn get_wallet_summary<P: consensus::Parameters>(
         drop(transparent_trace);
     }
 
-    // The approach used here for Sapling and Orchard subtree indexing was a quick hack
-    // that has not yet been replaced. TODO: Make less hacky.
-    // https://github.com/zcash/librustzcash/issues/1249
-    let next_sapling_subtree_index = {
-        let shard_store =
-            SqliteShardStore::<_, ::sapling::Node, SAPLING_SHARD_HEIGHT>::from_connection(
-                tx,
-                SAPLING_TABLES_PREFIX,
-            )?;
-
-        // The last shard will be incomplete, and we want the next range to overlap with
-        // the last complete shard, so return the index of the second-to-last shard root.
-        shard_store
-            .get_shard_roots()
-            .map_err(ShardTreeError::Storage)?
-            .iter()
-            .rev()
-            .nth(1)
-            .map(|addr| addr.index())
-            .unwrap_or(0)
-    };
-
-    #[cfg(feature = "orchard")]
-    let next_orchard_subtree_index = {
-        let shard_store = SqliteShardStore::<
-            _,
-            ::orchard::tree::MerkleHashOrchard,
-            ORCHARD_SHARD_HEIGHT,
-        >::from_connection(tx, ORCHARD_TABLES_PREFIX)?;
-
-        // The last shard will be incomplete, and we want the next range to overlap with
-        // the last complete shard, so return the index of the second-to-last shard root.
-        shard_store
-            .get_shard_roots()
-            .map_err(ShardTreeError::Storage)?
-            .iter()
-            .rev()
-            .nth(1)
-            .map(|addr| addr.index())
-            .unwrap_or(0)
-    };
-
-    let summary = WalletSummary::new(
-        account_balances,
-        chain_tip_height,
-        fully_scanned_height,
-        scan_progress,
-        next_sapling_subtree_index,
-        #[cfg(feature = "orchard")]
-        next_orchard_subtree_index,
-    );
-
-    Ok(Some(summary))
+    Ok(account_balances)
 }
 
 /// Returns the memo for a received note, if the note is known to the wallet.
@@ -1542,64 +2252,1328 @@ pub(crate) fn get_transaction<P: Parameters>(
     .transpose()
 }
 
-pub(crate) fn get_funding_accounts(
+/// Re-decrypts the transaction with the given ID using the wallet's unified full viewing keys,
+/// and compares the notes recovered to the wallet's records of the notes received in the
+/// transaction.
+pub(crate) fn audit_transaction<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
-    tx: &Transaction,
-) -> Result<HashSet<AccountId>, rusqlite::Error> {
-    let mut funding_accounts = HashSet::new();
-    #[cfg(feature = "transparent-inputs")]
-    funding_accounts.extend(transparent::detect_spending_accounts(
-        conn,
-        tx.transparent_bundle()
-            .iter()
-            .flat_map(|bundle| bundle.vin.iter().map(|txin| &txin.prevout)),
-    )?);
-
-    funding_accounts.extend(sapling::detect_spending_accounts(
-        conn,
-        tx.sapling_bundle().iter().flat_map(|bundle| {
-            bundle
-                .shielded_spends()
-                .iter()
-                .map(|spend| spend.nullifier())
-        }),
-    )?);
+    params: &P,
+    txid: TxId,
+) -> Result<AuditReport<AccountId>, SqliteClientError> {
+    let (height, tx) =
+        get_transaction(conn, params, txid)?.ok_or(SqliteClientError::TransactionNotFound(txid))?;
+    let ufvks = get_unified_full_viewing_keys(conn, params)?;
+    let d_tx = decrypt_transaction(params, height, &tx, &ufvks);
+
+    // Outputs recovered with an outgoing viewing key are not notes received by the wallet.
+    let mut recovered = BTreeMap::new();
+    for output in d_tx.sapling_outputs() {
+        if output.transfer_type() != TransferType::Outgoing {
+            recovered.insert(
+                NoteId::new(txid, ShieldedProtocol::Sapling, output.index() as u16),
+                (
+                    *output.account(),
+                    output.note_value(),
+                    output.memo().clone(),
+                ),
+            );
+        }
+    }
+    #[cfg(feature = "orchard")]
+    for output in d_tx.orchard_outputs() {
+        if output.transfer_type() != TransferType::Outgoing {
+            recovered.insert(
+                NoteId::new(txid, ShieldedProtocol::Orchard, output.index() as u16),
+                (
+                    *output.account(),
+                    output.note_value(),
+                    output.memo().clone(),
+                ),
+            );
+        }
+    }
 
+    let mut stored = BTreeMap::new();
+    let mut fetch_stored = |protocol: ShieldedProtocol,
+                            table_prefix: &'static str,
+                            output_col: &'static str|
+     -> Result<(), SqliteClientError> {
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT rn.{output_col}, rn.account_id, rn.value, rn.memo
+            FROM {table_prefix}_received_notes rn
+            JOIN transactions t ON t.id_tx = rn.tx
+            WHERE t.txid = :txid"
+        ))?;
+        let mut rows = stmt.query(named_params![":txid": txid.as_ref()])?;
+        while let Some(row) = rows.next()? {
+            let value = NonNegativeAmount::from_nonnegative_i64(row.get(2)?)?;
+            let memo = row
+                .get::<_, Option<Vec<u8>>>(3)?
+                .map(|b| MemoBytes::from_bytes(&b))
+                .transpose()?;
+            stored.insert(
+                NoteId::new(txid, protocol, row.get(0)?),
+                (AccountId(row.get(1)?), value, memo),
+            );
+        }
+        Ok(())
+    };
+    fetch_stored(
+        ShieldedProtocol::Sapling,
+        SAPLING_TABLES_PREFIX,
+        "output_index",
+    )?;
     #[cfg(feature = "orchard")]
-    funding_accounts.extend(orchard::detect_spending_accounts(
-        conn,
-        tx.orchard_bundle()
-            .iter()
-            .flat_map(|bundle| bundle.actions().iter().map(|action| action.nullifier())),
-    )?);
+    fetch_stored(
+        ShieldedProtocol::Orchard,
+        ORCHARD_TABLES_PREFIX,
+        "action_index",
+    )?;
 
-    Ok(funding_accounts)
+    let mut mismatches = vec![];
+    for (note_id, (account, value, memo)) in &recovered {
+        match stored.get(note_id) {
+            None => mismatches.push(AuditMismatch::MissingNote(*note_id)),
+            Some((stored_account, stored_value, stored_memo)) => {
+                if stored_account != account {
+                    mismatches.push(AuditMismatch::Account {
+                        note_id: *note_id,
+                        stored: *stored_account,
+                        recovered: *account,
+                    });
+                }
+                if stored_value != value {
+                    mismatches.push(AuditMismatch::Value {
+                        note_id: *note_id,
+                        stored: *stored_value,
+                        recovered: *value,
+                    });
+                }
+                match stored_memo {
+                    Some(stored_memo) if stored_memo != memo => {
+                        mismatches.push(AuditMismatch::Memo {
+                            note_id: *note_id,
+                            stored: stored_memo.clone(),
+                            recovered: memo.clone(),
+                        })
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+    for note_id in stored.keys() {
+        if !recovered.contains_key(note_id) {
+            mismatches.push(AuditMismatch::UnexpectedNote(*note_id));
+        }
+    }
+
+    Ok(AuditReport::from_parts(txid, recovered.len(), mismatches))
 }
 
-/// Returns the memo for a sent note, if the sent note is known to the wallet.
-pub(crate) fn get_sent_memo(
+/// Returns the fee paid by the given transaction, if it can be determined.
+///
+/// If the fee was recorded when the transaction was stored, that value is returned. Otherwise
+/// the fee is computed from the transaction's value balances. The value balances of the
+/// shielded pools are public, so this only requires that the wallet know the value of each
+/// transparent output spent by the transaction; if it does not, this returns `Ok(None)`.
+/// Returns the raw serialized bytes of the transaction with the given ID, if the transaction is
+/// known to the wallet and its full data has been stored.
+pub(crate) fn get_transaction_bytes(
     conn: &rusqlite::Connection,
-    note_id: NoteId,
-) -> Result<Option<Memo>, SqliteClientError> {
-    let memo_bytes: Option<Vec<_>> = conn
+    txid: TxId,
+) -> Result<Option<Vec<u8>>, SqliteClientError> {
+    Ok(conn
         .query_row(
-            "SELECT memo FROM sent_notes
-            JOIN transactions ON sent_notes.tx = transactions.id_tx
-            WHERE transactions.txid = :txid
-            AND sent_notes.output_pool = :pool_code
-            AND sent_notes.output_index = :output_index",
-            named_params![
-                ":txid": note_id.txid().as_ref(),
-                ":pool_code": pool_code(PoolType::Shielded(note_id.protocol())),
-                ":output_index": note_id.output_index()
-            ],
-            |row| row.get(0),
+            "SELECT raw FROM transactions
+            WHERE txid = :txid",
+            named_params![":txid": txid.as_ref()],
+            |row| row.get::<_, Option<Vec<u8>>>(0),
         )
         .optional()?
-        .flatten();
+        .flatten())
+}
 
-    memo_bytes
-        .map(|b| {
+pub(crate) fn get_transaction_fee<P: Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    txid: TxId,
+) -> Result<Option<NonNegativeAmount>, SqliteClientError> {
+    let stored = conn
+        .query_row(
+            "SELECT fee, raw IS NOT NULL FROM transactions
+            WHERE txid = :txid",
+            named_params![":txid": txid.as_ref()],
+            |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, bool>(1)?)),
+        )
+        .optional()?;
+
+    match stored {
+        Some((Some(fee), _)) => {
+            return NonNegativeAmount::from_nonnegative_i64(fee)
+                .map(Some)
+                .map_err(|_| {
+                    SqliteClientError::CorruptedData(format!("Negative transaction fee {:?}", fee))
+                });
+        }
+        Some((None, true)) => (),
+        // The transaction is unknown, or its raw data is not available.
+        _ => return Ok(None),
+    }
+
+    let tx = match get_transaction(conn, params, txid)? {
+        Some((_, tx)) => tx,
+        None => return Ok(None),
+    };
+
+    let mut prevout_values = BTreeMap::new();
+    for txin in tx.transparent_bundle().iter().flat_map(|b| b.vin.iter()) {
+        match get_prevout_value(conn, params, &txin.prevout)? {
+            Some(value) => {
+                prevout_values.insert(txin.prevout.clone(), value);
+            }
+            None => return Ok(None),
+        }
+    }
+
+    let fee =
+        tx.fee_paid(|outpoint| Ok::<_, SqliteClientError>(Amount::from(prevout_values[outpoint])))?;
+
+    NonNegativeAmount::try_from(fee).map(Some).map_err(|_| {
+        SqliteClientError::CorruptedData(format!("Negative transaction fee {:?}", fee))
+    })
+}
+
+/// Returns the fees paid by the transactions mined within the given range of block heights that
+/// spent any of the wallet's notes or transparent outputs, in the order in which they were mined.
+///
+/// If the fee was recorded when the transaction was stored, that value is returned. Otherwise the
+/// fee is computed as the total value of the wallet's notes and transparent outputs spent by the
+/// transaction, less the total value of the transaction's outputs recorded in `sent_notes`.
+/// Transactions for which neither is available are omitted.
+pub(crate) fn get_fees_paid(
+    conn: &rusqlite::Connection,
+    range: Range<BlockHeight>,
+) -> Result<Vec<(TxId, NonNegativeAmount)>, SqliteClientError> {
+    let mut stmt_fees = conn.prepare_cached(
+        "WITH spent AS (
+            SELECT transaction_id, SUM(value) AS total
+            FROM (
+                SELECT s.transaction_id, n.value
+                FROM sapling_received_note_spends s
+                JOIN sapling_received_notes n ON n.id = s.sapling_received_note_id
+                UNION ALL
+                SELECT s.transaction_id, n.value
+                FROM orchard_received_note_spends s
+                JOIN orchard_received_notes n ON n.id = s.orchard_received_note_id
+                UNION ALL
+                SELECT s.transaction_id, u.value_zat
+                FROM transparent_received_output_spends s
+                JOIN utxos u ON u.id = s.transparent_received_output_id
+            )
+            GROUP BY transaction_id
+        ),
+        sent AS (
+            SELECT tx, SUM(value) AS total
+            FROM sent_notes
+            GROUP BY tx
+        )
+        SELECT t.txid, COALESCE(t.fee, spent.total - sent.total) AS fee
+        FROM transactions t
+        JOIN spent ON spent.transaction_id = t.id_tx
+        LEFT OUTER JOIN sent ON sent.tx = t.id_tx
+        WHERE t.block >= :start_height
+        AND t.block < :end_height
+        AND (t.fee IS NOT NULL OR sent.total IS NOT NULL)
+        ORDER BY t.block, t.tx_index",
+    )?;
+
+    let fees = stmt_fees
+        .query_and_then(
+            named_params![
+                ":start_height": u32::from(range.start),
+                ":end_height": u32::from(range.end),
+            ],
+            |row| -> Result<_, SqliteClientError> {
+                let txid = TxId::from_bytes(row.get(0)?);
+                let fee_raw: i64 = row.get(1)?;
+                let fee = NonNegativeAmount::from_nonnegative_i64(fee_raw).map_err(|_| {
+                    SqliteClientError::CorruptedData(format!(
+                        "Negative fee {} computed for transaction {}",
+                        fee_raw, txid
+                    ))
+                })?;
+                Ok((txid, fee))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(fees)
+}
+
+pub(crate) fn get_transaction_pool_flows<P: Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    txid: TxId,
+) -> Result<Option<PoolFlows>, SqliteClientError> {
+    let id_tx = match conn
+        .query_row(
+            "SELECT id_tx FROM transactions WHERE txid = :txid",
+            named_params![":txid": txid.as_ref()],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?
+    {
+        Some(id_tx) => id_tx,
+        None => return Ok(None),
+    };
+
+    // Computes the value received by the wallet in a pool minus the value of the wallet's
+    // outputs in that pool spent by the transaction, given queries for each of these sums.
+    let sum = |query: &str| {
+        conn.query_row(query, named_params![":id_tx": id_tx], |row| {
+            row.get::<_, i64>(0)
+        })
+    };
+    let pool_flow = |received: &str, spent: &str| -> Result<Amount, SqliteClientError> {
+        let flow = sum(received)? - sum(spent)?;
+        Amount::from_i64(flow).map_err(|_| {
+            SqliteClientError::CorruptedData(format!("Pool value flow {} is out of range", flow))
+        })
+    };
+
+    let shielded_flow = |table_prefix: &'static str| {
+        pool_flow(
+            &format!(
+                "SELECT COALESCE(SUM(value), 0)
+                FROM {table_prefix}_received_notes
+                WHERE tx = :id_tx"
+            ),
+            &format!(
+                "SELECT COALESCE(SUM(rn.value), 0)
+                FROM {table_prefix}_received_notes rn
+                JOIN {table_prefix}_received_note_spends rns
+                    ON rns.{table_prefix}_received_note_id = rn.id
+                WHERE rns.transaction_id = :id_tx"
+            ),
+        )
+    };
+
+    let transparent = pool_flow(
+        "SELECT COALESCE(SUM(u.value_zat), 0)
+        FROM utxos u
+        JOIN transactions t ON t.txid = u.prevout_txid
+        WHERE t.id_tx = :id_tx",
+        "SELECT COALESCE(SUM(u.value_zat), 0)
+        FROM utxos u
+        JOIN transparent_received_output_spends txo_spends
+            ON txo_spends.transparent_received_output_id = u.id
+        WHERE txo_spends.transaction_id = :id_tx",
+    )?;
+    let sapling = shielded_flow(SAPLING_TABLES_PREFIX)?;
+    #[cfg(feature = "orchard")]
+    let orchard = shielded_flow(ORCHARD_TABLES_PREFIX)?;
+    #[cfg(not(feature = "orchard"))]
+    let orchard = Amount::zero();
+
+    Ok(Some(PoolFlows::from_parts(
+        transparent,
+        sapling,
+        orchard,
+        get_transaction_fee(conn, params, txid)?,
+    )))
+}
+
+/// Returns the value of the given transparent output, if it is known to the wallet either as a
+/// received UTXO or as an output of a transaction for which the wallet has the raw data.
+fn get_prevout_value<P: Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    outpoint: &OutPoint,
+) -> Result<Option<NonNegativeAmount>, SqliteClientError> {
+    let utxo_value = conn
+        .query_row(
+            "SELECT value_zat FROM utxos
+            WHERE prevout_txid = :prevout_txid
+            AND prevout_idx = :prevout_idx",
+            named_params![
+                ":prevout_txid": outpoint.hash(),
+                ":prevout_idx": outpoint.n(),
+            ],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+
+    if let Some(raw_value) = utxo_value {
+        return NonNegativeAmount::from_nonnegative_i64(raw_value)
+            .map(Some)
+            .map_err(|_| {
+                SqliteClientError::CorruptedData(format!("Negative UTXO value {:?}", raw_value))
+            });
+    }
+
+    let has_raw = conn
+        .query_row(
+            "SELECT raw IS NOT NULL FROM transactions WHERE txid = :txid",
+            named_params![":txid": outpoint.hash()],
+            |row| row.get::<_, bool>(0),
+        )
+        .optional()?
+        .unwrap_or(false);
+
+    if !has_raw {
+        return Ok(None);
+    }
+
+    Ok(
+        get_transaction(conn, params, TxId::from_bytes(*outpoint.hash()))?.and_then(|(_, tx)| {
+            tx.transparent_bundle()
+                .and_then(|b| b.vout.get(outpoint.n() as usize))
+                .map(|txout| txout.value)
+        }),
+    )
+}
+
+/// Returns the identifiers of the wallet's notes that were spent by the given transaction.
+pub(crate) fn get_spent_notes(
+    conn: &rusqlite::Connection,
+    txid: TxId,
+) -> Result<Vec<NoteId>, SqliteClientError> {
+    let fetch_spent_notes =
+        |table_prefix: &'static str, output_col: &'static str, protocol: ShieldedProtocol| {
+            let mut stmt_spent_notes = conn.prepare_cached(&format!(
+                "SELECT received_tx.txid, rn.{output_col}
+            FROM {table_prefix}_received_note_spends rns
+            JOIN {table_prefix}_received_notes rn ON rn.id = rns.{table_prefix}_received_note_id
+            JOIN transactions received_tx ON received_tx.id_tx = rn.tx
+            JOIN transactions spending_tx ON spending_tx.id_tx = rns.transaction_id
+            WHERE spending_tx.txid = :txid
+            ORDER BY received_tx.id_tx, rn.{output_col}"
+            ))?;
+
+            let rows = stmt_spent_notes.query_and_then(
+                named_params![":txid": txid.as_ref()],
+                |row| -> Result<_, SqliteClientError> {
+                    Ok(NoteId::new(
+                        TxId::from_bytes(row.get(0)?),
+                        protocol,
+                        row.get(1)?,
+                    ))
+                },
+            )?;
+            rows.collect::<Result<Vec<_>, _>>()
+        };
+
+    #[allow(unused_mut)]
+    let mut spent_notes = fetch_spent_notes(
+        SAPLING_TABLES_PREFIX,
+        "output_index",
+        ShieldedProtocol::Sapling,
+    )?;
+    #[cfg(feature = "orchard")]
+    spent_notes.extend(fetch_spent_notes(
+        ORCHARD_TABLES_PREFIX,
+        "action_index",
+        ShieldedProtocol::Orchard,
+    )?);
+
+    Ok(spent_notes)
+}
+
+/// Returns the IDs of the transactions mined in the given range of block heights that spent
+/// notes received by the wallet in the given shielded pool.
+pub(crate) fn get_transactions_spending_pool(
+    conn: &rusqlite::Connection,
+    pool: ShieldedProtocol,
+    range: Range<BlockHeight>,
+) -> Result<Vec<TxId>, SqliteClientError> {
+    let table_prefix = match pool {
+        ShieldedProtocol::Sapling => SAPLING_TABLES_PREFIX,
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => ORCHARD_TABLES_PREFIX,
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => {
+            return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                ShieldedProtocol::Orchard,
+            )))
+        }
+    };
+
+    let mut stmt_txids = conn.prepare_cached(&format!(
+        "SELECT t.txid
+        FROM {table_prefix}_received_note_spends rns
+        JOIN transactions t ON t.id_tx = rns.transaction_id
+        WHERE t.block >= :start_height
+        AND t.block < :end_height
+        GROUP BY t.id_tx
+        ORDER BY t.block, t.tx_index"
+    ))?;
+
+    let rows = stmt_txids.query_and_then(
+        named_params![
+            ":start_height": u32::from(range.start),
+            ":end_height": u32::from(range.end),
+        ],
+        |row| -> Result<_, SqliteClientError> { Ok(TxId::from_bytes(row.get(0)?)) },
+    )?;
+    rows.collect()
+}
+
+pub(crate) fn get_spent_note_positions(
+    conn: &rusqlite::Connection,
+    txid: TxId,
+) -> Result<Vec<(ShieldedProtocol, Position)>, SqliteClientError> {
+    let fetch_positions = |table_prefix: &'static str, protocol: ShieldedProtocol| {
+        let mut stmt_positions = conn.prepare_cached(&format!(
+            "SELECT rn.commitment_tree_position
+            FROM {table_prefix}_received_note_spends rns
+            JOIN {table_prefix}_received_notes rn ON rn.id = rns.{table_prefix}_received_note_id
+            JOIN transactions spending_tx ON spending_tx.id_tx = rns.transaction_id
+            WHERE spending_tx.txid = :txid
+            AND rn.commitment_tree_position IS NOT NULL
+            ORDER BY rn.commitment_tree_position"
+        ))?;
+
+        let rows = stmt_positions.query_and_then(
+            named_params![":txid": txid.as_ref()],
+            |row| -> Result<_, SqliteClientError> {
+                Ok((protocol, Position::from(row.get::<_, u64>(0)?)))
+            },
+        )?;
+        rows.collect::<Result<Vec<_>, _>>()
+    };
+
+    #[allow(unused_mut)]
+    let mut positions = fetch_positions(SAPLING_TABLES_PREFIX, ShieldedProtocol::Sapling)?;
+    #[cfg(feature = "orchard")]
+    positions.extend(fetch_positions(
+        ORCHARD_TABLES_PREFIX,
+        ShieldedProtocol::Orchard,
+    )?);
+
+    Ok(positions)
+}
+
+/// Returns the identifiers of the notes received by the given account with external-scope (or
+/// imported) keys whose memos satisfy the given predicate.
+pub(crate) fn get_received_notes_matching_memo<F>(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+    predicate: F,
+) -> Result<Vec<NoteId>, SqliteClientError>
+where
+    F: Fn(&MemoBytes) -> bool,
+{
+    let fetch_matching =
+        |table_prefix: &'static str, output_col: &'static str, protocol: ShieldedProtocol| {
+            let mut stmt_memos = conn.prepare_cached(&format!(
+                "SELECT t.txid, rn.{output_col}, rn.memo
+                FROM {table_prefix}_received_notes rn
+                JOIN transactions t ON t.id_tx = rn.tx
+                WHERE rn.account_id = :account_id
+                AND rn.memo IS NOT NULL
+                AND rn.recipient_key_scope IS NOT :internal_scope
+                ORDER BY t.id_tx, rn.{output_col}"
+            ))?;
+
+            let mut matching = vec![];
+            let mut rows = stmt_memos.query(named_params![
+                ":account_id": account_id.0,
+                ":internal_scope": scope_code(Scope::Internal),
+            ])?;
+            while let Some(row) = rows.next()? {
+                let memo_bytes: Vec<u8> = row.get(2)?;
+                if predicate(&MemoBytes::from_bytes(&memo_bytes)?) {
+                    matching.push(NoteId::new(
+                        TxId::from_bytes(row.get(0)?),
+                        protocol,
+                        row.get(1)?,
+                    ));
+                }
+            }
+            Ok::<_, SqliteClientError>(matching)
+        };
+
+    #[allow(unused_mut)]
+    let mut matching = fetch_matching(
+        SAPLING_TABLES_PREFIX,
+        "output_index",
+        ShieldedProtocol::Sapling,
+    )?;
+    #[cfg(feature = "orchard")]
+    matching.extend(fetch_matching(
+        ORCHARD_TABLES_PREFIX,
+        "action_index",
+        ShieldedProtocol::Orchard,
+    )?);
+
+    Ok(matching)
+}
+
+pub(crate) fn get_newly_confirmed_notes(
+    conn: &rusqlite::Connection,
+    since_height: BlockHeight,
+    min_confirmations: NonZeroU32,
+) -> Result<Vec<NoteId>, SqliteClientError> {
+    let chain_tip_height = match scan_queue_extrema(conn)?.map(|range| *range.end()) {
+        Some(h) => h,
+        None => return Ok(vec![]),
+    };
+
+    // A note received in a transaction mined at height `h` is confirmed as of chain tip `t`
+    // when `h <= t + 1 - min_confirmations`.
+    let max_confirmed_height =
+        |tip: BlockHeight| u32::from(tip + 1).saturating_sub(min_confirmations.get());
+    let previously_confirmed_height = max_confirmed_height(since_height);
+    let confirmed_height = max_confirmed_height(chain_tip_height);
+    if confirmed_height <= previously_confirmed_height {
+        return Ok(vec![]);
+    }
+
+    let fetch_confirmed =
+        |table_prefix: &'static str, output_col: &'static str, protocol: ShieldedProtocol| {
+            let mut stmt_confirmed = conn.prepare_cached(&format!(
+                "SELECT t.txid, rn.{output_col}
+                FROM {table_prefix}_received_notes rn
+                JOIN transactions t ON t.id_tx = rn.tx
+                WHERE t.block > :previously_confirmed_height
+                AND t.block <= :confirmed_height
+                AND rn.recipient_key_scope IS NOT :internal_scope
+                ORDER BY t.id_tx, rn.{output_col}"
+            ))?;
+
+            let rows = stmt_confirmed.query_and_then(
+                named_params![
+                    ":previously_confirmed_height": previously_confirmed_height,
+                    ":confirmed_height": confirmed_height,
+                    ":internal_scope": scope_code(Scope::Internal),
+                ],
+                |row| -> Result<_, SqliteClientError> {
+                    Ok(NoteId::new(
+                        TxId::from_bytes(row.get(0)?),
+                        protocol,
+                        row.get(1)?,
+                    ))
+                },
+            )?;
+            rows.collect::<Result<Vec<_>, _>>()
+        };
+
+    #[allow(unused_mut)]
+    let mut confirmed = fetch_confirmed(
+        SAPLING_TABLES_PREFIX,
+        "output_index",
+        ShieldedProtocol::Sapling,
+    )?;
+    #[cfg(feature = "orchard")]
+    confirmed.extend(fetch_confirmed(
+        ORCHARD_TABLES_PREFIX,
+        "action_index",
+        ShieldedProtocol::Orchard,
+    )?);
+
+    Ok(confirmed)
+}
+
+/// Returns the unspent notes of the given account that are used as inputs by an unmined,
+/// unexpired transaction, along with the ID of each such transaction.
+pub(crate) fn get_notes_pending_spend(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+) -> Result<Vec<(NoteId, TxId)>, SqliteClientError> {
+    let chain_tip_height = scan_queue_extrema(conn)?.map(|range| u32::from(*range.end()));
+
+    let fetch_pending_spends =
+        |table_prefix: &'static str, output_col: &'static str, protocol: ShieldedProtocol| {
+            let mut stmt_pending_spends = conn.prepare_cached(&format!(
+                "SELECT received_tx.txid, rn.{output_col}, spending_tx.txid
+                FROM {table_prefix}_received_notes rn
+                JOIN transactions received_tx ON received_tx.id_tx = rn.tx
+                JOIN {table_prefix}_received_note_spends rns
+                    ON rns.{table_prefix}_received_note_id = rn.id
+                JOIN transactions spending_tx ON spending_tx.id_tx = rns.transaction_id
+                WHERE rn.account_id = :account_id
+                AND spending_tx.block IS NULL -- the spending tx is not mined
+                AND (
+                    spending_tx.expiry_height IS NULL -- the spending tx will not expire
+                    OR :chain_tip_height IS NULL
+                    OR spending_tx.expiry_height > :chain_tip_height -- the spending tx is unexpired
+                )
+                AND rn.id NOT IN (
+                    SELECT {table_prefix}_received_note_id
+                    FROM {table_prefix}_received_note_spends
+                    JOIN transactions stx ON stx.id_tx = transaction_id
+                    WHERE stx.block IS NOT NULL -- the note has been spent by a mined tx
+                )
+                ORDER BY received_tx.id_tx, rn.{output_col}, spending_tx.id_tx"
+            ))?;
+
+            let rows = stmt_pending_spends.query_and_then(
+                named_params![
+                    ":account_id": account_id.0,
+                    ":chain_tip_height": chain_tip_height,
+                ],
+                |row| -> Result<_, SqliteClientError> {
+                    Ok((
+                        NoteId::new(TxId::from_bytes(row.get(0)?), protocol, row.get(1)?),
+                        TxId::from_bytes(row.get(2)?),
+                    ))
+                },
+            )?;
+            rows.collect::<Result<Vec<_>, _>>()
+        };
+
+    #[allow(unused_mut)]
+    let mut pending_spends = fetch_pending_spends(
+        SAPLING_TABLES_PREFIX,
+        "output_index",
+        ShieldedProtocol::Sapling,
+    )?;
+    #[cfg(feature = "orchard")]
+    pending_spends.extend(fetch_pending_spends(
+        ORCHARD_TABLES_PREFIX,
+        "action_index",
+        ShieldedProtocol::Orchard,
+    )?);
+
+    Ok(pending_spends)
+}
+
+/// Returns the scope of the key with which the given received note was decrypted, if the
+/// note is known to the wallet and its recipient key scope was recorded.
+pub(crate) fn get_note_scope(
+    conn: &rusqlite::Connection,
+    note_id: NoteId,
+) -> Result<Option<Scope>, SqliteClientError> {
+    let fetch_scope_code = |table_prefix: &'static str, output_col: &'static str| {
+        conn.query_row(
+            &format!(
+                "SELECT recipient_key_scope FROM {table_prefix}_received_notes
+                JOIN transactions ON {table_prefix}_received_notes.tx = transactions.id_tx
+                WHERE transactions.txid = :txid
+                AND {table_prefix}_received_notes.{output_col} = :output_index"
+            ),
+            named_params![
+                ":txid": note_id.txid().as_ref(),
+                ":output_index": note_id.output_index()
+            ],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .optional()
+    };
+
+    let scope_code = match note_id.protocol() {
+        ShieldedProtocol::Sapling => {
+            fetch_scope_code(SAPLING_TABLES_PREFIX, "output_index")?.flatten()
+        }
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => {
+            fetch_scope_code(ORCHARD_TABLES_PREFIX, "action_index")?.flatten()
+        }
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => {
+            return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                ShieldedProtocol::Orchard,
+            )))
+        }
+    };
+
+    scope_code
+        .map(|code| {
+            parse_scope(code).ok_or_else(|| {
+                SqliteClientError::CorruptedData(format!("Invalid key scope code {}", code))
+            })
+        })
+        .transpose()
+}
+
+/// Sets the position of the given received note in its pool's note commitment tree.
+pub(crate) fn set_note_commitment_tree_position(
+    conn: &rusqlite::Connection,
+    note_id: NoteId,
+    position: Position,
+) -> Result<(), SqliteClientError> {
+    let update_position = |table_prefix: &'static str, output_col: &'static str| {
+        conn.execute(
+            &format!(
+                "UPDATE {table_prefix}_received_notes
+                SET commitment_tree_position = :position
+                WHERE tx = (SELECT id_tx FROM transactions WHERE txid = :txid)
+                AND {output_col} = :output_index"
+            ),
+            named_params![
+                ":position": u64::from(position),
+                ":txid": note_id.txid().as_ref(),
+                ":output_index": note_id.output_index()
+            ],
+        )
+    };
+
+    let updated = match note_id.protocol() {
+        ShieldedProtocol::Sapling => update_position(SAPLING_TABLES_PREFIX, "output_index")?,
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => update_position(ORCHARD_TABLES_PREFIX, "action_index")?,
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => {
+            return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                ShieldedProtocol::Orchard,
+            )))
+        }
+    };
+
+    if updated == 0 {
+        Err(SqliteClientError::NoteNotFound(note_id))
+    } else {
+        Ok(())
+    }
+}
+
+/// Replaces the note commitment tree positions of the received notes in the given pool with
+/// the given positions.
+///
+/// Notes of the pool that are not included in `note_positions` have their positions cleared,
+/// as their positions in the existing tree may not be valid in the replacement tree. If any
+/// such note was mined, a rescan is required from the least height at which one was mined, so
+/// that its position may be recovered.
+pub(crate) fn replace_note_commitment_tree_positions(
+    conn: &rusqlite::Connection,
+    protocol: ShieldedProtocol,
+    note_positions: &[(NoteId, Position)],
+) -> Result<(), SqliteClientError> {
+    let table_prefix = match protocol {
+        ShieldedProtocol::Sapling => SAPLING_TABLES_PREFIX,
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => ORCHARD_TABLES_PREFIX,
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => {
+            return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                ShieldedProtocol::Orchard,
+            )))
+        }
+    };
+
+    conn.execute(
+        &format!("UPDATE {table_prefix}_received_notes SET commitment_tree_position = NULL"),
+        [],
+    )?;
+
+    for (note_id, position) in note_positions {
+        // Notes of other pools cannot be positioned in the replaced tree.
+        if note_id.protocol() != protocol {
+            return Err(SqliteClientError::NoteNotFound(*note_id));
+        }
+        set_note_commitment_tree_position(conn, *note_id, *position)?;
+    }
+
+    let unpositioned_height = conn.query_row(
+        &format!(
+            "SELECT MIN(t.block)
+            FROM {table_prefix}_received_notes rn
+            JOIN transactions t ON t.id_tx = rn.tx
+            WHERE rn.commitment_tree_position IS NULL"
+        ),
+        [],
+        |row| row.get::<_, Option<u32>>(0),
+    )?;
+    if let Some(height) = unpositioned_height {
+        set_rescan_required(conn, BlockHeight::from(height))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the number of additional blocks that must be mined before the given received note
+/// has at least `min_confirmations` confirmations, or `None` if it already does.
+pub(crate) fn blocks_until_spendable<T: ChainTipProvider>(
+    conn: &rusqlite::Connection,
+    chain_tip: &T,
+    note_id: NoteId,
+    min_confirmations: NonZeroU32,
+) -> Result<Option<u32>, SqliteClientError>
+where
+    SqliteClientError: From<T::Error>,
+{
+    if chain_tip.chain_tip_height()?.is_none() {
+        return Err(SqliteClientError::ChainHeightUnknown);
+    }
+
+    let fetch_mined_height = |table_prefix: &'static str, output_col: &'static str| {
+        conn.query_row(
+            &format!(
+                "SELECT transactions.block FROM {table_prefix}_received_notes
+                JOIN transactions ON {table_prefix}_received_notes.tx = transactions.id_tx
+                WHERE transactions.txid = :txid
+                AND {table_prefix}_received_notes.{output_col} = :output_index"
+            ),
+            named_params![
+                ":txid": note_id.txid().as_ref(),
+                ":output_index": note_id.output_index()
+            ],
+            |row| row.get::<_, Option<u32>>(0),
+        )
+        .optional()
+    };
+
+    let mined_height = match note_id.protocol() {
+        ShieldedProtocol::Sapling => fetch_mined_height(SAPLING_TABLES_PREFIX, "output_index")?,
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => fetch_mined_height(ORCHARD_TABLES_PREFIX, "action_index")?,
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => {
+            return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                ShieldedProtocol::Orchard,
+            )))
+        }
+    }
+    .ok_or(SqliteClientError::NoteNotFound(note_id))?;
+
+    Ok(chain_tip::blocks_until_confirmed(
+        chain_tip,
+        mined_height.map(BlockHeight::from),
+        min_confirmations,
+    )?)
+}
+
+/// Sets or clears the lock on the given received note.
+pub(crate) fn set_note_locked(
+    conn: &rusqlite::Transaction,
+    note_id: NoteId,
+    locked: bool,
+) -> Result<(), SqliteClientError> {
+    let update_lock = |table_prefix: &'static str, output_col: &'static str| {
+        conn.execute(
+            &format!(
+                "UPDATE {table_prefix}_received_notes
+                SET is_locked = :is_locked
+                WHERE tx = (SELECT id_tx FROM transactions WHERE txid = :txid)
+                AND {output_col} = :output_index"
+            ),
+            named_params![
+                ":is_locked": locked,
+                ":txid": note_id.txid().as_ref(),
+                ":output_index": note_id.output_index()
+            ],
+        )
+    };
+
+    let updated = match note_id.protocol() {
+        ShieldedProtocol::Sapling => update_lock(SAPLING_TABLES_PREFIX, "output_index")?,
+        #[cfg(feature = "orchard")]
+        ShieldedProtocol::Orchard => update_lock(ORCHARD_TABLES_PREFIX, "action_index")?,
+        #[cfg(not(feature = "orchard"))]
+        ShieldedProtocol::Orchard => {
+            return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                ShieldedProtocol::Orchard,
+            )))
+        }
+    };
+
+    if updated == 0 {
+        Err(SqliteClientError::NoteNotFound(note_id))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the identifiers of the locked notes received by the given account.
+pub(crate) fn get_locked_notes(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+) -> Result<Vec<NoteId>, SqliteClientError> {
+    let fetch_locked_notes =
+        |table_prefix: &'static str, output_col: &'static str, protocol: ShieldedProtocol| {
+            let mut stmt_locked_notes = conn.prepare_cached(&format!(
+                "SELECT t.txid, rn.{output_col}
+                FROM {table_prefix}_received_notes rn
+                JOIN transactions t ON t.id_tx = rn.tx
+                WHERE rn.account_id = :account_id
+                AND rn.is_locked = 1
+                ORDER BY t.id_tx, rn.{output_col}"
+            ))?;
+
+            let rows = stmt_locked_notes.query_and_then(
+                named_params![":account_id": account_id.0],
+                |row| -> Result<_, SqliteClientError> {
+                    Ok(NoteId::new(
+                        TxId::from_bytes(row.get(0)?),
+                        protocol,
+                        row.get(1)?,
+                    ))
+                },
+            )?;
+            rows.collect::<Result<Vec<_>, _>>()
+        };
+
+    #[allow(unused_mut)]
+    let mut locked_notes = fetch_locked_notes(
+        SAPLING_TABLES_PREFIX,
+        "output_index",
+        ShieldedProtocol::Sapling,
+    )?;
+    #[cfg(feature = "orchard")]
+    locked_notes.extend(fetch_locked_notes(
+        ORCHARD_TABLES_PREFIX,
+        "action_index",
+        ShieldedProtocol::Orchard,
+    )?);
+
+    Ok(locked_notes)
+}
+
+/// Returns the internal identifiers of the locked notes received by the given account.
+pub(crate) fn get_locked_note_refs(
+    conn: &rusqlite::Connection,
+    account_id: AccountId,
+) -> Result<Vec<ReceivedNoteId>, SqliteClientError> {
+    let fetch_locked_refs = |table_prefix: &'static str, protocol: ShieldedProtocol| {
+        let mut stmt_locked_refs = conn.prepare_cached(&format!(
+            "SELECT id FROM {table_prefix}_received_notes
+            WHERE account_id = :account_id
+            AND is_locked = 1"
+        ))?;
+
+        let rows = stmt_locked_refs
+            .query_map(named_params![":account_id": account_id.0], |row| {
+                row.get(0).map(|id| ReceivedNoteId(protocol, id))
+            })?;
+        rows.collect::<Result<Vec<_>, _>>()
+    };
+
+    #[allow(unused_mut)]
+    let mut locked_refs = fetch_locked_refs(SAPLING_TABLES_PREFIX, ShieldedProtocol::Sapling)?;
+    #[cfg(feature = "orchard")]
+    locked_refs.extend(fetch_locked_refs(
+        ORCHARD_TABLES_PREFIX,
+        ShieldedProtocol::Orchard,
+    )?);
+
+    Ok(locked_refs)
+}
+
+/// The personalization used when computing [`unspent_set_fingerprint`].
+const UNSPENT_SET_FINGERPRINT_PERSONALIZATION: &[u8; 16] = b"ZUnspentSet_Hash";
+
+/// Returns a fingerprint of the set of mined, unspent outputs belonging to the given account.
+///
+/// Each output is encoded as its pool code, followed by its note commitment (or outpoint, for
+/// transparent outputs), its value, and its mined height. The encodings are sorted so that the
+/// fingerprint does not depend upon the order in which outputs were added to the wallet.
+pub(crate) fn unspent_set_fingerprint<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account: AccountId,
+) -> Result<[u8; 32], SqliteClientError> {
+    // Ensure that the account exists.
+    account_birthday(conn, account)?;
+
+    let encode = |pool: PoolType, id: &[u8], value: u64, height: BlockHeight| {
+        let mut entry = vec![pool_code(pool) as u8];
+        entry.extend_from_slice(id);
+        entry.extend_from_slice(&value.to_le_bytes());
+        entry.extend_from_slice(&u32::from(height).to_le_bytes());
+        entry
+    };
+
+    let mut entries = sapling::get_unspent_sapling_note_commitments(conn, params, account)?
+        .into_iter()
+        .map(|(cmu, value, height)| {
+            encode(
+                PoolType::Shielded(ShieldedProtocol::Sapling),
+                &cmu,
+                value,
+                height,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    #[cfg(feature = "orchard")]
+    entries.extend(
+        orchard::get_unspent_orchard_note_commitments(conn, params, account)?
+            .into_iter()
+            .map(|(cmx, value, height)| {
+                encode(
+                    PoolType::Shielded(ShieldedProtocol::Orchard),
+                    &cmx,
+                    value,
+                    height,
+                )
+            }),
+    );
+
+    let mut stmt_select_utxos = conn.prepare_cached(
+        "SELECT u.prevout_txid, u.prevout_idx, u.value_zat, u.height
+         FROM utxos u
+         WHERE u.received_by_account_id = :account_id
+         AND u.id NOT IN (
+            SELECT txo_spends.transparent_received_output_id
+            FROM transparent_received_output_spends txo_spends
+            JOIN transactions tx ON tx.id_tx = txo_spends.transaction_id
+            WHERE tx.block IS NOT NULL  -- the spending tx is mined
+            OR tx.expiry_height IS NULL -- the spending tx will not expire
+         )",
+    )?;
+    let mut rows = stmt_select_utxos.query(named_params![":account_id": account.0])?;
+    while let Some(row) = rows.next()? {
+        let mut outpoint = row.get::<_, Vec<u8>>(0)?;
+        outpoint.extend_from_slice(&row.get::<_, u32>(1)?.to_le_bytes());
+        let value = u64::try_from(row.get::<_, i64>(2)?).map_err(|_| {
+            SqliteClientError::CorruptedData("UTXO values must be nonnegative".to_string())
+        })?;
+        let height = BlockHeight::from(row.get::<_, u32>(3)?);
+        entries.push(encode(PoolType::Transparent, &outpoint, value, height));
+    }
+
+    entries.sort();
+
+    let mut state = blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(UNSPENT_SET_FINGERPRINT_PERSONALIZATION)
+        .to_state();
+    for entry in &entries {
+        state.update(entry);
+    }
+
+    let mut fingerprint = [0; 32];
+    fingerprint.copy_from_slice(state.finalize().as_bytes());
+    Ok(fingerprint)
+}
+
+/// Returns a witness for each of the given notes as of the checkpoint at `anchor_height`.
+///
+/// This does not modify the note commitment trees; the transaction is only required in order to
+/// construct the tree's backing store.
+pub(crate) fn get_witnesses(
+    tx: &rusqlite::Connection,
+    note_ids: &[NoteId],
+    anchor_height: BlockHeight,
+) -> Result<Vec<(NoteId, NoteWitness)>, SqliteClientError> {
+    let fetch_position =
+        |table_prefix: &'static str, output_col: &'static str, note_id: &NoteId| {
+            tx.query_row(
+                &format!(
+                    "SELECT commitment_tree_position FROM {table_prefix}_received_notes
+                JOIN transactions ON {table_prefix}_received_notes.tx = transactions.id_tx
+                WHERE transactions.txid = :txid
+                AND {table_prefix}_received_notes.{output_col} = :output_index"
+                ),
+                named_params![
+                    ":txid": note_id.txid().as_ref(),
+                    ":output_index": note_id.output_index()
+                ],
+                |row| row.get::<_, Option<u64>>(0),
+            )
+            .optional()
+            .map(|pos| pos.flatten().map(Position::from))
+        };
+
+    let sapling_tree: ShardTree<
+        _,
+        { ::sapling::NOTE_COMMITMENT_TREE_DEPTH },
+        SAPLING_SHARD_HEIGHT,
+    > = ShardTree::new(
+        SqliteShardStore::<_, ::sapling::Node, SAPLING_SHARD_HEIGHT>::from_connection(
+            tx,
+            SAPLING_TABLES_PREFIX,
+        )?,
+        PRUNING_DEPTH.try_into().unwrap(),
+    );
+
+    #[cfg(feature = "orchard")]
+    let orchard_tree: ShardTree<
+        _,
+        { ::orchard::NOTE_COMMITMENT_TREE_DEPTH as u8 },
+        ORCHARD_SHARD_HEIGHT,
+    > = ShardTree::new(
+        SqliteShardStore::<_, ::orchard::tree::MerkleHashOrchard, ORCHARD_SHARD_HEIGHT>::from_connection(
+            tx,
+            ORCHARD_TABLES_PREFIX,
+        )?,
+        PRUNING_DEPTH.try_into().unwrap(),
+    );
+
+    note_ids
+        .iter()
+        .map(|note_id| {
+            let witness = match note_id.protocol() {
+                ShieldedProtocol::Sapling => {
+                    let position = fetch_position(SAPLING_TABLES_PREFIX, "output_index", note_id)?
+                        .ok_or(SqliteClientError::NoteWitnessUnavailable(*note_id))?;
+                    NoteWitness::Sapling(
+                        sapling_tree.witness_at_checkpoint_id(position, &anchor_height)?,
+                    )
+                }
+                #[cfg(feature = "orchard")]
+                ShieldedProtocol::Orchard => {
+                    let position = fetch_position(ORCHARD_TABLES_PREFIX, "action_index", note_id)?
+                        .ok_or(SqliteClientError::NoteWitnessUnavailable(*note_id))?;
+                    NoteWitness::Orchard(
+                        orchard_tree.witness_at_checkpoint_id(position, &anchor_height)?,
+                    )
+                }
+                #[cfg(not(feature = "orchard"))]
+                ShieldedProtocol::Orchard => {
+                    return Err(SqliteClientError::UnsupportedPoolType(PoolType::Shielded(
+                        ShieldedProtocol::Orchard,
+                    )))
+                }
+            };
+
+            Ok((*note_id, witness))
+        })
+        .collect()
+}
+
+/// Returns the annotation that has been imported for the given transaction, if any.
+pub(crate) fn get_transaction_annotation(
+    conn: &rusqlite::Connection,
+    txid: TxId,
+) -> Result<Option<TransactionAnnotation>, SqliteClientError> {
+    conn.query_row(
+        "SELECT date, label, category, note FROM tx_annotations
+        WHERE txid = :txid",
+        named_params![":txid": txid.as_ref()],
+        |row| {
+            Ok(TransactionAnnotation::from_parts(
+                txid,
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+            ))
+        },
+    )
+    .optional()
+    .map_err(SqliteClientError::from)
+}
+
+/// Stores the given transaction annotation, replacing any existing annotation for the same
+/// transaction.
+pub(crate) fn put_transaction_annotation(
+    conn: &rusqlite::Transaction,
+    annotation: &TransactionAnnotation,
+) -> Result<(), SqliteClientError> {
+    let mut stmt_upsert_annotation = conn.prepare_cached(
+        "INSERT INTO tx_annotations (txid, date, label, category, note)
+        VALUES (:txid, :date, :label, :category, :note)
+        ON CONFLICT (txid) DO UPDATE
+        SET date = :date,
+            label = :label,
+            category = :category,
+            note = :note",
+    )?;
+
+    stmt_upsert_annotation.execute(named_params![
+        ":txid": annotation.txid().as_ref(),
+        ":date": annotation.date(),
+        ":label": annotation.label(),
+        ":category": annotation.category(),
+        ":note": annotation.note(),
+    ])?;
+
+    Ok(())
+}
+
+/// Returns the addresses being watched by the wallet, in the order in which they were added.
+pub(crate) fn get_watched_addresses<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+) -> Result<Vec<WatchedAddress>, SqliteClientError> {
+    let mut stmt = conn.prepare("SELECT address FROM watched_addresses ORDER BY id")?;
+    let mut rows = stmt.query([])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        let addr_str: String = row.get(0)?;
+        let addr = Address::decode(params, &addr_str).ok_or_else(|| {
+            SqliteClientError::CorruptedData("Not a valid Zcash recipient address".to_owned())
+        })?;
+        result.push(WatchedAddress::new(addr));
+    }
+    Ok(result)
+}
+
+/// Adds the given address to the set of addresses watched by the wallet, if it is not already
+/// present.
+pub(crate) fn put_watched_address<P: consensus::Parameters>(
+    conn: &rusqlite::Transaction,
+    params: &P,
+    address: &Address,
+) -> Result<WatchedAddress, SqliteClientError> {
+    let watched = WatchedAddress::new(address.clone());
+
+    let mut stmt_insert_watched_address = conn.prepare_cached(
+        "INSERT INTO watched_addresses (address, cached_transparent_receiver_address)
+        VALUES (:address, :cached_transparent_receiver_address)
+        ON CONFLICT (address) DO NOTHING",
+    )?;
+
+    stmt_insert_watched_address.execute(named_params![
+        ":address": address.encode(params),
+        ":cached_transparent_receiver_address": watched
+            .transparent_receiver()
+            .map(|taddr| Address::from(*taddr).encode(params)),
+    ])?;
+
+    Ok(watched)
+}
+
+pub(crate) fn get_funding_accounts(
+    conn: &rusqlite::Connection,
+    tx: &Transaction,
+) -> Result<HashSet<AccountId>, rusqlite::Error> {
+    let mut funding_accounts = HashSet::new();
+    #[cfg(feature = "transparent-inputs")]
+    funding_accounts.extend(transparent::detect_spending_accounts(
+        conn,
+        tx.transparent_bundle()
+            .iter()
+            .flat_map(|bundle| bundle.vin.iter().map(|txin| &txin.prevout)),
+    )?);
+
+    funding_accounts.extend(sapling::detect_spending_accounts(
+        conn,
+        tx.sapling_bundle().iter().flat_map(|bundle| {
+            bundle
+                .shielded_spends()
+                .iter()
+                .map(|spend| spend.nullifier())
+        }),
+    )?);
+
+    #[cfg(feature = "orchard")]
+    funding_accounts.extend(orchard::detect_spending_accounts(
+        conn,
+        tx.orchard_bundle()
+            .iter()
+            .flat_map(|bundle| bundle.actions().iter().map(|action| action.nullifier())),
+    )?);
+
+    Ok(funding_accounts)
+}
+
+/// Returns the memo for a sent note, if the sent note is known to the wallet.
+pub(crate) fn get_sent_memo(
+    conn: &rusqlite::Connection,
+    note_id: NoteId,
+) -> Result<Option<Memo>, SqliteClientError> {
+    let memo_bytes: Option<Vec<_>> = conn
+        .query_row(
+            "SELECT memo FROM sent_notes
+            JOIN transactions ON sent_notes.tx = transactions.id_tx
+            WHERE transactions.txid = :txid
+            AND sent_notes.output_pool = :pool_code
+            AND sent_notes.output_index = :output_index",
+            named_params![
+                ":txid": note_id.txid().as_ref(),
+                ":pool_code": pool_code(PoolType::Shielded(note_id.protocol())),
+                ":output_index": note_id.output_index()
+            ],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+
+    memo_bytes
+        .map(|b| {
             MemoBytes::from_bytes(&b)
                 .and_then(Memo::try_from)
                 .map_err(SqliteClientError::from)
@@ -1641,6 +3615,72 @@ pub(crate) fn account_birthday(
     .and_then(|opt| opt.ok_or(SqliteClientError::AccountUnknown))
 }
 
+/// Returns the data required to track the specified account on a watch-only device, or `None`
+/// if the account is unknown or the wallet does not have a UFVK for it.
+pub(crate) fn export_account_for_watching<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    account_id: AccountId,
+) -> Result<Option<WatchOnlyBundle>, SqliteClientError> {
+    let account: Option<(Option<String>, u32, Option<u32>)> = conn
+        .query_row(
+            "SELECT ufvk, birthday_height, recover_until_height
+            FROM accounts
+            WHERE id = :account_id",
+            named_params![":account_id": account_id.0],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+
+    let (ufvk, birthday_height, recover_until) = match account {
+        Some((Some(ufvk_str), birthday_height, recover_until)) => (
+            UnifiedFullViewingKey::decode(params, &ufvk_str)
+                .map_err(SqliteClientError::BadAccountData)?,
+            BlockHeight::from(birthday_height),
+            recover_until.map(BlockHeight::from),
+        ),
+        _ => return Ok(None),
+    };
+
+    let mut stmt_addresses = conn.prepare(
+        "SELECT address, diversifier_index_be
+        FROM addresses WHERE account_id = :account_id
+        ORDER BY diversifier_index_be",
+    )?;
+    let mut rows = stmt_addresses.query(named_params![":account_id": account_id.0])?;
+
+    let mut addresses = vec![];
+    while let Some(row) = rows.next()? {
+        let addr_str: String = row.get(0)?;
+        let di_vec: Vec<u8> = row.get(1)?;
+        let mut di: [u8; 11] = di_vec.try_into().map_err(|_| {
+            SqliteClientError::CorruptedData("Diversifier index is not an 11-byte value".to_owned())
+        })?;
+        di.reverse(); // BE -> LE conversion
+
+        let ua = Address::decode(params, &addr_str)
+            .ok_or_else(|| {
+                SqliteClientError::CorruptedData("Not a valid Zcash recipient address".to_owned())
+            })
+            .and_then(|addr| match addr {
+                Address::Unified(ua) => Ok(ua),
+                _ => Err(SqliteClientError::CorruptedData(format!(
+                    "Addresses table contains {} which is not a unified address",
+                    addr_str,
+                ))),
+            })?;
+
+        addresses.push((DiversifierIndex::from(di), ua));
+    }
+
+    Ok(Some(WatchOnlyBundle::from_parts(
+        ufvk,
+        birthday_height,
+        recover_until,
+        addresses,
+    )))
+}
+
 /// Returns the minimum and maximum heights for blocks stored in the wallet database.
 pub(crate) fn block_height_extrema(
     conn: &rusqlite::Connection,
@@ -1833,6 +3873,72 @@ pub(crate) fn block_metadata<P: consensus::Parameters>(
     .and_then(|meta_row| meta_row.map(|r| parse_block_metadata(params, r)).transpose())
 }
 
+/// Returns the sizes of the Sapling and Orchard note commitment trees as of the end of the block
+/// at the given height, if the block has been scanned.
+pub(crate) fn get_tree_sizes<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    block_height: BlockHeight,
+) -> Result<Option<(u32, u32)>, SqliteClientError> {
+    let sapling_tree_size = match block_metadata(conn, params, block_height)? {
+        Some(meta) => meta.sapling_tree_size(),
+        None => return Ok(None),
+    };
+
+    // The Orchard tree is empty prior to NU5 activation. After activation, the size of the
+    // Orchard tree is only recorded for blocks scanned with Orchard support enabled.
+    let orchard_tree_size = if params
+        .activation_height(NetworkUpgrade::Nu5)
+        .map_or(true, |nu5_activation| block_height < nu5_activation)
+    {
+        Some(0)
+    } else {
+        conn.query_row(
+            "SELECT orchard_commitment_tree_size FROM blocks WHERE height = :block_height",
+            named_params![":block_height": u32::from(block_height)],
+            |row| row.get::<_, Option<u32>>(0),
+        )?
+    };
+
+    Ok(sapling_tree_size.zip(orchard_tree_size))
+}
+
+/// Returns the height from which the wallet must be rescanned, if an inconsistency has been
+/// detected in its data.
+pub(crate) fn get_rescan_required(
+    conn: &rusqlite::Connection,
+) -> Result<Option<BlockHeight>, SqliteClientError> {
+    conn.query_row(
+        "SELECT from_height FROM rescan_required WHERE id = 0",
+        [],
+        |row| row.get::<_, u32>(0).map(BlockHeight::from),
+    )
+    .optional()
+    .map_err(SqliteClientError::from)
+}
+
+/// Records that the wallet must be rescanned from the given height. If a rescan is already
+/// required, the lesser of the two heights is retained.
+pub(crate) fn set_rescan_required(
+    conn: &rusqlite::Connection,
+    from_height: BlockHeight,
+) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "INSERT INTO rescan_required (id, from_height)
+         VALUES (0, :from_height)
+         ON CONFLICT (id) DO UPDATE
+         SET from_height = MIN(from_height, :from_height)",
+        named_params![":from_height": u32::from(from_height)],
+    )?;
+    Ok(())
+}
+
+/// Clears the record of a required rescan, if any.
+pub(crate) fn clear_rescan_required(conn: &rusqlite::Connection) -> Result<(), SqliteClientError> {
+    conn.execute("DELETE FROM rescan_required", [])?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 pub(crate) fn block_fully_scanned<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
@@ -1927,7 +4033,7 @@ pub(crate) fn get_tx_height(
     txid: TxId,
 ) -> Result<Option<BlockHeight>, rusqlite::Error> {
     conn.query_row(
-        "SELECT block FROM transactions WHERE txid = ?",
+        "SELECT IFNULL(block, mined_height) FROM transactions WHERE txid = ?",
         [txid.as_ref().to_vec()],
         |row| Ok(row.get::<_, Option<u32>>(0)?.map(BlockHeight::from)),
     )
@@ -1935,6 +4041,23 @@ pub(crate) fn get_tx_height(
     .map(|opt| opt.flatten())
 }
 
+/// Returns the time of the block in which the specified transaction was mined, if the
+/// transaction has been mined and the block is known to the wallet.
+pub(crate) fn get_transaction_time(
+    conn: &rusqlite::Connection,
+    txid: TxId,
+) -> Result<Option<u32>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT blocks.time
+         FROM transactions
+         JOIN blocks ON blocks.height = transactions.block
+         WHERE transactions.txid = ?",
+        [txid.as_ref().to_vec()],
+        |row| row.get::<_, u32>(0),
+    )
+    .optional()
+}
+
 /// Returns the block hash for the block at the specified height,
 /// if any.
 pub(crate) fn get_block_hash(
@@ -2065,9 +4188,12 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
     // database.
     if block_height < last_scanned_height {
         // Truncate the note commitment trees
+        // Truncation removes checkpoints without pruning any, so the retention window used
+        // here has no effect.
         let mut wdb = WalletDb {
             conn: SqlTransaction(conn),
             params: params.clone(),
+            checkpoint_retention: PRUNING_DEPTH as usize,
         };
         wdb.with_sapling_tree_mut(|tree| {
             tree.truncate_removing_checkpoint(&block_height).map(|_| ())
@@ -2090,6 +4216,36 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
             "DELETE FROM utxos WHERE height > ?",
             [u32::from(block_height)],
         )?;
+        conn.execute(
+            "DELETE FROM watched_address_utxos WHERE height > ?",
+            [u32::from(block_height)],
+        )?;
+
+        // Record the wallet's own transactions that are about to be un-mined as candidates for
+        // re-broadcast. These transactions remain valid unless they expire, so the notes and
+        // outputs that they spend remain locked.
+        conn.execute(
+            "INSERT OR IGNORE INTO tx_rebroadcast_candidates (transaction_id)
+            SELECT id_tx FROM transactions
+            WHERE block IS NOT NULL AND block > :block_height
+            AND raw IS NOT NULL
+            AND (
+                EXISTS (SELECT 1 FROM sent_notes WHERE sent_notes.tx = transactions.id_tx)
+                OR EXISTS (
+                    SELECT 1 FROM sapling_received_note_spends
+                    WHERE transaction_id = transactions.id_tx
+                )
+                OR EXISTS (
+                    SELECT 1 FROM orchard_received_note_spends
+                    WHERE transaction_id = transactions.id_tx
+                )
+                OR EXISTS (
+                    SELECT 1 FROM transparent_received_output_spends
+                    WHERE transaction_id = transactions.id_tx
+                )
+            )",
+            named_params![":block_height": u32::from(block_height)],
+        )?;
 
         // Un-mine transactions.
         conn.execute(
@@ -2097,6 +4253,11 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
             WHERE block IS NOT NULL AND block > ?",
             [u32::from(block_height)],
         )?;
+        conn.execute(
+            "UPDATE transactions SET mined_height = NULL
+            WHERE mined_height IS NOT NULL AND mined_height > ?",
+            [u32::from(block_height)],
+        )?;
 
         // Now that they aren't depended on, delete un-mined blocks.
         conn.execute(
@@ -2116,6 +4277,181 @@ pub(crate) fn truncate_to_height<P: consensus::Parameters>(
     Ok(())
 }
 
+/// Returns the IDs of the wallet's own transactions that were un-mined by a chain reorg, have
+/// not since been mined again, and have not expired as of the current chain tip.
+pub(crate) fn get_rebroadcast_candidates(
+    conn: &rusqlite::Connection,
+) -> Result<Vec<TxId>, SqliteClientError> {
+    let chain_tip_height = scan_queue_extrema(conn)?.map(|range| *range.end());
+
+    // A transaction with an expiry height of zero never expires. Otherwise, it may be mined in
+    // any block up to and including its expiry height.
+    let mut stmt = conn.prepare_cached(
+        "SELECT t.txid
+        FROM tx_rebroadcast_candidates c
+        JOIN transactions t ON t.id_tx = c.transaction_id
+        WHERE t.block IS NULL
+        AND (
+            :chain_tip_height IS NULL
+            OR t.expiry_height IS NULL
+            OR t.expiry_height = 0
+            OR t.expiry_height > :chain_tip_height
+        )
+        ORDER BY t.id_tx",
+    )?;
+
+    let rows = stmt.query_map(
+        named_params![":chain_tip_height": chain_tip_height.map(u32::from)],
+        |row| row.get(0).map(TxId::from_bytes),
+    )?;
+
+    rows.collect::<Result<_, _>>()
+        .map_err(SqliteClientError::from)
+}
+
+/// Returns the IDs of the wallet's own transactions that have not been mined and have not expired
+/// as of the current chain tip.
+///
+/// A transaction is considered to have been created by the wallet if it has outputs recorded in
+/// `sent_notes`, or if it spends any of the wallet's notes or transparent outputs.
+pub(crate) fn get_unmined_transactions(
+    conn: &rusqlite::Connection,
+) -> Result<Vec<TxId>, SqliteClientError> {
+    let chain_tip_height = scan_queue_extrema(conn)?.map(|range| *range.end());
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT t.txid
+        FROM transactions t
+        WHERE t.block IS NULL
+        AND (
+            :chain_tip_height IS NULL
+            OR t.expiry_height IS NULL
+            OR t.expiry_height = 0
+            OR t.expiry_height > :chain_tip_height
+        )
+        AND (
+            EXISTS (SELECT 1 FROM sent_notes WHERE sent_notes.tx = t.id_tx)
+            OR EXISTS (
+                SELECT 1 FROM sapling_received_note_spends
+                WHERE transaction_id = t.id_tx
+            )
+            OR EXISTS (
+                SELECT 1 FROM orchard_received_note_spends
+                WHERE transaction_id = t.id_tx
+            )
+            OR EXISTS (
+                SELECT 1 FROM transparent_received_output_spends
+                WHERE transaction_id = t.id_tx
+            )
+        )
+        ORDER BY t.id_tx",
+    )?;
+
+    let rows = stmt.query_map(
+        named_params![":chain_tip_height": chain_tip_height.map(u32::from)],
+        |row| row.get(0).map(TxId::from_bytes),
+    )?;
+
+    rows.collect::<Result<_, _>>()
+        .map_err(SqliteClientError::from)
+}
+
+/// Removes an unmined transaction and all of the wallet data derived from it, so that the
+/// notes and outputs it spent are no longer marked as spent.
+///
+/// Unless `force` is set, the transaction must have expired as of the current chain tip.
+///
+/// This should only be executed inside a transactional context.
+pub(crate) fn abandon_transaction<T: ChainTipProvider>(
+    conn: &rusqlite::Transaction,
+    chain_tip: &T,
+    txid: TxId,
+    force: bool,
+) -> Result<(), SqliteClientError>
+where
+    SqliteClientError: From<T::Error>,
+{
+    let tx = conn
+        .query_row(
+            "SELECT id_tx, block, expiry_height FROM transactions WHERE txid = :txid",
+            named_params![":txid": txid.as_ref()],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<u32>>(1)?,
+                    row.get::<_, Option<u32>>(2)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    let (id_tx, mined_height, expiry_height) = match tx {
+        Some(tx) => tx,
+        None => return Ok(()),
+    };
+
+    if mined_height.is_some() {
+        return Err(SqliteClientError::TransactionMined(txid));
+    }
+
+    if !force {
+        let expired = match expiry_height {
+            Some(expiry_height) => chain_tip::is_expired(chain_tip, expiry_height.into())?,
+            None => false,
+        };
+        if !expired {
+            return Err(SqliteClientError::TransactionMayHaveBeenBroadcast(txid));
+        }
+    }
+
+    // Unmark the notes and outputs spent by the transaction.
+    for table in [
+        "sapling_received_note_spends",
+        "orchard_received_note_spends",
+        "transparent_received_output_spends",
+        "transparent_spend_map",
+    ] {
+        conn.execute(
+            &format!("DELETE FROM {table} WHERE transaction_id = :id_tx"),
+            named_params![":id_tx": id_tx],
+        )?;
+    }
+    conn.execute(
+        "UPDATE watched_address_utxos SET spent_in_tx = NULL WHERE spent_in_tx = :id_tx",
+        named_params![":id_tx": id_tx],
+    )?;
+
+    // Remove the outputs of the transaction. Deleting a received note also removes any record
+    // of its having been spent.
+    conn.execute(
+        "DELETE FROM sent_notes WHERE tx = :id_tx",
+        named_params![":id_tx": id_tx],
+    )?;
+    conn.execute(
+        "DELETE FROM sapling_received_notes WHERE tx = :id_tx",
+        named_params![":id_tx": id_tx],
+    )?;
+    conn.execute(
+        "DELETE FROM orchard_received_notes WHERE tx = :id_tx",
+        named_params![":id_tx": id_tx],
+    )?;
+    conn.execute(
+        "DELETE FROM utxos WHERE prevout_txid = :txid",
+        named_params![":txid": txid.as_ref()],
+    )?;
+    conn.execute(
+        "DELETE FROM watched_address_utxos WHERE prevout_txid = :txid",
+        named_params![":txid": txid.as_ref()],
+    )?;
+
+    conn.execute(
+        "DELETE FROM transactions WHERE id_tx = :id_tx",
+        named_params![":id_tx": id_tx],
+    )?;
+
+    Ok(())
+}
+
 #[cfg(feature = "transparent-inputs")]
 fn to_unspent_transparent_output(row: &Row) -> Result<WalletTransparentOutput, SqliteClientError> {
     let txid: Vec<u8> = row.get("prevout_txid")?;
@@ -2129,6 +4465,7 @@ fn to_unspent_transparent_output(row: &Row) -> Result<WalletTransparentOutput, S
         SqliteClientError::CorruptedData(format!("Invalid UTXO value: {}", raw_value))
     })?;
     let height: u32 = row.get("height")?;
+    let is_coinbase: bool = row.get("is_coinbase")?;
 
     let outpoint = OutPoint::new(txid_bytes, index);
     WalletTransparentOutput::from_parts(
@@ -2138,6 +4475,7 @@ fn to_unspent_transparent_output(row: &Row) -> Result<WalletTransparentOutput, S
             script_pubkey,
         },
         BlockHeight::from(height),
+        is_coinbase,
     )
     .ok_or_else(|| {
         SqliteClientError::CorruptedData(
@@ -2152,7 +4490,7 @@ pub(crate) fn get_unspent_transparent_output(
     outpoint: &OutPoint,
 ) -> Result<Option<WalletTransparentOutput>, SqliteClientError> {
     let mut stmt_select_utxo = conn.prepare_cached(
-        "SELECT u.prevout_txid, u.prevout_idx, u.script, u.value_zat, u.height
+        "SELECT u.prevout_txid, u.prevout_idx, u.script, u.value_zat, u.height, u.is_coinbase
          FROM utxos u
          WHERE u.prevout_txid = :txid
          AND u.prevout_idx = :output_index
@@ -2197,7 +4535,7 @@ pub(crate) fn get_unspent_transparent_outputs<P: consensus::Parameters>(
 
     let mut stmt_utxos = conn.prepare(
         "SELECT u.prevout_txid, u.prevout_idx, u.script,
-                u.value_zat, u.height
+                u.value_zat, u.height, u.is_coinbase
          FROM utxos u
          WHERE u.address = :address
          AND u.height <= :max_height
@@ -2282,6 +4620,70 @@ pub(crate) fn get_transparent_balances<P: consensus::Parameters>(
     Ok(res)
 }
 
+/// Returns every transparent output received by the specified account, along with its spend
+/// status.
+///
+/// A spend by a transaction that has not been mined is treated as pending until the
+/// transaction's expiry height is in the stable range of the chain (below the chain tip by at
+/// least [`PRUNING_DEPTH`] blocks), consistent with [`get_unspent_transparent_outputs`].
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn get_transparent_outpoints(
+    conn: &rusqlite::Connection,
+    account: AccountId,
+) -> Result<Vec<(OutPoint, WalletTransparentOutput, SpendStatus)>, SqliteClientError> {
+    let stable_height =
+        scan_queue_extrema(conn)?.map(|range| u32::from(range.end().saturating_sub(PRUNING_DEPTH)));
+
+    let mut stmt_utxos = conn.prepare(
+        "SELECT u.prevout_txid, u.prevout_idx, u.script, u.value_zat, u.height, u.is_coinbase,
+            (
+                SELECT tx.txid
+                FROM transparent_received_output_spends txo_spends
+                JOIN transactions tx ON tx.id_tx = txo_spends.transaction_id
+                WHERE txo_spends.transparent_received_output_id = u.id
+                AND tx.block IS NOT NULL -- the spending tx is mined
+                LIMIT 1
+            ) AS mined_spend_txid,
+            (
+                SELECT tx.txid
+                FROM transparent_received_output_spends txo_spends
+                JOIN transactions tx ON tx.id_tx = txo_spends.transaction_id
+                WHERE txo_spends.transparent_received_output_id = u.id
+                AND tx.block IS NULL -- the spending tx is not mined
+                AND (
+                    tx.expiry_height IS NULL -- the spending tx will not expire
+                    OR :stable_height IS NULL
+                    OR tx.expiry_height > :stable_height -- the spending tx is unexpired
+                )
+                ORDER BY tx.id_tx
+                LIMIT 1
+            ) AS pending_spend_txid
+         FROM utxos u
+         WHERE u.received_by_account_id = :account_id
+         ORDER BY u.height, u.id",
+    )?;
+
+    let mut rows = stmt_utxos.query(named_params![
+        ":account_id": account.0,
+        ":stable_height": stable_height,
+    ])?;
+    let mut outpoints = vec![];
+    while let Some(row) = rows.next()? {
+        let output = to_unspent_transparent_output(row)?;
+        let mined_spend_txid: Option<[u8; 32]> = row.get("mined_spend_txid")?;
+        let pending_spend_txid: Option<[u8; 32]> = row.get("pending_spend_txid")?;
+        let status = match (mined_spend_txid, pending_spend_txid) {
+            (Some(txid), _) => SpendStatus::Spent(TxId::from_bytes(txid)),
+            (None, Some(txid)) => SpendStatus::PendingSpend(TxId::from_bytes(txid)),
+            (None, None) => SpendStatus::Unspent,
+        };
+
+        outpoints.push((output.outpoint().clone(), output, status));
+    }
+
+    Ok(outpoints)
+}
+
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn get_transparent_addresses_and_sync_heights<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
@@ -2368,6 +4770,32 @@ pub(crate) fn get_account_ids(
     Ok(result)
 }
 
+/// Returns summary information about each account in the wallet, in order of account ID.
+pub(crate) fn list_accounts(
+    conn: &rusqlite::Connection,
+) -> Result<Vec<AccountInfo<AccountId>>, SqliteClientError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, account_kind, hd_seed_fingerprint, hd_account_index, birthday_height
+        FROM accounts
+        ORDER BY id",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        let source = parse_account_source(
+            row.get("account_kind")?,
+            row.get("hd_seed_fingerprint")?,
+            row.get("hd_account_index")?,
+        )?;
+        result.push(AccountInfo::from_parts(
+            AccountId(row.get("id")?),
+            source,
+            BlockHeight::from(row.get::<_, u32>("birthday_height")?),
+        ));
+    }
+    Ok(result)
+}
+
 /// Inserts information about a scanned block into the database.
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn put_block(
@@ -2459,10 +4887,11 @@ pub(crate) fn put_tx_meta(
 ) -> Result<i64, SqliteClientError> {
     // It isn't there, so insert our transaction into the database.
     let mut stmt_upsert_tx_meta = conn.prepare_cached(
-        "INSERT INTO transactions (txid, block, tx_index)
-        VALUES (:txid, :block, :tx_index)
+        "INSERT INTO transactions (txid, block, mined_height, tx_index)
+        VALUES (:txid, :block, :block, :tx_index)
         ON CONFLICT (txid) DO UPDATE
         SET block = :block,
+            mined_height = :block,
             tx_index = :tx_index
         RETURNING id_tx",
     )?;
@@ -2474,9 +4903,15 @@ pub(crate) fn put_tx_meta(
         ":tx_index": i64::try_from(tx.block_index()).expect("transaction indices are representable as i64"),
     ];
 
-    stmt_upsert_tx_meta
-        .query_row(tx_params, |row| row.get::<_, i64>(0))
-        .map_err(SqliteClientError::from)
+    let id_tx = stmt_upsert_tx_meta.query_row(tx_params, |row| row.get::<_, i64>(0))?;
+
+    // A transaction that has been mined again no longer needs to be re-broadcast.
+    conn.execute(
+        "DELETE FROM tx_rebroadcast_candidates WHERE transaction_id = :id_tx",
+        named_params![":id_tx": id_tx],
+    )?;
+
+    Ok(id_tx)
 }
 
 /// Returns the most likely wallet address that corresponds to the protocol-level receiver of a
@@ -2555,13 +4990,35 @@ pub(crate) fn put_tx_data(
         .map_err(SqliteClientError::from)
 }
 
+/// Records the height at which the given transaction was mined.
+///
+/// This is used for transactions discovered outside of compact block scanning, for which the
+/// corresponding block may not be present in the `blocks` table.
+pub(crate) fn set_tx_mined_height(
+    conn: &rusqlite::Connection,
+    tx_ref: i64,
+    height: BlockHeight,
+) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "UPDATE transactions SET mined_height = :mined_height WHERE id_tx = :id_tx",
+        named_params![
+            ":mined_height": u32::from(height),
+            ":id_tx": tx_ref,
+        ],
+    )?;
+
+    Ok(())
+}
+
 /// Marks the given UTXO as having been spent.
+///
+/// Returns `true` if the UTXO is known to the wallet, or `false` otherwise.
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn mark_transparent_utxo_spent(
     conn: &rusqlite::Connection,
     tx_ref: i64,
     outpoint: &OutPoint,
-) -> Result<(), SqliteClientError> {
+) -> Result<bool, SqliteClientError> {
     let mut stmt_mark_transparent_utxo_spent = conn.prepare_cached(
         "INSERT INTO transparent_received_output_spends (transparent_received_output_id, transaction_id)
          SELECT txo.id, :spent_in_tx
@@ -2578,10 +5035,83 @@ pub(crate) fn mark_transparent_utxo_spent(
     ];
 
     stmt_mark_transparent_utxo_spent.execute(sql_args)?;
+
+    Ok(conn
+        .query_row(
+            "SELECT 1 FROM utxos WHERE prevout_txid = :prevout_txid AND prevout_idx = :prevout_idx",
+            &sql_args[1..],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+/// Records the spend of a transparent output by the given transaction input.
+///
+/// If the spent UTXO is not yet known to the wallet, but the input's `scriptSig` reveals that it
+/// was received at one of the wallet's transparent addresses, the spend is recorded in the
+/// `transparent_spend_map` table so that it can be applied if the UTXO is later added by
+/// [`put_received_transparent_utxo`]. Spends of outputs that cannot belong to the wallet are
+/// ignored.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn mark_transparent_input_spent<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    tx_ref: i64,
+    txin: &TxIn<transparent::Authorized>,
+) -> Result<(), SqliteClientError> {
+    if mark_transparent_utxo_spent(conn, tx_ref, &txin.prevout)? {
+        return Ok(());
+    }
+
+    let spends_wallet_address = match txin.script_sig.p2pkh_spend_address() {
+        Some(taddr) => {
+            find_account_for_transparent_output(conn, params, &taddr.script())?.is_some()
+        }
+        None => false,
+    };
+
+    if spends_wallet_address {
+        conn.prepare_cached(
+            "INSERT OR IGNORE INTO transparent_spend_map (transaction_id, prevout_txid, prevout_idx)
+             VALUES (:spent_in_tx, :prevout_txid, :prevout_idx)",
+        )?
+        .execute(named_params![
+            ":spent_in_tx": &tx_ref,
+            ":prevout_txid": &txin.prevout.hash().to_vec(),
+            ":prevout_idx": &txin.prevout.n(),
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// Deletes from the transparent spend map any entries for spending transactions that were mined
+/// at a height lower than the pruning height.
+///
+/// Once a spend is stable, the spent UTXO will no longer be returned by a light wallet server
+/// as unspent, so there is no need to retain the spend for application to it.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn prune_transparent_spend_map(
+    conn: &rusqlite::Connection,
+    block_height: BlockHeight,
+) -> Result<(), SqliteClientError> {
+    conn.prepare_cached(
+        "DELETE FROM transparent_spend_map
+         WHERE transaction_id IN (
+            SELECT id_tx FROM transactions
+            WHERE block < :block_height
+         )",
+    )?
+    .execute(named_params![":block_height": u32::from(block_height)])?;
+
     Ok(())
 }
 
-/// Adds the given received UTXO to the datastore.
+/// Adds the given received UTXO to the datastore, or updates it if it is already present.
+///
+/// The UTXO is attributed to the account that derived its receiving address. Any spend of the
+/// UTXO by a transaction that the wallet has already stored is recorded at the same time.
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn put_received_transparent_utxo<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
@@ -2620,15 +5150,158 @@ pub(crate) fn put_received_transparent_utxo<P: consensus::Parameters>(
                     Err(e) => Some(Err(e)),
                 },
             )
-            // The UTXO was not for any of the legacy transparent addresses.
+            // The UTXO was not for any of the legacy transparent addresses, so it can only be
+            // recorded if it was received at a watched address.
             .unwrap_or_else(|| {
-                Err(SqliteClientError::AddressNotRecognized(
-                    *output.recipient_address(),
-                ))
+                put_watched_transparent_utxo(conn, params, output)?.ok_or_else(|| {
+                    SqliteClientError::AddressNotRecognized(*output.recipient_address())
+                })
             })
     }
 }
 
+/// Records a transparent output received at the transparent receiver of a watched address.
+///
+/// Returns `Ok(None)` if the output was not received at a watched address.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn put_watched_transparent_utxo<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    output: &WalletTransparentOutput,
+) -> Result<Option<UtxoId>, SqliteClientError> {
+    let mut stmt_upsert_watched_utxo = conn.prepare_cached(
+        "INSERT INTO watched_address_utxos (
+            watched_address_id, address,
+            prevout_txid, prevout_idx, script,
+            value_zat, height, is_coinbase
+        )
+        SELECT id, :address, :prevout_txid, :prevout_idx, :script, :value_zat, :height, :is_coinbase
+        FROM watched_addresses
+        WHERE cached_transparent_receiver_address = :address
+        ON CONFLICT (prevout_txid, prevout_idx) DO UPDATE
+        SET height = :height
+        RETURNING id",
+    )?;
+
+    let sql_args = named_params![
+        ":address": &output.recipient_address().encode(params),
+        ":prevout_txid": &output.outpoint().hash().to_vec(),
+        ":prevout_idx": &output.outpoint().n(),
+        ":script": &output.txout().script_pubkey.0,
+        ":value_zat": &i64::from(Amount::from(output.txout().value)),
+        ":height": &u32::from(output.height()),
+        ":is_coinbase": &output.is_coinbase(),
+    ];
+
+    stmt_upsert_watched_utxo
+        .query_row(sql_args, |row| row.get::<_, i64>(0).map(UtxoId))
+        .optional()
+        .map_err(SqliteClientError::from)
+}
+
+/// Records the transparent outputs of the given transaction that were received at watched
+/// addresses, if the height at which the transaction was mined is known.
+///
+/// Outputs of unmined transactions are not recorded, as the wallet tracks the confirmations of
+/// outputs received at watched addresses from the height at which they were mined.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn put_watched_tx_outputs<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    tx: &Transaction,
+) -> Result<(), SqliteClientError> {
+    let bundle = match tx.transparent_bundle() {
+        Some(bundle) => bundle,
+        None => return Ok(()),
+    };
+    let height = match get_tx_height(conn, tx.txid())? {
+        Some(height) => height,
+        None => return Ok(()),
+    };
+
+    for (output_index, txout) in bundle.vout.iter().enumerate() {
+        let outpoint = OutPoint::new(tx.txid().into(), output_index as u32);
+        if let Some(output) = WalletTransparentOutput::from_parts(
+            outpoint,
+            txout.clone(),
+            height,
+            bundle.is_coinbase(),
+        ) {
+            put_watched_transparent_utxo(conn, params, &output)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Marks the output received at a watched address that is spent by the given transaction
+/// input as spent, if any.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn mark_watched_utxo_spent(
+    conn: &rusqlite::Connection,
+    tx_ref: i64,
+    outpoint: &OutPoint,
+) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "UPDATE watched_address_utxos
+        SET spent_in_tx = :spent_in_tx
+        WHERE prevout_txid = :prevout_txid
+        AND prevout_idx = :prevout_idx",
+        named_params![
+            ":spent_in_tx": tx_ref,
+            ":prevout_txid": &outpoint.hash().to_vec(),
+            ":prevout_idx": &outpoint.n(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Returns the unspent transparent outputs received at the given watched transparent address,
+/// such that the block that included the transaction was mined at a height less than or equal
+/// to the provided `max_height`.
+#[cfg(feature = "transparent-inputs")]
+pub(crate) fn get_unspent_watched_outputs<P: consensus::Parameters>(
+    conn: &rusqlite::Connection,
+    params: &P,
+    address: &TransparentAddress,
+    max_height: BlockHeight,
+) -> Result<Vec<WalletTransparentOutput>, SqliteClientError> {
+    let chain_tip_height = scan_queue_extrema(conn)?.map(|range| *range.end());
+    let stable_height = chain_tip_height
+        .unwrap_or(max_height)
+        .saturating_sub(PRUNING_DEPTH);
+
+    let mut stmt_utxos = conn.prepare(
+        "SELECT u.prevout_txid, u.prevout_idx, u.script,
+                u.value_zat, u.height, u.is_coinbase
+         FROM watched_address_utxos u
+         LEFT OUTER JOIN transactions tx ON tx.id_tx = u.spent_in_tx
+         WHERE u.address = :address
+         AND u.height <= :max_height
+         AND NOT (
+            u.spent_in_tx IS NOT NULL
+            AND (
+              IFNULL(tx.block, tx.mined_height) IS NOT NULL -- the spending tx is mined
+              OR tx.expiry_height IS NULL -- the spending tx will not expire
+              OR tx.expiry_height > :stable_height -- the spending tx is unexpired
+            )
+         )",
+    )?;
+
+    let mut rows = stmt_utxos.query(named_params![
+        ":address": address.encode(params),
+        ":max_height": u32::from(max_height),
+        ":stable_height": u32::from(stable_height),
+    ])?;
+    let mut utxos = vec![];
+    while let Some(row) = rows.next()? {
+        utxos.push(to_unspent_transparent_output(row)?);
+    }
+
+    Ok(utxos)
+}
+
 #[cfg(feature = "transparent-inputs")]
 pub(crate) fn put_legacy_transparent_utxo<P: consensus::Parameters>(
     conn: &rusqlite::Connection,
@@ -2641,17 +5314,18 @@ pub(crate) fn put_legacy_transparent_utxo<P: consensus::Parameters>(
         "INSERT INTO utxos (
             prevout_txid, prevout_idx,
             received_by_account_id, address, script,
-            value_zat, height)
+            value_zat, height, is_coinbase)
         VALUES
             (:prevout_txid, :prevout_idx,
             :received_by_account_id, :address, :script,
-            :value_zat, :height)
+            :value_zat, :height, :is_coinbase)
         ON CONFLICT (prevout_txid, prevout_idx) DO UPDATE
         SET received_by_account_id = :received_by_account_id,
             height = :height,
             address = :address,
             script = :script,
-            value_zat = :value_zat
+            value_zat = :value_zat,
+            is_coinbase = :is_coinbase
         RETURNING id",
     )?;
 
@@ -2663,9 +5337,36 @@ pub(crate) fn put_legacy_transparent_utxo<P: consensus::Parameters>(
         ":script": &output.txout().script_pubkey.0,
         ":value_zat": &i64::from(Amount::from(output.txout().value)),
         ":height": &u32::from(output.height()),
+        ":is_coinbase": output.is_coinbase(),
     ];
 
-    stmt_upsert_legacy_transparent_utxo.query_row(sql_args, |row| row.get::<_, i64>(0).map(UtxoId))
+    let utxo_id = stmt_upsert_legacy_transparent_utxo
+        .query_row(sql_args, |row| row.get::<_, i64>(0).map(UtxoId))?;
+
+    // If the UTXO was spent by a transaction that the wallet stored before the UTXO itself was
+    // known, record the spend now.
+    let spend_args = named_params![
+        ":utxo_id": utxo_id.0,
+        ":prevout_txid": &output.outpoint().hash().to_vec(),
+        ":prevout_idx": &output.outpoint().n(),
+    ];
+    conn.prepare_cached(
+        "INSERT INTO transparent_received_output_spends (transparent_received_output_id, transaction_id)
+         SELECT :utxo_id, transaction_id
+         FROM transparent_spend_map
+         WHERE prevout_txid = :prevout_txid
+         AND prevout_idx = :prevout_idx
+         ON CONFLICT (transparent_received_output_id, transaction_id) DO NOTHING",
+    )?
+    .execute(spend_args)?;
+    conn.prepare_cached(
+        "DELETE FROM transparent_spend_map
+         WHERE prevout_txid = :prevout_txid
+         AND prevout_idx = :prevout_idx",
+    )?
+    .execute(&spend_args[1..])?;
+
+    Ok(utxo_id)
 }
 
 // A utility function for creation of parameters for use in `insert_sent_output`
@@ -2951,13 +5652,15 @@ mod tests {
         AccountId,
     };
 
-    use super::account_birthday;
+    use super::{account_birthday, Address};
 
     #[cfg(feature = "transparent-inputs")]
     use {
         crate::PRUNING_DEPTH,
         zcash_client_backend::{
-            data_api::{wallet::input_selection::GreedyInputSelector, InputSource, WalletWrite},
+            data_api::{
+                wallet::input_selection::GreedyInputSelector, InputSource, SpendStatus, WalletWrite,
+            },
             encoding::AddressCodec,
             fees::{fixed, DustOutputPolicy},
             wallet::WalletTransparentOutput,
@@ -3003,6 +5706,39 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn find_account_for_transparent_output() {
+        use crate::testing::TestBuilder;
+        use zcash_primitives::legacy::TransparentAddress;
+
+        let st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        let account_id = st.test_account().unwrap().account_id();
+        let uaddr = st
+            .wallet()
+            .get_current_address(account_id)
+            .unwrap()
+            .unwrap();
+        let taddr = uaddr.transparent().unwrap();
+
+        assert_matches!(
+            st.wallet().find_account_for_transparent_output(&taddr.script()),
+            Ok(Some((id, _))) if id == account_id
+        );
+
+        // A script paying to an address that the wallet did not derive is not attributed
+        // to any account.
+        let foreign = TransparentAddress::PublicKeyHash([7; 20]);
+        assert_matches!(
+            st.wallet()
+                .find_account_for_transparent_output(&foreign.script()),
+            Ok(None)
+        );
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn put_received_transparent_utxo() {
@@ -3037,7 +5773,8 @@ mod tests {
 
         // Pretend the output's transaction was mined at `height_1`.
         let utxo =
-            WalletTransparentOutput::from_parts(outpoint.clone(), txout.clone(), height_1).unwrap();
+            WalletTransparentOutput::from_parts(outpoint.clone(), txout.clone(), height_1, false)
+                .unwrap();
         let res0 = st.wallet_mut().put_received_transparent_utxo(&utxo);
         assert_matches!(res0, Ok(_));
 
@@ -3058,7 +5795,7 @@ mod tests {
         // Change the mined height of the UTXO and upsert; we should get back
         // the same `UtxoId`.
         let height_2 = BlockHeight::from_u32(34567);
-        let utxo2 = WalletTransparentOutput::from_parts(outpoint, txout, height_2).unwrap();
+        let utxo2 = WalletTransparentOutput::from_parts(outpoint, txout, height_2, false).unwrap();
         let res1 = st.wallet_mut().put_received_transparent_utxo(&utxo2);
         assert_matches!(res1, Ok(id) if id == res0.unwrap());
 
@@ -3103,6 +5840,320 @@ mod tests {
         assert_matches!(res2, Err(_));
     }
 
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn watched_address_receives_funds() {
+        use zcash_client_backend::data_api::DecryptedTransaction;
+        use zcash_primitives::{
+            consensus::BranchId,
+            legacy::{Script, TransparentAddress},
+            transaction::{
+                components::transparent::{self, Authorized, TxIn},
+                TransactionData, TxVersion,
+            },
+        };
+
+        use crate::error::SqliteClientError;
+
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        let watched = TransparentAddress::PublicKeyHash([7; 20]);
+        let height_1 = BlockHeight::from_u32(12345);
+        let value = NonNegativeAmount::const_from_u64(100000);
+        let utxo = WalletTransparentOutput::from_parts(
+            OutPoint::new([1u8; 32], 1),
+            TxOut {
+                value,
+                script_pubkey: watched.script(),
+            },
+            height_1,
+            false,
+        )
+        .unwrap();
+
+        // An output received at an address that is neither the wallet's nor watched is
+        // rejected.
+        assert_matches!(
+            st.wallet_mut().put_received_transparent_utxo(&utxo),
+            Err(SqliteClientError::AddressNotRecognized(addr)) if addr == watched
+        );
+
+        // Once the address is watched, its received outputs are tracked, but cannot be spent
+        // by the wallet.
+        st.wallet_mut()
+            .add_watched_address(&Address::Transparent(watched))
+            .unwrap();
+        assert_matches!(st.wallet_mut().put_received_transparent_utxo(&utxo), Ok(_));
+        assert_matches!(
+            st.wallet().get_unspent_watched_outputs(&watched, height_1).as_deref(),
+            Ok([ret]) if (ret.outpoint(), ret.txout(), ret.height()) == (utxo.outpoint(), utxo.txout(), height_1)
+        );
+        assert!(st
+            .wallet()
+            .get_unspent_watched_outputs(&watched, height_1 - 1)
+            .unwrap()
+            .is_empty());
+        assert!(st
+            .wallet()
+            .get_unspent_transparent_outputs(&watched, height_1, &[])
+            .unwrap()
+            .is_empty());
+
+        // A mined transaction that spends the output and pays the watched address again
+        // replaces the spent output with the new one.
+        let change = NonNegativeAmount::const_from_u64(90000);
+        let tx = TransactionData::from_parts(
+            TxVersion::Sapling,
+            BranchId::Canopy,
+            0,
+            BlockHeight::from(0),
+            Some(transparent::Bundle {
+                vin: vec![TxIn {
+                    prevout: utxo.outpoint().clone(),
+                    script_sig: Script(vec![]),
+                    sequence: 0,
+                }],
+                vout: vec![TxOut {
+                    value: change,
+                    script_pubkey: watched.script(),
+                }],
+                authorization: Authorized,
+            }),
+            None,
+            None,
+            None,
+        )
+        .freeze()
+        .unwrap();
+        st.wallet_mut()
+            .store_decrypted_tx(DecryptedTransaction::new(
+                Some(height_1 + 1),
+                &tx,
+                vec![],
+                #[cfg(feature = "orchard")]
+                vec![],
+            ))
+            .unwrap();
+
+        let received = OutPoint::new(tx.txid().into(), 0);
+        assert_matches!(
+            st.wallet().get_unspent_watched_outputs(&watched, height_1 + 1).as_deref(),
+            Ok([ret]) if (ret.outpoint(), ret.txout().value, ret.height()) == (&received, change, height_1 + 1)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn scan_transparent_blocks() {
+        use std::{convert::Infallible, ops::Range};
+
+        use zcash_client_backend::data_api::chain::{
+            self, error::Error as ChainError, FullBlockSource,
+        };
+        use zcash_primitives::{
+            consensus::BranchId,
+            legacy::{Script, TransparentAddress},
+            transaction::{
+                components::transparent::{self, Authorized, TxIn},
+                Transaction, TransactionData, TxVersion,
+            },
+        };
+
+        struct TestFullBlockSource(Vec<(BlockHeight, Vec<Vec<u8>>)>);
+
+        impl FullBlockSource for TestFullBlockSource {
+            type Error = Infallible;
+
+            fn with_full_blocks<F, WalletErrT>(
+                &self,
+                range: Range<BlockHeight>,
+                mut with_block: F,
+            ) -> Result<(), ChainError<WalletErrT, Infallible>>
+            where
+                F: FnMut(
+                    BlockHeight,
+                    Vec<Transaction>,
+                ) -> Result<(), ChainError<WalletErrT, Infallible>>,
+            {
+                for (height, txs) in self.0.iter().filter(|(h, _)| range.contains(h)) {
+                    with_block(
+                        *height,
+                        txs.iter()
+                            .map(|raw| Transaction::read(&raw[..], BranchId::Canopy).unwrap())
+                            .collect(),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+
+        fn transparent_tx(vin: Vec<OutPoint>, vout: Vec<TxOut>) -> (Transaction, Vec<u8>) {
+            let tx = TransactionData::from_parts(
+                TxVersion::Sapling,
+                BranchId::Canopy,
+                0,
+                BlockHeight::from(0),
+                Some(transparent::Bundle {
+                    vin: vin
+                        .into_iter()
+                        .map(|prevout| TxIn {
+                            prevout,
+                            script_sig: Script(vec![]),
+                            sequence: 0,
+                        })
+                        .collect(),
+                    vout,
+                    authorization: Authorized,
+                }),
+                None,
+                None,
+                None,
+            )
+            .freeze()
+            .unwrap();
+            let mut raw = vec![];
+            tx.write(&mut raw).unwrap();
+            (tx, raw)
+        }
+
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        let account_id = st.test_account().unwrap().account_id();
+        let uaddr = st
+            .wallet()
+            .get_current_address(account_id)
+            .unwrap()
+            .unwrap();
+        let taddr = *uaddr.transparent().unwrap();
+        let foreign = TransparentAddress::PublicKeyHash([7; 20]);
+
+        // The first block pays to the wallet, and the second spends the received output.
+        let value = NonNegativeAmount::const_from_u64(100000);
+        let (tx1, raw1) = transparent_tx(
+            vec![OutPoint::new([1u8; 32], 1)],
+            vec![
+                TxOut {
+                    value: NonNegativeAmount::const_from_u64(5000),
+                    script_pubkey: foreign.script(),
+                },
+                TxOut {
+                    value,
+                    script_pubkey: taddr.script(),
+                },
+            ],
+        );
+        let received = OutPoint::new(tx1.txid().into(), 1);
+        let (tx2, raw2) = transparent_tx(
+            vec![received.clone()],
+            vec![TxOut {
+                value: NonNegativeAmount::const_from_u64(90000),
+                script_pubkey: foreign.script(),
+            }],
+        );
+
+        let height_1 = BlockHeight::from_u32(12345);
+        let source = TestFullBlockSource(vec![(height_1, vec![raw1]), (height_1 + 1, vec![raw2])]);
+
+        chain::scan_transparent_blocks(
+            &source,
+            st.wallet_mut(),
+            account_id,
+            height_1..height_1 + 1,
+        )
+        .unwrap();
+
+        // Only the output paying to the wallet's address has been stored.
+        assert_matches!(
+            st.wallet()
+                .get_unspent_transparent_outputs(&taddr, height_1, &[])
+                .as_deref(),
+            Ok([ret]) if (ret.outpoint(), ret.txout().value, ret.height()) == (&received, value, height_1)
+        );
+        assert_matches!(
+            st.wallet_mut()
+                .get_transparent_addresses_and_sync_heights()
+                .unwrap()
+                .iter()
+                .find(|info| info.address == taddr),
+            Some(info) if info.height == height_1
+        );
+
+        chain::scan_transparent_blocks(
+            &source,
+            st.wallet_mut(),
+            account_id,
+            height_1 + 1..height_1 + 2,
+        )
+        .unwrap();
+
+        // The transaction spending the received output has been recorded.
+        let spends: i64 = st
+            .wallet()
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM transparent_received_output_spends txo_spends
+                JOIN transactions ON transactions.id_tx = txo_spends.transaction_id
+                WHERE transactions.txid = ?",
+                [tx2.txid().as_ref()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(spends, 1);
+
+        // Both transactions have been stored with the heights at which they were mined.
+        assert_eq!(
+            st.wallet().get_tx_height(tx1.txid()).unwrap(),
+            Some(height_1)
+        );
+        assert_eq!(
+            st.wallet().get_tx_height(tx2.txid()).unwrap(),
+            Some(height_1 + 1)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn generated_block_with_taddr_output() {
+        use crate::testing::TestBuilder;
+
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        let account_id = st.test_account().unwrap().account_id();
+        let uaddr = st
+            .wallet()
+            .get_current_address(account_id)
+            .unwrap()
+            .unwrap();
+        let taddr = *uaddr.transparent().unwrap();
+
+        let value = NonNegativeAmount::const_from_u64(100000);
+        let (h, _, outpoint) = st.generate_next_block_with_taddr_output(value);
+        st.scan_cached_blocks(h, 1);
+
+        // The compact block does not reveal the transparent output.
+        assert_matches!(
+            st.wallet().get_unspent_transparent_output(&outpoint),
+            Ok(None)
+        );
+
+        st.scan_transparent_blocks(h, 1);
+        assert_matches!(
+            st.wallet().get_unspent_transparent_output(&outpoint),
+            Ok(Some(ret)) if (ret.txout().value, ret.height()) == (value, h)
+        );
+        assert_matches!(
+            st.wallet().get_transparent_balances(account_id, h),
+            Ok(balances) if balances.get(&taddr) == Some(&value)
+        );
+    }
+
     #[test]
     fn get_default_account_index() {
         use crate::testing::TestBuilder;
@@ -3202,7 +6253,22 @@ mod tests {
             );
         };
 
+        let check_status = |st: &TestState<_>, expected: Option<SpendStatus>| {
+            let outpoints = st
+                .wallet()
+                .get_transparent_outpoints(account.account_id())
+                .unwrap();
+            assert_eq!(
+                outpoints
+                    .iter()
+                    .map(|(_, _, status)| *status)
+                    .collect::<Vec<_>>(),
+                expected.into_iter().collect::<Vec<_>>(),
+            );
+        };
+
         // The wallet starts out with zero balance.
+        check_status(&st, None);
         check_balance(&st, 0, NonNegativeAmount::ZERO);
         check_balance(&st, 1, NonNegativeAmount::ZERO);
 
@@ -3216,11 +6282,18 @@ mod tests {
 
         // Pretend the output was received in the chain tip.
         let height = st.wallet().chain_height().unwrap().unwrap();
-        let utxo = WalletTransparentOutput::from_parts(outpoint, txout, height).unwrap();
+        let utxo = WalletTransparentOutput::from_parts(outpoint, txout, height, false).unwrap();
         st.wallet_mut()
             .put_received_transparent_utxo(&utxo)
             .unwrap();
 
+        // The wallet records the output as unspent.
+        check_status(&st, Some(SpendStatus::Unspent));
+        assert_matches!(
+            st.wallet().get_transparent_outpoints(account.account_id()).as_deref(),
+            Ok([(outpoint, output, _)]) if outpoint == utxo.outpoint() && output.txout() == utxo.txout()
+        );
+
         // The wallet should detect the balance as having 1 confirmation.
         check_balance(&st, 0, value);
         check_balance(&st, 1, value);
@@ -3236,9 +6309,12 @@ mod tests {
             DustOutputPolicy::default(),
         );
         let txid = st
-            .shield_transparent_funds(&input_selector, value, account.usk(), &[*taddr], 1)
+            .shield_transparent_funds(&input_selector, value, account.usk(), &[*taddr], 1, None)
             .unwrap()[0];
 
+        // The output is spent by the shielding transaction, which has not been mined.
+        check_status(&st, Some(SpendStatus::PendingSpend(txid)));
+
         // The wallet should have zero transparent balance, because the shielding
         // transaction can be mined.
         check_balance(&st, 0, NonNegativeAmount::ZERO);
@@ -3253,6 +6329,7 @@ mod tests {
         check_balance(&st, 0, NonNegativeAmount::ZERO);
         check_balance(&st, 1, NonNegativeAmount::ZERO);
         check_balance(&st, 2, NonNegativeAmount::ZERO);
+        check_status(&st, Some(SpendStatus::Spent(txid)));
 
         // Unmine the shielding transaction via a reorg.
         st.wallet_mut()
@@ -3264,6 +6341,7 @@ mod tests {
         check_balance(&st, 0, NonNegativeAmount::ZERO);
         check_balance(&st, 1, NonNegativeAmount::ZERO);
         check_balance(&st, 2, NonNegativeAmount::ZERO);
+        check_status(&st, Some(SpendStatus::PendingSpend(txid)));
 
         // Expire the shielding transaction.
         let expiry_height = st
@@ -3280,6 +6358,7 @@ mod tests {
         check_balance(&st, 0, NonNegativeAmount::ZERO);
         check_balance(&st, 1, NonNegativeAmount::ZERO);
         check_balance(&st, 2, NonNegativeAmount::ZERO);
+        check_status(&st, Some(SpendStatus::PendingSpend(txid)));
 
         // Roll forward the chain tip until the transaction's expiry height is in the
         // stable block range (so a reorg won't make it spendable again).
@@ -3291,6 +6370,7 @@ mod tests {
         check_balance(&st, 0, value);
         check_balance(&st, 1, value);
         check_balance(&st, 2, value);
+        check_status(&st, Some(SpendStatus::Unspent));
     }
 
     #[test]
@@ -3350,6 +6430,175 @@ mod tests {
         assert_eq!(block_fully_scanned(&st), Some(end_height));
     }
 
+    #[test]
+    fn get_account_balances() {
+        use secrecy::Secret;
+        use zcash_client_backend::data_api::{AccountBirthday, WalletWrite};
+
+        let mut st = TestBuilder::new().with_block_cache().build();
+
+        // The wallet has no balance data until the chain tip is known.
+        assert!(st.wallet().get_account_balances(1).unwrap().is_empty());
+
+        let seed = Secret::new([0u8; 32].to_vec());
+        let birthday = AccountBirthday::from_sapling_activation(&st.network(), BlockHash([0; 32]));
+        let (account0, usk0) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+        let (account1, usk1) = st.wallet_mut().create_account(&seed, &birthday).unwrap();
+        let dfvk0 = usk0.sapling().to_diversifiable_full_viewing_key();
+        let dfvk1 = usk1.sapling().to_diversifiable_full_viewing_key();
+
+        let value0 = NonNegativeAmount::const_from_u64(50000);
+        let value1 = NonNegativeAmount::const_from_u64(70000);
+        let (h, _, _) = st.generate_next_block(&dfvk0, AddressType::DefaultExternal, value0);
+        st.generate_next_block(&dfvk1, AddressType::DefaultExternal, value1);
+        st.scan_cached_blocks(h, 2);
+
+        // The balances of all accounts are computed at once, and match those of the summary.
+        for min_confirmations in [0, 1, 2] {
+            let balances = st.wallet().get_account_balances(min_confirmations).unwrap();
+            let summary = st.get_wallet_summary(min_confirmations).unwrap();
+            assert_eq!(&balances, summary.account_balances());
+        }
+
+        let balances = st.wallet().get_account_balances(1).unwrap();
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[&account0].total(), value0);
+        assert_eq!(balances[&account1].total(), value1);
+    }
+
+    #[test]
+    fn get_reused_addresses() {
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account_id = st.test_account().unwrap().account_id();
+        let dfvk = st.test_account_sapling().unwrap();
+        let uaddr = st
+            .wallet()
+            .get_current_address(account_id)
+            .unwrap()
+            .unwrap();
+
+        // A single receipt at an address is not reuse.
+        let value = NonNegativeAmount::const_from_u64(50000);
+        let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+        st.scan_cached_blocks(h, 1);
+        assert_eq!(
+            st.wallet().get_reused_addresses(account_id).unwrap(),
+            vec![]
+        );
+
+        // Receipts in two distinct transactions at the same diversifier index are reuse.
+        let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+        st.scan_cached_blocks(h, 1);
+        assert_eq!(
+            st.wallet().get_reused_addresses(account_id).unwrap(),
+            vec![(Address::Unified(uaddr.clone()), 2)]
+        );
+
+        // Transparent receipts are reported per transparent address.
+        #[cfg(feature = "transparent-inputs")]
+        {
+            let taddr = *uaddr.transparent().unwrap();
+            for txid in [[1u8; 32], [2u8; 32]] {
+                let utxo = WalletTransparentOutput::from_parts(
+                    OutPoint::new(txid, 0),
+                    TxOut {
+                        value,
+                        script_pubkey: taddr.script(),
+                    },
+                    h,
+                    false,
+                )
+                .unwrap();
+                st.wallet_mut()
+                    .put_received_transparent_utxo(&utxo)
+                    .unwrap();
+            }
+
+            assert_eq!(
+                st.wallet().get_reused_addresses(account_id).unwrap(),
+                vec![
+                    (Address::Unified(uaddr), 2),
+                    (Address::Transparent(taddr), 2)
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn get_transaction_time() {
+        use zcash_primitives::transaction::TxId;
+
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let dfvk = st.test_account_sapling().unwrap();
+
+        // An unknown transaction has no time.
+        assert_eq!(
+            st.wallet()
+                .get_transaction_time(TxId::from_bytes([0; 32]))
+                .unwrap(),
+            None
+        );
+
+        let value = NonNegativeAmount::const_from_u64(50000);
+        let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+        st.scan_cached_blocks(h, 1);
+
+        let txid = st
+            .wallet()
+            .conn
+            .query_row(
+                "SELECT txid FROM transactions WHERE block = ?",
+                [u32::from(h)],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .map(|bytes| TxId::from_bytes(bytes.try_into().unwrap()))
+            .unwrap();
+
+        // The time of the block in which the transaction was mined is returned.
+        st.wallet()
+            .conn
+            .execute(
+                "UPDATE blocks SET time = 1700000000 WHERE height = ?",
+                [u32::from(h)],
+            )
+            .unwrap();
+        assert_eq!(
+            st.wallet().get_transaction_time(txid).unwrap(),
+            Some(1700000000)
+        );
+    }
+
+    #[test]
+    fn rescan_required() {
+        use zcash_client_backend::data_api::WalletWrite;
+
+        let mut st = TestBuilder::new()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        // No rescan is required for a fresh wallet, and clearing the flag has no effect.
+        assert_eq!(st.wallet().rescan_required().unwrap(), None);
+        st.wallet_mut().clear_rescan_flag().unwrap();
+        assert_eq!(st.wallet().rescan_required().unwrap(), None);
+
+        // The earliest height at which an inconsistency was detected is retained.
+        let h = st.sapling_activation_height();
+        super::set_rescan_required(&st.wallet().conn, h + 5).unwrap();
+        assert_eq!(st.wallet().rescan_required().unwrap(), Some(h + 5));
+        super::set_rescan_required(&st.wallet().conn, h).unwrap();
+        super::set_rescan_required(&st.wallet().conn, h + 10).unwrap();
+        assert_eq!(st.wallet().rescan_required().unwrap(), Some(h));
+
+        st.wallet_mut().clear_rescan_flag().unwrap();
+        assert_eq!(st.wallet().rescan_required().unwrap(), None);
+    }
+
     #[test]
     fn test_account_birthday() {
         let st = TestBuilder::new()
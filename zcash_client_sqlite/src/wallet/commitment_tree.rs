@@ -13,7 +13,7 @@ use zcash_client_backend::data_api::chain::CommitmentTreeRoot;
 use incrementalmerkletree::{Address, Hashable, Level, Position, Retention};
 use shardtree::{
     error::ShardTreeError,
-    store::{Checkpoint, ShardStore, TreeState},
+    store::{memory::MemoryShardStore, Checkpoint, ShardStore, TreeState},
     LocatedPrunableTree, LocatedTree, PrunableTree, RetentionFlags,
 };
 
@@ -211,6 +211,127 @@ impl<'conn, 'a: 'conn, H: HashSer, const SHARD_HEIGHT: u8> ShardStore
     }
 }
 
+/// A shard store over a borrowed connection, for use when the connection may already have a
+/// transaction open (as is the case for a [`WalletDb::snapshot`]). Each write is performed in its
+/// own transaction, and so will fail if a transaction is already open on the connection.
+///
+/// [`WalletDb::snapshot`]: crate::WalletDb::snapshot
+impl<'a, H: HashSer, const SHARD_HEIGHT: u8> ShardStore
+    for SqliteShardStore<&'a rusqlite::Connection, H, SHARD_HEIGHT>
+{
+    type H = H;
+    type CheckpointId = BlockHeight;
+    type Error = Error;
+
+    fn get_shard(
+        &self,
+        shard_root: Address,
+    ) -> Result<Option<LocatedPrunableTree<Self::H>>, Self::Error> {
+        get_shard(self.conn, self.table_prefix, shard_root)
+    }
+
+    fn last_shard(&self) -> Result<Option<LocatedPrunableTree<Self::H>>, Self::Error> {
+        last_shard(self.conn, self.table_prefix, Self::SHARD_ROOT_LEVEL)
+    }
+
+    fn put_shard(&mut self, subtree: LocatedPrunableTree<Self::H>) -> Result<(), Self::Error> {
+        let tx = self.conn.unchecked_transaction().map_err(Error::Query)?;
+        put_shard(&tx, self.table_prefix, subtree)?;
+        tx.commit().map_err(Error::Query)
+    }
+
+    fn get_shard_roots(&self) -> Result<Vec<Address>, Self::Error> {
+        get_shard_roots(self.conn, self.table_prefix, Self::SHARD_ROOT_LEVEL)
+    }
+
+    fn truncate(&mut self, from: Address) -> Result<(), Self::Error> {
+        truncate(self.conn, self.table_prefix, from)
+    }
+
+    fn get_cap(&self) -> Result<PrunableTree<Self::H>, Self::Error> {
+        get_cap(self.conn, self.table_prefix)
+    }
+
+    fn put_cap(&mut self, cap: PrunableTree<Self::H>) -> Result<(), Self::Error> {
+        put_cap(self.conn, self.table_prefix, cap)
+    }
+
+    fn min_checkpoint_id(&self) -> Result<Option<Self::CheckpointId>, Self::Error> {
+        min_checkpoint_id(self.conn, self.table_prefix)
+    }
+
+    fn max_checkpoint_id(&self) -> Result<Option<Self::CheckpointId>, Self::Error> {
+        max_checkpoint_id(self.conn, self.table_prefix)
+    }
+
+    fn add_checkpoint(
+        &mut self,
+        checkpoint_id: Self::CheckpointId,
+        checkpoint: Checkpoint,
+    ) -> Result<(), Self::Error> {
+        let tx = self.conn.unchecked_transaction().map_err(Error::Query)?;
+        add_checkpoint(&tx, self.table_prefix, checkpoint_id, checkpoint)?;
+        tx.commit().map_err(Error::Query)
+    }
+
+    fn checkpoint_count(&self) -> Result<usize, Self::Error> {
+        checkpoint_count(self.conn, self.table_prefix)
+    }
+
+    fn get_checkpoint_at_depth(
+        &self,
+        checkpoint_depth: usize,
+    ) -> Result<Option<(Self::CheckpointId, Checkpoint)>, Self::Error> {
+        get_checkpoint_at_depth(self.conn, self.table_prefix, checkpoint_depth)
+            .map_err(Error::Query)
+    }
+
+    fn get_checkpoint(
+        &self,
+        checkpoint_id: &Self::CheckpointId,
+    ) -> Result<Option<Checkpoint>, Self::Error> {
+        get_checkpoint(self.conn, self.table_prefix, *checkpoint_id)
+    }
+
+    fn with_checkpoints<F>(&mut self, limit: usize, callback: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(&Self::CheckpointId, &Checkpoint) -> Result<(), Self::Error>,
+    {
+        let tx = self.conn.unchecked_transaction().map_err(Error::Query)?;
+        with_checkpoints(&tx, self.table_prefix, limit, callback)?;
+        tx.commit().map_err(Error::Query)
+    }
+
+    fn update_checkpoint_with<F>(
+        &mut self,
+        checkpoint_id: &Self::CheckpointId,
+        update: F,
+    ) -> Result<bool, Self::Error>
+    where
+        F: Fn(&mut Checkpoint) -> Result<(), Self::Error>,
+    {
+        let tx = self.conn.unchecked_transaction().map_err(Error::Query)?;
+        let result = update_checkpoint_with(&tx, self.table_prefix, *checkpoint_id, update)?;
+        tx.commit().map_err(Error::Query)?;
+        Ok(result)
+    }
+
+    fn remove_checkpoint(&mut self, checkpoint_id: &Self::CheckpointId) -> Result<(), Self::Error> {
+        let tx = self.conn.unchecked_transaction().map_err(Error::Query)?;
+        remove_checkpoint(&tx, self.table_prefix, *checkpoint_id)?;
+        tx.commit().map_err(Error::Query)
+    }
+
+    fn truncate_checkpoints(
+        &mut self,
+        checkpoint_id: &Self::CheckpointId,
+    ) -> Result<(), Self::Error> {
+        let tx = self.conn.unchecked_transaction().map_err(Error::Query)?;
+        truncate_checkpoints(&tx, self.table_prefix, *checkpoint_id)?;
+        tx.commit().map_err(Error::Query)
+    }
+}
+
 impl<H: HashSer, const SHARD_HEIGHT: u8> ShardStore
     for SqliteShardStore<rusqlite::Connection, H, SHARD_HEIGHT>
 {
@@ -952,6 +1073,92 @@ pub(crate) fn truncate_checkpoints(
     Ok(())
 }
 
+/// Replaces the contents of the note commitment tree stored in the tables having the given
+/// prefix with the shards, cap, and checkpoints of `new_tree`.
+///
+/// The subtree end heights recorded for shards that are present in both the existing and the
+/// new tree are retained, as these describe the chain rather than the tree's contents. The end
+/// heights of complete shards that have no recorded end height are recovered from the note
+/// commitment tree sizes of the blocks known to the wallet.
+pub(crate) fn replace_tree<H: HashSer + Clone>(
+    conn: &rusqlite::Transaction<'_>,
+    table_prefix: &'static str,
+    shard_root_level: Level,
+    mut new_tree: MemoryShardStore<H, BlockHeight>,
+) -> Result<(), Error> {
+    let new_shard_roots = new_tree.get_shard_roots().unwrap_or_else(|e| match e {});
+    let new_shard_indices = new_shard_roots
+        .iter()
+        .map(|addr| addr.index())
+        .collect::<BTreeSet<_>>();
+
+    // Shards may change position in the rebuilt tree, so clear the existing root hashes to
+    // avoid violating their uniqueness constraint while the new shards are inserted.
+    conn.execute(
+        &format!("UPDATE {}_tree_shards SET root_hash = NULL", table_prefix),
+        [],
+    )
+    .map_err(Error::Query)?;
+    for addr in get_shard_roots(conn, table_prefix, shard_root_level)? {
+        if !new_shard_indices.contains(&addr.index()) {
+            conn.execute(
+                &format!(
+                    "DELETE FROM {}_tree_shards WHERE shard_index = ?",
+                    table_prefix
+                ),
+                [addr.index()],
+            )
+            .map_err(Error::Query)?;
+        }
+    }
+    for addr in new_shard_roots {
+        if let Some(shard) = new_tree.get_shard(addr).unwrap_or_else(|e| match e {}) {
+            put_shard(conn, table_prefix, shard)?;
+        }
+    }
+
+    // A shard ends in the first block at which the tree size covers all of its leaves; if no
+    // such block is known, the end height remains unset as it would be after scanning.
+    conn.execute(
+        &format!(
+            "UPDATE {table_prefix}_tree_shards
+            SET subtree_end_height = (
+                SELECT MIN(height) FROM blocks
+                WHERE {table_prefix}_commitment_tree_size
+                    >= (({table_prefix}_tree_shards.shard_index + 1) << :shard_height)
+            )
+            WHERE subtree_end_height IS NULL"
+        ),
+        named_params![":shard_height": u8::from(shard_root_level)],
+    )
+    .map_err(Error::Query)?;
+
+    put_cap(
+        conn,
+        table_prefix,
+        new_tree.get_cap().unwrap_or_else(|e| match e {}),
+    )?;
+
+    // The cascading delete removes the marks removed at each checkpoint.
+    conn.execute(
+        &format!("DELETE FROM {}_tree_checkpoints", table_prefix),
+        [],
+    )
+    .map_err(Error::Query)?;
+    let mut checkpoints = vec![];
+    new_tree
+        .with_checkpoints(usize::MAX, |checkpoint_id, checkpoint| {
+            checkpoints.push((*checkpoint_id, checkpoint.clone()));
+            Ok(())
+        })
+        .unwrap_or_else(|e| match e {});
+    for (checkpoint_id, checkpoint) in checkpoints {
+        add_checkpoint(conn, table_prefix, checkpoint_id, checkpoint)?;
+    }
+
+    Ok(())
+}
+
 #[tracing::instrument(skip(conn, roots))]
 pub(crate) fn put_shard_roots<
     H: Hashable + HashSer + Clone + Eq,
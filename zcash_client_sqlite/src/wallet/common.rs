@@ -7,7 +7,7 @@ use zcash_client_backend::{wallet::ReceivedNote, ShieldedProtocol};
 use zcash_primitives::transaction::{components::amount::NonNegativeAmount, TxId};
 use zcash_protocol::consensus::{self, BlockHeight};
 
-use super::wallet_birthday;
+use super::{scan_queue_extrema, wallet_birthday};
 use crate::{error::SqliteClientError, AccountId, ReceivedNoteId, SAPLING_TABLES_PREFIX};
 
 #[cfg(feature = "orchard")]
@@ -25,6 +25,20 @@ fn per_protocol_names(protocol: ShieldedProtocol) -> (&'static str, &'static str
     }
 }
 
+/// Returns a subquery selecting the identifiers of the received notes in the given pool that are
+/// spent by a transaction that is mined, that will not expire, or that has not expired as of
+/// `:anchor_height`.
+fn spent_note_ids(table_prefix: &'static str) -> String {
+    format!(
+        "SELECT {table_prefix}_received_note_id
+         FROM {table_prefix}_received_note_spends
+         JOIN transactions stx ON stx.id_tx = transaction_id
+         WHERE stx.block IS NOT NULL -- the spending tx is mined
+         OR stx.expiry_height IS NULL -- the spending tx will not expire
+         OR stx.expiry_height > :anchor_height -- the spending tx is unexpired"
+    )
+}
+
 fn unscanned_tip_exists(
     conn: &Connection,
     anchor_height: BlockHeight,
@@ -102,6 +116,121 @@ where
     }
 }
 
+/// Invokes the provided callback for each unspent note belonging to the specified account for
+/// which a note commitment tree position is known, in order of increasing position.
+///
+/// Notes are treated as spent under the same rules as [`select_spendable_notes`], taking the
+/// current chain tip as the anchor height. Notes are read from the database one row at a time
+/// using a single prepared statement, so that the full set of notes is never held in memory at
+/// once.
+pub(crate) fn for_each_unspent_note<P: consensus::Parameters, F, Note, G, E>(
+    conn: &Connection,
+    params: &P,
+    account: AccountId,
+    protocol: ShieldedProtocol,
+    to_spendable_note: F,
+    mut callback: G,
+) -> Result<(), E>
+where
+    F: Fn(&P, &Row) -> Result<Option<ReceivedNote<ReceivedNoteId, Note>>, SqliteClientError>,
+    G: FnMut(ReceivedNote<ReceivedNoteId, Note>) -> Result<(), E>,
+    E: From<SqliteClientError>,
+{
+    let (table_prefix, index_col, note_reconstruction_cols) = per_protocol_names(protocol);
+    let spent_note_ids = spent_note_ids(table_prefix);
+    let chain_tip_height = scan_queue_extrema(conn)
+        .map_err(SqliteClientError::from)?
+        .map_or(BlockHeight::from(0), |range| *range.end());
+
+    let mut stmt_select_notes = conn
+        .prepare_cached(&format!(
+            "SELECT rn.id, txid, {index_col},
+                diversifier, value, {note_reconstruction_cols}, commitment_tree_position,
+                accounts.ufvk, recipient_key_scope
+             FROM {table_prefix}_received_notes rn
+             INNER JOIN accounts ON accounts.id = rn.account_id
+             INNER JOIN transactions ON transactions.id_tx = rn.tx
+             WHERE rn.account_id = :account
+             AND transactions.block IS NOT NULL
+             AND accounts.ufvk IS NOT NULL
+             AND recipient_key_scope IS NOT NULL
+             AND nf IS NOT NULL
+             AND commitment_tree_position IS NOT NULL
+             AND rn.id NOT IN ({spent_note_ids})
+             ORDER BY commitment_tree_position"
+        ))
+        .map_err(SqliteClientError::from)?;
+
+    let mut rows = stmt_select_notes
+        .query(named_params![
+            ":account": account.0,
+            ":anchor_height": u32::from(chain_tip_height),
+        ])
+        .map_err(SqliteClientError::from)?;
+
+    while let Some(row) = rows.next().map_err(SqliteClientError::from)? {
+        if let Some(note) = to_spendable_note(params, row)? {
+            callback(note)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the note commitment, value, and mined height of each unspent note belonging to the
+/// specified account for which a note commitment tree position is known.
+///
+/// A note is treated as spent if it is spent by a mined transaction, or by a transaction that
+/// will not expire.
+pub(crate) fn get_unspent_note_commitments<P: consensus::Parameters, F, Note, G>(
+    conn: &Connection,
+    params: &P,
+    account: AccountId,
+    protocol: ShieldedProtocol,
+    to_spendable_note: F,
+    note_commitment: G,
+) -> Result<Vec<([u8; 32], u64, BlockHeight)>, SqliteClientError>
+where
+    F: Fn(&P, &Row) -> Result<Option<ReceivedNote<ReceivedNoteId, Note>>, SqliteClientError>,
+    G: Fn(&Note) -> [u8; 32],
+{
+    let (table_prefix, index_col, note_reconstruction_cols) = per_protocol_names(protocol);
+    let mut stmt_select_notes = conn.prepare_cached(&format!(
+        "SELECT rn.id, txid, {index_col},
+            diversifier, value, {note_reconstruction_cols}, commitment_tree_position,
+            accounts.ufvk, recipient_key_scope, transactions.block AS mined_height
+         FROM {table_prefix}_received_notes rn
+         INNER JOIN accounts ON accounts.id = rn.account_id
+         INNER JOIN transactions ON transactions.id_tx = rn.tx
+         WHERE rn.account_id = :account
+         AND transactions.block IS NOT NULL
+         AND accounts.ufvk IS NOT NULL
+         AND recipient_key_scope IS NOT NULL
+         AND commitment_tree_position IS NOT NULL
+         AND rn.id NOT IN (
+           SELECT {table_prefix}_received_note_id
+           FROM {table_prefix}_received_note_spends
+           JOIN transactions stx ON stx.id_tx = transaction_id
+           WHERE stx.block IS NOT NULL -- the spending tx is mined
+           OR stx.expiry_height IS NULL -- the spending tx will not expire
+         )"
+    ))?;
+
+    let mut rows = stmt_select_notes.query(named_params![":account": account.0])?;
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        if let Some(note) = to_spendable_note(params, row)? {
+            let value = u64::try_from(row.get::<_, i64>("value")?).map_err(|_| {
+                SqliteClientError::CorruptedData("Note values must be nonnegative".to_string())
+            })?;
+            let mined_height = BlockHeight::from(row.get::<_, u32>("mined_height")?);
+            result.push((note_commitment(note.note()), value, mined_height));
+        }
+    }
+
+    Ok(result)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn select_spendable_notes<P: consensus::Parameters, F, Note>(
     conn: &Connection,
@@ -129,6 +258,7 @@ where
     if unscanned_tip_exists(conn, anchor_height, table_prefix)? {
         return Ok(vec![]);
     }
+    let spent_note_ids = spent_note_ids(table_prefix);
 
     // The goal of this SQL statement is to select the oldest notes until the required
     // value has been reached.
@@ -165,14 +295,7 @@ where
                  AND commitment_tree_position IS NOT NULL
                  AND transactions.block <= :anchor_height
                  AND {table_prefix}_received_notes.id NOT IN rarray(:exclude)
-                 AND {table_prefix}_received_notes.id NOT IN (
-                   SELECT {table_prefix}_received_note_id
-                   FROM {table_prefix}_received_note_spends
-                   JOIN transactions stx ON stx.id_tx = transaction_id
-                   WHERE stx.block IS NOT NULL -- the spending tx is mined
-                   OR stx.expiry_height IS NULL -- the spending tx will not expire
-                   OR stx.expiry_height > :anchor_height -- the spending tx is unexpired
-                 )
+                 AND {table_prefix}_received_notes.id NOT IN ({spent_note_ids})
                  AND NOT EXISTS (
                     SELECT 1 FROM v_{table_prefix}_shard_unscanned_ranges unscanned
                     -- select all the unscanned ranges involving the shard containing this note
@@ -403,6 +403,7 @@ mod tests {
                 diversifier_index_be BLOB NOT NULL,
                 address TEXT NOT NULL,
                 cached_transparent_receiver_address TEXT,
+                is_auto_discovered INTEGER NOT NULL DEFAULT 0,
                 FOREIGN KEY (account_id) REFERENCES accounts(id),
                 CONSTRAINT diversification UNIQUE (account_id, diversifier_index_be)
             )"#,
@@ -452,6 +453,7 @@ mod tests {
                 memo BLOB,
                 commitment_tree_position INTEGER,
                 recipient_key_scope INTEGER,
+                is_locked INTEGER NOT NULL DEFAULT 0,
                 FOREIGN KEY (tx) REFERENCES transactions(id_tx),
                 FOREIGN KEY (account_id) REFERENCES accounts(id),
                 CONSTRAINT tx_output UNIQUE (tx, action_index)
@@ -481,6 +483,10 @@ mod tests {
                 contains_marked INTEGER,
                 CONSTRAINT root_unique UNIQUE (root_hash)
             )",
+            "CREATE TABLE rescan_required (
+                id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+                from_height INTEGER NOT NULL
+            )",
             "CREATE TABLE sapling_received_note_spends (
                 sapling_received_note_id INTEGER NOT NULL,
                 transaction_id INTEGER NOT NULL,
@@ -505,6 +511,7 @@ mod tests {
                 memo BLOB,
                 commitment_tree_position INTEGER,
                 recipient_key_scope INTEGER,
+                is_locked INTEGER NOT NULL DEFAULT 0,
                 FOREIGN KEY (tx) REFERENCES transactions(id_tx),
                 FOREIGN KEY (account_id) REFERENCES accounts(id),
                 CONSTRAINT tx_output UNIQUE (tx, output_index)
@@ -576,6 +583,7 @@ mod tests {
                 expiry_height INTEGER,
                 raw BLOB,
                 fee INTEGER,
+                mined_height INTEGER,
                 FOREIGN KEY (block) REFERENCES blocks(height)
             )",
             "CREATE TABLE transparent_received_output_spends (
@@ -589,12 +597,30 @@ mod tests {
                     REFERENCES transactions(id_tx),
                 UNIQUE (transparent_received_output_id, transaction_id)
             )",
+            "CREATE TABLE transparent_spend_map (
+                transaction_id INTEGER NOT NULL,
+                prevout_txid BLOB NOT NULL,
+                prevout_idx INTEGER NOT NULL,
+                FOREIGN KEY (transaction_id) REFERENCES transactions(id_tx),
+                UNIQUE (transaction_id, prevout_txid, prevout_idx)
+            )",
+            "CREATE TABLE tx_annotations (
+                txid BLOB NOT NULL PRIMARY KEY,
+                date TEXT,
+                label TEXT,
+                category TEXT,
+                note TEXT
+            )",
             "CREATE TABLE tx_locator_map (
                 block_height INTEGER NOT NULL,
                 tx_index INTEGER NOT NULL,
                 txid BLOB NOT NULL UNIQUE,
                 PRIMARY KEY (block_height, tx_index)
             )",
+            "CREATE TABLE tx_rebroadcast_candidates (
+                transaction_id INTEGER NOT NULL PRIMARY KEY,
+                FOREIGN KEY (transaction_id) REFERENCES transactions(id_tx) ON DELETE CASCADE
+            )",
             r#"CREATE TABLE "utxos" (
                 id INTEGER PRIMARY KEY,
                 received_by_account_id INTEGER NOT NULL,
@@ -604,9 +630,30 @@ mod tests {
                 script BLOB NOT NULL,
                 value_zat INTEGER NOT NULL,
                 height INTEGER NOT NULL,
+                is_coinbase INTEGER NOT NULL DEFAULT 0,
                 FOREIGN KEY (received_by_account_id) REFERENCES accounts(id),
                 CONSTRAINT tx_outpoint UNIQUE (prevout_txid, prevout_idx)
             )"#,
+            "CREATE TABLE watched_address_utxos (
+                id INTEGER NOT NULL PRIMARY KEY,
+                watched_address_id INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                prevout_txid BLOB NOT NULL,
+                prevout_idx INTEGER NOT NULL,
+                script BLOB NOT NULL,
+                value_zat INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                is_coinbase INTEGER NOT NULL DEFAULT 0,
+                spent_in_tx INTEGER,
+                FOREIGN KEY (watched_address_id) REFERENCES watched_addresses(id),
+                FOREIGN KEY (spent_in_tx) REFERENCES transactions(id_tx),
+                CONSTRAINT watched_outpoint UNIQUE (prevout_txid, prevout_idx)
+            )",
+            "CREATE TABLE watched_addresses (
+                id INTEGER NOT NULL PRIMARY KEY,
+                address TEXT NOT NULL UNIQUE,
+                cached_transparent_receiver_address TEXT
+            )",
         ];
 
         let mut tables_query = st
@@ -2,19 +2,27 @@ mod add_account_birthdays;
 mod add_transaction_views;
 mod add_transparent_sync_tracking;
 mod add_utxo_account;
+mod addresses_auto_discovered;
 mod addresses_table;
 mod ensure_orchard_ua_receiver;
 mod full_account_ids;
 mod initial_setup;
+mod note_locks;
 mod nullifier_map;
 mod orchard_received_notes;
 mod orchard_shardtree;
 mod received_notes_nullable_nf;
 mod receiving_key_scopes;
+mod rescan_required;
 mod sapling_memo_consistency;
 mod sent_notes_to_internal;
 mod shardtree_support;
+mod transparent_spend_map;
+mod tx_annotations;
+mod tx_mined_height;
+mod tx_rebroadcast_candidates;
 mod ufvk_support;
+mod utxos_coinbase;
 mod utxos_table;
 mod v_sapling_shard_unscanned_ranges;
 mod v_transactions_net;
@@ -23,6 +31,8 @@ mod v_transactions_shielding_balance;
 mod v_transactions_transparent_history;
 mod v_tx_outputs_use_legacy_false;
 mod wallet_summaries;
+mod watched_address_utxos;
+mod watched_addresses;
 
 use std::rc::Rc;
 
@@ -66,6 +76,26 @@ pub(super) fn all_migrations<P: consensus::Parameters + 'static>(
     //                                             orchard_received_notes
     //                                                       |
     //                                           ensure_orchard_ua_receiver
+    //                                                       |
+    //                                                tx_annotations
+    //                                                       |
+    //                                               watched_addresses
+    //                                                       |
+    //                                                utxos_coinbase
+    //                                                       |
+    //                                                rescan_required
+    //                                                       |
+    //                                           tx_rebroadcast_candidates
+    //                                                       |
+    //                                             transparent_spend_map
+    //                                                       |
+    //                                           addresses_auto_discovered
+    //                                                       |
+    //                                                  note_locks
+    //                                                       |
+    //                                                tx_mined_height
+    //                                                       |
+    //                                             watched_address_utxos
     vec![
         Box::new(initial_setup::Migration {}),
         Box::new(utxos_table::Migration {}),
@@ -116,5 +146,15 @@ pub(super) fn all_migrations<P: consensus::Parameters + 'static>(
         Box::new(ensure_orchard_ua_receiver::Migration {
             params: params.clone(),
         }),
+        Box::new(tx_annotations::Migration),
+        Box::new(watched_addresses::Migration),
+        Box::new(utxos_coinbase::Migration),
+        Box::new(rescan_required::Migration),
+        Box::new(tx_rebroadcast_candidates::Migration),
+        Box::new(transparent_spend_map::Migration),
+        Box::new(addresses_auto_discovered::Migration),
+        Box::new(note_locks::Migration),
+        Box::new(tx_mined_height::Migration),
+        Box::new(watched_address_utxos::Migration),
     ]
 }
@@ -0,0 +1,48 @@
+//! This migration adds a column to the `addresses` table recording whether each address was
+//! added to the wallet because a note was received at it during scanning, rather than because
+//! the wallet generated it.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::transparent_spend_map;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x8e8428cd_9ae6_42bf_bd0d_da41c9fa89ff);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [transparent_spend_map::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a column to the addresses table recording whether each address was discovered by scanning."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        // All existing addresses were either generated by the wallet or explicitly added by the
+        // caller, so none of them are treated as having been discovered by scanning.
+        transaction.execute_batch(
+            "ALTER TABLE addresses ADD COLUMN is_auto_discovered INTEGER NOT NULL DEFAULT 0;",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("ALTER TABLE addresses DROP COLUMN is_auto_discovered;")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+//! This migration adds a column to the received notes tables recording whether each note has
+//! been locked by the user against being selected as an input to new transactions.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::addresses_auto_discovered;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x3f6c2a1e_7b54_4d0e_9a8c_52e1d7b40c96);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [addresses_auto_discovered::MIGRATION_ID]
+            .into_iter()
+            .collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a column to the received notes tables recording whether each note is locked."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch(
+            "ALTER TABLE sapling_received_notes ADD COLUMN is_locked INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE orchard_received_notes ADD COLUMN is_locked INTEGER NOT NULL DEFAULT 0;",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch(
+            "ALTER TABLE sapling_received_notes DROP COLUMN is_locked;
+            ALTER TABLE orchard_received_notes DROP COLUMN is_locked;",
+        )?;
+        Ok(())
+    }
+}
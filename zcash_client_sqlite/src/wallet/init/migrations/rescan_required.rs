@@ -0,0 +1,50 @@
+//! This migration adds a table recording that the wallet must be rescanned from a given height,
+//! so that an inconsistency detected during scanning is not forgotten when the wallet is
+//! restarted.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::utxos_coinbase;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x3f1d8a27_95c4_4b0e_a6d2_c84e07b1f953);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [utxos_coinbase::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a table recording the height from which the wallet must be rescanned."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        // The table holds at most a single row.
+        transaction.execute_batch(
+            "CREATE TABLE rescan_required (
+                id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+                from_height INTEGER NOT NULL
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("DROP TABLE rescan_required;")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+//! This migration adds a table for storing externally-provided transaction annotations, such as
+//! labels and accounting categories imported from other wallets.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::ensure_orchard_ua_receiver;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x12f85377_1bf9_4366_a76f_6e860bd123ba);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [ensure_orchard_ua_receiver::MIGRATION_ID]
+            .into_iter()
+            .collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a table for externally-provided transaction annotations."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        // Annotations are keyed by txid rather than referencing the `transactions` table, so
+        // that they may be imported before the wallet has discovered the transactions they
+        // describe.
+        transaction.execute_batch(
+            "CREATE TABLE tx_annotations (
+                txid BLOB NOT NULL PRIMARY KEY,
+                date TEXT,
+                label TEXT,
+                category TEXT,
+                note TEXT
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("DROP TABLE tx_annotations;")?;
+        Ok(())
+    }
+}
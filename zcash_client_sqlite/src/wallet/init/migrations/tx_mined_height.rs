@@ -0,0 +1,48 @@
+//! This migration adds a column to the `transactions` table recording the height at which each
+//! transaction was mined, which may be known for transactions in blocks that the wallet has not
+//! scanned (for example, transactions detected by scanning full blocks for transparent
+//! activity).
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::note_locks;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x8d0e4b37_2c91_4f6a_a5d3_1e7b96c0f482);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [note_locks::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a column to the transactions table recording the height at which each transaction was mined."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch(
+            "ALTER TABLE transactions ADD COLUMN mined_height INTEGER;
+            UPDATE transactions SET mined_height = block;",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("ALTER TABLE transactions DROP COLUMN mined_height;")?;
+        Ok(())
+    }
+}
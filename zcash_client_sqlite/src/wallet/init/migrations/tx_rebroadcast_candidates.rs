@@ -0,0 +1,48 @@
+//! This migration adds a table recording the wallet's own transactions that were un-mined by a
+//! chain reorg, and which may therefore need to be re-broadcast.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::rescan_required;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x8c5e02d9_6b1f_4a73_9f40_1e7d3ab56c28);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [rescan_required::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a table recording sent transactions that were orphaned by a chain reorg."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch(
+            "CREATE TABLE tx_rebroadcast_candidates (
+                transaction_id INTEGER NOT NULL PRIMARY KEY,
+                FOREIGN KEY (transaction_id) REFERENCES transactions(id_tx) ON DELETE CASCADE
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("DROP TABLE tx_rebroadcast_candidates;")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+//! This migration adds a column to the `utxos` table recording whether each output was created
+//! by a coinbase transaction, so that the coinbase maturity rule may be enforced when spending.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::watched_addresses;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0xebe32174_4330_4685_9e77_e109121b2a4a);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [watched_addresses::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a column to the utxos table recording whether each output is a coinbase output."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        // The wallet has never been able to distinguish coinbase outputs, so existing UTXOs are
+        // treated as non-coinbase outputs. The flag will be corrected if a UTXO is received again.
+        transaction.execute_batch(
+            "ALTER TABLE utxos ADD COLUMN is_coinbase INTEGER NOT NULL DEFAULT 0;",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("ALTER TABLE utxos DROP COLUMN is_coinbase;")?;
+        Ok(())
+    }
+}
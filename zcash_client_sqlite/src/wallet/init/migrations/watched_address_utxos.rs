@@ -0,0 +1,61 @@
+//! This migration adds a table for storing the transparent outputs received at the transparent
+//! receivers of watched addresses.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::tx_mined_height;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x3f5a9c21_7e4d_4b86_b0c2_9d61e8a4f317);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [tx_mined_height::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a table for transparent outputs received at watched addresses."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        // Watched addresses do not belong to any account, so their outputs are stored
+        // separately from the wallet's own UTXOs and can never be selected for spending.
+        transaction.execute_batch(
+            "CREATE TABLE watched_address_utxos (
+                id INTEGER NOT NULL PRIMARY KEY,
+                watched_address_id INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                prevout_txid BLOB NOT NULL,
+                prevout_idx INTEGER NOT NULL,
+                script BLOB NOT NULL,
+                value_zat INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                is_coinbase INTEGER NOT NULL DEFAULT 0,
+                spent_in_tx INTEGER,
+                FOREIGN KEY (watched_address_id) REFERENCES watched_addresses(id),
+                FOREIGN KEY (spent_in_tx) REFERENCES transactions(id_tx),
+                CONSTRAINT watched_outpoint UNIQUE (prevout_txid, prevout_idx)
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("DROP TABLE watched_address_utxos;")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+//! This migration adds a table for storing addresses that the wallet watches without holding a
+//! viewing key for them.
+
+use std::collections::HashSet;
+
+use schemer_rusqlite::RusqliteMigration;
+use uuid::Uuid;
+
+use super::tx_annotations;
+use crate::wallet::init::WalletMigrationError;
+
+pub(super) const MIGRATION_ID: Uuid = Uuid::from_u128(0x6b0c4e5d_2a8f_4c1e_9d3a_57f1e2b9c804);
+
+pub(super) struct Migration;
+
+impl schemer::Migration for Migration {
+    fn id(&self) -> Uuid {
+        MIGRATION_ID
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        [tx_annotations::MIGRATION_ID].into_iter().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a table for addresses watched without a viewing key."
+    }
+}
+
+impl RusqliteMigration for Migration {
+    type Error = WalletMigrationError;
+
+    fn up(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        // The transparent receiver is cached separately so that it may be matched against
+        // transparent outputs without decoding every watched address.
+        transaction.execute_batch(
+            "CREATE TABLE watched_addresses (
+                id INTEGER NOT NULL PRIMARY KEY,
+                address TEXT NOT NULL UNIQUE,
+                cached_transparent_receiver_address TEXT
+            );",
+        )?;
+
+        Ok(())
+    }
+
+    fn down(&self, transaction: &rusqlite::Transaction<'_>) -> Result<(), Self::Error> {
+        transaction.execute_batch("DROP TABLE watched_addresses;")?;
+        Ok(())
+    }
+}
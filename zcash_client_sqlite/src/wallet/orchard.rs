@@ -3,7 +3,7 @@ use std::{collections::HashSet, rc::Rc};
 use incrementalmerkletree::Position;
 use orchard::{
     keys::Diversifier,
-    note::{Note, Nullifier, RandomSeed, Rho},
+    note::{ExtractedNoteCommitment, Note, Nullifier, RandomSeed, Rho},
 };
 use rusqlite::{named_params, types::Value, Connection, Row, Transaction};
 
@@ -19,7 +19,7 @@ use zcash_protocol::{
     memo::MemoBytes,
     value::Zatoshis,
 };
-use zip32::Scope;
+use zip32::{DiversifierIndex, Scope};
 
 use crate::{error::SqliteClientError, AccountId, ReceivedNoteId};
 
@@ -199,6 +199,45 @@ pub(crate) fn get_spendable_orchard_note<P: consensus::Parameters>(
     )
 }
 
+/// Invokes the provided callback for each unspent Orchard note belonging to the specified
+/// account for which a note commitment tree position is known.
+pub(crate) fn for_each_unspent_orchard_note<P: consensus::Parameters, G, E>(
+    conn: &Connection,
+    params: &P,
+    account: AccountId,
+    callback: G,
+) -> Result<(), E>
+where
+    G: FnMut(ReceivedNote<ReceivedNoteId, Note>) -> Result<(), E>,
+    E: From<SqliteClientError>,
+{
+    super::common::for_each_unspent_note(
+        conn,
+        params,
+        account,
+        ShieldedProtocol::Orchard,
+        to_spendable_note,
+        callback,
+    )
+}
+
+/// Returns the note commitment, value, and mined height of each unspent Orchard note belonging
+/// to the specified account for which a note commitment tree position is known.
+pub(crate) fn get_unspent_orchard_note_commitments<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account: AccountId,
+) -> Result<Vec<([u8; 32], u64, BlockHeight)>, SqliteClientError> {
+    super::common::get_unspent_note_commitments(
+        conn,
+        params,
+        account,
+        ShieldedProtocol::Orchard,
+        to_spendable_note,
+        |note: &Note| ExtractedNoteCommitment::from(note.commitment()).to_bytes(),
+    )
+}
+
 pub(crate) fn select_spendable_orchard_notes<P: consensus::Parameters>(
     conn: &Connection,
     params: &P,
@@ -219,6 +258,20 @@ pub(crate) fn select_spendable_orchard_notes<P: consensus::Parameters>(
     )
 }
 
+/// Returns the diversifier index of the external address of the given viewing key at which the
+/// specified output was received, if the output was received at such an address.
+pub(crate) fn external_diversifier_index<T: ReceivedOrchardOutput>(
+    ufvk: &UnifiedFullViewingKey,
+    output: &T,
+) -> Option<DiversifierIndex> {
+    ufvk.orchard()
+        .and_then(|fvk| {
+            fvk.to_ivk(Scope::External)
+                .diversifier_index(&output.note().recipient())
+        })
+        .map(|index| DiversifierIndex::from(*index.to_bytes()))
+}
+
 /// Records the specified shielded output as having been received.
 ///
 /// This implementation relies on the facts that:
@@ -396,16 +449,20 @@ pub(crate) mod tests {
     use shardtree::error::ShardTreeError;
     use zcash_client_backend::{
         data_api::{
-            chain::CommitmentTreeRoot, DecryptedTransaction, WalletCommitmentTrees, WalletSummary,
+            chain::CommitmentTreeRoot, CommitmentTreeReplacement, DecryptedTransaction,
+            WalletCommitmentTrees, WalletSummary, WalletWrite,
         },
         wallet::{Note, ReceivedNote},
     };
     use zcash_keys::{
         address::{Address, UnifiedAddress},
-        keys::UnifiedSpendingKey,
+        keys::{UnifiedAddressRequest, UnifiedSpendingKey},
     };
     use zcash_note_encryption::try_output_recovery_with_ovk;
-    use zcash_primitives::transaction::Transaction;
+    use zcash_primitives::{
+        block::BlockHash,
+        transaction::{components::amount::NonNegativeAmount, Transaction},
+    };
     use zcash_protocol::{consensus::BlockHeight, memo::MemoBytes, ShieldedProtocol};
 
     use super::select_spendable_orchard_notes;
@@ -414,9 +471,9 @@ pub(crate) mod tests {
         testing::{
             self,
             pool::{OutputRecoveryError, ShieldedPoolTester},
-            TestState,
+            AddressType, TestBuilder, TestState,
         },
-        wallet::{commitment_tree, sapling::tests::SaplingPoolTester},
+        wallet::{self, commitment_tree, sapling::tests::SaplingPoolTester},
         ORCHARD_TABLES_PREFIX,
     };
 
@@ -471,6 +528,16 @@ pub(crate) mod tests {
             a == b
         }
 
+        fn sk_internal_diversifier(sk: &Self::Sk, j: zip32::DiversifierIndex) -> Option<[u8; 11]> {
+            let j = orchard::keys::DiversifierIndex::from(*j.as_bytes());
+            Some(
+                *Self::sk_to_fvk(sk)
+                    .address_at(j, zip32::Scope::Internal)
+                    .diversifier()
+                    .as_array(),
+            )
+        }
+
         fn empty_tree_leaf() -> Self::MerkleTreeHash {
             MerkleHashOrchard::empty_leaf()
         }
@@ -492,6 +559,17 @@ pub(crate) mod tests {
             s.next_orchard_subtree_index()
         }
 
+        fn copy_tree<Cache>(st: &mut TestState<Cache>) -> CommitmentTreeReplacement {
+            CommitmentTreeReplacement::Orchard(
+                st.wallet_mut()
+                    .with_orchard_tree_mut(|tree| {
+                        testing::pool::copy_shard_store(tree.store_mut())
+                            .map_err(ShardTreeError::Storage)
+                    })
+                    .unwrap(),
+            )
+        }
+
         fn select_spendable_notes<Cache>(
             st: &TestState<Cache>,
             account: crate::AccountId,
@@ -569,12 +647,82 @@ pub(crate) mod tests {
         testing::pool::send_single_step_proposed_transfer::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn change_consolidation_keeps_note_count_flat() {
+        testing::pool::change_consolidation_keeps_note_count_flat::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn propose_transfer_with_fee_override() {
+        testing::pool::propose_transfer_with_fee_override::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_note_scope() {
+        testing::pool::get_note_scope::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_spent_note_positions() {
+        testing::pool::get_spent_note_positions::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_spent_notes() {
+        testing::pool::get_spent_notes::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_transaction_fee() {
+        testing::pool::get_transaction_fee::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn proposal_validate_size() {
+        testing::pool::proposal_validate_size::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_transaction_bytes() {
+        testing::pool::get_transaction_bytes::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn proposal_estimated_size() {
+        testing::pool::proposal_estimated_size::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn proposal_serialization_version() {
+        testing::pool::proposal_serialization_version::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn send_proposed_transfer_from_signable_package() {
+        testing::pool::send_proposed_transfer_from_signable_package::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn received_notes_matching_memo() {
+        testing::pool::received_notes_matching_memo::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn newly_confirmed_notes() {
+        testing::pool::newly_confirmed_notes::<OrchardPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn send_multi_step_proposed_transfer() {
         testing::pool::send_multi_step_proposed_transfer::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn propose_transfer_rejects_invalid_memos() {
+        testing::pool::propose_transfer_rejects_invalid_memos::<OrchardPoolTester>()
+    }
+
     #[test]
     #[allow(deprecated)]
     fn create_to_address_fails_on_incorrect_usk() {
@@ -597,6 +745,131 @@ pub(crate) mod tests {
         testing::pool::spend_fails_on_locked_notes::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn get_notes_pending_spend() {
+        testing::pool::get_notes_pending_spend::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn abandon_transaction() {
+        testing::pool::abandon_transaction::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn abandon_expired_transaction() {
+        testing::pool::abandon_expired_transaction::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn inconsistent_tree_sets_rescan_required() {
+        testing::pool::inconsistent_tree_sets_rescan_required::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn orphaned_transaction_is_rebroadcast_candidate() {
+        testing::pool::orphaned_transaction_is_rebroadcast_candidate::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_unmined_transactions() {
+        testing::pool::get_unmined_transactions::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_tree_sizes() {
+        testing::pool::get_tree_sizes::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_witnesses() {
+        testing::pool::get_witnesses::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn witness_unavailable() {
+        testing::pool::witness_unavailable::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn projected_spendable_balance() {
+        testing::pool::projected_spendable_balance::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn balances_readable_from_snapshot() {
+        testing::pool::balances_readable_from_snapshot::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_fees_paid() {
+        testing::pool::get_fees_paid::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn locked_note_skipped_by_selection() {
+        testing::pool::locked_note_skipped_by_selection::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn pending_change_reported_separately() {
+        testing::pool::pending_change_reported_separately::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_transactions_spending_pool() {
+        testing::pool::get_transactions_spending_pool::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn pool_requirement_violations() {
+        testing::pool::pool_requirement_violations::<OrchardPoolTester, SaplingPoolTester>()
+    }
+
+    #[test]
+    fn audit_transaction() {
+        testing::pool::audit_transaction::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn replace_commitment_tree() {
+        testing::pool::replace_commitment_tree::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn blocks_until_spendable() {
+        testing::pool::blocks_until_spendable::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn unspent_set_fingerprint() {
+        testing::pool::unspent_set_fingerprint::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn get_change_notes() {
+        testing::pool::get_change_notes::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn iter_received_notes_excludes_pending_spends() {
+        testing::pool::iter_received_notes_excludes_pending_spends::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn proposal_anchor_info() {
+        testing::pool::proposal_anchor_info::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn create_proposed_transactions_for_branch() {
+        testing::pool::create_proposed_transactions_for_branch::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn send_change_to_diversifier_index() {
+        testing::pool::send_change_to_diversifier_index::<OrchardPoolTester>()
+    }
+
     #[test]
     fn ovk_policy_prevents_recovery_from_chain() {
         testing::pool::ovk_policy_prevents_recovery_from_chain::<OrchardPoolTester>()
@@ -619,6 +892,21 @@ pub(crate) mod tests {
         >()
     }
 
+    #[test]
+    fn send_to_other_account_records_pending_note() {
+        testing::pool::send_to_other_account_records_pending_note::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn discover_diversified_addresses() {
+        testing::pool::discover_diversified_addresses::<OrchardPoolTester>()
+    }
+
+    #[test]
+    fn auto_discovered_addresses() {
+        testing::pool::auto_discovered_addresses::<OrchardPoolTester>()
+    }
+
     #[test]
     #[ignore] // FIXME: #1316 This requires support for dust outputs.
     #[cfg(not(feature = "expensive-tests"))]
@@ -626,12 +914,29 @@ pub(crate) mod tests {
         testing::pool::zip317_spend::<OrchardPoolTester>()
     }
 
+    #[test]
+    fn dust_change_added_to_fee() {
+        testing::pool::dust_change_added_to_fee::<OrchardPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn shield_transparent() {
         testing::pool::shield_transparent::<OrchardPoolTester>()
     }
 
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn transparent_spend_recorded_before_utxo() {
+        testing::pool::transparent_spend_recorded_before_utxo::<OrchardPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn shield_coinbase_transparent() {
+        testing::pool::shield_coinbase_transparent::<OrchardPoolTester>()
+    }
+
     #[test]
     fn birthday_in_anchor_shard() {
         testing::pool::birthday_in_anchor_shard::<OrchardPoolTester>()
@@ -652,11 +957,21 @@ pub(crate) mod tests {
         testing::pool::pool_crossing_required::<OrchardPoolTester, SaplingPoolTester>()
     }
 
+    #[test]
+    fn get_transaction_pool_flows() {
+        testing::pool::get_transaction_pool_flows::<OrchardPoolTester, SaplingPoolTester>()
+    }
+
     #[test]
     fn fully_funded_fully_private() {
         testing::pool::fully_funded_fully_private::<OrchardPoolTester, SaplingPoolTester>()
     }
 
+    #[test]
+    fn change_pool_preference_is_respected() {
+        testing::pool::change_pool_preference_is_respected::<OrchardPoolTester, SaplingPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn fully_funded_send_to_t() {
@@ -672,4 +987,65 @@ pub(crate) mod tests {
     fn multi_pool_checkpoints_with_pruning() {
         testing::pool::multi_pool_checkpoints_with_pruning::<OrchardPoolTester, SaplingPoolTester>()
     }
+
+    #[test]
+    fn orchard_only_address_receives_funds() {
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+        let account = st.test_account().cloned().unwrap();
+
+        // Generate a unified address that has only an Orchard receiver.
+        let request = UnifiedAddressRequest::new(true, false, false).unwrap();
+        let ua = st
+            .wallet_mut()
+            .get_next_available_address(account.account_id(), request)
+            .unwrap()
+            .unwrap();
+        assert!(ua.orchard().is_some());
+        assert!(ua.sapling().is_none());
+        assert!(ua.transparent().is_none());
+
+        // The address round-trips through the addresses table.
+        let (current_ua, diversifier_index) = wallet::get_current_address(
+            &st.wallet().conn,
+            &st.wallet().params,
+            account.account_id(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(current_ua, ua);
+
+        // Receive a note at the address's Orchard receiver.
+        let fvk = OrchardPoolTester::test_account_fvk(&st);
+        assert_eq!(
+            &fvk.address_at(diversifier_index, zip32::Scope::External),
+            ua.orchard().unwrap()
+        );
+        let value = NonNegativeAmount::const_from_u64(50000);
+        let (h, _, _) = st.generate_next_block(
+            &fvk,
+            AddressType::DiversifiedExternal(diversifier_index),
+            value,
+        );
+        st.scan_cached_blocks(h, 1);
+
+        // The note is attributed to the account, and was received at the Orchard-only address.
+        assert_eq!(st.get_total_balance(account.account_id()), value);
+        let (account_id, diversifier): (u32, Vec<u8>) = st
+            .wallet()
+            .conn
+            .query_row(
+                "SELECT account_id, diversifier FROM orchard_received_notes",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(account_id, account.account_id().0);
+        assert_eq!(
+            &diversifier[..],
+            ua.orchard().unwrap().diversifier().as_array()
+        );
+    }
 }
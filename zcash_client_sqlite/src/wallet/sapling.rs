@@ -18,7 +18,7 @@ use zcash_protocol::{
     consensus::{self, BlockHeight},
     memo::MemoBytes,
 };
-use zip32::Scope;
+use zip32::{DiversifierIndex, Scope};
 
 use crate::{error::SqliteClientError, AccountId, ReceivedNoteId};
 
@@ -229,6 +229,45 @@ pub(crate) fn select_spendable_sapling_notes<P: consensus::Parameters>(
     )
 }
 
+/// Invokes the provided callback for each unspent Sapling note belonging to the specified
+/// account for which a note commitment tree position is known.
+pub(crate) fn for_each_unspent_sapling_note<P: consensus::Parameters, G, E>(
+    conn: &Connection,
+    params: &P,
+    account: AccountId,
+    callback: G,
+) -> Result<(), E>
+where
+    G: FnMut(ReceivedNote<ReceivedNoteId, sapling::Note>) -> Result<(), E>,
+    E: From<SqliteClientError>,
+{
+    super::common::for_each_unspent_note(
+        conn,
+        params,
+        account,
+        ShieldedProtocol::Sapling,
+        to_spendable_note,
+        callback,
+    )
+}
+
+/// Returns the note commitment, value, and mined height of each unspent Sapling note belonging
+/// to the specified account for which a note commitment tree position is known.
+pub(crate) fn get_unspent_sapling_note_commitments<P: consensus::Parameters>(
+    conn: &Connection,
+    params: &P,
+    account: AccountId,
+) -> Result<Vec<([u8; 32], u64, BlockHeight)>, SqliteClientError> {
+    super::common::get_unspent_note_commitments(
+        conn,
+        params,
+        account,
+        ShieldedProtocol::Sapling,
+        to_spendable_note,
+        |note: &sapling::Note| note.cmu().to_bytes(),
+    )
+}
+
 /// Retrieves the set of nullifiers for "potentially spendable" Sapling notes that the
 /// wallet is tracking.
 ///
@@ -319,6 +358,17 @@ pub(crate) fn mark_sapling_note_spent(
     }
 }
 
+/// Returns the diversifier index of the external address of the given viewing key at which the
+/// specified output was received, if the output was received at such an address.
+pub(crate) fn external_diversifier_index<T: ReceivedSaplingOutput>(
+    ufvk: &UnifiedFullViewingKey,
+    output: &T,
+) -> Option<DiversifierIndex> {
+    ufvk.sapling()
+        .and_then(|dfvk| dfvk.decrypt_diversifier(&output.note().recipient()))
+        .and_then(|(index, scope)| (scope == Scope::External).then_some(index))
+}
+
 /// Records the specified shielded output as having been received.
 ///
 /// This implementation relies on the facts that:
@@ -417,13 +467,14 @@ pub(crate) mod tests {
             components::{amount::NonNegativeAmount, sapling::zip212_enforcement},
             Transaction,
         },
-        zip32::Scope,
+        zip32::{DiversifierIndex, Scope},
     };
 
     use zcash_client_backend::{
         address::Address,
         data_api::{
-            chain::CommitmentTreeRoot, DecryptedTransaction, WalletCommitmentTrees, WalletSummary,
+            chain::CommitmentTreeRoot, CommitmentTreeReplacement, DecryptedTransaction,
+            WalletCommitmentTrees, WalletSummary,
         },
         keys::UnifiedSpendingKey,
         wallet::{Note, ReceivedNote},
@@ -480,6 +531,13 @@ pub(crate) mod tests {
             a.to_bytes() == b.to_bytes()
         }
 
+        fn sk_internal_diversifier(sk: &Self::Sk, j: DiversifierIndex) -> Option<[u8; 11]> {
+            sk.derive_internal()
+                .to_diversifiable_full_viewing_key()
+                .address(j)
+                .map(|addr| addr.diversifier().0)
+        }
+
         fn empty_tree_leaf() -> Self::MerkleTreeHash {
             sapling::Node::empty_leaf()
         }
@@ -501,6 +559,17 @@ pub(crate) mod tests {
             s.next_sapling_subtree_index()
         }
 
+        fn copy_tree<Cache>(st: &mut TestState<Cache>) -> CommitmentTreeReplacement {
+            CommitmentTreeReplacement::Sapling(
+                st.wallet_mut()
+                    .with_sapling_tree_mut(|tree| {
+                        testing::pool::copy_shard_store(tree.store_mut())
+                            .map_err(ShardTreeError::Storage)
+                    })
+                    .unwrap(),
+            )
+        }
+
         fn select_spendable_notes<Cache>(
             st: &TestState<Cache>,
             account: AccountId,
@@ -575,12 +644,88 @@ pub(crate) mod tests {
         testing::pool::send_single_step_proposed_transfer::<SaplingPoolTester>()
     }
 
+    #[test]
+    fn change_consolidation_keeps_note_count_flat() {
+        testing::pool::change_consolidation_keeps_note_count_flat::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn propose_transfer_with_fee_override() {
+        testing::pool::propose_transfer_with_fee_override::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_note_scope() {
+        testing::pool::get_note_scope::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_spent_note_positions() {
+        testing::pool::get_spent_note_positions::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_spent_notes() {
+        testing::pool::get_spent_notes::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_transaction_fee() {
+        testing::pool::get_transaction_fee::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn proposal_validate_size() {
+        testing::pool::proposal_validate_size::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_transaction_bytes() {
+        testing::pool::get_transaction_bytes::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn proposal_estimated_size() {
+        testing::pool::proposal_estimated_size::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn proposal_serialization_version() {
+        testing::pool::proposal_serialization_version::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn send_proposed_transfer_from_signable_package() {
+        testing::pool::send_proposed_transfer_from_signable_package::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn received_notes_matching_memo() {
+        testing::pool::received_notes_matching_memo::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn newly_confirmed_notes() {
+        testing::pool::newly_confirmed_notes::<SaplingPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn send_multi_step_proposed_transfer() {
         testing::pool::send_multi_step_proposed_transfer::<SaplingPoolTester>()
     }
 
+    #[test]
+    #[cfg(not(feature = "orchard"))]
+    fn propose_transfer_respects_memo_policy() {
+        testing::pool::propose_transfer_respects_memo_policy::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn propose_transfer_rejects_invalid_memos() {
+        testing::pool::propose_transfer_rejects_invalid_memos::<SaplingPoolTester>()
+    }
+
     #[test]
     #[allow(deprecated)]
     fn create_to_address_fails_on_incorrect_usk() {
@@ -603,6 +748,134 @@ pub(crate) mod tests {
         testing::pool::spend_fails_on_locked_notes::<SaplingPoolTester>()
     }
 
+    #[test]
+    fn get_notes_pending_spend() {
+        testing::pool::get_notes_pending_spend::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn abandon_transaction() {
+        testing::pool::abandon_transaction::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn abandon_expired_transaction() {
+        testing::pool::abandon_expired_transaction::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn inconsistent_tree_sets_rescan_required() {
+        testing::pool::inconsistent_tree_sets_rescan_required::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn orphaned_transaction_is_rebroadcast_candidate() {
+        testing::pool::orphaned_transaction_is_rebroadcast_candidate::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_unmined_transactions() {
+        testing::pool::get_unmined_transactions::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_tree_sizes() {
+        testing::pool::get_tree_sizes::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_witnesses() {
+        testing::pool::get_witnesses::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn witness_unavailable() {
+        testing::pool::witness_unavailable::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn projected_spendable_balance() {
+        testing::pool::projected_spendable_balance::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn balances_readable_from_snapshot() {
+        testing::pool::balances_readable_from_snapshot::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_fees_paid() {
+        testing::pool::get_fees_paid::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn locked_note_skipped_by_selection() {
+        testing::pool::locked_note_skipped_by_selection::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn pending_change_reported_separately() {
+        testing::pool::pending_change_reported_separately::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_transactions_spending_pool() {
+        testing::pool::get_transactions_spending_pool::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn pool_requirement_violations() {
+        use crate::wallet::orchard::tests::OrchardPoolTester;
+
+        testing::pool::pool_requirement_violations::<SaplingPoolTester, OrchardPoolTester>()
+    }
+
+    #[test]
+    fn audit_transaction() {
+        testing::pool::audit_transaction::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn replace_commitment_tree() {
+        testing::pool::replace_commitment_tree::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn blocks_until_spendable() {
+        testing::pool::blocks_until_spendable::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn unspent_set_fingerprint() {
+        testing::pool::unspent_set_fingerprint::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn get_change_notes() {
+        testing::pool::get_change_notes::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn iter_received_notes_excludes_pending_spends() {
+        testing::pool::iter_received_notes_excludes_pending_spends::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn proposal_anchor_info() {
+        testing::pool::proposal_anchor_info::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn create_proposed_transactions_for_branch() {
+        testing::pool::create_proposed_transactions_for_branch::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn send_change_to_diversifier_index() {
+        testing::pool::send_change_to_diversifier_index::<SaplingPoolTester>()
+    }
+
     #[test]
     fn ovk_policy_prevents_recovery_from_chain() {
         testing::pool::ovk_policy_prevents_recovery_from_chain::<SaplingPoolTester>()
@@ -625,6 +898,21 @@ pub(crate) mod tests {
         >()
     }
 
+    #[test]
+    fn send_to_other_account_records_pending_note() {
+        testing::pool::send_to_other_account_records_pending_note::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn discover_diversified_addresses() {
+        testing::pool::discover_diversified_addresses::<SaplingPoolTester>()
+    }
+
+    #[test]
+    fn auto_discovered_addresses() {
+        testing::pool::auto_discovered_addresses::<SaplingPoolTester>()
+    }
+
     #[test]
     #[ignore] // FIXME: #1316 This requires support for dust outputs.
     #[cfg(not(feature = "expensive-tests"))]
@@ -632,12 +920,29 @@ pub(crate) mod tests {
         testing::pool::zip317_spend::<SaplingPoolTester>()
     }
 
+    #[test]
+    fn dust_change_added_to_fee() {
+        testing::pool::dust_change_added_to_fee::<SaplingPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "transparent-inputs")]
     fn shield_transparent() {
         testing::pool::shield_transparent::<SaplingPoolTester>()
     }
 
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn transparent_spend_recorded_before_utxo() {
+        testing::pool::transparent_spend_recorded_before_utxo::<SaplingPoolTester>()
+    }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    fn shield_coinbase_transparent() {
+        testing::pool::shield_coinbase_transparent::<SaplingPoolTester>()
+    }
+
     #[test]
     fn birthday_in_anchor_shard() {
         testing::pool::birthday_in_anchor_shard::<SaplingPoolTester>()
@@ -661,6 +966,14 @@ pub(crate) mod tests {
         testing::pool::pool_crossing_required::<SaplingPoolTester, OrchardPoolTester>()
     }
 
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn get_transaction_pool_flows() {
+        use crate::wallet::orchard::tests::OrchardPoolTester;
+
+        testing::pool::get_transaction_pool_flows::<SaplingPoolTester, OrchardPoolTester>()
+    }
+
     #[test]
     #[cfg(feature = "orchard")]
     fn fully_funded_fully_private() {
@@ -669,6 +982,14 @@ pub(crate) mod tests {
         testing::pool::fully_funded_fully_private::<SaplingPoolTester, OrchardPoolTester>()
     }
 
+    #[test]
+    #[cfg(feature = "orchard")]
+    fn change_pool_preference_is_respected() {
+        use crate::wallet::orchard::tests::OrchardPoolTester;
+
+        testing::pool::change_pool_preference_is_respected::<SaplingPoolTester, OrchardPoolTester>()
+    }
+
     #[test]
     #[cfg(all(feature = "orchard", feature = "transparent-inputs"))]
     fn fully_funded_send_to_t() {
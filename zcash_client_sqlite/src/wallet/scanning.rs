@@ -9,6 +9,7 @@ use tracing::{debug, trace};
 
 use zcash_client_backend::{
     data_api::{
+        chain::error::Error as ChainError,
         scanning::{spanning_tree::SpanningTree, ScanPriority, ScanRange},
         SAPLING_SHARD_HEIGHT,
     },
@@ -88,6 +89,46 @@ pub(crate) fn suggest_scan_ranges(
     Ok(result)
 }
 
+pub(crate) fn suggest_download_ranges<E>(
+    conn: &rusqlite::Connection,
+    cached_tip: impl FnMut(&ScanRange) -> Result<Option<BlockHeight>, E>,
+) -> Result<Vec<Range<BlockHeight>>, ChainError<SqliteClientError, E>> {
+    let birthday = match wallet_birthday(conn).map_err(|e| ChainError::Wallet(e.into()))? {
+        Some(birthday) => birthday,
+        None => return Ok(vec![]),
+    };
+    let scan_ranges =
+        suggest_scan_ranges(conn, ScanPriority::Historic).map_err(ChainError::Wallet)?;
+
+    download_ranges(scan_ranges, birthday, cached_tip).map_err(ChainError::BlockSource)
+}
+
+fn download_ranges<E>(
+    scan_ranges: Vec<ScanRange>,
+    birthday: BlockHeight,
+    mut cached_tip: impl FnMut(&ScanRange) -> Result<Option<BlockHeight>, E>,
+) -> Result<Vec<Range<BlockHeight>>, E> {
+    let mut download_ranges = vec![];
+    for scan_range in scan_ranges
+        .iter()
+        .filter_map(|range| range.truncate_start(birthday))
+    {
+        // Blocks cached for a range that must be verified may have been invalidated by a reorg.
+        let to_download = if scan_range.priority() == ScanPriority::Verify {
+            Some(scan_range)
+        } else {
+            match cached_tip(&scan_range)? {
+                Some(tip) => scan_range.truncate_start(tip + 1),
+                None => Some(scan_range),
+            }
+        };
+
+        download_ranges.extend(to_download.map(|range| range.block_range().clone()));
+    }
+
+    Ok(download_ranges)
+}
+
 pub(crate) fn insert_queue_entries<'a>(
     conn: &rusqlite::Connection,
     entries: impl Iterator<Item = &'a ScanRange>,
@@ -301,11 +342,18 @@ fn extend_range(
         .map_err(SqliteClientError::from)
 }
 
+/// Updates the scan queue to reflect the completion of scanning the given range.
+///
+/// If `fully_scanned` is `false`, the range was scanned with the keys of only a subset of the
+/// wallet's accounts; the existing queue entries for the range are then left in place (so that
+/// the range will be scanned again for the remaining accounts), and only the ranges required to
+/// make any discovered notes spendable are added to the queue.
 pub(crate) fn scan_complete<P: consensus::Parameters>(
     conn: &rusqlite::Transaction<'_>,
     params: &P,
     range: Range<BlockHeight>,
     wallet_note_positions: &[(ShieldedProtocol, Position)],
+    fully_scanned: bool,
 ) -> Result<(), SqliteClientError> {
     // Read the wallet birthday (if known).
     // TODO: use per-pool birthdays?
@@ -367,7 +415,8 @@ pub(crate) fn scan_complete<P: consensus::Parameters>(
 
     let query_range = extended_range.clone().unwrap_or_else(|| range.clone());
 
-    let scanned = ScanRange::from_parts(range.clone(), ScanPriority::Scanned);
+    let scanned =
+        fully_scanned.then(|| ScanRange::from_parts(range.clone(), ScanPriority::Scanned));
 
     // If any of the extended range actually extends beyond the scanned range, we need to
     // scan that extension in order to make the found note(s) spendable. We need to avoid
@@ -381,7 +430,7 @@ pub(crate) fn scan_complete<P: consensus::Parameters>(
         .map(|extended| ScanRange::from_parts(range.end..extended.end, ScanPriority::FoundNote))
         .filter(|range| !range.is_empty());
 
-    let replacement = Some(scanned)
+    let replacement = scanned
         .into_iter()
         .chain(extended_before)
         .chain(extended_after);
@@ -579,14 +628,14 @@ pub(crate) fn update_chain_tip<P: consensus::Parameters>(
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use std::num::NonZeroU8;
+    use std::{collections::BTreeSet, convert::Infallible, num::NonZeroU8, ops::Range};
 
     use incrementalmerkletree::{frontier::Frontier, Hashable, Position};
 
     use secrecy::SecretVec;
     use zcash_client_backend::data_api::{
         chain::{ChainState, CommitmentTreeRoot},
-        scanning::{spanning_tree::testing::scan_range, ScanPriority},
+        scanning::{spanning_tree::testing::scan_range, ScanPriority, ScanRange},
         AccountBirthday, Ratio, WalletRead, WalletWrite, SAPLING_SHARD_HEIGHT,
     };
     use zcash_primitives::{
@@ -603,7 +652,9 @@ pub(crate) mod tests {
         },
         wallet::{
             sapling::tests::SaplingPoolTester,
-            scanning::{insert_queue_entries, replace_queue_entries, suggest_scan_ranges},
+            scanning::{
+                download_ranges, insert_queue_entries, replace_queue_entries, suggest_scan_ranges,
+            },
         },
         VERIFY_LOOKAHEAD,
     };
@@ -895,6 +946,31 @@ pub(crate) mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn sync_lag() {
+        let mut st = TestBuilder::new()
+            .with_block_cache()
+            .with_account_from_sapling_activation(BlockHash([0; 32]))
+            .build();
+
+        // Nothing has been scanned yet, so the lag is unknown.
+        assert_eq!(st.wallet().sync_lag().unwrap(), None);
+
+        let dfvk = SaplingPoolTester::test_account_fvk(&st);
+        let value = NonNegativeAmount::const_from_u64(50000);
+        let (h, _, _) = st.generate_next_block(&dfvk, AddressType::DefaultExternal, value);
+        st.scan_cached_blocks(h, 1);
+
+        // The network tip is ahead of the scanned tip.
+        st.wallet_mut().update_chain_tip(h + 10).unwrap();
+        assert_eq!(st.wallet().sync_lag().unwrap(), Some(10));
+
+        // Scanning another block reduces the lag.
+        let (h, _) = st.generate_empty_block();
+        st.scan_cached_blocks(h, 1);
+        assert_eq!(st.wallet().sync_lag().unwrap(), Some(9));
+    }
+
     #[test]
     fn sapling_update_chain_tip_with_no_subtree_roots() {
         update_chain_tip_with_no_subtree_roots::<SaplingPoolTester>();
@@ -1475,4 +1551,78 @@ pub(crate) mod tests {
         let actual = suggest_scan_ranges(&st.wallet().conn, Ignored).unwrap();
         assert_eq!(actual, expected);
     }
+
+    fn height_range(r: Range<u32>) -> Range<BlockHeight> {
+        BlockHeight::from_u32(r.start)..BlockHeight::from_u32(r.end)
+    }
+
+    #[test]
+    fn download_ranges_omit_cached_blocks() {
+        use ScanPriority::*;
+
+        // Blocks 100..110 and 200..205 are already cached.
+        let cached: BTreeSet<BlockHeight> = (100..110)
+            .chain(200..205)
+            .map(BlockHeight::from_u32)
+            .collect();
+        let cached_tip = |range: &ScanRange| {
+            Ok::<_, Infallible>(
+                cached
+                    .range(range.block_range().clone())
+                    .next_back()
+                    .copied(),
+            )
+        };
+
+        let scan_ranges = vec![
+            scan_range(200..220, ChainTip),
+            scan_range(100..110, FoundNote),
+            scan_range(150..160, Historic),
+        ];
+
+        assert_eq!(
+            download_ranges(scan_ranges, BlockHeight::from_u32(50), cached_tip).unwrap(),
+            vec![height_range(205..220), height_range(150..160)]
+        );
+    }
+
+    #[test]
+    fn download_ranges_exclude_pre_birthday_blocks() {
+        use ScanPriority::*;
+
+        let scan_ranges = vec![scan_range(90..110, Historic), scan_range(50..80, Historic)];
+        let nothing_cached = |_: &ScanRange| Ok::<_, Infallible>(None);
+
+        assert_eq!(
+            download_ranges(scan_ranges, BlockHeight::from_u32(100), nothing_cached).unwrap(),
+            vec![height_range(100..110)]
+        );
+    }
+
+    #[test]
+    fn download_ranges_always_include_verify_ranges() {
+        use ScanPriority::*;
+
+        let scan_ranges = vec![scan_range(100..110, Verify), scan_range(110..120, ChainTip)];
+
+        // Every range is fully cached.
+        let all_cached = |range: &ScanRange| Ok::<_, Infallible>(Some(range.block_range().end - 1));
+
+        assert_eq!(
+            download_ranges(scan_ranges, BlockHeight::from_u32(50), all_cached).unwrap(),
+            vec![height_range(100..110)]
+        );
+    }
+
+    #[test]
+    fn suggest_download_ranges_without_accounts() {
+        let st = TestBuilder::new().build();
+
+        assert_eq!(
+            st.wallet()
+                .suggest_download_ranges(|_| Ok::<_, Infallible>(None))
+                .unwrap(),
+            vec![]
+        );
+    }
 }
@@ -11,6 +11,20 @@ use zcash_protocol::consensus::{self, NetworkType};
 use sapling::PaymentAddress;
 use zcash_protocol::{PoolType, ShieldedProtocol};
 
+/// The encoding with which an address is presented to a sender.
+///
+/// Not all senders support Unified Addresses; the legacy formats allow a single receiver of a
+/// [`UnifiedAddress`] to be presented to such senders as a bare Sapling or transparent address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressFormat {
+    /// A Unified Address containing all of the address's receivers.
+    Unified,
+    /// A bare Sapling address encoding the address's Sapling receiver.
+    SaplingLegacy,
+    /// A transparent address encoding the address's transparent receiver.
+    TransparentLegacy,
+}
+
 /// A Unified Address.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct UnifiedAddress {
@@ -213,6 +227,31 @@ impl UnifiedAddress {
         self.to_address(params.network_type()).to_string()
     }
 
+    /// Returns the string encoding of this `UnifiedAddress` for the given network in the
+    /// requested format.
+    ///
+    /// Returns `None` if a legacy format is requested and this address does not contain a
+    /// receiver of the corresponding type.
+    pub fn encode_as<P: consensus::Parameters>(
+        &self,
+        params: &P,
+        format: AddressFormat,
+    ) -> Option<String> {
+        match format {
+            AddressFormat::Unified => Some(self.encode(params)),
+            AddressFormat::SaplingLegacy => {
+                #[cfg(feature = "sapling")]
+                return self.sapling.map(|pa| Address::Sapling(pa).encode(params));
+
+                #[cfg(not(feature = "sapling"))]
+                return None;
+            }
+            AddressFormat::TransparentLegacy => self
+                .transparent
+                .map(|taddr| Address::Transparent(taddr).encode(params)),
+        }
+    }
+
     /// Returns the set of receiver typecodes.
     pub fn receiver_types(&self) -> Vec<Typecode> {
         let result = std::iter::empty();
@@ -466,7 +505,7 @@ mod tests {
     use zcash_address::test_vectors;
     use zcash_primitives::consensus::MAIN_NETWORK;
 
-    use super::{Address, UnifiedAddress};
+    use super::{Address, AddressFormat, UnifiedAddress};
 
     #[cfg(feature = "sapling")]
     use crate::keys::sapling;
@@ -508,6 +547,39 @@ mod tests {
         assert_eq!(Address::decode(&MAIN_NETWORK, &addr_str), Some(addr));
     }
 
+    #[test]
+    fn ua_encode_as() {
+        for tv in test_vectors::UNIFIED {
+            let ua = match Address::decode(&MAIN_NETWORK, tv.unified_addr) {
+                Some(Address::Unified(ua)) => ua,
+                _ => panic!("{} did not decode to a unified address.", tv.unified_addr),
+            };
+
+            assert_eq!(
+                ua.encode_as(&MAIN_NETWORK, AddressFormat::Unified),
+                Some(ua.encode(&MAIN_NETWORK))
+            );
+
+            #[cfg(feature = "sapling")]
+            assert_eq!(
+                ua.encode_as(&MAIN_NETWORK, AddressFormat::SaplingLegacy)
+                    .and_then(|s| Address::decode(&MAIN_NETWORK, &s)),
+                ua.sapling().map(|pa| Address::Sapling(*pa))
+            );
+            #[cfg(not(feature = "sapling"))]
+            assert_eq!(
+                ua.encode_as(&MAIN_NETWORK, AddressFormat::SaplingLegacy),
+                None
+            );
+
+            assert_eq!(
+                ua.encode_as(&MAIN_NETWORK, AddressFormat::TransparentLegacy)
+                    .and_then(|s| Address::decode(&MAIN_NETWORK, &s)),
+                ua.transparent().map(|taddr| Address::Transparent(*taddr))
+            );
+        }
+    }
+
     #[test]
     #[cfg(not(any(feature = "orchard", feature = "sapling")))]
     fn ua_round_trip() {
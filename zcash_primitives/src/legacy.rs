@@ -342,6 +342,29 @@ impl Script {
             None
         }
     }
+
+    /// Returns the address of the P2PKH output spent by an input having this Script as its
+    /// `scriptSig`, if this is a standard P2PKH `scriptSig` consisting of a signature followed
+    /// by a public key.
+    #[cfg(feature = "transparent-inputs")]
+    pub fn p2pkh_spend_address(&self) -> Option<TransparentAddress> {
+        use ripemd::Ripemd160;
+        use sha2::{Digest, Sha256};
+
+        // Both the signature and the public key are short enough to be pushed directly.
+        let (&sig_len, rest) = self.0.split_first()?;
+        if sig_len == 0 || sig_len >= OpCode::PushData1 as u8 {
+            return None;
+        }
+        let (&pubkey_len, pubkey) = rest.get(usize::from(sig_len)..)?.split_first()?;
+        if !(pubkey_len == 33 || pubkey_len == 65) || pubkey.len() != usize::from(pubkey_len) {
+            return None;
+        }
+
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&Ripemd160::digest(Sha256::digest(pubkey)));
+        Some(TransparentAddress::PublicKeyHash(hash))
+    }
 }
 
 impl Shl<OpCode> for Script {
@@ -484,4 +507,25 @@ mod tests {
         );
         assert_eq!(addr.script().address(), Some(addr));
     }
+
+    #[test]
+    #[cfg(feature = "transparent-inputs")]
+    #[allow(deprecated)]
+    fn p2pkh_spend_address() {
+        let secp = secp256k1::Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = secp256k1::PublicKey::from_secret_key(&secp, &sk);
+
+        let script_sig = Script::default() << &[0x30; 71][..] << &pubkey.serialize()[..];
+        assert_eq!(
+            script_sig.p2pkh_spend_address(),
+            Some(super::keys::pubkey_to_address(&pubkey))
+        );
+
+        // Scripts that do not end with a public key are not recognized.
+        let script_sig = Script::default() << &[0x30; 71][..];
+        assert_eq!(script_sig.p2pkh_spend_address(), None);
+        let script_sig = Script::default() << &[0x30; 71][..] << &[2; 20][..];
+        assert_eq!(script_sig.p2pkh_spend_address(), None);
+    }
 }
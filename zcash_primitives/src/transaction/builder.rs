@@ -274,6 +274,7 @@ pub struct Builder<'a, P, U: sapling::builder::ProverProgress> {
     params: P,
     build_config: BuildConfig,
     target_height: BlockHeight,
+    consensus_branch_id: BranchId,
     expiry_height: BlockHeight,
     transparent_builder: TransparentBuilder,
     sapling_builder: Option<sapling::builder::Builder>,
@@ -302,6 +303,11 @@ impl<'a, P, U: sapling::builder::ProverProgress> Builder<'a, P, U> {
         self.target_height
     }
 
+    /// Returns the consensus branch ID under whose rules the transaction will be built.
+    pub fn consensus_branch_id(&self) -> BranchId {
+        self.consensus_branch_id
+    }
+
     /// Returns the set of transparent inputs currently committed to be consumed
     /// by the transaction.
     #[cfg(feature = "transparent-inputs")]
@@ -340,6 +346,9 @@ impl<'a, P: consensus::Parameters> Builder<'a, P, ()> {
     ///
     /// The expiry height will be set to the given height plus the default transaction
     /// expiry delta (20 blocks).
+    ///
+    /// The consensus branch ID will be the one that is active at the given height; see
+    /// [`Builder::with_consensus_branch_id`] to override it.
     pub fn new(params: P, target_height: BlockHeight, build_config: BuildConfig) -> Self {
         let orchard_builder = if params.is_nu_active(NetworkUpgrade::Nu5, target_height) {
             build_config
@@ -360,6 +369,7 @@ impl<'a, P: consensus::Parameters> Builder<'a, P, ()> {
             });
 
         Builder {
+            consensus_branch_id: BranchId::for_height(&params, target_height),
             params,
             build_config,
             target_height,
@@ -391,6 +401,7 @@ impl<'a, P: consensus::Parameters> Builder<'a, P, ()> {
             params: self.params,
             build_config: self.build_config,
             target_height: self.target_height,
+            consensus_branch_id: self.consensus_branch_id,
             expiry_height: self.expiry_height,
             transparent_builder: self.transparent_builder,
             sapling_builder: self.sapling_builder,
@@ -404,6 +415,18 @@ impl<'a, P: consensus::Parameters> Builder<'a, P, ()> {
 }
 
 impl<'a, P: consensus::Parameters, U: sapling::builder::ProverProgress> Builder<'a, P, U> {
+    /// Overrides the consensus branch ID under whose rules the transaction will be built,
+    /// instead of using the branch ID that is active at the target height.
+    ///
+    /// This is primarily useful for constructing transactions close to a network upgrade
+    /// activation height. The caller is responsible for ensuring that the resulting
+    /// transaction can be mined; a transaction built for a branch that is not active at the
+    /// height at which it is mined will be rejected by consensus nodes.
+    pub fn with_consensus_branch_id(mut self, consensus_branch_id: BranchId) -> Self {
+        self.consensus_branch_id = consensus_branch_id;
+        self
+    }
+
     /// Adds an Orchard note to be spent in this bundle.
     ///
     /// Returns an error if the given Merkle path does not have the required anchor for
@@ -665,7 +688,7 @@ impl<'a, P: consensus::Parameters, U: sapling::builder::ProverProgress> Builder<
         output_prover: &OP,
         fee: NonNegativeAmount,
     ) -> Result<BuildResult, Error<FE>> {
-        let consensus_branch_id = BranchId::for_height(&self.params, self.target_height);
+        let consensus_branch_id = self.consensus_branch_id;
 
         // determine transaction version
         let version = TxVersion::suggested_for_branch(consensus_branch_id);
@@ -739,7 +762,7 @@ impl<'a, P: consensus::Parameters, U: sapling::builder::ProverProgress> Builder<
 
         let unauthed_tx: TransactionData<Unauthorized> = TransactionData {
             version,
-            consensus_branch_id: BranchId::for_height(&self.params, self.target_height),
+            consensus_branch_id,
             lock_time: 0,
             expiry_height: self.expiry_height,
             transparent_bundle,
@@ -922,7 +945,7 @@ mod tests {
     use rand_core::OsRng;
 
     use crate::{
-        consensus::{NetworkUpgrade, Parameters, TEST_NETWORK},
+        consensus::{BranchId, NetworkUpgrade, Parameters, TEST_NETWORK},
         legacy::TransparentAddress,
         memo::MemoBytes,
         sapling::{self, zip32::ExtendedSpendingKey, Node, Rseed},
@@ -966,6 +989,7 @@ mod tests {
                 orchard_anchor: Some(orchard::Anchor::empty_tree()),
             },
             target_height: sapling_activation_height,
+            consensus_branch_id: BranchId::Sapling,
             expiry_height: sapling_activation_height + DEFAULT_TX_EXPIRY_DELTA,
             transparent_builder: TransparentBuilder::empty(),
             sapling_builder: None,
@@ -1056,6 +1080,53 @@ mod tests {
         assert!(res.transaction().sapling_bundle().is_some());
     }
 
+    #[test]
+    fn consensus_branch_id_override() {
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let dfvk = extsk.to_diversifiable_full_viewing_key();
+        let to = dfvk.default_address().1;
+
+        let mut rng = OsRng;
+
+        let note1 = to.create_note(
+            sapling::value::NoteValue::from_raw(50000),
+            Rseed::BeforeZip212(jubjub::Fr::random(&mut rng)),
+        );
+        let cmu1 = Node::from_cmu(&note1.cmu());
+        let mut tree = CommitmentTree::<Node, 32>::empty();
+        tree.append(cmu1).unwrap();
+        let witness1 = IncrementalWitness::from_tree(tree);
+
+        let tx_height = TEST_NETWORK
+            .activation_height(NetworkUpgrade::Sapling)
+            .unwrap();
+
+        let build_config = BuildConfig::Standard {
+            sapling_anchor: Some(witness1.root().into()),
+            orchard_anchor: None,
+        };
+
+        // By default, the branch ID is the one active at the target height.
+        let builder = Builder::new(TEST_NETWORK, tx_height, build_config);
+        assert_eq!(builder.consensus_branch_id(), BranchId::Sapling);
+
+        let mut builder = builder.with_consensus_branch_id(BranchId::Blossom);
+        assert_eq!(builder.consensus_branch_id(), BranchId::Blossom);
+
+        builder
+            .add_sapling_spend::<Infallible>(&extsk, note1, witness1.path().unwrap())
+            .unwrap();
+        builder
+            .add_transparent_output(
+                &TransparentAddress::PublicKeyHash([0; 20]),
+                NonNegativeAmount::const_from_u64(40000),
+            )
+            .unwrap();
+
+        let res = builder.mock_build(OsRng).unwrap();
+        assert_eq!(res.transaction().consensus_branch_id(), BranchId::Blossom);
+    }
+
     #[test]
     fn fails_on_negative_change() {
         use crate::transaction::fees::zip317::MINIMUM_FEE;
@@ -5,6 +5,7 @@ use crate::{
     transaction::components::amount::NonNegativeAmount,
 };
 
+pub mod dynamic;
 pub mod fixed;
 pub mod transparent;
 pub mod zip317;
@@ -0,0 +1,164 @@
+//! Types related to implementing a [`FeeRule`] that scales the fee computed by another fee rule.
+//!
+//! This permits a wallet to raise (or lower) the fees that it pays in response to external
+//! conditions such as mempool congestion, via a single multiplier, while retaining the structure
+//! of an underlying fee rule such as [ZIP 317].
+//!
+//! [`FeeRule`]: crate::transaction::fees::FeeRule
+//! [ZIP 317]: https//zips.z.cash/zip-0317
+use core::cmp::max;
+
+use crate::{
+    consensus::{self, BlockHeight},
+    transaction::{
+        components::amount::{BalanceError, NonNegativeAmount},
+        fees::transparent,
+    },
+};
+
+#[cfg(zcash_unstable = "zfuture")]
+use crate::transaction::fees::tze;
+
+/// The multiplier, in basis points, that leaves the fee computed by the base rule unchanged.
+pub const UNIT_MULTIPLIER_BPS: u16 = 10_000;
+
+/// A [`FeeRule`] that scales the fee required by a base fee rule by a multiplier expressed in
+/// basis points.
+///
+/// The fee required by this rule is never less than the fee required by the base rule, so a
+/// transaction that pays the fee computed by this rule will satisfy the base rule (such as the
+/// [ZIP 317] conventional fee) even if the multiplier is less than [`UNIT_MULTIPLIER_BPS`].
+///
+/// [`FeeRule`]: crate::transaction::fees::FeeRule
+/// [ZIP 317]: https//zips.z.cash/zip-0317
+#[derive(Clone, Debug)]
+pub struct FeeRule<R> {
+    base: R,
+    multiplier_bps: u16,
+}
+
+impl<R> FeeRule<R> {
+    /// Constructs a new fee rule that scales the fee required by `base` by `multiplier_bps`
+    /// basis points. For example, a multiplier of `15_000` results in fees that are 150% of
+    /// those required by the base rule.
+    pub fn new(base: R, multiplier_bps: u16) -> Self {
+        Self {
+            base,
+            multiplier_bps,
+        }
+    }
+
+    /// Returns the fee rule whose result is scaled by this rule.
+    pub fn base(&self) -> &R {
+        &self.base
+    }
+
+    /// Returns the multiplier, in basis points, applied to the fee required by the base rule.
+    pub fn multiplier_bps(&self) -> u16 {
+        self.multiplier_bps
+    }
+
+    /// Scales the given base fee by this rule's multiplier, without allowing the result to fall
+    /// below the base fee.
+    fn scale<E>(&self, base_fee: NonNegativeAmount) -> Result<NonNegativeAmount, FeeError<E>> {
+        let scaled = u128::from(u64::from(base_fee)) * u128::from(self.multiplier_bps)
+            / u128::from(UNIT_MULTIPLIER_BPS);
+        let scaled = u64::try_from(scaled)
+            .ok()
+            .and_then(|v| NonNegativeAmount::from_u64(v).ok())
+            .ok_or(FeeError::Balance(BalanceError::Overflow))?;
+
+        Ok(max(scaled, base_fee))
+    }
+}
+
+/// Errors that can occur in dynamic fee calculation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeeError<E> {
+    /// An error occurred in computing the fee required by the base fee rule.
+    Base(E),
+    /// Scaling the fee required by the base fee rule produced an invalid amount.
+    Balance(BalanceError),
+}
+
+impl<E> From<BalanceError> for FeeError<E> {
+    fn from(err: BalanceError) -> Self {
+        FeeError::Balance(err)
+    }
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for FeeError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self {
+            FeeError::Base(e) => write!(f, "Base fee rule error: {}", e),
+            FeeError::Balance(e) => write!(
+                f,
+                "A balance calculation violated amount validity bounds: {}.",
+                e
+            ),
+        }
+    }
+}
+
+impl<R: super::FeeRule> super::FeeRule for FeeRule<R> {
+    type Error = FeeError<R::Error>;
+
+    fn fee_required<P: consensus::Parameters>(
+        &self,
+        params: &P,
+        target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::InputView],
+        transparent_outputs: &[impl transparent::OutputView],
+        sapling_input_count: usize,
+        sapling_output_count: usize,
+        orchard_action_count: usize,
+    ) -> Result<NonNegativeAmount, Self::Error> {
+        let base_fee = self
+            .base
+            .fee_required(
+                params,
+                target_height,
+                transparent_inputs,
+                transparent_outputs,
+                sapling_input_count,
+                sapling_output_count,
+                orchard_action_count,
+            )
+            .map_err(FeeError::Base)?;
+
+        self.scale(base_fee)
+    }
+}
+
+#[cfg(zcash_unstable = "zfuture")]
+impl<R: super::FutureFeeRule> super::FutureFeeRule for FeeRule<R> {
+    fn fee_required_zfuture<P: consensus::Parameters>(
+        &self,
+        params: &P,
+        target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::InputView],
+        transparent_outputs: &[impl transparent::OutputView],
+        sapling_input_count: usize,
+        sapling_output_count: usize,
+        orchard_action_count: usize,
+        tze_inputs: &[impl tze::InputView],
+        tze_outputs: &[impl tze::OutputView],
+    ) -> Result<NonNegativeAmount, Self::Error> {
+        let base_fee = self
+            .base
+            .fee_required_zfuture(
+                params,
+                target_height,
+                transparent_inputs,
+                transparent_outputs,
+                sapling_input_count,
+                sapling_output_count,
+                orchard_action_count,
+                tze_inputs,
+                tze_outputs,
+            )
+            .map_err(FeeError::Base)?;
+
+        self.scale(base_fee)
+    }
+}